@@ -0,0 +1,256 @@
+//! SilverBullet server running as a Cloudflare Worker
+//!
+//! The space is stored in the `SPACE` R2 bucket and the server config is read from the
+//! `CONFIG` KV namespace, under the key in the `SB_CONFIG_KEY` var (`config.toml` by
+//! default, YAML for `.yaml` and `.yml` keys). Proxy requests go through the Fetch API;
+//! there is no shell on Workers.
+//!
+//! With a `COORDINATOR` Durable Object binding, writes go through the [`SpaceCoordinator`]
+//! (see [`coordinator`]), and clients can follow the changes of the space at
+//! `GET /.journal?since=<cursor>`.
+//!
+//! Attachments are served from the Cache API on custom domains, see [`Cached`]. The
+//! `warm.paths` of the config are read when an isolate starts, see [`Warmer`].
+//!
+//! Build and deploy with `wrangler deploy`, see `wrangler.toml` for the bindings.
+
+#![cfg(target_arch = "wasm32")]
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use axum::extract::{FromRef, RawQuery, State};
+use axum::{Json, routing};
+use axum_client_ip::ClientIpSource;
+use http::request::Parts;
+use silverbullet::config::{self, Format};
+use silverbullet::fs::cloudflare::{Filesystem, cache::Cached, coordinator};
+use silverbullet::fs::{
+    ReadWriteFilesystem,
+    case_insensitive::CaseInsensitiveLayer,
+    cloudflare::coordinator::Coordinated,
+    memo,
+    normalized::NormalizeLayer,
+    perm::{self, Perm, PermissionsLayer},
+    retry::{Backoff, RetryLayer},
+    singleflight::SingleflightLayer,
+    warm::Warmer,
+};
+use silverbullet::{client, proxy, server, shell};
+use tower_service::Service as _;
+use worker::{Context, DurableObject, Env, HttpRequest, durable_object, event};
+
+const DEFAULT_CONFIG_KEY: &str = "config.toml";
+
+/// Whether the isolate warmed the space already
+static WARMED: AtomicBool = AtomicBool::new(false);
+
+/// Name of the coordinator object of the space
+const SPACE: &str = "space";
+
+#[derive(Clone, FromRef)]
+struct AppState {
+    config: client::Config,
+    manifest: client::ManifestConfig,
+    fs: Space,
+    journal: Option<Arc<Coordinated>>,
+    policy: proxy::Policy,
+    forwarded_for: bool,
+}
+
+type Space = Arc<dyn ReadWriteFilesystem>;
+
+impl server::routes::fs::Provider for AppState {
+    type Output = Space;
+
+    fn provide(&self, parts: &mut Parts) -> Result<Self::Output, server::Error> {
+        let user = parts.extensions.get::<client::User>();
+
+        // Files of read-only spaces and users are read-only, and can't be written
+        let fs: Space = if self.config.read_only || user.is_some_and(|user| user.read_only) {
+            Arc::new(perm::Filesystem::new(self.fs.clone(), Perm::Read))
+        } else {
+            self.fs.clone()
+        };
+
+        // A stat per file and request is enough
+        Ok(Arc::new(memo::Filesystem::new(fs)))
+    }
+}
+
+impl server::routes::shell::Provider for AppState {
+    type Output = shell::NoShell;
+
+    fn provide(&self, _parts: &mut Parts) -> Result<Self::Output, server::Error> {
+        Ok(shell::NoShell {})
+    }
+}
+
+impl server::routes::proxy::Provider for AppState {
+    type Output = proxy::cloudflare::Client;
+
+    fn provide(&self) -> Self::Output {
+        proxy::cloudflare::Client::new()
+    }
+
+    fn proxy(&self) -> proxy::Proxy<Self::Output> {
+        proxy::Proxy::new(self.provide())
+            .policy(self.policy.clone())
+            .forwarded_for(self.forwarded_for)
+            .base_path(self.config.url_prefix.as_deref().unwrap_or_default())
+    }
+}
+
+impl server::routes::log::Provider for AppState {
+    type Output = client::DiscardLogger;
+
+    fn provide(&self) -> Self::Output {
+        client::DiscardLogger
+    }
+}
+
+/// Durable Object applying the writes to the space one by one
+#[durable_object]
+pub struct SpaceCoordinator(coordinator::Coordinator);
+
+impl DurableObject for SpaceCoordinator {
+    fn new(state: worker::State, env: Env) -> Self {
+        let bucket = env.bucket("SPACE").expect("missing SPACE bucket binding");
+
+        Self(coordinator::Coordinator::new(
+            &state,
+            Filesystem::new(bucket, String::new()),
+        ))
+    }
+
+    async fn fetch(&self, request: worker::Request) -> worker::Result<worker::Response> {
+        self.0.fetch(request).await
+    }
+}
+
+/// Changes of the space after the `since` cursor.
+#[worker::send]
+async fn journal(
+    State(journal): State<Option<Arc<Coordinated>>>,
+    RawQuery(query): RawQuery,
+) -> Result<Json<coordinator::Changes>, server::Error> {
+    let journal = journal.ok_or_else(|| server::Error::not_found("no coordinator configured"))?;
+
+    let since = query
+        .as_deref()
+        .unwrap_or_default()
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("since="))
+        .map(|since| since.parse())
+        .transpose()
+        .map_err(|err| server::Error::BadRequest(Box::new(err)))?;
+
+    Ok(Json(journal.changes(since.unwrap_or(0)).await?))
+}
+
+/// Read the server config from KV, the defaults apply without one.
+async fn load_config(env: &Env) -> worker::Result<config::Config> {
+    let key = env
+        .var("SB_CONFIG_KEY")
+        .map(|var| var.to_string())
+        .unwrap_or_else(|_| DEFAULT_CONFIG_KEY.to_string());
+
+    let Some(contents) = env.kv("CONFIG")?.get(&key).text().await? else {
+        return Ok(config::Config::default());
+    };
+
+    config::Config::parse(&contents, Format::from_path(&key))
+        .map_err(|err| worker::Error::RustError(format!("invalid config in {key}: {err}")))
+}
+
+fn space(env: &Env, config: &config::Config) -> worker::Result<(Space, Option<Arc<Coordinated>>)> {
+    let fs = Filesystem::new(env.bucket("SPACE")?, String::new());
+
+    let (space, coordinated): (Space, _) = match env.durable_object("COORDINATOR") {
+        Ok(namespace) => {
+            let coordinated = Coordinated::new(fs, &namespace, SPACE)
+                .map(Arc::new)
+                .map_err(|err| worker::Error::RustError(err.to_string()))?;
+
+            (
+                Arc::new(Cached::new(coordinated.clone())),
+                Some(coordinated),
+            )
+        }
+        Err(_) => (Arc::new(Cached::new(fs)), None),
+    };
+
+    // R2 names are case-sensitive, links written on other systems may not be
+    let space = silverbullet::fs::stack()
+        .option_layer(
+            config
+                .space
+                .case_insensitive
+                .then_some(CaseInsensitiveLayer),
+        )
+        .option_layer(config.space.normalize_names.then_some(NormalizeLayer))
+        .option_layer(config.space.permissions().map(PermissionsLayer::new))
+        .option_layer(config.space.coalesce_reads.then_some(SingleflightLayer))
+        .option_layer(
+            (config.space.retries > 0).then(|| RetryLayer::new(Backoff::new(config.space.retries))),
+        )
+        .build(space);
+
+    Ok((Arc::new(space), coordinated))
+}
+
+#[event(fetch)]
+async fn fetch(
+    request: HttpRequest,
+    env: Env,
+    ctx: Context,
+) -> worker::Result<http::Response<axum::body::Body>> {
+    let config = load_config(&env).await?;
+    let (fs, coordinated) = space(&env, &config)?;
+
+    // Once per isolate, alongside its first request: a cold start misses the caches
+    if !config.warm.paths.is_empty() && !WARMED.swap(true, Ordering::Relaxed) {
+        let warmer = Warmer::new(fs.clone()).paths(config.warm.paths.clone());
+
+        ctx.wait_until(async move {
+            let _ = warmer.warm().await;
+        });
+    }
+
+    let state = AppState {
+        config: config.client(),
+        manifest: config.manifest(),
+        fs,
+        journal: coordinated,
+        policy: config
+            .proxy
+            .policy()
+            .map_err(|err| worker::Error::RustError(format!("invalid proxy config: {err}")))?,
+        forwarded_for: config.proxy.forwarded_for,
+    };
+
+    let base_path = config.server.base_path().unwrap_or_default();
+
+    let mut builder = server::builder().shell(false).base_path(&base_path);
+
+    if let Some(cors) = config.cors.cors() {
+        builder = builder.cors(cors);
+    }
+
+    if let Some(headers) = config.security.headers() {
+        builder = builder.security_headers(headers);
+    }
+
+    if let Some(auth) = &config.auth {
+        builder = builder
+            .auth(server::auth::Basic::new(&auth.user, &auth.password).read_only(auth.read_only));
+    }
+
+    let mut router = builder
+        .build()
+        .route(&format!("{base_path}/.journal"), routing::get(journal))
+        .layer(ClientIpSource::CfConnectingIp.into_extension())
+        .with_state(state);
+
+    Ok(router.call(request).await?)
+}
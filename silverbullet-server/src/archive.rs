@@ -0,0 +1,413 @@
+//! `silverbullet import` and `silverbullet export`: copy a space between the configured
+//! storage and a local directory or `.zip` archive, and `silverbullet bundle`: write a
+//! page with the pages it links to a `.zip` archive or an HTML document
+//!
+//! Modification times are kept in both directions, as file modification times or zip
+//! entry times (rounded to two seconds by the zip format). Content types are guessed from
+//! file extensions on import, like the upstream server does for files on disk.
+//!
+//! With `--from`, exports of other apps are [converted](silverbullet::import) on import.
+
+use std::io::{Read as _, Write as _};
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use bytes::Bytes;
+use futures::StreamExt as _;
+use silverbullet::fs::{
+    self, IncomingFileMeta, ReadOnlyFilesystem, ReadWriteFilesystem, StreamExt as _,
+};
+use silverbullet::import::{self, Converter};
+use silverbullet::{bundle, client, ssr};
+use tokio::io::AsyncWriteExt as _;
+use zip::{DateTime, ZipArchive, ZipWriter, write::SimpleFileOptions};
+
+/// Size of the chunks files are read in
+const CHUNK_SIZE: usize = 64 * 1024;
+
+fn is_zip(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("zip"))
+}
+
+pub async fn export(fs: &impl ReadOnlyFilesystem, target: &Path) -> fs::Result<()> {
+    let files = fs.list().await?;
+
+    let mut zip = if is_zip(target) {
+        Some(ZipWriter::new(std::fs::File::create(target)?))
+    } else {
+        None
+    };
+
+    for meta in &files {
+        let Some(path) = local_path(&meta.name) else {
+            tracing::warn!("skipping {}: not a relative path", meta.name);
+            continue;
+        };
+
+        let (mut stream, _) = fs.get(&meta.name).await?;
+
+        match &mut zip {
+            Some(zip) => {
+                let mut options = SimpleFileOptions::default()
+                    .compression_method(zip::CompressionMethod::Deflated)
+                    .large_file(meta.size >= u64::from(u32::MAX));
+
+                if let Some(time) = zip_time(meta.last_modified) {
+                    options = options.last_modified_time(time);
+                }
+
+                zip.start_file(path.to_string_lossy(), options)
+                    .map_err(std::io::Error::from)?;
+
+                while let Some(chunk) = stream.next().await {
+                    zip.write_all(&chunk?)?;
+                }
+            }
+            None => {
+                let path = target.join(path);
+
+                if let Some(parent) = path.parent() {
+                    tokio::fs::create_dir_all(parent).await?;
+                }
+
+                let mut file = tokio::fs::File::create(&path).await?;
+
+                while let Some(chunk) = stream.next().await {
+                    file.write_all(&chunk?).await?;
+                }
+
+                let file = file.into_std().await;
+                file.set_modified(UNIX_EPOCH + Duration::from_millis(meta.last_modified))?;
+            }
+        }
+    }
+
+    if let Some(zip) = zip {
+        zip.finish().map_err(std::io::Error::from)?;
+    }
+
+    tracing::info!("exported {} files to {}", files.len(), target.display());
+
+    Ok(())
+}
+
+/// Write `page` and the pages `depth` links away to a `.zip` archive, or an HTML document
+/// for other targets.
+pub async fn bundle(
+    fs: &impl ReadOnlyFilesystem,
+    config: &client::Config,
+    page: &str,
+    depth: usize,
+    target: &Path,
+) -> bundle::Result<()> {
+    let bundle = bundle::Bundle::collect(fs, page, depth).await?;
+
+    let data = match is_zip(target) {
+        true => bundle.zip(fs).await?,
+        false => bundle
+            .html(fs, &ssr::Jinja::new(), config)
+            .await?
+            .into_bytes(),
+    };
+    tokio::fs::write(target, data)
+        .await
+        .map_err(fs::Error::from)?;
+
+    tracing::info!(
+        "bundled {} pages and {} files to {}",
+        bundle.pages.len(),
+        bundle.files.len(),
+        target.display()
+    );
+
+    Ok(())
+}
+
+pub async fn import(
+    fs: &impl ReadWriteFilesystem,
+    source: &Path,
+    from: Option<import::Source>,
+) -> fs::Result<()> {
+    let count = if is_zip(source) {
+        import_zip(fs, source, from).await?
+    } else {
+        import_dir(fs, source, from).await?
+    };
+
+    tracing::info!("imported {count} files from {}", source.display());
+
+    Ok(())
+}
+
+async fn import_dir(
+    fs: &impl ReadWriteFilesystem,
+    root: &Path,
+    from: Option<import::Source>,
+) -> fs::Result<usize> {
+    let mut files = Vec::new();
+    let mut dirs = vec![root.to_path_buf()];
+
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(&dir)? {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+
+            if file_type.is_dir() {
+                dirs.push(entry.path());
+                continue;
+            }
+
+            if !file_type.is_file() {
+                continue;
+            }
+
+            let path = entry.path();
+            if let Some(name) = space_name(path.strip_prefix(root).unwrap_or(&path)) {
+                files.push((path, name));
+            }
+        }
+    }
+
+    // Converted exports are listed first, links between their files are resolved
+    let converter =
+        from.map(|from| Converter::new(from, files.iter().map(|(_, name)| name.as_str())));
+    let mut count = 0;
+
+    for (path, name) in &files {
+        let Some(target) = target_name(converter.as_ref(), name) else {
+            continue;
+        };
+
+        let metadata = std::fs::metadata(path)?;
+        let modified = metadata.modified().ok().and_then(millis);
+        let created = metadata.created().ok().and_then(millis).or(modified);
+
+        match converter
+            .as_ref()
+            .filter(|converter| converter.is_page(name))
+        {
+            Some(converter) => {
+                let content = convert(converter, name, std::fs::read(path)?);
+                put(fs, &target, content, created, modified).await?;
+            }
+            None => {
+                let meta = IncomingFileMeta {
+                    created,
+                    last_modified: modified,
+                    content_type: Some(content_type(&target)),
+                    size: Some(metadata.len()),
+                    ..Default::default()
+                };
+
+                let file = tokio::fs::File::open(path).await?;
+                fs.put(&target, read_stream(file), meta).await?;
+            }
+        }
+
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+async fn import_zip(
+    fs: &impl ReadWriteFilesystem,
+    source: &Path,
+    from: Option<import::Source>,
+) -> fs::Result<usize> {
+    let mut archive =
+        ZipArchive::new(std::fs::File::open(source)?).map_err(std::io::Error::from)?;
+
+    let mut files = Vec::new();
+    for index in 0..archive.len() {
+        let entry = archive.by_index_raw(index).map_err(std::io::Error::from)?;
+
+        if let Some(name) = entry
+            .enclosed_name()
+            .filter(|_| entry.is_file())
+            .and_then(|path| space_name(&path))
+        {
+            files.push((index, name));
+        }
+    }
+
+    let converter =
+        from.map(|from| Converter::new(from, files.iter().map(|(_, name)| name.as_str())));
+    let mut count = 0;
+
+    for (index, name) in &files {
+        let Some(target) = target_name(converter.as_ref(), name) else {
+            continue;
+        };
+
+        // Entries are read whole, the archive reader can't be held across writes
+        let (modified, content) = {
+            let mut entry = archive.by_index(*index).map_err(std::io::Error::from)?;
+
+            let mut content = Vec::with_capacity(entry.size() as usize);
+            entry.read_to_end(&mut content)?;
+
+            (entry.last_modified().and_then(zip_millis), content)
+        };
+
+        let content = match converter
+            .as_ref()
+            .filter(|converter| converter.is_page(name))
+        {
+            Some(converter) => convert(converter, name, content),
+            None => content,
+        };
+
+        put(fs, &target, content, modified, modified).await?;
+
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+/// Name in the space of an imported file, `None` when a converter skips it.
+fn target_name(converter: Option<&Converter>, name: &str) -> Option<String> {
+    match converter {
+        Some(converter) => converter.name(name).map(str::to_string),
+        None => Some(name.to_string()),
+    }
+}
+
+/// Converted page, as it is when it isn't UTF-8.
+fn convert(converter: &Converter, name: &str, content: Vec<u8>) -> Vec<u8> {
+    match std::str::from_utf8(&content) {
+        Ok(page) => converter.page(name, page).into_bytes(),
+        Err(_) => content,
+    }
+}
+
+async fn put(
+    fs: &impl ReadWriteFilesystem,
+    name: &str,
+    content: Vec<u8>,
+    created: Option<u64>,
+    modified: Option<u64>,
+) -> fs::Result<()> {
+    let meta = IncomingFileMeta {
+        created,
+        last_modified: modified,
+        content_type: Some(content_type(name)),
+        size: Some(content.len() as u64),
+        ..Default::default()
+    };
+
+    let data = futures::stream::once(std::future::ready(Ok(Bytes::from(content))));
+    fs.put(name, data.into_boxed(), meta).await?;
+
+    Ok(())
+}
+
+fn read_stream(file: tokio::fs::File) -> fs::Stream {
+    use tokio::io::AsyncReadExt as _;
+
+    futures::stream::try_unfold(file, |mut file| async move {
+        let mut buffer = vec![0; CHUNK_SIZE];
+        let read = file.read(&mut buffer).await?;
+
+        buffer.truncate(read);
+
+        Ok((read > 0).then(|| (Bytes::from(buffer), file)))
+    })
+    .into_boxed()
+}
+
+/// Relative path a file of the space is exported to, rejecting names escaping the target.
+fn local_path(name: &str) -> Option<PathBuf> {
+    let path = Path::new(name.trim_start_matches('/'));
+
+    path.components()
+        .all(|component| matches!(component, Component::Normal(_)))
+        .then(|| path.to_path_buf())
+}
+
+/// Name in the space of an imported file, always `/` separated.
+fn space_name(path: &Path) -> Option<String> {
+    let parts = path
+        .components()
+        .map(|component| match component {
+            Component::Normal(part) => part.to_str(),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>()?;
+
+    (!parts.is_empty()).then(|| parts.join("/"))
+}
+
+pub fn content_type(name: &str) -> String {
+    mime_guess::from_path(name)
+        .first_or_octet_stream()
+        .to_string()
+}
+
+fn millis(time: SystemTime) -> Option<u64> {
+    time.duration_since(UNIX_EPOCH)
+        .ok()
+        .map(|elapsed| elapsed.as_millis() as u64)
+}
+
+/// Zip entry time of a timestamp in milliseconds, in UTC.
+fn zip_time(millis: u64) -> Option<DateTime> {
+    let secs = millis / 1000;
+    let (year, month, day) = fs::time::civil_from_days((secs / 86_400) as i64);
+    let time = secs % 86_400;
+
+    DateTime::from_date_and_time(
+        u16::try_from(year).ok()?,
+        month,
+        day,
+        (time / 3600) as u8,
+        (time % 3600 / 60) as u8,
+        (time % 60) as u8,
+    )
+    .ok()
+}
+
+fn zip_millis(time: DateTime) -> Option<u64> {
+    let days = fs::time::days_from_civil(i64::from(time.year()), time.month(), time.day());
+    let secs = days * 86_400
+        + i64::from(time.hour()) * 3600
+        + i64::from(time.minute()) * 60
+        + i64::from(time.second());
+
+    u64::try_from(secs).ok().map(|secs| secs * 1000)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_zip_times() {
+        // 2024-02-29T13:45:30Z
+        let millis = 1_709_214_330_000;
+        let time = zip_time(millis).unwrap();
+
+        assert_eq!(
+            (time.year(), time.month(), time.day(), time.hour()),
+            (2024, 2, 29, 13)
+        );
+        assert_eq!(zip_millis(time), Some(millis));
+
+        // Before the earliest zip time
+        assert!(zip_time(0).is_none());
+    }
+
+    #[test]
+    fn maps_paths() {
+        assert_eq!(local_path("notes/a.md"), Some(PathBuf::from("notes/a.md")));
+        assert_eq!(local_path("../a.md"), None);
+        assert_eq!(
+            space_name(Path::new("notes/a.md")).as_deref(),
+            Some("notes/a.md")
+        );
+        assert_eq!(space_name(Path::new("/etc/passwd")), None);
+        assert_eq!(content_type("notes/a.md"), "text/markdown");
+    }
+}
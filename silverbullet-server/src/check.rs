@@ -0,0 +1,259 @@
+//! `silverbullet check`: validate the configuration and the files of the space
+//!
+//! Every file is read back and compared to its listed size. Metadata is checked for
+//! missing content types, zero timestamps and names only differing by case, which clash
+//! on case-insensitive filesystems. With `--repair` files with bad metadata are rewritten
+//! with a guessed content type and a valid timestamp; case conflicts need a manual rename.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use futures::StreamExt as _;
+use silverbullet::config::Config;
+use silverbullet::fs::{self, FileMeta, IncomingFileMeta, ReadWriteFilesystem, StreamExt as _};
+
+use crate::archive::content_type;
+
+const OCTET_STREAM: &str = "application/octet-stream";
+
+#[derive(Debug, PartialEq)]
+enum Issue {
+    MissingContentType { guessed: String },
+    ZeroTimestamp,
+    CaseConflict { other: String },
+    SizeMismatch { listed: u64, read: u64 },
+    Unreadable { error: String },
+}
+
+impl Issue {
+    fn repairable(&self) -> bool {
+        matches!(self, Self::MissingContentType { .. } | Self::ZeroTimestamp)
+    }
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingContentType { guessed } => {
+                write!(f, "missing content type, expected {guessed}")
+            }
+            Self::ZeroTimestamp => write!(f, "zero timestamp"),
+            Self::CaseConflict { other } => write!(f, "name only differs by case from {other}"),
+            Self::SizeMismatch { listed, read } => {
+                write!(f, "listed as {listed} bytes but {read} bytes were read")
+            }
+            Self::Unreadable { error } => write!(f, "unreadable: {error}"),
+        }
+    }
+}
+
+pub async fn run(config: &Config, fs: &impl ReadWriteFilesystem, repair: bool) -> fs::Result<bool> {
+    let mut ok = true;
+
+    if let Err(err) = config.proxy.policy().and(config.proxy.upstream()) {
+        tracing::error!("invalid proxy config: {err}");
+        ok = false;
+    }
+
+    let files = fs.list().await?;
+    let size: u64 = files.iter().map(|meta| meta.size).sum();
+
+    tracing::info!("checking {} files, {size} bytes", files.len());
+
+    let mut issues = metadata_issues(&files);
+
+    for meta in &files {
+        if let Some(issue) = verify_content(fs, meta).await {
+            issues.push((meta.name.clone(), issue));
+        }
+    }
+
+    let mut repaired = HashSet::new();
+
+    for (name, issue) in &issues {
+        if repair && issue.repairable() {
+            // A rewrite fixes all the repairable issues of a file
+            if repaired.contains(name) {
+                tracing::info!("{name}: {issue}, repaired");
+                continue;
+            }
+
+            match repair_file(fs, name).await {
+                Ok(()) => {
+                    tracing::info!("{name}: {issue}, repaired");
+                    repaired.insert(name.clone());
+                    continue;
+                }
+                Err(err) => tracing::error!("{name}: failed to repair: {err}"),
+            }
+        }
+
+        tracing::warn!("{name}: {issue}");
+        ok = false;
+    }
+
+    tracing::info!(
+        "found {} issues, repaired {} files",
+        issues.len(),
+        repaired.len()
+    );
+
+    Ok(ok)
+}
+
+fn metadata_issues(files: &[FileMeta]) -> Vec<(String, Issue)> {
+    let mut issues = Vec::new();
+    let mut lowercase = HashMap::new();
+
+    // Backends that don't store content types, eg. fs, report every file as binary
+    let stores_content_types = files
+        .iter()
+        .any(|meta| !meta.content_type.is_empty() && meta.content_type != OCTET_STREAM);
+
+    for meta in files {
+        let guessed = content_type(&meta.name);
+
+        if meta.content_type.is_empty()
+            || (stores_content_types
+                && meta.content_type == OCTET_STREAM
+                && guessed != OCTET_STREAM)
+        {
+            issues.push((meta.name.clone(), Issue::MissingContentType { guessed }));
+        }
+
+        if meta.created == 0 || meta.last_modified == 0 {
+            issues.push((meta.name.clone(), Issue::ZeroTimestamp));
+        }
+
+        if let Some(other) = lowercase.insert(meta.name.to_lowercase(), &meta.name) {
+            issues.push((
+                meta.name.clone(),
+                Issue::CaseConflict {
+                    other: other.clone(),
+                },
+            ));
+        }
+    }
+
+    issues
+}
+
+async fn verify_content(fs: &impl ReadWriteFilesystem, meta: &FileMeta) -> Option<Issue> {
+    let unreadable = |err: &dyn fmt::Display| Issue::Unreadable {
+        error: err.to_string(),
+    };
+
+    let (mut stream, _) = match fs.get(&meta.name).await {
+        Ok(file) => file,
+        Err(err) => return Some(unreadable(&err)),
+    };
+
+    let mut read = 0;
+
+    while let Some(chunk) = stream.next().await {
+        match chunk {
+            Ok(chunk) => read += chunk.len() as u64,
+            Err(err) => return Some(unreadable(&err)),
+        }
+    }
+
+    (read != meta.size).then_some(Issue::SizeMismatch {
+        listed: meta.size,
+        read,
+    })
+}
+
+/// Rewrite a file with its content, keeping its valid metadata.
+async fn repair_file(fs: &impl ReadWriteFilesystem, name: &str) -> fs::Result<()> {
+    let (stream, meta) = fs.get(name).await?;
+
+    // Read the file whole, some backends replace the file while it's still being read
+    let content: Vec<bytes::Bytes> = stream
+        .collect::<Vec<_>>()
+        .await
+        .into_iter()
+        .collect::<Result<_, _>>()?;
+
+    let timestamp = [meta.last_modified, meta.created]
+        .into_iter()
+        .find(|time| *time != 0);
+
+    let content_type = if meta.content_type.is_empty() || meta.content_type == OCTET_STREAM {
+        content_type(name)
+    } else {
+        meta.content_type
+    };
+
+    let incoming = IncomingFileMeta {
+        created: timestamp,
+        last_modified: timestamp,
+        content_type: Some(content_type),
+        perm: Some(meta.perm),
+        size: Some(meta.size),
+        ..Default::default()
+    };
+
+    fs.put(
+        name,
+        futures::stream::iter(content.into_iter().map(Ok)).into_boxed(),
+        incoming,
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meta(name: &str, content_type: &str, created: u64) -> FileMeta {
+        FileMeta {
+            name: name.to_string(),
+            created,
+            perm: "rw".to_string(),
+            content_type: content_type.to_string(),
+            last_modified: created,
+            size: 0,
+            etag: None,
+            version: None,
+        }
+    }
+
+    #[test]
+    fn finds_metadata_issues() {
+        let issues = metadata_issues(&[
+            meta("index.md", "text/markdown", 1),
+            meta("Index.md", "text/markdown", 1),
+            meta("photo.png", "application/octet-stream", 1),
+            meta("data.bin", "application/octet-stream", 0),
+        ]);
+
+        assert!(
+            metadata_issues(&[
+                meta("index.md", OCTET_STREAM, 1),
+                meta("photo.png", OCTET_STREAM, 1),
+            ])
+            .is_empty()
+        );
+
+        assert_eq!(
+            issues,
+            [
+                (
+                    "Index.md".to_string(),
+                    Issue::CaseConflict {
+                        other: "index.md".to_string()
+                    }
+                ),
+                (
+                    "photo.png".to_string(),
+                    Issue::MissingContentType {
+                        guessed: "image/png".to_string()
+                    }
+                ),
+                ("data.bin".to_string(), Issue::ZeroTimestamp),
+            ]
+        );
+    }
+}
@@ -0,0 +1,177 @@
+//! Command line interface
+//!
+//! Settings are layered: the config file is read first, then `SB_*` environment
+//! variables, then command line flags.
+
+use std::path::PathBuf;
+
+use clap::{Args, Parser, Subcommand};
+use silverbullet::config::{self, Config};
+
+#[derive(Debug, Parser)]
+#[command(name = "silverbullet", version, about = "SilverBullet server")]
+pub struct Cli {
+    /// Config file, YAML for .yaml and .yml files and TOML otherwise
+    #[arg(long, short, global = true, env = "SB_CONFIG")]
+    pub config: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub space: SpaceArgs,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Serve the space (the default)
+    Serve(ServeArgs),
+    /// Copy all files of the space to a directory or .zip archive
+    Export {
+        /// Directory to write the files to, created when missing, or a .zip file
+        target: PathBuf,
+    },
+    /// Copy all files from a directory or .zip archive into the space, converting
+    /// exports of other apps with --from
+    Import {
+        /// Directory or .zip file to read the files from
+        source: PathBuf,
+        /// Convert an export of another app: obsidian, notion or logseq
+        #[arg(long)]
+        from: Option<silverbullet::import::Source>,
+    },
+    /// Write a page and the pages it links to a .zip archive or an HTML document
+    Bundle {
+        /// Name of the page, e.g. Projects/Launch
+        page: String,
+        /// .zip file, or HTML file for other extensions
+        target: PathBuf,
+        /// Links followed from the page
+        #[arg(long, default_value_t = 1)]
+        depth: usize,
+    },
+    /// Check the configuration and the files of the space
+    Check {
+        /// Rewrite files with missing content types or zero timestamps
+        #[arg(long)]
+        repair: bool,
+    },
+}
+
+/// Where the space is stored
+#[derive(Debug, Args)]
+pub struct SpaceArgs {
+    /// Space directory, serves it with the fs backend unless `--backend` is given
+    #[arg(long, global = true)]
+    pub space: Option<String>,
+
+    /// Storage backend: fs, s3, memory or a storage URI, eg. sqlite://space.db
+    #[arg(long, global = true, value_parser = parse_backend)]
+    pub backend: Option<String>,
+}
+
+#[derive(Debug, Default, Args)]
+pub struct ServeArgs {
+    /// Address to listen on, eg. 0.0.0.0:3000
+    #[arg(long, short)]
+    pub bind: Option<String>,
+}
+
+fn parse_backend(value: &str) -> Result<String, String> {
+    match value {
+        "fs" | "s3" | "memory" => Ok(value.to_string()),
+        uri if uri.contains("://") => Ok(uri.to_string()),
+        _ => Err("expected fs, s3, memory or a storage URI".to_string()),
+    }
+}
+
+impl Cli {
+    /// Load the configuration with the flags applied on top.
+    pub fn config(&self) -> config::Result<Config> {
+        let mut config = Config::load(self.config.as_deref())?;
+
+        // Flags share the parsing and validation of the matching environment variables
+        let mut overrides = Vec::new();
+
+        if let Some(space) = &self.space.space {
+            overrides.push(("SB_FOLDER", space.clone()));
+        }
+        match (&self.space.space, &self.space.backend) {
+            (_, Some(backend)) => overrides.push(("SB_BACKEND", backend.clone())),
+            (Some(_), None) => overrides.push(("SB_BACKEND", "fs".to_string())),
+            (None, None) => {}
+        }
+
+        config.apply_env(overrides)?;
+
+        if let Some(Command::Serve(ServeArgs { bind: Some(bind) })) = &self.command {
+            config.server.bind = bind.clone();
+        }
+
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_commands() {
+        use clap::CommandFactory as _;
+
+        Cli::command().debug_assert();
+
+        let cli = Cli::try_parse_from([
+            "silverbullet",
+            "serve",
+            "--space",
+            "./notes",
+            "--bind",
+            "127.0.0.1:8080",
+            "--backend",
+            "memory",
+        ])
+        .unwrap();
+
+        assert_eq!(cli.space.space.as_deref(), Some("./notes"));
+        assert_eq!(cli.space.backend.as_deref(), Some("memory"));
+        assert!(matches!(
+            cli.command,
+            Some(Command::Serve(ServeArgs { bind: Some(ref bind) })) if bind == "127.0.0.1:8080"
+        ));
+
+        let cli = Cli::try_parse_from(["silverbullet", "export", "out"]).unwrap();
+        assert!(matches!(cli.command, Some(Command::Export { .. })));
+
+        let cli =
+            Cli::try_parse_from(["silverbullet", "import", "vault", "--from", "Obsidian"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Command::Import {
+                from: Some(silverbullet::import::Source::Obsidian),
+                ..
+            })
+        ));
+        assert!(
+            Cli::try_parse_from(["silverbullet", "import", "out", "--from", "evernote"]).is_err()
+        );
+
+        let cli = Cli::try_parse_from([
+            "silverbullet",
+            "bundle",
+            "Topic",
+            "topic.zip",
+            "--depth",
+            "2",
+        ])
+        .unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Command::Bundle { ref page, depth: 2, .. }) if page == "Topic"
+        ));
+
+        let cli = Cli::try_parse_from(["silverbullet"]).unwrap();
+        assert!(cli.command.is_none());
+    }
+}
@@ -0,0 +1,81 @@
+//! Hooks of the space, from the `[[hooks]]` of the config
+//!
+//! Webhooks are sent with the proxy client but not its policy, their URLs being set by
+//! the server rather than asked for by clients, and commands run as local processes
+//! outside of the shell allowlist. Templates are read from the space at startup.
+
+use std::sync::Arc;
+
+use futures::TryStreamExt as _;
+use silverbullet::config::Config;
+use silverbullet::fs::{self, ReadWriteFilesystem};
+use silverbullet::hooks::Hooks;
+use silverbullet::ssr;
+
+use crate::Space;
+
+/// Wrap the space in its hooks, dispatched in the background, failing on invalid hooks.
+pub async fn attach(config: &Config, space: Space) -> Result<Space, String> {
+    if config.hooks.is_empty() {
+        return Ok(space);
+    }
+
+    let mut hooks = Hooks::new();
+    let mut renderer = ssr::Jinja::new();
+
+    for hook in &config.hooks {
+        hooks = hooks.hook(
+            hook.hook()
+                .map_err(|err| format!("invalid hook config: {err}"))?,
+        );
+
+        if let Some(template) = &hook.template {
+            match read(&*space, template).await {
+                Ok(source) => {
+                    renderer = renderer
+                        .template(template, source)
+                        .map_err(|err| format!("invalid hook template {template}: {err}"))?;
+                }
+                Err(err) => tracing::warn!(template, error = %err, "Failed to read hook template"),
+            }
+        }
+    }
+
+    #[cfg(feature = "proxy")]
+    {
+        hooks = hooks.client(Arc::new(crate::proxy_client(&config.proxy)?));
+    }
+
+    #[cfg(not(feature = "proxy"))]
+    if config.hooks.iter().any(|hook| hook.webhook.is_some()) {
+        tracing::warn!("webhooks are configured, but this build has no HTTP client");
+    }
+
+    #[cfg(feature = "shell")]
+    {
+        hooks = hooks.shell(Arc::new(silverbullet::shell::process::Shell::new()));
+    }
+
+    #[cfg(not(feature = "shell"))]
+    if config.hooks.iter().any(|hook| hook.command.is_some()) {
+        tracing::warn!("hook commands are configured, but this build can't run processes");
+    }
+
+    let (space, dispatcher) = hooks.renderer(Arc::new(renderer)).attach(space);
+    tokio::spawn(dispatcher.run());
+
+    Ok(Arc::new(space))
+}
+
+async fn read(space: &dyn ReadWriteFilesystem, name: &str) -> fs::Result<String> {
+    let (stream, _) = space.get(name).await?;
+
+    let bytes = stream
+        .try_fold(Vec::new(), |mut acc, chunk| async move {
+            acc.extend_from_slice(&chunk);
+            Ok(acc)
+        })
+        .await?;
+
+    String::from_utf8(bytes).map_err(|err| fs::Error::Other(err.into()))
+}
@@ -0,0 +1,124 @@
+//! Scheduled jobs, from the `[[jobs]]` of the config
+//!
+//! Commands run as local processes outside of the shell allowlist, like the commands of
+//! hooks.
+
+use silverbullet::backup::Backup;
+use silverbullet::config::Config;
+use silverbullet::fs;
+use silverbullet::scheduler::{Job, Scheduler};
+use silverbullet::stats::Stats;
+use silverbullet::sync::git::GitSync;
+
+use crate::Space;
+
+/// Run the jobs of the config in the background, failing on invalid jobs.
+pub fn spawn(
+    config: &Config,
+    space: &Space,
+    backup: Option<&Backup>,
+    git: Option<&GitSync>,
+    stats: Option<&Stats>,
+) -> Result<(), String> {
+    if config.jobs.is_empty() {
+        return Ok(());
+    }
+
+    let mut scheduler = Scheduler::new();
+
+    for job in &config.jobs {
+        let schedule = job
+            .schedule()
+            .map_err(|err| format!("invalid job config: {err}"))?;
+
+        let scheduled = match job.task.as_str() {
+            "backup" => match backup {
+                Some(backup) => Job::new(&job.name, schedule, backup.clone()),
+                None => {
+                    tracing::warn!(
+                        job = job.name,
+                        "Backup job without a backup target, skipping"
+                    );
+                    continue;
+                }
+            },
+            "git" => match git {
+                Some(git) => Job::new(&job.name, schedule, git.clone()),
+                None => {
+                    tracing::warn!(job = job.name, "Git job without a git sync, skipping");
+                    continue;
+                }
+            },
+            "gc" => {
+                let collector = config
+                    .gc
+                    .collector(space.clone())
+                    .map_err(|err| format!("invalid gc config: {err}"))?;
+                Job::new(&job.name, schedule, collector)
+            }
+            "stats" => match stats {
+                Some(stats) => Job::new(&job.name, schedule, stats.clone()),
+                None => {
+                    tracing::warn!(
+                        job = job.name,
+                        "Stats job, but stats are disabled, skipping"
+                    );
+                    continue;
+                }
+            },
+            "warm" => {
+                let warmer = fs::warm::Warmer::new(space.clone()).paths(config.warm.paths.clone());
+                Job::new(&job.name, schedule, warmer)
+            }
+            "command" => match command(job)? {
+                Some(command) => Job::new(&job.name, schedule, command),
+                None => continue,
+            },
+            task => {
+                return Err(format!(
+                    "invalid job config: unknown task {task} of job {}",
+                    job.name
+                ));
+            }
+        };
+
+        scheduler = scheduler.job(scheduled.jitter(job.jitter()));
+    }
+
+    tracing::info!(jobs = scheduler.jobs().len(), "Scheduled jobs");
+    tokio::spawn(scheduler.run());
+
+    Ok(())
+}
+
+#[cfg(feature = "shell")]
+fn command(
+    job: &silverbullet::config::Job,
+) -> Result<Option<silverbullet::scheduler::Command>, String> {
+    let Some((cmd, args)) = job
+        .command
+        .as_deref()
+        .and_then(|command| command.split_first())
+    else {
+        return Err(format!(
+            "invalid job config: job {} has no command",
+            job.name
+        ));
+    };
+
+    let shell = std::sync::Arc::new(silverbullet::shell::process::Shell::new());
+    Ok(Some(silverbullet::scheduler::Command::new(
+        shell, cmd, args,
+    )))
+}
+
+#[cfg(not(feature = "shell"))]
+fn command(
+    job: &silverbullet::config::Job,
+) -> Result<Option<silverbullet::scheduler::Command>, String> {
+    tracing::warn!(
+        job = job.name,
+        "Command job, but this build can't run processes, skipping"
+    );
+    Ok(None)
+}
@@ -0,0 +1,144 @@
+//! Listening sockets: inherited from systemd, a Unix socket path or a TCP address
+
+use std::io;
+use std::path::Path;
+
+use silverbullet::config;
+use tokio::net::TcpListener;
+
+#[cfg(unix)]
+use tokio::net::UnixListener;
+
+/// First file descriptor passed by the service manager
+#[cfg(unix)]
+const LISTEN_FDS_START: i32 = 3;
+
+pub enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener),
+}
+
+impl Listener {
+    /// Use the socket passed with systemd socket activation, or bind the configured one.
+    pub async fn new(server: &config::Server) -> io::Result<Self> {
+        if let Some(listener) = activated()? {
+            return Ok(listener);
+        }
+
+        match &server.unix_socket {
+            Some(path) => bind_unix(path),
+            None => Ok(Self::Tcp(TcpListener::bind(&server.bind).await?)),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Self::Tcp(listener) => listener
+                .local_addr()
+                .map_or_else(|_| "TCP socket".to_string(), |addr| addr.to_string()),
+            #[cfg(unix)]
+            Self::Unix(listener) => listener
+                .local_addr()
+                .ok()
+                .and_then(|addr| addr.as_pathname().map(|path| path.display().to_string()))
+                .unwrap_or_else(|| "Unix socket".to_string()),
+        }
+    }
+}
+
+/// Socket passed by the service manager through `LISTEN_PID` and `LISTEN_FDS`, see
+/// `sd_listen_fds(3)`. Only the first socket is used.
+#[cfg(unix)]
+fn activated() -> io::Result<Option<Listener>> {
+    use std::os::fd::{FromRawFd as _, IntoRawFd as _};
+
+    let pid = std::env::var("LISTEN_PID").ok();
+    let fds = std::env::var("LISTEN_FDS").ok();
+
+    let Some(count) = passed(pid.as_deref(), fds.as_deref(), std::process::id()) else {
+        return Ok(None);
+    };
+
+    if count > 1 {
+        tracing::warn!(count, "Only the first of the passed sockets is used");
+    }
+
+    // SAFETY: the service manager passes ownership of the descriptor to this process
+    let inherited = unsafe { std::net::TcpListener::from_raw_fd(LISTEN_FDS_START) };
+
+    // Inherited descriptors are left open on exec, duplicate it so shell commands don't
+    // get the socket too
+    let listener = inherited.try_clone()?;
+    drop(inherited);
+
+    // Only internet sockets have an IP address
+    if listener.local_addr().is_ok() {
+        listener.set_nonblocking(true)?;
+        return Ok(Some(Listener::Tcp(TcpListener::from_std(listener)?)));
+    }
+
+    // SAFETY: the descriptor was released by the TCP listener above
+    let listener = unsafe { std::os::unix::net::UnixListener::from_raw_fd(listener.into_raw_fd()) };
+
+    listener.set_nonblocking(true)?;
+    Ok(Some(Listener::Unix(UnixListener::from_std(listener)?)))
+}
+
+/// Number of sockets passed to the process `id`, `None` when they are meant for another
+/// process or there are none.
+#[cfg(unix)]
+fn passed(pid: Option<&str>, fds: Option<&str>, id: u32) -> Option<u32> {
+    if pid?.parse() != Ok(id) {
+        return None;
+    }
+
+    fds?.parse().ok().filter(|&count| count > 0)
+}
+
+#[cfg(not(unix))]
+fn activated() -> io::Result<Option<Listener>> {
+    Ok(None)
+}
+
+#[cfg(unix)]
+fn bind_unix(path: &Path) -> io::Result<Listener> {
+    use std::os::unix::fs::FileTypeExt as _;
+
+    // Remove the socket left over by a previous run, but never a regular file
+    if std::fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_socket()) {
+        std::fs::remove_file(path)?;
+    }
+
+    Ok(Listener::Unix(UnixListener::bind(path)?))
+}
+
+#[cfg(not(unix))]
+fn bind_unix(_path: &Path) -> io::Result<Listener> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "Unix sockets are not supported on this platform",
+    ))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_passed_sockets() {
+        assert_eq!(passed(Some("42"), Some("1"), 42), Some(1));
+        assert_eq!(passed(Some("42"), Some("2"), 42), Some(2));
+
+        // Meant for another process
+        assert_eq!(passed(Some("41"), Some("1"), 42), None);
+        assert_eq!(passed(Some("pid"), Some("1"), 42), None);
+        assert_eq!(passed(None, Some("1"), 42), None);
+
+        // No sockets
+        assert_eq!(passed(Some("42"), None, 42), None);
+        assert_eq!(passed(Some("42"), Some("0"), 42), None);
+        assert_eq!(passed(Some("42"), Some("many"), 42), None);
+        assert_eq!(passed(Some("42"), Some("-1"), 42), None);
+    }
+}
@@ -1,39 +1,227 @@
-use axum::extract::FromRef;
-use axum_client_ip::ClientIpSource;
+mod archive;
+mod check;
+mod cli;
+mod hooks;
+mod jobs;
+mod listen;
+#[cfg(feature = "otel")]
+mod otel;
+#[cfg(feature = "tls")]
+mod tls;
+
+use std::process::ExitCode;
+use std::sync::Arc;
+
+use axum::extract::{FromRef, FromRequestParts};
+use axum_client_ip::{ClientIp, ClientIpSource};
+use clap::Parser as _;
+use futures::FutureExt;
 use http::request::Parts;
-use opendal::{Operator, services::Memory};
+use opendal::Operator;
 use silverbullet::client::TracingLogger;
-use silverbullet::{client, fs::opendal::Filesystem, proxy, server, shell::NoShell};
+use silverbullet::config::{self, Backend};
+use silverbullet::fs::{
+    self, ReadWriteFilesystem,
+    cache::CacheLayer,
+    case_insensitive::CaseInsensitiveLayer,
+    memo,
+    normalized::NormalizeLayer,
+    opendal::Filesystem,
+    perm::{self, Perm, PermissionsLayer},
+    retry::{Backoff, RetryLayer},
+    singleflight::SingleflightLayer,
+};
+use silverbullet::sync::git::GitSync;
+use silverbullet::{backup, client, proxy, server, shell, ssr, stats};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 #[derive(Clone, FromRef)]
 pub struct AppState {
     config: client::Config,
-    operator: Operator,
+    manifest: client::ManifestConfig,
+    fs: Space,
+    shell: config::Shell,
+    proxy: config::Proxy,
+    policy: proxy::Policy,
+    breaker: proxy::circuit::Breaker,
+    /// Shared by the proxied requests, with its connection pool
+    #[cfg(feature = "proxy")]
+    client: proxy::reqwest::Client,
+    #[cfg(feature = "otel")]
+    telemetry: Option<otel::Telemetry>,
 }
 
 impl AppState {
-    pub fn new(config: client::Config, operator: Operator) -> Self {
-        Self { config, operator }
+    pub fn new(config: &config::Config, fs: Space) -> Result<Self, String> {
+        Ok(Self {
+            config: config.client(),
+            manifest: config.manifest(),
+            fs,
+            shell: config.shell.clone(),
+            proxy: config.proxy.clone(),
+            policy: config
+                .proxy
+                .policy()
+                .map_err(context("invalid proxy config"))?,
+            breaker: proxy::circuit::Breaker::default(),
+            #[cfg(feature = "proxy")]
+            client: proxy_client(&config.proxy)?,
+            #[cfg(feature = "otel")]
+            telemetry: None,
+        })
     }
 }
 
+/// Prefix errors with what failed, for the message logged before exiting.
+fn context<E: std::fmt::Display>(what: &str) -> impl FnOnce(E) -> String + '_ {
+    move |err| format!("{what}: {err}")
+}
+
+/// Storage of the space, chosen from the config at startup
+type Space = Arc<dyn ReadWriteFilesystem>;
+
 impl server::routes::fs::Provider for AppState {
-    type Output = Filesystem;
+    type Output = Space;
 
-    fn provide(&self, _parts: &mut Parts) -> Result<Self::Output, server::Error> {
-        Ok(Filesystem::new(self.operator.clone()))
+    fn provide(&self, parts: &mut Parts) -> Result<Self::Output, server::Error> {
+        let user = parts.extensions.get::<client::User>();
+
+        // Files of read-only spaces and users are read-only, and can't be written
+        let fs: Space = if self.config.read_only || user.is_some_and(|user| user.read_only) {
+            Arc::new(perm::Filesystem::new(self.fs.clone(), Perm::Read))
+        } else {
+            self.fs.clone()
+        };
+
+        // A stat per file and request is enough
+        Ok(Arc::new(memo::Filesystem::new(fs)))
     }
 }
 
 impl server::routes::shell::Provider for AppState {
-    type Output = NoShell;
+    #[cfg(feature = "shell")]
+    type Output = shell::audit::Audited<
+        shell::allowlist::Allowlist<shell::process::Shell>,
+        shell::audit::TracingSink,
+    >;
 
-    fn provide(&self) -> Self::Output {
-        NoShell::default()
+    #[cfg(not(feature = "shell"))]
+    type Output = shell::audit::Audited<
+        shell::allowlist::Allowlist<shell::NoShell>,
+        shell::audit::TracingSink,
+    >;
+
+    fn provide(&self, parts: &mut Parts) -> Result<Self::Output, server::Error> {
+        if self.config.read_only {
+            return Err(server::Error::forbidden(
+                "shell is disabled in read-only mode",
+            ));
+        }
+
+        if parts
+            .extensions
+            .get::<client::User>()
+            .is_some_and(|user| user.read_only)
+        {
+            return Err(server::Error::forbidden(
+                "read-only users can't run commands",
+            ));
+        }
+
+        let allowlist = match &self.shell.allowed_commands {
+            Some(commands) => shell::allowlist::Allowlist::new(process(&self.shell), commands),
+            None => shell::allowlist::Allowlist::allow_all(process(&self.shell)),
+        };
+
+        let shell = shell::audit::Audited::new(allowlist, shell::audit::TracingSink);
+
+        // The client IP extractor only reads headers, so it completes immediately
+        let client_ip = ClientIp::from_request_parts(parts, self)
+            .now_or_never()
+            .and_then(Result::ok);
+
+        Ok(match client_ip {
+            Some(ClientIp(ip)) => shell.requester(ip.to_string()),
+            None => shell,
+        })
     }
 }
 
+/// Shell of the `/.shell` route, restricted by the sandbox of the config
+#[cfg(feature = "shell")]
+fn process(config: &config::Shell) -> shell::process::Shell {
+    let Some(config) = &config.sandbox else {
+        return shell::process::Shell::new();
+    };
+
+    let mut sandbox = shell::sandbox::Sandbox::new().no_network(config.no_network);
+
+    for (key, value) in &config.env {
+        sandbox = sandbox.env(key, value);
+    }
+
+    if let Some(names) = &config.inherit_env {
+        sandbox = sandbox.inherit_env(names);
+    }
+
+    if let Some(dir) = &config.working_dir {
+        sandbox = sandbox.working_dir(dir);
+    }
+
+    if let Some(bytes) = config.max_output {
+        sandbox = sandbox.max_output(bytes);
+    }
+
+    #[cfg(unix)]
+    {
+        if let Some(uid) = config.uid {
+            sandbox = sandbox.uid(uid);
+        }
+
+        if let Some(gid) = config.gid {
+            sandbox = sandbox.gid(gid);
+        }
+
+        if let Some(seconds) = config.cpu_seconds {
+            sandbox = sandbox.cpu_seconds(seconds);
+        }
+
+        if let Some(bytes) = config.memory_bytes {
+            sandbox = sandbox.memory_bytes(bytes);
+        }
+    }
+
+    shell::process::Shell::new().sandbox(sandbox)
+}
+
+#[cfg(not(feature = "shell"))]
+fn process(_config: &config::Shell) -> shell::NoShell {
+    shell::NoShell::default()
+}
+
+#[cfg(feature = "proxy")]
+fn proxy_client(config: &config::Proxy) -> Result<proxy::reqwest::Client, String> {
+    let builder = proxy::reqwest::Client::builder();
+    let policy = config.policy().map_err(context("invalid proxy config"))?;
+
+    let builder = match config.upstream().map_err(context("invalid proxy config"))? {
+        Some(upstream) => builder.proxy(
+            proxy::reqwest::Proxy::all(upstream.to_string())
+                .map_err(context("invalid proxy upstream"))?,
+        ),
+        // Through an upstream only its own address is resolved, the proxy checks the targets
+        None if policy.checks_addresses() => builder.guarded(policy),
+        None => builder,
+    };
+
+    #[cfg(feature = "native-tls")]
+    let builder = builder.native_tls(true);
+
+    builder
+        .build()
+        .map_err(context("failed to build proxy client"))
+}
+
 impl server::routes::proxy::Provider for AppState {
     #[cfg(feature = "proxy")]
     type Output = proxy::reqwest::Client;
@@ -41,51 +229,630 @@ impl server::routes::proxy::Provider for AppState {
     #[cfg(not(feature = "proxy"))]
     type Output = proxy::NoProxy;
 
+    #[cfg(feature = "proxy")]
     fn provide(&self) -> Self::Output {
-        Self::Output::default()
+        self.client.clone()
+    }
+
+    #[cfg(not(feature = "proxy"))]
+    fn provide(&self) -> Self::Output {
+        proxy::NoProxy
+    }
+
+    fn proxy(&self) -> proxy::Proxy<Self::Output> {
+        let proxy = proxy::Proxy::new(self.provide())
+            .policy(self.policy.clone())
+            .retry(proxy::Retry::new(2))
+            .circuit_breaker(self.breaker.clone())
+            .forwarded_for(self.proxy.forwarded_for)
+            .base_path(self.config.url_prefix.as_deref().unwrap_or_default());
+
+        // Host names are checked too, not only IP literals
+        #[cfg(feature = "proxy")]
+        let proxy = proxy.resolver(proxy::dns::SystemResolver);
+
+        match self.proxy.timeout() {
+            Some(timeout) => proxy.timeout(timeout),
+            None => proxy,
+        }
     }
 }
 
 impl server::routes::log::Provider for AppState {
+    #[cfg(feature = "otel")]
+    type Output = (
+        TracingLogger,
+        Option<client::OtelLogger<opentelemetry_sdk::logs::SdkLogger>>,
+    );
+
+    #[cfg(not(feature = "otel"))]
     type Output = TracingLogger;
 
+    #[cfg(feature = "otel")]
+    fn provide(&self) -> Self::Output {
+        (
+            TracingLogger::new(),
+            self.telemetry.as_ref().map(otel::Telemetry::client_logger),
+        )
+    }
+
+    #[cfg(not(feature = "otel"))]
     fn provide(&self) -> Self::Output {
         TracingLogger::new()
     }
 }
 
 #[tokio::main]
-async fn main() {
-    tracing_subscriber::registry()
+async fn main() -> ExitCode {
+    let cli = cli::Cli::parse();
+
+    #[cfg(feature = "otel")]
+    let telemetry = match otel::Telemetry::new() {
+        Ok(telemetry) => telemetry,
+        // Before the subscriber is set up
+        Err(err) => {
+            eprintln!("failed to set up OpenTelemetry export: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let registry = tracing_subscriber::registry()
         .with(tracing_subscriber::fmt::layer())
-        .with(tracing_subscriber::EnvFilter::from_default_env())
-        .init();
-
-    let config = client::Config {
-        space_folder_path: "/".to_string(),
-        index_page: "index".to_string(),
-        read_only: false,
-        log_push: false,
-        enable_client_encryption: false,
+        .with(
+            tracing_subscriber::EnvFilter::builder()
+                .with_default_directive(tracing::level_filters::LevelFilter::INFO.into())
+                .from_env_lossy(),
+        );
+
+    #[cfg(feature = "otel")]
+    let registry = registry.with(telemetry.layer());
+
+    registry.init();
+
+    let code = run(
+        cli,
+        #[cfg(feature = "otel")]
+        &telemetry,
+    )
+    .await
+    .unwrap_or_else(|err| {
+        tracing::error!("{err}");
+        ExitCode::FAILURE
+    });
+
+    #[cfg(feature = "otel")]
+    telemetry.shutdown();
+
+    code
+}
+
+async fn run(
+    cli: cli::Cli,
+    #[cfg(feature = "otel")] telemetry: &otel::Telemetry,
+) -> Result<ExitCode, String> {
+    let config = cli.config().map_err(context("failed to load config"))?;
+
+    let space = filesystem(&config).map_err(context("failed to open the space storage"))?;
+
+    Ok(match cli.command {
+        None | Some(cli::Command::Serve(_)) => {
+            let space = hooks::attach(&config, space).await?;
+
+            // Counting the writes of the clients and the jobs
+            let stats = match config.stats.stats(space.clone()) {
+                Some(_) if config.space.read_only => {
+                    tracing::warn!("stats are enabled, but the space is read-only");
+                    None
+                }
+                stats => stats,
+            };
+            let space: Space = match &stats {
+                Some(stats) => Arc::new(stats.attach(space)),
+                None => space,
+            };
+
+            #[cfg(feature = "otel")]
+            let state = AppState {
+                telemetry: Some(telemetry.clone()),
+                ..AppState::new(&config, space)?
+            };
+
+            #[cfg(not(feature = "otel"))]
+            let state = AppState::new(&config, space)?;
+
+            serve(&config, state, stats).await?;
+
+            ExitCode::SUCCESS
+        }
+        Some(cli::Command::Export { target }) => {
+            archive::export(&space, &target)
+                .await
+                .map_err(context("failed to export space"))?;
+
+            ExitCode::SUCCESS
+        }
+        Some(cli::Command::Import { source, from }) => {
+            archive::import(&space, &source, from)
+                .await
+                .map_err(context("failed to import space"))?;
+
+            ExitCode::SUCCESS
+        }
+        Some(cli::Command::Bundle {
+            page,
+            target,
+            depth,
+        }) => {
+            archive::bundle(&space, &config.client(), &page, depth, &target)
+                .await
+                .map_err(context("failed to bundle page"))?;
+
+            ExitCode::SUCCESS
+        }
+        Some(cli::Command::Check { repair }) => match check::run(&config, &space, repair).await {
+            Ok(true) => ExitCode::SUCCESS,
+            Ok(false) => ExitCode::FAILURE,
+            Err(err) => {
+                tracing::error!("failed to read space: {err}");
+                ExitCode::FAILURE
+            }
+        },
+    })
+}
+
+async fn serve(
+    config: &config::Config,
+    state: AppState,
+    stats: Option<stats::Stats>,
+) -> Result<(), String> {
+    let mut builder = server::builder()
+        .shell(config.shell.enabled)
+        .request_id(true)
+        .compression(config.server.compression)
+        .openapi(config.server.openapi)
+        .boot(ssr::Jinja::new().global("url_prefix", config.server.base_path()))
+        .base_path(config.server.base_path().unwrap_or_default());
+
+    if let Some(limit) = config.rate_limit.limiter() {
+        builder = builder.rate_limit(limit);
+    }
+
+    if config.metrics.enabled {
+        builder = builder.metrics(server::metrics::Metrics::new());
+    }
+
+    if let Some(cors) = config.cors.cors() {
+        builder = builder.cors(cors);
+    }
+
+    if let Some(headers) = config.security.headers() {
+        builder = builder.security_headers(headers);
+    }
+
+    if let Some(log) = config
+        .access_log
+        .log()
+        .map_err(context("invalid access log config"))?
+    {
+        builder = builder.access_log(log);
+    }
+
+    if let Some(key) = &config.server.url_signing_key {
+        builder = builder.signed_urls(server::signed::Signer::new(key));
+    }
+
+    if let Some(plugs) = config.plugs.plugs() {
+        builder = builder.plugs(plugs);
+    }
+
+    if let Some(ingest) = config.ingest.ingest() {
+        builder = builder.ingest(ingest);
+    }
+
+    if let Some(capture) = config.capture.capture() {
+        builder = builder.capture(capture);
+    }
+
+    if let Some(calendar) = config.calendar.calendar(&config.space) {
+        builder = builder.calendar(calendar);
+    }
+
+    let renderer = ssr::Jinja::new().global("url_prefix", config.server.base_path());
+    if let Some(bundles) = config.bundle.bundles(renderer) {
+        builder = builder.bundles(bundles);
+    }
+
+    // Presigned by the bucket behind the space
+    if config.upload.enabled {
+        match &config.backend {
+            _ if config.space.read_only => {
+                tracing::warn!("uploads are enabled, but the space is read-only");
+            }
+            #[cfg(feature = "s3")]
+            Backend::S3 { .. } => {
+                let storage = Filesystem::new(
+                    operator(config).map_err(context("failed to open the bucket"))?,
+                );
+
+                if let Some(uploads) = config.upload.uploads(storage, &config.space) {
+                    builder = builder.uploads(uploads);
+                }
+            }
+            _ => tracing::warn!("uploads are enabled, but the backend isn't S3"),
+        }
+    }
+
+    // Kept in the space, unless the shares have a store of their own
+    if config.share.enabled {
+        if config.server.url_signing_key.is_none() {
+            tracing::warn!("sharing is enabled, but there's no URL signing key");
+        } else if config.share.store.is_none() && config.space.read_only {
+            tracing::warn!("sharing is enabled, but the space is read-only and has no share store");
+        } else {
+            let store = match &config.share.store {
+                Some(uri) => fs::from_uri(uri)
+                    .map_err(context("failed to open the share storage"))?
+                    .into(),
+                None => state.fs.clone(),
+            };
+            let renderer = ssr::Jinja::new().global("url_prefix", config.server.base_path());
+
+            if let Some(shares) = config.share.shares(store, renderer) {
+                builder = builder.shares(shares);
+            }
+        }
+    }
+
+    // Written for every user, but not to a read-only space
+    if let Some(templates) = config.templates.templates(state.fs.clone()) {
+        match config.space.read_only {
+            true => tracing::warn!("templates are configured, but the space is read-only"),
+            false => builder = builder.templates(templates),
+        }
+    }
+
+    #[cfg(feature = "media")]
+    if config.media.enabled {
+        let media = server::media::Media::new().cache_size(config.media.cache_size * 1024 * 1024);
+        builder = builder.media(media);
+    }
+
+    #[cfg(not(feature = "media"))]
+    if config.media.enabled {
+        tracing::warn!("media is enabled, but this build has no image support");
+    }
+
+    if !config.warm.paths.is_empty() && config.space.cache_size == 0 {
+        tracing::warn!("warm.paths are set, but without space.cache_size nothing is kept");
+    }
+
+    if !config.warm.paths.is_empty() {
+        let warmer = fs::warm::Warmer::new(state.fs.clone()).paths(config.warm.paths.clone());
+
+        match config.warm.interval() {
+            Some(interval) => {
+                tokio::spawn(warmer.run(interval));
+            }
+            None => {
+                tokio::spawn(async move {
+                    if let Err(err) = warmer.warm().await {
+                        tracing::error!(error = %err, "Failed to warm space");
+                    }
+                });
+            }
+        }
+    }
+
+    let backup = match &config.backup.target {
+        Some(target) => {
+            let target =
+                fs::from_uri(target).map_err(context("failed to open the backup storage"))?;
+            let backup = backup::Backup::new(state.fs.clone(), target.into())
+                .incremental(config.backup.incremental)
+                .keep(config.backup.keep);
+
+            if let Some(interval) = config.backup.interval() {
+                tokio::spawn(backup.clone().run(interval));
+            }
+
+            Some(backup)
+        }
+        None => None,
     };
 
-    let operator = Operator::new(Memory::default())
-        .expect("failed to create memory operator")
-        .finish();
+    let git = git_sync(config, &state)?;
+    if let (Some(git), Some(interval)) = (&git, config.git.interval()) {
+        tokio::spawn(git.clone().run(interval));
+    }
+
+    if let Some(stats) = &stats {
+        if let Some(interval) = config.stats.interval() {
+            tokio::spawn(stats.clone().run(interval));
+        }
+
+        builder = builder.stats(stats.clone());
+    }
+
+    jobs::spawn(
+        config,
+        &state.fs,
+        backup.as_ref(),
+        git.as_ref(),
+        stats.as_ref(),
+    )?;
+
+    if let Some(backup) = &backup {
+        builder = builder.backup(backup.clone());
+    }
+
+    if let Some(git) = git {
+        builder = builder.git_sync(git);
+    }
+
+    // Admin routes are only usable by authenticated users
+    if let Some(auth) = &config.auth {
+        let name = config.space.name.as_deref().unwrap_or("default");
+        let mut admin = server::admin::Admin::new().space(name, state.fs.clone());
 
-    let state = AppState::new(config, operator);
+        if let Some(backup) = backup {
+            admin = admin.backup(backup);
+        }
 
-    let app = server::router()
+        if !config.space.read_only {
+            admin = admin.gc(config
+                .gc
+                .collector(state.fs.clone())
+                .map_err(context("invalid gc config"))?);
+            admin = admin.dedupe(config.dedupe.dedupe(state.fs.clone()));
+        }
+
+        builder = builder
+            .admin(admin)
+            .auth(server::auth::Basic::new(&auth.user, &auth.password).read_only(auth.read_only));
+    }
+
+    let app = builder
+        .build()
         .layer(ClientIpSource::RightmostXForwardedFor.into_extension())
         .with_state(state);
 
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:3000")
+    let listener = listen::Listener::new(&config.server)
         .await
-        .expect("failed to bind to port 3000");
+        .map_err(|err| format!("failed to listen on {}: {err}", config.server.bind))?;
 
-    tracing::info!("listening on {}", listener.local_addr().unwrap());
+    tracing::info!("listening on {}", listener.describe());
 
-    axum::serve(listener, app)
-        .await
-        .expect("failed to start server");
+    match (listener, &config.server.tls) {
+        #[cfg(feature = "tls")]
+        (listen::Listener::Tcp(listener), Some(tls_config)) => {
+            let listener = listener
+                .into_std()
+                .map_err(context("failed to set up TLS listener"))?;
+
+            tls::serve(app, listener, tls_config).await
+        }
+        #[cfg(feature = "tls")]
+        (_, Some(_)) => return Err("TLS is not supported on Unix sockets".to_string()),
+        #[cfg(not(feature = "tls"))]
+        (_, Some(_)) => {
+            return Err(
+                "TLS is configured but the server was built without the tls feature".to_string(),
+            );
+        }
+        (listen::Listener::Tcp(listener), None) => axum::serve(listener, app).await,
+        #[cfg(unix)]
+        (listen::Listener::Unix(listener), None) => axum::serve(listener, app).await,
+    }
+    .map_err(context("failed to start server"))
+}
+
+/// Sync of the space with its git remote, running git as a local process in the clone.
+#[cfg(feature = "shell")]
+fn git_sync(config: &config::Config, state: &AppState) -> Result<Option<GitSync>, String> {
+    let Some(path) = &config.git.checkout else {
+        return Ok(None);
+    };
+
+    if config.space.read_only {
+        tracing::warn!("git sync is configured, but the space is read-only");
+        return Ok(None);
+    }
+
+    let checkout =
+        fs::from_uri(&format!("file://{path}")).map_err(context("failed to open the git clone"))?;
+    let shell = Arc::new(shell::process::Shell::new());
+
+    config
+        .git
+        .sync(state.fs.clone(), checkout.into(), shell)
+        .map_err(context("invalid git config"))
+}
+
+#[cfg(not(feature = "shell"))]
+fn git_sync(config: &config::Config, _state: &AppState) -> Result<Option<GitSync>, String> {
+    if config.git.checkout.is_some() {
+        tracing::warn!("git sync is configured, but this build can't run processes");
+    }
+
+    Ok(None)
+}
+
+fn filesystem(config: &config::Config) -> fs::Result<Space> {
+    let space: Space = match &config.backend {
+        Backend::Uri { uri } => fs::from_uri(uri)?.into(),
+        _ => Arc::new(Filesystem::new(operator(config)?)),
+    };
+
+    let space = fs::stack()
+        .option_layer(
+            config
+                .space
+                .case_insensitive
+                .then_some(CaseInsensitiveLayer),
+        )
+        .option_layer(config.space.normalize_names.then_some(NormalizeLayer))
+        .option_layer(config.space.permissions().map(PermissionsLayer::new))
+        .option_layer(
+            (config.space.cache_size > 0)
+                .then(|| CacheLayer::new(config.space.cache_size * 1024 * 1024)),
+        )
+        .option_layer(config.space.coalesce_reads.then_some(SingleflightLayer))
+        .option_layer(
+            (config.space.retries > 0).then(|| RetryLayer::new(Backoff::new(config.space.retries))),
+        )
+        .trace("space")
+        .build(space);
+
+    Ok(Arc::new(space))
+}
+
+fn operator(config: &config::Config) -> opendal::Result<Operator> {
+    use opendal::services;
+
+    Ok(match &config.backend {
+        Backend::Memory => Operator::new(services::Memory::default())?.finish(),
+        Backend::Fs { root } => {
+            let root = root.as_deref().unwrap_or(&config.space.path);
+
+            Operator::new(services::Fs::default().root(root))?.finish()
+        }
+        #[cfg(feature = "s3")]
+        Backend::S3 {
+            bucket,
+            region,
+            endpoint,
+            root,
+            access_key_id,
+            secret_access_key,
+        } => {
+            let mut builder = services::S3::default().bucket(bucket);
+
+            if let Some(region) = region {
+                builder = builder.region(region);
+            }
+            if let Some(endpoint) = endpoint {
+                builder = builder.endpoint(endpoint);
+            }
+            if let Some(root) = root {
+                builder = builder.root(root);
+            }
+            if let Some(key) = access_key_id {
+                builder = builder.access_key_id(key);
+            }
+            if let Some(secret) = secret_access_key {
+                builder = builder.secret_access_key(secret);
+            }
+
+            Operator::new(builder)?.finish()
+        }
+        Backend::Uri { .. } => unreachable!("storage URIs are opened with fs::from_uri"),
+        #[cfg(not(feature = "s3"))]
+        Backend::S3 { .. } => {
+            return Err(opendal::Error::new(
+                opendal::ErrorKind::Unsupported,
+                "the server was built without the s3 feature",
+            ));
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use server::routes::shell::Provider as _;
+
+    fn parts(user: Option<client::User>) -> Parts {
+        let (mut parts, ()) = http::Request::new(()).into_parts();
+        if let Some(user) = user {
+            parts.extensions.insert(user);
+        }
+        parts
+    }
+
+    #[test]
+    fn denies_the_shell_to_read_only_users() {
+        let config = config::Config::default();
+        let state = AppState::new(
+            &config,
+            Arc::new(Filesystem::new(operator(&config).unwrap())),
+        )
+        .unwrap();
+
+        let reader = client::User {
+            name: "reader".to_string(),
+            read_only: true,
+        };
+        let err = state.provide(&mut parts(Some(reader))).err().unwrap();
+        assert!(matches!(err, server::Error::Forbidden(_)));
+
+        let writer = client::User {
+            name: "writer".to_string(),
+            read_only: false,
+        };
+        assert!(state.provide(&mut parts(Some(writer))).is_ok());
+        assert!(state.provide(&mut parts(None)).is_ok());
+    }
+
+    #[test]
+    fn rejects_invalid_proxy_config() {
+        let mut config = config::Config::default();
+        config.proxy.allowed_methods = vec!["NOT A METHOD".to_string()];
+
+        let err = AppState::new(
+            &config,
+            Arc::new(Filesystem::new(operator(&config).unwrap())),
+        )
+        .err()
+        .unwrap();
+        assert!(err.starts_with("invalid proxy config: "), "{err}");
+    }
+
+    #[cfg(feature = "shell")]
+    #[tokio::test]
+    async fn runs_commands_in_the_sandbox() {
+        use shell::Shell as _;
+
+        let mut config = config::Shell::default();
+        config.sandbox = Some(config::Sandbox {
+            inherit_env: Some(vec!["PATH".to_string()]),
+            env: [("VISIBLE".to_string(), "yes".to_string())].into(),
+            ..Default::default()
+        });
+
+        let request = shell::Request {
+            cmd: "env".to_string(),
+            args: Vec::new(),
+            stdin: None,
+        };
+        let response = process(&config).exec(request).await.unwrap();
+        let mut names: Vec<_> = response
+            .stdout
+            .lines()
+            .filter_map(|line| line.split_once('=').map(|(name, _)| name))
+            .collect();
+        names.sort_unstable();
+
+        assert_eq!(names, ["PATH", "VISIBLE"]);
+    }
+
+    #[cfg(feature = "proxy")]
+    #[tokio::test]
+    async fn refuses_hosts_resolving_to_private_addresses() {
+        use server::routes::proxy::Provider as _;
+
+        let mut config = config::Config::default();
+        config.proxy.deny_private = true;
+        let state = AppState::new(
+            &config,
+            Arc::new(Filesystem::new(operator(&config).unwrap())),
+        )
+        .unwrap();
+
+        let request = http::Request::builder()
+            .uri("/.proxy/localhost/")
+            .body(bytes::Bytes::new())
+            .unwrap();
+        let result = state.proxy().proxy(request).await;
+        assert!(matches!(result, Err(proxy::Error::Forbidden(_))));
+    }
 }
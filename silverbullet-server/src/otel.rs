@@ -0,0 +1,60 @@
+//! OpenTelemetry export of server spans and client logs over OTLP
+//!
+//! The exporters are configured with the standard `OTEL_EXPORTER_OTLP_*` environment variables.
+
+use opentelemetry::logs::LoggerProvider as _;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_otlp::{LogExporter, SpanExporter};
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::logs::{SdkLogger, SdkLoggerProvider};
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use silverbullet::client::OtelLogger;
+
+#[derive(Clone)]
+pub struct Telemetry {
+    logs: SdkLoggerProvider,
+    traces: SdkTracerProvider,
+}
+
+impl Telemetry {
+    pub fn new() -> Result<Self, opentelemetry_otlp::ExporterBuildError> {
+        let resource = Resource::builder()
+            .with_service_name(env!("CARGO_PKG_NAME"))
+            .build();
+
+        let logs = SdkLoggerProvider::builder()
+            .with_resource(resource.clone())
+            .with_batch_exporter(LogExporter::builder().with_http().build()?)
+            .build();
+
+        let traces = SdkTracerProvider::builder()
+            .with_resource(resource)
+            .with_batch_exporter(SpanExporter::builder().with_http().build()?)
+            .build();
+
+        Ok(Self { logs, traces })
+    }
+
+    /// Layer exporting `tracing` spans, so client logs can be linked to them.
+    pub fn layer<S>(&self) -> impl tracing_subscriber::Layer<S>
+    where
+        S: tracing::Subscriber + for<'span> tracing_subscriber::registry::LookupSpan<'span>,
+    {
+        tracing_opentelemetry::layer().with_tracer(self.traces.tracer(env!("CARGO_PKG_NAME")))
+    }
+
+    pub fn client_logger(&self) -> OtelLogger<SdkLogger> {
+        OtelLogger::new(self.logs.logger("client"))
+    }
+
+    /// Flush pending spans and logs.
+    pub fn shutdown(&self) {
+        if let Err(err) = self.logs.shutdown() {
+            tracing::warn!("failed to shut down log export: {err}");
+        }
+
+        if let Err(err) = self.traces.shutdown() {
+            tracing::warn!("failed to shut down trace export: {err}");
+        }
+    }
+}
@@ -0,0 +1,58 @@
+//! TLS termination with certificates from PEM files or ACME
+
+use axum::Router;
+use axum_server::tls_rustls::RustlsConfig;
+use futures::StreamExt as _;
+use rustls_acme::{AcmeConfig, caches::DirCache};
+use silverbullet::config;
+
+pub async fn serve(
+    app: Router,
+    listener: std::net::TcpListener,
+    tls: &config::Tls,
+) -> std::io::Result<()> {
+    // Several dependencies enable rustls, which then can't pick a crypto provider itself
+    let _ = rustls::crypto::ring::default_provider().install_default();
+
+    let app = app.into_make_service();
+
+    match (&tls.cert, &tls.key, &tls.acme) {
+        (Some(cert), Some(key), _) => {
+            let config = RustlsConfig::from_pem_file(cert, key).await?;
+
+            axum_server::from_tcp_rustls(listener, config)?
+                .serve(app)
+                .await
+        }
+        (_, _, Some(acme)) => {
+            let mut state = AcmeConfig::new(&acme.domains)
+                .contact(&acme.contact)
+                .cache_option(acme.cache.clone().map(DirCache::new));
+
+            state = match &acme.directory {
+                Some(url) => state.directory(url),
+                None => state.directory_lets_encrypt(acme.production),
+            };
+
+            let mut state = state.state();
+            let acceptor = state.axum_acceptor(state.default_rustls_config());
+
+            tokio::spawn(async move {
+                while let Some(event) = state.next().await {
+                    match event {
+                        Ok(ok) => tracing::info!("ACME: {ok:?}"),
+                        Err(err) => tracing::error!("ACME: {err}"),
+                    }
+                }
+            });
+
+            tracing::info!("requesting certificates for {}", acme.domains.join(", "));
+
+            axum_server::from_tcp(listener)?
+                .acceptor(acceptor)
+                .serve(app)
+                .await
+        }
+        _ => unreachable!("TLS config is validated when loaded"),
+    }
+}
@@ -0,0 +1,198 @@
+//! Throughput of the filesystem wrappers, the `/.fs` routes and the proxy
+//!
+//! Run with `cargo bench -p silverbullet --features opendal,test-util`, optionally with a
+//! filter on the names, e.g. `-- list`. Each benchmark runs for about a second after a
+//! warmup and reports the median time per iteration of its samples.
+//!
+//! Criterion isn't a dependency of the crate, so this is a plain `harness = false` binary.
+
+use std::hint::black_box;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::{StreamExt as _, TryStreamExt as _, stream};
+use http::{Request, Response};
+use silverbullet::fs::perm::{Perm, PermissionsLayer};
+use silverbullet::fs::retry::{Backoff, RetryLayer};
+use silverbullet::fs::singleflight::SingleflightLayer;
+use silverbullet::fs::{
+    self, IncomingFileMeta, ReadOnlyFilesystem, ReadWriteFilesystem, WritableFilesystem as _,
+};
+use silverbullet::proxy;
+use silverbullet::server::test::TestServer;
+use tokio::runtime::Runtime;
+
+/// Files of the spaces listed
+const FILES: usize = 10_000;
+
+/// Body of the large puts, 16 MiB in 64 KiB chunks
+const LARGE: usize = 16 * 1024 * 1024;
+const CHUNK: usize = 64 * 1024;
+
+const SAMPLES: usize = 20;
+const TARGET: Duration = Duration::from_secs(1);
+
+struct Bench {
+    runtime: Runtime,
+    filter: Option<String>,
+}
+
+impl Bench {
+    fn new() -> Self {
+        let filter = std::env::args().skip(1).find(|arg| !arg.starts_with('-'));
+
+        Self {
+            runtime: tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .expect("runtime should build"),
+            filter,
+        }
+    }
+
+    /// Time `run`, called once per iteration, and print the median of the samples.
+    fn run<F, Fut, T>(&self, name: &str, mut run: F)
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = T>,
+    {
+        if self
+            .filter
+            .as_ref()
+            .is_some_and(|filter| !name.contains(filter))
+        {
+            return;
+        }
+
+        self.runtime.block_on(async {
+            // Warm up, and size the samples to fill the target time
+            let start = Instant::now();
+            black_box(run().await);
+            let once = start.elapsed().max(Duration::from_nanos(1));
+            let iterations = ((TARGET / SAMPLES as u32).as_nanos() / once.as_nanos()).max(1);
+
+            let mut samples = Vec::with_capacity(SAMPLES);
+            for _ in 0..SAMPLES {
+                let start = Instant::now();
+                for _ in 0..iterations {
+                    black_box(run().await);
+                }
+                samples.push(start.elapsed() / iterations as u32);
+            }
+            samples.sort();
+
+            println!("{name:<40} {:>12.3?}/iter", samples[SAMPLES / 2]);
+        });
+    }
+}
+
+fn memory() -> fs::opendal::Filesystem {
+    let operator = opendal::Operator::new(opendal::services::Memory::default())
+        .expect("memory service should build")
+        .finish();
+
+    fs::opendal::Filesystem::new(operator)
+}
+
+fn body(size: usize) -> fs::Stream {
+    let chunk = Bytes::from(vec![b'x'; CHUNK.min(size)]);
+    let chunks = size.div_ceil(CHUNK);
+
+    stream::iter((0..chunks).map(move |_| Ok(chunk.clone()))).boxed()
+}
+
+async fn fill(fs: &impl ReadWriteFilesystem) {
+    for i in 0..FILES {
+        let path = format!("Journal/{:04}/Page {i}.md", i % 100);
+
+        fs.put(&path, body(512), IncomingFileMeta::default())
+            .await
+            .expect("put should succeed");
+    }
+}
+
+/// The wrappers of the server's space, over a memory backend
+fn layered() -> impl ReadWriteFilesystem {
+    fs::stack()
+        .layer(PermissionsLayer::new(Perm::ReadWrite))
+        .layer(SingleflightLayer)
+        .layer(RetryLayer::new(Backoff::default()))
+        .build(memory())
+}
+
+async fn read(fs: &impl ReadOnlyFilesystem, path: &str) -> usize {
+    let (stream, _) = fs.get(path).await.expect("get should succeed");
+
+    stream
+        .try_fold(0, |len, chunk| async move { Ok(len + chunk.len()) })
+        .await
+        .expect("body should read")
+}
+
+/// Answers every request with the same response
+struct Canned(Response<Bytes>);
+
+#[async_trait]
+impl proxy::Client for Canned {
+    async fn send(&self, _request: Request<Bytes>) -> proxy::Result<Response<Bytes>> {
+        Ok(self.0.clone())
+    }
+}
+
+fn main() {
+    let bench = Bench::new();
+
+    let plain = memory();
+    let layered = layered();
+    bench.runtime.block_on(async {
+        fill(&plain).await;
+        fill(&layered).await;
+    });
+
+    let page = "Journal/0042/Page 42.md";
+    bench.run("fs/get", || read(&plain, page));
+    bench.run("fs/get layered", || read(&layered, page));
+    bench.run("fs/list", || plain.list());
+    bench.run("fs/list layered", || layered.list());
+    bench.run("fs/list_stream layered", || async {
+        layered.list_stream().await.unwrap().count().await
+    });
+
+    bench.run("fs/put 16 MiB", || {
+        plain.put("Large.bin", body(LARGE), IncomingFileMeta::default())
+    });
+    bench.run("fs/put 16 MiB layered", || {
+        layered.put("Large.bin", body(LARGE), IncomingFileMeta::default())
+    });
+
+    let server = TestServer::with_fs(Arc::new(layered));
+    bench.run("routes/list json", || server.get("/.fs"));
+    bench.run("routes/list packed", || {
+        let request = Request::get("/.fs")
+            .header("Accept", "application/vnd.silverbullet.packed+json")
+            .body(axum::body::Body::empty())
+            .unwrap();
+
+        server.request(request)
+    });
+    bench.run("routes/get", || {
+        server.get("/.fs/Journal/0042/Page%2042.md")
+    });
+
+    let response = Response::builder()
+        .header("content-type", "application/json")
+        .body(Bytes::from_static(br#"{"ok":true}"#))
+        .unwrap();
+    let client = Canned(response.clone());
+    let proxied = proxy::Proxy::new(Canned(response));
+    let request = || {
+        Request::get("/.proxy/example.com/api/items?page=2")
+            .header("x-proxy-header-accept", "application/json")
+            .body(Bytes::new())
+            .unwrap()
+    };
+    bench.run("proxy/client", || proxy::Client::send(&client, request()));
+    bench.run("proxy/proxy", || proxied.proxy(request()));
+}
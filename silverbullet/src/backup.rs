@@ -0,0 +1,431 @@
+//! Scheduled snapshots of a space
+//!
+//! Snapshots are tar archives written to a separate filesystem, named after the time
+//! they were taken, eg. `silverbullet-20261014T120000.000Z-full.tar`. Each archive starts
+//! with a `.silverbullet-backup.json` manifest listing every file of the space at that
+//! time. Full snapshots contain all files; incremental ones only those modified since the
+//! previous snapshot, so restoring means extracting the last full snapshot followed by
+//! the incremental ones after it, then removing the files missing from the last
+//! manifest.
+//!
+//! Run snapshots periodically with [`Backup::run`], or on demand with the server's
+//! `/.backup/trigger` route.
+
+mod tar;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use bytes::Bytes;
+use serde::Serialize;
+
+use crate::fs::{
+    self, FileMeta, IncomingFileMeta, ReadOnlyFilesystem, ReadWriteFilesystem,
+    WritableFilesystem as _,
+};
+
+const PREFIX: &str = "silverbullet-";
+const MANIFEST: &str = ".silverbullet-backup.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    Full,
+    Incremental,
+}
+
+impl Kind {
+    fn suffix(self) -> &'static str {
+        match self {
+            Self::Full => "full",
+            Self::Incremental => "incr",
+        }
+    }
+}
+
+/// A snapshot stored in the backup filesystem
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct Snapshot {
+    pub name: String,
+    pub kind: Kind,
+    /// Time the snapshot was taken, in milliseconds since the epoch
+    pub created: u64,
+    /// Files in the archive, only known for the snapshot just taken
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub files: Option<usize>,
+    /// Size of the archive in bytes
+    pub size: u64,
+}
+
+impl Snapshot {
+    /// Parse the name of a snapshot archive.
+    fn parse(name: &str) -> Option<(Kind, u64)> {
+        let rest = name.strip_prefix(PREFIX)?.strip_suffix(".tar")?;
+        let (time, kind) = rest.rsplit_once('-')?;
+
+        let kind = match kind {
+            "full" => Kind::Full,
+            "incr" => Kind::Incremental,
+            _ => return None,
+        };
+
+        Some((kind, parse_time(time)?))
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Manifest<'a> {
+    kind: Kind,
+    created: u64,
+    /// Files modified after this time are in the archive, for incremental snapshots
+    since: Option<u64>,
+    files: &'a [FileMeta],
+}
+
+/// Takes snapshots of a space to a backup filesystem
+///
+/// Clones share a lock, so snapshots never run concurrently.
+#[derive(Clone)]
+pub struct Backup {
+    source: Arc<dyn ReadOnlyFilesystem>,
+    target: Arc<dyn ReadWriteFilesystem>,
+    incremental: u32,
+    keep: usize,
+    lock: Arc<futures::lock::Mutex<()>>,
+}
+
+impl Backup {
+    /// Only take full snapshots and keep the last 7 by default.
+    pub fn new(source: Arc<dyn ReadOnlyFilesystem>, target: Arc<dyn ReadWriteFilesystem>) -> Self {
+        Self {
+            source,
+            target,
+            incremental: 0,
+            keep: 7,
+            lock: Arc::default(),
+        }
+    }
+
+    /// Take up to `count` incremental snapshots between full ones.
+    #[must_use]
+    pub fn incremental(mut self, count: u32) -> Self {
+        self.incremental = count;
+        self
+    }
+
+    /// Keep the last `count` full snapshots, with the incremental ones following them.
+    #[must_use]
+    pub fn keep(mut self, count: usize) -> Self {
+        self.keep = count.max(1);
+        self
+    }
+
+    /// Snapshots in the backup filesystem, oldest first.
+    pub async fn snapshots(&self) -> fs::Result<Vec<Snapshot>> {
+        let mut snapshots: Vec<_> = self
+            .target
+            .list()
+            .await?
+            .into_iter()
+            .filter_map(|meta| {
+                let (kind, created) = Snapshot::parse(&meta.name)?;
+
+                Some(Snapshot {
+                    name: meta.name,
+                    kind,
+                    created,
+                    files: None,
+                    size: meta.size,
+                })
+            })
+            .collect();
+
+        snapshots.sort_by_key(|snapshot| snapshot.created);
+
+        Ok(snapshots)
+    }
+
+    /// Take a snapshot, full or incremental depending on the previous ones, then remove
+    /// the snapshots past retention.
+    pub async fn snapshot(&self) -> fs::Result<Snapshot> {
+        let _guard = self.lock.lock().await;
+
+        let snapshots = self.snapshots().await?;
+        let since_full = snapshots
+            .iter()
+            .rev()
+            .take_while(|snapshot| snapshot.kind == Kind::Incremental)
+            .count();
+
+        let previous = snapshots.last().filter(|_| {
+            since_full < self.incremental as usize
+                && snapshots.iter().any(|snapshot| snapshot.kind == Kind::Full)
+        });
+
+        // Taken before listing, so files changed while listing are in the next snapshot
+        let created = fs::time::now();
+        let files = self.source.list().await?;
+
+        let (kind, since, included) = match previous {
+            Some(previous) => (
+                Kind::Incremental,
+                Some(previous.created),
+                files
+                    .iter()
+                    .filter(|meta| meta.last_modified >= previous.created)
+                    .cloned()
+                    .collect(),
+            ),
+            None => (Kind::Full, None, files.clone()),
+        };
+
+        let manifest = serde_json::to_vec_pretty(&Manifest {
+            kind,
+            created,
+            since,
+            files: &files,
+        })
+        .map_err(|err| fs::Error::Other(err.into()))?;
+
+        let name = format!("{PREFIX}{}-{}.tar", format_time(created), kind.suffix());
+        let files = included.len();
+
+        let archive = tar::archive(
+            self.source.clone(),
+            vec![(MANIFEST.to_string(), Bytes::from(manifest))],
+            included,
+        );
+
+        let meta = self
+            .target
+            .put(
+                &name,
+                archive,
+                IncomingFileMeta {
+                    content_type: Some("application/x-tar".to_string()),
+                    created: Some(created),
+                    ..Default::default()
+                },
+            )
+            .await?;
+
+        let snapshot = Snapshot {
+            name,
+            kind,
+            created,
+            files: Some(files),
+            size: meta.size,
+        };
+
+        #[cfg(feature = "tracing")]
+        tracing::info!(
+            snapshot = snapshot.name,
+            files,
+            size = snapshot.size,
+            "Backup snapshot taken"
+        );
+
+        let mut snapshots = snapshots;
+        snapshots.push(snapshot.clone());
+        self.prune(&snapshots).await?;
+
+        Ok(snapshot)
+    }
+
+    /// Delete the snapshots older than the last `keep` full ones.
+    async fn prune(&self, snapshots: &[Snapshot]) -> fs::Result<()> {
+        for snapshot in expired(snapshots, self.keep) {
+            self.target.delete(&snapshot.name).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Take snapshots forever, every `interval`.
+    ///
+    /// Failures are logged with the `tracing` feature and retried at the next interval.
+    pub async fn run(self, interval: Duration) {
+        loop {
+            futures_timer::Delay::new(interval).await;
+
+            #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+            if let Err(err) = self.snapshot().await {
+                #[cfg(feature = "tracing")]
+                tracing::error!(error = %err, "Backup snapshot failed");
+            }
+        }
+    }
+}
+
+fn expired(snapshots: &[Snapshot], keep: usize) -> impl Iterator<Item = &Snapshot> {
+    let oldest_kept = snapshots
+        .iter()
+        .enumerate()
+        .filter(|(_, snapshot)| snapshot.kind == Kind::Full)
+        .map(|(index, _)| index)
+        .rev()
+        .nth(keep - 1)
+        .unwrap_or(0);
+
+    snapshots[..oldest_kept].iter()
+}
+
+/// Format a time in milliseconds as `20261014T120000.000Z`, in UTC.
+fn format_time(millis: u64) -> String {
+    let secs = millis / 1000;
+    let (year, month, day) = fs::time::civil_from_days((secs / 86_400) as i64);
+    let time = secs % 86_400;
+
+    format!(
+        "{year:04}{month:02}{day:02}T{:02}{:02}{:02}.{:03}Z",
+        time / 3600,
+        time % 3600 / 60,
+        time % 60,
+        millis % 1000
+    )
+}
+
+fn parse_time(text: &str) -> Option<u64> {
+    let text = text.strip_suffix('Z')?;
+    let (date, time) = text.split_once('T')?;
+    let (time, millis) = time.split_once('.')?;
+
+    if date.len() != 8 || time.len() != 6 || millis.len() != 3 {
+        return None;
+    }
+
+    let number = |s: &str| s.parse::<u64>().ok();
+
+    let days = fs::time::days_from_civil(
+        number(&date[..4])? as i64,
+        number(&date[4..6])? as u8,
+        number(&date[6..])? as u8,
+    );
+    let secs = u64::try_from(days).ok()? * 86_400
+        + number(&time[..2])? * 3600
+        + number(&time[2..4])? * 60
+        + number(&time[4..])?;
+
+    Some(secs * 1000 + number(millis)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_snapshots() {
+        let millis = 1_791_979_200_123;
+        assert_eq!(format_time(millis), "20261014T120000.123Z");
+        assert_eq!(parse_time("20261014T120000.123Z"), Some(millis));
+
+        assert_eq!(
+            Snapshot::parse("silverbullet-20261014T120000.123Z-incr.tar"),
+            Some((Kind::Incremental, millis))
+        );
+        assert_eq!(Snapshot::parse("notes.tar"), None);
+    }
+
+    #[test]
+    fn keeps_last_full_snapshots() {
+        let snapshot = |kind, created: u64| Snapshot {
+            name: created.to_string(),
+            kind,
+            created,
+            files: None,
+            size: 0,
+        };
+
+        let snapshots = [
+            snapshot(Kind::Full, 1),
+            snapshot(Kind::Incremental, 2),
+            snapshot(Kind::Full, 3),
+            snapshot(Kind::Incremental, 4),
+            snapshot(Kind::Full, 5),
+        ];
+
+        let names = |keep| {
+            expired(&snapshots, keep)
+                .map(|snapshot| snapshot.created)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(names(1), [1, 2, 3, 4]);
+        assert_eq!(names(2), [1, 2]);
+        assert!(names(3).is_empty());
+    }
+
+    #[cfg(feature = "opendal")]
+    #[tokio::test]
+    async fn takes_snapshots() {
+        use ::opendal::{Operator, services};
+        use futures::TryStreamExt as _;
+
+        use crate::fs::StreamExt as _;
+
+        let filesystem = |builder| Arc::new(fs::opendal::Filesystem::new(builder));
+
+        // The memory service doesn't keep modification times, the space needs them
+        let root = std::env::temp_dir().join(format!("silverbullet-backup-{}", std::process::id()));
+        let space = filesystem(
+            Operator::new(services::Fs::default().root(root.to_str().unwrap()))
+                .unwrap()
+                .finish(),
+        );
+        let target = filesystem(Operator::new(services::Memory::default()).unwrap().finish());
+
+        let put = async |fs: &fs::opendal::Filesystem, name: &str, content: &'static [u8]| {
+            let data = futures::stream::once(std::future::ready(Ok(Bytes::from_static(content))));
+            fs.put(name, data.into_boxed(), IncomingFileMeta::default())
+                .await
+                .unwrap();
+
+            // Keep modification times apart from snapshot times, file times lag the clock by
+            // a few milliseconds
+            std::thread::sleep(Duration::from_millis(20));
+        };
+
+        let backup = Backup::new(space.clone(), target.clone())
+            .incremental(1)
+            .keep(1);
+
+        put(&space, "index.md", b"# Index").await;
+        put(&space, "notes/page.md", b"hello").await;
+
+        let full = backup.snapshot().await.unwrap();
+        assert_eq!((full.kind, full.files), (Kind::Full, Some(2)));
+
+        let (stream, _) = target.get(&full.name).await.unwrap();
+        let archive: Vec<Bytes> = stream.try_collect().await.unwrap();
+        let archive = archive.concat();
+        assert_eq!(&archive[..MANIFEST.len()], MANIFEST.as_bytes());
+        assert_eq!(archive.len() as u64, full.size);
+        assert_eq!(archive.len() % 512, 0);
+
+        std::thread::sleep(Duration::from_millis(20));
+        put(&space, "notes/page.md", b"hello world").await;
+
+        let incremental = backup.snapshot().await.unwrap();
+        assert_eq!(
+            (incremental.kind, incremental.files),
+            (Kind::Incremental, Some(1))
+        );
+
+        // The incremental count is reached, older snapshots past retention are removed
+        let next = backup.snapshot().await.unwrap();
+        assert_eq!(next.kind, Kind::Full);
+        assert_eq!(
+            backup.snapshots().await.unwrap(),
+            [Snapshot {
+                files: None,
+                ..next
+            }]
+        );
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
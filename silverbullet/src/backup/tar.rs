@@ -0,0 +1,228 @@
+//! Streaming writer for tar archives in the GNU format
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use futures::StreamExt as _;
+
+use crate::fs::{self, FileMeta, ReadOnlyFilesystem, StreamExt as _};
+
+const BLOCK: usize = 512;
+
+/// Stream an archive of `files` read from `source`, after `extra` in-memory entries.
+///
+/// Entry sizes are taken when each file is opened; files that change while being read
+/// are truncated or zero-padded to that size so the archive stays valid, and files
+/// deleted since they were listed are skipped.
+pub(super) fn archive(
+    source: Arc<dyn ReadOnlyFilesystem>,
+    extra: Vec<(String, Bytes)>,
+    files: Vec<FileMeta>,
+) -> fs::Stream {
+    let mut pending = VecDeque::new();
+
+    for (name, content) in extra {
+        pending.extend(header(&name, content.len() as u64, now_secs()));
+        pending.push_back(content.clone());
+        pending.extend(padding(content.len() as u64));
+    }
+
+    let writer = Writer {
+        source,
+        files: files.into(),
+        pending,
+        current: None,
+        finished: false,
+    };
+
+    futures::stream::try_unfold(writer, |mut writer| async move {
+        Ok(writer.next_chunk().await?.map(|chunk| (chunk, writer)))
+    })
+    .into_boxed()
+}
+
+struct Writer {
+    source: Arc<dyn ReadOnlyFilesystem>,
+    files: VecDeque<FileMeta>,
+    pending: VecDeque<Bytes>,
+    current: Option<Entry>,
+    finished: bool,
+}
+
+struct Entry {
+    stream: fs::Stream,
+    size: u64,
+    remaining: u64,
+}
+
+impl Writer {
+    async fn next_chunk(&mut self) -> std::io::Result<Option<Bytes>> {
+        loop {
+            if let Some(chunk) = self.pending.pop_front() {
+                return Ok(Some(chunk));
+            }
+
+            if let Some(entry) = &mut self.current {
+                if entry.remaining > 0 {
+                    let Some(chunk) = entry.stream.next().await else {
+                        // The file shrank since it was opened
+                        let missing = entry.remaining;
+                        entry.remaining = 0;
+                        return Ok(Some(zeros(missing as usize)));
+                    };
+
+                    let mut chunk = chunk?;
+                    chunk.truncate(entry.remaining.min(chunk.len() as u64) as usize);
+                    entry.remaining -= chunk.len() as u64;
+
+                    if !chunk.is_empty() {
+                        return Ok(Some(chunk));
+                    }
+
+                    continue;
+                }
+
+                self.pending.extend(padding(entry.size));
+                self.current = None;
+                continue;
+            }
+
+            let Some(meta) = self.files.pop_front() else {
+                if self.finished {
+                    return Ok(None);
+                }
+
+                self.finished = true;
+                return Ok(Some(zeros(2 * BLOCK)));
+            };
+
+            let (stream, meta) = match self.source.get(&meta.name).await {
+                Ok(file) => file,
+                Err(fs::Error::NotFound(_)) => continue,
+                Err(err) => return Err(std::io::Error::other(err)),
+            };
+
+            self.pending
+                .extend(header(&meta.name, meta.size, meta.last_modified / 1000));
+            self.current = Some(Entry {
+                stream,
+                size: meta.size,
+                remaining: meta.size,
+            });
+        }
+    }
+}
+
+fn now_secs() -> u64 {
+    crate::fs::time::as_secs(crate::fs::time::now())
+}
+
+fn zeros(len: usize) -> Bytes {
+    Bytes::from(vec![0; len])
+}
+
+fn padding(size: u64) -> Option<Bytes> {
+    let remainder = (size % BLOCK as u64) as usize;
+
+    (remainder != 0).then(|| zeros(BLOCK - remainder))
+}
+
+/// Header blocks of an entry, preceded by a GNU long name entry for names over 100 bytes.
+fn header(name: &str, size: u64, mtime: u64) -> Vec<Bytes> {
+    let name = name.trim_start_matches('/');
+    let mut blocks = Vec::new();
+
+    if name.len() > 100 {
+        let mut long_name = name.as_bytes().to_vec();
+        long_name.push(0);
+
+        let len = long_name.len() as u64;
+
+        blocks.push(header_block(b"././@LongLink", len, 0, b'L'));
+        blocks.push(Bytes::from(long_name));
+        blocks.extend(padding(len));
+    }
+
+    let short_name = &name.as_bytes()[..name.len().min(100)];
+    blocks.push(header_block(short_name, size, mtime, b'0'));
+
+    blocks
+}
+
+fn header_block(name: &[u8], size: u64, mtime: u64, kind: u8) -> Bytes {
+    let mut block = [0u8; BLOCK];
+
+    block[..name.len()].copy_from_slice(name);
+    octal(&mut block[100..108], 0o644);
+    octal(&mut block[108..116], 0);
+    octal(&mut block[116..124], 0);
+    numeric(&mut block[124..136], size);
+    numeric(&mut block[136..148], mtime);
+    block[156] = kind;
+    block[257..265].copy_from_slice(b"ustar  \0");
+
+    // The checksum is computed with its own field set to spaces
+    block[148..156].fill(b' ');
+    let checksum: u32 = block.iter().map(|b| u32::from(*b)).sum();
+    octal(&mut block[148..155], u64::from(checksum));
+
+    Bytes::copy_from_slice(&block)
+}
+
+/// Zero-padded octal number followed by a NUL.
+fn octal(field: &mut [u8], value: u64) {
+    let digits = field.len() - 1;
+    let text = format!("{value:0digits$o}");
+
+    field[..digits].copy_from_slice(&text.as_bytes()[text.len() - digits..]);
+    field[digits] = 0;
+}
+
+/// Octal when it fits, GNU base-256 otherwise (files of 8 GiB and more).
+fn numeric(field: &mut [u8], value: u64) {
+    if value < 1 << (3 * (field.len() - 1)) {
+        octal(field, value);
+    } else {
+        field.fill(0);
+        field[0] = 0x80;
+        let len = field.len();
+        field[len - 8..].copy_from_slice(&value.to_be_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_headers() {
+        let blocks = header("notes/page.md", 5, 1_700_000_000);
+        assert_eq!(blocks.len(), 1);
+
+        let block = &blocks[0];
+        assert_eq!(&block[..13], b"notes/page.md");
+        assert_eq!(&block[124..136], b"00000000005\0");
+        assert_eq!(block[156], b'0');
+
+        // Checksum of the block with the checksum field as spaces
+        let mut unsummed = block.to_vec();
+        unsummed[148..156].fill(b' ');
+        let sum: u32 = unsummed.iter().map(|b| u32::from(*b)).sum();
+        assert_eq!(
+            std::str::from_utf8(&block[148..154]).unwrap(),
+            format!("{sum:06o}")
+        );
+
+        let long = "a/".repeat(60) + "page.md";
+        let blocks = header(&long, 0, 0);
+        assert_eq!(blocks[0][156], b'L');
+        assert_eq!(blocks.iter().map(Bytes::len).sum::<usize>(), 3 * BLOCK);
+    }
+
+    #[test]
+    fn pads_to_blocks() {
+        assert!(padding(1024).is_none());
+        assert_eq!(padding(5).unwrap().len(), 507);
+    }
+}
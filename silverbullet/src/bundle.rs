@@ -0,0 +1,461 @@
+//! Bundles of a page and the pages it links, e.g. to hand a topic to someone outside the
+//! space
+//!
+//! [`Bundle::collect`] follows the wiki links of a page, `[[page]]` and `![[page]]`, to
+//! the pages they lead to, and theirs in turn, up to a depth: 0 only bundles the page, 1
+//! also the pages it links, and so on. The files the pages link are collected along,
+//! `![[diagram.png]]` and `[[report.pdf]]` from the root of the space, and
+//! `![diagram](diagram.png)` from the folder of the page, or the root with a leading `/`.
+//! Links in fenced code and to pages that don't exist are left out, and a bundle has at
+//! most [`MAX_PAGES`] pages.
+//!
+//! A bundle is written as a zip archive of its pages and files with [`Bundle::zip`], or
+//! as a single HTML document with [`Bundle::html`], its files embedded as `data:` URLs,
+//! so it opens without the space.
+
+use std::collections::{HashSet, VecDeque};
+use std::io::{Cursor, Write as _};
+
+use base64::Engine as _;
+use futures::TryStreamExt as _;
+use thiserror::Error;
+use zip::write::SimpleFileOptions;
+
+use crate::client;
+use crate::fs::{self, FileMeta, ReadOnlyFilesystem, time};
+use crate::ssr::{self, Attachment, Document, Renderer};
+
+/// Pages of a bundle at most
+pub const MAX_PAGES: usize = 1000;
+
+const IMAGES: [&str; 7] = ["png", "jpg", "jpeg", "gif", "webp", "svg", "avif"];
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("Page not found: {0}")]
+    NotFound(String),
+
+    #[error(transparent)]
+    Fs(#[from] fs::Error),
+
+    #[error("Failed to write zip archive: {0}")]
+    Zip(#[from] zip::result::ZipError),
+
+    #[error(transparent)]
+    Render(#[from] ssr::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Page of a bundle
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Page {
+    pub name: String,
+    pub content: String,
+    /// Links followed from the first page to this one
+    pub depth: usize,
+}
+
+/// Pages and files of a bundle
+#[derive(Debug, Clone)]
+pub struct Bundle {
+    /// Pages in the order they were reached, the first page first
+    pub pages: Vec<Page>,
+    pub files: Vec<FileMeta>,
+}
+
+/// Link of a page
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Link {
+    Page(String),
+    File(String),
+}
+
+impl Bundle {
+    /// Collect `page` and the pages it links, `depth` links away at most.
+    pub async fn collect<F>(fs: &F, page: &str, depth: usize) -> Result<Self>
+    where
+        F: ReadOnlyFilesystem + ?Sized,
+    {
+        let page = page.trim_matches('/');
+        let Some(content) = read(fs, page).await? else {
+            return Err(Error::NotFound(page.to_string()));
+        };
+
+        let mut seen = HashSet::from([page.to_string()]);
+        let mut queue = VecDeque::from([(page.to_string(), content, 0)]);
+        let mut pages = Vec::new();
+        let mut files = Vec::new();
+        let mut linked = HashSet::new();
+
+        while let Some((name, content, at)) = queue.pop_front() {
+            for link in links(&name, &content) {
+                match link {
+                    Link::Page(target) => {
+                        if at >= depth || seen.len() >= MAX_PAGES || !seen.insert(target.clone()) {
+                            continue;
+                        }
+                        if let Some(content) = read(fs, &target).await? {
+                            queue.push_back((target, content, at + 1));
+                        }
+                    }
+                    Link::File(path) => {
+                        if !linked.insert(path.clone()) {
+                            continue;
+                        }
+                        match fs.meta(&path).await {
+                            Ok(meta) => files.push(meta),
+                            Err(fs::Error::NotFound(_)) => {}
+                            Err(err) => return Err(err.into()),
+                        }
+                    }
+                }
+            }
+
+            pages.push(Page {
+                name,
+                content,
+                depth: at,
+            });
+        }
+
+        Ok(Self { pages, files })
+    }
+
+    /// Zip archive of the pages, as `.md` files, and of the files, at their paths.
+    pub async fn zip<F>(&self, fs: &F) -> Result<Vec<u8>>
+    where
+        F: ReadOnlyFilesystem + ?Sized,
+    {
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options =
+            SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+
+        for page in &self.pages {
+            zip.start_file(format!("{}.md", page.name), options)?;
+            zip.write_all(page.content.as_bytes())
+                .map_err(fs::Error::from)?;
+        }
+
+        for meta in &self.files {
+            let mut options = options.large_file(meta.size >= u64::from(u32::MAX));
+            if let Some(time) = zip_time(meta.last_modified) {
+                options = options.last_modified_time(time);
+            }
+
+            zip.start_file(meta.name.as_str(), options)?;
+            zip.write_all(&read_file(fs, &meta.name).await?)
+                .map_err(fs::Error::from)?;
+        }
+
+        Ok(zip.finish()?.into_inner())
+    }
+
+    /// The pages as a single HTML document, rendered by the `bundle.html` template, the
+    /// files embedded.
+    pub async fn html<F>(
+        &self,
+        fs: &F,
+        renderer: &dyn Renderer,
+        config: &client::Config,
+    ) -> Result<String>
+    where
+        F: ReadOnlyFilesystem + ?Sized,
+    {
+        let mut attachments = Vec::with_capacity(self.files.len());
+        for meta in &self.files {
+            let data = read_file(fs, &meta.name).await?;
+            let content_type = match meta.content_type.is_empty() {
+                true => "application/octet-stream",
+                false => meta.content_type.as_str(),
+            };
+
+            attachments.push(Attachment {
+                name: meta.name.clone(),
+                url: format!(
+                    "data:{content_type};base64,{}",
+                    base64::engine::general_purpose::STANDARD.encode(data)
+                ),
+                image: meta.name.rsplit_once('.').is_some_and(|(_, extension)| {
+                    IMAGES.contains(&extension.to_ascii_lowercase().as_str())
+                }),
+            });
+        }
+
+        let pages: Vec<ssr::Page> = self
+            .pages
+            .iter()
+            .map(|page| ssr::Page {
+                name: &page.name,
+                content: &page.content,
+            })
+            .collect();
+        let document = Document {
+            config,
+            title: self.pages.first().map_or("", |page| page.name.as_str()),
+            pages: &pages,
+            attachments: &attachments,
+        };
+
+        Ok(document.render(renderer).await?)
+    }
+}
+
+/// Links of the page `name`, outside fenced code, in order.
+fn links(name: &str, content: &str) -> Vec<Link> {
+    let folder = name.rsplit_once('/').map(|(folder, _)| folder);
+    let mut links = Vec::new();
+    let mut fenced = false;
+
+    for line in content.lines() {
+        if line.trim_start().starts_with("```") {
+            fenced = !fenced;
+            continue;
+        }
+        if fenced {
+            continue;
+        }
+
+        let mut rest = line;
+        while let Some(at) = rest.find(['[', '!']) {
+            rest = &rest[at..];
+
+            if let Some(inner) = rest.strip_prefix("![[").or_else(|| rest.strip_prefix("[["))
+                && let Some(end) = inner.find("]]")
+            {
+                let target = inner[..end]
+                    .split(['|', '#', '@'])
+                    .next()
+                    .unwrap_or_default();
+                let target = target.trim().trim_start_matches('/');
+                if !target.is_empty() && !leaves(target) {
+                    links.push(match is_file(target) {
+                        true => Link::File(target.to_string()),
+                        false => Link::Page(target.to_string()),
+                    });
+                }
+                rest = &inner[end + 2..];
+            } else if let Some(inner) = rest.strip_prefix("![")
+                && let Some(close) = inner.find("](")
+                && let Some(len) = inner[close + 2..].find([')', ' '])
+            {
+                let target = inner[close + 2..close + 2 + len].trim_matches(['<', '>']);
+                let path = match (target.strip_prefix('/'), folder) {
+                    (Some(path), _) => path.to_string(),
+                    (None, Some(folder)) => format!("{folder}/{target}"),
+                    (None, None) => target.to_string(),
+                };
+                if is_file(&path) && !path.contains(':') {
+                    links.push(Link::File(decode(&path)));
+                }
+                rest = &inner[close + 2 + len..];
+            } else {
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    links
+}
+
+/// Whether a link leaves the space, or the folder it's relative to.
+fn leaves(path: &str) -> bool {
+    path.split('/')
+        .any(|segment| segment == ".." || segment == ".")
+}
+
+/// Whether a link is a file, rather than a page, and stays in the space.
+fn is_file(path: &str) -> bool {
+    let name = path.rsplit('/').next().unwrap_or(path);
+
+    !leaves(path)
+        && name.rsplit_once('.').is_some_and(|(stem, extension)| {
+            !stem.is_empty() && !extension.contains(' ') && extension != "md"
+        })
+}
+
+/// Decode the `%20`s and such of a Markdown link.
+fn decode(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn zip_time(millis: u64) -> Option<zip::DateTime> {
+    let secs = time::as_secs(millis) as i64;
+    let (year, month, day) = time::civil_from_days(secs.div_euclid(86_400));
+    let secs = secs.rem_euclid(86_400);
+
+    zip::DateTime::from_date_and_time(
+        u16::try_from(year).ok()?,
+        month,
+        day,
+        (secs / 3600) as u8,
+        (secs / 60 % 60) as u8,
+        (secs % 60) as u8,
+    )
+    .ok()
+}
+
+/// Text of a page, `None` if it doesn't exist.
+async fn read<F>(fs: &F, name: &str) -> fs::Result<Option<String>>
+where
+    F: ReadOnlyFilesystem + ?Sized,
+{
+    match read_file(fs, &format!("{name}.md")).await {
+        Ok(bytes) => Ok(Some(String::from_utf8_lossy(&bytes).into_owned())),
+        Err(fs::Error::NotFound(_)) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+async fn read_file<F>(fs: &F, path: &str) -> fs::Result<Vec<u8>>
+where
+    F: ReadOnlyFilesystem + ?Sized,
+{
+    let (stream, _) = fs.get(path).await?;
+
+    Ok(stream
+        .try_fold(Vec::new(), |mut acc, chunk| async move {
+            acc.extend_from_slice(&chunk);
+            Ok(acc)
+        })
+        .await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::testing::MemoryFs;
+    use crate::ssr::Jinja;
+    use std::io::Read as _;
+
+    fn space() -> MemoryFs {
+        MemoryFs::new()
+            .with_file(
+                "Topic.md",
+                b"# Topic\nSee [[Projects/Launch|the launch]] and [[Missing]].\n![[banner.png]]",
+            )
+            .with_file(
+                "Projects/Launch.md",
+                b"![plan](Plan%201.svg)\n[[Topic]] [[Projects/Budget#Q3]]\n```\n[[Secret]]\n```",
+            )
+            .with_file("Projects/Budget.md", b"[[Far away]]")
+            .with_file("Projects/Plan 1.svg", b"<svg/>")
+            .with_file("banner.png", b"png")
+            .with_file("Secret.md", b"secret")
+            .with_file("Far away.md", b"far")
+    }
+
+    #[test]
+    fn finds_links() {
+        assert_eq!(
+            links(
+                "Projects/Launch",
+                "[[Topic]] ![[/Files/a.pdf]] ![x](b.png \"B\") ![y](/c.png) ![z](https://x/y.png)\n\
+                 [text](d.png) [[../up.png]] [[Notes v1.2 draft]]"
+            ),
+            [
+                Link::Page("Topic".to_string()),
+                Link::File("Files/a.pdf".to_string()),
+                Link::File("Projects/b.png".to_string()),
+                Link::File("c.png".to_string()),
+                Link::Page("Notes v1.2 draft".to_string()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn collects_linked_pages() {
+        let fs = space();
+
+        let bundle = Bundle::collect(&fs, "Topic", 0).await.unwrap();
+        assert_eq!(bundle.pages.len(), 1);
+        assert_eq!(bundle.files.len(), 1);
+
+        let bundle = Bundle::collect(&fs, "Topic", 2).await.unwrap();
+        let pages: Vec<_> = bundle
+            .pages
+            .iter()
+            .map(|page| (page.name.as_str(), page.depth))
+            .collect();
+        assert_eq!(
+            pages,
+            [("Topic", 0), ("Projects/Launch", 1), ("Projects/Budget", 2)]
+        );
+        let files: Vec<_> = bundle.files.iter().map(|meta| meta.name.as_str()).collect();
+        assert_eq!(files, ["banner.png", "Projects/Plan 1.svg"]);
+
+        assert!(matches!(
+            Bundle::collect(&fs, "Missing", 1).await,
+            Err(Error::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn writes_zip_archives() {
+        let fs = space();
+        let bundle = Bundle::collect(&fs, "Topic", 1).await.unwrap();
+
+        let mut zip = zip::ZipArchive::new(Cursor::new(bundle.zip(&fs).await.unwrap())).unwrap();
+        let mut names: Vec<_> = zip.file_names().collect();
+        names.sort_unstable();
+        assert_eq!(
+            names,
+            [
+                "Projects/Launch.md",
+                "Projects/Plan 1.svg",
+                "Topic.md",
+                "banner.png"
+            ]
+        );
+
+        let mut svg = String::new();
+        zip.by_name("Projects/Plan 1.svg")
+            .unwrap()
+            .read_to_string(&mut svg)
+            .unwrap();
+        assert_eq!(svg, "<svg/>");
+    }
+
+    #[tokio::test]
+    async fn renders_documents() {
+        let fs = space();
+        let bundle = Bundle::collect(&fs, "Topic", 1).await.unwrap();
+
+        let html = bundle
+            .html(&fs, &Jinja::new(), &client::Config::default())
+            .await
+            .unwrap();
+
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<title>Topic - SilverBullet</title>"));
+        assert!(
+            html.contains(r##"<li><a href="#page-projectslaunch">Projects&#x2f;Launch</a></li>"##)
+        );
+        assert!(html.contains(r#"<article id="page-topic">"#));
+        assert!(html.contains(r#"<h1 id="page-topic-topic">Topic</h1>"#));
+        // png, embedded
+        assert!(html.contains(";base64,cG5n"));
+    }
+}
@@ -1,3 +1,5 @@
+use std::collections::BTreeMap;
+
 use serde::{Deserialize, Serialize};
 
 #[cfg(feature = "tracing")]
@@ -6,7 +8,18 @@ mod tracing;
 #[cfg(feature = "tracing")]
 pub use tracing::*;
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[cfg(all(feature = "file-log", not(target_arch = "wasm32")))]
+mod file;
+#[cfg(all(feature = "file-log", not(target_arch = "wasm32")))]
+pub use file::*;
+
+#[cfg(feature = "otel")]
+mod otel;
+#[cfg(feature = "otel")]
+pub use otel::*;
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct Config {
     pub space_folder_path: String,
@@ -14,9 +27,68 @@ pub struct Config {
     pub read_only: bool,
     pub log_push: bool,
     pub enable_client_encryption: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub space_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub space_description: Option<String>,
+    /// Name of the authenticated user
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    /// Permission of the user on the space (`rw` or `ro`)
+    #[serde(default = "default_perm")]
+    pub perm: String,
+    #[serde(default)]
+    pub sync: SyncConfig,
+    /// Client features switched on or off by the server
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub features: BTreeMap<String, bool>,
+    /// Path prefix the server is mounted under, e.g. `/notes`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url_prefix: Option<String>,
+}
+
+impl Config {
+    /// Restrict the config to what the user may do.
+    pub fn for_user(mut self, user: Option<&User>) -> Self {
+        if let Some(user) = user {
+            self.user = Some(user.name.clone());
+            self.read_only |= user.read_only;
+        }
+
+        self.perm = if self.read_only { "ro" } else { "rw" }.to_string();
+        self
+    }
+}
+
+/// Normalize a URL prefix to `/a/b` form, `None` meaning the root.
+pub fn normalize_url_prefix(path: &str) -> Option<String> {
+    let path = path.trim().trim_matches('/');
+
+    (!path.is_empty()).then(|| format!("/{path}"))
+}
+
+fn default_perm() -> String {
+    "rw".to_string()
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct SyncConfig {
+    /// Paths excluded from sync (gitignore syntax)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ignore: Vec<String>,
+}
+
+/// Authenticated user of a request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct User {
+    pub name: String,
+    pub read_only: bool,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct Manifest {
     pub short_name: String,
     pub name: String,
@@ -31,6 +103,7 @@ pub struct Manifest {
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct ManifestIcon {
     pub src: String,
     #[serde(rename = "type")]
@@ -38,16 +111,124 @@ pub struct ManifestIcon {
     pub sizes: String,
 }
 
+/// Settings the PWA manifest at `/.client/manifest.json` is built from
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ManifestConfig {
+    pub name: String,
+    /// Defaults to the name
+    pub short_name: Option<String>,
+    pub description: String,
+    pub theme_color: String,
+    /// Icons with absolute paths are served relative to the URL prefix
+    pub icons: Vec<ManifestIcon>,
+    /// Path the server is mounted under, e.g. `/notes`
+    pub url_prefix: Option<String>,
+}
+
+impl Default for ManifestConfig {
+    fn default() -> Self {
+        Self {
+            name: "SilverBullet".to_string(),
+            short_name: None,
+            description: "Markdown as a platform".to_string(),
+            theme_color: "#e1e1e1".to_string(),
+            icons: vec![ManifestIcon {
+                src: "/.client/logo-dock.png".to_string(),
+                type_: "image/png".to_string(),
+                sizes: "512x512".to_string(),
+            }],
+            url_prefix: None,
+        }
+    }
+}
+
+impl ManifestConfig {
+    pub fn manifest(&self) -> Manifest {
+        let prefix = self
+            .url_prefix
+            .as_deref()
+            .map_or("", |prefix| prefix.trim_end_matches('/'));
+
+        let url = |path: &str| {
+            if path.starts_with('/') {
+                format!("{prefix}{path}")
+            } else {
+                path.to_string()
+            }
+        };
+
+        Manifest {
+            short_name: self.short_name.clone().unwrap_or_else(|| self.name.clone()),
+            name: self.name.clone(),
+            icons: self
+                .icons
+                .iter()
+                .map(|icon| ManifestIcon {
+                    src: url(&icon.src),
+                    ..icon.clone()
+                })
+                .collect(),
+            capture_links: "new-client".to_string(),
+            start_url: url("/#boot"),
+            display: "standalone".to_string(),
+            display_override: vec!["window-controls-overlay".to_string()],
+            scope: url("/"),
+            theme_color: self.theme_color.clone(),
+            description: self.description.clone(),
+        }
+    }
+}
+
 pub trait Logger {
     fn log(&self, client_ip: String, entries: Vec<LogEntry>);
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+/// Discard entries when there is no logger
+impl<L> Logger for Option<L>
+where
+    L: Logger,
+{
+    fn log(&self, client_ip: String, entries: Vec<LogEntry>) {
+        if let Some(logger) = self {
+            logger.log(client_ip, entries);
+        }
+    }
+}
+
+/// Send entries to both loggers
+impl<A, B> Logger for (A, B)
+where
+    A: Logger,
+    B: Logger,
+{
+    fn log(&self, client_ip: String, entries: Vec<LogEntry>) {
+        self.0.log(client_ip.clone(), entries.clone());
+        self.1.log(client_ip, entries);
+    }
+}
+
+/// Log entry pushed by the client to `/.logs`
+///
+/// Unknown fields are ignored so newer clients can send more context.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "camelCase")]
 pub struct LogEntry {
     pub source: String,
     pub level: String,
     pub message: String,
     pub timestamp: i64,
+    /// Page open when the entry was logged
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page: Option<String>,
+    /// Filled from the request's `User-Agent` when the client does not send it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_agent: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stack: Option<String>,
 }
 
 pub struct DiscardLogger;
@@ -55,3 +236,107 @@ pub struct DiscardLogger;
 impl Logger for DiscardLogger {
     fn log(&self, _client_ip: String, _entries: Vec<LogEntry>) {}
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_for_user() {
+        let config = Config {
+            index_page: "index".to_string(),
+            ..Config::default()
+        };
+
+        let anonymous = config.clone().for_user(None);
+        assert_eq!(anonymous.perm, "rw");
+        assert_eq!(anonymous.user, None);
+
+        let user = User {
+            name: "alice".to_string(),
+            read_only: true,
+        };
+
+        let config = config.for_user(Some(&user));
+        assert_eq!(config.user.as_deref(), Some("alice"));
+        assert!(config.read_only);
+        assert_eq!(config.perm, "ro");
+    }
+
+    #[test]
+    fn manifest_with_prefix() {
+        let config = ManifestConfig {
+            name: "Notes".to_string(),
+            url_prefix: Some("/notes/".to_string()),
+            icons: vec![
+                ManifestIcon {
+                    src: "/.client/logo.png".to_string(),
+                    type_: "image/png".to_string(),
+                    sizes: "512x512".to_string(),
+                },
+                ManifestIcon {
+                    src: "https://cdn.example.com/icon.png".to_string(),
+                    type_: "image/png".to_string(),
+                    sizes: "192x192".to_string(),
+                },
+            ],
+            ..ManifestConfig::default()
+        };
+
+        let manifest = config.manifest();
+        assert_eq!(manifest.short_name, "Notes");
+        assert_eq!(manifest.start_url, "/notes/#boot");
+        assert_eq!(manifest.scope, "/notes/");
+        assert_eq!(manifest.icons[0].src, "/notes/.client/logo.png");
+        assert_eq!(manifest.icons[1].src, "https://cdn.example.com/icon.png");
+
+        let manifest = ManifestConfig::default().manifest();
+        assert_eq!(manifest.start_url, "/#boot");
+        assert_eq!(manifest.scope, "/");
+        assert_eq!(manifest.icons[0].src, "/.client/logo-dock.png");
+    }
+
+    #[test]
+    fn normalizes_url_prefixes() {
+        assert_eq!(normalize_url_prefix("notes/").as_deref(), Some("/notes"));
+        assert_eq!(normalize_url_prefix("/a/b").as_deref(), Some("/a/b"));
+        assert_eq!(normalize_url_prefix("/"), None);
+        assert_eq!(normalize_url_prefix(""), None);
+    }
+
+    #[test]
+    fn parses_log_entries() {
+        let entry: LogEntry = serde_json::from_value(serde_json::json!({
+            "source": "client",
+            "level": "error",
+            "message": "boom",
+            "timestamp": 1,
+            "page": "index",
+            "clientVersion": "2.0.0",
+            "stack": "at foo",
+            "somethingNew": true,
+        }))
+        .unwrap();
+
+        assert_eq!(entry.page.as_deref(), Some("index"));
+        assert_eq!(entry.client_version.as_deref(), Some("2.0.0"));
+        assert_eq!(entry.stack.as_deref(), Some("at foo"));
+        assert_eq!(entry.user_agent, None);
+
+        let entry: LogEntry = serde_json::from_str(
+            r#"{"source": "client", "level": "info", "message": "hi", "timestamp": 1}"#,
+        )
+        .unwrap();
+        assert_eq!(entry.page, None);
+    }
+
+    #[test]
+    fn config_serialization() {
+        let json = serde_json::to_value(Config::default().for_user(None)).unwrap();
+
+        assert_eq!(json["perm"], "rw");
+        assert_eq!(json["sync"], serde_json::json!({}));
+        assert!(json.get("spaceName").is_none());
+        assert!(json.get("features").is_none());
+    }
+}
@@ -0,0 +1,214 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write as _};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use serde::Serialize;
+
+use crate::client::{LogEntry, Logger};
+
+/// Logger appending client log entries as JSON lines to a file
+///
+/// The file is rotated once it grows past [`JsonFileLogger::max_size`] or gets older
+/// than [`JsonFileLogger::max_age`]: `client.log` is renamed to `client.log.1`,
+/// older files shift up and the oldest beyond [`JsonFileLogger::max_files`] is removed.
+pub struct JsonFileLogger {
+    path: PathBuf,
+    max_size: Option<u64>,
+    max_age: Option<Duration>,
+    max_files: usize,
+    file: Mutex<Option<Current>>,
+}
+
+struct Current {
+    file: File,
+    size: u64,
+    opened_at: SystemTime,
+}
+
+#[derive(Serialize)]
+struct Line<'a> {
+    client_ip: &'a str,
+    #[serde(flatten)]
+    entry: &'a LogEntry,
+}
+
+impl JsonFileLogger {
+    /// Log to the file at `path`, which is created on the first write.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            max_size: Some(10 * 1024 * 1024),
+            max_age: None,
+            max_files: 5,
+            file: Mutex::new(None),
+        }
+    }
+
+    /// Rotate after the file reaches this many bytes (10 MiB by default, `None` to disable).
+    #[must_use]
+    pub fn max_size(mut self, bytes: Option<u64>) -> Self {
+        self.max_size = bytes;
+        self
+    }
+
+    /// Rotate files older than this (disabled by default).
+    #[must_use]
+    pub fn max_age(mut self, age: Option<Duration>) -> Self {
+        self.max_age = age;
+        self
+    }
+
+    /// Number of rotated files to keep (5 by default).
+    #[must_use]
+    pub fn max_files(mut self, count: usize) -> Self {
+        self.max_files = count;
+        self
+    }
+
+    fn write(&self, client_ip: &str, entries: &[LogEntry]) -> io::Result<()> {
+        let mut buf = Vec::new();
+
+        for entry in entries {
+            serde_json::to_writer(&mut buf, &Line { client_ip, entry })?;
+            buf.push(b'\n');
+        }
+
+        let mut current = self.file.lock().unwrap_or_else(|e| e.into_inner());
+
+        if current.as_ref().is_some_and(|c| self.should_rotate(c)) {
+            *current = None;
+            self.rotate()?;
+        }
+
+        let current = match &mut *current {
+            Some(current) => current,
+            None => current.insert(self.open()?),
+        };
+
+        current.file.write_all(&buf)?;
+        current.size += buf.len() as u64;
+
+        Ok(())
+    }
+
+    fn open(&self) -> io::Result<Current> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+
+        let metadata = file.metadata()?;
+
+        Ok(Current {
+            file,
+            size: metadata.len(),
+            opened_at: metadata.created().unwrap_or_else(|_| SystemTime::now()),
+        })
+    }
+
+    fn should_rotate(&self, current: &Current) -> bool {
+        let too_large = self.max_size.is_some_and(|max| current.size >= max);
+        let too_old = self.max_age.is_some_and(|max| {
+            current
+                .opened_at
+                .elapsed()
+                .is_ok_and(|elapsed| elapsed >= max)
+        });
+
+        too_large || too_old
+    }
+
+    fn rotate(&self) -> io::Result<()> {
+        if self.max_files == 0 {
+            return remove_if_exists(&self.path);
+        }
+
+        remove_if_exists(&self.rotated(self.max_files))?;
+
+        for n in (1..self.max_files).rev() {
+            rename_if_exists(&self.rotated(n), &self.rotated(n + 1))?;
+        }
+
+        rename_if_exists(&self.path, &self.rotated(1))
+    }
+
+    fn rotated(&self, n: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{n}"));
+        path.into()
+    }
+}
+
+impl Logger for JsonFileLogger {
+    fn log(&self, client_ip: String, entries: Vec<LogEntry>) {
+        if let Err(_e) = self.write(&client_ip, &entries) {
+            #[cfg(feature = "tracing")]
+            tracing::warn!(path = %self.path.display(), "failed to write client logs: {_e}");
+        }
+    }
+}
+
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+fn rename_if_exists(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::rename(from, to) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(message: &str) -> LogEntry {
+        LogEntry {
+            source: "client".to_string(),
+            level: "error".to_string(),
+            message: message.to_string(),
+            timestamp: 1,
+            ..LogEntry::default()
+        }
+    }
+
+    #[test]
+    fn appends_json_lines_and_rotates() {
+        let dir = std::env::temp_dir().join(format!("sb-client-log-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("client.log");
+
+        let logger = JsonFileLogger::new(&path).max_size(Some(1)).max_files(1);
+
+        logger.log("127.0.0.1".to_string(), vec![entry("first")]);
+
+        let line: serde_json::Value =
+            serde_json::from_str(fs::read_to_string(&path).unwrap().trim()).unwrap();
+        assert_eq!(line["client_ip"], "127.0.0.1");
+        assert_eq!(line["message"], "first");
+        assert_eq!(line["level"], "error");
+
+        logger.log("127.0.0.1".to_string(), vec![entry("second")]);
+        logger.log("127.0.0.1".to_string(), vec![entry("third")]);
+
+        assert!(fs::read_to_string(&path).unwrap().contains("third"));
+        assert!(
+            fs::read_to_string(dir.join("client.log.1"))
+                .unwrap()
+                .contains("second")
+        );
+        assert!(!dir.join("client.log.2").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,143 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use opentelemetry::logs::{AnyValue, LogRecord as _, Severity};
+use opentelemetry::trace::TraceContextExt as _;
+use tracing_opentelemetry::OpenTelemetrySpanExt as _;
+
+use crate::client::{LogEntry, Logger};
+
+/// Logger forwarding client log entries to OpenTelemetry
+///
+/// Entries are emitted as log records with the client IP and source as attributes.
+/// Records are linked to the current `tracing` span (the `/.logs` request) when
+/// spans are exported through `tracing-opentelemetry`.
+pub struct OtelLogger<L> {
+    logger: L,
+}
+
+impl<L> OtelLogger<L>
+where
+    L: opentelemetry::logs::Logger,
+{
+    /// Use a logger obtained from a `LoggerProvider`, e.g. one exporting over OTLP.
+    pub fn new(logger: L) -> Self {
+        Self { logger }
+    }
+}
+
+impl<L> Logger for OtelLogger<L>
+where
+    L: opentelemetry::logs::Logger,
+{
+    fn log(&self, client_ip: String, entries: Vec<LogEntry>) {
+        let span = tracing::Span::current()
+            .context()
+            .span()
+            .span_context()
+            .clone();
+
+        for entry in entries {
+            let (severity, text) = severity(&entry.level);
+
+            let mut record = self.logger.create_log_record();
+            record.set_target("client");
+            record.set_severity_number(severity);
+            record.set_severity_text(text);
+            record.set_body(AnyValue::from(entry.message));
+            record.add_attribute("client.address", client_ip.clone());
+            record.add_attribute("log.source", entry.source);
+
+            let optional = [
+                ("page.name", entry.page),
+                ("user_agent.original", entry.user_agent),
+                ("client.version", entry.client_version),
+                ("exception.stacktrace", entry.stack),
+            ];
+
+            for (key, value) in optional {
+                if let Some(value) = value {
+                    record.add_attribute(key, value);
+                }
+            }
+
+            if let Ok(millis) = u64::try_from(entry.timestamp) {
+                record.set_timestamp(UNIX_EPOCH + Duration::from_millis(millis));
+            }
+
+            record.set_observed_timestamp(SystemTime::now());
+
+            if span.is_valid() {
+                record.set_trace_context(span.trace_id(), span.span_id(), Some(span.trace_flags()));
+            }
+
+            self.logger.emit(record);
+        }
+    }
+}
+
+fn severity(level: &str) -> (Severity, &'static str) {
+    match level.to_lowercase().as_str() {
+        "trace" => (Severity::Trace, "TRACE"),
+        "debug" => (Severity::Debug, "DEBUG"),
+        "warn" => (Severity::Warn, "WARN"),
+        "error" => (Severity::Error, "ERROR"),
+        _ => (Severity::Info, "INFO"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use opentelemetry::logs::LoggerProvider as _;
+    use opentelemetry_sdk::logs::{InMemoryLogExporter, SdkLoggerProvider};
+
+    use super::*;
+
+    #[test]
+    fn emits_records() {
+        let exporter = InMemoryLogExporter::default();
+        let provider = SdkLoggerProvider::builder()
+            .with_simple_exporter(exporter.clone())
+            .build();
+
+        let logger = OtelLogger::new(provider.logger("client"));
+
+        logger.log(
+            "127.0.0.1".to_string(),
+            vec![LogEntry {
+                source: "editor".to_string(),
+                level: "error".to_string(),
+                message: "boom".to_string(),
+                timestamp: 1_700_000_000_000,
+                page: Some("index".to_string()),
+                ..LogEntry::default()
+            }],
+        );
+
+        let logs = exporter.get_emitted_logs().unwrap();
+        assert_eq!(logs.len(), 1);
+
+        let record = &logs[0].record;
+        assert_eq!(record.severity_number(), Some(Severity::Error));
+        assert_eq!(record.body(), Some(&AnyValue::from("boom".to_string())));
+        assert_eq!(
+            record.timestamp(),
+            Some(UNIX_EPOCH + Duration::from_millis(1_700_000_000_000))
+        );
+        assert!(
+            record
+                .attributes_iter()
+                .any(|(key, value)| key.as_str() == "client.address"
+                    && value == &AnyValue::from("127.0.0.1".to_string()))
+        );
+        assert!(
+            record
+                .attributes_iter()
+                .any(|(key, _)| key.as_str() == "page.name")
+        );
+        assert!(
+            !record
+                .attributes_iter()
+                .any(|(key, _)| key.as_str() == "exception.stacktrace")
+        );
+    }
+}
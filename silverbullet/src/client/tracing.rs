@@ -21,6 +21,11 @@ macro_rules! tracing_log_entry {
             $level,
             message = $entry.message,
             timestamp = $entry.timestamp,
+            source = $entry.source,
+            page = $entry.page,
+            user_agent = $entry.user_agent,
+            client_version = $entry.client_version,
+            stack = $entry.stack,
         )
     };
 }
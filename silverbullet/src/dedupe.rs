@@ -0,0 +1,362 @@
+//! Reports of the attachments stored more than once
+//!
+//! [`Dedupe::find`] hashes the content of the attachments, the files that aren't pages,
+//! with SHA-256 and reports the sets of identical ones, with the bytes their copies waste.
+//! Only files sharing a size are read. The canonical file of a set is the one created
+//! first, the others being its copies.
+//!
+//! [`Dedupe::rewrite`] also points the links of the pages at the copies to their
+//! canonical file, wiki links, `![[copy.png]]`, keeping their alias, and Markdown links,
+//! `![diagram](copy.png)`, made absolute. The copies are left in place: once no page
+//! references them, [`gc`](crate::gc) collects them. HTML attributes aren't rewritten.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use futures::TryStreamExt as _;
+use serde::Serialize;
+use sha2::{Digest as _, Sha256};
+
+use crate::fs::{self, FileMeta, IncomingFileMeta, ReadWriteFilesystem};
+use crate::glob;
+
+/// Identical attachments
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct Duplicates {
+    /// SHA-256 of the content, in hex
+    pub hash: String,
+    /// Size of each file in bytes
+    pub size: u64,
+    /// File created first, kept by rewrites
+    pub canonical: String,
+    /// The other files, in name order
+    pub copies: Vec<String>,
+}
+
+/// Outcome of a search for duplicates
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct Report {
+    /// Attachments compared
+    pub files: usize,
+    /// Sets of identical attachments, the most wasteful first
+    pub duplicates: Vec<Duplicates>,
+    /// Bytes of all the copies
+    pub wasted: u64,
+    /// Pages whose links were rewritten to the canonical files, in name order
+    pub rewritten: Vec<String>,
+}
+
+/// Finds the duplicate attachments of a space
+#[derive(Clone)]
+pub struct Dedupe {
+    fs: Arc<dyn ReadWriteFilesystem>,
+    exclude: Vec<String>,
+}
+
+impl Dedupe {
+    pub fn new(fs: Arc<dyn ReadWriteFilesystem>) -> Self {
+        Self {
+            fs,
+            exclude: Vec::new(),
+        }
+    }
+
+    /// Leave the files matching a glob out, e.g. `Library/**`.
+    #[must_use]
+    pub fn exclude(mut self, pattern: impl Into<String>) -> Self {
+        self.exclude.push(pattern.into());
+        self
+    }
+
+    /// Report the sets of identical attachments.
+    pub async fn find(&self) -> fs::Result<Report> {
+        let files = self.fs.list().await?;
+        self.duplicates(&files).await
+    }
+
+    /// Report the sets of identical attachments, and point the links to their copies to
+    /// the canonical files.
+    pub async fn rewrite(&self) -> fs::Result<Report> {
+        let files = self.fs.list().await?;
+        let mut report = self.duplicates(&files).await?;
+
+        let canonical: HashMap<String, &str> = report
+            .duplicates
+            .iter()
+            .flat_map(|set| {
+                set.copies
+                    .iter()
+                    .map(|copy| (copy.to_lowercase(), set.canonical.as_str()))
+            })
+            .collect();
+
+        let mut rewritten = Vec::new();
+        for page in files.iter().filter(|meta| meta.name.ends_with(".md")) {
+            let (stream, meta) = self.fs.get(&page.name).await?;
+            let content = read(stream).await?;
+            let name = page.name.strip_suffix(".md").unwrap_or(&page.name);
+
+            let Some(content) = rewrite(name, &content, &canonical) else {
+                continue;
+            };
+
+            // Left alone if edited meanwhile
+            let incoming = IncomingFileMeta {
+                content_type: Some(meta.content_type),
+                if_match: meta.etag,
+                ..Default::default()
+            };
+            let data = futures::stream::once(async move { Ok(bytes::Bytes::from(content)) });
+            match self.fs.put(&page.name, Box::pin(data), incoming).await {
+                Ok(_) => rewritten.push(page.name.clone()),
+                Err(fs::Error::PreconditionFailed(_)) => {}
+                Err(err) => return Err(err),
+            }
+        }
+
+        #[cfg(feature = "tracing")]
+        tracing::info!(pages = rewritten.len(), "Rewrote links to duplicates");
+
+        rewritten.sort_unstable();
+        report.rewritten = rewritten;
+        Ok(report)
+    }
+
+    async fn duplicates(&self, files: &[FileMeta]) -> fs::Result<Report> {
+        let attachments: Vec<&FileMeta> = files
+            .iter()
+            .filter(|meta| {
+                !meta.name.ends_with(".md")
+                    && !meta.name.split('/').any(|segment| segment.starts_with('.'))
+                    && !self
+                        .exclude
+                        .iter()
+                        .any(|pattern| glob::matches(pattern, &meta.name))
+            })
+            .collect();
+
+        let mut sizes: HashMap<u64, Vec<&FileMeta>> = HashMap::new();
+        for meta in &attachments {
+            sizes.entry(meta.size).or_default().push(meta);
+        }
+
+        let mut duplicates = Vec::new();
+        for (size, same_size) in sizes {
+            if same_size.len() < 2 {
+                continue;
+            }
+
+            let mut hashes: BTreeMap<String, Vec<&FileMeta>> = BTreeMap::new();
+            for meta in same_size {
+                let hash = match self.fs.get(&meta.name).await {
+                    Ok((stream, _)) => hash(stream).await?,
+                    // Gone since the listing
+                    Err(fs::Error::NotFound(_)) => continue,
+                    Err(err) => return Err(err),
+                };
+                hashes.entry(hash).or_default().push(meta);
+            }
+
+            for (hash, mut same) in hashes {
+                if same.len() < 2 {
+                    continue;
+                }
+
+                same.sort_by(|a, b| a.created.cmp(&b.created).then(a.name.cmp(&b.name)));
+                let canonical = same.remove(0).name.clone();
+                let mut copies: Vec<String> =
+                    same.into_iter().map(|meta| meta.name.clone()).collect();
+                copies.sort_unstable();
+
+                duplicates.push(Duplicates {
+                    hash,
+                    size,
+                    canonical,
+                    copies,
+                });
+            }
+        }
+
+        let wasted = |set: &Duplicates| set.size * set.copies.len() as u64;
+        duplicates.sort_by(|a, b| {
+            wasted(b)
+                .cmp(&wasted(a))
+                .then_with(|| a.canonical.cmp(&b.canonical))
+        });
+
+        Ok(Report {
+            files: attachments.len(),
+            wasted: duplicates.iter().map(wasted).sum(),
+            duplicates,
+            rewritten: Vec::new(),
+        })
+    }
+}
+
+async fn read(stream: fs::Stream) -> fs::Result<String> {
+    let bytes = stream
+        .try_fold(Vec::new(), |mut bytes, chunk| async move {
+            bytes.extend_from_slice(&chunk);
+            Ok(bytes)
+        })
+        .await
+        .map_err(fs::Error::from)?;
+
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// SHA-256 of a file, in hex.
+async fn hash(stream: fs::Stream) -> fs::Result<String> {
+    let hasher = stream
+        .try_fold(Sha256::new(), |mut hasher, chunk| async move {
+            hasher.update(&chunk);
+            Ok(hasher)
+        })
+        .await
+        .map_err(fs::Error::from)?;
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Content of the page `name` with its links to copies pointed to their canonical files,
+/// `None` when it links none. `canonical` maps the lowercase names of the copies.
+fn rewrite(name: &str, content: &str, canonical: &HashMap<String, &str>) -> Option<String> {
+    let folder = name.rsplit_once('/').map(|(folder, _)| folder);
+    let find = |target: &str, relative: bool| -> Option<&str> {
+        let target = crate::import::decode(target).to_lowercase();
+        let path = match (target.strip_prefix('/'), folder) {
+            (Some(path), _) => path.to_string(),
+            (None, Some(folder)) if relative => format!("{}/{target}", folder.to_lowercase()),
+            (None, _) => target.clone(),
+        };
+        canonical
+            .get(&path)
+            .or_else(|| canonical.get(&target))
+            .copied()
+    };
+
+    let mut out = String::with_capacity(content.len());
+    let mut changed = false;
+    let mut rest = content;
+
+    while let Some(at) = rest.find(['[', ']']) {
+        out.push_str(&rest[..at]);
+        let after = &rest[at..];
+
+        if let Some(inner) = after.strip_prefix("[[")
+            && let Some(end) = inner.find("]]")
+        {
+            let link = &inner[..end];
+            let split = link.find(['|', '#', '@']).unwrap_or(link.len());
+            let target = link[..split].trim();
+
+            out.push_str("[[");
+            match find(target.trim_start_matches('/'), false) {
+                Some(file) => {
+                    out.push_str(file);
+                    out.push_str(&link[split..]);
+                    changed = true;
+                }
+                None => out.push_str(link),
+            }
+            out.push_str("]]");
+            rest = &inner[end + 2..];
+        } else if let Some(inner) = after.strip_prefix("](")
+            && let Some(end) = inner.find([')', ' ', '\n'])
+        {
+            let target = &inner[..end];
+
+            out.push_str("](");
+            match find(target.trim_matches(['<', '>']), true) {
+                Some(file) if !target.contains("://") => {
+                    out.push('/');
+                    out.push_str(&file.replace(' ', "%20"));
+                    changed = true;
+                }
+                _ => out.push_str(target),
+            }
+            rest = &inner[end..];
+        } else {
+            out.push_str(&after[..1]);
+            rest = &after[1..];
+        }
+    }
+    out.push_str(rest);
+
+    changed.then_some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::ReadOnlyFilesystem as _;
+    use crate::fs::testing::MemoryFs;
+
+    fn space() -> Arc<MemoryFs> {
+        Arc::new(
+            MemoryFs::new()
+                .with_file(
+                    "Projects/Launch.md",
+                    b"![[Projects/chart copy.png|chart]] ![again](chart%20copy.png) ![[logo.svg]]",
+                )
+                .with_file("Projects/Plan.md", b"[[chart.png]] and nothing else")
+                .with_file("chart.png", b"same chart")
+                .with_file("Projects/chart copy.png", b"same chart")
+                .with_file("Other/chart.png", b"same chart")
+                .with_file("logo.svg", b"svg")
+                .with_file("different.png", b"diff chart")
+                .with_file(".trash/chart.png", b"same chart"),
+        )
+    }
+
+    #[tokio::test]
+    async fn finds_duplicates() {
+        let report = Dedupe::new(space()).find().await.unwrap();
+
+        assert_eq!(report.files, 5);
+        assert_eq!(
+            report.duplicates,
+            [Duplicates {
+                hash: format!("{:x}", Sha256::digest(b"same chart")),
+                size: 10,
+                canonical: "Other/chart.png".to_string(),
+                copies: vec![
+                    "Projects/chart copy.png".to_string(),
+                    "chart.png".to_string()
+                ],
+            }]
+        );
+        assert_eq!(report.wasted, 20);
+        assert!(report.rewritten.is_empty());
+
+        let report = Dedupe::new(space())
+            .exclude("Other/**")
+            .find()
+            .await
+            .unwrap();
+        assert_eq!(report.duplicates[0].canonical, "Projects/chart copy.png");
+    }
+
+    #[tokio::test]
+    async fn rewrites_links() {
+        let space = space();
+        let report = Dedupe::new(space.clone()).rewrite().await.unwrap();
+        assert_eq!(report.rewritten, ["Projects/Launch.md", "Projects/Plan.md"]);
+
+        let (stream, _) = space.get("Projects/Launch.md").await.unwrap();
+        assert_eq!(
+            read(stream).await.unwrap(),
+            "![[Other/chart.png|chart]] ![again](/Other/chart.png) ![[logo.svg]]"
+        );
+
+        let (stream, _) = space.get("Projects/Plan.md").await.unwrap();
+        assert_eq!(
+            read(stream).await.unwrap(),
+            "[[Other/chart.png]] and nothing else"
+        );
+    }
+}
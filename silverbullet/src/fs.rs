@@ -1,21 +1,60 @@
+use std::sync::Arc;
+
 use async_trait::async_trait;
 use bytes::Bytes;
 use futures::StreamExt as _;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
+pub mod cache;
+pub mod case_insensitive;
 pub mod layer;
+pub mod memo;
+pub mod normalized;
+pub mod perm;
+pub mod retry;
+pub mod singleflight;
+pub mod stack;
+#[cfg(feature = "tracing")]
+pub mod trace;
+pub mod warm;
+pub use stack::{FsLayer, stack};
 
 #[cfg(feature = "embed")]
 pub mod embed;
 
+#[cfg(feature = "fs-http")]
+pub mod remote;
+
 #[cfg(feature = "opendal")]
 pub mod opendal;
 
 #[cfg(all(target_arch = "wasm32", feature = "cloudflare"))]
 pub mod cloudflare;
 
-mod utils;
+#[cfg(all(not(target_arch = "wasm32"), feature = "sqlite"))]
+pub mod sqlite;
+
+#[cfg(any(
+    feature = "opendal",
+    all(not(target_arch = "wasm32"), feature = "sqlite")
+))]
+mod uri;
+
+#[cfg(any(
+    feature = "opendal",
+    all(not(target_arch = "wasm32"), feature = "sqlite")
+))]
+pub use uri::from_uri;
+
+pub mod time;
+
+#[cfg(any(test, feature = "test-util"))]
+pub mod conformance;
+#[cfg(any(test, feature = "test-util"))]
+pub mod model;
+#[cfg(any(test, feature = "test-util"))]
+pub mod testing;
 
 #[derive(Error, Debug)]
 pub enum Error {
@@ -28,6 +67,14 @@ pub enum Error {
     #[error("Permission denied: {0}")]
     PermissionDenied(#[source] Box<dyn std::error::Error + Send + Sync>),
 
+    /// A conditional write found the file changed, e.g. its etag not matching `If-Match`
+    #[error("Precondition failed: {0}")]
+    PreconditionFailed(#[source] Box<dyn std::error::Error + Send + Sync>),
+
+    /// The backend failed in a way that may not happen again, e.g. it was rate limited
+    #[error("Temporary failure: {0}")]
+    Transient(#[source] Box<dyn std::error::Error + Send + Sync>),
+
     #[error(transparent)]
     Other(#[from] Box<dyn std::error::Error + Send + Sync>),
 }
@@ -44,6 +91,16 @@ pub type Stream = futures::stream::BoxStream<'static, std::result::Result<Bytes,
 pub type Stream =
     futures::stream::LocalBoxStream<'static, std::result::Result<Bytes, std::io::Error>>;
 
+/// Files of a listing, see [`ReadOnlyFilesystem::list_stream`]
+#[cfg(any(
+    not(target_arch = "wasm32"),
+    all(target_arch = "wasm32", feature = "unsafe")
+))]
+pub type FileStream = futures::stream::BoxStream<'static, Result<FileMeta>>;
+
+#[cfg(all(target_arch = "wasm32", not(feature = "unsafe")))]
+pub type FileStream = futures::stream::LocalBoxStream<'static, Result<FileMeta>>;
+
 #[cfg(not(target_arch = "wasm32"))]
 pub trait StreamExt {
     fn into_boxed(self) -> Stream
@@ -55,6 +112,13 @@ pub trait StreamExt {
     {
         self.boxed()
     }
+
+    fn into_file_stream(self) -> FileStream
+    where
+        Self: Sized + futures::Stream<Item = Result<FileMeta>> + Send + 'static,
+    {
+        self.boxed()
+    }
 }
 
 #[cfg(all(target_arch = "wasm32", feature = "unsafe"))]
@@ -68,6 +132,16 @@ pub trait StreamExt {
         // SAFETY: Only safe on single-threaded WASM environments
         unsafe { std::mem::transmute(local_stream) }
     }
+
+    fn into_file_stream(self) -> FileStream
+    where
+        Self: Sized + futures::Stream<Item = Result<FileMeta>> + 'static,
+    {
+        let local_stream = self.boxed_local();
+
+        // SAFETY: Only safe on single-threaded WASM environments
+        unsafe { std::mem::transmute(local_stream) }
+    }
 }
 
 #[cfg(all(target_arch = "wasm32", not(feature = "unsafe")))]
@@ -78,6 +152,13 @@ pub trait StreamExt {
     {
         self.boxed_local()
     }
+
+    fn into_file_stream(self) -> FileStream
+    where
+        Self: Sized + futures::Stream<Item = Result<FileMeta>> + 'static,
+    {
+        self.boxed_local()
+    }
 }
 
 impl<S> StreamExt for S where S: futures::Stream {}
@@ -90,6 +171,18 @@ pub trait ReadOnlyFilesystem: Send + Sync {
     async fn list(&self) -> Result<Vec<FileMeta>>;
     async fn get(&self, path: &str) -> Result<(Stream, FileMeta)>;
     async fn meta(&self, path: &str) -> Result<FileMeta>;
+
+    /// Files of the space as the backend lists them, e.g. page by page from an object
+    /// store, without holding them all in memory.
+    ///
+    /// Defaults to the files of [`list`](Self::list), sorted by name. Backends streaming
+    /// them list in their own order, by name for object stores.
+    async fn list_stream(&self) -> Result<FileStream> {
+        let mut files = self.list().await?;
+        files.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(futures::stream::iter(files.into_iter().map(Ok)).into_file_stream())
+    }
 }
 
 #[allow(async_fn_in_trait)]
@@ -103,7 +196,53 @@ pub trait WritableFilesystem: Send + Sync {
 pub trait ReadWriteFilesystem: ReadOnlyFilesystem + WritableFilesystem {}
 impl<T: ReadOnlyFilesystem + WritableFilesystem> ReadWriteFilesystem for T {}
 
+macro_rules! impl_for_pointer {
+    ($pointer:ident) => {
+        #[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+        #[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+        impl<T: ReadOnlyFilesystem + ?Sized> ReadOnlyFilesystem for $pointer<T> {
+            async fn list(&self) -> Result<Vec<FileMeta>> {
+                (**self).list().await
+            }
+
+            async fn get(&self, path: &str) -> Result<(Stream, FileMeta)> {
+                (**self).get(path).await
+            }
+
+            async fn meta(&self, path: &str) -> Result<FileMeta> {
+                (**self).meta(path).await
+            }
+
+            async fn list_stream(&self) -> Result<FileStream> {
+                (**self).list_stream().await
+            }
+        }
+
+        #[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+        #[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+        impl<T: WritableFilesystem + ?Sized> WritableFilesystem for $pointer<T> {
+            async fn put(
+                &self,
+                path: &str,
+                data: Stream,
+                meta: IncomingFileMeta,
+            ) -> Result<FileMeta> {
+                (**self).put(path, data, meta).await
+            }
+
+            async fn delete(&self, path: &str) -> Result<()> {
+                (**self).delete(path).await
+            }
+        }
+    };
+}
+
+// Lets backends picked at runtime, eg. with `from_uri`, be used as `Box<dyn ReadWriteFilesystem>`
+impl_for_pointer!(Box);
+impl_for_pointer!(Arc);
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct FileMeta {
     pub name: String,
@@ -112,6 +251,12 @@ pub struct FileMeta {
     pub content_type: String,
     pub last_modified: u64,
     pub size: u64,
+    /// Entity tag of the content, from backends that keep one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    /// Version ID of the object, from backends keeping versions, e.g. R2 or versioned S3
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
 }
 
 impl TryFrom<FileMeta> for http::HeaderMap {
@@ -130,6 +275,14 @@ impl TryFrom<FileMeta> for http::HeaderMap {
         headers.insert("X-Last-Modified", value.last_modified.to_string().parse()?);
         headers.insert("X-Permission", value.perm.as_str().parse()?);
 
+        if let Some(etag) = &value.etag {
+            headers.insert(http::header::ETAG, etag.parse()?);
+        }
+
+        if let Some(version) = &value.version {
+            headers.insert("X-Version", version.parse()?);
+        }
+
         Ok(headers)
     }
 }
@@ -141,6 +294,11 @@ pub struct IncomingFileMeta {
     pub content_type: Option<String>,
     pub last_modified: Option<u64>,
     pub size: Option<u64>,
+    /// Only write if the stored file has this etag (`*` for any), from `If-Match`
+    pub if_match: Option<String>,
+    /// Only write if the stored file doesn't have this etag (`*` if it doesn't exist),
+    /// from `If-None-Match`
+    pub if_none_match: Option<String>,
 }
 
 impl TryFrom<http::HeaderMap> for IncomingFileMeta {
@@ -163,10 +321,13 @@ impl TryFrom<http::HeaderMap> for IncomingFileMeta {
         }
 
         Ok(IncomingFileMeta {
-            // TODO: add now
-            created: get_header(&value, "x-created")?.or(Some(utils::now())),
+            // Left to the backend without one, which keeps the time of the file overwritten
+            created: get_header(&value, "x-created")?,
             content_type: get_header(&value, http::header::CONTENT_TYPE)?,
+            last_modified: get_header(&value, "x-last-modified")?,
             size: get_header(&value, http::header::CONTENT_LENGTH)?,
+            if_match: get_header(&value, http::header::IF_MATCH)?,
+            if_none_match: get_header(&value, http::header::IF_NONE_MATCH)?,
             ..Default::default()
         })
     }
@@ -194,7 +355,11 @@ impl From<Error> for axum::http::StatusCode {
         match value {
             Error::NotFound(..) => axum::http::StatusCode::NOT_FOUND,
             Error::PermissionDenied(..) => axum::http::StatusCode::FORBIDDEN,
+            Error::PreconditionFailed(..) => axum::http::StatusCode::PRECONDITION_FAILED,
+            Error::Transient(..) => axum::http::StatusCode::SERVICE_UNAVAILABLE,
+            #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
             e => {
+                #[cfg(feature = "tracing")]
                 tracing::error!("Error: {:?}", e);
 
                 axum::http::StatusCode::INTERNAL_SERVER_ERROR
@@ -262,6 +427,8 @@ mod http_tests {
             content_type: "text/plain".to_string(),
             last_modified: 2000000,
             size: 42,
+            etag: None,
+            version: None,
         };
 
         let headers: http::HeaderMap = meta.try_into().unwrap();
@@ -277,6 +444,49 @@ mod http_tests {
         assert_eq!(headers.get("X-Permission").unwrap(), "rw");
     }
 
+    #[test]
+    fn file_meta_times_in_milliseconds() {
+        let meta = FileMeta {
+            name: "index.md".to_string(),
+            created: time::from_secs(1_700_000_000),
+            perm: "rw".to_string(),
+            content_type: "text/markdown".to_string(),
+            last_modified: 1_700_000_000_123,
+            size: 4,
+            etag: None,
+            version: None,
+        };
+
+        assert_eq!(
+            serde_json::to_string(&meta).unwrap(),
+            r#"{"name":"index.md","created":1700000000000,"perm":"rw","contentType":"text/markdown","lastModified":1700000000123,"size":4}"#
+        );
+
+        let headers: http::HeaderMap = meta.try_into().unwrap();
+        assert_eq!(headers.get("X-Created").unwrap(), "1700000000000");
+        assert_eq!(headers.get("X-Last-Modified").unwrap(), "1700000000123");
+    }
+
+    #[test]
+    fn file_meta_to_header_map_with_etag() {
+        let meta = FileMeta {
+            name: "test.txt".to_string(),
+            created: 0,
+            perm: "rw".to_string(),
+            content_type: "text/plain".to_string(),
+            last_modified: 0,
+            size: 0,
+            etag: Some("\"abc123\"".to_string()),
+            version: None,
+        };
+
+        let json = serde_json::to_value(&meta).unwrap();
+        assert_eq!(json["etag"], "\"abc123\"");
+
+        let headers: http::HeaderMap = meta.try_into().unwrap();
+        assert_eq!(headers.get(http::header::ETAG).unwrap(), "\"abc123\"");
+    }
+
     #[test]
     fn file_meta_to_header_map_invalid_content_type() {
         let meta = FileMeta {
@@ -286,6 +496,8 @@ mod http_tests {
             content_type: "invalid\x00header".to_string(),
             last_modified: 2000000,
             size: 42,
+            etag: None,
+            version: None,
         };
 
         let result: std::result::Result<http::HeaderMap, _> = meta.try_into();
@@ -300,13 +512,17 @@ mod http_tests {
             "application/json".parse().unwrap(),
         );
         headers.insert("x-created", "1234".parse().unwrap());
+        headers.insert("x-last-modified", "5678".parse().unwrap());
+        headers.insert(http::header::IF_MATCH, "\"v1\"".parse().unwrap());
 
         let meta: IncomingFileMeta = headers.try_into().unwrap();
 
         assert_eq!(meta.content_type, Some("application/json".to_string()));
         assert_eq!(meta.created, Some(1234));
+        assert_eq!(meta.if_match.as_deref(), Some("\"v1\""));
+        assert_eq!(meta.if_none_match, None);
         assert_eq!(meta.perm, None);
-        assert_eq!(meta.last_modified, None);
+        assert_eq!(meta.last_modified, Some(5678));
         assert_eq!(meta.size, None);
     }
 
@@ -317,7 +533,7 @@ mod http_tests {
         let meta: IncomingFileMeta = headers.try_into().unwrap();
 
         assert_eq!(meta.content_type, None);
-        assert!(meta.created.is_some()); // Should have fallback timestamp
+        assert_eq!(meta.created, None);
         assert_eq!(meta.perm, None);
         assert_eq!(meta.last_modified, None);
         assert_eq!(meta.size, None);
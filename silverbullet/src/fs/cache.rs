@@ -0,0 +1,351 @@
+//! In-memory cache of the files read, shared by the requests
+//!
+//! [`Filesystem`] keeps the content of the files read through it, up to
+//! [`Filesystem::capacity`] bytes, the least recently read ones being dropped first.
+//! Entries are keyed by the path and the version of the file, its etag or its
+//! modification time and size, so files changed behind the server are read again: each
+//! read still looks up the metadata, only the body comes from memory on a hit. Writes and
+//! deletes through the cache drop the entry of their path.
+//!
+//! ```ignore
+//! let fs = cache::Filesystem::new(opendal::Filesystem::new(operator)).capacity(64 << 20);
+//! ```
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::{TryStreamExt as _, stream};
+
+use crate::fs::*;
+
+/// Bytes of content kept, 32 MiB by default
+pub const DEFAULT_CAPACITY: u64 = 32 * 1024 * 1024;
+
+/// Largest file kept, 1 MiB by default
+pub const DEFAULT_MAX_SIZE: u64 = 1024 * 1024;
+
+pub struct Filesystem<F> {
+    inner: F,
+    entries: Mutex<Entries>,
+    capacity: u64,
+    max_size: u64,
+}
+
+/// Version of a file, changed by every write to it
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Version {
+    etag: Option<String>,
+    last_modified: u64,
+    size: u64,
+}
+
+impl From<&FileMeta> for Version {
+    fn from(meta: &FileMeta) -> Self {
+        Self {
+            etag: meta.etag.clone(),
+            last_modified: meta.last_modified,
+            size: meta.size,
+        }
+    }
+}
+
+struct Entry {
+    version: Version,
+    data: Bytes,
+    used: u64,
+}
+
+#[derive(Default)]
+struct Entries {
+    entries: HashMap<String, Entry>,
+    size: u64,
+    tick: u64,
+}
+
+impl Entries {
+    fn get(&mut self, path: &str, version: &Version) -> Option<Bytes> {
+        self.tick += 1;
+
+        let entry = self.entries.get_mut(path)?;
+        if entry.version != *version {
+            return None;
+        }
+        entry.used = self.tick;
+
+        Some(entry.data.clone())
+    }
+
+    fn insert(&mut self, path: &str, version: Version, data: Bytes, capacity: u64) {
+        self.tick += 1;
+        self.size += data.len() as u64;
+        let entry = Entry {
+            version,
+            data,
+            used: self.tick,
+        };
+        if let Some(old) = self.entries.insert(path.to_string(), entry) {
+            self.size -= old.data.len() as u64;
+        }
+
+        while self.size > capacity {
+            let Some(oldest) = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.used)
+                .map(|(path, _)| path.clone())
+            else {
+                break;
+            };
+
+            self.remove(&oldest);
+        }
+    }
+
+    fn remove(&mut self, path: &str) {
+        if let Some(entry) = self.entries.remove(path) {
+            self.size -= entry.data.len() as u64;
+        }
+    }
+}
+
+impl<F> Filesystem<F> {
+    pub fn new(inner: F) -> Self {
+        Self {
+            inner,
+            entries: Mutex::new(Entries::default()),
+            capacity: DEFAULT_CAPACITY,
+            max_size: DEFAULT_MAX_SIZE,
+        }
+    }
+
+    /// Bytes of content kept, [`DEFAULT_CAPACITY`] by default.
+    #[must_use]
+    pub fn capacity(mut self, bytes: u64) -> Self {
+        self.capacity = bytes;
+        self
+    }
+
+    /// Largest file kept, [`DEFAULT_MAX_SIZE`] by default. Bigger ones are streamed from
+    /// the filesystem.
+    #[must_use]
+    pub fn max_size(mut self, bytes: u64) -> Self {
+        self.max_size = bytes;
+        self
+    }
+
+    fn forget(&self, path: &str) {
+        self.entries.lock().unwrap().remove(path);
+    }
+}
+
+/// [`FsLayer`](crate::fs::stack::FsLayer) wrapping filesystems in a cache of `capacity`
+/// bytes
+#[derive(Debug, Clone, Copy)]
+pub struct CacheLayer {
+    capacity: u64,
+}
+
+impl CacheLayer {
+    pub fn new(capacity: u64) -> Self {
+        Self { capacity }
+    }
+}
+
+impl<F> crate::fs::stack::FsLayer<F> for CacheLayer {
+    type Output = Filesystem<F>;
+
+    fn layer(&self, inner: F) -> Self::Output {
+        Filesystem::new(inner).capacity(self.capacity)
+    }
+}
+
+fn once(data: Bytes) -> Stream {
+    stream::once(async move { Ok(data) }).into_boxed()
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl<F> ReadOnlyFilesystem for Filesystem<F>
+where
+    F: ReadOnlyFilesystem,
+{
+    async fn list(&self) -> Result<Vec<FileMeta>> {
+        self.inner.list().await
+    }
+
+    async fn get(&self, path: &str) -> Result<(Stream, FileMeta)> {
+        let meta = self.inner.meta(path).await?;
+        let cached = self.entries.lock().unwrap().get(path, &(&meta).into());
+        if let Some(data) = cached {
+            return Ok((once(data), meta));
+        }
+
+        let (stream, meta) = self.inner.get(path).await?;
+        if meta.size > self.max_size.min(self.capacity) {
+            return Ok((stream, meta));
+        }
+
+        let data = stream
+            .map_ok(|chunk| chunk.to_vec())
+            .try_concat()
+            .await
+            .map(Bytes::from)?;
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(path, (&meta).into(), data.clone(), self.capacity);
+
+        Ok((once(data), meta))
+    }
+
+    async fn meta(&self, path: &str) -> Result<FileMeta> {
+        self.inner.meta(path).await
+    }
+
+    async fn list_stream(&self) -> Result<FileStream> {
+        self.inner.list_stream().await
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl<F> WritableFilesystem for Filesystem<F>
+where
+    F: WritableFilesystem,
+{
+    async fn put(&self, path: &str, data: Stream, meta: IncomingFileMeta) -> Result<FileMeta> {
+        self.forget(path);
+        self.inner.put(path, data, meta).await
+    }
+
+    async fn delete(&self, path: &str) -> Result<()> {
+        self.forget(path);
+        self.inner.delete(path).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::testing::{MemoryFs, read_stream};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Counts the reads of the backend
+    struct Counted {
+        inner: MemoryFs,
+        gets: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl ReadOnlyFilesystem for Counted {
+        async fn list(&self) -> Result<Vec<FileMeta>> {
+            self.inner.list().await
+        }
+
+        async fn get(&self, path: &str) -> Result<(Stream, FileMeta)> {
+            self.gets.fetch_add(1, Ordering::Relaxed);
+            self.inner.get(path).await
+        }
+
+        async fn meta(&self, path: &str) -> Result<FileMeta> {
+            self.inner.meta(path).await
+        }
+    }
+
+    #[async_trait]
+    impl WritableFilesystem for Counted {
+        async fn put(&self, path: &str, data: Stream, meta: IncomingFileMeta) -> Result<FileMeta> {
+            self.inner.put(path, data, meta).await
+        }
+
+        async fn delete(&self, path: &str) -> Result<()> {
+            self.inner.delete(path).await
+        }
+    }
+
+    fn cached(inner: MemoryFs) -> Filesystem<Counted> {
+        Filesystem::new(Counted {
+            inner,
+            gets: AtomicUsize::new(0),
+        })
+    }
+
+    fn reads(fs: &Filesystem<Counted>) -> usize {
+        fs.inner.gets.load(Ordering::Relaxed)
+    }
+
+    async fn read(fs: &Filesystem<Counted>, path: &str) -> Vec<u8> {
+        read_stream(fs.get(path).await.unwrap().0).await
+    }
+
+    #[tokio::test]
+    async fn serves_reads_from_memory() {
+        let fs = cached(MemoryFs::new().with_file("index.md", b"home"));
+
+        assert_eq!(read(&fs, "index.md").await, b"home");
+        assert_eq!(read(&fs, "index.md").await, b"home");
+        assert_eq!(reads(&fs), 1);
+
+        // Changed behind the cache
+        let body = once(Bytes::from("new home"));
+        let incoming = IncomingFileMeta {
+            last_modified: Some(1),
+            ..Default::default()
+        };
+        fs.inner.put("index.md", body, incoming).await.unwrap();
+        assert_eq!(read(&fs, "index.md").await, b"new home");
+        assert_eq!(reads(&fs), 2);
+
+        fs.delete("index.md").await.unwrap();
+        assert!(matches!(fs.get("index.md").await, Err(Error::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn drops_writes_and_large_files() {
+        let fs = cached(
+            MemoryFs::new()
+                .with_file("index.md", b"home")
+                .with_file("photo.png", b"0123456789"),
+        )
+        .max_size(8);
+
+        read(&fs, "index.md").await;
+        fs.put(
+            "index.md",
+            once(Bytes::from("away")),
+            IncomingFileMeta::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(read(&fs, "index.md").await, b"away");
+        assert_eq!(reads(&fs), 2);
+
+        read(&fs, "photo.png").await;
+        read(&fs, "photo.png").await;
+        assert_eq!(reads(&fs), 4);
+    }
+
+    #[tokio::test]
+    async fn evicts_least_recently_read() {
+        let fs = cached(
+            MemoryFs::new()
+                .with_file("a.md", b"aaaa")
+                .with_file("b.md", b"bbbb")
+                .with_file("c.md", b"cccc"),
+        )
+        .capacity(8);
+
+        read(&fs, "a.md").await;
+        read(&fs, "b.md").await;
+        read(&fs, "a.md").await;
+        read(&fs, "c.md").await;
+        assert_eq!(reads(&fs), 3);
+
+        read(&fs, "a.md").await;
+        assert_eq!(reads(&fs), 3);
+        read(&fs, "b.md").await;
+        assert_eq!(reads(&fs), 4);
+    }
+}
@@ -0,0 +1,157 @@
+//! Case-insensitive file lookups
+//!
+//! [`Filesystem`] wraps a filesystem and, when a read finds no file with the exact
+//! name, falls back to the file whose name only differs in case, e.g. `get("index.md")`
+//! returns `Index.md`. The metadata keeps the stored name, so clients learn the canonical
+//! one. Links written on case-insensitive systems then keep working against case-sensitive
+//! storage like S3 or R2.
+//!
+//! The fallback lists the space, so misses cost a listing. Writes and deletes always use
+//! the exact name: resolving them would turn renames that only change case into
+//! overwrites.
+//!
+//! ```ignore
+//! let fs = case_insensitive::Filesystem::new(opendal::Filesystem::new(operator));
+//! ```
+
+use async_trait::async_trait;
+
+use crate::fs::*;
+
+pub struct Filesystem<F> {
+    inner: F,
+}
+
+impl<F> Filesystem<F> {
+    pub fn new(inner: F) -> Self {
+        Self { inner }
+    }
+}
+
+/// [`FsLayer`](crate::fs::stack::FsLayer) wrapping filesystems in [`Filesystem`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CaseInsensitiveLayer;
+
+impl<F> crate::fs::stack::FsLayer<F> for CaseInsensitiveLayer {
+    type Output = Filesystem<F>;
+
+    fn layer(&self, inner: F) -> Self::Output {
+        Filesystem::new(inner)
+    }
+}
+
+impl<F> Filesystem<F>
+where
+    F: ReadOnlyFilesystem,
+{
+    /// Stored name of a file only differing from `path` in case, the first in order when
+    /// several do.
+    async fn resolve(&self, path: &str) -> Result<Option<String>> {
+        let wanted = path.to_lowercase();
+
+        let name = self
+            .inner
+            .list()
+            .await?
+            .into_iter()
+            .map(|file| file.name)
+            .filter(|name| name != path && name.to_lowercase() == wanted)
+            .min();
+
+        Ok(name)
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl<F> ReadOnlyFilesystem for Filesystem<F>
+where
+    F: ReadOnlyFilesystem,
+{
+    async fn list(&self) -> Result<Vec<FileMeta>> {
+        self.inner.list().await
+    }
+
+    async fn list_stream(&self) -> Result<FileStream> {
+        self.inner.list_stream().await
+    }
+
+    async fn get(&self, path: &str) -> Result<(Stream, FileMeta)> {
+        match self.inner.get(path).await {
+            Err(Error::NotFound(err)) => match self.resolve(path).await? {
+                Some(name) => self.inner.get(&name).await,
+                None => Err(Error::NotFound(err)),
+            },
+            result => result,
+        }
+    }
+
+    async fn meta(&self, path: &str) -> Result<FileMeta> {
+        match self.inner.meta(path).await {
+            Err(Error::NotFound(err)) => match self.resolve(path).await? {
+                Some(name) => self.inner.meta(&name).await,
+                None => Err(Error::NotFound(err)),
+            },
+            result => result,
+        }
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl<F> WritableFilesystem for Filesystem<F>
+where
+    F: WritableFilesystem,
+{
+    async fn put(&self, path: &str, data: Stream, meta: IncomingFileMeta) -> Result<FileMeta> {
+        self.inner.put(path, data, meta).await
+    }
+
+    async fn delete(&self, path: &str) -> Result<()> {
+        self.inner.delete(path).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::testing::{MemoryFs, read_stream};
+
+    #[tokio::test]
+    async fn falls_back_to_other_cases() {
+        let fs = Filesystem::new(
+            MemoryFs::new()
+                .with_file("Index.md", b"home")
+                .with_file("Notes/Ideas.md", b"ideas"),
+        );
+
+        let (stream, meta) = fs.get("index.md").await.unwrap();
+        assert_eq!(read_stream(stream).await, b"home");
+        assert_eq!(meta.name, "Index.md");
+
+        assert_eq!(
+            fs.meta("notes/ideas.MD").await.unwrap().name,
+            "Notes/Ideas.md"
+        );
+        assert!(matches!(
+            fs.get("missing.md").await,
+            Err(Error::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn prefers_exact_matches() {
+        let fs = Filesystem::new(
+            MemoryFs::new()
+                .with_file("TODO.md", b"upper")
+                .with_file("todo.md", b"lower")
+                .with_file("Todo.md", b"title"),
+        );
+
+        let (stream, _) = fs.get("todo.md").await.unwrap();
+        assert_eq!(read_stream(stream).await, b"lower");
+
+        // The first in order among the other cases
+        assert_eq!(fs.meta("tODO.md").await.unwrap().name, "TODO.md");
+    }
+}
@@ -3,14 +3,47 @@ use std::collections::HashMap;
 use async_trait::async_trait;
 use bytes::Bytes;
 use futures::StreamExt;
-use worker::{Bucket, Data, FixedLengthStream, HttpMetadata, Include};
+use worker::{
+    Bucket, Data, FixedLengthStream, HttpMetadata, Include, MultipartUpload, UploadedPart,
+};
 
 use crate::fs::*;
 
+pub mod cache;
+pub mod coordinator;
+
+/// Uploads of unknown size up to this are buffered and stored with a single put
+const DEFAULT_BUFFER_LIMIT: usize = 8 * 1024 * 1024;
+
+/// Largest upload of unknown size
+const DEFAULT_MAX_UPLOAD_SIZE: u64 = 1024 * 1024 * 1024;
+
+/// Size of the multipart upload parts
+const DEFAULT_PART_SIZE: usize = 8 * 1024 * 1024;
+
+/// Smallest part R2 takes, but for the last one
+const MIN_PART_SIZE: usize = 5 * 1024 * 1024;
+
+/// Largest object R2 stores with a single put
+const MAX_PUT_SIZE: u64 = 5 * 1024 * 1024 * 1024 - 5 * 1024 * 1024;
+
+/// Folders listed at the same time by [`Filesystem::list`]
+const LIST_CONCURRENCY: usize = 8;
+
+/// Files directly in a folder and the folders in it
+#[derive(Debug, Clone, Default)]
+pub struct Folder {
+    pub files: Vec<FileMeta>,
+    /// Paths of the folders, ending with `/`
+    pub folders: Vec<String>,
+}
+
 pub struct Filesystem {
     bucket: Bucket,
     prefix: String,
-    allow_buffered_upload: bool,
+    buffer_limit: usize,
+    max_upload_size: u64,
+    part_size: usize,
 }
 
 // SAFETY: wasm32 is single-threaded, so Send + Sync is safe
@@ -22,15 +55,32 @@ impl Filesystem {
         Self {
             bucket,
             prefix,
-            allow_buffered_upload: false,
+            buffer_limit: DEFAULT_BUFFER_LIMIT,
+            max_upload_size: DEFAULT_MAX_UPLOAD_SIZE,
+            part_size: DEFAULT_PART_SIZE,
         }
     }
 
-    /// Allow falling back to buffered uploads when size is not provided.
-    /// This may cause memory issues with large files.
-    #[allow(dead_code)]
-    pub fn allow_buffered_upload(mut self, allow: bool) -> Self {
-        self.allow_buffered_upload = allow;
+    /// Bytes to buffer of uploads without a size, 8 MiB by default. Bigger uploads spill
+    /// to an R2 multipart upload, holding one part in memory at a time.
+    #[must_use]
+    pub fn buffer_limit(mut self, bytes: usize) -> Self {
+        self.buffer_limit = bytes;
+        self
+    }
+
+    /// Largest upload without a size, 1 GiB by default. Bigger uploads fail and their
+    /// multipart upload is aborted.
+    #[must_use]
+    pub fn max_upload_size(mut self, bytes: u64) -> Self {
+        self.max_upload_size = bytes;
+        self
+    }
+
+    /// Size of the parts of multipart uploads, 8 MiB by default and at least 5 MiB.
+    #[must_use]
+    pub fn part_size(mut self, bytes: usize) -> Self {
+        self.part_size = bytes.max(MIN_PART_SIZE);
         self
     }
 
@@ -54,39 +104,23 @@ impl Filesystem {
             path.strip_prefix(&prefix_with_slash).unwrap_or(path)
         }
     }
-}
-
-fn file_meta_from_r2_object(object: &worker::Object, name: &str) -> FileMeta {
-    let now_millis = worker::Date::now().as_millis();
-
-    let custom_metadata = object.custom_metadata().ok();
-    let created = custom_metadata
-        .as_ref()
-        .and_then(|m| m.get("created"))
-        .and_then(|s| s.parse().ok())
-        .unwrap_or(now_millis);
-
-    let content_type = object
-        .http_metadata()
-        .content_type
-        .unwrap_or_else(|| "application/octet-stream".to_string());
 
-    let last_modified = object.uploaded().as_millis();
+    /// List the files directly in `folder`, `""` for the top of the space, and the folders
+    /// in it, without going through the objects below them.
+    pub async fn list_folder(&self, folder: &str) -> Result<Folder> {
+        let folder = folder.trim_matches('/');
+        let prefix = if folder.is_empty() {
+            self.full_path("")
+        } else {
+            self.full_path(&format!("{folder}/"))
+        };
 
-    FileMeta {
-        name: name.to_string(),
-        created,
-        perm: "rw".to_string(),
-        content_type,
-        last_modified,
-        size: object.size(),
+        self.list_objects(prefix, Some("/")).await
     }
-}
 
-#[async_trait(?Send)]
-impl ReadOnlyFilesystem for Filesystem {
-    async fn list(&self) -> Result<Vec<FileMeta>> {
-        let mut all_objects = Vec::new();
+    /// All pages of a listing of `prefix`.
+    async fn list_objects(&self, prefix: String, delimiter: Option<&str>) -> Result<Folder> {
+        let mut folder = Folder::default();
         let mut cursor: Option<String> = None;
 
         loop {
@@ -95,9 +129,12 @@ impl ReadOnlyFilesystem for Filesystem {
                 .list()
                 .include(vec![Include::HttpMetadata, Include::CustomMetadata]);
 
-            if !self.prefix.is_empty() {
-                list_builder =
-                    list_builder.prefix(format!("{}/", self.prefix.trim_end_matches('/')));
+            if !prefix.is_empty() {
+                list_builder = list_builder.prefix(prefix.clone());
+            }
+
+            if let Some(delimiter) = delimiter {
+                list_builder = list_builder.delimiter(delimiter);
             }
 
             if let Some(ref c) = cursor {
@@ -111,16 +148,156 @@ impl ReadOnlyFilesystem for Filesystem {
 
             for obj in objects.objects() {
                 let name = self.strip_prefix(&obj.key()).to_string();
-                all_objects.push(file_meta_from_r2_object(&obj, &name));
+                folder.files.push(file_meta_from_r2_object(&obj, &name));
             }
 
+            folder.folders.extend(
+                objects
+                    .delimited_prefixes()
+                    .iter()
+                    .map(|prefix| self.strip_prefix(prefix).to_string()),
+            );
+
             cursor = objects.cursor();
             if cursor.is_none() || !objects.truncated() {
                 break;
             }
         }
 
-        Ok(all_objects)
+        Ok(folder)
+    }
+
+    /// Store `buffer` and the rest of `data`, up to `max_size` bytes, with a multipart upload.
+    ///
+    /// The upload is aborted if any part fails, so no parts are left behind.
+    async fn put_multipart(
+        &self,
+        key: &str,
+        http_metadata: HttpMetadata,
+        custom_metadata: HashMap<String, String>,
+        buffer: Vec<u8>,
+        data: Stream,
+        max_size: u64,
+    ) -> Result<worker::Object> {
+        let upload = self
+            .bucket
+            .create_multipart_upload(key)
+            .http_metadata(http_metadata)
+            .custom_metadata(custom_metadata)
+            .execute()
+            .await
+            .map_err(|e| Error::Other(e.to_string().into()))?;
+
+        let parts = match self.upload_parts(&upload, buffer, data, max_size).await {
+            Ok(parts) => parts,
+            Err(err) => {
+                let _ = upload.abort().await;
+                return Err(err);
+            }
+        };
+
+        upload
+            .complete(parts)
+            .await
+            .map_err(|e| Error::Other(e.to_string().into()))
+    }
+
+    async fn upload_parts(
+        &self,
+        upload: &MultipartUpload,
+        mut buffer: Vec<u8>,
+        mut data: Stream,
+        max_size: u64,
+    ) -> Result<Vec<UploadedPart>> {
+        let mut parts = Vec::new();
+        let mut size = buffer.len() as u64;
+
+        loop {
+            let chunk = data.next().await.transpose()?;
+            let done = chunk.is_none();
+
+            if let Some(chunk) = chunk {
+                size += chunk.len() as u64;
+                buffer.extend_from_slice(&chunk);
+            }
+
+            if size > max_size {
+                return Err(Error::Other(
+                    format!("Upload larger than {max_size} bytes").into(),
+                ));
+            }
+
+            // All parts but the last have the same size
+            while buffer.len() >= self.part_size || (done && !buffer.is_empty()) {
+                let rest = buffer.split_off(buffer.len().min(self.part_size));
+                let part_number = u16::try_from(parts.len() + 1)
+                    .map_err(|_| Error::Other("Upload has too many parts".into()))?;
+
+                let part = upload
+                    .upload_part(part_number, std::mem::replace(&mut buffer, rest))
+                    .await
+                    .map_err(|e| Error::Other(e.to_string().into()))?;
+                parts.push(part);
+            }
+
+            if done {
+                return Ok(parts);
+            }
+        }
+    }
+}
+
+fn file_meta_from_r2_object(object: &worker::Object, name: &str) -> FileMeta {
+    let now_millis = worker::Date::now().as_millis();
+
+    let custom_metadata = object.custom_metadata().ok();
+    let custom_time = |key: &str| {
+        custom_metadata
+            .as_ref()
+            .and_then(|m| m.get(key))
+            .and_then(|s| s.parse().ok())
+    };
+    let created = custom_time("created").unwrap_or(now_millis);
+
+    let content_type = object
+        .http_metadata()
+        .content_type
+        .unwrap_or_else(|| "application/octet-stream".to_string());
+
+    // The time given to the write, e.g. of a file synced from a client, over the upload's
+    let last_modified =
+        custom_time("last_modified").unwrap_or_else(|| object.uploaded().as_millis());
+
+    FileMeta {
+        name: name.to_string(),
+        created,
+        perm: perm::Perm::ReadWrite.to_string(),
+        content_type,
+        last_modified,
+        size: object.size(),
+        etag: Some(object.http_etag()),
+        version: Some(object.version()),
+    }
+}
+
+#[async_trait(?Send)]
+impl ReadOnlyFilesystem for Filesystem {
+    async fn list(&self) -> Result<Vec<FileMeta>> {
+        // Top level first, then the folders side by side, since pages of one listing
+        // have to be fetched one after the other
+        let Folder { mut files, folders } = self.list_folder("").await?;
+
+        let mut listings = futures::stream::iter(folders)
+            .map(|folder| async move { self.list_objects(self.full_path(&folder), None).await })
+            .buffer_unordered(LIST_CONCURRENCY);
+
+        while let Some(listing) = listings.next().await {
+            files.extend(listing?.files);
+        }
+
+        files.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(files)
     }
 
     async fn get(&self, path: &str) -> Result<(Stream, FileMeta)> {
@@ -179,12 +356,39 @@ impl WritableFilesystem for Filesystem {
             ..Default::default()
         };
 
+        // Overwrites keep the creation time of the file
+        let created = match meta.created {
+            Some(created) => created,
+            None => self
+                .bucket
+                .head(&full_path)
+                .await
+                .map_err(|e| Error::Other(e.to_string().into()))?
+                .and_then(|object| object.custom_metadata().ok()?.get("created")?.parse().ok())
+                .unwrap_or_else(|| worker::Date::now().as_millis()),
+        };
+
         let mut custom_metadata = HashMap::<String, String>::new();
-        if let Some(created) = meta.created {
-            custom_metadata.insert("created".to_string(), created.to_string());
+        custom_metadata.insert("created".to_string(), created.to_string());
+        if let Some(last_modified) = meta.last_modified {
+            custom_metadata.insert("last_modified".to_string(), last_modified.to_string());
         }
 
         let r2_data = match meta.size {
+            Some(size) if size > MAX_PUT_SIZE => {
+                let object = self
+                    .put_multipart(
+                        &full_path,
+                        http_metadata,
+                        custom_metadata,
+                        Vec::new(),
+                        data,
+                        size,
+                    )
+                    .await?;
+
+                return Ok(file_meta_from_r2_object(&object, path));
+            }
             Some(size) => {
                 // Stream directly to R2 without buffering
                 let byte_stream = data.map(|result| {
@@ -194,19 +398,33 @@ impl WritableFilesystem for Filesystem {
                 });
                 Data::Stream(FixedLengthStream::wrap(byte_stream, size))
             }
-            None if self.allow_buffered_upload => {
-                // Fall back to buffering the entire file in memory
+            None => {
+                // Buffer small files, spill to a multipart upload past the limit
                 let mut buffer = Vec::new();
-                while let Some(chunk) = data.next().await {
-                    buffer.extend_from_slice(&chunk?);
+                while buffer.len() <= self.buffer_limit {
+                    match data.next().await {
+                        Some(chunk) => buffer.extend_from_slice(&chunk?),
+                        None => break,
+                    }
                 }
+
+                if buffer.len() > self.buffer_limit {
+                    let object = self
+                        .put_multipart(
+                            &full_path,
+                            http_metadata,
+                            custom_metadata,
+                            buffer,
+                            data,
+                            self.max_upload_size,
+                        )
+                        .await?;
+
+                    return Ok(file_meta_from_r2_object(&object, path));
+                }
+
                 Data::Bytes(buffer)
             }
-            None => {
-                return Err(Error::Other(
-                    "Size must be provided for streaming uploads".into(),
-                ));
-            }
         };
 
         let object = self
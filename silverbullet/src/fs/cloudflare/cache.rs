@@ -0,0 +1,178 @@
+//! Attachment reads served from the Cloudflare Cache API
+//!
+//! [`Cached`] wraps a filesystem and keeps the attachments read through it, every file but
+//! the `.md` pages, in the cache of the data center. Entries are keyed by the path and the
+//! etag of the file, so they never go stale: a write changes the etag and the next read
+//! misses. Each read still costs a metadata lookup, the body only comes from the
+//! filesystem on a miss, which cuts the R2 reads and egress of image-heavy spaces.
+//!
+//! The Cache API needs a custom domain, on `workers.dev` every read misses.
+//!
+//! ```ignore
+//! let fs = Cached::new(Filesystem::new(env.bucket("SPACE")?, String::new())).namespace("notes");
+//! ```
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::{StreamExt as _, TryStreamExt as _, stream};
+use worker::{Cache, Headers, Response, Url};
+
+use crate::fs::*;
+
+/// Files larger than this are streamed past the cache
+const DEFAULT_MAX_SIZE: u64 = 8 * 1024 * 1024;
+
+/// Lifetime of the entries, they are immutable
+const CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+
+pub struct Cached<F> {
+    inner: F,
+    namespace: String,
+    max_size: u64,
+}
+
+impl<F> Cached<F> {
+    pub fn new(inner: F) -> Self {
+        Self {
+            inner,
+            namespace: "space".to_string(),
+            max_size: DEFAULT_MAX_SIZE,
+        }
+    }
+
+    /// Keep the entries apart from other spaces served on the same zone.
+    #[must_use]
+    pub fn namespace(mut self, namespace: impl Into<String>) -> Self {
+        self.namespace = namespace.into();
+        self
+    }
+
+    /// Largest file to cache, in bytes. Cached files are read into memory, bigger ones are
+    /// streamed from the filesystem.
+    #[must_use]
+    pub fn max_size(mut self, max_size: u64) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    fn key(&self, meta: &FileMeta) -> Result<String> {
+        let mut url = Url::parse("https://silverbullet.cache/")
+            .map_err(|err| Error::Other(err.to_string().into()))?;
+
+        url.path_segments_mut()
+            .map_err(|()| Error::Other("cache URL can't have a path".into()))?
+            .push(&self.namespace)
+            .extend(meta.name.split('/'));
+
+        url.query_pairs_mut().append_pair("v", &version(meta));
+
+        Ok(url.into())
+    }
+}
+
+/// Version tag of a file, changed by every write to it.
+fn version(meta: &FileMeta) -> String {
+    match &meta.etag {
+        Some(etag) => etag.trim_matches('"').to_string(),
+        None => format!("{:x}-{:x}", meta.last_modified, meta.size),
+    }
+}
+
+/// Attachments are cached, pages are edited all the time.
+fn cacheable(path: &str) -> bool {
+    !path.ends_with(".md")
+}
+
+fn cache_error(err: worker::Error) -> Error {
+    Error::Other(err.to_string().into())
+}
+
+fn stream_of(mut response: Response) -> Result<Stream> {
+    let stream = response.stream().map_err(cache_error)?.map(|result| {
+        result
+            .map(Bytes::from)
+            .map_err(|err| std::io::Error::other(err.to_string()))
+    });
+
+    Ok(stream.into_boxed())
+}
+
+#[async_trait(?Send)]
+impl<F> ReadOnlyFilesystem for Cached<F>
+where
+    F: ReadOnlyFilesystem,
+{
+    async fn list(&self) -> Result<Vec<FileMeta>> {
+        self.inner.list().await
+    }
+
+    async fn list_stream(&self) -> Result<FileStream> {
+        self.inner.list_stream().await
+    }
+
+    async fn get(&self, path: &str) -> Result<(Stream, FileMeta)> {
+        if !cacheable(path) {
+            return self.inner.get(path).await;
+        }
+
+        let meta = self.inner.meta(path).await?;
+        if meta.size > self.max_size {
+            return self.inner.get(path).await;
+        }
+
+        let key = self.key(&meta)?;
+        let cache = Cache::default();
+
+        if let Some(response) = cache.get(&key, false).await.map_err(cache_error)? {
+            return Ok((stream_of(response)?, meta));
+        }
+
+        let (data, meta) = self.inner.get(path).await?;
+        let bytes: Vec<u8> = data
+            .try_fold(Vec::new(), |mut acc, chunk| async move {
+                acc.extend_from_slice(&chunk);
+                Ok(acc)
+            })
+            .await?;
+
+        // Written between the lookup and the read, it is cached under the version read
+        let key = self.key(&meta)?;
+
+        let headers = Headers::new();
+        headers
+            .set("cache-control", CACHE_CONTROL)
+            .map_err(cache_error)?;
+        headers
+            .set("content-type", &meta.content_type)
+            .map_err(cache_error)?;
+
+        let response = Response::from_bytes(bytes.clone())
+            .map_err(cache_error)?
+            .with_headers(headers);
+
+        // A full cache only costs the next read
+        let _ = cache.put(&key, response).await;
+
+        let data = stream::once(async move { Ok(Bytes::from(bytes)) });
+
+        Ok((data.into_boxed(), meta))
+    }
+
+    async fn meta(&self, path: &str) -> Result<FileMeta> {
+        self.inner.meta(path).await
+    }
+}
+
+#[async_trait(?Send)]
+impl<F> WritableFilesystem for Cached<F>
+where
+    F: WritableFilesystem,
+{
+    async fn put(&self, path: &str, data: Stream, meta: IncomingFileMeta) -> Result<FileMeta> {
+        self.inner.put(path, data, meta).await
+    }
+
+    async fn delete(&self, path: &str) -> Result<()> {
+        self.inner.delete(path).await
+    }
+}
@@ -0,0 +1,446 @@
+//! Writes serialized through a Durable Object
+//!
+//! R2 keeps the last write when clients write the same object concurrently. With a
+//! [`Coordinator`] behind a Durable Object, [`Coordinated`] sends all writes of a space to
+//! that one object, which applies writes to the same path one after the other and records
+//! every change in a journal. The journal gives clients a sync cursor: [`Coordinated::changes`]
+//! returns the changes after the cursor they last saw.
+//!
+//! The Durable Object class has to be defined by the worker:
+//!
+//! ```ignore
+//! #[durable_object]
+//! pub struct SpaceCoordinator(Coordinator);
+//!
+//! impl DurableObject for SpaceCoordinator {
+//!     fn new(state: State, env: Env) -> Self {
+//!         let bucket = env.bucket("SPACE").expect("missing SPACE bucket");
+//!         Self(Coordinator::new(&state, Filesystem::new(bucket, String::new())))
+//!     }
+//!
+//!     async fn fetch(&self, request: Request) -> worker::Result<Response> {
+//!         self.0.fetch(request).await
+//!     }
+//! }
+//! ```
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use async_trait::async_trait;
+use futures::lock::Mutex;
+use futures::{StreamExt as _, TryStreamExt as _};
+use serde::{Deserialize, Serialize};
+use worker::js_sys::{Object, Reflect};
+use worker::wasm_bindgen::JsValue;
+use worker::{Headers, ListOptions, Method, RequestInit, Storage, Stub, Url};
+
+use super::Filesystem;
+use crate::fs::*;
+
+/// Changes kept in the journal, older ones are dropped
+const JOURNAL_SIZE: u64 = 10_000;
+
+/// Changes returned per [`Coordinated::changes`] call
+const PAGE_SIZE: usize = 1_000;
+
+const BASE_URL: &str = "https://coordinator/";
+
+/// A change recorded in the journal
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Change {
+    /// Position in the journal, increasing with every change
+    pub seq: u64,
+    pub name: String,
+    pub kind: ChangeKind,
+    /// Time of the change, in milliseconds since the epoch
+    pub time: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Put,
+    Delete,
+}
+
+/// Page of the journal
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct Changes {
+    /// Cursor to continue from, the `seq` of the last change
+    pub cursor: u64,
+    /// Oldest first, empty when up to date
+    pub changes: Vec<Change>,
+}
+
+fn journal_key(seq: u64) -> String {
+    format!("journal:{seq:020}")
+}
+
+fn file_url(path: &str) -> worker::Result<Url> {
+    let mut url = Url::parse(BASE_URL)?.join("file")?;
+    url.query_pairs_mut().append_pair("path", path);
+    Ok(url)
+}
+
+/// Request handler of the Durable Object, applying the writes it receives
+pub struct Coordinator {
+    fs: Filesystem,
+    storage: Storage,
+    locks: RefCell<HashMap<String, Rc<Mutex<()>>>>,
+    cursor: Cell<Option<u64>>,
+}
+
+impl Coordinator {
+    pub fn new(state: &worker::State, fs: Filesystem) -> Self {
+        Self {
+            fs,
+            storage: state.storage(),
+            locks: RefCell::default(),
+            cursor: Cell::new(None),
+        }
+    }
+
+    /// Handle a request sent by [`Coordinated`].
+    pub async fn fetch(&self, mut request: worker::Request) -> worker::Result<worker::Response> {
+        let url = request.url()?;
+
+        let param = |name: &str| {
+            url.query_pairs()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.into_owned())
+        };
+
+        let result = match (request.method(), url.path()) {
+            (Method::Get, "/journal") => {
+                let since = param("since").and_then(|since| since.parse().ok());
+                let changes = self.changes(since.unwrap_or(0)).await?;
+
+                return worker::Response::from_json(&changes);
+            }
+            (Method::Put, "/file") => {
+                let path = param("path").unwrap_or_default();
+                let meta = incoming_meta(request.headers());
+                let body = request
+                    .stream()?
+                    .map(|chunk| {
+                        chunk
+                            .map(Bytes::from)
+                            .map_err(|err| std::io::Error::other(err.to_string()))
+                    })
+                    .into_boxed();
+
+                self.put(&path, body, meta).await.map(Some)
+            }
+            (Method::Delete, "/file") => {
+                let path = param("path").unwrap_or_default();
+
+                self.delete(&path).await.map(|()| None)
+            }
+            _ => return worker::Response::error("Not found", 404),
+        };
+
+        match result {
+            Ok(Some(meta)) => worker::Response::from_json(&meta),
+            Ok(None) => worker::Response::ok("OK"),
+            Err(Error::NotFound(err)) => worker::Response::error(err.to_string(), 404),
+            Err(err) => worker::Response::error(err.to_string(), 500),
+        }
+    }
+
+    async fn put(&self, path: &str, data: Stream, meta: IncomingFileMeta) -> Result<FileMeta> {
+        let lock = self.lock(path);
+
+        let result = {
+            let _guard = lock.lock().await;
+
+            match self.fs.put(path, data, meta).await {
+                Ok(meta) => self.record(path, ChangeKind::Put).await.map(|()| meta),
+                Err(err) => Err(err),
+            }
+        };
+
+        self.unlock(path, lock);
+        result
+    }
+
+    async fn delete(&self, path: &str) -> Result<()> {
+        let lock = self.lock(path);
+
+        let result = {
+            let _guard = lock.lock().await;
+
+            match self.fs.delete(path).await {
+                Ok(()) => self.record(path, ChangeKind::Delete).await,
+                Err(err) => Err(err),
+            }
+        };
+
+        self.unlock(path, lock);
+        result
+    }
+
+    fn lock(&self, path: &str) -> Rc<Mutex<()>> {
+        self.locks
+            .borrow_mut()
+            .entry(path.to_string())
+            .or_default()
+            .clone()
+    }
+
+    /// Forget the lock of a path once no other write waits for it.
+    fn unlock(&self, path: &str, lock: Rc<Mutex<()>>) {
+        let mut locks = self.locks.borrow_mut();
+
+        // One reference in the map, one held here
+        if Rc::strong_count(&lock) <= 2 {
+            locks.remove(path);
+        }
+    }
+
+    async fn cursor(&self) -> Result<u64> {
+        if let Some(cursor) = self.cursor.get() {
+            return Ok(cursor);
+        }
+
+        let stored = self
+            .storage
+            .get::<u64>("cursor")
+            .await
+            .map_err(storage_error)?
+            .unwrap_or(0);
+
+        // Another request may have loaded and advanced it meanwhile
+        match self.cursor.get() {
+            Some(cursor) => Ok(cursor),
+            None => {
+                self.cursor.set(Some(stored));
+                Ok(stored)
+            }
+        }
+    }
+
+    async fn record(&self, name: &str, kind: ChangeKind) -> Result<()> {
+        // Advanced right away, so concurrent writes get distinct positions
+        let seq = self.cursor().await? + 1;
+        self.cursor.set(Some(seq));
+
+        let change = Change {
+            seq,
+            name: name.to_string(),
+            kind,
+            time: time::now(),
+        };
+        let entry = serde_json::to_string(&change).map_err(|err| Error::Other(err.into()))?;
+
+        // Both in one write, so the cursor never points past the journal
+        let values = Object::new();
+        for (key, value) in [
+            (journal_key(seq), JsValue::from_str(&entry)),
+            ("cursor".to_string(), JsValue::from_f64(seq as f64)),
+        ] {
+            Reflect::set(&values, &JsValue::from_str(&key), &value)
+                .map_err(|err| Error::Other(format!("{err:?}").into()))?;
+        }
+
+        self.storage
+            .put_multiple_raw(values)
+            .await
+            .map_err(storage_error)?;
+
+        if seq > JOURNAL_SIZE {
+            self.storage
+                .delete(&journal_key(seq - JOURNAL_SIZE))
+                .await
+                .map_err(storage_error)?;
+        }
+
+        Ok(())
+    }
+
+    async fn changes(&self, since: u64) -> worker::Result<Changes> {
+        let start = journal_key(since + 1);
+        let entries = self
+            .storage
+            .list_with_options(
+                ListOptions::new()
+                    .prefix("journal:")
+                    .start(&start)
+                    .limit(PAGE_SIZE),
+            )
+            .await?;
+
+        let mut changes = Vec::new();
+        entries.for_each(&mut |value: JsValue, _key: JsValue| {
+            if let Some(change) = value
+                .as_string()
+                .and_then(|entry| serde_json::from_str::<Change>(&entry).ok())
+            {
+                changes.push(change);
+            }
+        });
+
+        let cursor = changes.last().map_or(since, |change| change.seq);
+
+        Ok(Changes { cursor, changes })
+    }
+}
+
+fn storage_error(err: worker::Error) -> Error {
+    Error::Other(err.to_string().into())
+}
+
+fn incoming_meta(headers: &Headers) -> IncomingFileMeta {
+    let number = |name: &str| {
+        headers
+            .get(name)
+            .ok()
+            .flatten()
+            .and_then(|value| value.parse().ok())
+    };
+
+    IncomingFileMeta {
+        created: number("x-created"),
+        last_modified: number("x-last-modified"),
+        content_type: headers.get("content-type").ok().flatten(),
+        size: number("x-content-length"),
+        ..Default::default()
+    }
+}
+
+/// Space reading from R2 directly and writing through the coordinator
+pub struct Coordinated {
+    fs: Filesystem,
+    stub: Stub,
+}
+
+// SAFETY: wasm32 is single-threaded, so Send + Sync is safe
+unsafe impl Send for Coordinated {}
+unsafe impl Sync for Coordinated {}
+
+impl Coordinated {
+    /// Send writes to the Durable Object of `namespace` named `space`.
+    pub fn new(fs: Filesystem, namespace: &worker::ObjectNamespace, space: &str) -> Result<Self> {
+        let stub = namespace
+            .get_by_name(space)
+            .map_err(|err| Error::Other(err.to_string().into()))?;
+
+        Ok(Self { fs, stub })
+    }
+
+    /// Changes after the cursor, at most a page of them.
+    pub async fn changes(&self, since: u64) -> Result<Changes> {
+        let mut url = Url::parse(BASE_URL)
+            .and_then(|url| url.join("journal"))
+            .map_err(|err| Error::Other(err.into()))?;
+        url.query_pairs_mut()
+            .append_pair("since", &since.to_string());
+
+        let mut response = self
+            .send(worker::Request::new(url.as_str(), Method::Get))
+            .await?;
+
+        response
+            .json()
+            .await
+            .map_err(|err| Error::Other(err.to_string().into()))
+    }
+
+    async fn send(&self, request: worker::Result<worker::Request>) -> Result<worker::Response> {
+        let error = |err: worker::Error| Error::Other(err.to_string().into());
+
+        let mut response = self
+            .stub
+            .fetch_with_request(request.map_err(error)?)
+            .await
+            .map_err(error)?;
+
+        match response.status_code() {
+            200..300 => Ok(response),
+            404 => Err(Error::NotFound(
+                response.text().await.unwrap_or_default().into(),
+            )),
+            status => Err(Error::Other(
+                format!(
+                    "coordinator responded with {status}: {}",
+                    response.text().await.unwrap_or_default()
+                )
+                .into(),
+            )),
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl ReadOnlyFilesystem for Coordinated {
+    async fn list(&self) -> Result<Vec<FileMeta>> {
+        self.fs.list().await
+    }
+
+    async fn list_stream(&self) -> Result<FileStream> {
+        self.fs.list_stream().await
+    }
+
+    async fn get(&self, path: &str) -> Result<(Stream, FileMeta)> {
+        self.fs.get(path).await
+    }
+
+    async fn meta(&self, path: &str) -> Result<FileMeta> {
+        self.fs.meta(path).await
+    }
+}
+
+#[async_trait(?Send)]
+impl WritableFilesystem for Coordinated {
+    async fn put(&self, path: &str, data: Stream, meta: IncomingFileMeta) -> Result<FileMeta> {
+        let error = |err: worker::Error| Error::Other(err.to_string().into());
+
+        let headers = Headers::new();
+        if let Some(created) = meta.created {
+            headers
+                .set("x-created", &created.to_string())
+                .map_err(error)?;
+        }
+        if let Some(last_modified) = meta.last_modified {
+            headers
+                .set("x-last-modified", &last_modified.to_string())
+                .map_err(error)?;
+        }
+        if let Some(size) = meta.size {
+            headers
+                .set("x-content-length", &size.to_string())
+                .map_err(error)?;
+        }
+        if let Some(content_type) = &meta.content_type {
+            headers.set("content-type", content_type).map_err(error)?;
+        }
+
+        let body = wasm_streams::ReadableStream::from_stream(
+            data.map_ok(|chunk| JsValue::from(worker::js_sys::Uint8Array::from(chunk.as_ref())))
+                .map_err(|err| JsValue::from_str(&err.to_string())),
+        );
+
+        let mut init = RequestInit::new();
+        init.with_method(Method::Put)
+            .with_headers(headers)
+            .with_body(Some(body.into_raw().into()));
+
+        let url = file_url(path).map_err(error)?;
+        let mut response = self
+            .send(worker::Request::new_with_init(url.as_str(), &init))
+            .await?;
+
+        response.json().await.map_err(error)
+    }
+
+    async fn delete(&self, path: &str) -> Result<()> {
+        let url = file_url(path).map_err(|err| Error::Other(err.to_string().into()))?;
+
+        self.send(worker::Request::new(url.as_str(), Method::Delete))
+            .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,261 @@
+//! Checks of the behavior a filesystem must have
+//!
+//! The server and the wrappers of [`crate::fs`] rely on backends behaving alike: a put
+//! replaces the whole file, missing files are [`Error::NotFound`], and the metadata of a
+//! file is the same whether it's read, listed or returned by the write. [`check`] runs
+//! every check of this module against a filesystem, panicking at the first difference, so
+//! a new backend proves it behaves with a test. Enabled with the `test-util` feature.
+//!
+//! ```ignore
+//! #[tokio::test]
+//! async fn conforms() {
+//!     silverbullet::fs::conformance::check(&MyFilesystem::new()).await;
+//! }
+//! ```
+//!
+//! The checks write under `conformance/`, which must not exist, and delete their files.
+
+use bytes::Bytes;
+use futures::{TryStreamExt as _, stream};
+
+use crate::fs::*;
+
+/// Size of the body of [`large_body`], 5 MiB
+pub const LARGE_BODY: usize = 5 * 1024 * 1024;
+
+/// Run every check.
+pub async fn check<F: ReadWriteFilesystem>(fs: &F) {
+    missing(fs).await;
+    overwrite(fs).await;
+    metadata(fs).await;
+    large_body(fs).await;
+    unicode_names(fs).await;
+}
+
+fn body(data: impl Into<Bytes>) -> Stream {
+    let data = data.into();
+
+    stream::once(async move { Ok(data) }).into_boxed()
+}
+
+async fn read<F: ReadWriteFilesystem>(fs: &F, path: &str) -> (Vec<u8>, FileMeta) {
+    let (stream, meta) = fs
+        .get(path)
+        .await
+        .unwrap_or_else(|err| panic!("get {path}: {err}"));
+    let data = stream
+        .map_ok(|chunk| chunk.to_vec())
+        .try_concat()
+        .await
+        .unwrap_or_else(|err| panic!("read {path}: {err}"));
+
+    (data, meta)
+}
+
+async fn put<F: ReadWriteFilesystem>(fs: &F, path: &str, data: Stream) -> FileMeta {
+    fs.put(path, data, IncomingFileMeta::default())
+        .await
+        .unwrap_or_else(|err| panic!("put {path}: {err}"))
+}
+
+async fn delete<F: ReadWriteFilesystem>(fs: &F, path: &str) {
+    fs.delete(path)
+        .await
+        .unwrap_or_else(|err| panic!("delete {path}: {err}"));
+}
+
+async fn listed<F: ReadWriteFilesystem>(fs: &F, path: &str) -> Vec<FileMeta> {
+    let files = fs.list().await.unwrap_or_else(|err| panic!("list: {err}"));
+
+    files.into_iter().filter(|file| file.name == path).collect()
+}
+
+/// Missing files are [`Error::NotFound`] for every call, and aren't listed.
+pub async fn missing<F: ReadWriteFilesystem>(fs: &F) {
+    let path = "conformance/missing.md";
+
+    assert!(
+        matches!(fs.get(path).await, Err(Error::NotFound(_))),
+        "get of a missing file should be NotFound"
+    );
+    assert!(
+        matches!(fs.meta(path).await, Err(Error::NotFound(_))),
+        "meta of a missing file should be NotFound"
+    );
+    assert!(
+        matches!(fs.delete(path).await, Err(Error::NotFound(_))),
+        "delete of a missing file should be NotFound"
+    );
+    assert!(
+        listed(fs, path).await.is_empty(),
+        "a missing file should not be listed"
+    );
+
+    // Nor once deleted
+    put(fs, path, body("gone")).await;
+    delete(fs, path).await;
+    assert!(
+        matches!(fs.get(path).await, Err(Error::NotFound(_))),
+        "get of a deleted file should be NotFound"
+    );
+    assert!(
+        matches!(fs.delete(path).await, Err(Error::NotFound(_))),
+        "delete of a deleted file should be NotFound"
+    );
+}
+
+/// A put replaces the whole file, also with a shorter one, and it's listed once.
+pub async fn overwrite<F: ReadWriteFilesystem>(fs: &F) {
+    let path = "conformance/overwrite.md";
+
+    put(fs, path, body("a longer first version")).await;
+    let meta = put(fs, path, body("second")).await;
+    assert_eq!(meta.size, 6, "size of an overwritten file");
+
+    let (data, meta) = read(fs, path).await;
+    assert_eq!(data, b"second", "content of an overwritten file");
+    assert_eq!(meta.size, 6, "size of an overwritten file");
+    assert_eq!(
+        listed(fs, path).await.len(),
+        1,
+        "an overwritten file should be listed once"
+    );
+
+    delete(fs, path).await;
+}
+
+/// The metadata of a file is the same from put, get, meta and list.
+pub async fn metadata<F: ReadWriteFilesystem>(fs: &F) {
+    let path = "conformance/metadata.md";
+    let incoming = IncomingFileMeta {
+        content_type: Some("text/markdown".to_string()),
+        created: Some(1_600_000_000_000),
+        last_modified: Some(1_700_000_000_000),
+        ..Default::default()
+    };
+
+    let written = fs
+        .put(path, body("# Metadata"), incoming)
+        .await
+        .unwrap_or_else(|err| panic!("put {path}: {err}"));
+    assert_eq!(written.name, path, "name from put");
+    assert_eq!(written.size, 10, "size from put");
+    assert!(
+        matches!(written.perm.as_str(), "rw" | "ro"),
+        "perm should be rw or ro, got {:?}",
+        written.perm
+    );
+
+    // Times are in milliseconds, see `fs::time`
+    assert!(
+        written.last_modified == 0 || written.last_modified > 1_000_000_000_000,
+        "last_modified should be in milliseconds, got {}",
+        written.last_modified
+    );
+
+    let same = |meta: &FileMeta, call: &str| {
+        assert_eq!(meta.name, written.name, "name from {call}");
+        assert_eq!(meta.size, written.size, "size from {call}");
+        assert_eq!(meta.perm, written.perm, "perm from {call}");
+        assert_eq!(
+            meta.content_type, written.content_type,
+            "content type from {call}"
+        );
+        assert_eq!(
+            meta.last_modified, written.last_modified,
+            "last_modified from {call}"
+        );
+        assert_eq!(meta.created, written.created, "created from {call}");
+    };
+
+    let (_, got) = read(fs, path).await;
+    same(&got, "get");
+    same(
+        &fs.meta(path)
+            .await
+            .unwrap_or_else(|err| panic!("meta {path}: {err}")),
+        "meta",
+    );
+
+    match listed(fs, path).await.as_slice() {
+        [file] => same(file, "list"),
+        files => panic!("{path} should be listed once, got {}", files.len()),
+    }
+
+    delete(fs, path).await;
+}
+
+/// A body of [`LARGE_BODY`] bytes in many chunks is written and read back whole.
+pub async fn large_body<F: ReadWriteFilesystem>(fs: &F) {
+    let path = "conformance/large.bin";
+    let data: Vec<u8> = (0..LARGE_BODY).map(|i| (i % 251) as u8).collect();
+
+    let chunks: Vec<_> = data
+        .chunks(64 * 1024)
+        .map(|chunk| Ok(Bytes::copy_from_slice(chunk)))
+        .collect();
+    let meta = put(fs, path, stream::iter(chunks).into_boxed()).await;
+    assert_eq!(meta.size, LARGE_BODY as u64, "size of a large file");
+
+    let (read, meta) = read(fs, path).await;
+    assert_eq!(meta.size, LARGE_BODY as u64, "size of a large file");
+    assert!(
+        read == data,
+        "content of a large file should be the one written"
+    );
+
+    delete(fs, path).await;
+}
+
+/// Names with spaces and non-ASCII characters are kept as written.
+pub async fn unicode_names<F: ReadWriteFilesystem>(fs: &F) {
+    for path in [
+        "conformance/Café ☕/Crème brûlée.md",
+        "conformance/日本語/ページ.md",
+        "conformance/Emoji 🚀 & co.md",
+    ] {
+        let meta = put(fs, path, body(path.to_string())).await;
+        assert_eq!(meta.name, path, "name of a file written");
+
+        let (data, meta) = read(fs, path).await;
+        assert_eq!(data, path.as_bytes(), "content of {path}");
+        assert_eq!(meta.name, path, "name of a file read");
+        assert_eq!(listed(fs, path).await.len(), 1, "{path} should be listed");
+
+        delete(fs, path).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::testing::MemoryFs;
+
+    #[tokio::test]
+    async fn memory_fs_conforms() {
+        check(&MemoryFs::new()).await;
+    }
+
+    #[cfg(feature = "opendal")]
+    #[tokio::test]
+    async fn opendal_conforms() {
+        // The memory service has no file times, so the metadata wouldn't be the same
+        let root =
+            std::env::temp_dir().join(format!("silverbullet-conformance-{}", std::process::id()));
+        let operator = ::opendal::Operator::new(
+            ::opendal::services::Fs::default().root(root.to_str().unwrap()),
+        )
+        .unwrap()
+        .finish();
+
+        check(&crate::fs::opendal::Filesystem::new(operator)).await;
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[cfg(all(not(target_arch = "wasm32"), feature = "sqlite"))]
+    #[tokio::test]
+    async fn sqlite_conforms() {
+        check(&crate::fs::sqlite::Filesystem::in_memory().unwrap()).await;
+    }
+}
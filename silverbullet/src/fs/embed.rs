@@ -66,16 +66,18 @@ impl From<(&str, rust_embed::EmbeddedFile)> for FileMeta {
             created: file
                 .metadata
                 .created()
-                .map(|s| s * 1000)
-                .unwrap_or_else(utils::now),
+                .map(time::from_secs)
+                .unwrap_or_else(time::now),
             perm: "ro".to_string(), // Default permission for embedded files
             content_type: file.metadata.mimetype().to_string(),
             last_modified: file
                 .metadata
                 .last_modified()
-                .map(|s| s * 1000)
-                .unwrap_or_else(utils::now),
+                .map(time::from_secs)
+                .unwrap_or_else(time::now),
             size: file.data.len() as u64,
+            etag: None,
+            version: None,
         }
     }
 }
@@ -1,10 +1,186 @@
+//! Read-only layers over writable storage
+//!
+//! Reads query the root and every layer concurrently and take the answer of the highest
+//! priority, the last layer added, that has the file. A layer that doesn't have it falls
+//! through to the next one, while any other error is returned rather than hidden, so an
+//! unreachable layer shows up instead of silently serving the files below it. The
+//! [`ErrorPolicy`] can skip failing layers instead, and [`Filesystem::status`] reports
+//! which ones are failing.
+//!
+//! Layers added with [`Builder::mount`] only cover the files under a folder, e.g. a plug
+//! library mounted at `Library/` serves `Library/Core/Plug.md` from its `Core/Plug.md`.
+//!
+//! Writes go to the root, or to the first [route](Builder::route) whose glob matches the
+//! file name, e.g. attachments to S3 and pages to SQLite. Routed files are read from their
+//! route before the root, which still serves the files written before the route existed.
+
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::Duration;
+
 use async_trait::async_trait;
+use futures::{StreamExt as _, future, stream::FuturesOrdered};
 
+use crate::fs::time::now;
 use crate::fs::*;
 
 pub struct Filesystem {
-    layers: Vec<Box<dyn ReadOnlyFilesystem + Send + Sync>>,
+    layers: Vec<Layer>,
+    routes: Vec<Route>,
     root: Box<dyn ReadWriteFilesystem + Send + Sync>,
+    on_error: ErrorPolicy,
+}
+
+/// What reads do when a layer fails with another error than `NotFound`
+///
+/// The root and the routes are the storage of the space, their errors are always returned.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    /// Return the error
+    #[default]
+    FailFast,
+    /// Read as if the layer didn't have the file
+    Skip,
+    /// Skip the layer, and leave it out of reads until `retry` passed since it last failed
+    Degrade { retry: Duration },
+}
+
+/// Health of a layer, from the last read that reached it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Health {
+    Healthy,
+    /// Failing since `since`, in milliseconds since the epoch
+    Failing {
+        error: String,
+        since: u64,
+    },
+}
+
+/// Health of a layer, see [`Filesystem::status`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LayerStatus {
+    /// Folder the layer is mounted at, `None` when it covers the whole space
+    pub prefix: Option<String>,
+    pub health: Health,
+}
+
+struct State {
+    health: Health,
+    /// Last failure, in milliseconds since the epoch
+    failed: u64,
+}
+
+struct Route {
+    pattern: String,
+    fs: Box<dyn ReadWriteFilesystem + Send + Sync>,
+}
+
+struct Layer {
+    /// Folder the layer is mounted at, with a trailing slash
+    prefix: Option<String>,
+    fs: Box<dyn ReadOnlyFilesystem + Send + Sync>,
+    state: Mutex<State>,
+}
+
+impl Layer {
+    fn new(prefix: Option<String>, fs: Box<dyn ReadOnlyFilesystem + Send + Sync>) -> Self {
+        Self {
+            prefix,
+            fs,
+            state: Mutex::new(State {
+                health: Health::Healthy,
+                failed: 0,
+            }),
+        }
+    }
+
+    /// Whether reads query the layer, degraded ones wait for their retry.
+    fn available(&self, policy: ErrorPolicy) -> bool {
+        let ErrorPolicy::Degrade { retry } = policy else {
+            return true;
+        };
+
+        let state = self.state.lock().unwrap();
+
+        state.health == Health::Healthy || now() >= state.failed + retry.as_millis() as u64
+    }
+
+    /// Record the health of the layer from a result, and apply the policy to its error.
+    fn check<T>(&self, policy: ErrorPolicy, result: Result<T>) -> Result<T> {
+        let mut state = self.state.lock().unwrap();
+
+        let err = match result {
+            Ok(_) | Err(Error::NotFound(_)) => {
+                state.health = Health::Healthy;
+                return result;
+            }
+            Err(err) => err,
+        };
+
+        let failed = now();
+        let since = match state.health {
+            Health::Failing { since, .. } => since,
+            Health::Healthy => {
+                #[cfg(feature = "tracing")]
+                tracing::warn!(error = %err, prefix = ?self.prefix, "Filesystem layer failing");
+
+                failed
+            }
+        };
+
+        state.health = Health::Failing {
+            error: err.to_string(),
+            since,
+        };
+        state.failed = failed;
+
+        match policy {
+            ErrorPolicy::FailFast => Err(err),
+            ErrorPolicy::Skip | ErrorPolicy::Degrade { .. } => {
+                Err(Error::NotFound(err.to_string().into()))
+            }
+        }
+    }
+
+    /// Name of a file within the layer, `None` when outside of its folder.
+    fn inner<'a>(&self, path: &'a str) -> Option<&'a str> {
+        match &self.prefix {
+            Some(prefix) => path.strip_prefix(prefix.as_str()),
+            None => Some(path),
+        }
+    }
+
+    fn outer(&self, mut meta: FileMeta) -> FileMeta {
+        if let Some(prefix) = &self.prefix {
+            meta.name.insert_str(0, prefix);
+        }
+
+        meta
+    }
+}
+
+/// Filesystem to read a file from, with the name of the file in it
+struct Source<'a> {
+    fs: &'a dyn ReadOnlyFilesystem,
+    /// `None` for the root and routes
+    layer: Option<&'a Layer>,
+    name: &'a str,
+}
+
+impl Source<'_> {
+    fn check<T>(&self, policy: ErrorPolicy, result: Result<T>) -> Result<T> {
+        match self.layer {
+            Some(layer) => layer.check(policy, result),
+            None => result,
+        }
+    }
+
+    fn outer(&self, meta: FileMeta) -> FileMeta {
+        match self.layer {
+            Some(layer) => layer.outer(meta),
+            None => meta,
+        }
+    }
 }
 
 impl Filesystem {
@@ -17,8 +193,10 @@ impl Filesystem {
 }
 
 pub struct Builder {
-    layers: Vec<Box<dyn ReadOnlyFilesystem + Send + Sync>>,
+    layers: Vec<Layer>,
+    routes: Vec<Route>,
     root: Box<dyn ReadWriteFilesystem + Send + Sync>,
+    on_error: ErrorPolicy,
 }
 
 impl Builder {
@@ -28,16 +206,57 @@ impl Builder {
     {
         Self {
             layers: Vec::new(),
+            routes: Vec::new(),
             root: Box::new(root),
+            on_error: ErrorPolicy::default(),
         }
     }
 
+    /// Add a layer over the whole space, taking priority over the root and the layers
+    /// added before.
     #[must_use]
     pub fn layer<L>(mut self, layer: L) -> Self
     where
         L: ReadOnlyFilesystem + Send + Sync + 'static,
     {
-        self.layers.push(Box::new(layer));
+        self.layers.push(Layer::new(None, Box::new(layer)));
+        self
+    }
+
+    /// Add a layer serving the files under `prefix`, e.g. `Library/`, with its names
+    /// relative to the folder.
+    #[must_use]
+    pub fn mount<L>(mut self, prefix: &str, layer: L) -> Self
+    where
+        L: ReadOnlyFilesystem + Send + Sync + 'static,
+    {
+        let prefix = prefix.trim_matches('/');
+
+        self.layers.push(Layer::new(
+            (!prefix.is_empty()).then(|| format!("{prefix}/")),
+            Box::new(layer),
+        ));
+        self
+    }
+
+    /// Write the files matching a glob, e.g. `**.png`, to `fs` rather than the root. The
+    /// first matching route, in the order added, takes the file.
+    #[must_use]
+    pub fn route<W>(mut self, pattern: impl Into<String>, fs: W) -> Self
+    where
+        W: ReadWriteFilesystem + Send + Sync + 'static,
+    {
+        self.routes.push(Route {
+            pattern: pattern.into(),
+            fs: Box::new(fs),
+        });
+        self
+    }
+
+    /// What reads do when a layer fails (fail fast by default).
+    #[must_use]
+    pub fn on_error(mut self, policy: ErrorPolicy) -> Self {
+        self.on_error = policy;
         self
     }
 
@@ -45,33 +264,134 @@ impl Builder {
     pub fn build(self) -> Filesystem {
         Filesystem {
             layers: self.layers,
+            routes: self.routes,
             root: self.root,
+            on_error: self.on_error,
         }
     }
 }
 
+/// First result in priority order that isn't `NotFound`.
+///
+/// The queries run concurrently, the ones of lower priority are dropped once a result is
+/// known.
+async fn first_found<T>(
+    queries: impl IntoIterator<Item = impl Future<Output = Result<T>>>,
+) -> Result<T> {
+    let mut results: FuturesOrdered<_> = queries.into_iter().collect();
+    let mut not_found = None;
+
+    while let Some(result) = results.next().await {
+        match result {
+            Err(Error::NotFound(err)) => {
+                not_found.get_or_insert(err);
+            }
+            result => return result,
+        }
+    }
+
+    Err(Error::NotFound(
+        not_found.unwrap_or_else(|| "no layers".into()),
+    ))
+}
+
+impl Filesystem {
+    /// Health of the layers, in the order they were added.
+    pub fn status(&self) -> Vec<LayerStatus> {
+        self.layers
+            .iter()
+            .map(|layer| LayerStatus {
+                prefix: layer.prefix.clone(),
+                health: layer.state.lock().unwrap().health.clone(),
+            })
+            .collect()
+    }
+
+    /// Index of the route writing `path`, `None` for the root.
+    fn route(&self, path: &str) -> Option<usize> {
+        self.routes
+            .iter()
+            .position(|route| crate::glob::matches(&route.pattern, path))
+    }
+
+    /// Layers covering `path` with the name of the file within them, in priority order.
+    fn sources<'a>(&'a self, path: &'a str) -> impl Iterator<Item = Source<'a>> {
+        // Last layer = highest priority, then the route, root last
+        let layers = self
+            .layers
+            .iter()
+            .rev()
+            .filter(|layer| layer.available(self.on_error))
+            .filter_map(|layer| {
+                Some(Source {
+                    fs: &*layer.fs,
+                    layer: Some(layer),
+                    name: layer.inner(path)?,
+                })
+            });
+
+        let route = self.route(path).map(|index| Source {
+            fs: &*self.routes[index].fs,
+            layer: None,
+            name: path,
+        });
+
+        layers.chain(route).chain(std::iter::once(Source {
+            fs: &*self.root,
+            layer: None,
+            name: path,
+        }))
+    }
+}
+
 #[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
 #[cfg_attr(not(target_arch = "wasm32"), async_trait)]
 impl ReadOnlyFilesystem for Filesystem {
     async fn list(&self) -> Result<Vec<FileMeta>> {
+        let (root, routes, layers) = future::join3(
+            self.root.list(),
+            future::join_all(
+                self.routes
+                    .iter()
+                    .enumerate()
+                    .map(|(index, route)| async move {
+                        let files = route.fs.list().await?;
+
+                        // Only the files written there
+                        Ok(files
+                            .into_iter()
+                            .filter(|file| self.route(&file.name) == Some(index))
+                            .collect())
+                    }),
+            ),
+            future::join_all(
+                self.layers
+                    .iter()
+                    .filter(|layer| layer.available(self.on_error))
+                    .map(|layer| async move {
+                        let files = layer.check(self.on_error, layer.fs.list().await)?;
+
+                        Ok(files.into_iter().map(|file| layer.outer(file)).collect())
+                    }),
+            ),
+        )
+        .await;
+
         let mut all_files = std::collections::HashMap::new();
 
-        // Start with root (lowest priority)
-        if let Ok(files) = self.root.list().await {
+        // Root first, it has the lowest priority
+        for list in std::iter::once(root).chain(routes).chain(layers) {
+            let files = match list {
+                Ok(files) => files,
+                Err(Error::NotFound(_)) => continue,
+                Err(err) => return Err(err),
+            };
+
             for file in files {
                 all_files.insert(file.name.clone(), file);
             }
         }
 
-        // Apply layers in reverse order (last layer = highest priority)
-        for layer in self.layers.iter().rev() {
-            if let Ok(files) = layer.list().await {
-                for file in files {
-                    all_files.insert(file.name.clone(), file);
-                }
-            }
-        }
-
         let mut files: Vec<_> = all_files.into_values().collect();
         files.sort_by(|a, b| a.name.cmp(&b.name));
 
@@ -79,27 +399,21 @@ impl ReadOnlyFilesystem for Filesystem {
     }
 
     async fn get(&self, path: &str) -> Result<(Stream, FileMeta)> {
-        // Try each layer first (last layer = highest priority)
-        for layer in self.layers.iter().rev() {
-            if let Ok(result) = layer.get(path).await {
-                return Ok(result);
-            }
-        }
+        first_found(self.sources(path).map(|source| async move {
+            let (stream, meta) = source.check(self.on_error, source.fs.get(source.name).await)?;
 
-        // Fall back to root
-        self.root.get(path).await
+            Ok((stream, source.outer(meta)))
+        }))
+        .await
     }
 
     async fn meta(&self, path: &str) -> Result<FileMeta> {
-        // Try each layer first (last layer = highest priority)
-        for layer in self.layers.iter().rev() {
-            if let Ok(meta) = layer.meta(path).await {
-                return Ok(meta);
-            }
-        }
+        first_found(self.sources(path).map(|source| async move {
+            let meta = source.check(self.on_error, source.fs.meta(source.name).await)?;
 
-        // Fall back to root
-        self.root.meta(path).await
+            Ok(source.outer(meta))
+        }))
+        .await
     }
 }
 
@@ -107,126 +421,35 @@ impl ReadOnlyFilesystem for Filesystem {
 #[cfg_attr(not(target_arch = "wasm32"), async_trait)]
 impl WritableFilesystem for Filesystem {
     async fn put(&self, path: &str, data: Stream, meta: IncomingFileMeta) -> Result<FileMeta> {
-        self.root.put(path, data, meta).await
+        match self.route(path) {
+            Some(index) => self.routes[index].fs.put(path, data, meta).await,
+            None => self.root.put(path, data, meta).await,
+        }
     }
 
     async fn delete(&self, path: &str) -> Result<()> {
-        self.root.delete(path).await
+        let Some(index) = self.route(path) else {
+            return self.root.delete(path).await;
+        };
+
+        // Also the copy from before the route, which would show up again
+        let (routed, root) =
+            future::join(self.routes[index].fs.delete(path), self.root.delete(path)).await;
+
+        match (routed, root) {
+            (Err(Error::NotFound(_)), root) => root,
+            (routed, Ok(()) | Err(Error::NotFound(_))) => routed,
+            (_, err) => err,
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::fs::testing::{MemoryFs, read_stream};
     use bytes::Bytes;
     use futures::stream;
-    use std::collections::HashMap;
-    use std::sync::RwLock;
-
-    /// A simple in-memory filesystem for testing
-    struct MemoryFs {
-        files: RwLock<HashMap<String, (Bytes, FileMeta)>>,
-    }
-
-    impl MemoryFs {
-        fn new() -> Self {
-            Self {
-                files: RwLock::new(HashMap::new()),
-            }
-        }
-
-        fn with_file(self, name: &str, content: &[u8]) -> Self {
-            self.files.write().unwrap().insert(
-                name.to_string(),
-                (
-                    Bytes::copy_from_slice(content),
-                    FileMeta {
-                        name: name.to_string(),
-                        created: 0,
-                        perm: "rw".to_string(),
-                        content_type: "text/plain".to_string(),
-                        last_modified: 0,
-                        size: content.len() as u64,
-                    },
-                ),
-            );
-            self
-        }
-    }
-
-    #[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
-    #[cfg_attr(not(target_arch = "wasm32"), async_trait)]
-    impl ReadOnlyFilesystem for MemoryFs {
-        async fn list(&self) -> Result<Vec<FileMeta>> {
-            Ok(self
-                .files
-                .read()
-                .unwrap()
-                .values()
-                .map(|(_, meta)| meta.clone())
-                .collect())
-        }
-
-        async fn get(&self, path: &str) -> Result<(Stream, FileMeta)> {
-            let files = self.files.read().unwrap();
-            let (data, meta) = files
-                .get(path)
-                .ok_or_else(|| Error::NotFound(path.into()))?;
-            let data = data.clone();
-            let meta = meta.clone();
-            Ok((stream::once(async move { Ok(data) }).boxed(), meta))
-        }
-
-        async fn meta(&self, path: &str) -> Result<FileMeta> {
-            self.files
-                .read()
-                .unwrap()
-                .get(path)
-                .map(|(_, meta)| meta.clone())
-                .ok_or_else(|| Error::NotFound(path.into()))
-        }
-    }
-
-    #[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
-    #[cfg_attr(not(target_arch = "wasm32"), async_trait)]
-    impl WritableFilesystem for MemoryFs {
-        async fn put(&self, path: &str, data: Stream, meta: IncomingFileMeta) -> Result<FileMeta> {
-            use futures::TryStreamExt;
-            let bytes: Vec<u8> = data
-                .try_fold(Vec::new(), |mut acc, chunk| async move {
-                    acc.extend_from_slice(&chunk);
-                    Ok(acc)
-                })
-                .await?;
-
-            let file_meta = FileMeta {
-                name: path.to_string(),
-                created: meta.created.unwrap_or(0),
-                perm: meta.perm.unwrap_or_else(|| "rw".to_string()),
-                content_type: meta
-                    .content_type
-                    .unwrap_or_else(|| "text/plain".to_string()),
-                last_modified: meta.last_modified.unwrap_or(0),
-                size: bytes.len() as u64,
-            };
-
-            self.files
-                .write()
-                .unwrap()
-                .insert(path.to_string(), (Bytes::from(bytes), file_meta.clone()));
-
-            Ok(file_meta)
-        }
-
-        async fn delete(&self, path: &str) -> Result<()> {
-            self.files
-                .write()
-                .unwrap()
-                .remove(path)
-                .ok_or_else(|| Error::NotFound(path.into()))?;
-            Ok(())
-        }
-    }
 
     #[tokio::test]
     async fn get_returns_from_root_when_no_layers() {
@@ -366,14 +589,278 @@ mod tests {
         assert_eq!(read_stream(stream).await, b"layer");
     }
 
-    async fn read_stream(stream: Stream) -> Vec<u8> {
-        use futures::TryStreamExt;
-        stream
-            .try_fold(Vec::new(), |mut acc, chunk| async move {
-                acc.extend_from_slice(&chunk);
-                Ok(acc)
-            })
+    #[tokio::test]
+    async fn mounted_layers_serve_their_folder() {
+        let root = MemoryFs::new()
+            .with_file("index.md", b"home")
+            .with_file("Library/Core/Plug.md", b"old");
+        let library = MemoryFs::new()
+            .with_file("Core/Plug.md", b"library")
+            .with_file("index.md", b"library index");
+
+        let fs = Filesystem::builder(root)
+            .mount("/Library/", library)
+            .build();
+
+        let names: Vec<_> = fs
+            .list()
             .await
             .unwrap()
+            .into_iter()
+            .map(|f| f.name)
+            .collect();
+        assert_eq!(
+            names,
+            ["Library/Core/Plug.md", "Library/index.md", "index.md"]
+        );
+
+        let (stream, meta) = fs.get("Library/Core/Plug.md").await.unwrap();
+        assert_eq!(read_stream(stream).await, b"library");
+        assert_eq!(meta.name, "Library/Core/Plug.md");
+
+        // Only the files under the folder come from the layer
+        let (stream, _) = fs.get("index.md").await.unwrap();
+        assert_eq!(read_stream(stream).await, b"home");
+        assert!(fs.meta("LibraryCore/Plug.md").await.is_err());
+        assert_eq!(
+            fs.meta("Library/index.md").await.unwrap().name,
+            "Library/index.md"
+        );
+    }
+
+    #[tokio::test]
+    async fn routes_writes_by_name() {
+        let root = MemoryFs::new().with_file("old.png", b"before routing");
+        let fs = Filesystem::builder(root)
+            .route("**.png", MemoryFs::new().with_file("stray.md", b""))
+            .route("**.p*", MemoryFs::new())
+            .build();
+
+        for name in ["index.md", "photos/cat.png", "doc.pdf"] {
+            let data = stream::once(async { Ok(Bytes::from("new")) }).boxed();
+            fs.put(name, data, IncomingFileMeta::default())
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(fs.root.list().await.unwrap().len(), 2);
+        assert_eq!(fs.routes[0].fs.list().await.unwrap().len(), 2);
+        assert_eq!(fs.routes[1].fs.list().await.unwrap()[0].name, "doc.pdf");
+
+        // Files the routes don't take stay out of the listing
+        let names: Vec<_> = fs
+            .list()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|f| f.name)
+            .collect();
+        assert_eq!(names, ["doc.pdf", "index.md", "old.png", "photos/cat.png"]);
+
+        let (stream, _) = fs.get("old.png").await.unwrap();
+        assert_eq!(read_stream(stream).await, b"before routing");
+
+        fs.delete("old.png").await.unwrap();
+        fs.delete("photos/cat.png").await.unwrap();
+        assert!(fs.meta("old.png").await.is_err());
+        assert!(matches!(
+            fs.delete("photos/cat.png").await,
+            Err(Error::NotFound(_))
+        ));
+    }
+
+    /// Layer failing every read, like an unreachable remote
+    struct Unreachable;
+
+    #[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+    #[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+    impl ReadOnlyFilesystem for Unreachable {
+        async fn list(&self) -> Result<Vec<FileMeta>> {
+            Err(std::io::Error::other("unreachable").into())
+        }
+
+        async fn get(&self, _path: &str) -> Result<(Stream, FileMeta)> {
+            Err(std::io::Error::other("unreachable").into())
+        }
+
+        async fn meta(&self, _path: &str) -> Result<FileMeta> {
+            Err(std::io::Error::other("unreachable").into())
+        }
+    }
+
+    #[tokio::test]
+    async fn errors_are_not_hidden() {
+        let root = MemoryFs::new().with_file("test.txt", b"root");
+        let fs = Filesystem::builder(root).layer(Unreachable).build();
+
+        assert!(matches!(fs.list().await, Err(Error::Io(_))));
+        assert!(matches!(fs.meta("test.txt").await, Err(Error::Io(_))));
+
+        // Layers below the failing one still answer when they have priority
+        let fs = Filesystem::builder(MemoryFs::new())
+            .layer(Unreachable)
+            .layer(MemoryFs::new().with_file("test.txt", b"top"))
+            .build();
+
+        let (stream, _) = fs.get("test.txt").await.unwrap();
+        assert_eq!(read_stream(stream).await, b"top");
+        assert!(matches!(fs.get("missing.txt").await, Err(Error::Io(_))));
+    }
+
+    #[tokio::test]
+    async fn skips_failing_layers_by_policy() {
+        let root = || MemoryFs::new().with_file("test.txt", b"root");
+
+        for policy in [
+            ErrorPolicy::Skip,
+            ErrorPolicy::Degrade {
+                retry: Duration::from_secs(60),
+            },
+        ] {
+            let fs = Filesystem::builder(root())
+                .mount("Library", Unreachable)
+                .on_error(policy)
+                .build();
+
+            assert_eq!(fs.status()[0].health, Health::Healthy);
+
+            let (stream, _) = fs.get("test.txt").await.unwrap();
+            assert_eq!(read_stream(stream).await, b"root");
+            assert_eq!(fs.list().await.unwrap().len(), 1);
+
+            let status = fs.status();
+            assert_eq!(status[0].prefix.as_deref(), Some("Library/"));
+            assert!(matches!(
+                &status[0].health,
+                Health::Failing { error, .. } if error.contains("unreachable")
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn degraded_layers_wait_for_their_retry() {
+        let fs = Filesystem::builder(MemoryFs::new())
+            .layer(Unreachable)
+            .on_error(ErrorPolicy::Degrade {
+                retry: Duration::from_secs(60),
+            })
+            .build();
+
+        assert!(fs.layers[0].available(fs.on_error));
+        assert!(matches!(fs.meta("a.txt").await, Err(Error::NotFound(_))));
+        assert!(!fs.layers[0].available(fs.on_error));
+
+        let Health::Failing { since, .. } = fs.status()[0].health else {
+            panic!("layer is healthy");
+        };
+
+        // Retried once the time passed, still failing since the first error
+        fs.layers[0].state.lock().unwrap().failed -= 60_000;
+        assert!(fs.layers[0].available(fs.on_error));
+        fs.list().await.unwrap();
+        assert!(
+            matches!(fs.status()[0].health, Health::Failing { since: again, .. } if again == since)
+        );
+    }
+
+    #[tokio::test]
+    async fn not_found_falls_through_every_layer() {
+        let fs = Filesystem::builder(MemoryFs::new())
+            .layer(MemoryFs::new())
+            .layer(MemoryFs::new())
+            .build();
+
+        assert!(matches!(
+            fs.meta("missing.txt").await,
+            Err(Error::NotFound(_))
+        ));
+    }
+
+    /// Layer whose reads wait until the other layers were queried
+    struct Waiting(future::Shared<futures::channel::oneshot::Receiver<()>>);
+
+    #[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+    #[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+    impl ReadOnlyFilesystem for Waiting {
+        async fn list(&self) -> Result<Vec<FileMeta>> {
+            let _ = self.0.clone().await;
+            Ok(Vec::new())
+        }
+
+        async fn get(&self, path: &str) -> Result<(Stream, FileMeta)> {
+            let _ = self.0.clone().await;
+            Err(Error::NotFound(path.into()))
+        }
+
+        async fn meta(&self, path: &str) -> Result<FileMeta> {
+            let _ = self.0.clone().await;
+            Err(Error::NotFound(path.into()))
+        }
+    }
+
+    /// Root signalling the waiting layer when queried
+    struct Signalling(
+        MemoryFs,
+        std::sync::Mutex<Option<futures::channel::oneshot::Sender<()>>>,
+    );
+
+    #[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+    #[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+    impl ReadOnlyFilesystem for Signalling {
+        async fn list(&self) -> Result<Vec<FileMeta>> {
+            self.signal();
+            self.0.list().await
+        }
+
+        async fn get(&self, path: &str) -> Result<(Stream, FileMeta)> {
+            self.signal();
+            self.0.get(path).await
+        }
+
+        async fn meta(&self, path: &str) -> Result<FileMeta> {
+            self.signal();
+            self.0.meta(path).await
+        }
+    }
+
+    #[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+    #[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+    impl WritableFilesystem for Signalling {
+        async fn put(&self, path: &str, data: Stream, meta: IncomingFileMeta) -> Result<FileMeta> {
+            self.0.put(path, data, meta).await
+        }
+
+        async fn delete(&self, path: &str) -> Result<()> {
+            self.0.delete(path).await
+        }
+    }
+
+    impl Signalling {
+        fn signal(&self) {
+            if let Some(sender) = self.1.lock().unwrap().take() {
+                let _ = sender.send(());
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn queries_layers_concurrently() {
+        let (sender, receiver) = futures::channel::oneshot::channel();
+        let root = Signalling(
+            MemoryFs::new().with_file("test.txt", b"root"),
+            std::sync::Mutex::new(Some(sender)),
+        );
+        let fs = Filesystem::builder(root)
+            .layer(Waiting(future::FutureExt::shared(receiver)))
+            .build();
+
+        // Queried one after the other, the layer would wait for the root forever
+        let timeout = futures_timer::Delay::new(std::time::Duration::from_secs(5));
+        let meta = match future::select(fs.meta("test.txt"), timeout).await {
+            future::Either::Left((meta, _)) => meta.unwrap(),
+            future::Either::Right(_) => panic!("layers were queried sequentially"),
+        };
+
+        assert_eq!(meta.name, "test.txt");
     }
 }
@@ -0,0 +1,189 @@
+//! Memoization of metadata within a request
+//!
+//! A request can read the metadata of a file more than once, e.g. to check a condition
+//! and then to answer, and each read is a stat on remote stores. [`Filesystem`] remembers
+//! the metadata of the files a request read or wrote, so the next `meta` of a path is
+//! answered without a backend call. Files are still read from the backend, and writes
+//! replace what's remembered of their path.
+//!
+//! It never forgets, so it wraps the space of a single request, in
+//! [`Provider::provide`](crate::server::routes::fs::Provider::provide), and not the
+//! space of the server.
+//!
+//! ```ignore
+//! fn provide(&self, _parts: &mut Parts) -> Result<Self::Output, server::Error> {
+//!     Ok(Arc::new(memo::Filesystem::new(self.fs.clone())))
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+
+use crate::fs::*;
+
+pub struct Filesystem<F> {
+    inner: F,
+    metas: Mutex<HashMap<String, FileMeta>>,
+}
+
+impl<F> Filesystem<F> {
+    pub fn new(inner: F) -> Self {
+        Self {
+            inner,
+            metas: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn remember(&self, meta: &FileMeta) {
+        self.metas
+            .lock()
+            .unwrap()
+            .insert(meta.name.clone(), meta.clone());
+    }
+
+    fn forget(&self, path: &str) {
+        self.metas.lock().unwrap().remove(path);
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl<F> ReadOnlyFilesystem for Filesystem<F>
+where
+    F: ReadOnlyFilesystem,
+{
+    async fn list(&self) -> Result<Vec<FileMeta>> {
+        self.inner.list().await
+    }
+
+    async fn get(&self, path: &str) -> Result<(Stream, FileMeta)> {
+        let (stream, meta) = self.inner.get(path).await?;
+        self.remember(&meta);
+
+        Ok((stream, meta))
+    }
+
+    async fn meta(&self, path: &str) -> Result<FileMeta> {
+        if let Some(meta) = self.metas.lock().unwrap().get(path) {
+            return Ok(meta.clone());
+        }
+
+        let meta = self.inner.meta(path).await?;
+        self.remember(&meta);
+
+        Ok(meta)
+    }
+
+    async fn list_stream(&self) -> Result<FileStream> {
+        self.inner.list_stream().await
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl<F> WritableFilesystem for Filesystem<F>
+where
+    F: WritableFilesystem,
+{
+    async fn put(&self, path: &str, data: Stream, meta: IncomingFileMeta) -> Result<FileMeta> {
+        self.forget(path);
+
+        let meta = self.inner.put(path, data, meta).await?;
+        self.remember(&meta);
+
+        Ok(meta)
+    }
+
+    async fn delete(&self, path: &str) -> Result<()> {
+        self.forget(path);
+
+        self.inner.delete(path).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::testing::MemoryFs;
+    use bytes::Bytes;
+    use futures::stream;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Counts the stats of the backend
+    struct Counted {
+        inner: MemoryFs,
+        metas: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl ReadOnlyFilesystem for Counted {
+        async fn list(&self) -> Result<Vec<FileMeta>> {
+            self.inner.list().await
+        }
+
+        async fn get(&self, path: &str) -> Result<(Stream, FileMeta)> {
+            self.inner.get(path).await
+        }
+
+        async fn meta(&self, path: &str) -> Result<FileMeta> {
+            self.metas.fetch_add(1, Ordering::Relaxed);
+            self.inner.meta(path).await
+        }
+    }
+
+    #[async_trait]
+    impl WritableFilesystem for Counted {
+        async fn put(&self, path: &str, data: Stream, meta: IncomingFileMeta) -> Result<FileMeta> {
+            self.inner.put(path, data, meta).await
+        }
+
+        async fn delete(&self, path: &str) -> Result<()> {
+            self.inner.delete(path).await
+        }
+    }
+
+    fn memoized() -> Filesystem<Counted> {
+        Filesystem::new(Counted {
+            inner: MemoryFs::new().with_file("index.md", b"home"),
+            metas: AtomicUsize::new(0),
+        })
+    }
+
+    fn stats(fs: &Filesystem<Counted>) -> usize {
+        fs.inner.metas.load(Ordering::Relaxed)
+    }
+
+    #[tokio::test]
+    async fn remembers_metadata() {
+        let fs = memoized();
+
+        assert_eq!(fs.meta("index.md").await.unwrap().size, 4);
+        assert_eq!(fs.meta("index.md").await.unwrap().size, 4);
+        assert_eq!(stats(&fs), 1);
+
+        // Nor after a read, but missing files are checked again
+        let _ = fs.get("index.md").await.unwrap();
+        fs.meta("index.md").await.unwrap();
+        assert!(matches!(fs.meta("a.md").await, Err(Error::NotFound(_))));
+        assert!(matches!(fs.meta("a.md").await, Err(Error::NotFound(_))));
+        assert_eq!(stats(&fs), 3);
+    }
+
+    #[tokio::test]
+    async fn follows_writes() {
+        let fs = memoized();
+        fs.meta("index.md").await.unwrap();
+
+        let body = stream::once(async { Ok(Bytes::from("new home")) }).into_boxed();
+        fs.put("index.md", body, IncomingFileMeta::default())
+            .await
+            .unwrap();
+        assert_eq!(fs.meta("index.md").await.unwrap().size, 8);
+
+        fs.delete("index.md").await.unwrap();
+        assert!(matches!(fs.meta("index.md").await, Err(Error::NotFound(_))));
+        assert_eq!(stats(&fs), 2);
+    }
+}
@@ -0,0 +1,411 @@
+//! Model checking of filesystems
+//!
+//! [`Checker`] runs random sequences of puts, deletes, reads and listings against a
+//! filesystem and against a map of the files it should hold, to catch the ordering and
+//! idempotency bugs of wrappers, e.g. a cache serving a file after its delete. Runs are
+//! seeded so a failure is reproducible, and the sequence of a failure is shrunk to the
+//! calls needed to reproduce it before panicking with it. Enabled with the `test-util`
+//! feature.
+//!
+//! ```ignore
+//! #[tokio::test]
+//! async fn cache_matches_the_model() {
+//!     Checker::new().check(|| Cached::new(MemoryFs::new())).await;
+//! }
+//! ```
+//!
+//! The calls use names under `model/`, and each run uses a new filesystem.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use bytes::Bytes;
+use futures::{TryStreamExt as _, stream};
+
+use crate::fs::*;
+
+/// Names the calls pick from, with folders and non-ASCII characters
+const PATHS: &[&str] = &[
+    "model/index.md",
+    "model/Journal/Today.md",
+    "model/Journal/Tomorrow.md",
+    "model/Café ☕.md",
+];
+
+/// Longest body of a put
+const MAX_BODY: usize = 96;
+
+/// Call of a run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Op {
+    Put { path: String, data: Vec<u8> },
+    Delete(String),
+    Get(String),
+    Meta(String),
+    List,
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Op::Put { path, data } => write!(f, "put {path} ({} bytes)", data.len()),
+            Op::Delete(path) => write!(f, "delete {path}"),
+            Op::Get(path) => write!(f, "get {path}"),
+            Op::Meta(path) => write!(f, "meta {path}"),
+            Op::List => write!(f, "list"),
+        }
+    }
+}
+
+/// SplitMix64, enough to pick calls without a dependency
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Number in `0..n`.
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+/// Runs random calls against filesystems and a model of them
+#[derive(Debug, Clone, Copy)]
+pub struct Checker {
+    seed: u64,
+    runs: u64,
+    steps: usize,
+}
+
+impl Default for Checker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Checker {
+    /// 64 runs of 64 calls, from a fixed seed.
+    pub fn new() -> Self {
+        Self {
+            seed: 0x5eed,
+            runs: 64,
+            steps: 64,
+        }
+    }
+
+    /// Seed of the first run, the next ones counting up from it.
+    #[must_use]
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    #[must_use]
+    pub fn runs(mut self, runs: u64) -> Self {
+        self.runs = runs;
+        self
+    }
+
+    /// Calls of a run.
+    #[must_use]
+    pub fn steps(mut self, steps: usize) -> Self {
+        self.steps = steps;
+        self
+    }
+
+    /// Calls of the run from `seed`.
+    pub fn ops(&self, seed: u64) -> Vec<Op> {
+        let mut rng = Rng(seed);
+        let path = |rng: &mut Rng| PATHS[rng.below(PATHS.len())].to_string();
+
+        (0..self.steps)
+            .map(|_| match rng.below(10) {
+                0..=3 => {
+                    let path = path(&mut rng);
+                    let len = rng.below(MAX_BODY + 1);
+                    let data = (0..len).map(|_| rng.next() as u8).collect();
+
+                    Op::Put { path, data }
+                }
+                4 | 5 => Op::Delete(path(&mut rng)),
+                6 | 7 => Op::Get(path(&mut rng)),
+                8 => Op::Meta(path(&mut rng)),
+                _ => Op::List,
+            })
+            .collect()
+    }
+
+    /// Run every run, each against a new filesystem, panicking with the shrunk calls of
+    /// the first one differing from the model.
+    pub async fn check<F, M>(&self, make: M)
+    where
+        F: ReadWriteFilesystem,
+        M: Fn() -> F,
+    {
+        for seed in self.seed..self.seed.saturating_add(self.runs) {
+            let ops = self.ops(seed);
+
+            if let Err((at, message)) = replay(&make(), &ops).await {
+                let (ops, message) = shrink(&make, ops[..=at].to_vec(), message).await;
+                let calls: Vec<_> = ops.iter().map(|op| format!("  {op}")).collect();
+
+                panic!(
+                    "filesystem differs from the model with seed {seed}, after:\n{}\n{message}",
+                    calls.join("\n")
+                );
+            }
+        }
+    }
+}
+
+/// Drop the calls a failure doesn't need, one at a time.
+async fn shrink<F, M>(make: &M, mut ops: Vec<Op>, mut message: String) -> (Vec<Op>, String)
+where
+    F: ReadWriteFilesystem,
+    M: Fn() -> F,
+{
+    let mut index = 0;
+
+    while index < ops.len() {
+        let mut candidate = ops.clone();
+        candidate.remove(index);
+
+        match replay(&make(), &candidate).await {
+            Err((at, failure)) => {
+                candidate.truncate(at + 1);
+                ops = candidate;
+                message = failure;
+            }
+            Ok(()) => index += 1,
+        }
+    }
+
+    (ops, message)
+}
+
+/// Run the calls, `Err` with the index and difference of the first one differing.
+async fn replay<F: ReadWriteFilesystem>(
+    fs: &F,
+    ops: &[Op],
+) -> std::result::Result<(), (usize, String)> {
+    let mut model = BTreeMap::new();
+
+    for (index, op) in ops.iter().enumerate() {
+        step(fs, &mut model, op)
+            .await
+            .map_err(|message| (index, format!("{op}: {message}")))?;
+    }
+
+    Ok(())
+}
+
+fn not_found<T: fmt::Debug>(result: Result<T>) -> std::result::Result<(), String> {
+    match result {
+        Err(Error::NotFound(_)) => Ok(()),
+        other => Err(format!("expected NotFound, got {other:?}")),
+    }
+}
+
+fn size(meta: &FileMeta, data: &[u8]) -> std::result::Result<(), String> {
+    match meta.size == data.len() as u64 {
+        true => Ok(()),
+        false => Err(format!("expected size {}, got {}", data.len(), meta.size)),
+    }
+}
+
+async fn step<F: ReadWriteFilesystem>(
+    fs: &F,
+    model: &mut BTreeMap<String, Vec<u8>>,
+    op: &Op,
+) -> std::result::Result<(), String> {
+    match op {
+        Op::Put { path, data } => {
+            let body = Bytes::from(data.clone());
+            let body = stream::once(async move { Ok(body) }).into_boxed();
+            let meta = fs
+                .put(path, body, IncomingFileMeta::default())
+                .await
+                .map_err(|err| format!("failed with {err}"))?;
+
+            if meta.name != *path {
+                return Err(format!("expected name {path}, got {}", meta.name));
+            }
+
+            size(&meta, data)?;
+            model.insert(path.clone(), data.clone());
+        }
+        Op::Delete(path) => match model.remove(path) {
+            Some(_) => fs
+                .delete(path)
+                .await
+                .map_err(|err| format!("failed with {err}"))?,
+            None => not_found(fs.delete(path).await)?,
+        },
+        Op::Get(path) => match model.get(path) {
+            Some(expected) => {
+                let (stream, meta) = fs
+                    .get(path)
+                    .await
+                    .map_err(|err| format!("failed with {err}"))?;
+                let data = stream
+                    .map_ok(|chunk| chunk.to_vec())
+                    .try_concat()
+                    .await
+                    .map_err(|err| format!("body failed with {err}"))?;
+
+                if data != *expected {
+                    return Err(format!("expected {expected:?}, got {data:?}"));
+                }
+
+                size(&meta, expected)?;
+            }
+            None => not_found(fs.get(path).await.map(|(_, meta)| meta))?,
+        },
+        Op::Meta(path) => match model.get(path) {
+            Some(expected) => {
+                let meta = fs
+                    .meta(path)
+                    .await
+                    .map_err(|err| format!("failed with {err}"))?;
+
+                size(&meta, expected)?;
+            }
+            None => not_found(fs.meta(path).await)?,
+        },
+        Op::List => {
+            let files = fs
+                .list()
+                .await
+                .map_err(|err| format!("failed with {err}"))?;
+            let mut listed: Vec<_> = files
+                .iter()
+                .filter(|file| file.name.starts_with("model/"))
+                .map(|file| (file.name.as_str(), file.size))
+                .collect();
+            listed.sort();
+
+            let expected: Vec<_> = model
+                .iter()
+                .map(|(name, data)| (name.as_str(), data.len() as u64))
+                .collect();
+
+            if listed != expected {
+                return Err(format!("expected {expected:?}, got {listed:?}"));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::testing::MemoryFs;
+    use async_trait::async_trait;
+    use futures::FutureExt as _;
+
+    #[test]
+    fn runs_are_reproducible() {
+        let checker = Checker::new();
+
+        assert_eq!(checker.ops(7), checker.ops(7));
+        assert_ne!(checker.ops(7), checker.ops(8));
+        assert_eq!(checker.steps(5).ops(7).len(), 5);
+    }
+
+    #[tokio::test]
+    async fn wrappers_match_the_model() {
+        let checker = Checker::new();
+
+        checker.check(MemoryFs::new).await;
+        checker
+            .check(|| crate::fs::singleflight::Filesystem::new(MemoryFs::new()))
+            .await;
+        checker
+            .check(|| crate::fs::retry::Filesystem::new(MemoryFs::new()))
+            .await;
+        checker
+            .check(|| {
+                crate::fs::perm::Filesystem::new(MemoryFs::new(), crate::fs::perm::Rules::new())
+            })
+            .await;
+    }
+
+    #[cfg(feature = "opendal")]
+    #[tokio::test]
+    async fn opendal_matches_the_model() {
+        Checker::new()
+            .runs(16)
+            .check(|| {
+                let operator = ::opendal::Operator::new(::opendal::services::Memory::default())
+                    .unwrap()
+                    .finish();
+
+                crate::fs::opendal::Filesystem::new(operator)
+            })
+            .await;
+    }
+
+    /// Deletes missing files without a NotFound
+    struct IdempotentDelete(MemoryFs);
+
+    #[async_trait]
+    impl ReadOnlyFilesystem for IdempotentDelete {
+        async fn list(&self) -> Result<Vec<FileMeta>> {
+            self.0.list().await
+        }
+
+        async fn get(&self, path: &str) -> Result<(Stream, FileMeta)> {
+            self.0.get(path).await
+        }
+
+        async fn meta(&self, path: &str) -> Result<FileMeta> {
+            self.0.meta(path).await
+        }
+    }
+
+    #[async_trait]
+    impl WritableFilesystem for IdempotentDelete {
+        async fn put(&self, path: &str, data: Stream, meta: IncomingFileMeta) -> Result<FileMeta> {
+            self.0.put(path, data, meta).await
+        }
+
+        async fn delete(&self, path: &str) -> Result<()> {
+            match self.0.delete(path).await {
+                Err(Error::NotFound(_)) => Ok(()),
+                result => result,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn shrinks_failures() {
+        let checker = Checker::new();
+        let run = checker.check(|| IdempotentDelete(MemoryFs::new()));
+        let panic = std::panic::AssertUnwindSafe(run)
+            .catch_unwind()
+            .await
+            .unwrap_err();
+        let message = panic.downcast_ref::<String>().unwrap();
+
+        // A single delete of a missing file is enough
+        let calls: Vec<_> = message
+            .lines()
+            .filter(|line| line.starts_with("  "))
+            .collect();
+        assert_eq!(calls.len(), 1, "{message}");
+        assert!(calls[0].starts_with("  delete model/"), "{message}");
+        assert!(
+            message.contains("expected NotFound, got Ok(())"),
+            "{message}"
+        );
+    }
+}
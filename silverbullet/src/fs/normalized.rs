@@ -0,0 +1,190 @@
+//! Unicode normalization of file names
+//!
+//! macOS writes names decomposed (NFD, `e` followed by a combining accent), Linux and
+//! Windows keep them as typed, usually composed (NFC). The same page can then exist
+//! twice, and a link from one system misses the file written by the other.
+//! [`Filesystem`] wraps a filesystem so names are written and reported in NFC: reads
+//! fall back to the decomposed and the given name when the composed one is missing,
+//! listings merge the entries that only differ in normalization, keeping the most
+//! recently modified, and deletes remove every variant.
+//!
+//! ```ignore
+//! let fs = normalized::Filesystem::new(opendal::Filesystem::new(operator));
+//! ```
+
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use unicode_normalization::{UnicodeNormalization as _, is_nfc};
+
+use crate::fs::*;
+
+pub struct Filesystem<F> {
+    inner: F,
+}
+
+impl<F> Filesystem<F> {
+    pub fn new(inner: F) -> Self {
+        Self { inner }
+    }
+}
+
+/// [`FsLayer`](crate::fs::stack::FsLayer) wrapping filesystems in [`Filesystem`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NormalizeLayer;
+
+impl<F> crate::fs::stack::FsLayer<F> for NormalizeLayer {
+    type Output = Filesystem<F>;
+
+    fn layer(&self, inner: F) -> Self::Output {
+        Filesystem::new(inner)
+    }
+}
+
+/// NFC name of a file followed by the other names it may be stored under.
+fn variants(path: &str) -> Vec<String> {
+    let mut names = vec![path.nfc().collect::<String>(), path.nfd().collect()];
+
+    if !names.iter().any(|name| name == path) {
+        names.push(path.to_string());
+    }
+
+    names.dedup();
+    names
+}
+
+fn normalize(mut meta: FileMeta) -> FileMeta {
+    if !is_nfc(&meta.name) {
+        meta.name = meta.name.nfc().collect();
+    }
+
+    meta
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl<F> ReadOnlyFilesystem for Filesystem<F>
+where
+    F: ReadOnlyFilesystem,
+{
+    async fn list(&self) -> Result<Vec<FileMeta>> {
+        let mut files = HashMap::<String, FileMeta>::new();
+
+        for file in self.inner.list().await?.into_iter().map(normalize) {
+            match files.get(&file.name) {
+                Some(other) if other.last_modified >= file.last_modified => {}
+                _ => {
+                    files.insert(file.name.clone(), file);
+                }
+            }
+        }
+
+        Ok(files.into_values().collect())
+    }
+
+    async fn get(&self, path: &str) -> Result<(Stream, FileMeta)> {
+        let mut names = variants(path).into_iter();
+        let mut result = self.inner.get(&names.next().unwrap_or_default()).await;
+
+        for name in names {
+            match result {
+                Err(Error::NotFound(_)) => result = self.inner.get(&name).await,
+                _ => break,
+            }
+        }
+
+        result.map(|(stream, meta)| (stream, normalize(meta)))
+    }
+
+    async fn meta(&self, path: &str) -> Result<FileMeta> {
+        let mut names = variants(path).into_iter();
+        let mut result = self.inner.meta(&names.next().unwrap_or_default()).await;
+
+        for name in names {
+            match result {
+                Err(Error::NotFound(_)) => result = self.inner.meta(&name).await,
+                _ => break,
+            }
+        }
+
+        result.map(normalize)
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl<F> WritableFilesystem for Filesystem<F>
+where
+    F: WritableFilesystem,
+{
+    async fn put(&self, path: &str, data: Stream, meta: IncomingFileMeta) -> Result<FileMeta> {
+        let path: String = path.nfc().collect();
+
+        self.inner.put(&path, data, meta).await.map(normalize)
+    }
+
+    async fn delete(&self, path: &str) -> Result<()> {
+        let mut deleted = false;
+
+        for name in variants(path) {
+            match self.inner.delete(&name).await {
+                Ok(()) => deleted = true,
+                Err(Error::NotFound(_)) => {}
+                Err(err) => return Err(err),
+            }
+        }
+
+        if deleted {
+            Ok(())
+        } else {
+            Err(Error::NotFound(path.to_string().into()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::testing::{MemoryFs, read_stream};
+    use bytes::Bytes;
+    use futures::stream;
+
+    const COMPOSED: &str = "Caf\u{e9}.md";
+    const DECOMPOSED: &str = "Cafe\u{301}.md";
+
+    #[tokio::test]
+    async fn reads_either_form() {
+        let fs = Filesystem::new(MemoryFs::new().with_file(DECOMPOSED, b"written on macOS"));
+
+        let (stream, meta) = fs.get(COMPOSED).await.unwrap();
+        assert_eq!(read_stream(stream).await, b"written on macOS");
+        assert_eq!(meta.name, COMPOSED);
+
+        assert_eq!(fs.meta(DECOMPOSED).await.unwrap().name, COMPOSED);
+        assert_eq!(fs.list().await.unwrap()[0].name, COMPOSED);
+    }
+
+    #[tokio::test]
+    async fn writes_and_deletes_composed_names() {
+        let fs = Filesystem::new(MemoryFs::new().with_file(DECOMPOSED, b"old"));
+
+        let data = stream::once(async { Ok(Bytes::from("new")) }).boxed();
+        let meta = IncomingFileMeta {
+            last_modified: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(fs.put(DECOMPOSED, data, meta).await.unwrap().name, COMPOSED);
+
+        // The composed copy wins, and the old one doesn't show up twice
+        let files = fs.list().await.unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].size, 3);
+
+        let (stream, _) = fs.get(DECOMPOSED).await.unwrap();
+        assert_eq!(read_stream(stream).await, b"new");
+
+        fs.delete(COMPOSED).await.unwrap();
+        assert!(fs.list().await.unwrap().is_empty());
+        assert!(matches!(fs.delete(COMPOSED).await, Err(Error::NotFound(_))));
+    }
+}
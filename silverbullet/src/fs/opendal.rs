@@ -1,12 +1,20 @@
 use std::collections::HashMap;
+use std::time::Duration;
 
 use ::opendal::Operator;
+use ::opendal::options::WriteOptions;
+use ::opendal::raw::PresignedRequest;
 use async_trait::async_trait;
-use futures::StreamExt;
+use futures::{StreamExt as _, TryStreamExt as _, future};
 
-use super::utils::now;
+use super::time::now;
 use crate::fs::*;
 
+/// Filesystem on an opendal operator
+///
+/// The creation time and the modification time given to writes are kept in the user
+/// metadata on services supporting it, and preferred over the time of the write. Listings
+/// only report them on services listing user metadata, others report the write time.
 pub struct Filesystem {
     operator: Operator,
 }
@@ -15,20 +23,125 @@ impl Filesystem {
     pub fn new(operator: Operator) -> Self {
         Self { operator }
     }
+
+    /// Request for clients to write `path` straight to the storage until `expire`, `None`
+    /// on services that can't presign writes, e.g. fs.
+    ///
+    /// Only the write is signed: the file keeps the times of the storage, not those of a
+    /// [`put`](WritableFilesystem::put).
+    pub async fn presign_put(
+        &self,
+        path: &str,
+        expire: Duration,
+    ) -> Result<Option<PresignedRequest>> {
+        if !self.operator.info().full_capability().presign_write {
+            return Ok(None);
+        }
+
+        Ok(Some(self.operator.presign_write(path, expire).await?))
+    }
+
+    /// Creation time kept in the user metadata of a stored file, to carry over to the
+    /// file overwriting it.
+    async fn created(&self, path: &str) -> Result<Option<u64>> {
+        // Not worth a stat on services dropping it
+        if !self
+            .operator
+            .info()
+            .full_capability()
+            .write_with_user_metadata
+        {
+            return Ok(None);
+        }
+
+        match self.operator.stat(path).await {
+            Ok(stat) => Ok(stat
+                .user_metadata()
+                .and_then(|metadata| metadata.get("created"))
+                .and_then(|created| created.parse().ok())),
+            Err(err) if err.kind() == ::opendal::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Pass the conditions of a write to the backend, or check them against the stored
+    /// file when it can't. The check races with other writers, only native conditions,
+    /// e.g. on S3, are atomic.
+    async fn condition(
+        &self,
+        path: &str,
+        meta: &IncomingFileMeta,
+        options: &mut WriteOptions,
+    ) -> Result<()> {
+        let capability = self.operator.info().full_capability();
+
+        let if_match = match meta.if_match.as_deref() {
+            Some(etag) if etag != "*" && capability.write_with_if_match => {
+                options.if_match = Some(etag.to_string());
+                None
+            }
+            condition => condition,
+        };
+
+        let if_none_match = match meta.if_none_match.as_deref() {
+            Some("*") if capability.write_with_if_not_exists => {
+                options.if_not_exists = true;
+                None
+            }
+            Some(etag) if etag != "*" && capability.write_with_if_none_match => {
+                options.if_none_match = Some(etag.to_string());
+                None
+            }
+            condition => condition,
+        };
+
+        if if_match.is_none() && if_none_match.is_none() {
+            return Ok(());
+        }
+
+        let stored = match self.operator.stat(path).await {
+            Ok(stat) => Some(stat),
+            Err(err) if err.kind() == ::opendal::ErrorKind::NotFound => None,
+            Err(err) => return Err(err.into()),
+        };
+        let matches = |condition| {
+            stored
+                .as_ref()
+                .is_some_and(|stat| etag_matches(condition, stat.etag()))
+        };
+
+        match (if_match, if_none_match) {
+            (Some(condition), _) if !matches(condition) => Err(Error::PreconditionFailed(
+                format!("{path} doesn't match {condition}").into(),
+            )),
+            (_, Some(condition)) if matches(condition) => Err(Error::PreconditionFailed(
+                format!("{path} matches {condition}").into(),
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Whether an `If-Match` style list of etags, or `*`, contains an etag. Weak and strong
+/// etags compare equal, as the content is compared for writes.
+fn etag_matches(condition: &str, etag: Option<&str>) -> bool {
+    let normalize = |etag: &str| {
+        etag.trim()
+            .trim_start_matches("W/")
+            .trim_matches('"')
+            .to_string()
+    };
+
+    condition.split(',').any(|candidate| {
+        candidate.trim() == "*" || etag.is_some_and(|etag| normalize(candidate) == normalize(etag))
+    })
 }
 
 #[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
 #[cfg_attr(not(target_arch = "wasm32"), async_trait)]
 impl ReadOnlyFilesystem for Filesystem {
     async fn list(&self) -> Result<Vec<FileMeta>> {
-        Ok(self
-            .operator
-            .list_with("/")
-            .recursive(true)
-            .await?
-            .iter()
-            .map(FileMeta::from)
-            .collect())
+        self.list_stream().await?.try_collect().await
     }
 
     async fn get(&self, path: &str) -> Result<(Stream, FileMeta)> {
@@ -41,8 +154,6 @@ impl ReadOnlyFilesystem for Filesystem {
             .into_bytes_stream(..)
             .await?;
 
-        use crate::fs::StreamExt;
-
         Ok((stream.into_boxed(), (path, stat).into()))
     }
 
@@ -51,24 +162,56 @@ impl ReadOnlyFilesystem for Filesystem {
 
         Ok((path, stat).into())
     }
+
+    async fn list_stream(&self) -> Result<FileStream> {
+        let lister = self.operator.lister_with("/").recursive(true).await?;
+        let operator = self.operator.clone();
+
+        Ok(lister
+            .map_err(Error::from)
+            .try_filter(|entry| future::ready(!entry.metadata().is_dir()))
+            .and_then(move |entry| {
+                let operator = operator.clone();
+
+                async move {
+                    // Some services, eg. fs, only return the entry mode when listing
+                    if entry.metadata().last_modified().is_some() {
+                        return Ok((&entry).into());
+                    }
+
+                    let stat = operator.stat(entry.path()).await?;
+
+                    Ok((entry.path(), stat).into())
+                }
+            })
+            .into_file_stream())
+    }
 }
 
 #[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
 #[cfg_attr(not(target_arch = "wasm32"), async_trait)]
 impl WritableFilesystem for Filesystem {
     async fn put(&self, path: &str, mut data: Stream, meta: IncomingFileMeta) -> Result<FileMeta> {
-        let mut options = ::opendal::options::WriteOptions {
-            content_type: meta.content_type,
+        let mut options = WriteOptions {
+            content_type: meta.content_type.clone(),
             ..Default::default()
         };
 
-        if let Some(created) = meta.created {
-            options.user_metadata = Some(HashMap::from([(
-                "created".to_string(),
-                created.to_string(),
-            )]));
+        self.condition(path, &meta, &mut options).await?;
+
+        let created = match meta.created {
+            Some(created) => created,
+            None => self.created(path).await?.unwrap_or_else(now),
+        };
+        let mut user_metadata = HashMap::from([("created".to_string(), created.to_string())]);
+
+        // Uploads of older files, e.g. by sync clients, keep their time
+        if let Some(last_modified) = meta.last_modified {
+            user_metadata.insert("last_modified".to_string(), last_modified.to_string());
         }
 
+        options.user_metadata = Some(user_metadata);
+
         let mut writer = self.operator.writer_options(path, options).await?;
 
         while let Some(chunk) = data.next().await {
@@ -104,23 +247,35 @@ impl From<&::opendal::Entry> for FileMeta {
 
 impl From<(&str, ::opendal::Metadata)> for FileMeta {
     fn from((path, metadata): (&str, ::opendal::Metadata)) -> Self {
-        FileMeta {
-            name: path.to_string(),
-            created: metadata
+        let user_time = |key: &str| {
+            metadata
                 .user_metadata()
-                .and_then(|um| um.get("created"))
+                .and_then(|um| um.get(key))
                 .and_then(|s| s.parse().ok())
-                .unwrap_or_else(now),
-            perm: "rw".to_string(), // Default to read-write for now
+        };
+
+        let last_modified = user_time("last_modified")
+            .or_else(|| {
+                metadata
+                    .last_modified()
+                    .map(|lm| time::from_signed_millis(lm.into_inner().as_millisecond()))
+            })
+            .unwrap_or_else(now);
+
+        FileMeta {
+            name: path.to_string(),
+            // Services dropping the user metadata have no creation time, the modification
+            // time at least stays the same across calls
+            created: user_time("created").unwrap_or(last_modified),
+            perm: perm::Perm::ReadWrite.to_string(),
             content_type: metadata
                 .content_type()
                 .unwrap_or("application/octet-stream")
                 .to_string(),
-            last_modified: metadata
-                .last_modified()
-                .map(|lm| lm.into_inner().as_millisecond().unsigned_abs())
-                .unwrap_or_else(now),
+            last_modified,
             size: metadata.content_length(),
+            etag: metadata.etag().map(str::to_string),
+            version: metadata.version().map(str::to_string),
         }
     }
 }
@@ -130,6 +285,9 @@ impl From<::opendal::Error> for Error {
         match err.kind() {
             ::opendal::ErrorKind::NotFound => Error::NotFound(err.into()),
             ::opendal::ErrorKind::PermissionDenied => Error::PermissionDenied(err.into()),
+            ::opendal::ErrorKind::ConditionNotMatch => Error::PreconditionFailed(err.into()),
+            ::opendal::ErrorKind::RateLimited => Error::Transient(err.into()),
+            _ if err.is_temporary() => Error::Transient(err.into()),
             _ => Error::Other(err.into()),
         }
     }
@@ -170,6 +328,24 @@ mod tests {
         assert!(files.is_empty());
     }
 
+    #[tokio::test]
+    async fn list_skips_directories() {
+        let root = std::env::temp_dir().join(format!("silverbullet-list-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("sub")).unwrap();
+        std::fs::write(root.join("sub/page.md"), "hello").unwrap();
+
+        let op = Operator::new(::opendal::services::Fs::default().root(root.to_str().unwrap()))
+            .unwrap()
+            .finish();
+        let files = Filesystem::new(op).list().await.unwrap();
+
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].name, "sub/page.md");
+        assert_eq!(files[0].size, 5);
+    }
+
     #[tokio::test]
     async fn put_and_get() {
         let fs = memory_fs();
@@ -264,6 +440,71 @@ mod tests {
         assert_eq!(meta.perm, "rw");
     }
 
+    #[tokio::test]
+    async fn conditional_writes() {
+        let fs = memory_fs();
+        let only_new = || IncomingFileMeta {
+            if_none_match: Some("*".to_string()),
+            ..Default::default()
+        };
+
+        fs.put("a.md", bytes_stream(b"a"), only_new())
+            .await
+            .unwrap();
+        assert!(matches!(
+            fs.put("a.md", bytes_stream(b"b"), only_new()).await,
+            Err(Error::PreconditionFailed(_))
+        ));
+
+        // The memory service keeps no etags, so only `*` matches
+        let existing = |etag: &str| IncomingFileMeta {
+            if_match: Some(etag.to_string()),
+            ..Default::default()
+        };
+        fs.put("a.md", bytes_stream(b"c"), existing("*"))
+            .await
+            .unwrap();
+        assert!(matches!(
+            fs.put("b.md", bytes_stream(b"b"), existing("*")).await,
+            Err(Error::PreconditionFailed(_))
+        ));
+        assert!(matches!(
+            fs.put("a.md", bytes_stream(b"d"), existing("\"v1\"")).await,
+            Err(Error::PreconditionFailed(_))
+        ));
+
+        let (stream, _) = fs.get("a.md").await.unwrap();
+        assert_eq!(collect_stream(stream).await, b"c");
+    }
+
+    #[test]
+    fn prefers_times_given_to_writes() {
+        let metadata = ::opendal::Metadata::new(::opendal::EntryMode::FILE)
+            .with_last_modified("2026-10-14T12:00:00Z".parse().unwrap())
+            .with_user_metadata(HashMap::from([
+                ("created".to_string(), "1000".to_string()),
+                ("last_modified".to_string(), "2000".to_string()),
+            ]));
+
+        let meta = FileMeta::from(("a.md", metadata.clone()));
+        assert_eq!((meta.created, meta.last_modified), (1000, 2000));
+
+        let meta = FileMeta::from(("a.md", metadata.with_user_metadata(HashMap::new())));
+        assert_eq!(
+            (meta.created, meta.last_modified),
+            (1_791_979_200_000, 1_791_979_200_000)
+        );
+    }
+
+    #[test]
+    fn matches_etags() {
+        assert!(etag_matches("\"v1\"", Some("\"v1\"")));
+        assert!(etag_matches("\"v0\", W/\"v1\"", Some("v1")));
+        assert!(etag_matches("*", None));
+        assert!(!etag_matches("\"v1\"", Some("\"v2\"")));
+        assert!(!etag_matches("\"v1\"", None));
+    }
+
     #[tokio::test]
     async fn get_not_found() {
         let fs = memory_fs();
@@ -344,4 +585,34 @@ mod tests {
         let meta = fs.meta("test.bin").await.unwrap();
         assert_eq!(meta.content_type, "application/octet-stream");
     }
+
+    #[tokio::test]
+    async fn presigns_puts() {
+        let expire = Duration::from_secs(60);
+
+        // Nothing to presign with
+        let presigned = memory_fs().presign_put("big.zip", expire).await.unwrap();
+        assert!(presigned.is_none());
+
+        let s3 = ::opendal::services::S3::default()
+            .bucket("space")
+            .region("us-east-1")
+            .endpoint("http://127.0.0.1:1")
+            .access_key_id("key")
+            .secret_access_key("secret")
+            .disable_config_load()
+            .disable_ec2_metadata();
+        let s3 = Filesystem::new(Operator::new(s3).unwrap().finish());
+
+        let presigned = s3.presign_put("big.zip", expire).await.unwrap().unwrap();
+        assert_eq!(presigned.method(), ::http::Method::PUT);
+
+        let url = presigned.uri().to_string();
+        assert!(
+            url.starts_with("http://127.0.0.1:1/space/big.zip?"),
+            "{url}"
+        );
+        assert!(url.contains("X-Amz-Expires=60"), "{url}");
+        assert!(url.contains("X-Amz-Signature="), "{url}");
+    }
 }
@@ -0,0 +1,315 @@
+//! Permissions reported to clients
+//!
+//! The client disables editing of the files whose [`FileMeta::perm`] is `ro`. Backends
+//! report what they can do, e.g. embedded files are read-only, and [`Filesystem`] wraps
+//! a filesystem to narrow it down with a [`PermissionResolver`]: per-path [`Rules`], or
+//! [`Perm::Read`] for the whole space, e.g. for a read-only user. Writes to files it
+//! resolves as read-only fail with [`Error::PermissionDenied`], so the permission is also
+//! what the client may do. A resolver never grants more than the backend reports.
+//!
+//! ```ignore
+//! let fs = perm::Filesystem::new(fs, Rules::new().read_only("Library/**"));
+//!
+//! // In a request of a read-only user
+//! let fs = perm::Filesystem::new(fs, Perm::Read);
+//! ```
+
+use std::fmt;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use futures::TryStreamExt as _;
+
+use crate::fs::*;
+use crate::glob;
+
+/// Access to a file, `ro` or `rw` on the wire
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Perm {
+    Read,
+    ReadWrite,
+}
+
+impl Perm {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Perm::Read => "ro",
+            Perm::ReadWrite => "rw",
+        }
+    }
+
+    /// Permission of a [`FileMeta::perm`], anything but `ro` being writable.
+    pub fn parse(perm: &str) -> Self {
+        match perm {
+            "ro" => Perm::Read,
+            _ => Perm::ReadWrite,
+        }
+    }
+}
+
+impl fmt::Display for Perm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Decides the permission of files
+pub trait PermissionResolver: Send + Sync {
+    /// Permission of `path`, given the one reported by the backend.
+    fn resolve(&self, path: &str, perm: Perm) -> Perm;
+}
+
+/// Caps every file at this permission.
+impl PermissionResolver for Perm {
+    fn resolve(&self, _: &str, perm: Perm) -> Perm {
+        perm.min(*self)
+    }
+}
+
+/// Applies the first resolver, then the second.
+impl<A, B> PermissionResolver for (A, B)
+where
+    A: PermissionResolver,
+    B: PermissionResolver,
+{
+    fn resolve(&self, path: &str, perm: Perm) -> Perm {
+        self.1.resolve(path, self.0.resolve(path, perm))
+    }
+}
+
+impl<R: PermissionResolver + ?Sized> PermissionResolver for Arc<R> {
+    fn resolve(&self, path: &str, perm: Perm) -> Perm {
+        (**self).resolve(path, perm)
+    }
+}
+
+/// Permissions by path glob, the last matching rule applying
+#[derive(Debug, Clone, Default)]
+pub struct Rules {
+    rules: Vec<(String, Perm)>,
+}
+
+impl Rules {
+    /// Leave every file as the backend reports it.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Make the files matching a glob read-only, e.g. `Library/**`.
+    #[must_use]
+    pub fn read_only(self, pattern: impl Into<String>) -> Self {
+        self.rule(pattern, Perm::Read)
+    }
+
+    /// Leave the files matching a glob writable, e.g. after a broader read-only rule.
+    #[must_use]
+    pub fn read_write(self, pattern: impl Into<String>) -> Self {
+        self.rule(pattern, Perm::ReadWrite)
+    }
+
+    /// Give the files matching a glob a permission, at most the backend's.
+    #[must_use]
+    pub fn rule(mut self, pattern: impl Into<String>, perm: Perm) -> Self {
+        self.rules.push((pattern.into(), perm));
+        self
+    }
+}
+
+impl PermissionResolver for Rules {
+    fn resolve(&self, path: &str, perm: Perm) -> Perm {
+        self.rules
+            .iter()
+            .rev()
+            .find(|(pattern, _)| glob::matches(pattern, path))
+            .map_or(perm, |(_, rule)| perm.min(*rule))
+    }
+}
+
+pub struct Filesystem<F, R> {
+    inner: F,
+    resolver: Arc<R>,
+}
+
+impl<F, R> Filesystem<F, R> {
+    pub fn new(inner: F, resolver: R) -> Self {
+        Self {
+            inner,
+            resolver: Arc::new(resolver),
+        }
+    }
+}
+
+fn resolve<R: PermissionResolver>(resolver: &R, mut meta: FileMeta) -> FileMeta {
+    let perm = resolver.resolve(&meta.name, Perm::parse(&meta.perm));
+    meta.perm = perm.to_string();
+    meta
+}
+
+impl<F, R> Filesystem<F, R>
+where
+    R: PermissionResolver,
+{
+    fn resolve(&self, meta: FileMeta) -> FileMeta {
+        resolve(&*self.resolver, meta)
+    }
+
+    fn check_writable(&self, path: &str) -> Result<()> {
+        match self.resolver.resolve(path, Perm::ReadWrite) {
+            Perm::ReadWrite => Ok(()),
+            Perm::Read => Err(Error::PermissionDenied(
+                format!("{path} is read-only").into(),
+            )),
+        }
+    }
+}
+
+/// [`FsLayer`](crate::fs::stack::FsLayer) wrapping filesystems in [`Filesystem`]
+#[derive(Debug, Clone)]
+pub struct PermissionsLayer<R> {
+    resolver: R,
+}
+
+impl<R> PermissionsLayer<R> {
+    pub fn new(resolver: R) -> Self {
+        Self { resolver }
+    }
+}
+
+impl<F, R: Clone> crate::fs::stack::FsLayer<F> for PermissionsLayer<R> {
+    type Output = Filesystem<F, R>;
+
+    fn layer(&self, inner: F) -> Self::Output {
+        Filesystem::new(inner, self.resolver.clone())
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl<F, R> ReadOnlyFilesystem for Filesystem<F, R>
+where
+    F: ReadOnlyFilesystem,
+    R: PermissionResolver + 'static,
+{
+    async fn list(&self) -> Result<Vec<FileMeta>> {
+        let files = self.inner.list().await?;
+
+        Ok(files.into_iter().map(|meta| self.resolve(meta)).collect())
+    }
+
+    async fn get(&self, path: &str) -> Result<(Stream, FileMeta)> {
+        let (stream, meta) = self.inner.get(path).await?;
+
+        Ok((stream, self.resolve(meta)))
+    }
+
+    async fn meta(&self, path: &str) -> Result<FileMeta> {
+        self.inner.meta(path).await.map(|meta| self.resolve(meta))
+    }
+
+    async fn list_stream(&self) -> Result<FileStream> {
+        let resolver = self.resolver.clone();
+        let files = self.inner.list_stream().await?;
+
+        Ok(files
+            .map_ok(move |meta| resolve(&*resolver, meta))
+            .into_file_stream())
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl<F, R> WritableFilesystem for Filesystem<F, R>
+where
+    F: WritableFilesystem,
+    R: PermissionResolver,
+{
+    async fn put(&self, path: &str, data: Stream, meta: IncomingFileMeta) -> Result<FileMeta> {
+        self.check_writable(path)?;
+
+        self.inner
+            .put(path, data, meta)
+            .await
+            .map(|meta| self.resolve(meta))
+    }
+
+    async fn delete(&self, path: &str) -> Result<()> {
+        self.check_writable(path)?;
+
+        self.inner.delete(path).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::testing::MemoryFs;
+    use bytes::Bytes;
+    use futures::stream;
+
+    fn body() -> Stream {
+        stream::once(async { Ok(Bytes::from("new")) }).into_boxed()
+    }
+
+    #[tokio::test]
+    async fn applies_rules() {
+        let rules = Rules::new()
+            .read_only("Library/**")
+            .read_write("Library/Personal/**");
+        let fs = Filesystem::new(
+            MemoryFs::new()
+                .with_file("index.md", b"")
+                .with_file("Library/Std/Widgets.md", b"")
+                .with_file("Library/Personal/Ideas.md", b""),
+            rules,
+        );
+
+        assert_eq!(fs.meta("index.md").await.unwrap().perm, "rw");
+        assert_eq!(fs.meta("Library/Std/Widgets.md").await.unwrap().perm, "ro");
+        assert_eq!(
+            fs.meta("Library/Personal/Ideas.md").await.unwrap().perm,
+            "rw"
+        );
+
+        assert!(matches!(
+            fs.put(
+                "Library/Std/Widgets.md",
+                body(),
+                IncomingFileMeta::default()
+            )
+            .await,
+            Err(Error::PermissionDenied(_))
+        ));
+        assert!(matches!(
+            fs.delete("Library/Std/Widgets.md").await,
+            Err(Error::PermissionDenied(_))
+        ));
+        fs.put(
+            "Library/Personal/Ideas.md",
+            body(),
+            IncomingFileMeta::default(),
+        )
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn never_grants_more_than_the_backend() {
+        let fs = Filesystem::new(MemoryFs::new(), Rules::new().read_write("**"));
+
+        let meta = IncomingFileMeta {
+            perm: Some("ro".to_string()),
+            ..Default::default()
+        };
+        fs.inner.put("a.md", body(), meta).await.unwrap();
+
+        assert_eq!(fs.list().await.unwrap()[0].perm, "ro");
+        assert_eq!(fs.meta("a.md").await.unwrap().perm, "ro");
+
+        // Read-only users can't write anywhere
+        let fs = Filesystem::new(fs, Perm::Read);
+        assert!(matches!(
+            fs.put("b.md", body(), IncomingFileMeta::default()).await,
+            Err(Error::PermissionDenied(_))
+        ));
+    }
+}
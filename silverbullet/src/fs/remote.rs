@@ -0,0 +1,373 @@
+//! Space of a remote SilverBullet server
+//!
+//! [`Filesystem`] reads and writes through the `/.fs` API of a SilverBullet server over
+//! any [`proxy::Client`], so a remote space can be layered, backed up or synced like a
+//! local one. On wasm32, [`proxy::fetch::Client`](crate::proxy) sends the requests with
+//! the Fetch API, which makes it the sync layer of a client running in the browser.
+//!
+//! Bodies are buffered, the client interface sends and receives whole files.
+//!
+//! ```ignore
+//! let fs = remote::Filesystem::new(client, "https://notes.example.com")
+//!     .header(header::AUTHORIZATION, HeaderValue::from_static("Bearer token"));
+//! ```
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::{TryStreamExt as _, stream};
+use http::{HeaderMap, HeaderName, HeaderValue, Method, Request, Response, StatusCode, header};
+
+use crate::fs::*;
+use crate::proxy;
+
+pub struct Filesystem<C> {
+    client: C,
+    base_url: String,
+    headers: HeaderMap,
+}
+
+impl<C> Filesystem<C> {
+    /// Filesystem of the server at `base_url`, including its URL prefix if it has one.
+    pub fn new(client: C, base_url: impl Into<String>) -> Self {
+        Self {
+            client,
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            headers: HeaderMap::new(),
+        }
+    }
+
+    /// Send a header with every request, e.g. the `Authorization` of the server.
+    #[must_use]
+    pub fn header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.headers.insert(name, value);
+        self
+    }
+
+    fn url(&self, path: &str) -> String {
+        let mut url = format!("{}/.fs", self.base_url);
+
+        for segment in path.trim_start_matches('/').split('/') {
+            url.push('/');
+            url.push_str(&crate::percent::encode(segment));
+        }
+
+        url
+    }
+}
+
+impl<C> Filesystem<C>
+where
+    C: proxy::Client,
+{
+    async fn send(
+        &self,
+        method: Method,
+        url: String,
+        request: Request<Bytes>,
+    ) -> Result<Response<Bytes>> {
+        let (mut parts, body) = request.into_parts();
+        parts.method = method;
+        parts.uri = url
+            .parse()
+            .map_err(|_| Error::Other(format!("invalid URL: {url}").into()))?;
+
+        for (name, value) in &self.headers {
+            parts.headers.insert(name, value.clone());
+        }
+
+        let response = self
+            .client
+            .send(Request::from_parts(parts, body))
+            .await
+            .map_err(|err| Error::Other(err.to_string().into()))?;
+
+        match response.status() {
+            status if status.is_success() => Ok(response),
+            StatusCode::NOT_FOUND => Err(Error::NotFound(url.into())),
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+                Err(Error::PermissionDenied(url.into()))
+            }
+            StatusCode::TOO_MANY_REQUESTS
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT => Err(Error::Transient(
+                format!("{url} responded with {}", response.status()).into(),
+            )),
+            status => Err(Error::Other(
+                format!(
+                    "{url} responded with {status}: {}",
+                    String::from_utf8_lossy(response.body())
+                )
+                .into(),
+            )),
+        }
+    }
+}
+
+/// Metadata from the headers the server sends along with a file.
+fn file_meta(name: &str, headers: &HeaderMap, body: &Bytes) -> FileMeta {
+    let text = |name: HeaderName| headers.get(name).and_then(|value| value.to_str().ok());
+    let number = |name: &'static str| {
+        text(HeaderName::from_static(name)).and_then(|value| value.parse().ok())
+    };
+
+    FileMeta {
+        name: name.to_string(),
+        created: number("x-created").unwrap_or(0),
+        perm: text(HeaderName::from_static("x-permission"))
+            .unwrap_or("rw")
+            .to_string(),
+        content_type: text(header::CONTENT_TYPE)
+            .unwrap_or("application/octet-stream")
+            .to_string(),
+        last_modified: number("x-last-modified").unwrap_or(0),
+        size: number("x-content-length").unwrap_or(body.len() as u64),
+        etag: text(header::ETAG).map(str::to_string),
+        version: text(HeaderName::from_static("x-version")).map(str::to_string),
+    }
+}
+
+fn json<T: serde::de::DeserializeOwned>(response: &Response<Bytes>) -> Result<T> {
+    serde_json::from_slice(response.body()).map_err(|err| Error::Other(err.into()))
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl<C> ReadOnlyFilesystem for Filesystem<C>
+where
+    C: proxy::Client,
+{
+    async fn list(&self) -> Result<Vec<FileMeta>> {
+        let url = format!("{}/.fs", self.base_url);
+        let response = self.send(Method::GET, url, Request::default()).await?;
+
+        json(&response)
+    }
+
+    async fn get(&self, path: &str) -> Result<(Stream, FileMeta)> {
+        let response = self
+            .send(Method::GET, self.url(path), Request::default())
+            .await?;
+
+        let (parts, body) = response.into_parts();
+        let meta = file_meta(path, &parts.headers, &body);
+
+        Ok((stream::once(async move { Ok(body) }).into_boxed(), meta))
+    }
+
+    async fn meta(&self, path: &str) -> Result<FileMeta> {
+        let request = Request::builder()
+            .header("X-Get-Meta", "true")
+            .body(Bytes::new())
+            .map_err(|err| Error::Other(err.into()))?;
+
+        let response = self.send(Method::GET, self.url(path), request).await?;
+
+        Ok(file_meta(path, response.headers(), response.body()))
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl<C> WritableFilesystem for Filesystem<C>
+where
+    C: proxy::Client,
+{
+    async fn put(&self, path: &str, data: Stream, meta: IncomingFileMeta) -> Result<FileMeta> {
+        let body: Vec<u8> = data
+            .try_fold(Vec::new(), |mut acc, chunk| async move {
+                acc.extend_from_slice(&chunk);
+                Ok(acc)
+            })
+            .await?;
+
+        let mut request = Request::builder();
+        if let Some(content_type) = &meta.content_type {
+            request = request.header(header::CONTENT_TYPE, content_type);
+        }
+        if let Some(created) = meta.created {
+            request = request.header("X-Created", created);
+        }
+        if let Some(last_modified) = meta.last_modified {
+            request = request.header("X-Last-Modified", last_modified);
+        }
+
+        let request = request
+            .body(Bytes::from(body))
+            .map_err(|err| Error::Other(err.into()))?;
+
+        let response = self.send(Method::PUT, self.url(path), request).await?;
+
+        json(&response)
+    }
+
+    async fn delete(&self, path: &str) -> Result<()> {
+        self.send(Method::DELETE, self.url(path), Request::default())
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    /// Client answering every request with the same response
+    struct Canned {
+        requests: Mutex<Vec<Request<Bytes>>>,
+        status: StatusCode,
+        headers: Vec<(&'static str, &'static str)>,
+        body: &'static str,
+    }
+
+    impl Canned {
+        fn new(
+            status: StatusCode,
+            headers: Vec<(&'static str, &'static str)>,
+            body: &'static str,
+        ) -> Self {
+            Self {
+                requests: Mutex::new(Vec::new()),
+                status,
+                headers,
+                body,
+            }
+        }
+
+        fn request(&self) -> Request<Bytes> {
+            self.requests.lock().unwrap().pop().unwrap()
+        }
+    }
+
+    #[async_trait]
+    impl proxy::Client for &Canned {
+        async fn send(&self, request: Request<Bytes>) -> proxy::Result<Response<Bytes>> {
+            self.requests.lock().unwrap().push(request);
+
+            let mut response = Response::builder().status(self.status);
+            for (name, value) in &self.headers {
+                response = response.header(*name, *value);
+            }
+
+            Ok(response.body(Bytes::from_static(self.body.as_bytes()))?)
+        }
+    }
+
+    async fn read(stream: Stream) -> Vec<u8> {
+        stream
+            .try_fold(Vec::new(), |mut acc, chunk| async move {
+                acc.extend_from_slice(&chunk);
+                Ok(acc)
+            })
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn lists_files_with_default_headers() {
+        let client = Canned::new(
+            StatusCode::OK,
+            Vec::new(),
+            r#"[{"name":"index.md","created":1,"perm":"rw","contentType":"text/markdown","lastModified":2,"size":3}]"#,
+        );
+        let fs = Filesystem::new(&client, "https://sb.example.com/notes/")
+            .header(header::AUTHORIZATION, HeaderValue::from_static("Bearer t"));
+
+        let files = fs.list().await.unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].name, "index.md");
+        assert_eq!(files[0].size, 3);
+
+        let request = client.request();
+        assert_eq!(request.method(), Method::GET);
+        assert_eq!(request.uri(), "https://sb.example.com/notes/.fs");
+        assert_eq!(request.headers()[header::AUTHORIZATION], "Bearer t");
+    }
+
+    #[tokio::test]
+    async fn reads_files_and_their_meta() {
+        let client = Canned::new(
+            StatusCode::OK,
+            vec![
+                ("content-type", "image/png"),
+                ("x-content-length", "4"),
+                ("x-created", "10"),
+                ("x-last-modified", "20"),
+                ("x-permission", "ro"),
+                ("etag", "\"v1\""),
+            ],
+            "data",
+        );
+        let fs = Filesystem::new(&client, "https://sb.example.com");
+
+        let (stream, meta) = fs.get("Daily notes/a+b.png").await.unwrap();
+        assert_eq!(read(stream).await, b"data");
+        assert_eq!(
+            client.request().uri(),
+            "https://sb.example.com/.fs/Daily%20notes/a%2Bb.png"
+        );
+
+        assert_eq!(meta.name, "Daily notes/a+b.png");
+        assert_eq!(meta.content_type, "image/png");
+        assert_eq!((meta.created, meta.last_modified, meta.size), (10, 20, 4));
+        assert_eq!(meta.perm, "ro");
+        assert_eq!(meta.etag.as_deref(), Some("\"v1\""));
+
+        fs.meta("a.png").await.unwrap();
+        assert!(client.request().headers().contains_key("x-get-meta"));
+    }
+
+    #[tokio::test]
+    async fn writes_files() {
+        let client = Canned::new(
+            StatusCode::OK,
+            Vec::new(),
+            r#"{"name":"a.md","created":5,"perm":"rw","contentType":"text/markdown","lastModified":6,"size":2}"#,
+        );
+        let fs = Filesystem::new(&client, "https://sb.example.com");
+
+        let data = stream::once(async { Ok(Bytes::from("hi")) }).into_boxed();
+        let meta = IncomingFileMeta {
+            created: Some(5),
+            last_modified: Some(6),
+            content_type: Some("text/markdown".to_string()),
+            ..Default::default()
+        };
+
+        let written = fs.put("a.md", data, meta).await.unwrap();
+        assert_eq!((written.created, written.size), (5, 2));
+
+        let request = client.request();
+        assert_eq!(request.method(), Method::PUT);
+        assert_eq!(request.headers()["x-created"], "5");
+        assert_eq!(request.headers()["x-last-modified"], "6");
+        assert_eq!(request.headers()[header::CONTENT_TYPE], "text/markdown");
+        assert_eq!(request.body().as_ref(), b"hi");
+    }
+
+    #[tokio::test]
+    async fn maps_error_statuses() {
+        let client = Canned::new(StatusCode::NOT_FOUND, Vec::new(), "");
+        let fs = Filesystem::new(&client, "https://sb.example.com");
+        assert!(matches!(fs.delete("a.md").await, Err(Error::NotFound(_))));
+
+        let client = Canned::new(StatusCode::UNAUTHORIZED, Vec::new(), "");
+        let fs = Filesystem::new(&client, "https://sb.example.com");
+        assert!(matches!(
+            fs.meta("a.md").await,
+            Err(Error::PermissionDenied(_))
+        ));
+
+        let client = Canned::new(StatusCode::BAD_GATEWAY, Vec::new(), "");
+        let fs = Filesystem::new(&client, "https://sb.example.com");
+        assert!(matches!(fs.list().await, Err(Error::Transient(_))));
+
+        let client = Canned::new(StatusCode::INTERNAL_SERVER_ERROR, Vec::new(), "down");
+        let fs = Filesystem::new(&client, "https://sb.example.com");
+        let err = fs.list().await.unwrap_err().to_string();
+        assert!(err.contains("500") && err.contains("down"), "{err}");
+    }
+}
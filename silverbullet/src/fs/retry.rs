@@ -0,0 +1,478 @@
+//! Retries of transient backend failures
+//!
+//! S3-compatible providers occasionally time out, reset connections or rate limit.
+//! [`Filesystem`] wraps a filesystem and retries the calls failing with
+//! [`Error::Transient`] or a dropped connection, waiting an exponentially growing,
+//! randomized delay between attempts so clients retrying together don't hit the backend
+//! in lockstep. Each operation has its own [`Backoff`], e.g. to not retry deletes.
+//!
+//! Reads only retry opening the file: a body failing midway is reported to the client.
+//! Uploads are buffered up to [`Filesystem::buffer_limit`] to be sent again, larger ones
+//! are tried once.
+//!
+//! ```ignore
+//! let fs = retry::Filesystem::new(opendal::Filesystem::new(operator))
+//!     .reads(Backoff::new(3))
+//!     .operation(Operation::Delete, Backoff::none());
+//! ```
+
+use std::future::Future;
+use std::hash::{BuildHasher as _, RandomState};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::{StreamExt as _, TryStreamExt as _, stream};
+use futures_timer::Delay;
+
+use crate::fs::*;
+
+/// Size of the uploads buffered to be retried, 8 MiB by default
+pub const DEFAULT_BUFFER_LIMIT: usize = 8 * 1024 * 1024;
+
+/// Filesystem calls with their own [`Backoff`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    List,
+    Get,
+    Meta,
+    Put,
+    Delete,
+}
+
+/// How often and how long to wait before retrying a call
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Backoff {
+    retries: u32,
+    initial: Duration,
+    max: Duration,
+}
+
+impl Default for Backoff {
+    /// Two retries, waiting up to 100ms and then 200ms.
+    fn default() -> Self {
+        Self::new(2)
+    }
+}
+
+impl Backoff {
+    /// Retry up to `retries` times, waiting up to 100ms before the first retry and twice
+    /// as long before each next one, at most 5s.
+    pub fn new(retries: u32) -> Self {
+        Self {
+            retries,
+            initial: Duration::from_millis(100),
+            max: Duration::from_secs(5),
+        }
+    }
+
+    /// Don't retry.
+    pub fn none() -> Self {
+        Self::new(0)
+    }
+
+    /// Longest wait before the first retry.
+    #[must_use]
+    pub fn initial(mut self, delay: Duration) -> Self {
+        self.initial = delay;
+        self
+    }
+
+    /// Longest wait before any retry.
+    #[must_use]
+    pub fn max(mut self, delay: Duration) -> Self {
+        self.max = delay;
+        self
+    }
+
+    /// Wait before retry `retry`, counted from 0: a random duration up to the exponential
+    /// delay ("full jitter").
+    fn delay(&self, retry: u32) -> Duration {
+        let cap = self
+            .initial
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.max);
+
+        cap.mul_f64(random())
+    }
+}
+
+/// Random number in `[0, 1]`, from the randomly keyed std hasher.
+fn random() -> f64 {
+    RandomState::new().hash_one(()) as f64 / u64::MAX as f64
+}
+
+/// Whether a failed call may succeed when tried again.
+pub fn is_transient(err: &Error) -> bool {
+    use std::io::ErrorKind;
+
+    match err {
+        Error::Transient(_) => true,
+        Error::Io(err) => matches!(
+            err.kind(),
+            ErrorKind::TimedOut
+                | ErrorKind::ConnectionReset
+                | ErrorKind::ConnectionAborted
+                | ErrorKind::Interrupted
+        ),
+        _ => false,
+    }
+}
+
+pub struct Filesystem<F> {
+    inner: F,
+    list: Backoff,
+    get: Backoff,
+    meta: Backoff,
+    put: Backoff,
+    delete: Backoff,
+    buffer_limit: usize,
+}
+
+impl<F> Filesystem<F> {
+    /// Retry every operation with the default [`Backoff`].
+    pub fn new(inner: F) -> Self {
+        Self {
+            inner,
+            list: Backoff::default(),
+            get: Backoff::default(),
+            meta: Backoff::default(),
+            put: Backoff::default(),
+            delete: Backoff::default(),
+            buffer_limit: DEFAULT_BUFFER_LIMIT,
+        }
+    }
+
+    /// Backoff of lists, gets and metadata reads.
+    #[must_use]
+    pub fn reads(self, backoff: Backoff) -> Self {
+        self.operation(Operation::List, backoff)
+            .operation(Operation::Get, backoff)
+            .operation(Operation::Meta, backoff)
+    }
+
+    /// Backoff of puts and deletes.
+    #[must_use]
+    pub fn writes(self, backoff: Backoff) -> Self {
+        self.operation(Operation::Put, backoff)
+            .operation(Operation::Delete, backoff)
+    }
+
+    /// Backoff of one operation.
+    #[must_use]
+    pub fn operation(mut self, operation: Operation, backoff: Backoff) -> Self {
+        *match operation {
+            Operation::List => &mut self.list,
+            Operation::Get => &mut self.get,
+            Operation::Meta => &mut self.meta,
+            Operation::Put => &mut self.put,
+            Operation::Delete => &mut self.delete,
+        } = backoff;
+        self
+    }
+
+    /// Largest upload buffered to be retried, [`DEFAULT_BUFFER_LIMIT`] by default.
+    #[must_use]
+    pub fn buffer_limit(mut self, bytes: usize) -> Self {
+        self.buffer_limit = bytes;
+        self
+    }
+}
+
+/// [`FsLayer`](crate::fs::stack::FsLayer) wrapping filesystems in [`Filesystem`]
+#[derive(Debug, Clone, Copy)]
+pub struct RetryLayer {
+    reads: Backoff,
+    writes: Backoff,
+}
+
+impl RetryLayer {
+    /// Retry reads and writes with the same backoff.
+    pub fn new(backoff: Backoff) -> Self {
+        Self {
+            reads: backoff,
+            writes: backoff,
+        }
+    }
+
+    /// Backoff of puts and deletes.
+    #[must_use]
+    pub fn writes(mut self, backoff: Backoff) -> Self {
+        self.writes = backoff;
+        self
+    }
+}
+
+impl<F> crate::fs::stack::FsLayer<F> for RetryLayer {
+    type Output = Filesystem<F>;
+
+    fn layer(&self, inner: F) -> Self::Output {
+        Filesystem::new(inner).reads(self.reads).writes(self.writes)
+    }
+}
+
+/// Call until it succeeds, fails for good or runs out of retries.
+async fn retry<T, Fut>(
+    backoff: Backoff,
+    operation: Operation,
+    path: &str,
+    mut call: impl FnMut() -> Fut,
+) -> Result<T>
+where
+    Fut: Future<Output = Result<T>>,
+{
+    let mut retry = 0;
+
+    loop {
+        match call().await {
+            Err(err) if retry < backoff.retries && is_transient(&err) => {
+                let delay = backoff.delay(retry);
+
+                #[cfg(feature = "tracing")]
+                tracing::debug!(?operation, path, error = %err, ?delay, "Retrying filesystem call");
+                #[cfg(not(feature = "tracing"))]
+                let _ = (operation, path);
+
+                Delay::new(delay).await;
+                retry += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Read up to `limit` bytes of a body, `Ok` with all of it when it fits, `Err` with the
+/// chunks read so far when it doesn't.
+async fn buffer(
+    data: &mut Stream,
+    limit: usize,
+) -> std::result::Result<std::result::Result<Vec<Bytes>, Vec<Bytes>>, std::io::Error> {
+    let mut chunks = Vec::new();
+    let mut size = 0;
+
+    while let Some(chunk) = data.try_next().await? {
+        size += chunk.len();
+        chunks.push(chunk);
+
+        if size > limit {
+            return Ok(Err(chunks));
+        }
+    }
+
+    Ok(Ok(chunks))
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl<F> ReadOnlyFilesystem for Filesystem<F>
+where
+    F: ReadOnlyFilesystem,
+{
+    async fn list(&self) -> Result<Vec<FileMeta>> {
+        retry(self.list, Operation::List, "", || self.inner.list()).await
+    }
+
+    async fn get(&self, path: &str) -> Result<(Stream, FileMeta)> {
+        retry(self.get, Operation::Get, path, || self.inner.get(path)).await
+    }
+
+    async fn meta(&self, path: &str) -> Result<FileMeta> {
+        retry(self.meta, Operation::Meta, path, || self.inner.meta(path)).await
+    }
+
+    /// Retries opening the listing, the files streamed after it fail as they come.
+    async fn list_stream(&self) -> Result<FileStream> {
+        retry(self.list, Operation::List, "", || self.inner.list_stream()).await
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl<F> WritableFilesystem for Filesystem<F>
+where
+    F: WritableFilesystem,
+{
+    async fn put(&self, path: &str, mut data: Stream, meta: IncomingFileMeta) -> Result<FileMeta> {
+        if self.put.retries == 0 {
+            return self.inner.put(path, data, meta).await;
+        }
+
+        match buffer(&mut data, self.buffer_limit).await? {
+            Ok(chunks) => {
+                retry(self.put, Operation::Put, path, || {
+                    let data = stream::iter(chunks.clone().into_iter().map(Ok)).into_boxed();
+
+                    self.inner.put(path, data, meta.clone())
+                })
+                .await
+            }
+            // Too large to send again
+            Err(chunks) => {
+                let data = stream::iter(chunks.into_iter().map(Ok))
+                    .chain(data)
+                    .into_boxed();
+
+                self.inner.put(path, data, meta).await
+            }
+        }
+    }
+
+    async fn delete(&self, path: &str) -> Result<()> {
+        let mut failed = false;
+
+        retry(self.delete, Operation::Delete, path, || {
+            let first = !failed;
+            failed = true;
+
+            async move {
+                match self.inner.delete(path).await {
+                    // An attempt that timed out may have deleted the file
+                    Err(Error::NotFound(_)) if !first => Ok(()),
+                    result => result,
+                }
+            }
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::testing::{MemoryFs, read_stream};
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    /// Fails the first calls with a transient error
+    struct Flaky {
+        inner: MemoryFs,
+        failures: AtomicU32,
+    }
+
+    impl Flaky {
+        fn new(inner: MemoryFs, failures: u32) -> Self {
+            Self {
+                inner,
+                failures: AtomicU32::new(failures),
+            }
+        }
+
+        fn fail(&self) -> Result<()> {
+            match self
+                .failures
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1))
+            {
+                Ok(_) => Err(Error::Transient("rate limited".into())),
+                Err(_) => Ok(()),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl ReadOnlyFilesystem for Flaky {
+        async fn list(&self) -> Result<Vec<FileMeta>> {
+            self.fail()?;
+            self.inner.list().await
+        }
+
+        async fn get(&self, path: &str) -> Result<(Stream, FileMeta)> {
+            self.fail()?;
+            self.inner.get(path).await
+        }
+
+        async fn meta(&self, path: &str) -> Result<FileMeta> {
+            self.fail()?;
+            self.inner.meta(path).await
+        }
+    }
+
+    #[async_trait]
+    impl WritableFilesystem for Flaky {
+        async fn put(&self, path: &str, data: Stream, meta: IncomingFileMeta) -> Result<FileMeta> {
+            // Consume the body like a failed upload would
+            let data = read_stream(data).await;
+            self.fail()?;
+
+            let data = stream::once(async { Ok(Bytes::from(data)) }).into_boxed();
+            self.inner.put(path, data, meta).await
+        }
+
+        async fn delete(&self, path: &str) -> Result<()> {
+            self.inner.delete(path).await?;
+            self.fail()
+        }
+    }
+
+    fn fast(retries: u32) -> Backoff {
+        Backoff::new(retries).initial(Duration::from_millis(1))
+    }
+
+    fn body() -> Stream {
+        stream::iter([Ok(Bytes::from("he")), Ok(Bytes::from("llo"))]).into_boxed()
+    }
+
+    #[tokio::test]
+    async fn retries_transient_failures() {
+        let fs =
+            Filesystem::new(Flaky::new(MemoryFs::new().with_file("a.md", b"a"), 2)).reads(fast(2));
+
+        let (stream, _) = fs.get("a.md").await.unwrap();
+        assert_eq!(read_stream(stream).await, b"a");
+
+        // Out of retries
+        fs.inner.failures.store(3, Ordering::Relaxed);
+        assert!(matches!(fs.meta("a.md").await, Err(Error::Transient(_))));
+
+        // Other failures aren't retried
+        fs.inner.failures.store(0, Ordering::Relaxed);
+        assert!(matches!(fs.get("b.md").await, Err(Error::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn resends_buffered_uploads() {
+        let fs = Filesystem::new(Flaky::new(MemoryFs::new(), 1)).writes(fast(1));
+
+        let meta = fs
+            .put("a.md", body(), IncomingFileMeta::default())
+            .await
+            .unwrap();
+        assert_eq!(meta.size, 5);
+
+        // Larger uploads are sent once, in full
+        let fs = fs.buffer_limit(2);
+        fs.inner.failures.store(1, Ordering::Relaxed);
+        assert!(
+            fs.put("b.md", body(), IncomingFileMeta::default())
+                .await
+                .is_err()
+        );
+
+        let meta = fs
+            .put("b.md", body(), IncomingFileMeta::default())
+            .await
+            .unwrap();
+        assert_eq!(meta.size, 5);
+    }
+
+    #[tokio::test]
+    async fn deletes_once() {
+        let fs = Filesystem::new(Flaky::new(MemoryFs::new().with_file("a.md", b"a"), 1))
+            .operation(Operation::Delete, fast(1));
+
+        // The retry finds the file deleted by the failed attempt
+        fs.delete("a.md").await.unwrap();
+        assert!(matches!(fs.delete("a.md").await, Err(Error::NotFound(_))));
+    }
+
+    #[test]
+    fn backs_off_exponentially() {
+        let backoff = Backoff::new(5)
+            .initial(Duration::from_millis(100))
+            .max(Duration::from_millis(300));
+
+        for _ in 0..100 {
+            assert!(backoff.delay(0) <= Duration::from_millis(100));
+            assert!(backoff.delay(1) <= Duration::from_millis(200));
+            assert!(backoff.delay(4) <= Duration::from_millis(300));
+        }
+    }
+}
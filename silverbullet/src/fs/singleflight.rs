@@ -0,0 +1,349 @@
+//! Coalescing of concurrent reads
+//!
+//! When many clients reload the same page at once, e.g. after a deploy, a remote store
+//! would serve the same file once per client. [`Filesystem`] wraps a filesystem so
+//! concurrent `get` and `meta` calls for a path share a single backend call: the first
+//! caller makes it and the others wait for its result.
+//!
+//! Shared bodies are read into memory, so files larger than
+//! [`Filesystem::buffer_limit`] are streamed to the first caller only and the others
+//! read them on their own, as they do when the first caller goes away. Reads starting
+//! after a write to the path don't join the reads started before it.
+//!
+//! ```ignore
+//! let fs = singleflight::Filesystem::new(opendal::Filesystem::new(operator));
+//! ```
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::channel::oneshot;
+use futures::{TryStreamExt as _, stream};
+
+use crate::fs::*;
+
+/// Size of the bodies shared between callers, 8 MiB by default
+pub const DEFAULT_BUFFER_LIMIT: u64 = 8 * 1024 * 1024;
+
+pub struct Filesystem<F> {
+    inner: F,
+    gets: Flights<(Bytes, FileMeta)>,
+    metas: Flights<FileMeta>,
+    buffer_limit: u64,
+}
+
+impl<F> Filesystem<F> {
+    pub fn new(inner: F) -> Self {
+        Self {
+            inner,
+            gets: Flights::default(),
+            metas: Flights::default(),
+            buffer_limit: DEFAULT_BUFFER_LIMIT,
+        }
+    }
+
+    /// Largest file shared between callers, [`DEFAULT_BUFFER_LIMIT`] by default.
+    #[must_use]
+    pub fn buffer_limit(mut self, bytes: u64) -> Self {
+        self.buffer_limit = bytes;
+        self
+    }
+
+    /// Start new calls for reads of `path` from now on.
+    fn forget(&self, path: &str) {
+        self.gets.forget(path);
+        self.metas.forget(path);
+    }
+}
+
+/// [`FsLayer`](crate::fs::stack::FsLayer) wrapping filesystems in [`Filesystem`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SingleflightLayer;
+
+impl<F> crate::fs::stack::FsLayer<F> for SingleflightLayer {
+    type Output = Filesystem<F>;
+
+    fn layer(&self, inner: F) -> Self::Output {
+        Filesystem::new(inner)
+    }
+}
+
+/// Calls in flight by path, with the callers waiting for them
+struct Flights<T> {
+    calls: Mutex<HashMap<String, Flight<T>>>,
+    next: AtomicU64,
+}
+
+struct Flight<T> {
+    id: u64,
+    waiters: Vec<oneshot::Sender<Result<T>>>,
+}
+
+enum Join<'a, T> {
+    /// Make the call, and hand its result to the waiters
+    Leader(Leader<'a, T>),
+    /// Wait for the result of the leader, which is gone when the receiver is canceled
+    Waiter(oneshot::Receiver<Result<T>>),
+}
+
+/// Ends a flight when finished or dropped, the waiters of a dropped one are canceled
+struct Leader<'a, T> {
+    flights: &'a Flights<T>,
+    path: String,
+    id: u64,
+}
+
+impl<T> Default for Flights<T> {
+    fn default() -> Self {
+        Self {
+            calls: Mutex::new(HashMap::new()),
+            next: AtomicU64::new(0),
+        }
+    }
+}
+
+impl<T> Flights<T> {
+    fn join(&self, path: &str) -> Join<'_, T> {
+        let mut calls = self.calls.lock().unwrap();
+
+        if let Some(flight) = calls.get_mut(path) {
+            let (sender, receiver) = oneshot::channel();
+            flight.waiters.push(sender);
+
+            return Join::Waiter(receiver);
+        }
+
+        let id = self.next.fetch_add(1, Ordering::Relaxed);
+        calls.insert(
+            path.to_string(),
+            Flight {
+                id,
+                waiters: Vec::new(),
+            },
+        );
+
+        Join::Leader(Leader {
+            flights: self,
+            path: path.to_string(),
+            id,
+        })
+    }
+
+    fn forget(&self, path: &str) {
+        self.calls.lock().unwrap().remove(path);
+    }
+
+    /// Remove the flight unless a write already replaced it.
+    fn land(&self, path: &str, id: u64) -> Vec<oneshot::Sender<Result<T>>> {
+        let mut calls = self.calls.lock().unwrap();
+
+        match calls.get(path) {
+            Some(flight) if flight.id == id => calls
+                .remove(path)
+                .map(|flight| flight.waiters)
+                .unwrap_or_default(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl<T: Clone> Leader<'_, T> {
+    fn finish(self, result: &Result<T>) {
+        for waiter in self.flights.land(&self.path, self.id) {
+            let _ = waiter.send(match result {
+                Ok(value) => Ok(value.clone()),
+                Err(err) => Err(duplicate(err)),
+            });
+        }
+    }
+}
+
+impl<T> Drop for Leader<'_, T> {
+    fn drop(&mut self) {
+        self.flights.land(&self.path, self.id);
+    }
+}
+
+/// Copy of an error for another caller, with its kind and message.
+fn duplicate(err: &Error) -> Error {
+    let message = err.to_string();
+
+    match err {
+        Error::NotFound(_) => Error::NotFound(message.into()),
+        Error::Io(io) => Error::Io(std::io::Error::new(io.kind(), message)),
+        Error::PermissionDenied(_) => Error::PermissionDenied(message.into()),
+        Error::PreconditionFailed(_) => Error::PreconditionFailed(message.into()),
+        Error::Transient(_) => Error::Transient(message.into()),
+        Error::Other(_) => Error::Other(message.into()),
+    }
+}
+
+fn once(data: Bytes) -> Stream {
+    stream::once(async move { Ok(data) }).into_boxed()
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl<F> ReadOnlyFilesystem for Filesystem<F>
+where
+    F: ReadOnlyFilesystem,
+{
+    async fn list(&self) -> Result<Vec<FileMeta>> {
+        self.inner.list().await
+    }
+
+    async fn get(&self, path: &str) -> Result<(Stream, FileMeta)> {
+        let leader = match self.gets.join(path) {
+            Join::Leader(leader) => leader,
+            Join::Waiter(receiver) => {
+                return match receiver.await {
+                    Ok(result) => result.map(|(data, meta)| (once(data), meta)),
+                    Err(oneshot::Canceled) => self.inner.get(path).await,
+                };
+            }
+        };
+
+        let (stream, meta) = match self.inner.get(path).await {
+            // Too large to share, the waiters read it themselves when the leader drops
+            Ok((stream, meta)) if meta.size > self.buffer_limit => return Ok((stream, meta)),
+            Ok(file) => file,
+            Err(err) => {
+                leader.finish(&Err(duplicate(&err)));
+                return Err(err);
+            }
+        };
+
+        let result = stream
+            .map_ok(|chunk| chunk.to_vec())
+            .try_concat()
+            .await
+            .map(|data| (Bytes::from(data), meta))
+            .map_err(Error::from);
+        leader.finish(&result);
+
+        result.map(|(data, meta)| (once(data), meta))
+    }
+
+    async fn meta(&self, path: &str) -> Result<FileMeta> {
+        match self.metas.join(path) {
+            Join::Leader(leader) => {
+                let result = self.inner.meta(path).await;
+                leader.finish(&result);
+                result
+            }
+            Join::Waiter(receiver) => match receiver.await {
+                Ok(result) => result,
+                Err(oneshot::Canceled) => self.inner.meta(path).await,
+            },
+        }
+    }
+
+    async fn list_stream(&self) -> Result<FileStream> {
+        self.inner.list_stream().await
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl<F> WritableFilesystem for Filesystem<F>
+where
+    F: WritableFilesystem,
+{
+    async fn put(&self, path: &str, data: Stream, meta: IncomingFileMeta) -> Result<FileMeta> {
+        self.forget(path);
+        self.inner.put(path, data, meta).await
+    }
+
+    async fn delete(&self, path: &str) -> Result<()> {
+        self.forget(path);
+        self.inner.delete(path).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::testing::{MemoryFs, read_stream};
+    use std::sync::atomic::AtomicUsize;
+    use std::time::Duration;
+
+    /// Counts the backend calls, which take a while
+    struct Slow {
+        inner: MemoryFs,
+        calls: AtomicUsize,
+    }
+
+    impl Slow {
+        fn new(inner: MemoryFs) -> Self {
+            Self {
+                inner,
+                calls: AtomicUsize::new(0),
+            }
+        }
+
+        async fn call(&self) {
+            self.calls.fetch_add(1, Ordering::Relaxed);
+            futures_timer::Delay::new(Duration::from_millis(10)).await;
+        }
+    }
+
+    #[async_trait]
+    impl ReadOnlyFilesystem for Slow {
+        async fn list(&self) -> Result<Vec<FileMeta>> {
+            self.inner.list().await
+        }
+
+        async fn get(&self, path: &str) -> Result<(Stream, FileMeta)> {
+            self.call().await;
+            self.inner.get(path).await
+        }
+
+        async fn meta(&self, path: &str) -> Result<FileMeta> {
+            self.call().await;
+            self.inner.meta(path).await
+        }
+    }
+
+    async fn read(fs: &Filesystem<Slow>, path: &str) -> Result<Vec<u8>> {
+        Ok(read_stream(fs.get(path).await?.0).await)
+    }
+
+    #[tokio::test]
+    async fn shares_concurrent_reads() {
+        let fs = Filesystem::new(Slow::new(MemoryFs::new().with_file("index.md", b"home")));
+
+        let (a, b, c) = futures::join!(
+            read(&fs, "index.md"),
+            read(&fs, "index.md"),
+            read(&fs, "index.md")
+        );
+        assert_eq!(
+            (a.unwrap(), b.unwrap(), c.unwrap()),
+            (b"home".to_vec(), b"home".to_vec(), b"home".to_vec())
+        );
+        assert_eq!(fs.inner.calls.load(Ordering::Relaxed), 1);
+
+        let (a, b) = futures::join!(fs.meta("missing.md"), fs.meta("missing.md"));
+        assert!(matches!(a, Err(Error::NotFound(_))));
+        assert!(matches!(b, Err(Error::NotFound(_))));
+        assert_eq!(fs.inner.calls.load(Ordering::Relaxed), 2);
+
+        // Later reads make their own call
+        read(&fs, "index.md").await.unwrap();
+        assert_eq!(fs.inner.calls.load(Ordering::Relaxed), 3);
+    }
+
+    #[tokio::test]
+    async fn streams_large_files_separately() {
+        let fs = Filesystem::new(Slow::new(MemoryFs::new().with_file("big.md", b"large")))
+            .buffer_limit(4);
+
+        let (a, b) = futures::join!(read(&fs, "big.md"), read(&fs, "big.md"));
+        assert_eq!(a.unwrap(), b"large");
+        assert_eq!(b.unwrap(), b"large");
+        assert_eq!(fs.inner.calls.load(Ordering::Relaxed), 2);
+    }
+}
@@ -0,0 +1,266 @@
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::{TryStreamExt as _, stream};
+use rusqlite::{Connection, OptionalExtension as _, params};
+
+use super::time::now;
+use crate::fs::*;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS files (
+        name TEXT PRIMARY KEY NOT NULL,
+        content BLOB NOT NULL,
+        content_type TEXT NOT NULL,
+        created INTEGER NOT NULL,
+        last_modified INTEGER NOT NULL,
+        perm TEXT NOT NULL
+    )
+";
+
+const META_COLUMNS: &str = "name, content_type, created, last_modified, perm, length(content)";
+
+/// Space stored in a single SQLite database file
+///
+/// Queries run on Tokio's blocking thread pool. Files are read and written whole, so
+/// this suits spaces of mostly small pages.
+#[derive(Clone)]
+pub struct Filesystem {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl Filesystem {
+    /// Open the database at `path`, creating it when missing.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Self::from_connection(Connection::open(path).map_err(other)?)
+    }
+
+    /// Open a database that only lives as long as the filesystem.
+    pub fn in_memory() -> Result<Self> {
+        Self::from_connection(Connection::open_in_memory().map_err(other)?)
+    }
+
+    fn from_connection(connection: Connection) -> Result<Self> {
+        connection.execute_batch(SCHEMA).map_err(other)?;
+
+        Ok(Self {
+            connection: Arc::new(Mutex::new(connection)),
+        })
+    }
+
+    async fn run<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Connection) -> Result<T> + Send + 'static,
+    {
+        let connection = self.connection.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let connection = connection.lock().unwrap_or_else(|e| e.into_inner());
+            f(&connection)
+        })
+        .await
+        .map_err(|err| Error::Other(err.into()))?
+    }
+}
+
+fn other(err: rusqlite::Error) -> Error {
+    Error::Other(err.into())
+}
+
+fn not_found(path: &str) -> Error {
+    Error::NotFound(format!("File not found: {path}").into())
+}
+
+fn meta_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<FileMeta> {
+    Ok(FileMeta {
+        name: row.get(0)?,
+        content_type: row.get(1)?,
+        created: row.get(2)?,
+        last_modified: row.get(3)?,
+        perm: row.get(4)?,
+        size: row.get(5)?,
+        etag: None,
+        version: None,
+    })
+}
+
+fn select_meta(connection: &Connection, path: &str) -> Result<Option<FileMeta>> {
+    connection
+        .query_row(
+            &format!("SELECT {META_COLUMNS} FROM files WHERE name = ?1"),
+            [path],
+            meta_from_row,
+        )
+        .optional()
+        .map_err(other)
+}
+
+#[async_trait]
+impl ReadOnlyFilesystem for Filesystem {
+    async fn list(&self) -> Result<Vec<FileMeta>> {
+        self.run(|connection| {
+            let mut statement = connection
+                .prepare(&format!("SELECT {META_COLUMNS} FROM files ORDER BY name"))
+                .map_err(other)?;
+
+            statement
+                .query_map([], meta_from_row)
+                .and_then(Iterator::collect)
+                .map_err(other)
+        })
+        .await
+    }
+
+    async fn get(&self, path: &str) -> Result<(Stream, FileMeta)> {
+        let name = path.to_string();
+
+        let (content, meta) = self
+            .run(move |connection| {
+                let meta = select_meta(connection, &name)?.ok_or_else(|| not_found(&name))?;
+
+                let content: Vec<u8> = connection
+                    .query_row(
+                        "SELECT content FROM files WHERE name = ?1",
+                        [&name],
+                        |row| row.get(0),
+                    )
+                    .map_err(other)?;
+
+                Ok((content, meta))
+            })
+            .await?;
+
+        let stream = stream::once(std::future::ready(Ok(Bytes::from(content))));
+
+        Ok((stream.into_boxed(), meta))
+    }
+
+    async fn meta(&self, path: &str) -> Result<FileMeta> {
+        let name = path.to_string();
+
+        self.run(move |connection| select_meta(connection, &name)?.ok_or_else(|| not_found(&name)))
+            .await
+    }
+}
+
+#[async_trait]
+impl WritableFilesystem for Filesystem {
+    async fn put(&self, path: &str, data: Stream, meta: IncomingFileMeta) -> Result<FileMeta> {
+        let content: Vec<u8> = data
+            .try_fold(Vec::new(), |mut acc, chunk| async move {
+                acc.extend_from_slice(&chunk);
+                Ok(acc)
+            })
+            .await?;
+
+        let name = path.to_string();
+
+        self.run(move |connection| {
+            let now = now();
+
+            // Keep the creation time of overwritten files unless one is given
+            connection
+                .execute(
+                    "INSERT INTO files (name, content, content_type, created, last_modified, perm)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                     ON CONFLICT (name) DO UPDATE SET
+                        content = excluded.content,
+                        content_type = excluded.content_type,
+                        created = coalesce(?7, files.created),
+                        last_modified = excluded.last_modified,
+                        perm = excluded.perm",
+                    params![
+                        name,
+                        content,
+                        meta.content_type
+                            .unwrap_or_else(|| "application/octet-stream".to_string()),
+                        meta.created.unwrap_or(now),
+                        meta.last_modified.unwrap_or(now),
+                        meta.perm.unwrap_or_else(|| "rw".to_string()),
+                        meta.created,
+                    ],
+                )
+                .map_err(other)?;
+
+            select_meta(connection, &name)?.ok_or_else(|| not_found(&name))
+        })
+        .await
+    }
+
+    async fn delete(&self, path: &str) -> Result<()> {
+        let name = path.to_string();
+
+        self.run(move |connection| {
+            match connection
+                .execute("DELETE FROM files WHERE name = ?1", [&name])
+                .map_err(other)?
+            {
+                0 => Err(not_found(&name)),
+                _ => Ok(()),
+            }
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bytes_stream(data: &'static [u8]) -> Stream {
+        stream::once(std::future::ready(Ok(Bytes::from_static(data)))).into_boxed()
+    }
+
+    #[tokio::test]
+    async fn stores_files() {
+        let fs = Filesystem::in_memory().unwrap();
+
+        let meta = fs
+            .put(
+                "notes/page.md",
+                bytes_stream(b"hello"),
+                IncomingFileMeta {
+                    content_type: Some("text/markdown".to_string()),
+                    created: Some(1),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(meta.size, 5);
+        assert_eq!(meta.created, 1);
+        assert_eq!(meta.content_type, "text/markdown");
+
+        // Overwriting keeps the creation time
+        let meta = fs
+            .put(
+                "notes/page.md",
+                bytes_stream(b"hello world"),
+                IncomingFileMeta::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(meta.created, 1);
+        assert_eq!(meta.size, 11);
+
+        let (stream, _) = fs.get("notes/page.md").await.unwrap();
+        let content: Vec<Bytes> = stream.try_collect().await.unwrap();
+        assert_eq!(content.concat(), b"hello world");
+
+        assert_eq!(fs.list().await.unwrap().len(), 1);
+
+        fs.delete("notes/page.md").await.unwrap();
+        assert!(matches!(
+            fs.meta("notes/page.md").await,
+            Err(Error::NotFound(_))
+        ));
+        assert!(matches!(
+            fs.delete("notes/page.md").await,
+            Err(Error::NotFound(_))
+        ));
+    }
+}
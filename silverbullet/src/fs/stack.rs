@@ -0,0 +1,267 @@
+//! Composable filesystem wrappers
+//!
+//! An [`FsLayer`] wraps a filesystem in another, like `tower::Layer` does for services.
+//! [`stack`] composes them, the first one added being the outermost, so the wrappers of a
+//! space read top to bottom and can be switched on from config with
+//! [`Stack::option_layer`]:
+//!
+//! ```ignore
+//! let space = fs::stack()
+//!     .option_layer(config.space.case_insensitive.then_some(CaseInsensitiveLayer))
+//!     .normalize_names()
+//!     .layer(|fs| Cached::new(fs).namespace("notes"))
+//!     .build(backend);
+//! ```
+//!
+//! Any `Fn(F) -> O` closure is a layer, so wrappers with their own settings fit in too.
+
+use async_trait::async_trait;
+
+use crate::fs::case_insensitive::CaseInsensitiveLayer;
+use crate::fs::normalized::NormalizeLayer;
+use crate::fs::*;
+
+/// Wraps a filesystem in another
+pub trait FsLayer<F> {
+    type Output;
+
+    fn layer(&self, inner: F) -> Self::Output;
+}
+
+impl<F, O, T> FsLayer<F> for T
+where
+    T: Fn(F) -> O,
+{
+    type Output = O;
+
+    fn layer(&self, inner: F) -> O {
+        self(inner)
+    }
+}
+
+/// Layer leaving the filesystem as is
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Identity;
+
+/// Stack of wrappers, see [`stack`]
+#[derive(Debug, Clone)]
+pub struct Stack<L> {
+    layer: L,
+}
+
+/// Two layers, `outer` wrapping the filesystem wrapped by `inner`
+#[derive(Debug, Clone)]
+pub struct Pair<Inner, Outer> {
+    inner: Inner,
+    outer: Outer,
+}
+
+/// Layer applying an optional layer
+#[derive(Debug, Clone)]
+pub struct OptionLayer<L>(Option<L>);
+
+/// Filesystem that is one of two types, e.g. wrapped or not
+pub enum Either<A, B> {
+    A(A),
+    B(B),
+}
+
+/// Empty stack of wrappers.
+pub fn stack() -> Stack<Identity> {
+    Stack { layer: Identity }
+}
+
+impl<L> Stack<L> {
+    /// Add a layer, wrapped by the ones added before.
+    #[must_use]
+    pub fn layer<T>(self, layer: T) -> Stack<Pair<T, L>> {
+        Stack {
+            layer: Pair {
+                inner: layer,
+                outer: self.layer,
+            },
+        }
+    }
+
+    /// Add a layer when it is `Some`.
+    #[must_use]
+    pub fn option_layer<T>(self, layer: Option<T>) -> Stack<Pair<OptionLayer<T>, L>> {
+        self.layer(OptionLayer(layer))
+    }
+
+    /// Fall back to names only differing in case on reads, see [`case_insensitive`].
+    #[must_use]
+    pub fn case_insensitive(self) -> Stack<Pair<CaseInsensitiveLayer, L>> {
+        self.layer(CaseInsensitiveLayer)
+    }
+
+    /// Write and list names in Unicode NFC, see [`normalized`].
+    #[must_use]
+    pub fn normalize_names(self) -> Stack<Pair<NormalizeLayer, L>> {
+        self.layer(NormalizeLayer)
+    }
+
+    /// Narrow the permissions of files down, see [`perm`].
+    #[must_use]
+    pub fn permissions<R>(self, resolver: R) -> Stack<Pair<perm::PermissionsLayer<R>, L>> {
+        self.layer(perm::PermissionsLayer::new(resolver))
+    }
+
+    /// Share concurrent reads of a path, see [`singleflight`].
+    #[must_use]
+    pub fn singleflight(self) -> Stack<Pair<singleflight::SingleflightLayer, L>> {
+        self.layer(singleflight::SingleflightLayer)
+    }
+
+    /// Keep the files read in memory, up to `capacity` bytes, see [`cache`].
+    #[must_use]
+    pub fn cache(self, capacity: u64) -> Stack<Pair<cache::CacheLayer, L>> {
+        self.layer(cache::CacheLayer::new(capacity))
+    }
+
+    /// Retry transient failures, see [`retry`].
+    #[must_use]
+    pub fn retry(self, backoff: retry::Backoff) -> Stack<Pair<retry::RetryLayer, L>> {
+        self.layer(retry::RetryLayer::new(backoff))
+    }
+
+    /// Run the calls in tracing spans, see [`trace`].
+    #[cfg(feature = "tracing")]
+    #[must_use]
+    pub fn trace(self, name: impl Into<String>) -> Stack<Pair<trace::TraceLayer, L>> {
+        self.layer(trace::TraceLayer::new().name(name))
+    }
+
+    /// Wrap `fs` in the layers.
+    pub fn build<F>(self, fs: F) -> L::Output
+    where
+        L: FsLayer<F>,
+    {
+        self.layer.layer(fs)
+    }
+}
+
+impl<F> FsLayer<F> for Identity {
+    type Output = F;
+
+    fn layer(&self, inner: F) -> F {
+        inner
+    }
+}
+
+impl<F, Inner, Outer> FsLayer<F> for Pair<Inner, Outer>
+where
+    Inner: FsLayer<F>,
+    Outer: FsLayer<Inner::Output>,
+{
+    type Output = Outer::Output;
+
+    fn layer(&self, fs: F) -> Self::Output {
+        self.outer.layer(self.inner.layer(fs))
+    }
+}
+
+impl<F, L> FsLayer<F> for OptionLayer<L>
+where
+    L: FsLayer<F>,
+{
+    type Output = Either<L::Output, F>;
+
+    fn layer(&self, fs: F) -> Self::Output {
+        match &self.0 {
+            Some(layer) => Either::A(layer.layer(fs)),
+            None => Either::B(fs),
+        }
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl<A, B> ReadOnlyFilesystem for Either<A, B>
+where
+    A: ReadOnlyFilesystem,
+    B: ReadOnlyFilesystem,
+{
+    async fn list(&self) -> Result<Vec<FileMeta>> {
+        match self {
+            Either::A(fs) => fs.list().await,
+            Either::B(fs) => fs.list().await,
+        }
+    }
+
+    async fn get(&self, path: &str) -> Result<(Stream, FileMeta)> {
+        match self {
+            Either::A(fs) => fs.get(path).await,
+            Either::B(fs) => fs.get(path).await,
+        }
+    }
+
+    async fn meta(&self, path: &str) -> Result<FileMeta> {
+        match self {
+            Either::A(fs) => fs.meta(path).await,
+            Either::B(fs) => fs.meta(path).await,
+        }
+    }
+
+    async fn list_stream(&self) -> Result<FileStream> {
+        match self {
+            Either::A(fs) => fs.list_stream().await,
+            Either::B(fs) => fs.list_stream().await,
+        }
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl<A, B> WritableFilesystem for Either<A, B>
+where
+    A: WritableFilesystem,
+    B: WritableFilesystem,
+{
+    async fn put(&self, path: &str, data: Stream, meta: IncomingFileMeta) -> Result<FileMeta> {
+        match self {
+            Either::A(fs) => fs.put(path, data, meta).await,
+            Either::B(fs) => fs.put(path, data, meta).await,
+        }
+    }
+
+    async fn delete(&self, path: &str) -> Result<()> {
+        match self {
+            Either::A(fs) => fs.delete(path).await,
+            Either::B(fs) => fs.delete(path).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::testing::MemoryFs;
+    use crate::fs::{case_insensitive, normalized};
+
+    const DECOMPOSED: &str = "Cafe\u{301}.md";
+
+    #[tokio::test]
+    async fn stacks_layers_outermost_first() {
+        let fs: case_insensitive::Filesystem<normalized::Filesystem<MemoryFs>> = stack()
+            .case_insensitive()
+            .normalize_names()
+            .build(MemoryFs::new().with_file(DECOMPOSED, b""));
+
+        // Case folded over composed names
+        assert_eq!(fs.meta("CAF\u{c9}.md").await.unwrap().name, "Caf\u{e9}.md");
+    }
+
+    #[tokio::test]
+    async fn applies_optional_layers() {
+        let fs = stack()
+            .option_layer(Some(CaseInsensitiveLayer))
+            .option_layer(None::<NormalizeLayer>)
+            .layer(|fs| fs)
+            .build(MemoryFs::new().with_file("Index.md", b""));
+
+        assert!(matches!(fs, Either::A(_)));
+        assert_eq!(fs.meta("index.md").await.unwrap().name, "Index.md");
+        assert!(fs.meta("Cafe\u{301}.md").await.is_err());
+    }
+}
@@ -0,0 +1,132 @@
+//! Helpers for the filesystem tests, also of downstream crates with the `test-util`
+//! feature
+
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::stream;
+
+use crate::fs::*;
+
+/// A simple in-memory filesystem for testing
+#[derive(Default)]
+pub struct MemoryFs {
+    files: RwLock<HashMap<String, (Bytes, FileMeta)>>,
+}
+
+impl MemoryFs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn with_file(self, name: &str, content: &[u8]) -> Self {
+        self.files.write().unwrap().insert(
+            name.to_string(),
+            (
+                Bytes::copy_from_slice(content),
+                FileMeta {
+                    name: name.to_string(),
+                    created: 0,
+                    perm: "rw".to_string(),
+                    content_type: "text/plain".to_string(),
+                    last_modified: 0,
+                    size: content.len() as u64,
+                    etag: None,
+                    version: None,
+                },
+            ),
+        );
+        self
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl ReadOnlyFilesystem for MemoryFs {
+    async fn list(&self) -> Result<Vec<FileMeta>> {
+        Ok(self
+            .files
+            .read()
+            .unwrap()
+            .values()
+            .map(|(_, meta)| meta.clone())
+            .collect())
+    }
+
+    async fn get(&self, path: &str) -> Result<(Stream, FileMeta)> {
+        let files = self.files.read().unwrap();
+        let (data, meta) = files
+            .get(path)
+            .ok_or_else(|| Error::NotFound(path.into()))?;
+        let data = data.clone();
+        let meta = meta.clone();
+        Ok((stream::once(async move { Ok(data) }).into_boxed(), meta))
+    }
+
+    async fn meta(&self, path: &str) -> Result<FileMeta> {
+        self.files
+            .read()
+            .unwrap()
+            .get(path)
+            .map(|(_, meta)| meta.clone())
+            .ok_or_else(|| Error::NotFound(path.into()))
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl WritableFilesystem for MemoryFs {
+    async fn put(&self, path: &str, data: Stream, meta: IncomingFileMeta) -> Result<FileMeta> {
+        use futures::TryStreamExt;
+        let bytes: Vec<u8> = data
+            .try_fold(Vec::new(), |mut acc, chunk| async move {
+                acc.extend_from_slice(&chunk);
+                Ok(acc)
+            })
+            .await?;
+
+        let file_meta = FileMeta {
+            name: path.to_string(),
+            created: meta.created.unwrap_or(0),
+            perm: meta.perm.unwrap_or_else(|| "rw".to_string()),
+            content_type: meta
+                .content_type
+                .unwrap_or_else(|| "text/plain".to_string()),
+            last_modified: meta.last_modified.unwrap_or(0),
+            size: bytes.len() as u64,
+            etag: None,
+            version: None,
+        };
+
+        self.files
+            .write()
+            .unwrap()
+            .insert(path.to_string(), (Bytes::from(bytes), file_meta.clone()));
+
+        Ok(file_meta)
+    }
+
+    async fn delete(&self, path: &str) -> Result<()> {
+        self.files
+            .write()
+            .unwrap()
+            .remove(path)
+            .ok_or_else(|| Error::NotFound(path.into()))?;
+        Ok(())
+    }
+}
+
+/// Read a whole stream, panicking on errors
+pub async fn read_stream(stream: Stream) -> Vec<u8> {
+    use futures::TryStreamExt;
+    stream
+        .try_fold(Vec::new(), |mut acc, chunk| async move {
+            acc.extend_from_slice(&chunk);
+            Ok(acc)
+        })
+        .await
+        .unwrap()
+}
@@ -0,0 +1,114 @@
+//! Times of files, in milliseconds since the Unix epoch as the SilverBullet client
+//! compares them with `Date.now()`, and conversions for the stores keeping other units.
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[cfg(target_arch = "wasm32")]
+use web_time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Now, in milliseconds.
+pub fn now() -> u64 {
+    from_system_time(SystemTime::now())
+}
+
+/// Milliseconds of a time in seconds, e.g. of a file embedded in the binary.
+pub fn from_secs(secs: u64) -> u64 {
+    secs.saturating_mul(1000)
+}
+
+/// Seconds of a time in milliseconds, rounded down, e.g. for tar headers.
+pub fn as_secs(millis: u64) -> u64 {
+    millis / 1000
+}
+
+/// Milliseconds of a signed time, those before the epoch being 0.
+pub fn from_signed_millis(millis: i64) -> u64 {
+    u64::try_from(millis).unwrap_or(0)
+}
+
+/// Milliseconds of a system time, those before the epoch being 0.
+pub fn from_system_time(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// System time of a time in milliseconds.
+pub fn to_system_time(millis: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_millis(millis)
+}
+
+/// Date of a day since the Unix epoch, as year, month and day.
+///
+/// From <https://howardhinnant.github.io/date_algorithms.html>, as [`days_from_civil`].
+pub fn civil_from_days(days: i64) -> (i64, u8, u8) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u8;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u8;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    (year, month, day)
+}
+
+/// Days since the Unix epoch of a date, before it being negative.
+pub fn days_from_civil(year: i64, month: u8, day: u8) -> i64 {
+    let year = year - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let month = i64::from(month);
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + i64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+
+    era * 146_097 + doe - 719_468
+}
+
+/// Days since the Unix epoch of a `YYYY-MM-DD` date, `None` if it isn't a valid one.
+pub fn parse_date(date: &str) -> Option<i64> {
+    let mut parts = date.splitn(3, '-');
+    let (year, month, day) = (parts.next()?, parts.next()?, parts.next()?);
+
+    if year.len() != 4 || month.len() != 2 || day.len() != 2 {
+        return None;
+    }
+
+    let (year, month, day): (i64, u8, u8) =
+        (year.parse().ok()?, month.parse().ok()?, day.parse().ok()?);
+    let days = days_from_civil(year, month, day);
+
+    // Rejects e.g. the 31st of June, which would be the 1st of July
+    (civil_from_days(days) == (year, month, day)).then_some(days)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_to_milliseconds() {
+        assert_eq!(from_secs(1_700_000_000), 1_700_000_000_000);
+        assert_eq!(as_secs(1_700_000_000_999), 1_700_000_000);
+        assert_eq!(from_signed_millis(-1), 0);
+        assert_eq!(from_system_time(UNIX_EPOCH - Duration::from_secs(1)), 0);
+        assert_eq!(
+            from_system_time(to_system_time(1_700_000_000_123)),
+            1_700_000_000_123
+        );
+
+        // Past 2001-09-09 in milliseconds, which it would take seconds until year 5138
+        assert!(now() > 1_000_000_000_000);
+    }
+
+    #[test]
+    fn parses_dates() {
+        assert_eq!(parse_date("1970-01-02"), Some(1));
+        assert_eq!(parse_date("2024-06-31"), None);
+        assert_eq!(parse_date("2024-6-1"), None);
+        assert_eq!(parse_date("Launch"), None);
+    }
+}
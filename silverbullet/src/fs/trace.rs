@@ -0,0 +1,234 @@
+//! Tracing spans for filesystem calls
+//!
+//! [`Filesystem`] wraps a filesystem so every call runs in an `fs` span with the
+//! operation, path and backend name, and records its latency and the bytes read or
+//! written, so slow backend calls show up in traces below the request spans. Failures
+//! other than missing files are logged as warning events in the span.
+//!
+//! A read's latency is the time to open the file, its bytes the size of the file: the
+//! body is streamed after the call returns.
+//!
+//! ```ignore
+//! let fs = trace::Filesystem::new(opendal::Filesystem::new(operator)).name("s3");
+//! ```
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use async_trait::async_trait;
+use futures::TryStreamExt as _;
+use tracing::{Instrument as _, Span, field};
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+
+#[cfg(target_arch = "wasm32")]
+use web_time::Instant;
+
+use crate::fs::*;
+
+pub struct Filesystem<F> {
+    inner: F,
+    name: String,
+}
+
+impl<F> Filesystem<F> {
+    pub fn new(inner: F) -> Self {
+        Self {
+            inner,
+            name: "fs".to_string(),
+        }
+    }
+
+    /// Name of the backend in the spans, `fs` by default.
+    #[must_use]
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    fn span(&self, op: &'static str, path: &str) -> Span {
+        tracing::info_span!(
+            "fs",
+            op,
+            backend = %self.name,
+            path,
+            bytes = field::Empty,
+            latency_ms = field::Empty,
+        )
+    }
+}
+
+/// [`FsLayer`](crate::fs::stack::FsLayer) wrapping filesystems in [`Filesystem`]
+#[derive(Debug, Clone, Default)]
+pub struct TraceLayer {
+    name: Option<String>,
+}
+
+impl TraceLayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Name of the backend in the spans, `fs` by default.
+    #[must_use]
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+}
+
+impl<F> crate::fs::stack::FsLayer<F> for TraceLayer {
+    type Output = Filesystem<F>;
+
+    fn layer(&self, inner: F) -> Self::Output {
+        let fs = Filesystem::new(inner);
+
+        match &self.name {
+            Some(name) => fs.name(name.clone()),
+            None => fs,
+        }
+    }
+}
+
+/// Record the latency of a call in the current span, and log its failure.
+fn record<T>(start: Instant, result: Result<T>) -> Result<T> {
+    let span = Span::current();
+    span.record("latency_ms", start.elapsed().as_secs_f64() * 1000.0);
+
+    match &result {
+        Err(Error::NotFound(_)) | Ok(_) => {}
+        Err(err) => tracing::warn!(error = %err, "Filesystem call failed"),
+    }
+
+    result
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl<F> ReadOnlyFilesystem for Filesystem<F>
+where
+    F: ReadOnlyFilesystem,
+{
+    async fn list(&self) -> Result<Vec<FileMeta>> {
+        async {
+            let start = Instant::now();
+            let files = record(start, self.inner.list().await)?;
+
+            Span::current().record("files", files.len());
+
+            Ok(files)
+        }
+        .instrument(tracing::info_span!(
+            "fs",
+            op = "list",
+            backend = %self.name,
+            files = field::Empty,
+            latency_ms = field::Empty,
+        ))
+        .await
+    }
+
+    async fn get(&self, path: &str) -> Result<(Stream, FileMeta)> {
+        async {
+            let start = Instant::now();
+            let (stream, meta) = record(start, self.inner.get(path).await)?;
+
+            Span::current().record("bytes", meta.size);
+
+            Ok((stream, meta))
+        }
+        .instrument(self.span("get", path))
+        .await
+    }
+
+    async fn meta(&self, path: &str) -> Result<FileMeta> {
+        async {
+            let start = Instant::now();
+            record(start, self.inner.meta(path).await)
+        }
+        .instrument(self.span("meta", path))
+        .await
+    }
+
+    /// The latency is the time to open the listing, the files are streamed after it.
+    async fn list_stream(&self) -> Result<FileStream> {
+        async {
+            let start = Instant::now();
+            record(start, self.inner.list_stream().await)
+        }
+        .instrument(tracing::info_span!(
+            "fs",
+            op = "list_stream",
+            backend = %self.name,
+            latency_ms = field::Empty,
+        ))
+        .await
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl<F> WritableFilesystem for Filesystem<F>
+where
+    F: WritableFilesystem,
+{
+    async fn put(&self, path: &str, data: Stream, meta: IncomingFileMeta) -> Result<FileMeta> {
+        let bytes = Arc::new(AtomicU64::new(0));
+        let counted = {
+            let bytes = bytes.clone();
+
+            data.inspect_ok(move |chunk| {
+                bytes.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+            })
+            .into_boxed()
+        };
+
+        async {
+            let start = Instant::now();
+            let result = self.inner.put(path, counted, meta).await;
+
+            Span::current().record("bytes", bytes.load(Ordering::Relaxed));
+
+            record(start, result)
+        }
+        .instrument(self.span("put", path))
+        .await
+    }
+
+    async fn delete(&self, path: &str) -> Result<()> {
+        async {
+            let start = Instant::now();
+            record(start, self.inner.delete(path).await)
+        }
+        .instrument(self.span("delete", path))
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::testing::{MemoryFs, read_stream};
+    use bytes::Bytes;
+    use futures::stream;
+
+    #[tokio::test]
+    async fn passes_calls_through() {
+        let fs = Filesystem::new(MemoryFs::new()).name("memory");
+
+        let data = stream::iter([Ok(Bytes::from("he")), Ok(Bytes::from("llo"))]).into_boxed();
+        let meta = fs
+            .put("a.md", data, IncomingFileMeta::default())
+            .await
+            .unwrap();
+        assert_eq!(meta.size, 5);
+
+        let (stream, _) = fs.get("a.md").await.unwrap();
+        assert_eq!(read_stream(stream).await, b"hello");
+        assert_eq!(fs.list().await.unwrap().len(), 1);
+
+        fs.delete("a.md").await.unwrap();
+        assert!(matches!(fs.meta("a.md").await, Err(Error::NotFound(_))));
+    }
+}
@@ -0,0 +1,72 @@
+use crate::fs::*;
+
+/// Open the filesystem a storage URI points to.
+///
+/// | URI | Backend |
+/// |-----|---------|
+/// | `file:///path/to/space` (or `fs://`) | Local directory through opendal, needs `services-fs` |
+/// | `s3://bucket/prefix` | S3 through opendal, needs `services-s3` |
+/// | `memory://` | In-memory through opendal, needs `services-memory` |
+/// | `sqlite://space.db`, `sqlite:///path/to/space.db` | [`sqlite::Filesystem`](crate::fs::sqlite::Filesystem) |
+///
+/// Query parameters are passed to opendal as service options, eg.
+/// `s3://bucket/prefix?region=eu-west-1&endpoint=https://s3.example.com`. S3 credentials
+/// are read from the usual `AWS_*` environment variables.
+pub fn from_uri(uri: &str) -> Result<Box<dyn ReadWriteFilesystem>> {
+    let (scheme, rest) = uri
+        .split_once("://")
+        .ok_or_else(|| unsupported(format!("Not a storage URI: {uri}")))?;
+
+    match scheme.to_ascii_lowercase().as_str() {
+        #[cfg(feature = "opendal")]
+        "file" | "fs" => {
+            let root = rest.split_once('?').map_or(rest, |(path, _)| path);
+
+            if root.is_empty() {
+                return Err(unsupported(format!("Missing path in {uri}")));
+            }
+
+            let operator = ::opendal::Operator::via_iter(
+                ::opendal::services::FS_SCHEME,
+                [("root".to_string(), root.to_string())],
+            )?;
+
+            Ok(Box::new(opendal::Filesystem::new(operator)))
+        }
+        #[cfg(feature = "sqlite")]
+        "sqlite" => {
+            let path = rest.split_once('?').map_or(rest, |(path, _)| path);
+
+            if path.is_empty() {
+                return Err(unsupported(format!("Missing database path in {uri}")));
+            }
+
+            Ok(Box::new(sqlite::Filesystem::open(path)?))
+        }
+        #[cfg(feature = "opendal")]
+        _ => Ok(Box::new(opendal::Filesystem::new(
+            ::opendal::Operator::from_uri(uri)?,
+        ))),
+        #[cfg(not(feature = "opendal"))]
+        _ => Err(unsupported(format!("Unsupported storage URI: {uri}"))),
+    }
+}
+
+fn unsupported(message: String) -> Error {
+    Error::Other(message.into())
+}
+
+#[cfg(all(test, feature = "opendal"))]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn opens_backends() {
+        let fs = from_uri("memory://").unwrap();
+        assert!(fs.list().await.unwrap().is_empty());
+
+        assert!(from_uri("/no/scheme").is_err());
+        assert!(from_uri("file://").is_err());
+        assert!(from_uri("unknown://x").is_err());
+    }
+}
@@ -0,0 +1,183 @@
+//! Prefetching of the files read first
+//!
+//! A freshly started container or a cold Worker serves its first requests from the
+//! backend. [`Warmer`] reads a list of files through the space ahead of them, e.g. the
+//! index page, `PLUGS.md` and the templates, so a cache shared by the requests, like
+//! [`cache`](crate::fs::cache) or the Cache API of a Worker, holds them when the client
+//! asks. Without one, warming only opens the backend connections. Run it once at startup
+//! with [`Warmer::warm`], or periodically with [`Warmer::run`].
+//!
+//! ```ignore
+//! let warmer = Warmer::new(space.clone())
+//!     .path("index.md")
+//!     .path("PLUGS.md")
+//!     .path("Library/Templates/**");
+//! ```
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::TryStreamExt as _;
+
+use crate::fs::{self, ReadOnlyFilesystem};
+use crate::glob;
+
+/// Files read by a [`Warmer::warm`] run
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Warmed {
+    pub files: usize,
+    pub bytes: u64,
+}
+
+#[derive(Clone)]
+pub struct Warmer {
+    fs: Arc<dyn ReadOnlyFilesystem>,
+    patterns: Vec<String>,
+}
+
+impl Warmer {
+    /// Warm nothing until paths are added.
+    pub fn new(fs: Arc<dyn ReadOnlyFilesystem>) -> Self {
+        Self {
+            fs,
+            patterns: Vec::new(),
+        }
+    }
+
+    /// Read a file, or the files matching a glob, e.g. `Library/Templates/**`.
+    #[must_use]
+    pub fn path(mut self, pattern: impl Into<String>) -> Self {
+        self.patterns.push(pattern.into());
+        self
+    }
+
+    /// Add several paths, see [`Warmer::path`].
+    #[must_use]
+    pub fn paths<I>(self, patterns: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        patterns.into_iter().fold(self, Self::path)
+    }
+
+    /// Files to read, listing the space only when a pattern needs it.
+    async fn files(&self) -> fs::Result<Vec<String>> {
+        let (globs, mut names): (Vec<_>, Vec<_>) = self
+            .patterns
+            .iter()
+            .cloned()
+            .partition(|pattern| pattern.contains('*'));
+
+        if !globs.is_empty() {
+            for file in self.fs.list().await? {
+                if globs
+                    .iter()
+                    .any(|pattern| glob::matches(pattern, &file.name))
+                {
+                    names.push(file.name);
+                }
+            }
+        }
+
+        names.sort();
+        names.dedup();
+
+        Ok(names)
+    }
+
+    /// Read every file once.
+    ///
+    /// Missing files are skipped, and the failures of a file are logged with the
+    /// `tracing` feature without stopping the others. Only a failed listing is returned.
+    pub async fn warm(&self) -> fs::Result<Warmed> {
+        let mut warmed = Warmed::default();
+
+        for name in self.files().await? {
+            let read = async {
+                let (stream, _) = self.fs.get(&name).await?;
+
+                stream
+                    .try_fold(
+                        0,
+                        |bytes, chunk| async move { Ok(bytes + chunk.len() as u64) },
+                    )
+                    .await
+                    .map_err(fs::Error::from)
+            };
+
+            match read.await {
+                Ok(bytes) => {
+                    warmed.files += 1;
+                    warmed.bytes += bytes;
+                }
+                Err(fs::Error::NotFound(_)) => {}
+                #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+                Err(err) => {
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!(file = name, error = %err, "Failed to warm file");
+                }
+            }
+        }
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!(files = warmed.files, bytes = warmed.bytes, "Warmed space");
+
+        Ok(warmed)
+    }
+
+    /// Warm now and then every `interval`, forever.
+    ///
+    /// Failures are logged with the `tracing` feature and retried at the next interval.
+    pub async fn run(self, interval: Duration) {
+        loop {
+            #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+            if let Err(err) = self.warm().await {
+                #[cfg(feature = "tracing")]
+                tracing::error!(error = %err, "Failed to warm space");
+            }
+
+            futures_timer::Delay::new(interval).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::testing::MemoryFs;
+
+    #[tokio::test]
+    async fn reads_paths_and_globs() {
+        let fs = MemoryFs::new()
+            .with_file("index.md", b"home")
+            .with_file("PLUGS.md", b"")
+            .with_file("Library/Templates/Page.md", b"page")
+            .with_file("Library/Templates/Meeting/Notes.md", b"notes")
+            .with_file("Journal/2026-10-14.md", b"today");
+
+        let warmer = Warmer::new(Arc::new(fs)).paths([
+            "index.md",
+            "missing.md",
+            "Library/Templates/**",
+            "Library/Templates/Page.md",
+        ]);
+
+        assert_eq!(
+            warmer.files().await.unwrap(),
+            [
+                "Library/Templates/Meeting/Notes.md",
+                "Library/Templates/Page.md",
+                "index.md",
+                "missing.md",
+            ]
+        );
+        assert_eq!(
+            warmer.warm().await.unwrap(),
+            Warmed {
+                files: 3,
+                bytes: 13
+            }
+        );
+    }
+}
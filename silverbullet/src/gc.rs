@@ -0,0 +1,452 @@
+//! Garbage collection of the attachments no page references
+//!
+//! [`Collector::collect`] reads every page of the space for the files it references and
+//! reports the attachments, the files that aren't pages, referenced by none of them. It
+//! only reports them in a [`Mode::DryRun`], moves them to the trash folder of the space
+//! with [`Mode::Trash`], or deletes them with [`Mode::Delete`].
+//!
+//! References are read generously, as keeping an unreferenced file is cheaper than losing
+//! a referenced one: wiki links, `[[report.pdf]]` and `![[diagram.png]]`, Markdown links,
+//! `[report](report.pdf)` and `![diagram](diagram.png)`, and HTML `src` and `href`
+//! attributes, in fenced code too, relative to the folder of the page and to the root of
+//! the space, whatever their case. Files in hidden folders, e.g. the trash, those matching
+//! [`Collector::exclude`] patterns and those changed within [`Collector::min_age`], e.g.
+//! uploaded for a page not saved yet, are never collected.
+//!
+//! ```ignore
+//! let collector = Collector::new(space.clone())
+//!     .exclude("Library/**")
+//!     .mode(Mode::Trash);
+//!
+//! let report = collector.collect(Mode::DryRun).await?;
+//! ```
+
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::TryStreamExt as _;
+use serde::Serialize;
+
+use crate::fs::{self, FileMeta, IncomingFileMeta, ReadWriteFilesystem, time};
+use crate::glob;
+
+/// What a collection does with the unreferenced attachments
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "kebab-case")]
+pub enum Mode {
+    /// Only report them
+    #[default]
+    DryRun,
+    /// Move them to the trash folder
+    Trash,
+    Delete,
+}
+
+impl FromStr for Mode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "dry-run" => Ok(Mode::DryRun),
+            "trash" => Ok(Mode::Trash),
+            "delete" => Ok(Mode::Delete),
+            other => Err(format!(
+                "unsupported gc mode: {other}, expected dry-run, trash or delete"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Mode::DryRun => "dry-run",
+            Mode::Trash => "trash",
+            Mode::Delete => "delete",
+        })
+    }
+}
+
+/// Attachment no page references
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct Orphan {
+    pub name: String,
+    pub size: u64,
+    /// Time it was last changed, in milliseconds since the epoch
+    pub last_modified: u64,
+}
+
+/// Outcome of a collection
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct Report {
+    pub mode: Mode,
+    /// Pages read for references
+    pub pages: usize,
+    /// Attachments that could be collected, referenced or not
+    pub attachments: usize,
+    /// Unreferenced attachments, in name order, trashed or deleted unless in a dry run
+    pub orphans: Vec<Orphan>,
+    /// Total size of the orphans in bytes
+    pub size: u64,
+}
+
+/// Collects the unreferenced attachments of a space
+#[derive(Clone)]
+pub struct Collector {
+    fs: Arc<dyn ReadWriteFilesystem>,
+    exclude: Vec<String>,
+    trash: String,
+    min_age: Duration,
+    mode: Mode,
+}
+
+impl Collector {
+    pub fn new(fs: Arc<dyn ReadWriteFilesystem>) -> Self {
+        Self {
+            fs,
+            exclude: Vec::new(),
+            trash: ".trash".to_string(),
+            min_age: Duration::from_secs(24 * 60 * 60),
+            mode: Mode::DryRun,
+        }
+    }
+
+    /// Never collect the files matching a glob, e.g. `Library/**`.
+    #[must_use]
+    pub fn exclude(mut self, pattern: impl Into<String>) -> Self {
+        self.exclude.push(pattern.into());
+        self
+    }
+
+    /// Folder the trashed attachments are moved to, keeping their paths (`.trash` by
+    /// default).
+    #[must_use]
+    pub fn trash(mut self, folder: impl Into<String>) -> Self {
+        self.trash = folder.into().trim_matches('/').to_string();
+        self
+    }
+
+    /// Leave the attachments changed more recently alone (a day by default).
+    #[must_use]
+    pub fn min_age(mut self, age: Duration) -> Self {
+        self.min_age = age;
+        self
+    }
+
+    /// Mode of the scheduled collections, see [`Task`](crate::scheduler::Task) (a dry run
+    /// by default).
+    #[must_use]
+    pub fn mode(mut self, mode: Mode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Mode of the scheduled collections.
+    pub fn scheduled_mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Find the unreferenced attachments, and trash or delete them unless in a dry run.
+    pub async fn collect(&self, mode: Mode) -> fs::Result<Report> {
+        let files = self.fs.list().await?;
+        let cutoff = time::now().saturating_sub(self.min_age.as_millis() as u64);
+
+        let mut referenced = HashSet::new();
+        let mut pages = 0;
+        for page in files.iter().filter(|meta| meta.name.ends_with(".md")) {
+            let content = read(self.fs.as_ref(), &page.name).await?;
+            referenced.extend(references(&page.name, &content));
+            pages += 1;
+        }
+
+        let attachments: Vec<&FileMeta> = files
+            .iter()
+            .filter(|meta| !meta.name.ends_with(".md") && self.collectable(&meta.name))
+            .collect();
+        let mut orphans: Vec<Orphan> = attachments
+            .iter()
+            .filter(|meta| meta.last_modified <= cutoff)
+            .filter(|meta| !referenced.contains(&meta.name.to_lowercase()))
+            .map(|meta| Orphan {
+                name: meta.name.clone(),
+                size: meta.size,
+                last_modified: meta.last_modified,
+            })
+            .collect();
+        orphans.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+
+        for orphan in &orphans {
+            match mode {
+                Mode::DryRun => {}
+                Mode::Trash => self.move_to_trash(&orphan.name).await?,
+                Mode::Delete => match self.fs.delete(&orphan.name).await {
+                    Ok(()) | Err(fs::Error::NotFound(_)) => {}
+                    Err(err) => return Err(err),
+                },
+            }
+        }
+
+        let report = Report {
+            mode,
+            pages,
+            attachments: attachments.len(),
+            size: orphans.iter().map(|orphan| orphan.size).sum(),
+            orphans,
+        };
+
+        #[cfg(feature = "tracing")]
+        tracing::info!(
+            mode = %report.mode,
+            pages = report.pages,
+            attachments = report.attachments,
+            orphans = report.orphans.len(),
+            size = report.size,
+            "Collected attachments"
+        );
+
+        Ok(report)
+    }
+
+    /// Whether a file may be collected: outside hidden folders and the exclusions.
+    fn collectable(&self, name: &str) -> bool {
+        !name.split('/').any(|segment| segment.starts_with('.'))
+            && !name.starts_with(&format!("{}/", self.trash))
+            && !self
+                .exclude
+                .iter()
+                .any(|pattern| glob::matches(pattern, name))
+    }
+
+    async fn move_to_trash(&self, name: &str) -> fs::Result<()> {
+        let (stream, meta) = match self.fs.get(name).await {
+            Ok(file) => file,
+            // Gone since the listing
+            Err(fs::Error::NotFound(_)) => return Ok(()),
+            Err(err) => return Err(err),
+        };
+        let incoming = IncomingFileMeta {
+            created: Some(meta.created),
+            last_modified: Some(meta.last_modified),
+            content_type: Some(meta.content_type),
+            size: Some(meta.size),
+            ..Default::default()
+        };
+
+        self.fs
+            .put(&format!("{}/{name}", self.trash), stream, incoming)
+            .await?;
+        self.fs.delete(name).await
+    }
+}
+
+async fn read(fs: &dyn ReadWriteFilesystem, name: &str) -> fs::Result<String> {
+    let (stream, _) = fs.get(name).await?;
+    let bytes = stream
+        .try_fold(Vec::new(), |mut bytes, chunk| async move {
+            bytes.extend_from_slice(&chunk);
+            Ok(bytes)
+        })
+        .await
+        .map_err(fs::Error::from)?;
+
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// Paths the page `name` may reference, lowercase, both from its folder and from the
+/// root of the space.
+fn references(name: &str, content: &str) -> HashSet<String> {
+    let folder = name.rsplit_once('/').map(|(folder, _)| folder);
+    let mut paths = HashSet::new();
+    let mut add = |target: &str| {
+        let target = crate::import::decode(target.trim().trim_matches(['<', '>']));
+        if target.is_empty() || target.contains("://") || target.starts_with('#') {
+            return;
+        }
+
+        let target = target.to_lowercase();
+        match (target.strip_prefix('/'), folder) {
+            (Some(path), _) => {
+                paths.insert(path.to_string());
+            }
+            (None, Some(folder)) => {
+                paths.insert(format!("{}/{target}", folder.to_lowercase()));
+                paths.insert(target);
+            }
+            (None, None) => {
+                paths.insert(target);
+            }
+        }
+    };
+
+    let mut rest = content;
+    while let Some(at) = rest.find(['[', ']', '=']) {
+        let (token, after) = rest.split_at(at);
+        rest = &after[1..];
+
+        if after.starts_with("[[")
+            && let Some(end) = after[2..].find("]]")
+        {
+            let target = after[2..2 + end].split(['|', '#', '@']).next();
+            add(target.unwrap_or_default());
+            rest = &after[2 + end + 2..];
+        } else if let Some(inner) = after.strip_prefix("](")
+            && let Some(end) = inner.find([')', ' ', '\n'])
+        {
+            add(&inner[..end]);
+            rest = &inner[end..];
+        } else if after.starts_with('=')
+            && (token.ends_with("src") || token.ends_with("href"))
+            && let Some(quote) = after[1..]
+                .chars()
+                .next()
+                .filter(|c| matches!(c, '"' | '\''))
+            && let Some(end) = after[2..].find(quote)
+        {
+            add(&after[2..2 + end]);
+            rest = &after[2 + end..];
+        }
+    }
+
+    paths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::testing::MemoryFs;
+    use crate::fs::{ReadOnlyFilesystem as _, WritableFilesystem as _};
+
+    fn space() -> Arc<MemoryFs> {
+        Arc::new(
+            MemoryFs::new()
+                .with_file(
+                    "Projects/Launch.md",
+                    b"![[Projects/chart.png]] ![photo](Team%20Photo.JPG) [spec](/Specs/spec.pdf)\n\
+                      <img src=\"logo.svg\"> [[report.pdf|the report]]",
+                )
+                .with_file("Projects/chart.png", b"png")
+                .with_file("Projects/team photo.jpg", b"jpg")
+                .with_file("Projects/logo.svg", b"svg")
+                .with_file("Specs/spec.pdf", b"pdf")
+                .with_file("report.pdf", b"pdf")
+                .with_file("old.zip", b"zip!")
+                .with_file("Library/icon.png", b"png")
+                .with_file(".silverbullet-git.json", b"{}"),
+        )
+    }
+
+    #[test]
+    fn reads_references() {
+        let paths = references(
+            "Projects/Launch",
+            "![a](diagram.png) [b](/Files/b.pdf) [c](https://example.com/c.png) [[Other page]]\n\
+             ```\n![[kept.png]]\n```\n<a href='x.zip'>x</a> [d](#anchor)",
+        );
+
+        let mut paths: Vec<_> = paths.into_iter().collect();
+        paths.sort_unstable();
+        assert_eq!(
+            paths,
+            [
+                "diagram.png",
+                "files/b.pdf",
+                "kept.png",
+                "other page",
+                "projects/diagram.png",
+                "projects/kept.png",
+                "projects/other page",
+                "projects/x.zip",
+                "x.zip",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn collects_orphans() {
+        let space = space();
+        let collector = Collector::new(space.clone()).exclude("Library/**");
+
+        let report = collector.collect(Mode::DryRun).await.unwrap();
+        assert_eq!(report.pages, 1);
+        assert_eq!(report.attachments, 6);
+        assert_eq!(
+            report
+                .orphans
+                .iter()
+                .map(|o| o.name.as_str())
+                .collect::<Vec<_>>(),
+            ["old.zip"]
+        );
+        assert_eq!(report.size, 4);
+        assert!(space.meta("old.zip").await.is_ok());
+
+        let report = collector.collect(Mode::Trash).await.unwrap();
+        assert_eq!(report.orphans.len(), 1);
+        assert!(space.meta("old.zip").await.is_err());
+        assert_eq!(space.meta(".trash/old.zip").await.unwrap().size, 4);
+
+        // The trash is never collected
+        let report = collector.collect(Mode::Delete).await.unwrap();
+        assert!(report.orphans.is_empty());
+
+        space.delete("Projects/Launch.md").await.unwrap();
+        let report = collector.collect(Mode::Delete).await.unwrap();
+        assert_eq!(report.orphans.len(), 5);
+        assert!(space.meta("report.pdf").await.is_err());
+        assert!(space.meta("Library/icon.png").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn spares_recent_attachments() {
+        let space = space();
+        let data = futures::stream::once(async { Ok(bytes::Bytes::from_static(b"new")) });
+        space
+            .put(
+                "new.zip",
+                Box::pin(data),
+                IncomingFileMeta {
+                    last_modified: Some(time::now()),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+
+        let report = Collector::new(space.clone())
+            .collect(Mode::Delete)
+            .await
+            .unwrap();
+        assert_eq!(
+            report
+                .orphans
+                .iter()
+                .map(|o| o.name.as_str())
+                .collect::<Vec<_>>(),
+            ["Library/icon.png", "old.zip"]
+        );
+        assert!(space.meta("new.zip").await.is_ok());
+
+        let report = Collector::new(space)
+            .min_age(Duration::ZERO)
+            .collect(Mode::DryRun)
+            .await
+            .unwrap();
+        assert_eq!(report.orphans.len(), 1);
+    }
+
+    #[test]
+    fn parses_modes() {
+        assert_eq!("dry-run".parse(), Ok(Mode::DryRun));
+        assert_eq!("Trash".parse(), Ok(Mode::Trash));
+        assert_eq!(Mode::Delete.to_string(), "delete");
+        assert!("purge".parse::<Mode>().is_err());
+    }
+}
@@ -0,0 +1,133 @@
+//! Minimal glob matching used for path and host patterns.
+//!
+//! Supported syntax:
+//! - `?` matches a single character other than `/`
+//! - `*` matches any sequence of characters other than `/`
+//! - `**` matches any sequence of characters, including `/`
+//!
+//! Patterns are matched in a single pass over the text, tracking every position of the
+//! pattern that can be reached so far, so no pattern takes longer than the length of the
+//! text times the length of the pattern.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Token {
+    Char(char),
+    Any,
+    Star,
+    DoubleStar,
+}
+
+pub(crate) fn matches(pattern: &str, text: &str) -> bool {
+    let tokens = tokenize(pattern);
+
+    // `states[i]` is set when the first `i` tokens can match the text read so far
+    let mut states = vec![false; tokens.len() + 1];
+    states[0] = true;
+    close(&tokens, &mut states);
+
+    for c in text.chars() {
+        let mut next = vec![false; tokens.len() + 1];
+
+        for (i, token) in tokens.iter().enumerate() {
+            if !states[i] {
+                continue;
+            }
+
+            match token {
+                Token::Char(p) if *p == c => next[i + 1] = true,
+                Token::Any if c != '/' => next[i + 1] = true,
+                Token::Star if c != '/' => next[i] = true,
+                Token::DoubleStar => next[i] = true,
+                _ => {}
+            }
+        }
+
+        if !next.contains(&true) {
+            return false;
+        }
+
+        states = next;
+        close(&tokens, &mut states);
+    }
+
+    states[tokens.len()]
+}
+
+fn tokenize(pattern: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = pattern.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        let token = match c {
+            '*' if chars.next_if_eq(&'*').is_some() => Token::DoubleStar,
+            '*' => Token::Star,
+            '?' => Token::Any,
+            c => Token::Char(c),
+        };
+
+        tokens.push(token);
+    }
+
+    tokens
+}
+
+/// Stars can match nothing, so the token after a reached star is reached too.
+fn close(tokens: &[Token], states: &mut [bool]) {
+    for (i, token) in tokens.iter().enumerate() {
+        if states[i] && matches!(token, Token::Star | Token::DoubleStar) {
+            states[i + 1] = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn literal() {
+        assert!(matches("index.md", "index.md"));
+        assert!(!matches("index.md", "Index.md"));
+        assert!(!matches("index.md", "index.md.bak"));
+    }
+
+    #[test]
+    fn single_star_stays_in_segment() {
+        assert!(matches("*.md", "index.md"));
+        assert!(!matches("*.md", "journal/2024-01-01.md"));
+        assert!(matches("journal/*.md", "journal/2024-01-01.md"));
+        assert!(matches("*.example.com", "api.example.com"));
+    }
+
+    #[test]
+    fn double_star_crosses_segments() {
+        assert!(matches("**.md", "journal/2024/01.md"));
+        assert!(matches("attachments/**", "attachments/a/b.png"));
+        assert!(!matches("attachments/**", "other/a.png"));
+        assert!(matches("**", ""));
+    }
+
+    #[test]
+    fn question_mark() {
+        assert!(matches("page?.md", "page1.md"));
+        assert!(!matches("page?.md", "page/.md"));
+        assert!(!matches("page?.md", "page.md"));
+    }
+
+    #[test]
+    fn many_stars_are_linear() {
+        let text = "a".repeat(10_000);
+
+        assert!(!matches("*a*a*a*a*a*a*a*a*b", &text));
+        assert!(!matches("**a**a**a**a**a**a**a**a**b", &text));
+        assert!(matches("*a*a*a*a*a*a*a*a*", &text));
+    }
+
+    #[test]
+    fn star_runs() {
+        assert!(matches("***.md", "a/b.md"));
+        assert!(matches("a*", "a"));
+        assert!(!matches("a*b", "a/b"));
+        assert!(matches("a**b", "a/b"));
+    }
+}
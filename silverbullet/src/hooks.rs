@@ -0,0 +1,866 @@
+//! Actions run on changes of the space
+//!
+//! A [`Hook`] runs an [`Action`] when a file matching its glob is saved or deleted: POST
+//! the [`Event`] to a webhook, run a command with the [`Shell`], or render a template into
+//! another file, e.g. to publish pages on save or notify a chat of new journal entries.
+//!
+//! [`Hooks::attach`] wraps the space in a [`Hooked`] filesystem, which reports its writes
+//! to a [`Dispatcher`] without waiting for the actions. Run the dispatcher alongside the
+//! server, it retries failed actions with a doubling delay and logs the last failure with
+//! the `tracing` feature.
+//!
+//! ```ignore
+//! let (space, dispatcher) = Hooks::new()
+//!     .hook(Hook::saved("Journal/**", Action::webhook("https://chat.example.com/notify")))
+//!     .client(Arc::new(client))
+//!     .attach(space);
+//!
+//! tokio::spawn(dispatcher.run());
+//! ```
+//!
+//! Actions write to the space behind the hooks, so the files they render don't trigger
+//! hooks in turn.
+//!
+//! Webhooks are sent with these headers, as GitHub does, so automations like n8n or
+//! GitHub Actions check them the same way:
+//!
+//! | Header | Value |
+//! |---|---|
+//! | `X-Silverbullet-Event` | `saved` or `deleted` |
+//! | `X-Silverbullet-Delivery` | Id of the delivery, the same for its retries |
+//! | `X-Silverbullet-Signature-256` | `sha256=` and the hex HMAC-SHA256 of the body, with the [`Action::signed_webhook`] secret |
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::channel::mpsc;
+use futures::{StreamExt as _, stream};
+use hmac::{Hmac, Mac as _};
+use serde::Serialize;
+use sha2::Sha256;
+use thiserror::Error;
+
+use crate::fs::time::now;
+use crate::fs::{self, FileMeta, IncomingFileMeta, ReadWriteFilesystem};
+use crate::fs::{FileStream, ReadOnlyFilesystem, WritableFilesystem};
+use crate::{glob, proxy, shell};
+
+/// Events dispatched at once
+const CONCURRENCY: usize = 8;
+
+/// Longest delay between two attempts of an action
+const MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// Deliveries of this process, for unique ids within the same millisecond
+static DELIVERIES: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("No {0} to run the hook with")]
+    Unavailable(&'static str),
+
+    #[error("Webhook {url} failed: {message}")]
+    Webhook { url: String, message: String },
+
+    #[error("Command {cmd} failed: {message}")]
+    Command { cmd: String, message: String },
+
+    #[cfg(feature = "ssr")]
+    #[error("Rendering {target} failed: {message}")]
+    Render { target: String, message: String },
+}
+
+/// Change of the space, as sent to webhooks and commands
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "camelCase")]
+pub enum Event {
+    Saved { name: String, meta: FileMeta },
+    Deleted { name: String },
+}
+
+impl Event {
+    /// File changed.
+    pub fn name(&self) -> &str {
+        match self {
+            Event::Saved { name, .. } | Event::Deleted { name } => name,
+        }
+    }
+
+    pub fn trigger(&self) -> Trigger {
+        match self {
+            Event::Saved { .. } => Trigger::Saved,
+            Event::Deleted { .. } => Trigger::Deleted,
+        }
+    }
+}
+
+/// Kind of change a hook runs on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trigger {
+    Saved,
+    Deleted,
+}
+
+/// What a hook does
+///
+/// `{name}` in the arguments of a command and in the target of a render is the file
+/// changed, without `.md` for pages.
+#[derive(Debug, Clone)]
+pub enum Action {
+    /// POST the event as JSON to a URL, signed if given a secret
+    Webhook { url: String, secret: Option<String> },
+    /// Run a command, with the event as JSON on its stdin, failing unless it exits with 0
+    Command { cmd: String, args: Vec<String> },
+    /// Render a template into a file, with the event and the `content` of the page
+    ///
+    /// Deleting the page deletes the file.
+    #[cfg(feature = "ssr")]
+    Render { template: String, target: String },
+}
+
+impl Action {
+    pub fn webhook(url: impl Into<String>) -> Self {
+        Action::Webhook {
+            url: url.into(),
+            secret: None,
+        }
+    }
+
+    /// Webhook with the HMAC-SHA256 of its body under `secret` in
+    /// `X-Silverbullet-Signature-256`.
+    pub fn signed_webhook(url: impl Into<String>, secret: impl Into<String>) -> Self {
+        Action::Webhook {
+            url: url.into(),
+            secret: Some(secret.into()),
+        }
+    }
+
+    pub fn command<I>(cmd: impl Into<String>, args: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        Action::Command {
+            cmd: cmd.into(),
+            args: args.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// Render `template` into `target`, e.g. `_public/{name}.html`.
+    #[cfg(feature = "ssr")]
+    pub fn render(template: impl Into<String>, target: impl Into<String>) -> Self {
+        Action::Render {
+            template: template.into(),
+            target: target.into(),
+        }
+    }
+}
+
+/// Action run on the changes of the files matching a glob
+#[derive(Debug, Clone)]
+pub struct Hook {
+    pub trigger: Trigger,
+    pub pattern: String,
+    pub action: Action,
+}
+
+impl Hook {
+    /// Run `action` when a file matching `pattern` is written.
+    pub fn saved(pattern: impl Into<String>, action: Action) -> Self {
+        Self {
+            trigger: Trigger::Saved,
+            pattern: pattern.into(),
+            action,
+        }
+    }
+
+    /// Run `action` when a file matching `pattern` is deleted.
+    pub fn deleted(pattern: impl Into<String>, action: Action) -> Self {
+        Self {
+            trigger: Trigger::Deleted,
+            pattern: pattern.into(),
+            action,
+        }
+    }
+
+    fn matches(&self, event: &Event) -> bool {
+        self.trigger == event.trigger() && glob::matches(&self.pattern, event.name())
+    }
+}
+
+/// Hooks of a space, and what their actions run with
+#[derive(Clone)]
+pub struct Hooks {
+    hooks: Vec<Hook>,
+    client: Option<Arc<dyn proxy::Client>>,
+    shell: Option<Arc<dyn shell::Shell>>,
+    #[cfg(feature = "ssr")]
+    renderer: Option<Arc<dyn crate::ssr::Renderer>>,
+    retries: u32,
+    retry_delay: Duration,
+}
+
+impl Default for Hooks {
+    fn default() -> Self {
+        Self {
+            hooks: Vec::new(),
+            client: None,
+            shell: None,
+            #[cfg(feature = "ssr")]
+            renderer: None,
+            retries: 3,
+            retry_delay: Duration::from_secs(1),
+        }
+    }
+}
+
+impl Hooks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn hook(mut self, hook: Hook) -> Self {
+        self.hooks.push(hook);
+        self
+    }
+
+    /// Client sending the webhooks.
+    #[must_use]
+    pub fn client(mut self, client: Arc<dyn proxy::Client>) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    /// Shell running the commands.
+    #[must_use]
+    pub fn shell(mut self, shell: Arc<dyn shell::Shell>) -> Self {
+        self.shell = Some(shell);
+        self
+    }
+
+    /// Renderer of the templates.
+    #[cfg(feature = "ssr")]
+    #[must_use]
+    pub fn renderer(mut self, renderer: Arc<dyn crate::ssr::Renderer>) -> Self {
+        self.renderer = Some(renderer);
+        self
+    }
+
+    /// Attempts after the first of a failed action (3 by default), `delay` before the
+    /// first retry, doubled for every following one up to a minute.
+    #[must_use]
+    pub fn retry(mut self, retries: u32, delay: Duration) -> Self {
+        self.retries = retries;
+        self.retry_delay = delay;
+        self
+    }
+
+    /// Wrap the space, its writes being dispatched to the hooks by the [`Dispatcher`].
+    pub fn attach(
+        self,
+        fs: Arc<dyn ReadWriteFilesystem>,
+    ) -> (Hooked<Arc<dyn ReadWriteFilesystem>>, Dispatcher) {
+        let (sender, events) = mpsc::unbounded();
+        let runner = Runner {
+            hooks: self,
+            #[cfg(feature = "ssr")]
+            fs: fs.clone(),
+        };
+        let hooked = Hooked {
+            inner: fs,
+            events: sender,
+        };
+
+        (hooked, Dispatcher { runner, events })
+    }
+}
+
+/// Space reporting its writes to the hooks
+pub struct Hooked<F> {
+    inner: F,
+    events: mpsc::UnboundedSender<Event>,
+}
+
+impl<F> Hooked<F> {
+    fn send(&self, event: Event) {
+        // Without a dispatcher running the hooks, there's nothing left to tell
+        let _ = self.events.unbounded_send(event);
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl<F> ReadOnlyFilesystem for Hooked<F>
+where
+    F: ReadOnlyFilesystem,
+{
+    async fn list(&self) -> fs::Result<Vec<FileMeta>> {
+        self.inner.list().await
+    }
+
+    async fn get(&self, path: &str) -> fs::Result<(fs::Stream, FileMeta)> {
+        self.inner.get(path).await
+    }
+
+    async fn meta(&self, path: &str) -> fs::Result<FileMeta> {
+        self.inner.meta(path).await
+    }
+
+    async fn list_stream(&self) -> fs::Result<FileStream> {
+        self.inner.list_stream().await
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl<F> WritableFilesystem for Hooked<F>
+where
+    F: WritableFilesystem,
+{
+    async fn put(
+        &self,
+        path: &str,
+        data: fs::Stream,
+        meta: IncomingFileMeta,
+    ) -> fs::Result<FileMeta> {
+        let meta = self.inner.put(path, data, meta).await?;
+
+        self.send(Event::Saved {
+            name: path.to_string(),
+            meta: meta.clone(),
+        });
+
+        Ok(meta)
+    }
+
+    async fn delete(&self, path: &str) -> fs::Result<()> {
+        self.inner.delete(path).await?;
+
+        self.send(Event::Deleted {
+            name: path.to_string(),
+        });
+
+        Ok(())
+    }
+}
+
+/// Runs the hooks of the events of a [`Hooked`] space
+pub struct Dispatcher {
+    runner: Runner,
+    events: mpsc::UnboundedReceiver<Event>,
+}
+
+impl Dispatcher {
+    /// Run the hooks of the events as they come, until the space is dropped.
+    pub async fn run(self) {
+        let Dispatcher { runner, events } = self;
+        let runner = &runner;
+
+        events
+            .for_each_concurrent(CONCURRENCY, |event| async move {
+                #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+                for err in runner.dispatch(&event).await {
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!(file = event.name(), error = %err, "Hook failed");
+                }
+            })
+            .await;
+    }
+
+    /// Run the hooks matching an event, returning the errors of those that failed every
+    /// attempt.
+    pub async fn dispatch(&self, event: &Event) -> Vec<Error> {
+        self.runner.dispatch(event).await
+    }
+}
+
+struct Runner {
+    hooks: Hooks,
+    /// Space behind the hooks, written by the renders
+    #[cfg(feature = "ssr")]
+    fs: Arc<dyn ReadWriteFilesystem>,
+}
+
+impl Runner {
+    async fn dispatch(&self, event: &Event) -> Vec<Error> {
+        let hooks = self.hooks.hooks.iter().filter(|hook| hook.matches(event));
+
+        stream::iter(hooks)
+            .filter_map(|hook| async move { self.attempt(&hook.action, event).await.err() })
+            .collect()
+            .await
+    }
+
+    /// Run an action until it succeeds or runs out of retries.
+    async fn attempt(&self, action: &Action, event: &Event) -> Result<(), Error> {
+        let delivery = format!(
+            "{:x}-{:x}",
+            now(),
+            DELIVERIES.fetch_add(1, Ordering::Relaxed)
+        );
+        let mut delay = self.hooks.retry_delay;
+        let mut attempt = 0;
+
+        loop {
+            match self.run(action, event, &delivery).await {
+                Err(err)
+                    if attempt < self.hooks.retries && !matches!(err, Error::Unavailable(_)) =>
+                {
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!(file = event.name(), error = %err, attempt, "Retrying hook");
+                }
+                result => return result,
+            }
+
+            futures_timer::Delay::new(delay).await;
+            delay = (delay * 2).min(MAX_DELAY);
+            attempt += 1;
+        }
+    }
+
+    async fn run(&self, action: &Action, event: &Event, delivery: &str) -> Result<(), Error> {
+        match action {
+            Action::Webhook { url, secret } => {
+                self.webhook(url, secret.as_deref(), event, delivery).await
+            }
+            Action::Command { cmd, args } => self.command(cmd, args, event).await,
+            #[cfg(feature = "ssr")]
+            Action::Render { template, target } => self.render(template, target, event).await,
+        }
+    }
+
+    async fn webhook(
+        &self,
+        url: &str,
+        secret: Option<&str>,
+        event: &Event,
+        delivery: &str,
+    ) -> Result<(), Error> {
+        let client = self
+            .hooks
+            .client
+            .as_ref()
+            .ok_or(Error::Unavailable("client"))?;
+        let failed = |message: String| Error::Webhook {
+            url: url.to_string(),
+            message,
+        };
+
+        let body = serde_json::to_vec(event).map_err(|err| failed(err.to_string()))?;
+        let event_name = match event.trigger() {
+            Trigger::Saved => "saved",
+            Trigger::Deleted => "deleted",
+        };
+        let mut request = http::Request::post(url)
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .header("X-Silverbullet-Event", event_name)
+            .header("X-Silverbullet-Delivery", delivery);
+
+        if let Some(secret) = secret {
+            request = request.header("X-Silverbullet-Signature-256", signature(secret, &body));
+        }
+
+        let request = request
+            .body(Bytes::from(body))
+            .map_err(|err| failed(err.to_string()))?;
+
+        let response = client
+            .send(request)
+            .await
+            .map_err(|err| failed(err.to_string()))?;
+
+        match response.status().is_success() {
+            true => Ok(()),
+            false => Err(failed(format!("answered {}", response.status()))),
+        }
+    }
+
+    async fn command(&self, cmd: &str, args: &[String], event: &Event) -> Result<(), Error> {
+        let shell = self
+            .hooks
+            .shell
+            .as_ref()
+            .ok_or(Error::Unavailable("shell"))?;
+        let failed = |message: String| Error::Command {
+            cmd: cmd.to_string(),
+            message,
+        };
+
+        let request = shell::Request {
+            cmd: cmd.to_string(),
+            args: args.iter().map(|arg| expand(arg, event)).collect(),
+            stdin: Some(serde_json::to_string(event).map_err(|err| failed(err.to_string()))?),
+        };
+
+        let response = shell
+            .exec(request)
+            .await
+            .map_err(|err| failed(err.to_string()))?;
+
+        match response.code {
+            0 => Ok(()),
+            code => Err(failed(format!(
+                "exited with {code}: {}",
+                response.stderr.trim()
+            ))),
+        }
+    }
+
+    #[cfg(feature = "ssr")]
+    async fn render(&self, template: &str, target: &str, event: &Event) -> Result<(), Error> {
+        let renderer = self
+            .hooks
+            .renderer
+            .as_ref()
+            .ok_or(Error::Unavailable("renderer"))?;
+        let target = expand(target, event);
+        let failed = |message: String| Error::Render {
+            target: target.clone(),
+            message,
+        };
+
+        if let Event::Deleted { .. } = event {
+            return match self.fs.delete(&target).await {
+                Ok(()) | Err(fs::Error::NotFound(_)) => Ok(()),
+                Err(err) => Err(failed(err.to_string())),
+            };
+        }
+
+        let content = read(&*self.fs, event.name())
+            .await
+            .map_err(|err| failed(err.to_string()))?;
+
+        let mut context = serde_json::to_value(event).map_err(|err| failed(err.to_string()))?;
+        context["content"] = serde_json::Value::String(content);
+
+        let html = renderer
+            .render(template, &context)
+            .await
+            .map_err(|err| failed(err.to_string()))?;
+
+        use crate::fs::StreamExt as _;
+
+        let meta = IncomingFileMeta {
+            size: Some(html.len() as u64),
+            ..Default::default()
+        };
+        let data = stream::once(async move { Ok(Bytes::from(html)) }).into_boxed();
+        self.fs
+            .put(&target, data, meta)
+            .await
+            .map_err(|err| failed(err.to_string()))?;
+
+        Ok(())
+    }
+}
+
+/// `sha256=` and the hex HMAC-SHA256 of `body` under `secret`.
+fn signature(secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes any key");
+    mac.update(body);
+
+    let mut signature = String::from("sha256=");
+    for byte in mac.finalize().into_bytes() {
+        signature.push_str(&format!("{byte:02x}"));
+    }
+
+    signature
+}
+
+/// Replace `{name}` with the file of the event, without `.md`.
+fn expand(text: &str, event: &Event) -> String {
+    let name = event.name();
+
+    text.replace("{name}", name.strip_suffix(".md").unwrap_or(name))
+}
+
+/// Text of a file, invalid UTF-8 replaced.
+#[cfg(feature = "ssr")]
+async fn read(fs: &dyn ReadWriteFilesystem, name: &str) -> fs::Result<String> {
+    use futures::TryStreamExt as _;
+
+    let (stream, _) = fs.get(name).await?;
+
+    let bytes = stream
+        .try_fold(Vec::new(), |mut acc, chunk| async move {
+            acc.extend_from_slice(&chunk);
+            Ok(acc)
+        })
+        .await?;
+
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use http::{Response, StatusCode};
+
+    use super::*;
+    use crate::fs::StreamExt as _;
+    use crate::fs::testing::MemoryFs;
+
+    /// Webhook receiver recording the requests, failing the first `failures`
+    #[derive(Default)]
+    struct Receiver {
+        events: Mutex<Vec<serde_json::Value>>,
+        headers: Mutex<Vec<http::HeaderMap>>,
+        failures: Mutex<usize>,
+    }
+
+    #[async_trait]
+    impl proxy::Client for Receiver {
+        async fn send(&self, request: http::Request<Bytes>) -> proxy::Result<Response<Bytes>> {
+            let mut failures = self.failures.lock().unwrap();
+            let status = match *failures {
+                0 => StatusCode::NO_CONTENT,
+                _ => StatusCode::SERVICE_UNAVAILABLE,
+            };
+            *failures = failures.saturating_sub(1);
+            self.headers.lock().unwrap().push(request.headers().clone());
+
+            if status.is_success() {
+                let event = serde_json::from_slice(request.body()).unwrap();
+                self.events.lock().unwrap().push(event);
+            }
+
+            Ok(Response::builder().status(status).body(Bytes::new())?)
+        }
+    }
+
+    /// Shell recording the commands, exiting with `code`
+    struct Recorder {
+        requests: Mutex<Vec<shell::Request>>,
+        code: u16,
+    }
+
+    #[async_trait]
+    impl shell::Shell for Recorder {
+        async fn exec(&self, request: shell::Request) -> Result<shell::Response, shell::Error> {
+            self.requests.lock().unwrap().push(request);
+
+            Ok(shell::Response {
+                code: self.code,
+                stdout: String::new(),
+                stderr: "no space left".to_string(),
+            })
+        }
+    }
+
+    async fn write(fs: &dyn ReadWriteFilesystem, name: &str, content: &'static [u8]) {
+        let data = stream::once(async move { Ok(Bytes::from_static(content)) }).into_boxed();
+        fs.put(name, data, IncomingFileMeta::default())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn dispatches_writes() {
+        let receiver = Arc::new(Receiver::default());
+        let (fs, mut dispatcher) = Hooks::new()
+            .hook(Hook::saved(
+                "Journal/**",
+                Action::webhook("https://hooks.example.com"),
+            ))
+            .hook(Hook::deleted(
+                "*.md",
+                Action::webhook("https://hooks.example.com"),
+            ))
+            .client(receiver.clone())
+            .attach(Arc::new(MemoryFs::new()));
+
+        write(&fs, "Journal/Today.md", b"hello").await;
+        write(&fs, "index.md", b"").await;
+        fs.delete("index.md").await.unwrap();
+        drop(fs);
+
+        let mut errors = Vec::new();
+        while let Some(event) = dispatcher.events.next().await {
+            errors.extend(dispatcher.dispatch(&event).await);
+        }
+        assert!(errors.is_empty());
+
+        let events = receiver.events.lock().unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0]["event"], "saved");
+        assert_eq!(events[0]["name"], "Journal/Today.md");
+        assert_eq!(events[0]["meta"]["size"], 5);
+        assert_eq!(events[1]["event"], "deleted");
+        assert_eq!(events[1]["name"], "index.md");
+    }
+
+    #[tokio::test]
+    async fn retries_failed_actions() {
+        let receiver = Arc::new(Receiver::default());
+        *receiver.failures.lock().unwrap() = 2;
+
+        let (_, dispatcher) = Hooks::new()
+            .hook(Hook::saved(
+                "**",
+                Action::webhook("https://hooks.example.com"),
+            ))
+            .client(receiver.clone())
+            .retry(2, Duration::from_millis(1))
+            .attach(Arc::new(MemoryFs::new()));
+
+        let event = Event::Deleted {
+            name: "a.md".to_string(),
+        };
+        let saved = Event::Saved {
+            name: "a.md".to_string(),
+            meta: MemoryFs::new()
+                .with_file("a.md", b"")
+                .meta("a.md")
+                .await
+                .unwrap(),
+        };
+
+        // Not matching, so not sent
+        assert!(dispatcher.dispatch(&event).await.is_empty());
+        assert!(dispatcher.dispatch(&saved).await.is_empty());
+        assert_eq!(receiver.events.lock().unwrap().len(), 1);
+
+        // Retries are the same delivery
+        let headers = receiver.headers.lock().unwrap().clone();
+        let delivery = |i: usize| headers[i]["X-Silverbullet-Delivery"].clone();
+        assert_eq!(headers.len(), 3);
+        assert_eq!(delivery(0), delivery(2));
+
+        *receiver.failures.lock().unwrap() = 3;
+        let errors = dispatcher.dispatch(&saved).await;
+        assert!(matches!(errors[..], [Error::Webhook { .. }]));
+        assert_ne!(
+            receiver.headers.lock().unwrap()[3]["X-Silverbullet-Delivery"],
+            delivery(0)
+        );
+    }
+
+    #[tokio::test]
+    async fn signs_webhooks() {
+        let receiver = Arc::new(Receiver::default());
+        let (_, dispatcher) = Hooks::new()
+            .hook(Hook::deleted(
+                "**",
+                Action::signed_webhook("https://hooks.example.com", "It's a Secret to Everybody"),
+            ))
+            .hook(Hook::deleted(
+                "**",
+                Action::webhook("https://hooks.example.com"),
+            ))
+            .client(receiver.clone())
+            .attach(Arc::new(MemoryFs::new()));
+
+        let event = Event::Deleted {
+            name: "a.md".to_string(),
+        };
+        assert!(dispatcher.dispatch(&event).await.is_empty());
+
+        let headers = receiver.headers.lock().unwrap();
+        assert_eq!(headers[0]["X-Silverbullet-Event"], "deleted");
+        assert_eq!(
+            headers[0]["X-Silverbullet-Signature-256"],
+            signature(
+                "It's a Secret to Everybody",
+                br#"{"event":"deleted","name":"a.md"}"#
+            )
+        );
+        assert!(!headers[1].contains_key("X-Silverbullet-Signature-256"));
+
+        // The example of the GitHub docs
+        assert_eq!(
+            signature("It's a Secret to Everybody", b"Hello, World!"),
+            "sha256=757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17"
+        );
+    }
+
+    #[tokio::test]
+    async fn runs_commands() {
+        let shell = Arc::new(Recorder {
+            requests: Mutex::new(Vec::new()),
+            code: 0,
+        });
+        let (_, dispatcher) = Hooks::new()
+            .hook(Hook::deleted(
+                "**",
+                Action::command("publish", ["--remove", "{name}"]),
+            ))
+            .shell(shell.clone())
+            .attach(Arc::new(MemoryFs::new()));
+
+        let event = Event::Deleted {
+            name: "Notes/Old.md".to_string(),
+        };
+        assert!(dispatcher.dispatch(&event).await.is_empty());
+
+        let request = shell.requests.lock().unwrap().remove(0);
+        assert_eq!(request.cmd, "publish");
+        assert_eq!(request.args, ["--remove", "Notes/Old"]);
+        assert!(request.stdin.unwrap().contains(r#""event":"deleted""#));
+
+        let failing = Arc::new(Recorder {
+            requests: Mutex::new(Vec::new()),
+            code: 1,
+        });
+        let (_, dispatcher) = Hooks::new()
+            .hook(Hook::deleted("**", Action::command("publish", ["{name}"])))
+            .shell(failing)
+            .retry(0, Duration::ZERO)
+            .attach(Arc::new(MemoryFs::new()));
+        let errors = dispatcher.dispatch(&event).await;
+        assert_eq!(
+            errors[0].to_string(),
+            "Command publish failed: exited with 1: no space left"
+        );
+
+        // Without a shell, nothing is retried
+        let (_, dispatcher) = Hooks::new()
+            .hook(Hook::deleted("**", Action::command("publish", ["{name}"])))
+            .attach(Arc::new(MemoryFs::new()));
+        assert!(matches!(
+            dispatcher.dispatch(&event).await[..],
+            [Error::Unavailable("shell")]
+        ));
+    }
+
+    #[cfg(feature = "ssr")]
+    #[tokio::test]
+    async fn renders_pages() {
+        let renderer = crate::ssr::Jinja::new()
+            .template("publish.html", "<h1>{{ name }}</h1>{{ content }}")
+            .unwrap();
+        let space: Arc<dyn ReadWriteFilesystem> = Arc::new(MemoryFs::new());
+        let (fs, mut dispatcher) = Hooks::new()
+            .hook(Hook::saved(
+                "Public/*.md",
+                Action::render("publish.html", "_public/{name}.html"),
+            ))
+            .hook(Hook::deleted(
+                "Public/*.md",
+                Action::render("publish.html", "_public/{name}.html"),
+            ))
+            .renderer(Arc::new(renderer))
+            .attach(space.clone());
+
+        write(&fs, "Public/Hello.md", b"<b>hi</b>").await;
+        let event = dispatcher.events.next().await.unwrap();
+        assert!(dispatcher.dispatch(&event).await.is_empty());
+
+        let html = read(&*space, "_public/Public/Hello.html").await.unwrap();
+        assert_eq!(
+            html,
+            "<h1>Public&#x2f;Hello.md</h1>&lt;b&gt;hi&lt;&#x2f;b&gt;"
+        );
+
+        // Rendered files don't trigger hooks
+        fs.delete("Public/Hello.md").await.unwrap();
+        let event = dispatcher.events.next().await.unwrap();
+        assert!(matches!(event, Event::Deleted { .. }));
+        assert!(dispatcher.dispatch(&event).await.is_empty());
+        assert!(space.meta("_public/Public/Hello.html").await.is_err());
+    }
+}
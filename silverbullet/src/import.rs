@@ -0,0 +1,737 @@
+//! Conversion of notes exported from other apps, e.g. by `silverbullet import --from`
+//!
+//! A [`Converter`] is made from the paths of all the files of an export, since links are
+//! resolved against them. Each file is then imported on its own, under its
+//! [name](Converter::name) in the space, with pages [rewritten](Converter::page) into
+//! SilverBullet Markdown and other files as they are, so exports can be streamed into a
+//! space rather than held in memory.
+//!
+//! | Source | Conversions |
+//! |---|---|
+//! | [Obsidian](Source::Obsidian) vaults | Links and embeds naming a note or file without its folder get it, block references and `%%comments%%` are dropped, and `.obsidian` settings skipped |
+//! | [Notion](Source::Notion) Markdown or HTML exports | Ids are removed from the names of pages and folders, HTML pages are converted to Markdown, and links to pages and files become wiki links |
+//! | [Logseq](Source::Logseq) graphs | Journals move to `Journal/2024-06-01` and namespaced pages `a___b` to folders, page properties `key:: value` become frontmatter, and links to days point to their journal pages |
+//!
+//! ```ignore
+//! let converter = Converter::new(Source::Logseq, ["journals/2024_06_01.md", "pages/Books.md"]);
+//!
+//! assert_eq!(converter.name("journals/2024_06_01.md"), Some("Journal/2024-06-01.md"));
+//! assert_eq!(converter.page("pages/Books.md", "- Read on [[Jun 1st, 2024]]"), "- Read on [[Journal/2024-06-01]]");
+//! ```
+
+mod html;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// App an export comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    Obsidian,
+    Notion,
+    Logseq,
+}
+
+impl FromStr for Source {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "obsidian" => Ok(Source::Obsidian),
+            "notion" => Ok(Source::Notion),
+            "logseq" => Ok(Source::Logseq),
+            other => Err(format!(
+                "unsupported source: {other}, expected obsidian, notion or logseq"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Source::Obsidian => "obsidian",
+            Source::Notion => "notion",
+            Source::Logseq => "logseq",
+        })
+    }
+}
+
+/// Converter of the files of an export
+#[derive(Debug, Clone)]
+pub struct Converter {
+    source: Source,
+    /// Names in the space of the imported files, by their path in the export
+    names: HashMap<String, String>,
+    /// Names of pages without `.md` and of files, by the lowercased names links give
+    targets: HashMap<String, String>,
+}
+
+impl Converter {
+    /// Converter of an export with files at `paths`, `/` separated and relative to its root.
+    pub fn new<'a>(source: Source, paths: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut names: HashMap<String, String> = paths
+            .into_iter()
+            .filter_map(|path| Some((path.to_string(), source.name(path)?)))
+            .collect();
+
+        // Notion pages sharing a title keep their ids, rather than overwriting each other
+        if source == Source::Notion {
+            let mut counts = HashMap::<String, usize>::new();
+            for name in names.values() {
+                *counts.entry(name.to_lowercase()).or_default() += 1;
+            }
+            for (path, name) in &mut names {
+                if counts[&name.to_lowercase()] > 1 {
+                    *name = page_name(path);
+                }
+            }
+        }
+
+        let mut sorted: Vec<&str> = names.values().map(String::as_str).collect();
+        sorted.sort_unstable_by_key(|name| (name.len(), *name));
+
+        let mut targets = HashMap::new();
+        for name in &sorted {
+            let target = name.strip_suffix(".md").unwrap_or(name);
+            targets.insert(target.to_lowercase(), target.to_string());
+        }
+
+        // Obsidian links name notes and files by their file name when it's unique, the
+        // shortest path wins otherwise like in the app
+        if source == Source::Obsidian {
+            for name in &sorted {
+                let target = name.strip_suffix(".md").unwrap_or(name);
+                let file = target.rsplit('/').next().unwrap_or(target);
+                targets
+                    .entry(file.to_lowercase())
+                    .or_insert_with(|| target.to_string());
+            }
+        }
+
+        Self {
+            source,
+            names,
+            targets,
+        }
+    }
+
+    /// Name in the space of the file at `path`, `None` for files not imported, e.g. app
+    /// settings.
+    pub fn name(&self, path: &str) -> Option<&str> {
+        self.names.get(path).map(String::as_str)
+    }
+
+    /// Whether the file at `path` is a page to [convert](Self::page).
+    pub fn is_page(&self, path: &str) -> bool {
+        path.ends_with(".md") || (self.source == Source::Notion && path.ends_with(".html"))
+    }
+
+    /// Content in the space of the page at `path`.
+    pub fn page(&self, path: &str, content: &str) -> String {
+        let content = match self.source {
+            Source::Notion if path.ends_with(".html") => html::markdown(content),
+            Source::Logseq => logseq_properties(content),
+            _ => content.to_string(),
+        };
+        let folder = path.rsplit_once('/').map_or("", |(folder, _)| folder);
+
+        let mut comment = false;
+        outside_code(&content, |text| {
+            let text = match self.source {
+                Source::Obsidian => without_comments(text, &mut comment),
+                _ => text.to_string(),
+            };
+            let text = self.wiki_links(&text);
+            self.markdown_links(folder, &text)
+        })
+    }
+
+    /// Name of the page or file a wiki link points to.
+    fn target(&self, link: &str) -> Option<String> {
+        if self.source == Source::Logseq
+            && let Some(day) = journal_day(link)
+        {
+            return Some(format!("Journal/{day}"));
+        }
+
+        let link = link.strip_suffix(".md").unwrap_or(link);
+        self.targets.get(&link.to_lowercase()).cloned()
+    }
+
+    /// `text` with the targets of its `[[wiki links]]` resolved.
+    fn wiki_links(&self, text: &str) -> String {
+        let mut out = String::with_capacity(text.len());
+        let mut rest = text;
+
+        while let Some(start) = rest.find("[[") {
+            let Some(end) = rest[start + 2..].find("]]") else {
+                break;
+            };
+            let inner = &rest[start + 2..start + 2 + end];
+            out.push_str(&rest[..start + 2]);
+            rest = &rest[start + 2 + end..];
+
+            let (link, alias) = match inner.split_once('|') {
+                Some((link, alias)) => (link, Some(alias)),
+                None => (inner, None),
+            };
+            let (page, heading) = match link.split_once('#') {
+                Some((page, heading)) => (page, Some(heading)),
+                None => (link, None),
+            };
+
+            let page = match page.is_empty() {
+                true => String::new(),
+                false => self.target(page).unwrap_or_else(|| page.to_string()),
+            };
+            out.push_str(&page);
+            // Obsidian's block references have no counterpart
+            if let Some(heading) = heading.filter(|heading| !heading.starts_with('^')) {
+                out.push('#');
+                out.push_str(heading);
+            }
+            if let Some(alias) = alias {
+                out.push('|');
+                out.push_str(alias);
+            }
+        }
+        out.push_str(rest);
+
+        match self.source {
+            Source::Obsidian => without_block_ids(&out),
+            _ => out,
+        }
+    }
+
+    /// `text` with its Markdown links and images to files of the export as wiki links.
+    fn markdown_links(&self, folder: &str, text: &str) -> String {
+        let mut out = String::with_capacity(text.len());
+        let mut rest = text;
+
+        while let Some(start) = rest.find('[') {
+            // Wiki links were resolved already
+            if rest[start..].starts_with("[[") {
+                let end = rest[start..]
+                    .find("]]")
+                    .map_or(rest.len(), |end| start + end + 2);
+                out.push_str(&rest[..end]);
+                rest = &rest[end..];
+                continue;
+            }
+
+            let Some((label, destination, end)) = markdown_link(&rest[start..]) else {
+                out.push_str(&rest[..=start]);
+                rest = &rest[start + 1..];
+                continue;
+            };
+            let image = match start {
+                0 => out.ends_with('!'),
+                _ => rest[..start].ends_with('!'),
+            };
+
+            match self.link_target(folder, destination) {
+                Some(target) => {
+                    out.push_str(&rest[..start]);
+                    out.push_str("[[");
+                    out.push_str(&target);
+                    let shown = match image {
+                        true => target.rsplit('/').next().unwrap_or(&target),
+                        false => &target,
+                    };
+                    if !label.is_empty() && label != shown {
+                        out.push('|');
+                        out.push_str(label);
+                    }
+                    out.push_str("]]");
+                }
+                None => out.push_str(&rest[..start + end]),
+            }
+            rest = &rest[start + end..];
+        }
+        out.push_str(rest);
+
+        out
+    }
+
+    /// Page without `.md` or file a Markdown link in `folder` points to, for links to
+    /// files of the export.
+    fn link_target(&self, folder: &str, destination: &str) -> Option<String> {
+        let destination = destination.trim();
+        let destination = destination
+            .strip_prefix('<')
+            .and_then(|destination| destination.strip_suffix('>'))
+            .unwrap_or(destination);
+        let path = destination.split('#').next().unwrap_or_default();
+
+        if path.is_empty() || path.contains(':') {
+            return None;
+        }
+
+        let name = self.names.get(&join(folder, &decode(path))?)?;
+        Some(name.strip_suffix(".md").unwrap_or(name).to_string())
+    }
+}
+
+impl Source {
+    /// Name in the space of a file of an export.
+    fn name(self, path: &str) -> Option<String> {
+        let parts: Vec<&str> = path.split('/').collect();
+
+        // Settings, trash and such
+        if parts
+            .iter()
+            .any(|part| part.is_empty() || part.starts_with('.'))
+        {
+            return None;
+        }
+
+        match self {
+            Source::Obsidian => Some(path.to_string()),
+            Source::Notion => {
+                let name = parts
+                    .iter()
+                    .map(|part| without_notion_id(part))
+                    .collect::<Vec<_>>()
+                    .join("/");
+                Some(page_name(&name))
+            }
+            Source::Logseq => match parts.as_slice() {
+                ["logseq", ..] => None,
+                ["journals", file] => {
+                    let stem = file.strip_suffix(".md")?;
+                    let day = match stem.split('_').collect::<Vec<_>>().as_slice() {
+                        [year, month, day] => format!("{year}-{month}-{day}"),
+                        _ => stem.to_string(),
+                    };
+                    Some(format!("Journal/{day}.md"))
+                }
+                ["pages", file] => Some(
+                    decode(&file.replace("___", "/").replace("%2F", "/"))
+                        .trim_start_matches('/')
+                        .to_string(),
+                ),
+                _ => Some(path.to_string()),
+            },
+        }
+    }
+}
+
+/// `.md` name of an HTML page.
+fn page_name(path: &str) -> String {
+    match path.strip_suffix(".html") {
+        Some(stem) => format!("{stem}.md"),
+        None => path.to_string(),
+    }
+}
+
+/// Part of a Notion path without the 32 hex digits id after its title.
+fn without_notion_id(part: &str) -> String {
+    let is_id = |stem: &str| {
+        stem.len() > 33
+            && stem.is_char_boundary(stem.len() - 33)
+            && stem[stem.len() - 33..].starts_with(' ')
+            && stem[stem.len() - 32..]
+                .bytes()
+                .all(|byte| byte.is_ascii_hexdigit())
+    };
+
+    if is_id(part) {
+        return part[..part.len() - 33].to_string();
+    }
+
+    match part.rsplit_once('.') {
+        Some((stem, extension)) if is_id(stem) => {
+            format!("{}.{extension}", &stem[..stem.len() - 33])
+        }
+        _ => part.to_string(),
+    }
+}
+
+/// `YYYY-MM-DD` of a day in Logseq's default journal format, e.g. `Jun 1st, 2024`.
+fn journal_day(title: &str) -> Option<String> {
+    const MONTHS: [&str; 12] = [
+        "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+    ];
+
+    let (month, rest) = title.trim().split_once(' ')?;
+    let (day, year) = rest.split_once(", ")?;
+
+    let month = MONTHS
+        .iter()
+        .position(|name| month.to_ascii_lowercase().starts_with(name))?
+        + 1;
+    let day: u8 = day
+        .trim_end_matches(|c: char| c.is_ascii_alphabetic())
+        .parse()
+        .ok()
+        .filter(|day| (1..=31).contains(day))?;
+    let year: u16 = year
+        .trim()
+        .parse()
+        .ok()
+        .filter(|_| year.trim().len() == 4)?;
+
+    Some(format!("{year}-{month:02}-{day:02}"))
+}
+
+/// Logseq page with its leading `key:: value` properties as frontmatter, and without the
+/// `collapsed::` state of its blocks.
+fn logseq_properties(content: &str) -> String {
+    let mut lines = content.lines().peekable();
+    let mut frontmatter = Vec::new();
+
+    while let Some((key, value)) = lines.peek().and_then(|line| property(line)) {
+        let value = match key {
+            "tags" | "alias" => format!(
+                "[{}]",
+                value
+                    .split(',')
+                    .map(|item| scalar(item.trim().trim_start_matches("[[").trim_end_matches("]]")))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            _ => scalar(value),
+        };
+        frontmatter.push(format!("{key}: {value}"));
+        lines.next();
+    }
+
+    let mut out = String::with_capacity(content.len());
+    if !frontmatter.is_empty() {
+        out.push_str("---\n");
+        for line in frontmatter {
+            out.push_str(&line);
+            out.push('\n');
+        }
+        out.push_str("---\n");
+    }
+    for line in lines {
+        if property(line.trim_start()).is_some_and(|(key, _)| key == "collapsed") {
+            continue;
+        }
+        out.push_str(line);
+        out.push('\n');
+    }
+    if !content.ends_with('\n') {
+        out.pop();
+    }
+
+    out
+}
+
+/// Key and value of a `key:: value` line.
+fn property(line: &str) -> Option<(&str, &str)> {
+    let (key, value) = line.split_once("::")?;
+
+    (!key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '-' || c == '_'))
+    .then(|| (key, value.trim()))
+}
+
+/// YAML scalar of a value, quoted when needed.
+fn scalar(value: &str) -> String {
+    let plain = !value.is_empty()
+        && !value.starts_with(|c: char| "-?:,[]{}#&*!|>'\"%@`".contains(c))
+        && !value.contains(": ")
+        && !value.contains(" #")
+        && !value.ends_with(':');
+
+    match plain {
+        true => value.to_string(),
+        false => format!("'{}'", value.replace('\'', "''")),
+    }
+}
+
+/// `content` with the text outside of code blocks and spans converted.
+fn outside_code(content: &str, mut convert: impl FnMut(&str) -> String) -> String {
+    let mut out = String::with_capacity(content.len());
+    let mut fence: Option<&str> = None;
+
+    for line in content.split_inclusive('\n') {
+        let marker = ["```", "~~~"]
+            .into_iter()
+            .find(|marker| line.trim_start().starts_with(marker));
+
+        match (fence, marker) {
+            (Some(open), Some(marker)) if open == marker => fence = None,
+            (Some(_), _) => {}
+            (None, Some(marker)) => fence = Some(marker),
+            (None, None) => {
+                for (i, part) in line.split('`').enumerate() {
+                    if i > 0 {
+                        out.push('`');
+                    }
+                    match i % 2 {
+                        0 => out.push_str(&convert(part)),
+                        _ => out.push_str(part),
+                    }
+                }
+                continue;
+            }
+        }
+        out.push_str(line);
+    }
+
+    out
+}
+
+/// `text` without Obsidian `%%comments%%`, which may span lines.
+fn without_comments(text: &str, comment: &mut bool) -> String {
+    let mut out = String::with_capacity(text.len());
+
+    for (i, part) in text.split("%%").enumerate() {
+        if i > 0 {
+            *comment = !*comment;
+        }
+        if !*comment {
+            out.push_str(part);
+        }
+    }
+
+    out
+}
+
+/// `text` without the ` ^id` Obsidian adds to the ends of referenced blocks.
+fn without_block_ids(text: &str) -> String {
+    text.split_inclusive('\n')
+        .map(|line| {
+            let (content, end) = match line.strip_suffix('\n') {
+                Some(content) => (content, "\n"),
+                None => (line, ""),
+            };
+            match content.rsplit_once(" ^") {
+                Some((content, id))
+                    if !id.is_empty()
+                        && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') =>
+                {
+                    format!("{content}{end}")
+                }
+                _ => line.to_string(),
+            }
+        })
+        .collect()
+}
+
+/// Label and destination of the Markdown link at the start of `text`, with its length.
+fn markdown_link(text: &str) -> Option<(&str, &str, usize)> {
+    let mut depth = 0;
+    let close = text.char_indices().find_map(|(i, c)| {
+        match c {
+            '[' => depth += 1,
+            ']' => depth -= 1,
+            '\n' => return Some(None),
+            _ => {}
+        }
+        (depth == 0).then_some(Some(i))
+    })??;
+
+    let rest = text[close + 1..].strip_prefix('(')?;
+    let mut depth = 1;
+    let end = rest.char_indices().find_map(|(i, c)| {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            '\n' => return Some(None),
+            _ => {}
+        }
+        (depth == 0).then_some(Some(i))
+    })??;
+
+    Some((&text[1..close], &rest[..end], close + 2 + end + 1))
+}
+
+/// Path of `relative` from `folder`, `None` when it leaves the export.
+fn join(folder: &str, relative: &str) -> Option<String> {
+    let mut parts: Vec<&str> = match relative.starts_with('/') {
+        true => Vec::new(),
+        false => folder.split('/').filter(|part| !part.is_empty()).collect(),
+    };
+
+    for part in relative.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop()?;
+            }
+            part => parts.push(part),
+        }
+    }
+
+    Some(parts.join("/"))
+}
+
+/// Decode the `%20`s and such of a path.
+pub(crate) fn decode(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_obsidian_vaults() {
+        let converter = Converter::new(
+            Source::Obsidian,
+            [
+                "Home.md",
+                "Projects/Launch.md",
+                "Archive/Projects/Launch.md",
+                "attachments/chart.png",
+                ".obsidian/app.json",
+            ],
+        );
+
+        assert_eq!(converter.name(".obsidian/app.json"), None);
+        assert_eq!(
+            converter.name("Projects/Launch.md"),
+            Some("Projects/Launch.md")
+        );
+
+        let page = converter.page(
+            "Home.md",
+            "See [[Launch#^abc123|the launch]] and [[launch#Plan]] %%draft%%\n\
+             ![[chart.png]] [notes](Projects/Launch.md) [[Missing]]\n\
+             A quote ^quote-1\n\
+             `[[Launch]]`\n\
+             ```\n[[Launch]]\n```\n",
+        );
+
+        assert_eq!(
+            page,
+            "See [[Projects/Launch|the launch]] and [[Projects/Launch#Plan]] \n\
+             ![[attachments/chart.png]] [[Projects/Launch|notes]] [[Missing]]\n\
+             A quote\n\
+             `[[Launch]]`\n\
+             ```\n[[Launch]]\n```\n"
+        );
+    }
+
+    #[test]
+    fn converts_notion_exports() {
+        let id = "0123456789abcdef0123456789abcdef";
+        let launch = format!("Projects {id}/Launch {id}.md");
+        let chart = format!("Projects {id}/Launch {id}/chart.png");
+        let html = format!("Notes {id}.html");
+        let converter = Converter::new(
+            Source::Notion,
+            [
+                format!("Projects {id}.md").as_str(),
+                &launch,
+                &chart,
+                &html,
+                &format!("Notes {}.md", "f".repeat(32)),
+            ],
+        );
+
+        assert_eq!(converter.name(&launch), Some("Projects/Launch.md"));
+        assert_eq!(converter.name(&chart), Some("Projects/Launch/chart.png"));
+        // Kept ids of pages sharing a title
+        assert_eq!(
+            converter.name(&html),
+            Some(format!("Notes {id}.md").as_str())
+        );
+
+        let page = converter.page(
+            &format!("Projects {id}.md"),
+            &format!(
+                "# Projects\n\n[Launch](Projects%20{id}/Launch%20{id}.md) \
+                 ![](Projects%20{id}/Launch%20{id}/chart.png) [Site](https://example.com)\n"
+            ),
+        );
+        assert_eq!(
+            page,
+            "# Projects\n\n[[Projects/Launch|Launch]] ![[Projects/Launch/chart.png]] \
+             [Site](https://example.com)\n"
+        );
+
+        let page = converter.page(
+            &html,
+            &format!(
+                "<html><head><title>Notes</title></head><body><h1>Notes</h1>\
+                 <p>Read <a href=\"Projects%20{id}/Launch%20{id}.md\">the plan</a></p>\
+                 </body></html>"
+            ),
+        );
+        assert_eq!(page, "# Notes\n\nRead [[Projects/Launch|the plan]]\n");
+    }
+
+    #[test]
+    fn converts_logseq_graphs() {
+        let converter = Converter::new(
+            Source::Logseq,
+            [
+                "journals/2024_06_01.md",
+                "pages/Books___Dune.md",
+                "pages/Projects%2FLaunch.md",
+                "assets/cover_1717.png",
+                "logseq/config.edn",
+            ],
+        );
+
+        assert_eq!(
+            converter.name("journals/2024_06_01.md"),
+            Some("Journal/2024-06-01.md")
+        );
+        assert_eq!(
+            converter.name("pages/Books___Dune.md"),
+            Some("Books/Dune.md")
+        );
+        assert_eq!(
+            converter.name("pages/Projects%2FLaunch.md"),
+            Some("Projects/Launch.md")
+        );
+        assert_eq!(converter.name("logseq/config.edn"), None);
+
+        let page = converter.page(
+            "pages/Books___Dune.md",
+            "tags:: [[Books]], sci-fi\nauthor:: Frank Herbert: the author\n\
+             - Started on [[Jun 1st, 2024]] for [[projects/launch]]\n  collapsed:: true\n\
+             \t- ![cover](../assets/cover_1717.png)\n",
+        );
+
+        assert_eq!(
+            page,
+            "---\ntags: [Books, sci-fi]\nauthor: 'Frank Herbert: the author'\n---\n\
+             - Started on [[Journal/2024-06-01]] for [[Projects/Launch]]\n\
+             \t- ![[assets/cover_1717.png|cover]]\n"
+        );
+    }
+
+    #[test]
+    fn parses_days() {
+        assert_eq!(journal_day("Jun 1st, 2024").as_deref(), Some("2024-06-01"));
+        assert_eq!(
+            journal_day("December 22nd, 2023").as_deref(),
+            Some("2023-12-22")
+        );
+        assert_eq!(journal_day("Books"), None);
+        assert_eq!(journal_day("Jun 40th, 2024"), None);
+    }
+}
@@ -0,0 +1,313 @@
+//! Markdown of the HTML pages Notion exports
+//!
+//! Only what Notion writes is covered: headings, paragraphs, lists and to-dos, emphasis,
+//! code, links and images. Other tags are dropped, keeping their text.
+
+use std::fmt::Write as _;
+
+/// Markdown of an HTML page.
+pub(super) fn markdown(html: &str) -> String {
+    let mut out = String::with_capacity(html.len());
+    // Numbers of the items of the open lists, `None` for bulleted ones
+    let mut lists: Vec<Option<usize>> = Vec::new();
+    // Targets of the open links, with where their labels start
+    let mut links: Vec<Option<(String, usize)>> = Vec::new();
+    let mut pre = false;
+    let mut rest = html;
+
+    while let Some(start) = rest.find('<') {
+        text(&mut out, &rest[..start], pre);
+
+        let Some(end) = rest[start..].find('>') else {
+            rest = "";
+            break;
+        };
+        let tag = &rest[start + 1..start + end];
+        rest = &rest[start + end + 1..];
+
+        let closing = tag.starts_with('/');
+        let name = tag
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+
+        // Drop what isn't content at all
+        if matches!(name.as_str(), "head" | "style" | "script") && !closing {
+            let close = format!("</{name}");
+            rest = match rest.to_ascii_lowercase().find(&close) {
+                Some(at) => rest[at..].find('>').map_or("", |end| &rest[at + end + 1..]),
+                None => "",
+            };
+            continue;
+        }
+
+        match (name.as_str(), closing) {
+            ("h1" | "h2" | "h3" | "h4" | "h5" | "h6", false) => {
+                block(&mut out);
+                out.push_str(&"#".repeat(usize::from(name.as_bytes()[1] - b'0')));
+                out.push(' ');
+            }
+            ("div" | "span", false)
+                if attribute(tag, "class").is_some_and(|class| class.contains("checkbox-on")) =>
+            {
+                out.push_str("[x] ");
+            }
+            ("div" | "span", false)
+                if attribute(tag, "class").is_some_and(|class| class.contains("checkbox-off")) =>
+            {
+                out.push_str("[ ] ");
+            }
+            (
+                "p" | "div" | "blockquote" | "figure" | "table" | "details" | "h1" | "h2" | "h3"
+                | "h4" | "h5" | "h6",
+                _,
+            ) if lists.is_empty() => {
+                block(&mut out);
+            }
+            ("br", _) => out.push('\n'),
+            ("tr", _) => line(&mut out),
+            ("td" | "th", false) => out.push(' '),
+            ("hr", _) => {
+                block(&mut out);
+                out.push_str("---");
+                block(&mut out);
+            }
+            ("ul", false) => {
+                line(&mut out);
+                lists.push(None);
+            }
+            ("ol", false) => {
+                line(&mut out);
+                lists.push(Some(0));
+            }
+            ("ul" | "ol", true) => {
+                lists.pop();
+                line(&mut out);
+            }
+            ("li", false) => {
+                line(&mut out);
+                out.push_str(&"  ".repeat(lists.len().saturating_sub(1)));
+                match lists.last_mut() {
+                    Some(Some(number)) => {
+                        *number += 1;
+                        let _ = write!(out, "{number}. ");
+                    }
+                    _ => out.push_str("- "),
+                }
+            }
+            ("strong" | "b", _) => out.push_str("**"),
+            ("em" | "i", _) => out.push('_'),
+            ("del" | "s", _) => out.push_str("~~"),
+            ("code", _) if !pre => out.push('`'),
+            ("pre", false) => {
+                block(&mut out);
+                out.push_str("```\n");
+                pre = true;
+            }
+            ("pre", true) => {
+                line(&mut out);
+                out.push_str("```");
+                block(&mut out);
+                pre = false;
+            }
+            ("a", false) => links.push(attribute(tag, "href").map(|href| (href, out.len()))),
+            ("a", true) => {
+                if let Some(Some((href, at))) = links.pop() {
+                    let label = &out[at..];
+                    // Images linking to themselves, as Notion writes them, stay images
+                    if !(label.starts_with("![") || label.trim().is_empty()) {
+                        out.insert(at, '[');
+                        let _ = write!(out, "]({})", destination(&href));
+                    }
+                }
+            }
+            ("img", _) => {
+                if let Some(src) = attribute(tag, "src") {
+                    let alt = attribute(tag, "alt").unwrap_or_default();
+                    let _ = write!(out, "![{alt}]({})", destination(&src));
+                }
+            }
+            _ => {}
+        }
+    }
+    text(&mut out, rest, pre);
+
+    // At most one empty line between blocks
+    let mut lines: Vec<&str> = Vec::new();
+    for line in out.lines().map(str::trim_end) {
+        if !(line.is_empty() && lines.last().is_none_or(|last| last.is_empty())) {
+            lines.push(line);
+        }
+    }
+
+    let mut markdown = lines.join("\n").trim().to_string();
+    markdown.push('\n');
+    markdown
+}
+
+/// Append the text between tags, its spaces collapsed unless preformatted.
+fn text(out: &mut String, text: &str, pre: bool) {
+    let text = entities(text);
+
+    if pre {
+        out.push_str(&text);
+        return;
+    }
+
+    let space = |out: &mut String| {
+        if !out.is_empty() && !out.ends_with(char::is_whitespace) {
+            out.push(' ');
+        }
+    };
+
+    if text.starts_with(char::is_whitespace) {
+        space(out);
+    }
+    for (i, word) in text.split_whitespace().enumerate() {
+        if i > 0 {
+            out.push(' ');
+        }
+        out.push_str(word);
+    }
+    if text.ends_with(char::is_whitespace) {
+        space(out);
+    }
+}
+
+/// Start a block, after an empty line.
+fn block(out: &mut String) {
+    while !out.is_empty() && !out.ends_with("\n\n") {
+        out.push('\n');
+    }
+}
+
+/// Start a line.
+fn line(out: &mut String) {
+    if !out.is_empty() && !out.ends_with('\n') {
+        out.push('\n');
+    }
+}
+
+/// Value of an attribute of a tag.
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let lower = tag.to_ascii_lowercase();
+    let mut from = 0;
+
+    while let Some(at) = lower[from..].find(name).map(|at| from + at) {
+        from = at + name.len();
+
+        let before = lower[..at].chars().next_back();
+        let rest = lower[from..].trim_start();
+        if !before.is_some_and(char::is_whitespace) || !rest.starts_with('=') {
+            continue;
+        }
+
+        let value = tag[tag.len() - rest.len() + 1..].trim_start();
+        let quote = value.chars().next()?;
+        let value = match quote {
+            '"' | '\'' => value[1..].split(quote).next()?,
+            _ => value.split(char::is_whitespace).next()?,
+        };
+
+        return Some(entities(value));
+    }
+
+    None
+}
+
+/// Markdown link destination of a URL, in brackets when it has spaces.
+fn destination(url: &str) -> String {
+    match url.contains(' ') {
+        true => format!("<{url}>"),
+        false => url.to_string(),
+    }
+}
+
+/// Text with the common entities decoded.
+fn entities(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let entity = rest[1..]
+            .find(';')
+            .filter(|end| *end <= 10)
+            .map(|end| &rest[1..end + 1]);
+        let decoded = entity.and_then(|entity| match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            _ => entity
+                .strip_prefix("#x")
+                .or_else(|| entity.strip_prefix("#X"))
+                .map(|hex| u32::from_str_radix(hex, 16))
+                .or_else(|| entity.strip_prefix('#').map(str::parse))
+                .and_then(Result::ok)
+                .and_then(char::from_u32),
+        });
+
+        match (entity, decoded) {
+            (Some(entity), Some(decoded)) => {
+                out.push(decoded);
+                rest = &rest[entity.len() + 2..];
+            }
+            _ => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_notion_pages() {
+        let html = r#"<html><head><title>Plan</title><style>p { x: 1 }</style></head>
+            <body><article><header><h1 class="page-title">Plan &amp; budget</h1></header>
+            <div class="page-body"><p>Some <strong>bold</strong> and <em>soft</em> text,
+            see <a href="Budget%20abc.html">the budget</a>.</p>
+            <ul class="bulleted-list"><li>One</li></ul><ul class="bulleted-list"><li>Two
+            <ul><li>Nested</li></ul></li></ul>
+            <ol><li>First</li><li>Second</li></ol>
+            <ul class="to-do-list"><li><div class="checkbox checkbox-on"></div> <span>Done</span></li></ul>
+            <figure class="image"><a href="Plan/chart.png"><img src="Plan/chart.png"/></a></figure>
+            <pre><code>let x = 1;
+let y = 2;</code></pre></div></article></body></html>"#;
+
+        assert_eq!(
+            markdown(html),
+            "# Plan & budget\n\n\
+             Some **bold** and _soft_ text, see [the budget](Budget%20abc.html).\n\n\
+             - One\n\
+             - Two\n  \
+               - Nested\n\
+             1. First\n\
+             2. Second\n\
+             - [x] Done\n\n\
+             ![](Plan/chart.png)\n\n\
+             ```\nlet x = 1;\nlet y = 2;\n```\n"
+        );
+    }
+
+    #[test]
+    fn reads_attributes() {
+        let tag = r#"a class="x" href='A&amp;B.html' data-href="no""#;
+        assert_eq!(attribute(tag, "href").as_deref(), Some("A&B.html"));
+        assert_eq!(attribute(tag, "title"), None);
+        assert_eq!(entities("&lt;&#39;&#x41;&unknown; &"), "<'A&unknown; &");
+    }
+}
@@ -1,8 +1,37 @@
 pub mod fs;
 
+#[cfg(feature = "backup")]
+pub mod backup;
+
+#[cfg(feature = "bundle")]
+pub mod bundle;
+
 pub mod client;
+#[cfg(feature = "dedupe")]
+pub mod dedupe;
+pub mod gc;
+#[cfg(feature = "hooks")]
+pub mod hooks;
+pub mod import;
 pub mod proxy;
+pub mod scheduler;
 pub mod shell;
+#[cfg(feature = "stats")]
+pub mod stats;
+pub mod templates;
+
+#[cfg(feature = "config")]
+pub mod config;
 
 #[cfg(feature = "server")]
 pub mod server;
+
+#[cfg(feature = "ssr")]
+pub mod ssr;
+
+#[cfg(feature = "git-sync")]
+pub mod sync;
+
+mod glob;
+#[cfg(any(feature = "server", feature = "ssr", feature = "fs-http"))]
+mod percent;
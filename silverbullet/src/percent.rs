@@ -0,0 +1,28 @@
+//! Percent-encoding of page and file names in URLs.
+
+/// Percent-encode a name as a URL path, the `/` between its folders kept.
+pub(crate) fn encode(path: &str) -> String {
+    let mut out = String::with_capacity(path.len());
+
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                out.push(char::from(byte))
+            }
+            _ => out.push_str(&format!("%{byte:02X}")),
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_names() {
+        assert_eq!(encode("Journal/2026-10-14.md"), "Journal/2026-10-14.md");
+        assert_eq!(encode("My Notes/Café?.md"), "My%20Notes/Caf%C3%A9%3F.md");
+    }
+}
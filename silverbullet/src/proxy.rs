@@ -1,13 +1,38 @@
+use std::fmt;
+use std::net::IpAddr;
+use std::time::Duration;
+
 use async_trait::async_trait;
 use bytes::Bytes;
-use http::{HeaderMap, HeaderName, HeaderValue, Request, Response, StatusCode};
+use futures::future::{self, Either};
+use http::{HeaderMap, HeaderName, HeaderValue, Method, Request, Response, StatusCode, Uri};
 use thiserror::Error;
 
+pub mod circuit;
+pub mod dns;
+pub mod policy;
+mod redirect;
+pub mod retry;
+pub mod scheme;
+pub mod secrets;
+pub use policy::Policy;
+pub use retry::Retry;
+pub use secrets::Secrets;
+
+#[cfg(all(not(target_arch = "wasm32"), feature = "hyper"))]
+pub mod hyper;
+
 #[cfg(feature = "reqwest")]
 pub mod reqwest;
 
-// #[cfg(feature = "proxy-cloudflare")]
-// pub mod cloudflare;
+#[cfg(all(not(target_arch = "wasm32"), feature = "websocket"))]
+pub mod websocket;
+
+#[cfg(all(target_arch = "wasm32", feature = "proxy-cloudflare"))]
+pub mod cloudflare;
+
+#[cfg(all(target_arch = "wasm32", feature = "proxy-fetch"))]
+pub mod fetch;
 
 // Platform-specific error boxing
 #[cfg(not(target_arch = "wasm32"))]
@@ -27,6 +52,21 @@ pub enum Error {
     #[error("Proxy not supported: {0}")]
     NotSupported(String),
 
+    #[error("Request not allowed: {0}")]
+    Forbidden(String),
+
+    #[error("Upstream timed out after {0:?}")]
+    Timeout(Duration),
+
+    #[error("Circuit open for {0}")]
+    CircuitOpen(String),
+
+    #[error("Upstream response exceeds {0} bytes")]
+    ResponseTooLarge(usize),
+
+    #[error("Upstream content type not allowed: {0}")]
+    ContentTypeNotAllowed(String),
+
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
 
@@ -39,6 +79,163 @@ pub enum Error {
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// Why a request didn't get an upstream response, for users debugging a failing widget
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub enum Category {
+    /// The host name didn't resolve
+    Dns,
+    /// No connection could be opened, e.g. refused or unreachable
+    Connect,
+    /// The TLS handshake failed, e.g. on an untrusted certificate
+    Tls,
+    Timeout,
+    /// The circuit breaker stops requests to a failing host
+    CircuitOpen,
+    /// The response was refused, e.g. too large or of a denied content type
+    Response,
+    Other,
+}
+
+impl Category {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Category::Dns => "dns",
+            Category::Connect => "connect",
+            Category::Tls => "tls",
+            Category::Timeout => "timeout",
+            Category::CircuitOpen => "circuit_open",
+            Category::Response => "response",
+            Category::Other => "other",
+        }
+    }
+}
+
+impl fmt::Display for Category {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Error {
+    /// Category of a failure of the upstream, `None` for requests refused by the proxy
+    /// itself, e.g. by its policy.
+    ///
+    /// Client errors are told apart by their sources, whose types differ between clients,
+    /// so their messages are matched too.
+    pub fn category(&self) -> Option<Category> {
+        match self {
+            Error::InvalidUrl(_) | Error::NotSupported(_) | Error::Forbidden(_) => None,
+            Error::Timeout(_) => Some(Category::Timeout),
+            Error::CircuitOpen(_) => Some(Category::CircuitOpen),
+            Error::ResponseTooLarge(_) | Error::ContentTypeNotAllowed(_) => {
+                Some(Category::Response)
+            }
+            Error::Client(err) | Error::Other(err) => Some(classify(err.as_ref())),
+            Error::Io(err) => Some(classify(err)),
+            Error::Http(_) => Some(Category::Other),
+        }
+    }
+}
+
+fn classify(err: &(dyn std::error::Error + 'static)) -> Category {
+    use std::io::ErrorKind;
+
+    let mut source = Some(err);
+    let mut messages = String::new();
+
+    while let Some(err) = source {
+        if let Some(io) = err.downcast_ref::<std::io::Error>() {
+            match io.kind() {
+                ErrorKind::TimedOut => return Category::Timeout,
+                ErrorKind::ConnectionRefused
+                | ErrorKind::ConnectionReset
+                | ErrorKind::ConnectionAborted
+                | ErrorKind::NotConnected
+                | ErrorKind::AddrNotAvailable
+                | ErrorKind::HostUnreachable
+                | ErrorKind::NetworkUnreachable => return Category::Connect,
+                _ => {}
+            }
+        }
+
+        messages.push_str(&err.to_string().to_ascii_lowercase());
+        messages.push('\n');
+        source = err.source();
+    }
+
+    let mentions = |words: &[&str]| words.iter().any(|word| messages.contains(word));
+
+    if mentions(&[
+        "dns error",
+        "failed to lookup address",
+        "no such host",
+        "name or service not known",
+    ]) {
+        Category::Dns
+    } else if mentions(&["certificate", "tls", "ssl", "handshake"]) {
+        Category::Tls
+    } else if mentions(&["timed out", "timeout"]) {
+        Category::Timeout
+    } else if mentions(&["connect", "connection refused", "unreachable"]) {
+        Category::Connect
+    } else {
+        Category::Other
+    }
+}
+
+/// Host of the upstream of a `/.proxy/{url}` request, e.g. `example.com` for
+/// `/.proxy/example.com/feed.xml`.
+pub fn upstream_host(uri: &Uri) -> Option<String> {
+    let url = uri.path().strip_prefix("/.proxy/")?;
+    let url = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let authority = url.split('/').next()?;
+    let host = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+
+    (!host.is_empty()).then(|| host.to_ascii_lowercase())
+}
+
+/// Maximum size of an upstream response body
+///
+/// Set as an extension on requests passed to a [`Client`], so clients can stop reading
+/// a response as soon as it exceeds the limit (failing with [`Error::ResponseTooLarge`]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResponseLimit(pub usize);
+
+impl ResponseLimit {
+    /// Fail if `size` bytes exceed the limit of the request, if any.
+    pub fn check<B>(request: &Request<B>, size: usize) -> Result<()> {
+        match request.extensions().get::<ResponseLimit>() {
+            Some(ResponseLimit(limit)) if size > *limit => Err(Error::ResponseTooLarge(*limit)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Address of the client of a proxied request
+///
+/// Set as an extension on requests passed to [`Proxy::proxy`], and sent upstream as
+/// `X-Forwarded-For` when enabled with [`Proxy::forwarded_for`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientAddr(pub IpAddr);
+
+/// Headers of a single connection, never forwarded in either direction (RFC 9110,
+/// section 7.6.1), along with the ones named by `Connection`
+const HOP_BY_HOP: [HeaderName; 9] = [
+    http::header::CONNECTION,
+    HeaderName::from_static("keep-alive"),
+    HeaderName::from_static("proxy-connection"),
+    http::header::PROXY_AUTHENTICATE,
+    http::header::PROXY_AUTHORIZATION,
+    http::header::TE,
+    http::header::TRAILER,
+    http::header::TRANSFER_ENCODING,
+    http::header::UPGRADE,
+];
+
 /// HTTP client trait for making requests
 #[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
 #[cfg_attr(not(target_arch = "wasm32"), async_trait)]
@@ -49,6 +246,17 @@ pub trait Client: Send + Sync {
 /// Proxy handles proxying HTTP requests through a client
 pub struct Proxy<C> {
     client: C,
+    policy: Policy,
+    schemes: scheme::Rules,
+    resolver: Option<Box<dyn dns::Resolver>>,
+    timeout: Option<Duration>,
+    retry: Retry,
+    breaker: Option<circuit::Breaker>,
+    secrets: Secrets,
+    max_response_size: Option<usize>,
+    allowed_content_types: Option<Vec<String>>,
+    base_path: String,
+    forwarded_for: bool,
 }
 
 impl<C> Proxy<C>
@@ -56,45 +264,209 @@ where
     C: Client,
 {
     pub fn new(client: C) -> Self {
-        Self { client }
+        Self {
+            client,
+            policy: Policy::default(),
+            schemes: scheme::Rules::default(),
+            resolver: None,
+            timeout: None,
+            retry: Retry::default(),
+            breaker: None,
+            secrets: Secrets::default(),
+            max_response_size: None,
+            allowed_content_types: None,
+            base_path: String::new(),
+            forwarded_for: false,
+        }
+    }
+
+    /// Restrict which requests are forwarded.
+    #[must_use]
+    pub fn policy(mut self, policy: Policy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Methods forwarded, `None` for every method, see [`Policy::allowed_methods`].
+    pub fn allowed_methods(&self) -> Option<&[Method]> {
+        self.policy.allowed_methods()
+    }
+
+    /// Choose the scheme used to reach targets given without one.
+    #[must_use]
+    pub fn schemes(mut self, rules: scheme::Rules) -> Self {
+        self.schemes = rules;
+        self
+    }
+
+    /// Resolve target hosts before forwarding and check the addresses against the policy.
+    ///
+    /// Without a resolver only IP literals are checked. The check alone cannot prevent DNS
+    /// rebinding, as the client resolves the host again; clients should enforce the policy
+    /// when connecting too (see `proxy::reqwest::Client::guarded`).
+    #[must_use]
+    pub fn resolver<R>(mut self, resolver: R) -> Self
+    where
+        R: dns::Resolver + 'static,
+    {
+        self.resolver = Some(Box::new(resolver));
+        self
+    }
+
+    /// Fail upstream requests not answered within the given time.
+    #[must_use]
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Retry idempotent requests failing transiently.
+    #[must_use]
+    pub fn retry(mut self, retry: Retry) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Stop sending requests to hosts that keep failing.
+    ///
+    /// The breaker has to outlive the proxy to be useful: keep it in the application
+    /// state and pass a clone for every request.
+    #[must_use]
+    pub fn circuit_breaker(mut self, breaker: circuit::Breaker) -> Self {
+        self.breaker = Some(breaker);
+        self
+    }
+
+    /// Add credentials to requests for configured hosts.
+    #[must_use]
+    pub fn secrets(mut self, secrets: Secrets) -> Self {
+        self.secrets = secrets;
+        self
+    }
+
+    /// Fail with [`Error::ResponseTooLarge`] when an upstream response body exceeds the size.
+    #[must_use]
+    pub fn max_response_size(mut self, bytes: usize) -> Self {
+        self.max_response_size = Some(bytes);
+        self
+    }
+
+    /// Only pass on responses whose media type matches one of the globs (eg. `image/*`).
+    ///
+    /// Responses without body are always passed on.
+    #[must_use]
+    pub fn allow_content_types<I, T>(mut self, patterns: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        self.allowed_content_types = Some(
+            patterns
+                .into_iter()
+                .map(|pattern| pattern.into().to_ascii_lowercase())
+                .collect(),
+        );
+        self
+    }
+
+    /// Path prefix the server is mounted under, used when pointing redirects back at the proxy.
+    #[must_use]
+    pub fn base_path(mut self, path: impl AsRef<str>) -> Self {
+        self.base_path = path.as_ref().trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Send the [`ClientAddr`] of requests upstream as `X-Forwarded-For` (disabled by
+    /// default, it tells the upstream who uses the space).
+    #[must_use]
+    pub fn forwarded_for(mut self, enabled: bool) -> Self {
+        self.forwarded_for = enabled;
+        self
     }
 
     /// Proxy an HTTP request
     ///
     /// Extracts the target URL from the request path (everything after /.proxy/),
-    /// filters headers, adjusts the scheme, and forwards the request.
+    /// filters headers, selects the scheme (see [`scheme::Rules`]), and forwards the request.
+    ///
+    /// Redirects are followed up to [`Policy::max_redirects`], each target being checked
+    /// against the policy. A redirect that is not followed has its `Location` rewritten
+    /// to point back at the proxy.
     pub async fn proxy(&self, request: Request<Bytes>) -> Result<Response<Bytes>> {
         let (parts, body) = request.into_parts();
 
-        // Extract target URL from path (everything after /.proxy/)
-        let path = parts.uri.path();
-        let mut target_url = path.strip_prefix("/.proxy/").unwrap_or(path).to_string();
+        let target = self.target(&parts).await?;
 
-        // Append query parameters if present
-        if let Some(query) = parts.uri.query() {
-            target_url.push('?');
-            target_url.push_str(query);
+        // Filter headers (only forward x-proxy-header-* with prefix stripped)
+        let mut filtered_headers = filter_proxy_headers(&parts.headers);
+
+        if self.forwarded_for
+            && let Some(ClientAddr(addr)) = parts.extensions.get()
+            && let Ok(addr) = HeaderValue::try_from(addr.to_string())
+        {
+            filtered_headers.insert(HeaderName::from_static("x-forwarded-for"), addr);
         }
 
-        // Adjust scheme (http for localhost/IPs, https otherwise)
-        target_url = adjust_scheme(&target_url);
+        let mut method = parts.method;
+        let mut target = target;
+        let mut headers = filtered_headers;
+        let mut body = body;
+        let mut redirects = 0;
+
+        // Send the request, following redirects
+        let (upstream_parts, upstream_body) = loop {
+            let response = self
+                .send(
+                    method.clone(),
+                    target.clone(),
+                    headers.clone(),
+                    body.clone(),
+                )
+                .await?;
+
+            let next = redirect::is_redirect(response.status())
+                .then(|| redirect::location(&target, response.headers()))
+                .flatten();
+
+            let Some(next) = next else {
+                break response.into_parts();
+            };
+
+            if redirects >= self.policy.redirect_limit() {
+                let (mut parts, body) = response.into_parts();
+
+                if let Ok(location) =
+                    HeaderValue::from_str(&redirect::rewrite(&self.base_path, &next))
+                {
+                    parts.headers.insert(http::header::LOCATION, location);
+                }
+
+                break (parts, body);
+            }
+
+            let next_method = redirect::method(response.status(), &method);
 
-        // Filter headers (only forward x-proxy-header-* with prefix stripped)
-        let filtered_headers = filter_proxy_headers(&parts.headers);
+            if next_method != method {
+                body = Bytes::new();
+                headers.remove(http::header::CONTENT_TYPE);
+                headers.remove(http::header::CONTENT_LENGTH);
+            }
 
-        // Build proxied request
-        let mut proxied_request = Request::builder()
-            .method(parts.method)
-            .uri(target_url)
-            .body(body)?;
+            // Don't leak credentials to other hosts
+            if next.host() != target.host() {
+                headers.remove(http::header::AUTHORIZATION);
+                headers.remove(http::header::COOKIE);
+            }
 
-        *proxied_request.headers_mut() = filtered_headers;
+            self.policy.check(&next_method, &next)?;
+            self.check_resolved(&next).await?;
 
-        // Send the request
-        let upstream_response = self.client.send(proxied_request).await?;
+            method = next_method;
+            target = next;
+            redirects += 1;
+        };
 
-        // Process response headers
-        let (upstream_parts, upstream_body) = upstream_response.into_parts();
+        self.check_content_type(&upstream_parts, &upstream_body)?;
 
         let mut response_headers = HeaderMap::new();
 
@@ -109,8 +481,11 @@ where
             .get(http::header::CONTENT_TYPE)
             .cloned();
 
+        let mut upstream_headers = upstream_parts.headers;
+        strip_hop_by_hop(&mut upstream_headers);
+
         // Add all response headers with x-proxy-header- prefix
-        for (name, value) in upstream_parts.headers.iter() {
+        for (name, value) in upstream_headers.iter() {
             let prefixed = format!("x-proxy-header-{}", name.as_str());
             if let Ok(header_name) = prefixed.parse::<HeaderName>() {
                 response_headers.insert(header_name, value.clone());
@@ -134,10 +509,174 @@ where
     }
 }
 
+impl<C> Proxy<C> {
+    /// Determine the target of a proxy request and check it against the policy.
+    async fn target(&self, parts: &http::request::Parts) -> Result<Uri> {
+        // Extract target URL from path (everything after /.proxy/)
+        let path = parts.uri.path();
+        let mut target_url = path.strip_prefix("/.proxy/").unwrap_or(path).to_string();
+
+        // Append query parameters if present
+        if let Some(query) = parts.uri.query() {
+            target_url.push('?');
+            target_url.push_str(query);
+        }
+
+        target_url = self.schemes.apply(&target_url, &parts.headers);
+
+        let target: Uri = target_url
+            .parse()
+            .map_err(|_| Error::InvalidUrl(target_url.clone()))?;
+
+        self.policy.check(&parts.method, &target)?;
+        self.check_resolved(&target).await?;
+
+        Ok(target)
+    }
+
+    /// Send a request, applying timeout, retries and circuit breaker.
+    async fn send(
+        &self,
+        method: Method,
+        uri: Uri,
+        headers: HeaderMap,
+        body: Bytes,
+    ) -> Result<Response<Bytes>>
+    where
+        C: Client,
+    {
+        let host = uri.host().unwrap_or_default().to_ascii_lowercase();
+        let retries = self.retry.retries(&method);
+        let mut attempt = 0;
+
+        loop {
+            if let Some(breaker) = &self.breaker {
+                breaker.check(&host)?;
+            }
+
+            let mut request = Request::builder()
+                .method(method.clone())
+                .uri(uri.clone())
+                .body(body.clone())?;
+
+            *request.headers_mut() = headers.clone();
+            self.secrets.apply(&uri, request.headers_mut());
+
+            // fetch derives it from the URL, and refuses it as a header
+            #[cfg(not(target_arch = "wasm32"))]
+            if let Some(host) = host_header(&uri) {
+                request.headers_mut().insert(http::header::HOST, host);
+            }
+
+            if let Some(limit) = self.max_response_size {
+                request.extensions_mut().insert(ResponseLimit(limit));
+            }
+
+            let result = self.send_once(request).await;
+
+            let failed = match &result {
+                Ok(response) => retry::is_transient(response.status()),
+                Err(err) => matches!(err, Error::Client(_) | Error::Timeout(_)),
+            };
+
+            if let Some(breaker) = &self.breaker {
+                match (failed, &result) {
+                    (true, _) => breaker.record_failure(&host),
+                    (false, Ok(_)) => breaker.record_success(&host),
+                    (false, Err(_)) => {}
+                }
+            }
+
+            if !failed || attempt >= retries {
+                return result;
+            }
+
+            attempt += 1;
+            futures_timer::Delay::new(self.retry.delay(attempt)).await;
+        }
+    }
+
+    async fn send_once(&self, request: Request<Bytes>) -> Result<Response<Bytes>>
+    where
+        C: Client,
+    {
+        let response = match self.timeout {
+            None => self.client.send(request).await?,
+            Some(timeout) => match future::select(
+                self.client.send(request),
+                futures_timer::Delay::new(timeout),
+            )
+            .await
+            {
+                Either::Left((result, _)) => result?,
+                Either::Right(_) => return Err(Error::Timeout(timeout)),
+            },
+        };
+
+        // Clients may not honor the limit while reading
+        if let Some(limit) = self.max_response_size
+            && response.body().len() > limit
+        {
+            return Err(Error::ResponseTooLarge(limit));
+        }
+
+        Ok(response)
+    }
+
+    fn check_content_type(&self, parts: &http::response::Parts, body: &Bytes) -> Result<()> {
+        let Some(patterns) = &self.allowed_content_types else {
+            return Ok(());
+        };
+
+        if body.is_empty() {
+            return Ok(());
+        }
+
+        let media_type = parts
+            .headers
+            .get(http::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.split(';').next())
+            .map(|v| v.trim().to_ascii_lowercase())
+            .unwrap_or_default();
+
+        if patterns
+            .iter()
+            .any(|pattern| crate::glob::matches(pattern, &media_type))
+        {
+            Ok(())
+        } else {
+            Err(Error::ContentTypeNotAllowed(media_type))
+        }
+    }
+
+    async fn check_resolved(&self, target: &Uri) -> Result<()> {
+        let (Some(resolver), Some(host)) = (&self.resolver, target.host()) else {
+            return Ok(());
+        };
+
+        // IP literals are already checked by the policy
+        if !self.policy.checks_addresses()
+            || host
+                .trim_matches(['[', ']'])
+                .parse::<std::net::IpAddr>()
+                .is_ok()
+        {
+            return Ok(());
+        }
+
+        for addr in resolver.resolve(host).await? {
+            self.policy.check_addr(addr)?;
+        }
+
+        Ok(())
+    }
+}
+
 fn filter_proxy_headers(headers: &HeaderMap) -> HeaderMap {
     use std::str::FromStr;
 
-    headers
+    let mut filtered: HeaderMap = headers
         .iter()
         .filter_map(|(name, value)| {
             let name_str = name.as_str();
@@ -150,7 +689,40 @@ fn filter_proxy_headers(headers: &HeaderMap) -> HeaderMap {
                 None
             }
         })
-        .collect()
+        .collect();
+
+    // The connection to the upstream is the proxy's, and so is its host and framing
+    strip_hop_by_hop(&mut filtered);
+    filtered.remove(http::header::HOST);
+    filtered.remove(http::header::CONTENT_LENGTH);
+
+    filtered
+}
+
+/// Remove the [`HOP_BY_HOP`] headers, and the ones listed by `Connection`.
+fn strip_hop_by_hop(headers: &mut HeaderMap) {
+    let listed: Vec<HeaderName> = headers
+        .get_all(http::header::CONNECTION)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|name| name.trim().parse().ok())
+        .collect();
+
+    for name in listed.iter().chain(&HOP_BY_HOP) {
+        headers.remove(name);
+    }
+}
+
+/// `Host` of a request to `uri`, its authority without the credentials.
+#[cfg(not(target_arch = "wasm32"))]
+fn host_header(uri: &Uri) -> Option<HeaderValue> {
+    let authority = uri.authority()?.as_str();
+    let host = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+
+    HeaderValue::from_str(host).ok()
 }
 
 fn adjust_scheme(url: &str) -> String {
@@ -221,6 +793,50 @@ impl Client for NoProxy {
 mod tests {
     use super::*;
 
+    #[test]
+    fn categorizes_failures() {
+        let client = |err: std::io::Error| Error::Client(Box::new(err));
+
+        assert_eq!(
+            client(std::io::ErrorKind::ConnectionRefused.into()).category(),
+            Some(Category::Connect)
+        );
+        assert_eq!(
+            client(std::io::Error::other(
+                "dns error: failed to lookup address information"
+            ))
+            .category(),
+            Some(Category::Dns)
+        );
+        assert_eq!(
+            client(std::io::Error::other(
+                "invalid peer certificate: UnknownIssuer"
+            ))
+            .category(),
+            Some(Category::Tls)
+        );
+        assert_eq!(
+            Error::Timeout(Duration::from_secs(30)).category(),
+            Some(Category::Timeout)
+        );
+        assert_eq!(Error::Forbidden("host".to_string()).category(), None);
+    }
+
+    #[test]
+    fn finds_upstream_hosts() {
+        let host = |path: &str| upstream_host(&path.parse().unwrap());
+
+        assert_eq!(
+            host("/.proxy/Example.com/feed.xml").as_deref(),
+            Some("example.com")
+        );
+        assert_eq!(
+            host("/.proxy/https://user@api.example.com:8443/v1").as_deref(),
+            Some("api.example.com:8443")
+        );
+        assert_eq!(host("/.fs/index.md"), None);
+    }
+
     #[test]
     fn test_filter_proxy_headers() {
         let mut headers = HeaderMap::new();
@@ -392,6 +1008,451 @@ mod tests {
         assert_eq!(response.status(), StatusCode::OK);
     }
 
+    #[tokio::test]
+    async fn test_proxy_rejects_disallowed_host() {
+        let client = MockClient {
+            response: Response::new(Bytes::new()),
+        };
+        let proxy = Proxy::new(client).policy(Policy::new().allow_host("example.com"));
+
+        let request = Request::builder()
+            .uri("/.proxy/evil.com/")
+            .body(Bytes::new())
+            .unwrap();
+
+        let result = proxy.proxy(request).await;
+        assert!(matches!(result, Err(Error::Forbidden(_))));
+    }
+
+    struct StaticResolver(Vec<std::net::IpAddr>);
+
+    #[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+    #[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+    impl dns::Resolver for StaticResolver {
+        async fn resolve(&self, _host: &str) -> Result<Vec<std::net::IpAddr>> {
+            Ok(self.0.clone())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_proxy_rejects_host_resolving_to_private_address() {
+        let client = MockClient {
+            response: Response::new(Bytes::new()),
+        };
+        let proxy = Proxy::new(client)
+            .policy(Policy::new().deny_private(true))
+            .resolver(StaticResolver(vec![
+                "93.184.216.34".parse().unwrap(),
+                "127.0.0.1".parse().unwrap(),
+            ]));
+
+        let request = Request::builder()
+            .uri("/.proxy/rebind.example.com/")
+            .body(Bytes::new())
+            .unwrap();
+
+        let result = proxy.proxy(request).await;
+        assert!(matches!(result, Err(Error::Forbidden(_))));
+    }
+
+    #[tokio::test]
+    async fn test_proxy_allows_host_resolving_to_public_address() {
+        let client = MockClient {
+            response: Response::new(Bytes::new()),
+        };
+        let proxy = Proxy::new(client)
+            .policy(Policy::new().deny_private(true))
+            .resolver(StaticResolver(vec!["93.184.216.34".parse().unwrap()]));
+
+        let request = Request::builder()
+            .uri("/.proxy/example.com/")
+            .body(Bytes::new())
+            .unwrap();
+
+        assert!(proxy.proxy(request).await.is_ok());
+    }
+
+    struct RecordingClient(std::sync::Mutex<Vec<Uri>>);
+
+    #[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+    #[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+    impl Client for RecordingClient {
+        async fn send(&self, request: Request<Bytes>) -> Result<Response<Bytes>> {
+            self.0.lock().unwrap().push(request.uri().clone());
+            Ok(Response::new(Bytes::new()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_proxy_scheme_selection() {
+        let proxy = Proxy::new(RecordingClient(Default::default()))
+            .schemes(scheme::Rules::new().host("*.internal.example.com", scheme::Scheme::Http));
+
+        for request in [
+            Request::builder().uri("/.proxy/http://example.com/a?b=c"),
+            Request::builder()
+                .uri("/.proxy/example.com/a")
+                .header(scheme::SCHEME_HEADER, "http"),
+            Request::builder().uri("/.proxy/wiki.internal.example.com/a"),
+            Request::builder().uri("/.proxy/example.com/a"),
+        ] {
+            proxy
+                .proxy(request.body(Bytes::new()).unwrap())
+                .await
+                .unwrap();
+        }
+
+        let uris: Vec<_> = proxy
+            .client
+            .0
+            .lock()
+            .unwrap()
+            .iter()
+            .map(Uri::to_string)
+            .collect();
+        assert_eq!(
+            uris,
+            [
+                "http://example.com/a?b=c",
+                "http://example.com/a",
+                "http://wiki.internal.example.com/a",
+                "https://example.com/a",
+            ]
+        );
+    }
+
+    /// Client answering with the given statuses in turn, or hanging once they run out
+    struct SequenceClient {
+        statuses: std::sync::Mutex<Vec<StatusCode>>,
+        calls: std::sync::atomic::AtomicUsize,
+    }
+
+    impl SequenceClient {
+        fn new(statuses: impl IntoIterator<Item = StatusCode>) -> Self {
+            let mut statuses: Vec<_> = statuses.into_iter().collect();
+            statuses.reverse();
+
+            Self {
+                statuses: std::sync::Mutex::new(statuses),
+                calls: Default::default(),
+            }
+        }
+
+        fn calls(&self) -> usize {
+            self.calls.load(std::sync::atomic::Ordering::SeqCst)
+        }
+    }
+
+    #[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+    #[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+    impl Client for SequenceClient {
+        async fn send(&self, _request: Request<Bytes>) -> Result<Response<Bytes>> {
+            self.calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+
+            let status = self.statuses.lock().unwrap().pop();
+
+            match status {
+                Some(status) => Ok(Response::builder()
+                    .status(status)
+                    .body(Bytes::new())
+                    .unwrap()),
+                None => future::pending().await,
+            }
+        }
+    }
+
+    fn request(method: Method) -> Request<Bytes> {
+        Request::builder()
+            .method(method)
+            .uri("/.proxy/example.com/")
+            .body(Bytes::new())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_proxy_timeout() {
+        let proxy = Proxy::new(SequenceClient::new([])).timeout(Duration::from_millis(10));
+
+        let result = proxy.proxy(request(Method::GET)).await;
+        assert!(matches!(result, Err(Error::Timeout(_))));
+    }
+
+    #[tokio::test]
+    async fn test_proxy_retries_idempotent_requests() {
+        let retry = Retry::new(2).base_delay(Duration::from_millis(1));
+
+        let proxy = Proxy::new(SequenceClient::new([
+            StatusCode::SERVICE_UNAVAILABLE,
+            StatusCode::BAD_GATEWAY,
+            StatusCode::OK,
+        ]))
+        .retry(retry.clone());
+
+        let response = proxy.proxy(request(Method::GET)).await.unwrap();
+        assert_eq!(response.headers()["x-proxy-status-code"], "200");
+        assert_eq!(proxy.client.calls(), 3);
+
+        let proxy = Proxy::new(SequenceClient::new([
+            StatusCode::SERVICE_UNAVAILABLE,
+            StatusCode::OK,
+        ]))
+        .retry(retry);
+
+        let response = proxy.proxy(request(Method::POST)).await.unwrap();
+        assert_eq!(response.headers()["x-proxy-status-code"], "503");
+        assert_eq!(proxy.client.calls(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_proxy_circuit_breaker() {
+        let breaker = circuit::Breaker::new().failure_threshold(2);
+
+        for _ in 0..2 {
+            let proxy = Proxy::new(SequenceClient::new([StatusCode::BAD_GATEWAY]))
+                .circuit_breaker(breaker.clone());
+
+            assert!(proxy.proxy(request(Method::GET)).await.is_ok());
+        }
+
+        let proxy =
+            Proxy::new(SequenceClient::new([StatusCode::OK])).circuit_breaker(breaker.clone());
+
+        let result = proxy.proxy(request(Method::GET)).await;
+        assert!(matches!(result, Err(Error::CircuitOpen(_))));
+        assert_eq!(proxy.client.calls(), 0);
+    }
+
+    /// Client redirecting `/redirect` to the `to` query parameter
+    struct RedirectClient(std::sync::Mutex<Vec<(Method, Uri, HeaderMap)>>);
+
+    #[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+    #[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+    impl Client for RedirectClient {
+        async fn send(&self, request: Request<Bytes>) -> Result<Response<Bytes>> {
+            let uri = request.uri().clone();
+
+            self.0.lock().unwrap().push((
+                request.method().clone(),
+                uri.clone(),
+                request.headers().clone(),
+            ));
+
+            let response = match uri.query().and_then(|q| q.strip_prefix("to=")) {
+                Some(to) if uri.path() == "/redirect" => Response::builder()
+                    .status(StatusCode::FOUND)
+                    .header(http::header::LOCATION, to),
+                _ => Response::builder().status(StatusCode::OK),
+            };
+
+            Ok(response.body(Bytes::new()).unwrap())
+        }
+    }
+
+    fn redirect_request(method: Method, to: &str) -> Request<Bytes> {
+        Request::builder()
+            .method(method)
+            .uri(format!("/.proxy/example.com/redirect?to={to}"))
+            .header("x-proxy-header-authorization", "Bearer token")
+            .body(Bytes::from("body"))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn strips_hop_by_hop_headers() {
+        let proxy = Proxy::new(RedirectClient(Default::default()));
+
+        let request = Request::builder()
+            .uri("/.proxy/user@example.com/api")
+            .header("x-proxy-header-accept", "application/json")
+            .header("x-proxy-header-connection", "close, x-secret")
+            .header("x-proxy-header-x-secret", "smuggled")
+            .header("x-proxy-header-transfer-encoding", "chunked")
+            .header("x-proxy-header-host", "internal.example.com")
+            .body(Bytes::new())
+            .unwrap();
+        proxy.proxy(request).await.unwrap();
+
+        let (_, _, headers) = proxy.client.0.lock().unwrap().remove(0);
+        assert_eq!(headers.get("accept").unwrap(), "application/json");
+        assert_eq!(headers.get("host").unwrap(), "example.com");
+        assert_eq!(headers.len(), 2, "{headers:?}");
+
+        let upstream = Response::builder()
+            .header("connection", "keep-alive, x-hop")
+            .header("keep-alive", "timeout=5")
+            .header("x-hop", "1")
+            .header("x-custom", "value")
+            .body(Bytes::new())
+            .unwrap();
+        let proxy = Proxy::new(MockClient { response: upstream });
+        let request = Request::get("/.proxy/example.com")
+            .body(Bytes::new())
+            .unwrap();
+        let response = proxy.proxy(request).await.unwrap();
+
+        assert!(response.headers().get("x-proxy-header-x-custom").is_some());
+        assert!(
+            response
+                .headers()
+                .get("x-proxy-header-connection")
+                .is_none()
+        );
+        assert!(
+            response
+                .headers()
+                .get("x-proxy-header-keep-alive")
+                .is_none()
+        );
+        assert!(response.headers().get("x-proxy-header-x-hop").is_none());
+    }
+
+    #[tokio::test]
+    async fn forwards_client_address() {
+        let request = || {
+            let mut request = Request::get("/.proxy/example.com")
+                .header("x-proxy-header-x-forwarded-for", "10.0.0.1")
+                .body(Bytes::new())
+                .unwrap();
+            request
+                .extensions_mut()
+                .insert(ClientAddr("203.0.113.7".parse().unwrap()));
+            request
+        };
+
+        let proxy = Proxy::new(RedirectClient(Default::default())).forwarded_for(true);
+        proxy.proxy(request()).await.unwrap();
+        let (_, _, headers) = proxy.client.0.lock().unwrap().remove(0);
+        assert_eq!(headers.get("x-forwarded-for").unwrap(), "203.0.113.7");
+
+        // Disabled, the client's own header is passed on
+        let proxy = Proxy::new(RedirectClient(Default::default()));
+        proxy.proxy(request()).await.unwrap();
+        let (_, _, headers) = proxy.client.0.lock().unwrap().remove(0);
+        assert_eq!(headers.get("x-forwarded-for").unwrap(), "10.0.0.1");
+    }
+
+    #[tokio::test]
+    async fn test_proxy_follows_redirects() {
+        let proxy = Proxy::new(RedirectClient(Default::default()));
+
+        let response = proxy
+            .proxy(redirect_request(Method::POST, "https://other.com/done"))
+            .await
+            .unwrap();
+
+        assert_eq!(response.headers()["x-proxy-status-code"], "200");
+
+        let requests = proxy.client.0.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+
+        let (method, uri, headers) = &requests[1];
+        assert_eq!(method, Method::GET);
+        assert_eq!(uri, "https://other.com/done");
+        assert!(headers.get(http::header::AUTHORIZATION).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_proxy_rewrites_unfollowed_redirects() {
+        let proxy =
+            Proxy::new(RedirectClient(Default::default())).policy(Policy::new().max_redirects(0));
+
+        let response = proxy
+            .proxy(redirect_request(Method::GET, "/elsewhere"))
+            .await
+            .unwrap();
+
+        assert_eq!(response.headers()["x-proxy-status-code"], "302");
+        assert_eq!(
+            response.headers()["x-proxy-header-location"],
+            "/.proxy/https://example.com/elsewhere"
+        );
+        assert_eq!(proxy.client.0.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_proxy_redirects_cannot_escape_policy() {
+        let proxy = Proxy::new(RedirectClient(Default::default()))
+            .policy(Policy::new().allow_host("example.com"));
+
+        let result = proxy
+            .proxy(redirect_request(Method::GET, "http://169.254.169.254/"))
+            .await;
+
+        assert!(matches!(result, Err(Error::Forbidden(_))));
+        assert_eq!(proxy.client.0.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_proxy_injects_secrets_per_host() {
+        let proxy = Proxy::new(RedirectClient(Default::default())).secrets(
+            Secrets::new()
+                .header("example.com", "x-api-key", "secret")
+                .unwrap(),
+        );
+
+        proxy
+            .proxy(redirect_request(Method::GET, "https://other.com/"))
+            .await
+            .unwrap();
+
+        let requests = proxy.client.0.lock().unwrap();
+        assert_eq!(requests[0].2["x-api-key"], "secret");
+        assert!(requests[1].2.get("x-api-key").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_proxy_response_size_limit() {
+        let client = MockClient {
+            response: Response::new(Bytes::from("0123456789")),
+        };
+
+        let proxy = Proxy::new(client).max_response_size(5);
+        let result = proxy.proxy(request(Method::GET)).await;
+        assert!(matches!(result, Err(Error::ResponseTooLarge(5))));
+
+        let proxy = Proxy::new(RecordingLimitClient::default()).max_response_size(5);
+        proxy.proxy(request(Method::GET)).await.unwrap();
+        assert_eq!(*proxy.client.0.lock().unwrap(), Some(ResponseLimit(5)));
+    }
+
+    #[derive(Default)]
+    struct RecordingLimitClient(std::sync::Mutex<Option<ResponseLimit>>);
+
+    #[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+    #[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+    impl Client for RecordingLimitClient {
+        async fn send(&self, request: Request<Bytes>) -> Result<Response<Bytes>> {
+            *self.0.lock().unwrap() = request.extensions().get().copied();
+            Ok(Response::new(Bytes::new()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_proxy_content_type_filter() {
+        let response = |content_type: &str, body: &'static str| MockClient {
+            response: Response::builder()
+                .header(http::header::CONTENT_TYPE, content_type)
+                .body(Bytes::from(body))
+                .unwrap(),
+        };
+
+        let allowed = ["application/json", "image/*"];
+
+        for content_type in ["application/json; charset=utf-8", "image/PNG"] {
+            let proxy = Proxy::new(response(content_type, "x")).allow_content_types(allowed);
+            assert!(proxy.proxy(request(Method::GET)).await.is_ok());
+        }
+
+        let proxy = Proxy::new(response("text/html", "x")).allow_content_types(allowed);
+        assert!(matches!(
+            proxy.proxy(request(Method::GET)).await,
+            Err(Error::ContentTypeNotAllowed(_))
+        ));
+
+        let proxy = Proxy::new(response("text/html", "")).allow_content_types(allowed);
+        assert!(proxy.proxy(request(Method::GET)).await.is_ok());
+    }
+
     #[tokio::test]
     async fn test_proxy_wraps_upstream_status() {
         let mock_response = Response::builder()
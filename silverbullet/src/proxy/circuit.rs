@@ -0,0 +1,150 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+
+#[cfg(target_arch = "wasm32")]
+use web_time::Instant;
+
+use super::{Error, Result};
+
+const DEFAULT_FAILURE_THRESHOLD: u32 = 5;
+const DEFAULT_COOLDOWN: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Default)]
+struct Host {
+    failures: u32,
+    opened: Option<Instant>,
+    probing: bool,
+}
+
+/// Circuit breaker tracking upstream failures per host
+///
+/// After `failure_threshold` consecutive failures requests to the host are rejected
+/// for `cooldown`. Afterwards a single request is let through: success closes the
+/// circuit again, failure keeps it open for another cooldown.
+///
+/// Clones share their state, so a breaker kept in the application state applies
+/// across requests.
+#[derive(Debug, Clone)]
+pub struct Breaker {
+    hosts: Arc<Mutex<HashMap<String, Host>>>,
+    failure_threshold: u32,
+    cooldown: Duration,
+}
+
+impl Default for Breaker {
+    fn default() -> Self {
+        Self {
+            hosts: Arc::default(),
+            failure_threshold: DEFAULT_FAILURE_THRESHOLD,
+            cooldown: DEFAULT_COOLDOWN,
+        }
+    }
+}
+
+impl Breaker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of consecutive failures opening the circuit.
+    #[must_use]
+    pub fn failure_threshold(mut self, failures: u32) -> Self {
+        self.failure_threshold = failures.max(1);
+        self
+    }
+
+    /// Time the circuit stays open before a request is let through again.
+    #[must_use]
+    pub fn cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+
+    /// Check whether a request to the host may be sent.
+    pub fn check(&self, host: &str) -> Result<()> {
+        let mut hosts = self.hosts.lock().unwrap_or_else(|e| e.into_inner());
+
+        let Some(state) = hosts.get_mut(host) else {
+            return Ok(());
+        };
+
+        match state.opened {
+            None => Ok(()),
+            Some(opened) if opened.elapsed() >= self.cooldown && !state.probing => {
+                state.probing = true;
+                Ok(())
+            }
+            Some(_) => Err(Error::CircuitOpen(host.to_string())),
+        }
+    }
+
+    pub fn record_success(&self, host: &str) {
+        self.hosts
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(host);
+    }
+
+    pub fn record_failure(&self, host: &str) {
+        let mut hosts = self.hosts.lock().unwrap_or_else(|e| e.into_inner());
+        let state = hosts.entry(host.to_string()).or_default();
+
+        state.failures += 1;
+        state.probing = false;
+
+        if state.opened.is_some() || state.failures >= self.failure_threshold {
+            state.opened = Some(Instant::now());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opens_after_threshold() {
+        let breaker = Breaker::new().failure_threshold(2);
+
+        breaker.record_failure("a");
+        assert!(breaker.check("a").is_ok());
+
+        breaker.record_failure("a");
+        assert!(matches!(breaker.check("a"), Err(Error::CircuitOpen(_))));
+        assert!(breaker.check("b").is_ok());
+    }
+
+    #[test]
+    fn success_resets_failures() {
+        let breaker = Breaker::new().failure_threshold(2);
+
+        breaker.record_failure("a");
+        breaker.record_success("a");
+        breaker.record_failure("a");
+
+        assert!(breaker.check("a").is_ok());
+    }
+
+    #[test]
+    fn half_open_after_cooldown() {
+        let breaker = Breaker::new().failure_threshold(1).cooldown(Duration::ZERO);
+
+        breaker.record_failure("a");
+
+        // A single probe is let through
+        assert!(breaker.check("a").is_ok());
+        assert!(breaker.check("a").is_err());
+
+        // A failing probe opens the circuit again
+        breaker.record_failure("a");
+        assert!(breaker.check("a").is_ok());
+
+        breaker.record_success("a");
+        assert!(breaker.check("a").is_ok());
+        assert!(breaker.check("a").is_ok());
+    }
+}
@@ -0,0 +1,83 @@
+use async_trait::async_trait;
+use bytes::Bytes;
+use http::{HeaderName, HeaderValue, Request, Response, StatusCode};
+use worker::{Fetch, Headers, RequestInit, RequestRedirect};
+
+use super::{Error, ResponseLimit, Result};
+use crate::proxy;
+
+/// Client sending requests with the Workers Fetch API
+///
+/// Redirects are returned as is, the proxy follows them itself to apply its policy.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Client;
+
+impl Client {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+fn client_error(err: worker::Error) -> Error {
+    Error::Client(err.to_string().into())
+}
+
+#[async_trait(?Send)]
+impl proxy::Client for Client {
+    async fn send(&self, request: Request<Bytes>) -> Result<Response<Bytes>> {
+        let limit = request.extensions().get::<ResponseLimit>().copied();
+        let (parts, body) = request.into_parts();
+
+        let headers = Headers::new();
+        for (name, value) in &parts.headers {
+            let value = value
+                .to_str()
+                .map_err(|_| Error::Client(format!("invalid header value for {name}").into()))?;
+            headers.append(name.as_str(), value).map_err(client_error)?;
+        }
+
+        let mut init = RequestInit::new();
+        init.with_method(worker::Method::from(parts.method.to_string()))
+            .with_headers(headers)
+            .with_redirect(RequestRedirect::Manual);
+
+        if !body.is_empty() {
+            init.with_body(Some(worker::js_sys::Uint8Array::from(body.as_ref()).into()));
+        }
+
+        let request =
+            worker::Request::new_with_init(&parts.uri.to_string(), &init).map_err(client_error)?;
+
+        let mut upstream = Fetch::Request(request).send().await.map_err(client_error)?;
+
+        if let Some(ResponseLimit(limit)) = limit {
+            let length = upstream.headers().get("content-length").ok().flatten();
+            if length.and_then(|len| len.parse::<u64>().ok()) > Some(limit as u64) {
+                return Err(Error::ResponseTooLarge(limit));
+            }
+        }
+
+        let body = upstream.bytes().await.map_err(client_error)?;
+
+        if let Some(ResponseLimit(limit)) = limit
+            && body.len() > limit
+        {
+            return Err(Error::ResponseTooLarge(limit));
+        }
+
+        let mut response = Response::new(Bytes::from(body));
+        *response.status_mut() =
+            StatusCode::from_u16(upstream.status_code()).unwrap_or(StatusCode::BAD_GATEWAY);
+
+        for (name, value) in upstream.headers().entries() {
+            if let (Ok(name), Ok(value)) = (
+                HeaderName::try_from(name.as_str()),
+                HeaderValue::try_from(value.as_str()),
+            ) {
+                response.headers_mut().append(name, value);
+            }
+        }
+
+        Ok(response)
+    }
+}
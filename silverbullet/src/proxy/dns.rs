@@ -0,0 +1,147 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use async_trait::async_trait;
+
+use super::Result;
+
+/// Resolves host names to the addresses a client would connect to
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+pub trait Resolver: Send + Sync {
+    async fn resolve(&self, host: &str) -> Result<Vec<IpAddr>>;
+}
+
+/// Resolver using the operating system's name resolution
+#[cfg(all(not(target_arch = "wasm32"), feature = "dns"))]
+#[derive(Debug, Default)]
+pub struct SystemResolver;
+
+#[cfg(all(not(target_arch = "wasm32"), feature = "dns"))]
+#[async_trait]
+impl Resolver for SystemResolver {
+    async fn resolve(&self, host: &str) -> Result<Vec<IpAddr>> {
+        Ok(tokio::net::lookup_host((host, 0))
+            .await?
+            .map(|addr| addr.ip())
+            .collect())
+    }
+}
+
+/// Whether the address is not publicly routable (loopback, private, link-local, etc.)
+///
+/// IPv6 addresses embedding an IPv4 one, mapped, compatible, NAT64 or 6to4, are private
+/// when the embedded address is.
+pub fn is_private(addr: IpAddr) -> bool {
+    match addr {
+        IpAddr::V4(v4) => {
+            let [a, b, ..] = v4.octets();
+
+            v4.is_loopback()
+                || v4.is_private()
+                || v4.is_link_local()
+                || v4.is_broadcast()
+                || v4.is_multicast()
+                // "This network", routed to the host itself on Linux
+                || a == 0
+                // Shared address space (carrier-grade NAT)
+                || (a == 100 && (64..128).contains(&b))
+                // Benchmarking
+                || (a == 198 && (18..20).contains(&b))
+                // Reserved
+                || a >= 240
+        }
+        IpAddr::V6(v6) => {
+            if let Some(v4) = embedded_v4(v6) {
+                return is_private(IpAddr::V4(v4));
+            }
+
+            let first = v6.segments()[0];
+
+            v6.is_loopback()
+                || v6.is_unspecified()
+                || v6.is_multicast()
+                // Unique local (fc00::/7)
+                || (first & 0xfe00) == 0xfc00
+                // Link-local (fe80::/10)
+                || (first & 0xffc0) == 0xfe80
+        }
+    }
+}
+
+/// IPv4 address embedded in an IPv4-mapped, IPv4-compatible, NAT64 (`64:ff9b::/96`) or
+/// 6to4 (`2002::/16`) address.
+fn embedded_v4(v6: Ipv6Addr) -> Option<Ipv4Addr> {
+    let segments = v6.segments();
+    let octets = v6.octets();
+
+    if let Some(v4) = v6.to_ipv4_mapped() {
+        return Some(v4);
+    }
+
+    match segments {
+        // Compatible, but not :: and ::1
+        [0, 0, 0, 0, 0, 0, high, _] if high != 0 => Some(v4(&octets[12..])),
+        [0x64, 0xff9b, 0, 0, 0, 0, ..] => Some(v4(&octets[12..])),
+        [0x2002, ..] => Some(v4(&octets[2..6])),
+        _ => None,
+    }
+}
+
+fn v4(octets: &[u8]) -> Ipv4Addr {
+    Ipv4Addr::new(octets[0], octets[1], octets[2], octets[3])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn private(addr: &str) -> bool {
+        is_private(addr.parse().unwrap())
+    }
+
+    #[test]
+    fn private_v4() {
+        assert!(private("127.0.0.1"));
+        assert!(private("10.1.2.3"));
+        assert!(private("172.16.0.1"));
+        assert!(private("192.168.1.1"));
+        assert!(private("169.254.169.254"));
+        assert!(private("100.64.0.1"));
+        assert!(private("0.0.0.0"));
+        assert!(private("0.1.2.3"));
+        assert!(private("224.0.0.1"));
+        assert!(private("239.255.255.250"));
+        assert!(private("198.18.0.1"));
+        assert!(private("198.19.255.255"));
+        assert!(private("240.0.0.1"));
+        assert!(private("255.255.255.255"));
+
+        assert!(!private("8.8.8.8"));
+        assert!(!private("172.32.0.1"));
+        assert!(!private("100.128.0.1"));
+        assert!(!private("198.20.0.1"));
+        assert!(!private("223.255.255.255"));
+    }
+
+    #[test]
+    fn private_v6() {
+        assert!(private("::1"));
+        assert!(private("::"));
+        assert!(private("fd00::1"));
+        assert!(private("fe80::1"));
+        assert!(private("::ffff:127.0.0.1"));
+        assert!(private("ff02::1"));
+        assert!(private("::127.0.0.1"));
+        assert!(private("::10.0.0.1"));
+        assert!(private("64:ff9b::7f00:1"));
+        assert!(private("64:ff9b::a9fe:a9fe"));
+        assert!(private("2002:7f00:1::"));
+        assert!(private("2002:c0a8:101::1"));
+
+        assert!(!private("2606:4700:4700::1111"));
+        assert!(!private("::ffff:8.8.8.8"));
+        assert!(!private("::8.8.8.8"));
+        assert!(!private("64:ff9b::808:808"));
+        assert!(!private("2002:808:808::1"));
+    }
+}
@@ -0,0 +1,112 @@
+use async_trait::async_trait;
+use bytes::Bytes;
+use http::{HeaderName, HeaderValue, Request, Response, StatusCode};
+use js_sys::{Array, Promise, Uint8Array};
+use wasm_bindgen::{JsCast, JsValue, prelude::wasm_bindgen};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{Headers, RequestInit, RequestRedirect};
+
+use super::{Error, ResponseLimit, Result};
+use crate::proxy;
+
+#[wasm_bindgen]
+extern "C" {
+    // The global `fetch`, of windows and workers alike
+    #[wasm_bindgen(js_name = fetch)]
+    fn fetch_with_request(request: &web_sys::Request) -> Promise;
+}
+
+/// Client sending requests with the Fetch API of browsers and other JS runtimes
+///
+/// Redirects are returned as is, the proxy follows them itself to apply its policy. Only
+/// the headers allowed by CORS are readable on cross-origin responses.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Client;
+
+impl Client {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+fn client_error(err: JsValue) -> Error {
+    Error::Client(format!("{err:?}").into())
+}
+
+#[async_trait(?Send)]
+impl proxy::Client for Client {
+    async fn send(&self, request: Request<Bytes>) -> Result<Response<Bytes>> {
+        let limit = request.extensions().get::<ResponseLimit>().copied();
+        let (parts, body) = request.into_parts();
+
+        let headers = Headers::new().map_err(client_error)?;
+        for (name, value) in &parts.headers {
+            let value = value
+                .to_str()
+                .map_err(|_| Error::Client(format!("invalid header value for {name}").into()))?;
+            headers.append(name.as_str(), value).map_err(client_error)?;
+        }
+
+        let init = RequestInit::new();
+        init.set_method(parts.method.as_str());
+        init.set_headers(&headers);
+        init.set_redirect(RequestRedirect::Manual);
+
+        if !body.is_empty() {
+            init.set_body(&Uint8Array::from(body.as_ref()));
+        }
+
+        let request = web_sys::Request::new_with_str_and_init(&parts.uri.to_string(), &init)
+            .map_err(client_error)?;
+
+        let upstream: web_sys::Response = JsFuture::from(fetch_with_request(&request))
+            .await
+            .map_err(client_error)?
+            .dyn_into()
+            .map_err(client_error)?;
+
+        if let Some(ResponseLimit(limit)) = limit {
+            let length = upstream.headers().get("content-length").ok().flatten();
+            if length.and_then(|len| len.parse::<u64>().ok()) > Some(limit as u64) {
+                return Err(Error::ResponseTooLarge(limit));
+            }
+        }
+
+        let buffer = JsFuture::from(upstream.array_buffer().map_err(client_error)?)
+            .await
+            .map_err(client_error)?;
+        let body = Uint8Array::new(&buffer).to_vec();
+
+        if let Some(ResponseLimit(limit)) = limit
+            && body.len() > limit
+        {
+            return Err(Error::ResponseTooLarge(limit));
+        }
+
+        let mut response = Response::new(Bytes::from(body));
+        *response.status_mut() =
+            StatusCode::from_u16(upstream.status()).unwrap_or(StatusCode::BAD_GATEWAY);
+
+        let entries = js_sys::try_iter(&upstream.headers())
+            .map_err(client_error)?
+            .into_iter()
+            .flatten();
+
+        for entry in entries {
+            let entry: Array = entry.map_err(client_error)?.unchecked_into();
+            let (Some(name), Some(value)) = (entry.get(0).as_string(), entry.get(1).as_string())
+            else {
+                continue;
+            };
+
+            if let (Ok(name), Ok(value)) = (
+                HeaderName::try_from(name.as_str()),
+                HeaderValue::try_from(value.as_str()),
+            ) {
+                response.headers_mut().append(name, value);
+            }
+        }
+
+        Ok(response)
+    }
+}
@@ -0,0 +1,217 @@
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::task::{self, Poll};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use http::{Request, Response};
+use http_body_util::{BodyExt, Full, LengthLimitError, Limited};
+use hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::client::legacy::connect::dns::Name;
+use hyper_util::rt::TokioExecutor;
+
+use super::{Error, Policy, ResponseLimit, Result};
+use crate::proxy::{self, dns::Resolver as _};
+
+type Connector = HttpsConnector<HttpConnector<GuardedResolver>>;
+
+/// Proxy client using hyper and rustls directly
+pub struct Client {
+    client: hyper_util::client::legacy::Client<Connector, Full<Bytes>>,
+}
+
+impl Client {
+    pub fn new() -> Self {
+        Self::with_resolver(GuardedResolver { policy: None })
+    }
+
+    /// Create a client that checks every address it connects to against the policy.
+    ///
+    /// See `proxy::reqwest::Client::guarded`.
+    pub fn guarded(policy: Policy) -> Self {
+        Self::with_resolver(GuardedResolver {
+            policy: Some(policy),
+        })
+    }
+
+    fn with_resolver(resolver: GuardedResolver) -> Self {
+        let mut http = HttpConnector::new_with_resolver(resolver);
+        http.enforce_http(false);
+
+        let connector = HttpsConnectorBuilder::new()
+            .with_provider_and_webpki_roots(rustls::crypto::ring::default_provider())
+            .expect("failed to configure TLS")
+            .https_or_http()
+            .enable_all_versions()
+            .wrap_connector(http);
+
+        let client =
+            hyper_util::client::legacy::Client::builder(TokioExecutor::new()).build(connector);
+
+        Self { client }
+    }
+
+    /// Send a request without buffering the response body.
+    pub async fn stream(
+        &self,
+        request: Request<Bytes>,
+    ) -> Result<Response<::hyper::body::Incoming>> {
+        self.client
+            .request(request.map(Full::new))
+            .await
+            .map_err(|e| Error::Client(Box::new(e)))
+    }
+}
+
+impl Default for Client {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl proxy::Client for Client {
+    async fn send(&self, request: Request<Bytes>) -> Result<Response<Bytes>> {
+        let limit = request.extensions().get::<ResponseLimit>().copied();
+        let (parts, body) = self.stream(request).await?.into_parts();
+
+        let body = match limit {
+            None => body
+                .collect()
+                .await
+                .map_err(|e| Error::Client(Box::new(e)))?
+                .to_bytes(),
+            Some(ResponseLimit(limit)) => Limited::new(body, limit)
+                .collect()
+                .await
+                .map_err(|e| match e.downcast::<LengthLimitError>() {
+                    Ok(_) => Error::ResponseTooLarge(limit),
+                    Err(e) => Error::Client(e),
+                })?
+                .to_bytes(),
+        };
+
+        Ok(Response::from_parts(parts, body))
+    }
+}
+
+/// DNS resolver optionally filtering resolved addresses through a [`Policy`]
+#[derive(Clone)]
+struct GuardedResolver {
+    policy: Option<Policy>,
+}
+
+impl tower_service::Service<Name> for GuardedResolver {
+    type Response = std::vec::IntoIter<SocketAddr>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut task::Context<'_>) -> Poll<Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, name: Name) -> Self::Future {
+        let policy = self.policy.clone();
+
+        Box::pin(async move {
+            let addrs = proxy::dns::SystemResolver.resolve(name.as_str()).await?;
+
+            if let Some(policy) = policy {
+                addrs.iter().try_for_each(|addr| policy.check_addr(*addr))?;
+            }
+
+            Ok(addrs
+                .into_iter()
+                .map(|addr| SocketAddr::new(addr, 0))
+                .collect::<Vec<_>>()
+                .into_iter())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::Client as _;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn sends_request() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = [0; 1024];
+            let _ = socket.read(&mut buf).await.unwrap();
+            socket
+                .write_all(b"HTTP/1.1 201 Created\r\ncontent-length: 5\r\nx-test: yes\r\n\r\nhello")
+                .await
+                .unwrap();
+        });
+
+        let request = Request::builder()
+            .uri(format!("http://{addr}/"))
+            .body(Bytes::new())
+            .unwrap();
+
+        let response = Client::new().send(request).await.unwrap();
+
+        assert_eq!(response.status(), 201);
+        assert_eq!(response.headers()["x-test"], "yes");
+        assert_eq!(response.body(), "hello");
+    }
+
+    #[tokio::test]
+    async fn enforces_response_limit() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = [0; 1024];
+            let _ = socket.read(&mut buf).await.unwrap();
+            socket
+                .write_all(b"HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\n\r\n5\r\nhello\r\n5\r\nworld\r\n0\r\n\r\n")
+                .await
+                .unwrap();
+        });
+
+        let mut request = Request::builder()
+            .uri(format!("http://{addr}/"))
+            .body(Bytes::new())
+            .unwrap();
+        request.extensions_mut().insert(ResponseLimit(8));
+
+        let result = Client::new().send(request).await;
+        assert!(matches!(result, Err(Error::ResponseTooLarge(8))));
+    }
+
+    #[tokio::test]
+    async fn guarded_rejects_denied_addresses() {
+        let request = Request::builder()
+            .uri("http://localhost:1/")
+            .body(Bytes::new())
+            .unwrap();
+
+        let client = Client::guarded(Policy::new().deny_private(true));
+
+        let Err(Error::Client(err)) = client.send(request).await else {
+            panic!("expected client error");
+        };
+
+        let mut source: Option<&(dyn std::error::Error + 'static)> = Some(err.as_ref());
+        let mut forbidden = false;
+
+        while let Some(err) = source {
+            forbidden |= err
+                .downcast_ref::<Error>()
+                .is_some_and(|err| matches!(err, Error::Forbidden(_)));
+            source = err.source();
+        }
+
+        assert!(forbidden);
+    }
+}
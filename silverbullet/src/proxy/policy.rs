@@ -0,0 +1,281 @@
+use std::net::IpAddr;
+
+use http::{Method, Uri};
+use ipnet::IpNet;
+
+use super::{Error, Result, dns};
+use crate::glob;
+
+/// Default number of redirects followed for a single proxied request
+const DEFAULT_MAX_REDIRECTS: usize = 10;
+
+/// Methods forwarded by default
+pub const DEFAULT_METHODS: [Method; 5] = [
+    Method::GET,
+    Method::POST,
+    Method::PUT,
+    Method::DELETE,
+    Method::HEAD,
+];
+
+/// Rules deciding which requests may be forwarded by the proxy
+///
+/// The default policy allows every host and the [`DEFAULT_METHODS`]. Restrict it with
+/// [`Policy::allow_host`], [`Policy::deny_network`] and [`Policy::allow_methods`]. Other
+/// methods, e.g. `TRACE`, are only forwarded once allowed, or with
+/// [`Policy::allow_any_method`].
+#[derive(Debug, Clone)]
+pub struct Policy {
+    allowed_hosts: Vec<String>,
+    denied_networks: Vec<IpNet>,
+    allowed_networks: Vec<IpNet>,
+    deny_private: bool,
+    allowed_methods: Option<Vec<Method>>,
+    max_redirects: usize,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Self {
+            allowed_hosts: Vec::new(),
+            denied_networks: Vec::new(),
+            allowed_networks: Vec::new(),
+            deny_private: false,
+            allowed_methods: Some(DEFAULT_METHODS.to_vec()),
+            max_redirects: DEFAULT_MAX_REDIRECTS,
+        }
+    }
+}
+
+impl Policy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only allow hosts matching one of the given globs (eg. `api.github.com` or `*.example.com`).
+    ///
+    /// Once a host is allowed, every other host is denied.
+    #[must_use]
+    pub fn allow_host(mut self, pattern: impl Into<String>) -> Self {
+        self.allowed_hosts.push(pattern.into().to_ascii_lowercase());
+        self
+    }
+
+    /// Deny targets whose address falls into the given network.
+    #[must_use]
+    pub fn deny_network(mut self, network: IpNet) -> Self {
+        self.denied_networks.push(network);
+        self
+    }
+
+    /// Deny targets resolving to loopback, private or link-local addresses.
+    ///
+    /// Use [`Policy::allow_network`] to make exceptions for specific local services.
+    #[must_use]
+    pub fn deny_private(mut self, deny: bool) -> Self {
+        self.deny_private = deny;
+        self
+    }
+
+    /// Allow addresses in the given network even if they are denied otherwise.
+    #[must_use]
+    pub fn allow_network(mut self, network: IpNet) -> Self {
+        self.allowed_networks.push(network);
+        self
+    }
+
+    /// Only allow the given methods, instead of the [`DEFAULT_METHODS`].
+    #[must_use]
+    pub fn allow_methods(mut self, methods: impl IntoIterator<Item = Method>) -> Self {
+        self.allowed_methods = Some(methods.into_iter().collect());
+        self
+    }
+
+    /// Forward every method, including `TRACE` and extension methods.
+    #[must_use]
+    pub fn allow_any_method(mut self) -> Self {
+        self.allowed_methods = None;
+        self
+    }
+
+    /// Methods forwarded, `None` for every method.
+    pub fn allowed_methods(&self) -> Option<&[Method]> {
+        self.allowed_methods.as_deref()
+    }
+
+    /// Maximum number of redirects followed for a single request.
+    ///
+    /// With `0` redirects are passed to the client, pointing back at the proxy.
+    #[must_use]
+    pub fn max_redirects(mut self, max: usize) -> Self {
+        self.max_redirects = max;
+        self
+    }
+
+    pub fn redirect_limit(&self) -> usize {
+        self.max_redirects
+    }
+
+    /// Check whether a request to the given target is allowed.
+    pub fn check(&self, method: &Method, uri: &Uri) -> Result<()> {
+        if let Some(methods) = &self.allowed_methods
+            && !methods.contains(method)
+        {
+            return Err(Error::Forbidden(format!("method {method} is not allowed")));
+        }
+
+        let host = uri
+            .host()
+            .ok_or_else(|| Error::InvalidUrl(uri.to_string()))?
+            .to_ascii_lowercase();
+
+        if !self.is_host_allowed(&host) {
+            return Err(Error::Forbidden(format!("host {host} is not allowed")));
+        }
+
+        // IPv6 literals are bracketed in URIs
+        if let Ok(addr) = host.trim_start_matches('[').trim_end_matches(']').parse() {
+            self.check_addr(addr)?;
+        }
+
+        Ok(())
+    }
+
+    /// Whether addresses have to be checked after resolving the target host.
+    pub fn checks_addresses(&self) -> bool {
+        self.deny_private || !self.denied_networks.is_empty()
+    }
+
+    /// Check whether connecting to the given address is allowed.
+    pub fn check_addr(&self, addr: IpAddr) -> Result<()> {
+        if self.allowed_networks.iter().any(|net| net.contains(&addr)) {
+            return Ok(());
+        }
+
+        if self.deny_private && dns::is_private(addr) {
+            return Err(Error::Forbidden(format!("address {addr} is not public")));
+        }
+
+        match self.denied_networks.iter().find(|net| net.contains(&addr)) {
+            Some(network) => Err(Error::Forbidden(format!(
+                "address {addr} is in denied network {network}"
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    fn is_host_allowed(&self, host: &str) -> bool {
+        self.allowed_hosts.is_empty()
+            || self
+                .allowed_hosts
+                .iter()
+                .any(|pattern| glob::matches(pattern, host))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uri(uri: &str) -> Uri {
+        uri.parse().unwrap()
+    }
+
+    #[test]
+    fn default_allows_every_host_and_common_methods() {
+        let policy = Policy::default();
+
+        assert!(
+            policy
+                .check(&Method::GET, &uri("https://example.com"))
+                .is_ok()
+        );
+        assert!(
+            policy
+                .check(&Method::DELETE, &uri("http://10.0.0.1/"))
+                .is_ok()
+        );
+        assert!(matches!(
+            policy.check(&Method::TRACE, &uri("https://example.com")),
+            Err(Error::Forbidden(_))
+        ));
+
+        let policy = policy.allow_any_method();
+        assert!(
+            policy
+                .check(&Method::TRACE, &uri("https://example.com"))
+                .is_ok()
+        );
+        assert!(policy.allowed_methods().is_none());
+    }
+
+    #[test]
+    fn allowed_hosts() {
+        let policy = Policy::new()
+            .allow_host("api.github.com")
+            .allow_host("*.Example.com");
+
+        assert!(
+            policy
+                .check(&Method::GET, &uri("https://api.github.com/repos"))
+                .is_ok()
+        );
+        assert!(
+            policy
+                .check(&Method::GET, &uri("https://www.example.com"))
+                .is_ok()
+        );
+        assert!(matches!(
+            policy.check(&Method::GET, &uri("https://example.org")),
+            Err(Error::Forbidden(_))
+        ));
+    }
+
+    #[test]
+    fn denied_networks() {
+        let policy = Policy::new()
+            .deny_network("10.0.0.0/8".parse().unwrap())
+            .deny_network("::1/128".parse().unwrap());
+
+        assert!(matches!(
+            policy.check(&Method::GET, &uri("http://10.1.2.3:8080/")),
+            Err(Error::Forbidden(_))
+        ));
+        assert!(matches!(
+            policy.check(&Method::GET, &uri("http://[::1]:3000/")),
+            Err(Error::Forbidden(_))
+        ));
+        assert!(
+            policy
+                .check(&Method::GET, &uri("http://192.168.1.1/"))
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn deny_private_with_exceptions() {
+        let policy = Policy::new()
+            .deny_private(true)
+            .allow_network("192.168.1.10/32".parse().unwrap());
+
+        assert!(policy.check_addr("127.0.0.1".parse().unwrap()).is_err());
+        assert!(policy.check_addr("192.168.1.11".parse().unwrap()).is_err());
+        assert!(policy.check_addr("192.168.1.10".parse().unwrap()).is_ok());
+        assert!(policy.check_addr("8.8.8.8".parse().unwrap()).is_ok());
+    }
+
+    #[test]
+    fn allowed_methods() {
+        let policy = Policy::new().allow_methods([Method::GET, Method::HEAD]);
+
+        assert!(
+            policy
+                .check(&Method::GET, &uri("https://example.com"))
+                .is_ok()
+        );
+        assert!(matches!(
+            policy.check(&Method::DELETE, &uri("https://example.com")),
+            Err(Error::Forbidden(_))
+        ));
+    }
+}
@@ -0,0 +1,104 @@
+use http::{HeaderMap, Method, StatusCode, Uri, header};
+
+/// Whether the status is a redirect carrying a `Location` to follow.
+pub(crate) fn is_redirect(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::MOVED_PERMANENTLY
+            | StatusCode::FOUND
+            | StatusCode::SEE_OTHER
+            | StatusCode::TEMPORARY_REDIRECT
+            | StatusCode::PERMANENT_REDIRECT
+    )
+}
+
+/// Resolve the `Location` header of a redirect against the URI that was requested.
+pub(crate) fn location(base: &Uri, headers: &HeaderMap) -> Option<Uri> {
+    let location = headers.get(header::LOCATION)?.to_str().ok()?;
+
+    resolve(base, location)
+}
+
+fn resolve(base: &Uri, location: &str) -> Option<Uri> {
+    let scheme = base.scheme_str()?;
+    let authority = base.authority()?.as_str();
+
+    let resolved = if location.contains("://") {
+        location.to_string()
+    } else if let Some(rest) = location.strip_prefix("//") {
+        format!("{scheme}://{rest}")
+    } else if location.starts_with('/') {
+        format!("{scheme}://{authority}{location}")
+    } else {
+        let dir = base.path().rsplit_once('/').map_or("", |(dir, _)| dir);
+        format!("{scheme}://{authority}{dir}/{location}")
+    };
+
+    resolved.parse().ok()
+}
+
+/// Method of the request following a redirect.
+///
+/// `303` always switches to `GET`, as do `301` and `302` for `POST` (like browsers do).
+pub(crate) fn method(status: StatusCode, method: &Method) -> Method {
+    match status {
+        StatusCode::SEE_OTHER if method != Method::HEAD => Method::GET,
+        StatusCode::MOVED_PERMANENTLY | StatusCode::FOUND if method == Method::POST => Method::GET,
+        _ => method.clone(),
+    }
+}
+
+/// Express the target as a path under `/.proxy/`, so clients following it stay on the proxy.
+pub(crate) fn rewrite(base_path: &str, target: &Uri) -> String {
+    let path = target.path_and_query().map_or("/", |pq| pq.as_str());
+
+    match (target.scheme_str(), target.authority()) {
+        (Some(scheme), Some(authority)) => {
+            format!("{base_path}/.proxy/{scheme}://{authority}{path}")
+        }
+        _ => target.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolved(base: &str, location: &str) -> String {
+        resolve(&base.parse().unwrap(), location)
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn resolves_locations() {
+        let base = "https://example.com/a/b?c=d";
+
+        assert_eq!(resolved(base, "http://other.com/x"), "http://other.com/x");
+        assert_eq!(resolved(base, "//other.com/x"), "https://other.com/x");
+        assert_eq!(resolved(base, "/x?y=z"), "https://example.com/x?y=z");
+        assert_eq!(resolved(base, "x"), "https://example.com/a/x");
+    }
+
+    #[test]
+    fn switches_method() {
+        assert_eq!(method(StatusCode::SEE_OTHER, &Method::PUT), Method::GET);
+        assert_eq!(method(StatusCode::FOUND, &Method::POST), Method::GET);
+        assert_eq!(
+            method(StatusCode::TEMPORARY_REDIRECT, &Method::POST),
+            Method::POST
+        );
+    }
+
+    #[test]
+    fn rewrites_into_proxy_path() {
+        assert_eq!(
+            rewrite("", &"http://example.com:8080/a?b=c".parse().unwrap()),
+            "/.proxy/http://example.com:8080/a?b=c"
+        );
+        assert_eq!(
+            rewrite("/notes", &"https://example.com".parse().unwrap()),
+            "/notes/.proxy/https://example.com/"
+        );
+    }
+}
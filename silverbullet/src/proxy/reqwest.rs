@@ -1,23 +1,267 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
 use async_trait::async_trait;
-use bytes::Bytes;
+use bytes::{Bytes, BytesMut};
 use http::{Request, Response};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use super::{Error, Policy, ResponseLimit, Result};
+use crate::proxy::{self, dns::Resolver as _};
 
-use super::{Error, Result};
-use crate::proxy;
+pub use reqwest::{Certificate, Proxy};
 
+/// Proxy client using reqwest
+///
+/// Clones share the connection pool, so a server builds one client and hands out clones
+/// of it rather than building one per request, which would open new connections, and
+/// repeat the TLS handshakes, for every proxied request.
+#[derive(Clone)]
 pub struct Client {
     client: reqwest::Client,
+    hosts: Option<Arc<Hosts>>,
 }
 
 impl Client {
+    /// Wrap a reqwest client.
+    ///
+    /// The proxy follows redirects itself to apply its [`Policy`] to them, so the client
+    /// should be built with `reqwest::redirect::Policy::none()`.
     pub fn new(client: reqwest::Client) -> Self {
-        Self { client }
+        Self {
+            client,
+            hosts: None,
+        }
+    }
+
+    /// Configure a client, which doesn't follow redirects.
+    pub fn builder() -> Builder {
+        Builder::default()
+    }
+
+    /// Create a client that checks every address it connects to against the policy.
+    ///
+    /// Addresses are checked after resolution, right before connecting, which prevents
+    /// DNS rebinding between the proxy's own check and the connection.
+    pub fn guarded(policy: Policy) -> Self {
+        Self::builder()
+            .guarded(policy)
+            .build()
+            .expect("failed to build reqwest client")
     }
 }
 
 impl Default for Client {
     fn default() -> Self {
-        Self::new(reqwest::Client::new())
+        Self::builder()
+            .build()
+            .expect("failed to build reqwest client")
+    }
+}
+
+/// Configuration of a [`Client`]
+///
+/// Unset options keep the defaults of reqwest, e.g. 90 seconds for idle connections and
+/// the proxies of the `HTTP_PROXY`, `HTTPS_PROXY` and `NO_PROXY` variables.
+#[derive(Default)]
+pub struct Builder {
+    policy: Option<Policy>,
+    pool_max_idle_per_host: Option<usize>,
+    pool_idle_timeout: Option<Duration>,
+    max_connections_per_host: Option<usize>,
+    connect_timeout: Option<Duration>,
+    proxies: Option<Vec<Proxy>>,
+    root_certificates: Vec<Certificate>,
+    built_in_roots: Option<bool>,
+    #[cfg(feature = "native-tls")]
+    native_tls: bool,
+}
+
+impl Builder {
+    /// Check every address connected to against a policy, see [`Client::guarded`].
+    #[must_use]
+    pub fn guarded(mut self, policy: Policy) -> Self {
+        self.policy = Some(policy);
+        self
+    }
+
+    /// Idle connections kept open per host, for the next requests.
+    #[must_use]
+    pub fn pool_max_idle_per_host(mut self, max: usize) -> Self {
+        self.pool_max_idle_per_host = Some(max);
+        self
+    }
+
+    /// Time before closing an idle connection.
+    #[must_use]
+    pub fn pool_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.pool_idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Requests sent at once to a host, the next ones waiting for one to finish.
+    ///
+    /// reqwest opens a connection per request when none is idle, so this caps the
+    /// connections to a host too, e.g. to stay within the limits of an API.
+    #[must_use]
+    pub fn max_connections_per_host(mut self, max: usize) -> Self {
+        self.max_connections_per_host = Some(max);
+        self
+    }
+
+    #[must_use]
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Send the requests through a proxy, e.g. the HTTP proxy of a corporate network,
+    /// instead of the ones of the environment. May be called for several proxies, the
+    /// first one matching a request being used.
+    #[must_use]
+    pub fn proxy(mut self, proxy: Proxy) -> Self {
+        self.proxies.get_or_insert_default().push(proxy);
+        self
+    }
+
+    /// Connect directly, ignoring the proxies of the environment.
+    #[must_use]
+    pub fn no_proxy(mut self) -> Self {
+        self.proxies = Some(Vec::new());
+        self
+    }
+
+    /// Trust a certificate authority, e.g. the one of a TLS-inspecting corporate proxy.
+    #[must_use]
+    pub fn root_certificate(mut self, certificate: Certificate) -> Self {
+        self.root_certificates.push(certificate);
+        self
+    }
+
+    /// Trust the built-in root certificates, on by default: the Mozilla ones bundled with
+    /// rustls, or the ones of the system with [`native_tls`](Self::native_tls). Off, only
+    /// the [`root_certificate`](Self::root_certificate)s are trusted.
+    #[must_use]
+    pub fn built_in_roots(mut self, enabled: bool) -> Self {
+        self.built_in_roots = Some(enabled);
+        self
+    }
+
+    /// Use the TLS of the system, e.g. OpenSSL on Linux, instead of rustls.
+    #[cfg(feature = "native-tls")]
+    #[must_use]
+    pub fn native_tls(mut self, enabled: bool) -> Self {
+        self.native_tls = enabled;
+        self
+    }
+
+    /// Build the client, failing with [`Error::Client`] when reqwest can't, e.g. on an
+    /// invalid certificate.
+    pub fn build(self) -> Result<Client> {
+        let mut builder = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .use_rustls_tls();
+
+        #[cfg(feature = "native-tls")]
+        if self.native_tls {
+            builder = builder.use_native_tls();
+        }
+
+        if let Some(policy) = self.policy {
+            builder = builder.dns_resolver(Arc::new(GuardedResolver { policy }));
+        }
+
+        if let Some(max) = self.pool_max_idle_per_host {
+            builder = builder.pool_max_idle_per_host(max);
+        }
+
+        if let Some(timeout) = self.pool_idle_timeout {
+            builder = builder.pool_idle_timeout(timeout);
+        }
+
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+
+        if let Some(proxies) = self.proxies {
+            builder = builder.no_proxy();
+
+            for proxy in proxies {
+                builder = builder.proxy(proxy);
+            }
+        }
+
+        for certificate in self.root_certificates {
+            builder = builder.add_root_certificate(certificate);
+        }
+
+        if let Some(enabled) = self.built_in_roots {
+            builder = builder.tls_built_in_root_certs(enabled);
+        }
+
+        let client = builder.build().map_err(|e| Error::Client(Box::new(e)))?;
+
+        Ok(Client {
+            client,
+            hosts: self
+                .max_connections_per_host
+                .map(|max| Arc::new(Hosts::new(max))),
+        })
+    }
+}
+
+/// Requests in flight by host, with at most `max` at once
+struct Hosts {
+    max: usize,
+    hosts: Mutex<HashMap<String, Arc<Semaphore>>>,
+}
+
+/// Slot of a request to a host, freed when dropped
+struct Slot<'a> {
+    hosts: &'a Hosts,
+    host: String,
+    permit: Option<OwnedSemaphorePermit>,
+}
+
+impl Hosts {
+    fn new(max: usize) -> Self {
+        Self {
+            max: max.max(1),
+            hosts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    async fn acquire(&self, host: &str) -> Slot<'_> {
+        let semaphore = self
+            .hosts
+            .lock()
+            .unwrap()
+            .entry(host.to_string())
+            .or_insert_with(|| Arc::new(Semaphore::new(self.max)))
+            .clone();
+
+        Slot {
+            hosts: self,
+            host: host.to_string(),
+            // The semaphores are never closed
+            permit: semaphore.acquire_owned().await.ok(),
+        }
+    }
+}
+
+impl Drop for Slot<'_> {
+    fn drop(&mut self) {
+        let mut hosts = self.hosts.hosts.lock().unwrap();
+        drop(self.permit.take());
+
+        // Forget the hosts no request is waiting for, held by the map only
+        if hosts
+            .get(&self.host)
+            .is_some_and(|semaphore| Arc::strong_count(semaphore) == 1)
+        {
+            hosts.remove(&self.host);
+        }
     }
 }
 
@@ -25,6 +269,14 @@ impl Default for Client {
 #[cfg_attr(not(target_arch = "wasm32"), async_trait)]
 impl proxy::Client for Client {
     async fn send(&self, request: Request<Bytes>) -> Result<Response<Bytes>> {
+        let limit = request.extensions().get::<ResponseLimit>().copied();
+
+        // Held until the whole body is read
+        let _slot = match (&self.hosts, request.uri().authority()) {
+            (Some(hosts), Some(authority)) => Some(hosts.acquire(authority.as_str()).await),
+            _ => None,
+        };
+
         // Convert http::Request to reqwest::Request
         let (parts, body) = request.into_parts();
 
@@ -35,12 +287,34 @@ impl proxy::Client for Client {
             .body(body);
 
         // Send request
-        let resp = req.send().await.map_err(|e| Error::Client(Box::new(e)))?;
+        let mut resp = req.send().await.map_err(|e| Error::Client(Box::new(e)))?;
 
         // Build http::Response
         let status = resp.status();
         let headers = resp.headers().clone();
-        let body = resp.bytes().await.map_err(|e| Error::Client(Box::new(e)))?;
+
+        let body = match limit {
+            None => resp.bytes().await.map_err(|e| Error::Client(Box::new(e)))?,
+            Some(ResponseLimit(limit)) => {
+                if resp.content_length().is_some_and(|len| len > limit as u64) {
+                    return Err(Error::ResponseTooLarge(limit));
+                }
+
+                let mut body = BytesMut::new();
+
+                while let Some(chunk) =
+                    resp.chunk().await.map_err(|e| Error::Client(Box::new(e)))?
+                {
+                    if body.len() + chunk.len() > limit {
+                        return Err(Error::ResponseTooLarge(limit));
+                    }
+
+                    body.extend_from_slice(&chunk);
+                }
+
+                body.freeze()
+            }
+        };
 
         let mut response = Response::builder().status(status).body(body)?;
 
@@ -49,3 +323,71 @@ impl proxy::Client for Client {
         Ok(response)
     }
 }
+
+/// DNS resolver filtering resolved addresses through a [`Policy`]
+struct GuardedResolver {
+    policy: Policy,
+}
+
+impl reqwest::dns::Resolve for GuardedResolver {
+    fn resolve(&self, name: reqwest::dns::Name) -> reqwest::dns::Resolving {
+        let policy = self.policy.clone();
+
+        Box::pin(async move {
+            let addrs = proxy::dns::SystemResolver.resolve(name.as_str()).await?;
+
+            addrs.iter().try_for_each(|addr| policy.check_addr(*addr))?;
+
+            let addrs: reqwest::dns::Addrs = Box::new(
+                addrs
+                    .into_iter()
+                    .map(|addr| std::net::SocketAddr::new(addr, 0)),
+            );
+
+            Ok(addrs)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::FutureExt as _;
+
+    #[tokio::test]
+    async fn limits_requests_per_host() {
+        let hosts = Hosts::new(1);
+
+        let first = hosts.acquire("example.com").await;
+        assert!(hosts.acquire("example.com").now_or_never().is_none());
+        assert!(hosts.acquire("example.org").now_or_never().is_some());
+
+        drop(first);
+        let next = hosts.acquire("example.com").now_or_never();
+        assert!(next.is_some());
+
+        // Hosts without requests are forgotten
+        drop(next);
+        assert!(hosts.hosts.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn builds_clients() {
+        Client::builder()
+            .pool_max_idle_per_host(4)
+            .pool_idle_timeout(Duration::from_secs(30))
+            .max_connections_per_host(8)
+            .proxy(Proxy::all("http://proxy.internal:3128").unwrap())
+            .build()
+            .unwrap();
+
+        Client::builder()
+            .no_proxy()
+            .built_in_roots(false)
+            .build()
+            .unwrap();
+
+        #[cfg(feature = "native-tls")]
+        Client::builder().native_tls(true).build().unwrap();
+    }
+}
@@ -0,0 +1,102 @@
+use std::time::Duration;
+
+use http::{Method, StatusCode};
+
+const DEFAULT_BASE_DELAY: Duration = Duration::from_millis(100);
+const DEFAULT_MAX_DELAY: Duration = Duration::from_secs(2);
+
+/// Bounded retries with exponential backoff
+///
+/// Only idempotent methods are retried, after client errors, timeouts and
+/// `502`, `503` or `504` responses. No retries are made by default.
+#[derive(Debug, Clone)]
+pub struct Retry {
+    max_retries: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+}
+
+impl Default for Retry {
+    fn default() -> Self {
+        Self {
+            max_retries: 0,
+            base_delay: DEFAULT_BASE_DELAY,
+            max_delay: DEFAULT_MAX_DELAY,
+        }
+    }
+}
+
+impl Retry {
+    pub fn new(max_retries: u32) -> Self {
+        Self {
+            max_retries,
+            ..Self::default()
+        }
+    }
+
+    /// Delay before the first retry, doubled for every following one.
+    #[must_use]
+    pub fn base_delay(mut self, delay: Duration) -> Self {
+        self.base_delay = delay;
+        self
+    }
+
+    /// Upper bound of the delay between retries.
+    #[must_use]
+    pub fn max_delay(mut self, delay: Duration) -> Self {
+        self.max_delay = delay;
+        self
+    }
+
+    /// Number of retries allowed for the given method.
+    pub fn retries(&self, method: &Method) -> u32 {
+        if method.is_idempotent() {
+            self.max_retries
+        } else {
+            0
+        }
+    }
+
+    /// Delay before the given retry (starting at 1).
+    pub fn delay(&self, retry: u32) -> Duration {
+        self.base_delay
+            .saturating_mul(2u32.saturating_pow(retry.saturating_sub(1)))
+            .min(self.max_delay)
+    }
+}
+
+/// Whether an upstream response indicates a transient failure.
+pub(crate) fn is_transient(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_idempotent_methods_are_retried() {
+        let retry = Retry::new(3);
+
+        assert_eq!(retry.retries(&Method::GET), 3);
+        assert_eq!(retry.retries(&Method::PUT), 3);
+        assert_eq!(retry.retries(&Method::POST), 0);
+        assert_eq!(retry.retries(&Method::PATCH), 0);
+    }
+
+    #[test]
+    fn exponential_backoff() {
+        let retry = Retry::new(5)
+            .base_delay(Duration::from_millis(100))
+            .max_delay(Duration::from_millis(500));
+
+        assert_eq!(retry.delay(1), Duration::from_millis(100));
+        assert_eq!(retry.delay(2), Duration::from_millis(200));
+        assert_eq!(retry.delay(3), Duration::from_millis(400));
+        assert_eq!(retry.delay(4), Duration::from_millis(500));
+        assert_eq!(retry.delay(40), Duration::from_millis(500));
+    }
+}
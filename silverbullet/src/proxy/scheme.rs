@@ -0,0 +1,219 @@
+use http::HeaderMap;
+
+use crate::glob;
+
+/// Header clients can set to choose the scheme of the proxied request
+pub const SCHEME_HEADER: &str = "x-proxy-scheme";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scheme {
+    Http,
+    Https,
+}
+
+impl Scheme {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scheme::Http => "http",
+            Scheme::Https => "https",
+        }
+    }
+}
+
+impl std::str::FromStr for Scheme {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "http" => Ok(Scheme::Http),
+            "https" => Ok(Scheme::Https),
+            other => Err(format!("unsupported scheme: {other}")),
+        }
+    }
+}
+
+/// Rules selecting the scheme used to reach a proxy target
+///
+/// In order of precedence the scheme is taken from:
+/// 1. an explicit `http://` or `https://` prefix of the target
+/// 2. the `x-proxy-scheme` request header (unless disabled)
+/// 3. the first matching host override
+/// 4. the default scheme, or `http` for local and private hosts and `https` otherwise
+#[derive(Debug, Clone)]
+pub struct Rules {
+    honor_header: bool,
+    overrides: Vec<(String, Scheme)>,
+    default: Option<Scheme>,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Self {
+            honor_header: true,
+            overrides: Vec::new(),
+            default: None,
+        }
+    }
+}
+
+impl Rules {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Honor the `x-proxy-scheme` request header (enabled by default).
+    #[must_use]
+    pub fn honor_header(mut self, honor: bool) -> Self {
+        self.honor_header = honor;
+        self
+    }
+
+    /// Use the given scheme for hosts matching the glob.
+    #[must_use]
+    pub fn host(mut self, pattern: impl Into<String>, scheme: Scheme) -> Self {
+        self.overrides
+            .push((pattern.into().to_ascii_lowercase(), scheme));
+        self
+    }
+
+    /// Use the given scheme when no other rule applies instead of guessing from the host.
+    #[must_use]
+    pub fn default_scheme(mut self, scheme: Scheme) -> Self {
+        self.default = Some(scheme);
+        self
+    }
+
+    /// Turn a proxy target (`host[:port][/path]`, optionally prefixed with a scheme) into a URL.
+    pub fn apply(&self, target: &str, headers: &HeaderMap) -> String {
+        if let Some((scheme, rest)) = explicit_scheme(target) {
+            return format!("{}://{}", scheme.as_str(), rest);
+        }
+
+        if self.honor_header
+            && let Some(scheme) = headers
+                .get(SCHEME_HEADER)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse::<Scheme>().ok())
+        {
+            return format!("{}://{}", scheme.as_str(), target);
+        }
+
+        let host = host(target).to_ascii_lowercase();
+
+        if let Some((_, scheme)) = self
+            .overrides
+            .iter()
+            .find(|(pattern, _)| glob::matches(pattern, &host))
+        {
+            return format!("{}://{}", scheme.as_str(), target);
+        }
+
+        match self.default {
+            Some(scheme) => format!("{}://{}", scheme.as_str(), target),
+            None => super::adjust_scheme(target),
+        }
+    }
+}
+
+/// Split a target of the form `http://…` or `https://…` into scheme and remainder.
+///
+/// Path normalization in front of the server may collapse `//`, so `http:/…` is accepted too.
+fn explicit_scheme(target: &str) -> Option<(Scheme, &str)> {
+    let (scheme, rest) = target.split_once(':')?;
+    let scheme = scheme.parse().ok()?;
+    let rest = rest.strip_prefix('/')?;
+
+    Some((scheme, rest.strip_prefix('/').unwrap_or(rest)))
+}
+
+fn host(target: &str) -> &str {
+    let authority = target.split('/').next().unwrap_or(target);
+
+    match authority.strip_prefix('[') {
+        Some(v6) => v6.split(']').next().unwrap_or(v6),
+        None => authority.split(':').next().unwrap_or(authority),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(scheme: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(SCHEME_HEADER, scheme.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn falls_back_to_heuristics() {
+        let rules = Rules::default();
+
+        assert_eq!(
+            rules.apply("example.com/a", &HeaderMap::new()),
+            "https://example.com/a"
+        );
+        assert_eq!(
+            rules.apply("localhost:3000", &HeaderMap::new()),
+            "http://localhost:3000"
+        );
+    }
+
+    #[test]
+    fn explicit_prefix_wins() {
+        let rules = Rules::new().default_scheme(Scheme::Https);
+
+        assert_eq!(
+            rules.apply("http://example.com/a", &headers("https")),
+            "http://example.com/a"
+        );
+        assert_eq!(
+            rules.apply("http:/example.com/a", &HeaderMap::new()),
+            "http://example.com/a"
+        );
+        assert_eq!(
+            rules.apply("https://localhost", &HeaderMap::new()),
+            "https://localhost"
+        );
+    }
+
+    #[test]
+    fn header_selects_scheme() {
+        assert_eq!(
+            Rules::default().apply("example.com", &headers("http")),
+            "http://example.com"
+        );
+        assert_eq!(
+            Rules::new()
+                .honor_header(false)
+                .apply("example.com", &headers("http")),
+            "https://example.com"
+        );
+    }
+
+    #[test]
+    fn host_overrides() {
+        let rules = Rules::new()
+            .host("*.lan.example.com", Scheme::Http)
+            .host("localhost", Scheme::Https);
+
+        assert_eq!(
+            rules.apply("nas.lan.example.com:8080/x", &HeaderMap::new()),
+            "http://nas.lan.example.com:8080/x"
+        );
+        assert_eq!(
+            rules.apply("localhost:8443", &HeaderMap::new()),
+            "https://localhost:8443"
+        );
+    }
+
+    #[test]
+    fn port_is_not_a_scheme() {
+        assert_eq!(
+            Rules::new()
+                .default_scheme(Scheme::Http)
+                .apply("example.com:8080/path", &HeaderMap::new()),
+            "http://example.com:8080/path"
+        );
+    }
+}
@@ -0,0 +1,103 @@
+use http::{HeaderMap, HeaderName, HeaderValue, Uri, header};
+
+use super::{Error, Result};
+use crate::glob;
+
+/// Credentials added server-side to requests for configured hosts
+///
+/// Injected headers replace any header of the same name sent by the client,
+/// and are only added for the host they are configured for, so they do not
+/// follow redirects to other hosts.
+#[derive(Debug, Clone, Default)]
+pub struct Secrets {
+    rules: Vec<(String, HeaderName, HeaderValue)>,
+}
+
+impl Secrets {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a header to requests for hosts matching the glob.
+    pub fn header(
+        mut self,
+        pattern: impl Into<String>,
+        name: impl AsRef<str>,
+        value: impl AsRef<str>,
+    ) -> Result<Self> {
+        let name = HeaderName::from_bytes(name.as_ref().as_bytes())
+            .map_err(|e| Error::Other(Box::new(e)))?;
+
+        let mut value =
+            HeaderValue::from_str(value.as_ref()).map_err(|e| Error::Other(Box::new(e)))?;
+        value.set_sensitive(true);
+
+        self.rules
+            .push((pattern.into().to_ascii_lowercase(), name, value));
+
+        Ok(self)
+    }
+
+    /// Add an `Authorization: Bearer …` header to requests for hosts matching the glob.
+    pub fn bearer(self, pattern: impl Into<String>, token: impl AsRef<str>) -> Result<Self> {
+        self.header(
+            pattern,
+            header::AUTHORIZATION,
+            format!("Bearer {}", token.as_ref()),
+        )
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Add the headers configured for the target's host.
+    pub fn apply(&self, target: &Uri, headers: &mut HeaderMap) {
+        let Some(host) = target.host().map(str::to_ascii_lowercase) else {
+            return;
+        };
+
+        for (pattern, name, value) in &self.rules {
+            if glob::matches(pattern, &host) {
+                headers.insert(name.clone(), value.clone());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn injects_for_matching_hosts() {
+        let secrets = Secrets::new()
+            .bearer("api.github.com", "gh-token")
+            .unwrap()
+            .header("*.example.com", "x-api-key", "key")
+            .unwrap();
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, "Bearer client".parse().unwrap());
+        secrets.apply(
+            &"https://api.github.com/user".parse().unwrap(),
+            &mut headers,
+        );
+
+        assert_eq!(headers[header::AUTHORIZATION], "Bearer gh-token");
+        assert!(headers[header::AUTHORIZATION].is_sensitive());
+        assert!(headers.get("x-api-key").is_none());
+
+        let mut headers = HeaderMap::new();
+        secrets.apply(&"https://WWW.example.com/".parse().unwrap(), &mut headers);
+
+        assert_eq!(headers["x-api-key"], "key");
+        assert!(headers.get(header::AUTHORIZATION).is_none());
+    }
+
+    #[test]
+    fn rejects_invalid_headers() {
+        assert!(Secrets::new().header("*", "bad header", "value").is_err());
+        assert!(Secrets::new().bearer("*", "bad\ntoken").is_err());
+    }
+}
@@ -0,0 +1,121 @@
+use std::sync::Arc;
+
+use http::{HeaderValue, Uri, header, request::Parts};
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest as _;
+use tokio_tungstenite::{Connector, MaybeTlsStream, WebSocketStream};
+
+use super::{Error, Proxy, Result};
+
+pub type Upstream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Whether the request asks to upgrade to a WebSocket connection.
+pub fn is_upgrade(headers: &http::HeaderMap) -> bool {
+    headers
+        .get(header::UPGRADE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.eq_ignore_ascii_case("websocket"))
+}
+
+impl<C> Proxy<C> {
+    /// Open a WebSocket connection to the target of an upgrade request.
+    ///
+    /// The target is checked against the policy like any other request. Requested
+    /// subprotocols and configured secrets are forwarded; the subprotocol accepted
+    /// by the upstream is returned alongside the connection.
+    pub async fn connect_websocket(
+        &self,
+        parts: &Parts,
+    ) -> Result<(Upstream, Option<HeaderValue>)> {
+        let target = websocket_uri(&self.target(parts).await?)?;
+
+        let mut request = target
+            .into_client_request()
+            .map_err(|e| Error::Client(Box::new(e)))?;
+
+        if let Some(protocols) = parts.headers.get(header::SEC_WEBSOCKET_PROTOCOL) {
+            request
+                .headers_mut()
+                .insert(header::SEC_WEBSOCKET_PROTOCOL, protocols.clone());
+        }
+
+        let uri = request.uri().clone();
+        self.secrets.apply(&uri, request.headers_mut());
+
+        let (upstream, response) = tokio_tungstenite::connect_async_tls_with_config(
+            request,
+            None,
+            false,
+            Some(connector()),
+        )
+        .await
+        .map_err(|e| Error::Client(Box::new(e)))?;
+
+        let protocol = response
+            .headers()
+            .get(header::SEC_WEBSOCKET_PROTOCOL)
+            .cloned();
+
+        Ok((upstream, protocol))
+    }
+}
+
+/// Map an `http(s)://` target to `ws(s)://`.
+fn websocket_uri(target: &Uri) -> Result<Uri> {
+    let scheme = match target.scheme_str() {
+        Some("https") => "wss",
+        _ => "ws",
+    };
+
+    let authority = target
+        .authority()
+        .ok_or_else(|| Error::InvalidUrl(target.to_string()))?;
+
+    let path = target.path_and_query().map_or("/", |pq| pq.as_str());
+
+    format!("{scheme}://{authority}{path}")
+        .parse()
+        .map_err(|_| Error::InvalidUrl(target.to_string()))
+}
+
+fn connector() -> Connector {
+    let roots = rustls::RootCertStore {
+        roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+    };
+
+    let config = rustls::ClientConfig::builder_with_provider(Arc::new(
+        rustls::crypto::ring::default_provider(),
+    ))
+    .with_safe_default_protocol_versions()
+    .expect("failed to configure TLS")
+    .with_root_certificates(roots)
+    .with_no_client_auth();
+
+    Connector::Rustls(Arc::new(config))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_schemes() {
+        assert_eq!(
+            websocket_uri(&"https://example.com/a?b".parse().unwrap()).unwrap(),
+            "wss://example.com/a?b"
+        );
+        assert_eq!(
+            websocket_uri(&"http://localhost:3000".parse().unwrap()).unwrap(),
+            "ws://localhost:3000/"
+        );
+    }
+
+    #[test]
+    fn detects_upgrades() {
+        let mut headers = http::HeaderMap::new();
+        assert!(!is_upgrade(&headers));
+
+        headers.insert(header::UPGRADE, "WebSocket".parse().unwrap());
+        assert!(is_upgrade(&headers));
+    }
+}
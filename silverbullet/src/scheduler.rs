@@ -0,0 +1,557 @@
+//! Jobs run on a schedule inside the server
+//!
+//! A [`Job`] runs a [`Task`] at the times of a cron [`Schedule`], e.g. a backup every
+//! night or a `git commit` of the space every hour, so self-hosters don't need a cron
+//! next to the server. Run the [`Scheduler`] alongside the server:
+//!
+//! ```ignore
+//! let scheduler = Scheduler::new()
+//!     .job(Job::new("backup", "0 3 * * *".parse()?, backup).jitter(Duration::from_secs(600)))
+//!     .job(Job::new("commit", "@hourly".parse()?, Command::new(shell, "git", ["commit", "-am", "Auto"])));
+//!
+//! tokio::spawn(scheduler.run());
+//! ```
+//!
+//! Schedules have the five fields of cron, minute, hour, day of the month, month and day
+//! of the week, in UTC, with `*`, lists, ranges, steps and the names of months and days,
+//! or one of `@hourly`, `@daily` (`@midnight`), `@weekly`, `@monthly` and `@yearly`
+//! (`@annually`). As in cron, a day matches either of its fields when both are set.
+//!
+//! A job starts at a random time within its jitter after each scheduled time, so many
+//! servers of a host don't all back up at once. It never overlaps itself: runs missed
+//! while it was still running are skipped. With the `tracing` feature, every run is in a
+//! `job` span with the name of the job, and logs its outcome.
+
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher as _;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use thiserror::Error;
+
+use crate::fs::time::{self, civil_from_days, days_from_civil};
+use crate::shell;
+
+/// Failure of a task
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Years searched for the next time of a schedule, e.g. only the 29th of February
+const HORIZON_DAYS: i64 = 5 * 366;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+#[error("Invalid schedule {schedule:?}: {reason}")]
+pub struct ParseError {
+    pub schedule: String,
+    pub reason: String,
+}
+
+/// Times of a job, as a cron expression
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schedule {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    /// Days of the week, Sunday being 0
+    weekdays: u64,
+    /// Whether the day of the month or the day of the week is `*`
+    any_day: bool,
+    any_weekday: bool,
+}
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+
+const WEEKDAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+impl FromStr for Schedule {
+    type Err = ParseError;
+
+    fn from_str(schedule: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: String| ParseError {
+            schedule: schedule.to_string(),
+            reason,
+        };
+
+        let expression = match schedule.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            macro_ if macro_.starts_with('@') => {
+                return Err(invalid(format!("unknown macro {macro_}")));
+            }
+            expression => expression,
+        };
+
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(invalid(format!("expected 5 fields, got {}", fields.len())));
+        };
+
+        // Sunday is both 0 and 7
+        let weekdays = field(weekday, 0, 7, &WEEKDAYS).map_err(invalid)?;
+        let weekdays = (weekdays | weekdays >> 7) & 0x7f;
+
+        Ok(Self {
+            minutes: field(minute, 0, 59, &[]).map_err(invalid)?,
+            hours: field(hour, 0, 23, &[]).map_err(invalid)?,
+            days: field(day, 1, 31, &[]).map_err(invalid)?,
+            months: field(month, 1, 12, &MONTHS).map_err(invalid)?,
+            weekdays,
+            any_day: day == "*",
+            any_weekday: weekday == "*",
+        })
+    }
+}
+
+/// Bits of the values of a field, `names` being the values from `min` on.
+fn field(field: &str, min: u8, max: u8, names: &[&str]) -> Result<u64, String> {
+    let value = |text: &str| -> Result<u8, String> {
+        let lower = text.to_ascii_lowercase();
+        let value = match names.iter().position(|name| *name == lower) {
+            Some(index) => min + index as u8,
+            None => text
+                .parse()
+                .map_err(|_| format!("invalid value {text:?}"))?,
+        };
+
+        match (min..=max).contains(&value) {
+            true => Ok(value),
+            false => Err(format!("{value} is not within {min}-{max}")),
+        }
+    };
+
+    let mut bits = 0;
+
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => match step.parse::<u8>() {
+                Ok(step) if step > 0 => (range, step),
+                _ => return Err(format!("invalid step {step:?}")),
+            },
+            None => (part, 1),
+        };
+
+        let (from, to) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((from, to)) => (value(from)?, value(to)?),
+            // `5/15` is from 5 to the end
+            None if step > 1 => (value(range)?, max),
+            None => {
+                let value = value(range)?;
+                (value, value)
+            }
+        };
+
+        if from > to {
+            return Err(format!("empty range {range}"));
+        }
+
+        for value in (from..=to).step_by(usize::from(step)) {
+            bits |= 1 << value;
+        }
+    }
+
+    Ok(bits)
+}
+
+impl Schedule {
+    fn matches_day(&self, day: u8, weekday: u8) -> bool {
+        let by_day = self.days & 1 << day != 0;
+        let by_weekday = self.weekdays & 1 << weekday != 0;
+
+        match (self.any_day, self.any_weekday) {
+            (false, false) => by_day || by_weekday,
+            _ => by_day && by_weekday,
+        }
+    }
+
+    /// First time of the schedule after `millis`, in milliseconds, `None` if there's none
+    /// within five years, e.g. for `0 0 30 2 *`.
+    pub fn next_after(&self, millis: u64) -> Option<u64> {
+        let mut minute = (millis / 60_000 + 1) as i64;
+        let horizon = minute / 1440 + HORIZON_DAYS;
+
+        loop {
+            let days = minute / 1440;
+            if days > horizon {
+                return None;
+            }
+
+            let (year, month, day) = civil_from_days(days);
+            if self.months & 1 << month == 0 {
+                let (year, month) = match month {
+                    12 => (year + 1, 1),
+                    month => (year, month + 1),
+                };
+                minute = days_from_civil(year, month, 1) * 1440;
+                continue;
+            }
+
+            // The epoch was a Thursday
+            let weekday = (days + 4).rem_euclid(7) as u8;
+            if !self.matches_day(day, weekday) {
+                minute = (days + 1) * 1440;
+                continue;
+            }
+
+            if self.hours & 1 << (minute % 1440 / 60) == 0 {
+                minute = (minute / 60 + 1) * 60;
+                continue;
+            }
+
+            if self.minutes & 1 << (minute % 60) == 0 {
+                minute += 1;
+                continue;
+            }
+
+            return Some(minute as u64 * 60_000);
+        }
+    }
+}
+
+/// Work of a job
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+pub trait Task: Send + Sync {
+    async fn run(&self) -> Result<(), BoxError>;
+}
+
+#[cfg(feature = "backup")]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl Task for crate::backup::Backup {
+    async fn run(&self) -> Result<(), BoxError> {
+        self.snapshot().await?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "git-sync")]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl Task for crate::sync::git::GitSync {
+    async fn run(&self) -> Result<(), BoxError> {
+        self.sync().await?;
+        Ok(())
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl Task for crate::gc::Collector {
+    async fn run(&self) -> Result<(), BoxError> {
+        self.collect(self.scheduled_mode()).await?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "stats")]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl Task for crate::stats::Stats {
+    async fn run(&self) -> Result<(), BoxError> {
+        self.record().await?;
+        Ok(())
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl Task for crate::fs::warm::Warmer {
+    async fn run(&self) -> Result<(), BoxError> {
+        self.warm().await?;
+        Ok(())
+    }
+}
+
+/// Command run with a [`Shell`](shell::Shell), failing unless it exits with 0
+pub struct Command {
+    shell: Arc<dyn shell::Shell>,
+    cmd: String,
+    args: Vec<String>,
+}
+
+impl Command {
+    pub fn new<I>(shell: Arc<dyn shell::Shell>, cmd: impl Into<String>, args: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        Self {
+            shell,
+            cmd: cmd.into(),
+            args: args.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl Task for Command {
+    async fn run(&self) -> Result<(), BoxError> {
+        let request = shell::Request {
+            cmd: self.cmd.clone(),
+            args: self.args.clone(),
+            stdin: None,
+        };
+
+        match self.shell.exec(request).await? {
+            response if response.code == 0 => Ok(()),
+            response => Err(format!(
+                "{} exited with {}: {}",
+                self.cmd,
+                response.code,
+                response.stderr.trim()
+            )
+            .into()),
+        }
+    }
+}
+
+/// Task run on a schedule
+#[derive(Clone)]
+pub struct Job {
+    name: String,
+    schedule: Schedule,
+    task: Arc<dyn Task>,
+    jitter: Duration,
+}
+
+impl Job {
+    pub fn new(name: impl Into<String>, schedule: Schedule, task: impl Task + 'static) -> Self {
+        Self {
+            name: name.into(),
+            schedule,
+            task: Arc::new(task),
+            jitter: Duration::ZERO,
+        }
+    }
+
+    /// Start each run at a random time within `jitter` of its time (none by default).
+    #[must_use]
+    pub fn jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Time of the next run after `millis`, jitter included.
+    fn next_after(&self, millis: u64) -> Option<u64> {
+        let next = self.schedule.next_after(millis)?;
+        let jitter = self.jitter.as_millis() as u64;
+
+        Some(match jitter {
+            0 => next,
+            jitter => next + RandomState::new().hash_one(next) % jitter,
+        })
+    }
+
+    /// Run the task once, now.
+    pub async fn run_once(&self) -> Result<(), BoxError> {
+        #[cfg(feature = "tracing")]
+        {
+            use tracing::Instrument as _;
+
+            let started = time::now();
+            let result = self
+                .task
+                .run()
+                .instrument(tracing::info_span!("job", name = %self.name))
+                .await;
+            let elapsed = time::now().saturating_sub(started);
+
+            match &result {
+                Ok(()) => tracing::info!(job = %self.name, elapsed_ms = elapsed, "Job done"),
+                Err(err) => {
+                    tracing::error!(job = %self.name, elapsed_ms = elapsed, error = %err, "Job failed")
+                }
+            }
+
+            result
+        }
+
+        #[cfg(not(feature = "tracing"))]
+        self.task.run().await
+    }
+
+    /// Run the task at every time of its schedule, forever.
+    ///
+    /// Failures are logged with the `tracing` feature, and the job goes on.
+    async fn run(self) {
+        while let Some(next) = self.next_after(time::now()) {
+            let wait = next.saturating_sub(time::now());
+            futures_timer::Delay::new(Duration::from_millis(wait)).await;
+
+            let _ = self.run_once().await;
+        }
+
+        #[cfg(feature = "tracing")]
+        tracing::warn!(job = %self.name, "Job has no next run, stopping");
+    }
+}
+
+/// Jobs of the server
+#[derive(Clone, Default)]
+pub struct Scheduler {
+    jobs: Vec<Job>,
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn job(mut self, job: Job) -> Self {
+        self.jobs.push(job);
+        self
+    }
+
+    pub fn jobs(&self) -> &[Job] {
+        &self.jobs
+    }
+
+    /// Run every job on its schedule, forever.
+    pub async fn run(self) {
+        futures::future::join_all(self.jobs.into_iter().map(Job::run)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    /// Milliseconds of a time in UTC
+    fn at(year: i64, month: u8, day: u8, hour: u64, minute: u64) -> u64 {
+        let days = days_from_civil(year, month, day) as u64;
+        ((days * 24 + hour) * 60 + minute) * 60_000
+    }
+
+    fn next(schedule: &str, after: u64) -> Option<u64> {
+        schedule.parse::<Schedule>().unwrap().next_after(after)
+    }
+
+    #[test]
+    fn finds_next_times() {
+        // Wednesday, 14 October 2026, 12:00
+        let now = at(2026, 10, 14, 12, 0);
+
+        assert_eq!(next("* * * * *", now), Some(at(2026, 10, 14, 12, 1)));
+        assert_eq!(
+            next("*/15 * * * *", now + 1),
+            Some(at(2026, 10, 14, 12, 15))
+        );
+        assert_eq!(next("0 3 * * *", now), Some(at(2026, 10, 15, 3, 0)));
+        assert_eq!(next("30 9 * * mon-fri", now), Some(at(2026, 10, 15, 9, 30)));
+        assert_eq!(next("0 0 * * 7", now), Some(at(2026, 10, 18, 0, 0)));
+        assert_eq!(next("@monthly", now), Some(at(2026, 11, 1, 0, 0)));
+        assert_eq!(next("@yearly", now), Some(at(2027, 1, 1, 0, 0)));
+        assert_eq!(next("0 12 29 feb *", now), Some(at(2028, 2, 29, 12, 0)));
+        assert_eq!(next("5/20 1,2 * * *", now), Some(at(2026, 10, 15, 1, 5)));
+
+        // Either day field matches when both are set: the 1st, or a Friday
+        assert_eq!(next("0 0 1 * fri", now), Some(at(2026, 10, 16, 0, 0)));
+
+        assert_eq!(next("0 0 30 2 *", now), None);
+    }
+
+    #[test]
+    fn rejects_invalid_schedules() {
+        for schedule in [
+            "* * * *",
+            "60 * * * *",
+            "* * 0 * *",
+            "*/0 * * * *",
+            "5-1 * * * *",
+            "@often",
+            "* * * foo *",
+        ] {
+            assert!(schedule.parse::<Schedule>().is_err(), "{schedule}");
+        }
+
+        assert_eq!(
+            "* * * * 8".parse::<Schedule>().unwrap_err().to_string(),
+            "Invalid schedule \"* * * * 8\": 8 is not within 0-7"
+        );
+    }
+
+    #[test]
+    fn jitters_runs() {
+        struct Nothing;
+
+        #[async_trait]
+        impl Task for Nothing {
+            async fn run(&self) -> Result<(), BoxError> {
+                Ok(())
+            }
+        }
+
+        let now = at(2026, 10, 14, 12, 0);
+        let job = Job::new("nothing", "@hourly".parse().unwrap(), Nothing);
+        assert_eq!(job.next_after(now), Some(at(2026, 10, 14, 13, 0)));
+
+        let job = job.jitter(Duration::from_secs(600));
+        let next = job.next_after(now).unwrap();
+        assert!((at(2026, 10, 14, 13, 0)..at(2026, 10, 14, 13, 10)).contains(&next));
+    }
+
+    #[tokio::test]
+    async fn runs_tasks() {
+        struct Counter(AtomicUsize);
+
+        #[async_trait]
+        impl Task for Arc<Counter> {
+            async fn run(&self) -> Result<(), BoxError> {
+                match self.0.fetch_add(1, Ordering::Relaxed) {
+                    0 => Ok(()),
+                    _ => Err("failed".into()),
+                }
+            }
+        }
+
+        let counter = Arc::new(Counter(AtomicUsize::new(0)));
+        let job = Job::new("count", "@daily".parse().unwrap(), counter.clone());
+
+        assert!(job.run_once().await.is_ok());
+        assert!(job.run_once().await.is_err());
+        assert_eq!(counter.0.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn runs_commands() {
+        struct Exit(u16);
+
+        #[async_trait]
+        impl shell::Shell for Exit {
+            async fn exec(&self, request: shell::Request) -> Result<shell::Response, shell::Error> {
+                assert_eq!(request.args, ["commit", "-am", "Auto"]);
+
+                Ok(shell::Response {
+                    code: self.0,
+                    stdout: String::new(),
+                    stderr: "nothing to commit\n".to_string(),
+                })
+            }
+        }
+
+        let command = Command::new(Arc::new(Exit(0)), "git", ["commit", "-am", "Auto"]);
+        assert!(command.run().await.is_ok());
+
+        let command = Command::new(Arc::new(Exit(1)), "git", ["commit", "-am", "Auto"]);
+        assert_eq!(
+            command.run().await.unwrap_err().to_string(),
+            "git exited with 1: nothing to commit"
+        );
+    }
+}
@@ -1,8 +1,31 @@
+#[cfg(feature = "tracing")]
+pub mod access_log;
+pub mod admin;
+pub mod auth;
+#[cfg(all(feature = "compression", not(target_arch = "wasm32")))]
+pub mod compression;
+pub mod cors;
 pub mod error;
 pub use error::*;
 
+#[cfg(all(feature = "media", not(target_arch = "wasm32")))]
+pub mod media;
+
+pub mod metrics;
+#[cfg(feature = "openapi")]
+pub mod openapi;
+pub mod rate_limit;
+pub mod request_id;
+pub mod security;
+#[cfg(feature = "signed-urls")]
+pub mod signed;
+#[cfg(any(test, feature = "test-util"))]
+pub mod test;
+
 pub mod routes;
 
+use std::sync::Arc;
+
 use axum::{Router, extract::FromRef, routing};
 
 use crate::client;
@@ -18,16 +41,584 @@ where
         + Sync
         + 'static,
     client::Config: FromRef<S>,
+    client::ManifestConfig: FromRef<S>,
 {
-    Router::<S>::new()
-        .nest("/.fs", routes::fs::router())
-        .route("/.shell", routing::post(routes::shell::shell))
-        .route("/.proxy/{*url}", routing::any(routes::proxy::proxy))
-        .route("/.ping", routing::get(routes::ping))
-        .route("/.logs", routing::post(routes::log::log))
-        .route("/.config", routing::get(routes::config))
-        .route(
-            "/.client/manifest.json",
-            routing::get(routes::client_manifest),
-        )
+    Builder::new().build()
+}
+
+pub fn builder() -> Builder {
+    Builder::new()
+}
+
+/// Configures which parts of the API are exposed by the router
+pub struct Builder {
+    shell: bool,
+    base_path: Option<String>,
+    metrics: Option<metrics::Metrics>,
+    request_id: bool,
+    #[cfg(feature = "tracing")]
+    access_log: Option<access_log::AccessLog>,
+    rate_limit: Option<rate_limit::RateLimit>,
+    auth: Option<auth::Basic>,
+    cors: Option<cors::Cors>,
+    security_headers: Option<security::SecurityHeaders>,
+    #[cfg(feature = "signed-urls")]
+    signer: Option<signed::Signer>,
+    admin: Option<admin::Admin>,
+    plugs: Option<routes::plugs::Plugs>,
+    ingest: Option<routes::ingest::Ingest>,
+    capture: Option<routes::capture::Capture>,
+    templates: Option<crate::templates::Templates>,
+    calendar: Option<routes::calendar::Calendar>,
+    #[cfg(feature = "bundle")]
+    bundles: Option<routes::bundle::Bundles>,
+    #[cfg(all(feature = "ssr", feature = "signed-urls"))]
+    shares: Option<routes::share::Shares>,
+    #[cfg(feature = "opendal")]
+    uploads: Option<routes::upload::Uploads>,
+    #[cfg(feature = "openapi")]
+    openapi: bool,
+    #[cfg(feature = "backup")]
+    backup: Option<crate::backup::Backup>,
+    #[cfg(feature = "git-sync")]
+    git_sync: Option<crate::sync::git::GitSync>,
+    #[cfg(feature = "stats")]
+    stats: Option<crate::stats::Stats>,
+    #[cfg(all(feature = "compression", not(target_arch = "wasm32")))]
+    compression: bool,
+    #[cfg(all(feature = "media", not(target_arch = "wasm32")))]
+    media: Option<media::Media>,
+    #[cfg(feature = "client-assets")]
+    client_assets: bool,
+    #[cfg(all(feature = "ssr", feature = "client-assets"))]
+    boot: Option<routes::boot::BootRenderer>,
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Builder {
+    pub fn new() -> Self {
+        Self {
+            shell: true,
+            base_path: None,
+            metrics: None,
+            request_id: false,
+            #[cfg(feature = "tracing")]
+            access_log: None,
+            rate_limit: None,
+            auth: None,
+            cors: None,
+            security_headers: None,
+            #[cfg(feature = "signed-urls")]
+            signer: None,
+            admin: None,
+            plugs: None,
+            ingest: None,
+            capture: None,
+            templates: None,
+            calendar: None,
+            #[cfg(feature = "bundle")]
+            bundles: None,
+            #[cfg(all(feature = "ssr", feature = "signed-urls"))]
+            shares: None,
+            #[cfg(feature = "opendal")]
+            uploads: None,
+            #[cfg(feature = "openapi")]
+            openapi: false,
+            #[cfg(feature = "backup")]
+            backup: None,
+            #[cfg(feature = "git-sync")]
+            git_sync: None,
+            #[cfg(feature = "stats")]
+            stats: None,
+            #[cfg(all(feature = "compression", not(target_arch = "wasm32")))]
+            compression: false,
+            #[cfg(all(feature = "media", not(target_arch = "wasm32")))]
+            media: None,
+            #[cfg(feature = "client-assets")]
+            client_assets: true,
+            #[cfg(all(feature = "ssr", feature = "client-assets"))]
+            boot: None,
+        }
+    }
+
+    /// Expose the `/.shell` routes (enabled by default).
+    ///
+    /// When disabled the routes are not registered at all and respond with 404.
+    #[must_use]
+    pub fn shell(mut self, enabled: bool) -> Self {
+        self.shell = enabled;
+        self
+    }
+
+    /// Record request metrics and export them at `GET /.metrics` (disabled by default).
+    #[must_use]
+    pub fn metrics(mut self, metrics: metrics::Metrics) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Tag requests with an `x-request-id` (disabled by default, see [`request_id`]).
+    #[must_use]
+    pub fn request_id(mut self, enabled: bool) -> Self {
+        self.request_id = enabled;
+        self
+    }
+
+    /// Log every request with its status, latency and user (disabled by default, see
+    /// [`access_log`]).
+    #[cfg(feature = "tracing")]
+    #[must_use]
+    pub fn access_log(mut self, log: access_log::AccessLog) -> Self {
+        self.access_log = Some(log);
+        self
+    }
+
+    /// Limit writes, `/.shell` and `/.proxy` requests per client (disabled by default).
+    ///
+    /// Requires a `ClientIpSource` extension, like `/.logs`.
+    #[must_use]
+    pub fn rate_limit(mut self, limit: rate_limit::RateLimit) -> Self {
+        self.rate_limit = Some(limit);
+        self
+    }
+
+    /// Require HTTP basic authentication for all routes.
+    #[must_use]
+    pub fn auth(mut self, auth: auth::Basic) -> Self {
+        self.auth = Some(auth);
+        self
+    }
+
+    /// Allow cross-origin requests (disabled by default).
+    ///
+    /// Preflight requests are answered before authentication.
+    #[must_use]
+    pub fn cors(mut self, cors: cors::Cors) -> Self {
+        self.cors = Some(cors);
+        self
+    }
+
+    /// Add a Content Security Policy and other security headers to responses (disabled by
+    /// default, see [`security`]).
+    #[must_use]
+    pub fn security_headers(mut self, headers: security::SecurityHeaders) -> Self {
+        self.security_headers = Some(headers);
+        self
+    }
+
+    /// Let `/.fs` URLs signed by `signer` read files without credentials until they expire
+    /// (disabled by default, see [`signed`]).
+    #[cfg(feature = "signed-urls")]
+    #[must_use]
+    pub fn signed_urls(mut self, signer: signed::Signer) -> Self {
+        self.signer = Some(signer);
+        self
+    }
+
+    /// Expose the `/.admin` routes (disabled by default, see [`admin`]).
+    #[must_use]
+    pub fn admin(mut self, admin: admin::Admin) -> Self {
+        self.admin = Some(admin);
+        self
+    }
+
+    /// Serve the plugs of the space under `/.plugs`, and install them from their
+    /// sources (disabled by default, see [`routes::plugs`]).
+    #[must_use]
+    pub fn plugs(mut self, plugs: routes::plugs::Plugs) -> Self {
+        self.plugs = Some(plugs);
+        self
+    }
+
+    /// Create pages from emails posted to `/.ingest/email` (disabled by default, see
+    /// [`routes::ingest`]).
+    #[must_use]
+    pub fn ingest(mut self, ingest: routes::ingest::Ingest) -> Self {
+        self.ingest = Some(ingest);
+        self
+    }
+
+    /// Append snippets posted to `/.capture` to a page (disabled by default, see
+    /// [`routes::capture`]).
+    #[must_use]
+    pub fn capture(mut self, capture: routes::capture::Capture) -> Self {
+        self.capture = Some(capture);
+        self
+    }
+
+    /// Create the pages of templates opened under `/.template` (disabled by default, see
+    /// [`routes::template`]).
+    #[must_use]
+    pub fn templates(mut self, templates: crate::templates::Templates) -> Self {
+        self.templates = Some(templates);
+        self
+    }
+
+    /// Serve the due tasks and dated pages of the space at `/.calendar.ics` (disabled by
+    /// default, see [`routes::calendar`]).
+    #[must_use]
+    pub fn calendar(mut self, calendar: routes::calendar::Calendar) -> Self {
+        self.calendar = Some(calendar);
+        self
+    }
+
+    /// Download pages with the pages they link, as zip archives or HTML documents, at
+    /// `/.bundle/{page}` (disabled by default, see [`routes::bundle`]).
+    #[cfg(feature = "bundle")]
+    #[must_use]
+    pub fn bundles(mut self, bundles: routes::bundle::Bundles) -> Self {
+        self.bundles = Some(bundles);
+        self
+    }
+
+    /// Let users with write access share pages and folders publicly at `/share/{token}`,
+    /// managed at `/.shares` (disabled by default, see [`routes::share`]).
+    ///
+    /// # Panics
+    ///
+    /// [`Builder::build`] panics without [`Builder::signed_urls`], whose key makes the
+    /// tokens and links the files of the shared pages.
+    #[cfg(all(feature = "ssr", feature = "signed-urls"))]
+    #[must_use]
+    pub fn shares(mut self, shares: routes::share::Shares) -> Self {
+        self.shares = Some(shares);
+        self
+    }
+
+    /// Presign uploads of large files straight to the storage of the space at
+    /// `POST /.uploads/{path}` (disabled by default, see [`routes::upload`]).
+    ///
+    /// Read-only users are denied.
+    #[cfg(feature = "opendal")]
+    #[must_use]
+    pub fn uploads(mut self, uploads: routes::upload::Uploads) -> Self {
+        self.uploads = Some(uploads);
+        self
+    }
+
+    /// Serve the OpenAPI document at `GET /.openapi.json` and a Swagger UI at `GET /.openapi`
+    /// (disabled by default).
+    #[cfg(feature = "openapi")]
+    #[must_use]
+    pub fn openapi(mut self, enabled: bool) -> Self {
+        self.openapi = enabled;
+        self
+    }
+
+    /// OpenAPI document of the routes this builder registers.
+    #[cfg(feature = "openapi")]
+    pub fn openapi_document(&self) -> utoipa::openapi::OpenApi {
+        openapi::document(self)
+    }
+
+    /// Take backup snapshots on demand at `POST /.backup/trigger` (disabled by default).
+    ///
+    /// Responds with the snapshot taken. Read-only users are denied.
+    #[cfg(feature = "backup")]
+    #[must_use]
+    pub fn backup(mut self, backup: crate::backup::Backup) -> Self {
+        self.backup = Some(backup);
+        self
+    }
+
+    /// Sync the space with its git remote on demand at `POST /.sync/git` (disabled by
+    /// default), e.g. from a webhook of the remote on push.
+    ///
+    /// Responds with the [`Report`](crate::sync::git::Report) of the sync, or 409 when it
+    /// stopped on conflicting changes. Read-only users are denied.
+    #[cfg(feature = "git-sync")]
+    #[must_use]
+    pub fn git_sync(mut self, sync: crate::sync::git::GitSync) -> Self {
+        self.git_sync = Some(sync);
+        self
+    }
+
+    /// Serve the daily totals of the space at `GET /.stats` (disabled by default).
+    ///
+    /// Responds with the recorded days, oldest first, and the current totals of today.
+    #[cfg(feature = "stats")]
+    #[must_use]
+    pub fn stats(mut self, stats: crate::stats::Stats) -> Self {
+        self.stats = Some(stats);
+        self
+    }
+
+    /// Compress responses with gzip or brotli when the client accepts it (disabled by default).
+    ///
+    /// See [`compression`] for which responses are skipped.
+    #[cfg(all(feature = "compression", not(target_arch = "wasm32")))]
+    #[must_use]
+    pub fn compression(mut self, enabled: bool) -> Self {
+        self.compression = enabled;
+        self
+    }
+
+    /// Serve resized and re-encoded images for `/.fs` requests asking for them, e.g.
+    /// `?w=800&format=webp` (disabled by default, see [`media`]).
+    #[cfg(all(feature = "media", not(target_arch = "wasm32")))]
+    #[must_use]
+    pub fn media(mut self, media: media::Media) -> Self {
+        self.media = Some(media);
+        self
+    }
+
+    /// Serve the embedded web client for unmatched paths (enabled by default, see
+    /// [`routes::assets`]).
+    #[cfg(feature = "client-assets")]
+    #[must_use]
+    pub fn client_assets(mut self, enabled: bool) -> Self {
+        self.client_assets = enabled;
+        self
+    }
+
+    /// Serve the client shell at `/` with the config and index page inlined (disabled by
+    /// default, see [`routes::boot`]).
+    #[cfg(all(feature = "ssr", feature = "client-assets"))]
+    #[must_use]
+    pub fn boot(mut self, renderer: impl crate::ssr::Renderer + 'static) -> Self {
+        self.boot = Some(Arc::new(renderer));
+        self
+    }
+
+    /// Serve all routes under a path prefix, e.g. `/notes`.
+    ///
+    /// Handlers see paths with the prefix removed. An empty path or `/` serves from the root.
+    #[must_use]
+    pub fn base_path(mut self, path: impl AsRef<str>) -> Self {
+        self.base_path = client::normalize_url_prefix(path.as_ref());
+        self
+    }
+
+    pub fn build<S>(self) -> Router<S>
+    where
+        S: routes::fs::Provider
+            + routes::shell::Provider
+            + routes::proxy::Provider
+            + routes::log::Provider
+            + Clone
+            + Send
+            + Sync
+            + 'static,
+        client::Config: FromRef<S>,
+        client::ManifestConfig: FromRef<S>,
+    {
+        #[cfg(feature = "openapi")]
+        let document = self.openapi.then(|| {
+            let json = self.openapi_document().to_json();
+            bytes::Bytes::from(json.expect("OpenAPI document should serialize"))
+        });
+
+        let fs = routes::fs::router();
+
+        #[cfg(all(feature = "media", not(target_arch = "wasm32")))]
+        let fs = match self.media {
+            Some(media) => fs.layer(axum::middleware::from_fn_with_state(
+                Arc::new(media),
+                media::middleware,
+            )),
+            None => fs,
+        };
+
+        let mut router = Router::<S>::new()
+            .nest("/.fs", fs)
+            .route("/.proxy/{*url}", routing::any(routes::proxy::proxy))
+            .route("/.ping", routing::get(routes::ping))
+            .route("/.logs", routing::post(routes::log::log))
+            .route("/.config", routing::get(routes::config))
+            .route(
+                "/.client/manifest.json",
+                routing::get(routes::client_manifest),
+            );
+
+        if self.shell {
+            router = router
+                .route("/.shell", routing::post(routes::shell::shell))
+                .route("/.shell/stream", routing::post(routes::shell::stream));
+        }
+
+        #[cfg(feature = "openapi")]
+        if let Some(document) = document {
+            router = router
+                .route(
+                    "/.openapi.json",
+                    routing::get(openapi::json).with_state(document),
+                )
+                .route("/.openapi", routing::get(openapi::ui));
+        }
+
+        #[cfg(all(feature = "ssr", feature = "client-assets"))]
+        if let Some(renderer) = self.boot {
+            router = router.route(
+                "/",
+                routing::get(routes::boot::boot).layer(axum::Extension(renderer)),
+            );
+        }
+
+        #[cfg(feature = "client-assets")]
+        if self.client_assets {
+            router = router.fallback(routes::assets::asset::<routes::assets::Assets>);
+        }
+
+        if let Some(admin) = self.admin {
+            router = router.nest("/.admin", admin::router(admin));
+        }
+
+        if let Some(plugs) = self.plugs {
+            router = router.nest("/.plugs", routes::plugs::router(plugs));
+        }
+
+        if let Some(ingest) = self.ingest {
+            router = router.nest("/.ingest", routes::ingest::router(ingest));
+        }
+
+        if let Some(capture) = self.capture {
+            router = router.nest("/.capture", routes::capture::router(capture));
+        }
+
+        if let Some(templates) = self.templates {
+            let base_path = self.base_path.as_deref().unwrap_or_default();
+            router = router.nest("/.template", routes::template::router(templates, base_path));
+        }
+
+        if let Some(calendar) = self.calendar {
+            router = router.merge(routes::calendar::router(calendar));
+        }
+
+        #[cfg(feature = "bundle")]
+        if let Some(bundles) = self.bundles {
+            router = router.nest("/.bundle", routes::bundle::router(bundles));
+        }
+
+        #[cfg(feature = "opendal")]
+        if let Some(uploads) = self.uploads {
+            router = router.nest("/.uploads", routes::upload::router(uploads));
+        }
+
+        #[cfg(all(feature = "ssr", feature = "signed-urls"))]
+        let shares = self.shares.map(|shares| {
+            let signer = self
+                .signer
+                .clone()
+                .expect("shares need signed URLs, see Builder::signed_urls");
+            (shares, signer)
+        });
+
+        #[cfg(all(feature = "ssr", feature = "signed-urls"))]
+        if let Some((shares, signer)) = &shares {
+            let base_path = self.base_path.as_deref().unwrap_or_default();
+            router = router.nest(
+                "/.shares",
+                routes::share::api(shares.clone(), signer.clone(), base_path),
+            );
+        }
+
+        #[cfg(feature = "backup")]
+        if let Some(backup) = self.backup {
+            router = router.route(
+                "/.backup/trigger",
+                routing::post(routes::backup::trigger).with_state(backup),
+            );
+        }
+
+        #[cfg(feature = "git-sync")]
+        if let Some(sync) = self.git_sync {
+            router = router.route(
+                "/.sync/git",
+                routing::post(routes::sync::trigger).with_state(sync),
+            );
+        }
+
+        #[cfg(feature = "stats")]
+        if let Some(stats) = self.stats {
+            router = router.route(
+                "/.stats",
+                routing::get(routes::stats::history).with_state(stats),
+            );
+        }
+
+        if let Some(metrics) = self.metrics {
+            router = router
+                .route(
+                    "/.metrics",
+                    routing::get(metrics::export).with_state(metrics.clone()),
+                )
+                .layer(axum::middleware::from_fn_with_state(
+                    metrics,
+                    metrics::track,
+                ));
+        }
+
+        if let Some(limit) = self.rate_limit {
+            router = router.layer(axum::middleware::from_fn_with_state(
+                limit,
+                rate_limit::middleware,
+            ));
+        }
+
+        if let Some(auth) = self.auth {
+            router = router.layer(axum::middleware::from_fn_with_state(
+                Arc::new(auth),
+                auth::basic,
+            ));
+        }
+
+        // Outside of auth, for the public
+        #[cfg(all(feature = "ssr", feature = "signed-urls"))]
+        if let Some((shares, signer)) = shares {
+            let base_path = self.base_path.as_deref().unwrap_or_default();
+            router = router.nest("/share", routes::share::public(shares, signer, base_path));
+        }
+
+        // Outside of auth, which lets the signed requests through
+        #[cfg(feature = "signed-urls")]
+        if let Some(signer) = self.signer {
+            router = router.layer(axum::middleware::from_fn_with_state(
+                signer,
+                signed::middleware,
+            ));
+        }
+
+        if let Some(cors) = self.cors {
+            router = router.layer(axum::middleware::from_fn_with_state(
+                Arc::new(cors),
+                cors::middleware,
+            ));
+        }
+
+        if let Some(headers) = self.security_headers {
+            router = router.layer(axum::middleware::from_fn_with_state(
+                Arc::new(headers),
+                security::middleware,
+            ));
+        }
+
+        #[cfg(all(feature = "compression", not(target_arch = "wasm32")))]
+        if self.compression {
+            router = router.layer(compression::layer());
+        }
+
+        // Outside of the other layers, so rejections are logged too
+        #[cfg(feature = "tracing")]
+        if let Some(log) = self.access_log {
+            router = router.layer(axum::middleware::from_fn_with_state(
+                Arc::new(log),
+                access_log::middleware,
+            ));
+        }
+
+        // Outermost, so the span also covers the other layers
+        if self.request_id {
+            router = router.layer(axum::middleware::from_fn(request_id::middleware));
+        }
+
+        match self.base_path {
+            Some(base_path) => Router::new().nest(&base_path, router),
+            None => router,
+        }
+    }
 }
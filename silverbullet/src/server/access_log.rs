@@ -0,0 +1,217 @@
+//! HTTP access log
+//!
+//! Enable with [`Builder::access_log`](crate::server::Builder::access_log). Every request
+//! is logged as a `tracing` event with the `silverbullet::access` target once its response
+//! is ready, with the method, path, status, latency, response size and authenticated user.
+//! Query strings are left out unless enabled, and then logged with the values of
+//! credentials such as `sig` or `token` redacted. Requests to silenced paths, e.g. the
+//! `/.ping` health checks, aren't logged.
+
+use std::sync::Arc;
+
+use axum::{
+    body::HttpBody as _,
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use http::{HeaderName, header};
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+
+#[cfg(target_arch = "wasm32")]
+use web_time::Instant;
+
+use crate::client::User;
+use crate::glob;
+
+const REDACTED: &str = "[redacted]";
+
+/// Query parameters holding credentials
+const SENSITIVE_PARAMS: [&str; 7] = [
+    "sig",
+    "token",
+    "access_token",
+    "key",
+    "password",
+    "secret",
+    "auth*",
+];
+
+/// Headers holding credentials
+const SENSITIVE_HEADERS: [HeaderName; 4] = [
+    header::AUTHORIZATION,
+    header::COOKIE,
+    header::PROXY_AUTHORIZATION,
+    HeaderName::from_static("x-proxy-header-authorization"),
+];
+
+/// Access log settings
+#[derive(Debug, Clone, Default)]
+pub struct AccessLog {
+    silenced: Vec<String>,
+    query: bool,
+    headers: Vec<HeaderName>,
+}
+
+impl AccessLog {
+    /// Log every request, without query strings or headers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Don't log requests to paths matching a glob, e.g. `/.ping` or `/.client/**`.
+    #[must_use]
+    pub fn silence(mut self, pattern: impl Into<String>) -> Self {
+        self.silenced.push(pattern.into());
+        self
+    }
+
+    /// Log query strings, with credentials redacted (disabled by default).
+    #[must_use]
+    pub fn query(mut self, enabled: bool) -> Self {
+        self.query = enabled;
+        self
+    }
+
+    /// Log a request header, redacted if it holds credentials.
+    #[must_use]
+    pub fn header(mut self, name: HeaderName) -> Self {
+        self.headers.push(name);
+        self
+    }
+
+    fn silences(&self, path: &str) -> bool {
+        self.silenced
+            .iter()
+            .any(|pattern| glob::matches(pattern, path))
+    }
+
+    fn redact_query(query: &str) -> String {
+        query
+            .split('&')
+            .map(|pair| match pair.split_once('=') {
+                Some((name, _)) if is_sensitive(name) => format!("{name}={REDACTED}"),
+                _ => pair.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("&")
+    }
+
+    fn headers(&self, request: &Request) -> String {
+        self.headers
+            .iter()
+            .filter_map(|name| {
+                let value = request.headers().get(name)?;
+                let value = if SENSITIVE_HEADERS.contains(name) {
+                    REDACTED
+                } else {
+                    value.to_str().unwrap_or("[binary]")
+                };
+
+                Some(format!("{name}: {value}"))
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+fn is_sensitive(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+
+    SENSITIVE_PARAMS
+        .iter()
+        .any(|pattern| glob::matches(pattern, &name))
+}
+
+/// Middleware logging requests.
+///
+/// Use with `axum::middleware::from_fn_with_state(Arc::new(log), access_log::middleware)`.
+pub async fn middleware(
+    State(log): State<Arc<AccessLog>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if log.silences(request.uri().path()) {
+        return next.run(request).await;
+    }
+
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let query = log
+        .query
+        .then(|| request.uri().query().map(AccessLog::redact_query))
+        .flatten();
+    let headers = log.headers(&request);
+
+    let start = Instant::now();
+    let response = next.run(request).await;
+    let latency = start.elapsed();
+
+    // Streamed bodies, e.g. of files, only have a length header
+    let bytes = response.body().size_hint().exact().or_else(|| {
+        response
+            .headers()
+            .get(header::CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok()?.parse().ok())
+    });
+    let user = response
+        .extensions()
+        .get::<User>()
+        .map(|user| user.name.as_str());
+
+    tracing::info!(
+        target: "silverbullet::access",
+        method = %method,
+        path,
+        query,
+        status = response.status().as_u16(),
+        latency_ms = latency.as_secs_f64() * 1000.0,
+        bytes,
+        user,
+        headers = (!headers.is_empty()).then_some(headers),
+        "{method} {path} {}",
+        response.status().as_u16(),
+    );
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn silences_paths() {
+        let log = AccessLog::new().silence("/.ping").silence("/.client/**");
+
+        assert!(log.silences("/.ping"));
+        assert!(log.silences("/.client/js/client.js"));
+        assert!(!log.silences("/.fs/index.md"));
+    }
+
+    #[test]
+    fn redacts_credentials() {
+        assert_eq!(
+            AccessLog::redact_query("exp=10&sig=abc&Token=t&authKey=k&download=1"),
+            "exp=10&sig=[redacted]&Token=[redacted]&authKey=[redacted]&download=1"
+        );
+
+        let log = AccessLog::new()
+            .header(header::AUTHORIZATION)
+            .header(header::USER_AGENT)
+            .header(header::REFERER);
+
+        let request = Request::builder()
+            .header(header::AUTHORIZATION, "Basic YTpi")
+            .header(header::USER_AGENT, "curl/8")
+            .body(axum::body::Body::empty())
+            .unwrap();
+
+        assert_eq!(
+            log.headers(&request),
+            "authorization: [redacted], user-agent: curl/8"
+        );
+    }
+}
@@ -0,0 +1,526 @@
+//! Operational routes under `/.admin`, returning JSON for admin tools
+//!
+//! Enable with [`Builder::admin`](crate::server::Builder::admin). Only authenticated users
+//! with write access may use them, so they are closed unless auth is configured.
+//!
+//! | Route | Action |
+//! |---|---|
+//! | `GET /.admin/spaces` | List the spaces with their storage stats |
+//! | `GET /.admin/spaces/{name}` | Storage stats of a space |
+//! | `POST /.admin/caches/flush` | Flush the registered caches |
+//! | `GET /.admin/backups` | List the backup snapshots (`backup` feature) |
+//! | `POST /.admin/backups` | Take a backup snapshot (`backup` feature) |
+//! | `POST /.admin/gc` | Collect the unreferenced attachments, `?mode=dry-run` by default |
+//! | `GET /.admin/duplicates` | Report the identical attachments (`dedupe` feature) |
+//! | `POST /.admin/duplicates` | Point the links to the copies to the canonical files (`dedupe` feature) |
+
+use std::sync::Arc;
+
+use axum::{
+    Extension, Json, Router,
+    extract::{Path, Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    routing,
+};
+use http::Uri;
+use serde::Serialize;
+
+use crate::client;
+use crate::fs::{FileMeta, ReadOnlyFilesystem};
+use crate::server::error::Error;
+
+/// Spaces and maintenance hooks exposed by the admin routes
+#[derive(Clone, Default)]
+pub struct Admin {
+    spaces: Vec<Space>,
+    caches: Vec<Cache>,
+    #[cfg(feature = "backup")]
+    backup: Option<crate::backup::Backup>,
+    gc: Option<crate::gc::Collector>,
+    #[cfg(feature = "dedupe")]
+    dedupe: Option<crate::dedupe::Dedupe>,
+}
+
+#[derive(Clone)]
+struct Space {
+    name: String,
+    fs: Arc<dyn ReadOnlyFilesystem>,
+}
+
+#[derive(Clone)]
+struct Cache {
+    name: String,
+    flush: Arc<dyn Fn() + Send + Sync>,
+}
+
+impl Admin {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Report the storage stats of a space.
+    #[must_use]
+    pub fn space(mut self, name: impl Into<String>, fs: Arc<dyn ReadOnlyFilesystem>) -> Self {
+        self.spaces.push(Space {
+            name: name.into(),
+            fs,
+        });
+        self
+    }
+
+    /// Register a cache emptied by `POST /.admin/caches/flush`.
+    #[must_use]
+    pub fn cache(
+        mut self,
+        name: impl Into<String>,
+        flush: impl Fn() + Send + Sync + 'static,
+    ) -> Self {
+        self.caches.push(Cache {
+            name: name.into(),
+            flush: Arc::new(flush),
+        });
+        self
+    }
+
+    /// List and take snapshots with this backup.
+    #[cfg(feature = "backup")]
+    #[must_use]
+    pub fn backup(mut self, backup: crate::backup::Backup) -> Self {
+        self.backup = Some(backup);
+        self
+    }
+
+    /// Collect the unreferenced attachments with this collector.
+    #[must_use]
+    pub fn gc(mut self, collector: crate::gc::Collector) -> Self {
+        self.gc = Some(collector);
+        self
+    }
+
+    /// Report and rewrite the duplicate attachments with this dedupe.
+    #[cfg(feature = "dedupe")]
+    #[must_use]
+    pub fn dedupe(mut self, dedupe: crate::dedupe::Dedupe) -> Self {
+        self.dedupe = Some(dedupe);
+        self
+    }
+
+    fn find(&self, name: &str) -> Result<&Space, Error> {
+        self.spaces
+            .iter()
+            .find(|space| space.name == name)
+            .ok_or_else(|| Error::not_found(format!("no space named {name}")))
+    }
+}
+
+/// Storage stats of a space
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct Stats {
+    pub name: String,
+    pub files: usize,
+    /// Markdown files, the rest are attachments
+    pub pages: usize,
+    /// Total size in bytes
+    pub size: u64,
+    pub largest: Option<Largest>,
+    /// Latest modification time, in milliseconds since the epoch
+    pub last_modified: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Largest {
+    pub name: String,
+    pub size: u64,
+}
+
+impl Stats {
+    fn new(name: &str, files: &[FileMeta]) -> Self {
+        Self {
+            name: name.to_string(),
+            files: files.len(),
+            pages: files
+                .iter()
+                .filter(|meta| meta.name.ends_with(".md"))
+                .count(),
+            size: files.iter().map(|meta| meta.size).sum(),
+            largest: files
+                .iter()
+                .max_by_key(|meta| meta.size)
+                .map(|meta| Largest {
+                    name: meta.name.clone(),
+                    size: meta.size,
+                }),
+            last_modified: files.iter().map(|meta| meta.last_modified).max(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub(crate) struct Flushed {
+    flushed: Vec<String>,
+}
+
+pub(crate) fn router<S>(admin: Admin) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    let router = Router::new()
+        .route("/spaces", routing::get(spaces))
+        .route("/spaces/{name}", routing::get(space))
+        .route("/caches/flush", routing::post(flush))
+        .route("/gc", routing::post(gc));
+
+    #[cfg(feature = "backup")]
+    let router = router.route("/backups", routing::get(backups).post(snapshot));
+
+    #[cfg(feature = "dedupe")]
+    let router = router.route("/duplicates", routing::get(duplicates).post(deduplicate));
+
+    router
+        .route_layer(axum::middleware::from_fn(require_admin))
+        .with_state(Arc::new(admin))
+}
+
+/// Reject anonymous and read-only users.
+async fn require_admin(
+    user: Option<Extension<client::User>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    match user {
+        Some(Extension(user)) if !user.read_only => next.run(request).await,
+        Some(_) => Error::forbidden("read-only users can't use admin routes").into_response(),
+        None => Error::Unauthorized("admin routes require authentication".into()).into_response(),
+    }
+}
+
+#[cfg_attr(feature = "openapi", utoipa::path(
+    get,
+    path = "/.admin/spaces",
+    tag = "admin",
+    responses((status = 200, description = "Spaces with their storage stats", body = [Stats])),
+))]
+#[cfg_attr(feature = "cloudflare", worker::send)]
+pub(crate) async fn spaces(State(admin): State<Arc<Admin>>) -> Result<Json<Vec<Stats>>, Error> {
+    let mut stats = Vec::with_capacity(admin.spaces.len());
+
+    for space in &admin.spaces {
+        stats.push(Stats::new(&space.name, &space.fs.list().await?));
+    }
+
+    Ok(Json(stats))
+}
+
+#[cfg_attr(feature = "openapi", utoipa::path(
+    get,
+    path = "/.admin/spaces/{name}",
+    tag = "admin",
+    params(("name" = String, Path, description = "Space name")),
+    responses(
+        (status = 200, description = "Storage stats of the space", body = Stats),
+        (status = 404, description = "No such space"),
+    ),
+))]
+#[cfg_attr(feature = "cloudflare", worker::send)]
+pub(crate) async fn space(
+    State(admin): State<Arc<Admin>>,
+    Path(name): Path<String>,
+) -> Result<Json<Stats>, Error> {
+    let space = admin.find(&name)?;
+
+    Ok(Json(Stats::new(&space.name, &space.fs.list().await?)))
+}
+
+#[cfg_attr(feature = "openapi", utoipa::path(
+    post,
+    path = "/.admin/caches/flush",
+    tag = "admin",
+    responses((status = 200, description = "Names of the flushed caches", body = Flushed)),
+))]
+pub(crate) async fn flush(State(admin): State<Arc<Admin>>) -> Json<Flushed> {
+    for cache in &admin.caches {
+        (cache.flush)();
+    }
+
+    #[cfg(feature = "tracing")]
+    tracing::info!(caches = admin.caches.len(), "Caches flushed");
+
+    Json(Flushed {
+        flushed: admin
+            .caches
+            .iter()
+            .map(|cache| cache.name.clone())
+            .collect(),
+    })
+}
+
+#[cfg(feature = "backup")]
+fn backup(admin: &Admin) -> Result<&crate::backup::Backup, Error> {
+    admin
+        .backup
+        .as_ref()
+        .ok_or_else(|| Error::not_found("backups are not configured"))
+}
+
+#[cfg(feature = "backup")]
+#[cfg_attr(feature = "openapi", utoipa::path(
+    get,
+    path = "/.admin/backups",
+    tag = "admin",
+    responses(
+        (status = 200, description = "Snapshots, oldest first", body = [crate::backup::Snapshot]),
+        (status = 404, description = "Backups are not configured"),
+    ),
+))]
+#[cfg_attr(feature = "cloudflare", worker::send)]
+pub(crate) async fn backups(
+    State(admin): State<Arc<Admin>>,
+) -> Result<Json<Vec<crate::backup::Snapshot>>, Error> {
+    Ok(Json(backup(&admin)?.snapshots().await?))
+}
+
+#[cfg(feature = "backup")]
+#[cfg_attr(feature = "openapi", utoipa::path(
+    post,
+    path = "/.admin/backups",
+    tag = "admin",
+    responses(
+        (status = 200, description = "Snapshot taken", body = crate::backup::Snapshot),
+        (status = 404, description = "Backups are not configured"),
+    ),
+))]
+#[cfg_attr(feature = "cloudflare", worker::send)]
+pub(crate) async fn snapshot(
+    State(admin): State<Arc<Admin>>,
+) -> Result<Json<crate::backup::Snapshot>, Error> {
+    Ok(Json(backup(&admin)?.snapshot().await?))
+}
+
+#[cfg_attr(feature = "openapi", utoipa::path(
+    post,
+    path = "/.admin/gc",
+    tag = "admin",
+    params(("mode" = Option<String>, Query, description = "`dry-run`, the default, `trash` or `delete`")),
+    responses(
+        (status = 200, description = "Unreferenced attachments, trashed or deleted unless in a dry run", body = crate::gc::Report),
+        (status = 400, description = "Invalid mode"),
+        (status = 404, description = "Garbage collection is not configured"),
+    ),
+))]
+#[cfg_attr(feature = "tracing", tracing::instrument(name = "admin_gc", skip_all))]
+#[cfg_attr(feature = "cloudflare", worker::send)]
+pub(crate) async fn gc(
+    State(admin): State<Arc<Admin>>,
+    uri: Uri,
+) -> Result<Json<crate::gc::Report>, Error> {
+    let collector = admin
+        .gc
+        .as_ref()
+        .ok_or_else(|| Error::not_found("garbage collection is not configured"))?;
+
+    let mut mode = crate::gc::Mode::DryRun;
+    for pair in uri.query().unwrap_or_default().split('&') {
+        if let Some(("mode", value)) = pair.split_once('=') {
+            mode = value
+                .parse()
+                .map_err(|err: String| Error::BadRequest(err.into()))?;
+        }
+    }
+
+    Ok(Json(collector.collect(mode).await?))
+}
+
+#[cfg(feature = "dedupe")]
+fn dedupe(admin: &Admin) -> Result<&crate::dedupe::Dedupe, Error> {
+    admin
+        .dedupe
+        .as_ref()
+        .ok_or_else(|| Error::not_found("duplicate detection is not configured"))
+}
+
+#[cfg(feature = "dedupe")]
+#[cfg_attr(feature = "openapi", utoipa::path(
+    get,
+    path = "/.admin/duplicates",
+    tag = "admin",
+    responses(
+        (status = 200, description = "Sets of identical attachments", body = crate::dedupe::Report),
+        (status = 404, description = "Duplicate detection is not configured"),
+    ),
+))]
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(name = "admin_duplicates", skip_all)
+)]
+#[cfg_attr(feature = "cloudflare", worker::send)]
+pub(crate) async fn duplicates(
+    State(admin): State<Arc<Admin>>,
+) -> Result<Json<crate::dedupe::Report>, Error> {
+    Ok(Json(dedupe(&admin)?.find().await?))
+}
+
+#[cfg(feature = "dedupe")]
+#[cfg_attr(feature = "openapi", utoipa::path(
+    post,
+    path = "/.admin/duplicates",
+    tag = "admin",
+    responses(
+        (status = 200, description = "Sets of identical attachments, with the pages now linking the canonical files", body = crate::dedupe::Report),
+        (status = 404, description = "Duplicate detection is not configured"),
+    ),
+))]
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(name = "admin_deduplicate", skip_all)
+)]
+#[cfg_attr(feature = "cloudflare", worker::send)]
+pub(crate) async fn deduplicate(
+    State(admin): State<Arc<Admin>>,
+) -> Result<Json<crate::dedupe::Report>, Error> {
+    Ok(Json(dedupe(&admin)?.rewrite().await?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meta(name: &str, size: u64, last_modified: u64) -> FileMeta {
+        FileMeta {
+            name: name.to_string(),
+            created: last_modified,
+            perm: "rw".to_string(),
+            content_type: String::new(),
+            last_modified,
+            size,
+            etag: None,
+            version: None,
+        }
+    }
+
+    #[test]
+    fn computes_stats() {
+        let stats = Stats::new(
+            "notes",
+            &[
+                meta("index.md", 10, 3),
+                meta("photo.png", 100, 1),
+                meta("Journal/today.md", 5, 2),
+            ],
+        );
+
+        assert_eq!(
+            stats,
+            Stats {
+                name: "notes".to_string(),
+                files: 3,
+                pages: 2,
+                size: 115,
+                largest: Some(Largest {
+                    name: "photo.png".to_string(),
+                    size: 100
+                }),
+                last_modified: Some(3),
+            }
+        );
+
+        assert_eq!(Stats::new("empty", &[]).largest, None);
+    }
+
+    #[tokio::test]
+    async fn collects_garbage() {
+        use crate::fs::testing::MemoryFs;
+        use crate::server::Builder;
+        use crate::server::auth::Basic;
+        use crate::server::test::TestServer;
+        use http::{Method, StatusCode, header};
+
+        let space = Arc::new(
+            MemoryFs::new()
+                .with_file("index.md", b"![[kept.png]]")
+                .with_file("kept.png", b"png")
+                .with_file("old.png", b"png"),
+        );
+        let server = TestServer::build(
+            Builder::new()
+                .auth(Basic::new("admin", "s3cret"))
+                .admin(Admin::new().gc(crate::gc::Collector::new(space.clone()))),
+            client::Config::default(),
+            MemoryFs::new(),
+        );
+        let post = |uri: &str| {
+            let request = http::Request::builder()
+                .method(Method::POST)
+                .uri(uri)
+                .header(header::AUTHORIZATION, "Basic YWRtaW46czNjcmV0")
+                .body(axum::body::Body::empty())
+                .unwrap();
+            server.request(request)
+        };
+
+        let response = post("/.admin/gc").await;
+        assert_eq!(response.status, StatusCode::OK);
+        let report: serde_json::Value = response.json();
+        assert_eq!(report["mode"], "dry-run");
+        assert_eq!(report["orphans"][0]["name"], "old.png");
+
+        assert_eq!(
+            post("/.admin/gc?mode=purge").await.status,
+            StatusCode::BAD_REQUEST
+        );
+
+        let response = post("/.admin/gc?mode=delete").await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert!(space.meta("old.png").await.is_err());
+        assert!(space.meta("kept.png").await.is_ok());
+    }
+
+    #[cfg(feature = "dedupe")]
+    #[tokio::test]
+    async fn reports_duplicates() {
+        use crate::fs::testing::MemoryFs;
+        use crate::server::Builder;
+        use crate::server::auth::Basic;
+        use crate::server::test::TestServer;
+        use http::{Method, StatusCode, header};
+
+        let space = Arc::new(
+            MemoryFs::new()
+                .with_file("index.md", b"![[copy.png]]")
+                .with_file("chart.png", b"png")
+                .with_file("copy.png", b"png"),
+        );
+        let server = TestServer::build(
+            Builder::new()
+                .auth(Basic::new("admin", "s3cret"))
+                .admin(Admin::new().dedupe(crate::dedupe::Dedupe::new(space.clone()))),
+            client::Config::default(),
+            MemoryFs::new(),
+        );
+        let request = |method: Method| {
+            let request = http::Request::builder()
+                .method(method)
+                .uri("/.admin/duplicates")
+                .header(header::AUTHORIZATION, "Basic YWRtaW46czNjcmV0")
+                .body(axum::body::Body::empty())
+                .unwrap();
+            server.request(request)
+        };
+
+        let response = request(Method::GET).await;
+        assert_eq!(response.status, StatusCode::OK);
+        let report: serde_json::Value = response.json();
+        assert_eq!(report["duplicates"][0]["canonical"], "chart.png");
+        assert_eq!(report["duplicates"][0]["copies"][0], "copy.png");
+        assert_eq!(report["wasted"], 3);
+
+        let response = request(Method::POST).await;
+        assert_eq!(response.status, StatusCode::OK);
+        let report: serde_json::Value = response.json();
+        assert_eq!(report["rewritten"][0], "index.md");
+    }
+}
@@ -0,0 +1,137 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Request, State},
+    http::header,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use base64::Engine as _;
+
+use crate::server::error::Error;
+
+/// Authenticated user, added to the request extensions by the auth middleware
+pub use crate::client::User;
+
+/// Single user credentials checked with HTTP basic authentication
+#[derive(Clone)]
+pub struct Basic {
+    user: String,
+    password: String,
+    read_only: bool,
+}
+
+impl Basic {
+    pub fn new(user: impl Into<String>, password: impl Into<String>) -> Self {
+        Self {
+            user: user.into(),
+            password: password.into(),
+            read_only: false,
+        }
+    }
+
+    /// Only grant the user read access.
+    #[must_use]
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    fn verify(&self, authorization: &str) -> bool {
+        let Some(encoded) = authorization.strip_prefix("Basic ") else {
+            return false;
+        };
+
+        let Ok(decoded) = base64::engine::general_purpose::STANDARD.decode(encoded.trim()) else {
+            return false;
+        };
+
+        let Some((user, password)) = std::str::from_utf8(&decoded)
+            .ok()
+            .and_then(|credentials| credentials.split_once(':'))
+        else {
+            return false;
+        };
+
+        // Compare both to not reveal which one is wrong through timing
+        let user_matches = constant_time_eq(user.as_bytes(), self.user.as_bytes());
+        let password_matches = constant_time_eq(password.as_bytes(), self.password.as_bytes());
+
+        user_matches & password_matches
+    }
+}
+
+/// Middleware rejecting requests without valid basic auth credentials.
+///
+/// Use with `axum::middleware::from_fn_with_state(Arc::new(credentials), auth::basic)`.
+pub async fn basic(
+    State(credentials): State<Arc<Basic>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    #[cfg(feature = "signed-urls")]
+    if request
+        .extensions()
+        .get::<crate::server::signed::Signed>()
+        .is_some()
+    {
+        return next.run(request).await;
+    }
+
+    let authorized = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| credentials.verify(v));
+
+    if !authorized {
+        return (
+            [(header::WWW_AUTHENTICATE, r#"Basic realm="SilverBullet""#)],
+            Error::Unauthorized("invalid or missing credentials".into()),
+        )
+            .into_response();
+    }
+
+    let user = User {
+        name: credentials.user.clone(),
+        read_only: credentials.read_only,
+    };
+    request.extensions_mut().insert(user.clone());
+
+    // Also on the response, for the access log
+    let mut response = next.run(request).await;
+    response.extensions_mut().insert(user);
+
+    response
+}
+
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(credentials: &str) -> String {
+        format!(
+            "Basic {}",
+            base64::engine::general_purpose::STANDARD.encode(credentials)
+        )
+    }
+
+    #[test]
+    fn verifies_credentials() {
+        let basic = Basic::new("alice", "pa:ss");
+
+        assert!(basic.verify(&header("alice:pa:ss")));
+        assert!(!basic.verify(&header("alice:pa")));
+        assert!(!basic.verify(&header("bob:pa:ss")));
+        assert!(!basic.verify("Bearer token"));
+        assert!(!basic.verify("Basic not-base64!"));
+    }
+}
@@ -0,0 +1,83 @@
+//! Response compression
+//!
+//! Enable with [`Builder::compression`](crate::server::Builder::compression). Listings,
+//! pages and other text are compressed; tiny responses and content types that are
+//! already compressed, like most images, media and archives, are passed through.
+
+use axum::body::HttpBody;
+use http::header;
+use tower_http::compression::{
+    CompressionLayer,
+    predicate::{DefaultPredicate, Predicate},
+};
+
+/// Media type prefixes of formats that don't compress further
+const COMPRESSED: [&str; 11] = [
+    "video/",
+    "audio/",
+    "font/woff",
+    "application/zip",
+    "application/gzip",
+    "application/x-gzip",
+    "application/zstd",
+    "application/x-7z-compressed",
+    "application/vnd.rar",
+    "application/x-rar",
+    "application/pdf",
+];
+
+/// Predicate deciding which responses are compressed
+///
+/// On top of the listed formats, skips responses under 32 bytes, images other than
+/// SVG and event streams.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Compressible;
+
+impl Predicate for Compressible {
+    fn should_compress<B>(&self, response: &http::Response<B>) -> bool
+    where
+        B: HttpBody,
+    {
+        let content_type = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+
+        DefaultPredicate::new().should_compress(response)
+            && !COMPRESSED
+                .iter()
+                .any(|prefix| content_type.starts_with(prefix))
+    }
+}
+
+/// Layer compressing responses with brotli or gzip, as accepted by the client.
+pub fn layer() -> CompressionLayer<Compressible> {
+    CompressionLayer::new().compress_when(Compressible)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(content_type: &str) -> http::Response<String> {
+        http::Response::builder()
+            .header(http::header::CONTENT_TYPE, content_type)
+            .body("x".repeat(1024))
+            .unwrap()
+    }
+
+    #[test]
+    fn skips_compressed_formats() {
+        let predicate = Compressible;
+
+        assert!(predicate.should_compress(&response("application/json")));
+        assert!(predicate.should_compress(&response("text/markdown")));
+        assert!(predicate.should_compress(&response("image/svg+xml")));
+        assert!(!predicate.should_compress(&response("image/png")));
+        assert!(!predicate.should_compress(&response("video/mp4")));
+        assert!(!predicate.should_compress(&response("application/zip")));
+        assert!(!predicate.should_compress(&response("font/woff2")));
+    }
+}
@@ -0,0 +1,336 @@
+//! Cross-origin access to the API
+//!
+//! Enable with [`Builder::cors`](crate::server::Builder::cors). Preflight requests are
+//! answered directly, before authentication, and responses to allowed origins get the
+//! `Access-Control-*` headers browsers need to read them.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use http::{HeaderMap, HeaderValue, Method, StatusCode, header};
+
+use crate::glob;
+use crate::server::error::Error;
+
+/// Request headers used by the SilverBullet client
+const DEFAULT_HEADERS: [&str; 7] = [
+    "content-type",
+    "authorization",
+    "x-get-meta",
+    "x-created",
+    "x-last-modified",
+    "x-permission",
+    "x-proxy-header-*",
+];
+
+/// Response headers the client reads
+const EXPOSED_HEADERS: [&str; 6] = [
+    "x-content-length",
+    "x-created",
+    "x-last-modified",
+    "x-permission",
+    "x-proxy-status-code",
+    "x-request-id",
+];
+
+/// CORS settings
+#[derive(Debug, Clone)]
+pub struct Cors {
+    origins: Option<Vec<String>>,
+    headers: Vec<String>,
+    methods: Vec<Method>,
+    credentials: bool,
+    max_age: Option<Duration>,
+}
+
+impl Default for Cors {
+    fn default() -> Self {
+        Self {
+            origins: Some(Vec::new()),
+            headers: DEFAULT_HEADERS.iter().map(|h| h.to_string()).collect(),
+            methods: vec![
+                Method::GET,
+                Method::PUT,
+                Method::POST,
+                Method::DELETE,
+                Method::OPTIONS,
+            ],
+            credentials: false,
+            max_age: Some(Duration::from_secs(600)),
+        }
+    }
+}
+
+impl Cors {
+    /// No origin allowed until added with [`Cors::allow_origin`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allow an origin, eg. `https://example.com`.
+    #[must_use]
+    pub fn allow_origin(mut self, origin: impl Into<String>) -> Self {
+        if let Some(origins) = &mut self.origins {
+            origins.push(origin.into().trim_end_matches('/').to_ascii_lowercase());
+        }
+        self
+    }
+
+    /// Allow every origin.
+    #[must_use]
+    pub fn allow_any_origin(mut self) -> Self {
+        self.origins = None;
+        self
+    }
+
+    /// Allow a request header in addition to the ones used by the client, as a glob.
+    #[must_use]
+    pub fn allow_header(mut self, pattern: impl Into<String>) -> Self {
+        self.headers.push(pattern.into().to_ascii_lowercase());
+        self
+    }
+
+    /// Replace the allowed methods.
+    #[must_use]
+    pub fn allow_methods(mut self, methods: impl IntoIterator<Item = Method>) -> Self {
+        self.methods = methods.into_iter().collect();
+        self
+    }
+
+    /// Let browsers send cookies and credentials (disabled by default).
+    #[must_use]
+    pub fn allow_credentials(mut self, allow: bool) -> Self {
+        self.credentials = allow;
+        self
+    }
+
+    /// How long browsers may cache preflight responses (10 minutes by default).
+    #[must_use]
+    pub fn max_age(mut self, max_age: Option<Duration>) -> Self {
+        self.max_age = max_age;
+        self
+    }
+
+    fn allows_origin(&self, origin: &str) -> bool {
+        match &self.origins {
+            None => true,
+            Some(origins) => origins.iter().any(|o| o.eq_ignore_ascii_case(origin)),
+        }
+    }
+
+    fn allows_header(&self, name: &str) -> bool {
+        let name = name.trim().to_ascii_lowercase();
+
+        self.headers
+            .iter()
+            .any(|pattern| glob::matches(pattern, &name))
+    }
+
+    /// Headers common to preflight and actual responses.
+    fn origin_headers(&self, origin: &HeaderValue, headers: &mut HeaderMap) {
+        // A wildcard can't be combined with credentials, so echo the origin instead
+        let allow_origin = if self.origins.is_none() && !self.credentials {
+            HeaderValue::from_static("*")
+        } else {
+            origin.clone()
+        };
+
+        headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, allow_origin);
+
+        if self.credentials {
+            headers.insert(
+                header::ACCESS_CONTROL_ALLOW_CREDENTIALS,
+                HeaderValue::from_static("true"),
+            );
+        }
+
+        if self.origins.is_some() || self.credentials {
+            headers.append(header::VARY, HeaderValue::from_static("origin"));
+        }
+    }
+
+    fn preflight(&self, origin: &HeaderValue, request: &HeaderMap) -> Response {
+        let method_allowed = request
+            .get(header::ACCESS_CONTROL_REQUEST_METHOD)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<Method>().ok())
+            .is_some_and(|method| self.methods.contains(&method));
+
+        let requested_headers = request
+            .get(header::ACCESS_CONTROL_REQUEST_HEADERS)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default();
+
+        let headers_allowed = requested_headers
+            .split(',')
+            .filter(|name| !name.trim().is_empty())
+            .all(|name| self.allows_header(name));
+
+        if !method_allowed || !headers_allowed {
+            return Error::forbidden("preflight request not allowed").into_response();
+        }
+
+        let mut response = StatusCode::NO_CONTENT.into_response();
+        let headers = response.headers_mut();
+
+        self.origin_headers(origin, headers);
+
+        let methods = self
+            .methods
+            .iter()
+            .map(Method::as_str)
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        if let Ok(methods) = HeaderValue::from_str(&methods) {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_METHODS, methods);
+        }
+
+        // Echo the requested headers, they were all checked above
+        if let Ok(allowed) = HeaderValue::from_str(requested_headers)
+            && !requested_headers.is_empty()
+        {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_HEADERS, allowed);
+        }
+
+        if let Some(max_age) = self.max_age {
+            headers.insert(header::ACCESS_CONTROL_MAX_AGE, max_age.as_secs().into());
+        }
+
+        response
+    }
+
+    fn expose_headers(&self, response: &mut HeaderMap) {
+        // Proxied responses have arbitrary x-proxy-header-* headers, which can only be
+        // exposed with a wildcard, and credentialed requests don't support wildcards
+        let exposed = if self.credentials {
+            let mut exposed: Vec<String> = EXPOSED_HEADERS.iter().map(|h| h.to_string()).collect();
+            exposed.extend(
+                response
+                    .keys()
+                    .filter(|name| name.as_str().starts_with("x-proxy-header-"))
+                    .map(|name| name.to_string()),
+            );
+            exposed.join(", ")
+        } else {
+            "*".to_string()
+        };
+
+        if let Ok(exposed) = HeaderValue::from_str(&exposed) {
+            response.insert(header::ACCESS_CONTROL_EXPOSE_HEADERS, exposed);
+        }
+    }
+}
+
+/// Middleware answering preflight requests and adding CORS headers to responses.
+///
+/// Use with `axum::middleware::from_fn_with_state(Arc::new(cors), cors::middleware)`.
+pub async fn middleware(State(cors): State<Arc<Cors>>, request: Request, next: Next) -> Response {
+    let Some(origin) = request
+        .headers()
+        .get(header::ORIGIN)
+        .filter(|origin| origin.to_str().is_ok_and(|o| cors.allows_origin(o)))
+        .cloned()
+    else {
+        return next.run(request).await;
+    };
+
+    if request.method() == Method::OPTIONS
+        && request
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_REQUEST_METHOD)
+    {
+        return cors.preflight(&origin, request.headers());
+    }
+
+    let mut response = next.run(request).await;
+
+    cors.origin_headers(&origin, response.headers_mut());
+    cors.expose_headers(response.headers_mut());
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn preflight(method: &str, headers: &str) -> HeaderMap {
+        let mut request = HeaderMap::new();
+        request.insert(
+            header::ACCESS_CONTROL_REQUEST_METHOD,
+            method.parse().unwrap(),
+        );
+        request.insert(
+            header::ACCESS_CONTROL_REQUEST_HEADERS,
+            headers.parse().unwrap(),
+        );
+        request
+    }
+
+    #[test]
+    fn answers_preflight() {
+        let cors = Cors::new().allow_origin("https://Example.com/");
+        let origin = HeaderValue::from_static("https://example.com");
+
+        assert!(cors.allows_origin("https://example.com"));
+        assert!(!cors.allows_origin("https://evil.com"));
+
+        let response = cors.preflight(
+            &origin,
+            &preflight("PUT", "X-Get-Meta, x-proxy-header-authorization"),
+        );
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(
+            response.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://example.com"
+        );
+        assert_eq!(
+            response.headers()[header::ACCESS_CONTROL_ALLOW_HEADERS],
+            "X-Get-Meta, x-proxy-header-authorization"
+        );
+        assert_eq!(response.headers()[header::VARY], "origin");
+
+        let response = cors.preflight(&origin, &preflight("PUT", "x-unknown"));
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let response = cors.preflight(&origin, &preflight("PATCH", ""));
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[test]
+    fn wildcard_without_credentials() {
+        let origin = HeaderValue::from_static("https://example.com");
+
+        let mut headers = HeaderMap::new();
+        let cors = Cors::new().allow_any_origin();
+        cors.origin_headers(&origin, &mut headers);
+        cors.expose_headers(&mut headers);
+
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+        assert_eq!(headers[header::ACCESS_CONTROL_EXPOSE_HEADERS], "*");
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-proxy-header-etag", "abc".parse().unwrap());
+        let cors = cors.allow_credentials(true);
+        cors.origin_headers(&origin, &mut headers);
+        cors.expose_headers(&mut headers);
+
+        assert_eq!(
+            headers[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://example.com"
+        );
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+        assert!(
+            headers[header::ACCESS_CONTROL_EXPOSE_HEADERS]
+                .to_str()
+                .unwrap()
+                .ends_with("x-proxy-header-etag")
+        );
+    }
+}
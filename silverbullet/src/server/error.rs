@@ -1,25 +1,196 @@
+//! Errors returned by the route handlers
+//!
+//! Each variant has its own status code and is sent as a JSON [`ErrorBody`], e.g.
+//! `{"error": "not_found", "message": "File not found: notes/page.md"}`, so clients
+//! can tell failures apart. Internal errors are logged with their source and only
+//! respond with a generic message. Failures of proxied requests also name the
+//! [`Upstream`] host and why it failed, e.g. `"upstream": {"host": "example.com",
+//! "category": "dns"}`.
+
 use axum::{
+    Json,
     http::StatusCode,
     response::{IntoResponse, Response},
 };
+use serde::Serialize;
+
+use crate::{fs, proxy, shell};
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The request is malformed, e.g. invalid headers or body
+    #[error("{0}")]
+    BadRequest(#[source] BoxError),
+
+    /// The request lacks valid credentials
+    #[error("{0}")]
+    Unauthorized(#[source] BoxError),
+
+    /// The user may not do this, e.g. writing as a read-only user
+    #[error("{0}")]
+    Forbidden(#[source] BoxError),
+
+    #[error("{0}")]
+    NotFound(#[source] BoxError),
+
+    /// The request conflicts with the current state, e.g. a file changed since it was read
+    #[error("{0}")]
+    Conflict(#[source] BoxError),
+
+    /// A condition of the request doesn't hold, e.g. `If-Match` on a changed file
+    #[error("{0}")]
+    PreconditionFailed(#[source] BoxError),
+
+    #[error("{0}")]
+    PayloadTooLarge(#[source] BoxError),
+
+    #[error("{0}")]
+    TooManyRequests(#[source] BoxError),
+
+    /// The server doesn't support this, e.g. a proxy client without websockets
+    #[error("{0}")]
+    NotImplemented(#[source] BoxError),
+
+    /// An upstream server failed or could not be reached
+    #[error("{0}")]
+    Upstream(#[source] BoxError),
+
+    /// An upstream server didn't respond in time
+    #[error("{0}")]
+    UpstreamTimeout(#[source] BoxError),
+
+    /// The service is temporarily unavailable, e.g. an upstream circuit is open
+    #[error("{0}")]
+    Unavailable(#[source] BoxError),
+
+    #[error("{0}")]
+    Internal(#[source] BoxError),
+}
+
+/// JSON body of error responses
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ErrorBody {
+    /// Machine-readable kind of error, e.g. `not_found`
+    pub error: &'static str,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upstream: Option<Upstream>,
+}
+
+/// Upstream of a failed proxy request
+///
+/// The source of the [`Error::Upstream`], [`Error::UpstreamTimeout`] and
+/// [`Error::Unavailable`] of the proxy route, detailed in their body. Also set as an
+/// extension of their responses, e.g. for metrics.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, thiserror::Error)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[error("{message}")]
+pub struct Upstream {
+    pub host: String,
+    pub category: proxy::Category,
+    #[serde(skip)]
+    pub message: String,
+}
+
+impl Error {
+    /// Deny the request, eg. because the user is not allowed to use a route.
+    pub fn forbidden(source: impl Into<BoxError>) -> Self {
+        Error::Forbidden(source.into())
+    }
+
+    pub fn not_found(source: impl Into<BoxError>) -> Self {
+        Error::NotFound(source.into())
+    }
+
+    pub fn internal(source: impl Into<BoxError>) -> Self {
+        Error::Internal(source.into())
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Error::BadRequest(_) => StatusCode::BAD_REQUEST,
+            Error::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            Error::Forbidden(_) => StatusCode::FORBIDDEN,
+            Error::NotFound(_) => StatusCode::NOT_FOUND,
+            Error::Conflict(_) => StatusCode::CONFLICT,
+            Error::PreconditionFailed(_) => StatusCode::PRECONDITION_FAILED,
+            Error::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            Error::TooManyRequests(_) => StatusCode::TOO_MANY_REQUESTS,
+            Error::NotImplemented(_) => StatusCode::NOT_IMPLEMENTED,
+            Error::Upstream(_) => StatusCode::BAD_GATEWAY,
+            Error::UpstreamTimeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            Error::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            Error::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// Machine-readable kind of the error, sent as `error` in the body.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::BadRequest(_) => "bad_request",
+            Error::Unauthorized(_) => "unauthorized",
+            Error::Forbidden(_) => "forbidden",
+            Error::NotFound(_) => "not_found",
+            Error::Conflict(_) => "conflict",
+            Error::PreconditionFailed(_) => "precondition_failed",
+            Error::PayloadTooLarge(_) => "payload_too_large",
+            Error::TooManyRequests(_) => "too_many_requests",
+            Error::NotImplemented(_) => "not_implemented",
+            Error::Upstream(_) => "upstream",
+            Error::UpstreamTimeout(_) => "upstream_timeout",
+            Error::Unavailable(_) => "unavailable",
+            Error::Internal(_) => "internal",
+        }
+    }
 
-pub struct Error(Box<dyn std::error::Error + Send + Sync>);
+    pub fn body(&self) -> ErrorBody {
+        let message = match self {
+            Error::Internal(_) => "Internal server error".to_string(),
+            err => err.to_string(),
+        };
+
+        ErrorBody {
+            error: self.code(),
+            message,
+            upstream: self.upstream().cloned(),
+        }
+    }
+
+    /// Upstream of a failed proxy request.
+    pub fn upstream(&self) -> Option<&Upstream> {
+        match self {
+            Error::Upstream(source)
+            | Error::UpstreamTimeout(source)
+            | Error::Unavailable(source) => source.downcast_ref(),
+            _ => None,
+        }
+    }
+}
 
 impl IntoResponse for Error {
     fn into_response(self) -> Response {
+        let status = self.status();
+
         #[cfg(feature = "tracing")]
-        tracing::error!(error = %self.0, error.source = ?self.0.source(), "Internal server error");
+        if status.is_server_error() {
+            let source = std::error::Error::source(&self).and_then(|source| source.source());
+            tracing::error!(error = %self, error.source = ?source, "Internal server error");
+        } else {
+            tracing::debug!(error = %self, status = %status, "Request rejected");
+        }
 
-        StatusCode::INTERNAL_SERVER_ERROR.into_response()
-    }
-}
+        let body = self.body();
+        let upstream = body.upstream.clone();
+        let mut response = (status, Json(body)).into_response();
 
-impl<E> From<E> for Error
-where
-    E: std::error::Error + Send + Sync + 'static,
-{
-    fn from(err: E) -> Self {
-        Error(Box::new(err))
+        if let Some(upstream) = upstream {
+            response.extensions_mut().insert(upstream);
+        }
+
+        response
     }
 }
 
@@ -28,3 +199,92 @@ impl From<Error> for Response {
         err.into_response()
     }
 }
+
+impl From<fs::Error> for Error {
+    fn from(err: fs::Error) -> Self {
+        match &err {
+            fs::Error::NotFound(_) => Error::NotFound(err.into()),
+            fs::Error::Io(io) if io.kind() == std::io::ErrorKind::NotFound => {
+                Error::NotFound(err.into())
+            }
+            fs::Error::PermissionDenied(_) => Error::Forbidden(err.into()),
+            fs::Error::PreconditionFailed(_) => Error::PreconditionFailed(err.into()),
+            fs::Error::Transient(_) => Error::Unavailable(err.into()),
+            _ => Error::Internal(err.into()),
+        }
+    }
+}
+
+impl From<shell::Error> for Error {
+    fn from(err: shell::Error) -> Self {
+        Error::Internal(err.into())
+    }
+}
+
+#[cfg(feature = "ssr")]
+impl From<crate::ssr::Error> for Error {
+    fn from(err: crate::ssr::Error) -> Self {
+        Error::Internal(err.into())
+    }
+}
+
+impl From<http::header::InvalidHeaderValue> for Error {
+    fn from(err: http::header::InvalidHeaderValue) -> Self {
+        Error::Internal(err.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_fs_errors() {
+        let err = Error::from(fs::Error::NotFound("notes/page.md".into()));
+        assert_eq!(err.status(), StatusCode::NOT_FOUND);
+        assert_eq!(
+            err.body(),
+            ErrorBody {
+                error: "not_found",
+                message: "File not found: notes/page.md".to_string(),
+                upstream: None,
+            }
+        );
+
+        let err = Error::from(fs::Error::PermissionDenied("read-only".into()));
+        assert_eq!(err.status(), StatusCode::FORBIDDEN);
+
+        // Internal details stay in the logs
+        let err = Error::from(fs::Error::Other("connection string leaked".into()));
+        assert_eq!(err.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(err.body().message, "Internal server error");
+    }
+
+    #[test]
+    fn details_upstream_failures() {
+        let err = Error::Upstream(
+            Upstream {
+                host: "example.com".to_string(),
+                category: proxy::Category::Dns,
+                message: "HTTP client error: dns error".to_string(),
+            }
+            .into(),
+        );
+
+        assert_eq!(
+            serde_json::to_value(err.body()).unwrap(),
+            serde_json::json!({
+                "error": "upstream",
+                "message": "HTTP client error: dns error",
+                "upstream": {"host": "example.com", "category": "dns"},
+            })
+        );
+
+        let response = err.into_response();
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+        assert_eq!(
+            response.extensions().get::<Upstream>().unwrap().category,
+            proxy::Category::Dns
+        );
+    }
+}
@@ -0,0 +1,482 @@
+//! Resized and re-encoded variants of images
+//!
+//! Enable with [`Builder::media`](crate::server::Builder::media). A `GET` of an image in
+//! `/.fs` with `w`, `h` or `format` in its query gets a variant of the file instead of
+//! the original, e.g. `/.fs/photo.jpg?w=800&format=webp` for a 800 pixels wide WebP of
+//! it. Images are scaled down to fit within the given width and height, keeping their
+//! aspect ratio, and never scaled up.
+//!
+//! | Parameter | Value |
+//! |---|---|
+//! | `w`, `h` | Largest width and height in pixels, at most [`Media::max_dimension`] |
+//! | `format` | `png`, `jpeg` (or `jpg`) or `webp`, that of the original by default |
+//!
+//! PNG, JPEG, GIF and WebP images are converted; other files and requests without those
+//! parameters are served as usual. The original is read through the `/.fs` route, so
+//! permissions and signed URLs apply to variants too. Variants are kept in memory, up to
+//! [`Media::cache_size`] bytes, by name, modification time, size and parameters, so a
+//! changed file gets new ones. WebP variants are lossless.
+
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::sync::{Arc, Mutex};
+
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use bytes::Bytes;
+use http::{HeaderValue, Method, StatusCode, header};
+use image::{DynamicImage, ImageFormat, imageops::FilterType};
+
+use crate::server::error::Error;
+
+/// Format of a variant
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Format {
+    Png,
+    Jpeg,
+    Webp,
+}
+
+impl Format {
+    fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "png" => Some(Format::Png),
+            "jpeg" | "jpg" => Some(Format::Jpeg),
+            "webp" => Some(Format::Webp),
+            _ => None,
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Format::Png => "image/png",
+            Format::Jpeg => "image/jpeg",
+            Format::Webp => "image/webp",
+        }
+    }
+}
+
+/// Parameters of a variant
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Variant {
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub format: Option<Format>,
+}
+
+impl Variant {
+    /// Parameters of a query, `None` without any, `Err` with invalid ones.
+    pub fn from_query(query: &str, max_dimension: u32) -> Result<Option<Self>, Error> {
+        let mut variant = Variant::default();
+        let mut found = false;
+
+        for (name, value) in query.split('&').filter_map(|pair| pair.split_once('=')) {
+            let dimension = || match value.parse::<u32>() {
+                Ok(pixels) if (1..=max_dimension).contains(&pixels) => Ok(Some(pixels)),
+                _ => Err(Error::BadRequest(
+                    format!("{name} should be from 1 to {max_dimension} pixels, got {value}")
+                        .into(),
+                )),
+            };
+
+            match name {
+                "w" => variant.width = dimension()?,
+                "h" => variant.height = dimension()?,
+                "format" => {
+                    variant.format = Some(Format::parse(value).ok_or_else(|| {
+                        Error::BadRequest(format!("unsupported image format {value}").into())
+                    })?);
+                }
+                _ => continue,
+            }
+
+            found = true;
+        }
+
+        Ok(found.then_some(variant))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Key {
+    path: String,
+    last_modified: Option<HeaderValue>,
+    size: Option<HeaderValue>,
+    variant: Variant,
+}
+
+struct Entry {
+    body: Bytes,
+    format: Format,
+    used: u64,
+}
+
+/// Variants by recency of use, evicting the least recently used over capacity
+#[derive(Default)]
+struct Cache {
+    entries: HashMap<Key, Entry>,
+    size: usize,
+    tick: u64,
+}
+
+impl Cache {
+    fn get(&mut self, key: &Key) -> Option<(Bytes, Format)> {
+        self.tick += 1;
+
+        let entry = self.entries.get_mut(key)?;
+        entry.used = self.tick;
+
+        Some((entry.body.clone(), entry.format))
+    }
+
+    fn insert(&mut self, key: Key, body: Bytes, format: Format, capacity: usize) {
+        if body.len() > capacity {
+            return;
+        }
+
+        self.tick += 1;
+        self.size += body.len();
+        let entry = Entry {
+            body,
+            format,
+            used: self.tick,
+        };
+        if let Some(old) = self.entries.insert(key, entry) {
+            self.size -= old.body.len();
+        }
+
+        while self.size > capacity {
+            let Some(oldest) = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.used)
+                .map(|(key, _)| key.clone())
+            else {
+                break;
+            };
+
+            if let Some(entry) = self.entries.remove(&oldest) {
+                self.size -= entry.body.len();
+            }
+        }
+    }
+}
+
+/// Image variant settings
+pub struct Media {
+    cache: Mutex<Cache>,
+    cache_size: usize,
+    max_dimension: u32,
+    max_source: usize,
+    quality: u8,
+}
+
+impl Default for Media {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Media {
+    /// 64 MiB of variants, of images up to 32 MiB and 4096 pixels wide or high.
+    pub fn new() -> Self {
+        Self {
+            cache: Mutex::new(Cache::default()),
+            cache_size: 64 * 1024 * 1024,
+            max_dimension: 4096,
+            max_source: 32 * 1024 * 1024,
+            quality: 80,
+        }
+    }
+
+    /// Bytes of variants kept in memory, none when 0.
+    #[must_use]
+    pub fn cache_size(mut self, bytes: usize) -> Self {
+        self.cache_size = bytes;
+        self
+    }
+
+    /// Largest `w` and `h` accepted.
+    #[must_use]
+    pub fn max_dimension(mut self, pixels: u32) -> Self {
+        self.max_dimension = pixels;
+        self
+    }
+
+    /// Largest original converted, larger ones being refused with 413.
+    #[must_use]
+    pub fn max_source(mut self, bytes: usize) -> Self {
+        self.max_source = bytes;
+        self
+    }
+
+    /// Quality of JPEG variants, from 1 to 100.
+    #[must_use]
+    pub fn jpeg_quality(mut self, quality: u8) -> Self {
+        self.quality = quality.clamp(1, 100);
+        self
+    }
+}
+
+/// Format of a file by its extension, for those converted.
+fn source_format(path: &str) -> Option<ImageFormat> {
+    let (_, extension) = path.rsplit_once('.')?;
+
+    match extension.to_ascii_lowercase().as_str() {
+        "png" => Some(ImageFormat::Png),
+        "jpg" | "jpeg" => Some(ImageFormat::Jpeg),
+        "gif" => Some(ImageFormat::Gif),
+        "webp" => Some(ImageFormat::WebP),
+        _ => None,
+    }
+}
+
+/// Convert an image, `Err` if it doesn't decode.
+pub fn convert(
+    data: &[u8],
+    source: ImageFormat,
+    variant: Variant,
+    quality: u8,
+) -> image::ImageResult<(Vec<u8>, Format)> {
+    let image = image::load_from_memory_with_format(data, source)?;
+
+    // Fit within the bounds, without scaling up
+    let width = variant.width.unwrap_or(u32::MAX).min(image.width());
+    let height = variant.height.unwrap_or(u32::MAX).min(image.height());
+    let image = match width < image.width() || height < image.height() {
+        true => image.resize(width, height, FilterType::Lanczos3),
+        false => image,
+    };
+
+    let format = variant.format.unwrap_or(match source {
+        ImageFormat::Jpeg => Format::Jpeg,
+        ImageFormat::WebP => Format::Webp,
+        _ => Format::Png,
+    });
+
+    let mut out = Cursor::new(Vec::new());
+    match format {
+        Format::Png => image.write_to(&mut out, ImageFormat::Png)?,
+        Format::Jpeg => {
+            let encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(&mut out, quality);
+            DynamicImage::from(image.to_rgb8()).write_with_encoder(encoder)?;
+        }
+        Format::Webp => {
+            DynamicImage::from(image.to_rgba8()).write_to(&mut out, ImageFormat::WebP)?
+        }
+    }
+
+    Ok((out.into_inner(), format))
+}
+
+/// Serve variants of the images of the `/.fs` routes it wraps.
+pub async fn middleware(State(media): State<Arc<Media>>, request: Request, next: Next) -> Response {
+    match variant(&media, request, next).await {
+        Ok(response) => response,
+        Err(err) => err.into_response(),
+    }
+}
+
+async fn variant(media: &Arc<Media>, request: Request, next: Next) -> Result<Response, Error> {
+    let path = request.uri().path().to_string();
+    let query = request.uri().query().unwrap_or("");
+
+    let source = match (request.method(), source_format(&path)) {
+        (&Method::GET, Some(source)) if !request.headers().contains_key("x-get-meta") => source,
+        _ => return Ok(next.run(request).await),
+    };
+    let Some(variant) = Variant::from_query(query, media.max_dimension)? else {
+        return Ok(next.run(request).await);
+    };
+
+    let response = next.run(request).await;
+    if response.status() != StatusCode::OK {
+        return Ok(response);
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let key = Key {
+        path,
+        last_modified: parts.headers.get("x-last-modified").cloned(),
+        size: parts.headers.get("x-content-length").cloned(),
+        variant,
+    };
+
+    let cached = media.cache.lock().unwrap().get(&key);
+    let (body, format) = match cached {
+        Some(cached) => cached,
+        None => {
+            let data = axum::body::to_bytes(body, media.max_source)
+                .await
+                .map_err(|_| {
+                    Error::PayloadTooLarge(
+                        format!("images over {} bytes aren't converted", media.max_source).into(),
+                    )
+                })?;
+
+            let quality = media.quality;
+            let converted =
+                tokio::task::spawn_blocking(move || convert(&data, source, variant, quality))
+                    .await
+                    .map_err(Error::internal)?
+                    .map_err(|err| Error::BadRequest(format!("not a valid image: {err}").into()))?;
+            let (body, format) = (Bytes::from(converted.0), converted.1);
+
+            media
+                .cache
+                .lock()
+                .unwrap()
+                .insert(key, body.clone(), format, media.cache_size);
+
+            (body, format)
+        }
+    };
+
+    parts.headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static(format.content_type()),
+    );
+    parts
+        .headers
+        .insert(header::CONTENT_LENGTH, body.len().into());
+    parts.headers.remove(header::ETAG);
+
+    Ok(Response::from_parts(parts, Body::from(body)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client;
+    use crate::fs::testing::MemoryFs;
+    use crate::server::Builder;
+    use crate::server::test::TestServer;
+    use image::{GenericImageView as _, RgbaImage};
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let image = RgbaImage::from_pixel(width, height, image::Rgba([200, 40, 40, 255]));
+        let mut out = Cursor::new(Vec::new());
+        image.write_to(&mut out, ImageFormat::Png).unwrap();
+
+        out.into_inner()
+    }
+
+    fn server(media: Media) -> TestServer {
+        let fs = MemoryFs::new()
+            .with_file("photo.png", &png(400, 200))
+            .with_file("broken.png", b"not a png")
+            .with_file("notes.md", b"# Notes");
+
+        TestServer::build(Builder::new().media(media), client::Config::default(), fs)
+    }
+
+    #[test]
+    fn parses_queries() {
+        assert_eq!(Variant::from_query("", 100).unwrap(), None);
+        assert_eq!(Variant::from_query("sig=abc", 100).unwrap(), None);
+        assert_eq!(
+            Variant::from_query("w=80&format=JPG&sig=abc", 100).unwrap(),
+            Some(Variant {
+                width: Some(80),
+                height: None,
+                format: Some(Format::Jpeg),
+            })
+        );
+
+        assert!(Variant::from_query("w=0", 100).is_err());
+        assert!(Variant::from_query("h=101", 100).is_err());
+        assert!(Variant::from_query("format=bmp", 100).is_err());
+    }
+
+    #[tokio::test]
+    async fn serves_variants() {
+        let server = server(Media::new());
+
+        let response = server.get("/.fs/photo.png?w=100&format=webp").await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.header("content-type"), Some("image/webp"));
+        assert_eq!(
+            response.header("content-length"),
+            Some(response.body.len().to_string().as_str())
+        );
+        let image = image::load_from_memory(&response.body).unwrap();
+        assert_eq!(image.dimensions(), (100, 50));
+
+        // Never scaled up, and in the original format by default
+        let response = server.get("/.fs/photo.png?h=1000").await;
+        assert_eq!(response.header("content-type"), Some("image/png"));
+        let image = image::load_from_memory(&response.body).unwrap();
+        assert_eq!(image.dimensions(), (400, 200));
+
+        let response = server.get("/.fs/photo.png?w=50&h=50&format=jpeg").await;
+        let image = image::load_from_memory(&response.body).unwrap();
+        assert_eq!(image.dimensions(), (50, 25));
+    }
+
+    #[tokio::test]
+    async fn leaves_other_requests_alone() {
+        let server = server(Media::new());
+        let original = png(400, 200);
+
+        assert_eq!(server.get("/.fs/photo.png").await.body, original);
+        assert_eq!(server.get("/.fs/notes.md?w=100").await.body, "# Notes");
+        assert_eq!(
+            server.get("/.fs/missing.png?w=100").await.status,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            server.get("/.fs/photo.png?w=huge").await.status,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            server.get("/.fs/broken.png?w=100").await.status,
+            StatusCode::BAD_REQUEST
+        );
+
+        let server = self::server(Media::new().max_source(16));
+        assert_eq!(
+            server.get("/.fs/photo.png?w=100").await.status,
+            StatusCode::PAYLOAD_TOO_LARGE
+        );
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let key = |path: &str| Key {
+            path: path.to_string(),
+            last_modified: None,
+            size: None,
+            variant: Variant::default(),
+        };
+
+        let mut cache = Cache::default();
+        cache.insert(key("a"), Bytes::from_static(b"aaaa"), Format::Png, 8);
+        cache.insert(key("b"), Bytes::from_static(b"bbbb"), Format::Png, 8);
+        assert!(cache.get(&key("a")).is_some());
+
+        cache.insert(key("c"), Bytes::from_static(b"cccc"), Format::Png, 8);
+        assert!(cache.get(&key("b")).is_none());
+        assert!(cache.get(&key("a")).is_some());
+        assert_eq!(cache.size, 8);
+
+        // Nor kept when larger than the whole cache
+        cache.insert(key("d"), Bytes::from_static(b"ddddddddd"), Format::Png, 8);
+        assert!(cache.get(&key("d")).is_none());
+    }
+
+    #[tokio::test]
+    async fn follows_file_changes() {
+        let server = server(Media::new());
+        let before = server.get("/.fs/photo.png?w=100").await;
+        server.put("/.fs/photo.png", png(100, 100)).await;
+        let after = server.get("/.fs/photo.png?w=100").await;
+
+        let image = image::load_from_memory(&after.body).unwrap();
+        assert_ne!(before.body, after.body);
+        assert_eq!(image.dimensions(), (100, 100));
+    }
+}
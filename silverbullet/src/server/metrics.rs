@@ -0,0 +1,501 @@
+//! Prometheus metrics for the API, exported at `GET /.metrics`
+//!
+//! Enable with [`Builder::metrics`](crate::server::Builder::metrics). Requests are counted
+//! and timed per route; `/.fs` requests are also counted per operation with the bytes
+//! read and written, and `/.proxy` requests per upstream status. Proxied requests are
+//! also counted and timed per upstream host, with the status of the upstream or the
+//! [`Category`](crate::proxy::Category) of its failure.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::{
+    body::Body,
+    extract::{MatchedPath, Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use futures::TryStreamExt as _;
+use http::{HeaderMap, Method, header};
+
+use crate::proxy;
+use crate::server::error::Upstream;
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+
+#[cfg(target_arch = "wasm32")]
+use web_time::Instant;
+
+/// Upper bounds of the request duration histogram buckets, in seconds
+const BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Upstream hosts with their own series, the next ones counted as `other`
+const MAX_PROXY_HOSTS: usize = 100;
+
+/// Metrics registry shared by the middleware and the export route
+#[derive(Clone, Default)]
+pub struct Metrics {
+    inner: Arc<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    registry: Mutex<Registry>,
+    fs_read_bytes: AtomicU64,
+    fs_written_bytes: AtomicU64,
+}
+
+#[derive(Default)]
+struct Registry {
+    requests: BTreeMap<(String, String, u16), u64>,
+    durations: BTreeMap<(String, String), Histogram>,
+    fs_operations: BTreeMap<&'static str, u64>,
+    proxy_responses: BTreeMap<String, u64>,
+    proxy_upstreams: BTreeMap<(String, String), u64>,
+    proxy_durations: BTreeMap<String, Histogram>,
+    cache: BTreeMap<(String, bool), u64>,
+}
+
+#[derive(Default)]
+struct Histogram {
+    buckets: [u64; BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: f64) {
+        if let Some(i) = BUCKETS.iter().position(|bound| value <= *bound) {
+            self.buckets[i] += 1;
+        }
+
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count a lookup in a cache, eg. from a caching filesystem layer.
+    pub fn record_cache(&self, cache: &str, hit: bool) {
+        *self
+            .registry()
+            .cache
+            .entry((cache.to_string(), hit))
+            .or_default() += 1;
+    }
+
+    fn record_request(&self, route: &str, method: &Method, status: u16, duration: Duration) {
+        let mut registry = self.registry();
+
+        *registry
+            .requests
+            .entry((route.to_string(), method.to_string(), status))
+            .or_default() += 1;
+
+        registry
+            .durations
+            .entry((route.to_string(), method.to_string()))
+            .or_default()
+            .observe(duration.as_secs_f64());
+    }
+
+    fn record_fs_operation(&self, operation: &'static str) {
+        *self.registry().fs_operations.entry(operation).or_default() += 1;
+    }
+
+    fn record_proxy_response(&self, status: Option<&str>) {
+        *self
+            .registry()
+            .proxy_responses
+            .entry(status.unwrap_or("error").to_string())
+            .or_default() += 1;
+    }
+
+    fn record_proxy_upstream(&self, host: &str, result: &str, duration: Duration) {
+        let mut registry = self.registry();
+
+        let host = match registry.proxy_durations.contains_key(host)
+            || registry.proxy_durations.len() < MAX_PROXY_HOSTS
+        {
+            true => host,
+            false => "other",
+        };
+
+        *registry
+            .proxy_upstreams
+            .entry((host.to_string(), result.to_string()))
+            .or_default() += 1;
+
+        registry
+            .proxy_durations
+            .entry(host.to_string())
+            .or_default()
+            .observe(duration.as_secs_f64());
+    }
+
+    fn registry(&self) -> std::sync::MutexGuard<'_, Registry> {
+        self.inner
+            .registry
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Render all metrics in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let registry = self.registry();
+        let mut out = String::new();
+
+        header(
+            &mut out,
+            "silverbullet_http_requests_total",
+            "counter",
+            "HTTP requests by route, method and status",
+        );
+        for ((route, method, status), count) in &registry.requests {
+            let _ = writeln!(
+                out,
+                "silverbullet_http_requests_total{{route=\"{}\",method=\"{method}\",status=\"{status}\"}} {count}",
+                escape(route)
+            );
+        }
+
+        header(
+            &mut out,
+            "silverbullet_http_request_duration_seconds",
+            "histogram",
+            "HTTP request latency by route and method",
+        );
+        for ((route, method), histogram) in &registry.durations {
+            render_histogram(
+                &mut out,
+                "silverbullet_http_request_duration_seconds",
+                &format!("route=\"{}\",method=\"{method}\"", escape(route)),
+                histogram,
+            );
+        }
+
+        header(
+            &mut out,
+            "silverbullet_fs_operations_total",
+            "counter",
+            "Filesystem operations by type",
+        );
+        for (operation, count) in &registry.fs_operations {
+            let _ = writeln!(
+                out,
+                "silverbullet_fs_operations_total{{operation=\"{operation}\"}} {count}"
+            );
+        }
+
+        header(
+            &mut out,
+            "silverbullet_fs_bytes_total",
+            "counter",
+            "Bytes read from and written to the filesystem",
+        );
+        for (direction, bytes) in [
+            ("read", &self.inner.fs_read_bytes),
+            ("write", &self.inner.fs_written_bytes),
+        ] {
+            let _ = writeln!(
+                out,
+                "silverbullet_fs_bytes_total{{direction=\"{direction}\"}} {}",
+                bytes.load(Ordering::Relaxed)
+            );
+        }
+
+        header(
+            &mut out,
+            "silverbullet_proxy_responses_total",
+            "counter",
+            "Proxied requests by upstream status (error when no response was received)",
+        );
+        for (status, count) in &registry.proxy_responses {
+            let _ = writeln!(
+                out,
+                "silverbullet_proxy_responses_total{{status=\"{}\"}} {count}",
+                escape(status)
+            );
+        }
+
+        header(
+            &mut out,
+            "silverbullet_proxy_upstream_requests_total",
+            "counter",
+            "Proxied requests by upstream host and status, or category of the failure",
+        );
+        for ((host, result), count) in &registry.proxy_upstreams {
+            let _ = writeln!(
+                out,
+                "silverbullet_proxy_upstream_requests_total{{host=\"{}\",result=\"{}\"}} {count}",
+                escape(host),
+                escape(result)
+            );
+        }
+
+        header(
+            &mut out,
+            "silverbullet_proxy_upstream_duration_seconds",
+            "histogram",
+            "Proxied request latency by upstream host",
+        );
+        for (host, histogram) in &registry.proxy_durations {
+            render_histogram(
+                &mut out,
+                "silverbullet_proxy_upstream_duration_seconds",
+                &format!("host=\"{}\"", escape(host)),
+                histogram,
+            );
+        }
+
+        header(
+            &mut out,
+            "silverbullet_cache_requests_total",
+            "counter",
+            "Cache lookups by cache and result",
+        );
+        for ((cache, hit), count) in &registry.cache {
+            let result = if *hit { "hit" } else { "miss" };
+            let _ = writeln!(
+                out,
+                "silverbullet_cache_requests_total{{cache=\"{}\",result=\"{result}\"}} {count}",
+                escape(cache)
+            );
+        }
+
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+}
+
+fn render_histogram(out: &mut String, name: &str, labels: &str, histogram: &Histogram) {
+    let mut cumulative = 0;
+
+    for (bound, count) in BUCKETS.iter().zip(histogram.buckets) {
+        cumulative += count;
+        let _ = writeln!(out, "{name}_bucket{{{labels},le=\"{bound}\"}} {cumulative}");
+    }
+
+    let _ = writeln!(
+        out,
+        "{name}_bucket{{{labels},le=\"+Inf\"}} {}",
+        histogram.count
+    );
+    let _ = writeln!(out, "{name}_sum{{{labels}}} {}", histogram.sum);
+    let _ = writeln!(out, "{name}_count{{{labels}}} {}", histogram.count);
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', r"\\")
+        .replace('"', "\\\"")
+        .replace('\n', r"\n")
+}
+
+/// Filesystem operation performed by a `/.fs` request
+fn fs_operation(route: &str, method: &Method, headers: &HeaderMap) -> Option<&'static str> {
+    let path = route.strip_prefix("/.fs")?;
+
+    Some(match *method {
+        Method::GET if path.is_empty() || path == "/" => "list",
+        Method::GET if headers.contains_key("X-Get-Meta") => "meta",
+        Method::GET => "get",
+        Method::PUT => "put",
+        Method::DELETE => "delete",
+        _ => return None,
+    })
+}
+
+/// Middleware recording request metrics.
+///
+/// Use with `axum::middleware::from_fn_with_state(metrics, metrics::track)`.
+pub async fn track(State(metrics): State<Metrics>, mut request: Request, next: Next) -> Response {
+    let started = Instant::now();
+    let method = request.method().clone();
+
+    // Label by route pattern rather than path to keep the number of series bounded
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or("unmatched", |path| path.as_str())
+        .to_string();
+
+    let operation = fs_operation(&route, &method, request.headers());
+    let upstream = route
+        .starts_with("/.proxy")
+        .then(|| proxy::upstream_host(request.uri()))
+        .flatten();
+
+    if let Some(operation) = operation {
+        metrics.record_fs_operation(operation);
+
+        if operation == "put" {
+            let counter = metrics.clone();
+            request = request.map(|body| {
+                Body::from_stream(body.into_data_stream().inspect_ok(move |chunk| {
+                    counter
+                        .inner
+                        .fs_written_bytes
+                        .fetch_add(chunk.len() as u64, Ordering::Relaxed);
+                }))
+            });
+        }
+    }
+
+    let mut response = next.run(request).await;
+
+    if operation == Some("get") && response.status().is_success() {
+        let counter = metrics.clone();
+        response = response.map(|body| {
+            Body::from_stream(body.into_data_stream().inspect_ok(move |chunk| {
+                counter
+                    .inner
+                    .fs_read_bytes
+                    .fetch_add(chunk.len() as u64, Ordering::Relaxed);
+            }))
+        });
+    }
+
+    if route.starts_with("/.proxy") {
+        let status = response
+            .headers()
+            .get("x-proxy-status-code")
+            .and_then(|v| v.to_str().ok());
+
+        metrics.record_proxy_response(status);
+
+        // Requests refused by the proxy itself never reached the host
+        let failure = response.extensions().get::<Upstream>();
+        if let (Some(host), Some(result)) = (
+            upstream,
+            status.or(failure.map(|upstream| upstream.category.as_str())),
+        ) {
+            metrics.record_proxy_upstream(&host, result, started.elapsed());
+        }
+    }
+
+    metrics.record_request(
+        &route,
+        &method,
+        response.status().as_u16(),
+        started.elapsed(),
+    );
+
+    response
+}
+
+/// Route exporting the metrics in the Prometheus text format.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    get,
+    path = "/.metrics",
+    tag = "metrics",
+    responses((status = 200, description = "Metrics in the Prometheus text format", body = String, content_type = "text/plain; version=0.0.4")),
+))]
+pub async fn export(State(metrics): State<Metrics>) -> impl IntoResponse {
+    (
+        [
+            (
+                header::CONTENT_TYPE,
+                "text/plain; version=0.0.4; charset=utf-8",
+            ),
+            (header::CACHE_CONTROL, "no-cache"),
+        ],
+        metrics.render(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_prometheus_text() {
+        let metrics = Metrics::new();
+
+        metrics.record_request("/.fs/{*path}", &Method::GET, 200, Duration::from_millis(20));
+        metrics.record_request("/.fs/{*path}", &Method::GET, 200, Duration::from_secs(20));
+        metrics.record_fs_operation("get");
+        metrics.record_proxy_response(Some("404"));
+        metrics.record_proxy_response(None);
+        metrics.record_cache("listing", true);
+        metrics.record_proxy_upstream("example.com", "200", Duration::from_millis(20));
+        metrics.record_proxy_upstream("example.com", "dns", Duration::from_millis(2));
+        metrics.inner.fs_read_bytes.fetch_add(42, Ordering::Relaxed);
+
+        let text = metrics.render();
+
+        assert!(text.contains(
+            r#"silverbullet_http_requests_total{route="/.fs/{*path}",method="GET",status="200"} 2"#
+        ));
+        assert!(text.contains(
+            r#"silverbullet_http_request_duration_seconds_bucket{route="/.fs/{*path}",method="GET",le="0.025"} 1"#
+        ));
+        assert!(text.contains(
+            r#"silverbullet_http_request_duration_seconds_bucket{route="/.fs/{*path}",method="GET",le="+Inf"} 2"#
+        ));
+        assert!(text.contains(r#"silverbullet_fs_operations_total{operation="get"} 1"#));
+        assert!(text.contains(r#"silverbullet_fs_bytes_total{direction="read"} 42"#));
+        assert!(text.contains(r#"silverbullet_proxy_responses_total{status="404"} 1"#));
+        assert!(text.contains(r#"silverbullet_proxy_responses_total{status="error"} 1"#));
+        assert!(
+            text.contains(r#"silverbullet_cache_requests_total{cache="listing",result="hit"} 1"#)
+        );
+        assert!(text.contains(
+            r#"silverbullet_proxy_upstream_requests_total{host="example.com",result="dns"} 1"#
+        ));
+        assert!(text.contains(
+            r#"silverbullet_proxy_upstream_duration_seconds_bucket{host="example.com",le="0.025"} 2"#
+        ));
+    }
+
+    #[test]
+    fn bounds_proxy_hosts() {
+        let metrics = Metrics::new();
+
+        for i in 0..=MAX_PROXY_HOSTS {
+            metrics.record_proxy_upstream(&format!("{i}.example.com"), "200", Duration::ZERO);
+        }
+        metrics.record_proxy_upstream("0.example.com", "200", Duration::ZERO);
+
+        let registry = metrics.registry();
+        assert_eq!(registry.proxy_durations.len(), MAX_PROXY_HOSTS + 1);
+        assert_eq!(registry.proxy_durations["other"].count, 1);
+        assert_eq!(registry.proxy_durations["0.example.com"].count, 2);
+    }
+
+    #[test]
+    fn classifies_fs_operations() {
+        let mut headers = HeaderMap::new();
+
+        assert_eq!(fs_operation("/.fs", &Method::GET, &headers), Some("list"));
+        assert_eq!(
+            fs_operation("/.fs/{*path}", &Method::GET, &headers),
+            Some("get")
+        );
+        assert_eq!(
+            fs_operation("/.fs/{*path}", &Method::PUT, &headers),
+            Some("put")
+        );
+        assert_eq!(fs_operation("/.proxy/{*url}", &Method::GET, &headers), None);
+
+        headers.insert("X-Get-Meta", "true".parse().unwrap());
+        assert_eq!(
+            fs_operation("/.fs/{*path}", &Method::GET, &headers),
+            Some("meta")
+        );
+    }
+}
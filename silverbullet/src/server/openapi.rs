@@ -0,0 +1,321 @@
+//! OpenAPI description of the API, served at `GET /.openapi.json`
+//!
+//! Enable with [`Builder::openapi`](crate::server::Builder::openapi), which also serves a
+//! Swagger UI at `/.openapi`. The document only lists the routes the builder registers;
+//! get it without serving it with [`Builder::openapi_document`].
+
+use axum::{
+    extract::State,
+    response::{Html, IntoResponse},
+};
+use bytes::Bytes;
+use http::header;
+use utoipa::OpenApi;
+use utoipa::openapi::{self, server::Server};
+
+use super::{Builder, admin, metrics, routes};
+use crate::{client, fs, shell};
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "SilverBullet",
+        description = "API of a SilverBullet server, as used by the web client"
+    ),
+    paths(
+        routes::fs::list,
+        routes::fs::get,
+        routes::fs::put,
+        routes::fs::delete,
+        routes::proxy::proxy,
+        routes::ping,
+        routes::log::log,
+        routes::config,
+        routes::client_manifest,
+    ),
+    components(schemas(
+        fs::FileMeta,
+        client::Config,
+        client::SyncConfig,
+        client::Manifest,
+        client::ManifestIcon,
+        client::LogEntry,
+        super::ErrorBody,
+    )),
+    tags(
+        (name = "fs", description = "Files of the space"),
+        (name = "client", description = "Config and logs of the web client"),
+        (name = "proxy", description = "Requests to other servers on behalf of the client"),
+    )
+)]
+struct Core;
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(routes::shell::shell, routes::shell::stream),
+    components(schemas(shell::Request, shell::Response, shell::Event)),
+    tags((name = "shell", description = "Commands run on the server"))
+)]
+struct Shell;
+
+#[derive(OpenApi)]
+#[openapi(paths(metrics::export))]
+struct Metrics;
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(admin::spaces, admin::space, admin::flush, admin::gc),
+    components(schemas(
+        admin::Stats,
+        admin::Largest,
+        admin::Flushed,
+        crate::gc::Report,
+        crate::gc::Orphan,
+        crate::gc::Mode,
+    )),
+    tags((name = "admin", description = "Operational actions, for authenticated users with write access"))
+)]
+struct Admin;
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(routes::plugs::list, routes::plugs::bundle, routes::plugs::install),
+    components(schemas(routes::plugs::Plug, routes::plugs::Install)),
+    tags((name = "plugs", description = "Plugs of the space, and their installation"))
+)]
+struct Plugs;
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(routes::ingest::email),
+    components(schemas(routes::ingest::Email, routes::ingest::Attachment, routes::ingest::Ingested)),
+    tags((name = "ingest", description = "Pages created from emails"))
+)]
+struct Ingest;
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(routes::capture::append),
+    components(schemas(routes::capture::Snippet, routes::capture::Captured)),
+    tags((name = "capture", description = "Quick capture of snippets into a page"))
+)]
+struct Capture;
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(routes::template::today, routes::template::page),
+    components(schemas(crate::templates::Instance)),
+    tags((name = "templates", description = "Pages created from templates"))
+)]
+struct Templates;
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(routes::calendar::feed),
+    tags((name = "calendar", description = "iCalendar feed of the dates of the space"))
+)]
+struct Calendar;
+
+#[cfg(feature = "bundle")]
+#[derive(OpenApi)]
+#[openapi(
+    paths(routes::bundle::download),
+    tags((name = "bundles", description = "Downloads of pages with the pages they link"))
+)]
+struct Bundles;
+
+#[cfg(feature = "opendal")]
+#[derive(OpenApi)]
+#[openapi(
+    paths(routes::upload::presign),
+    components(schemas(routes::upload::NewUpload, routes::upload::Upload)),
+    tags((name = "uploads", description = "Uploads of large files straight to the storage"))
+)]
+struct Uploads;
+
+#[cfg(all(feature = "ssr", feature = "signed-urls"))]
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        routes::share::list,
+        routes::share::add,
+        routes::share::revoke,
+        routes::share::root,
+        routes::share::page,
+    ),
+    components(schemas(routes::share::Share, routes::share::NewShare, routes::share::Link)),
+    tags((name = "shares", description = "Public read-only links to pages and folders"))
+)]
+struct Shares;
+
+#[cfg(feature = "dedupe")]
+#[derive(OpenApi)]
+#[openapi(
+    paths(admin::duplicates, admin::deduplicate),
+    components(schemas(crate::dedupe::Report, crate::dedupe::Duplicates))
+)]
+struct AdminDedupe;
+
+#[cfg(feature = "backup")]
+#[derive(OpenApi)]
+#[openapi(
+    paths(admin::backups, admin::snapshot),
+    components(schemas(crate::backup::Snapshot, crate::backup::Kind))
+)]
+struct AdminBackup;
+
+#[cfg(feature = "backup")]
+#[derive(OpenApi)]
+#[openapi(
+    paths(routes::backup::trigger),
+    components(schemas(crate::backup::Snapshot, crate::backup::Kind))
+)]
+struct Backup;
+
+#[cfg(feature = "git-sync")]
+#[derive(OpenApi)]
+#[openapi(
+    paths(routes::sync::trigger),
+    components(schemas(crate::sync::git::Report)),
+    tags((name = "sync", description = "Sync of the space with a git remote"))
+)]
+struct GitSync;
+
+#[cfg(feature = "stats")]
+#[derive(OpenApi)]
+#[openapi(
+    paths(routes::stats::history),
+    components(schemas(crate::stats::Day)),
+    tags((name = "stats", description = "Daily totals of the space"))
+)]
+struct Stats;
+
+pub(super) fn document(builder: &Builder) -> openapi::OpenApi {
+    let mut document = Core::openapi();
+    document.info.version = env!("CARGO_PKG_VERSION").to_string();
+
+    if builder.shell {
+        document.merge(Shell::openapi());
+    }
+
+    if builder.metrics.is_some() {
+        document.merge(Metrics::openapi());
+    }
+
+    if builder.admin.is_some() {
+        document.merge(Admin::openapi());
+
+        #[cfg(feature = "backup")]
+        document.merge(AdminBackup::openapi());
+
+        #[cfg(feature = "dedupe")]
+        document.merge(AdminDedupe::openapi());
+    }
+
+    if builder.plugs.is_some() {
+        document.merge(Plugs::openapi());
+    }
+
+    if builder.ingest.is_some() {
+        document.merge(Ingest::openapi());
+    }
+
+    if builder.capture.is_some() {
+        document.merge(Capture::openapi());
+    }
+
+    if builder.templates.is_some() {
+        document.merge(Templates::openapi());
+    }
+
+    if builder.calendar.is_some() {
+        document.merge(Calendar::openapi());
+    }
+
+    #[cfg(feature = "bundle")]
+    if builder.bundles.is_some() {
+        document.merge(Bundles::openapi());
+    }
+
+    #[cfg(feature = "opendal")]
+    if builder.uploads.is_some() {
+        document.merge(Uploads::openapi());
+    }
+
+    #[cfg(all(feature = "ssr", feature = "signed-urls"))]
+    if builder.shares.is_some() {
+        document.merge(Shares::openapi());
+    }
+
+    #[cfg(feature = "backup")]
+    if builder.backup.is_some() {
+        document.merge(Backup::openapi());
+    }
+
+    #[cfg(feature = "git-sync")]
+    if builder.git_sync.is_some() {
+        document.merge(GitSync::openapi());
+    }
+
+    #[cfg(feature = "stats")]
+    if builder.stats.is_some() {
+        document.merge(Stats::openapi());
+    }
+
+    if let Some(base_path) = &builder.base_path {
+        document.servers = Some(vec![Server::new(base_path)]);
+    }
+
+    document
+}
+
+/// Route serving the document, serialized once when the router is built.
+pub(super) async fn json(State(document): State<Bytes>) -> impl IntoResponse {
+    ([(header::CONTENT_TYPE, "application/json")], document)
+}
+
+/// Swagger UI loaded from a CDN, reading the document next to it.
+pub(super) async fn ui() -> Html<&'static str> {
+    Html(
+        r##"<!doctype html>
+<html lang="en">
+<head>
+  <meta charset="utf-8">
+  <title>SilverBullet API</title>
+  <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css">
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js"></script>
+  <script>
+    SwaggerUIBundle({ url: ".openapi.json", dom_id: "#swagger-ui" });
+  </script>
+</body>
+</html>
+"##,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn documents_registered_routes() {
+        let document = document(&Builder::new().shell(false).base_path("/notes"));
+
+        assert!(document.paths.paths.contains_key("/.fs/{path}"));
+        assert!(document.paths.paths.contains_key("/.config"));
+        assert!(!document.paths.paths.contains_key("/.shell"));
+        assert!(!document.paths.paths.contains_key("/.admin/spaces"));
+        assert_eq!(document.servers.unwrap()[0].url, "/notes");
+
+        let document = self::document(&Builder::new().admin(admin::Admin::new()));
+        assert!(document.paths.paths.contains_key("/.shell/stream"));
+        assert!(document.paths.paths.contains_key("/.admin/spaces/{name}"));
+
+        let components = document.components.unwrap();
+        assert!(components.schemas.contains_key("FileMeta"));
+        assert!(components.schemas.contains_key("Stats"));
+    }
+}
@@ -0,0 +1,210 @@
+//! Rate limiting of write and command routes
+//!
+//! Enable with [`Builder::rate_limit`](crate::server::Builder::rate_limit). Writes to
+//! `/.fs`, `/.shell` and `/.proxy` requests take a token from a bucket per client;
+//! when it is empty the request is rejected with `429 Too Many Requests` and a
+//! `Retry-After` header. Reads are not limited, the client syncs them in bursts.
+
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash as _, Hasher as _};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use axum_client_ip::ClientIp;
+use http::{Method, header};
+
+use crate::server::error::Error;
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+
+#[cfg(target_arch = "wasm32")]
+use web_time::Instant;
+
+/// Number of tracked clients above which full buckets are dropped
+const MAX_TRACKED: usize = 10_000;
+
+/// What requests are grouped by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Key {
+    /// Client IP
+    #[default]
+    Ip,
+    /// `Authorization` header, or client IP for requests without one
+    Token,
+}
+
+/// Token bucket rate limiter
+///
+/// Clones share their state, so a limiter kept in the application state applies
+/// across requests.
+#[derive(Debug, Clone)]
+pub struct RateLimit {
+    buckets: Arc<Mutex<HashMap<String, Bucket>>>,
+    burst: f64,
+    per_second: f64,
+    key: Key,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl RateLimit {
+    /// Allow bursts of `burst` requests, refilled at `per_second` requests per second.
+    pub fn new(burst: u32, per_second: f64) -> Self {
+        Self {
+            buckets: Arc::default(),
+            burst: f64::from(burst.max(1)),
+            per_second: per_second.max(f64::MIN_POSITIVE),
+            key: Key::default(),
+        }
+    }
+
+    /// Group requests by client IP (the default) or auth token.
+    #[must_use]
+    pub fn key(mut self, key: Key) -> Self {
+        self.key = key;
+        self
+    }
+
+    /// Take a token for the client, or return how long until one is available.
+    fn acquire(&self, client: &str) -> Result<(), Duration> {
+        self.acquire_at(client, Instant::now())
+    }
+
+    fn acquire_at(&self, client: &str, now: Instant) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+
+        if buckets.len() >= MAX_TRACKED && !buckets.contains_key(client) {
+            buckets.retain(|_, bucket| self.refilled(bucket, now) < self.burst);
+        }
+
+        let bucket = buckets.entry(client.to_string()).or_insert(Bucket {
+            tokens: self.burst,
+            updated: now,
+        });
+
+        bucket.tokens = self.refilled(bucket, now);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - bucket.tokens) / self.per_second,
+            ))
+        }
+    }
+
+    fn refilled(&self, bucket: &Bucket, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+
+        (bucket.tokens + elapsed * self.per_second).min(self.burst)
+    }
+
+    fn client(&self, request: &Request, ip: &str) -> String {
+        let token = request.headers().get(header::AUTHORIZATION);
+
+        match (self.key, token) {
+            (Key::Token, Some(token)) => {
+                // Don't keep credentials around in memory
+                let mut hasher = DefaultHasher::new();
+                token.as_bytes().hash(&mut hasher);
+                format!("token:{:016x}", hasher.finish())
+            }
+            _ => format!("ip:{ip}"),
+        }
+    }
+}
+
+/// Whether a request counts against the limit.
+fn is_limited(method: &Method, path: &str) -> bool {
+    if path.starts_with("/.shell") || path.starts_with("/.proxy/") {
+        return true;
+    }
+
+    path.starts_with("/.fs/") && matches!(*method, Method::PUT | Method::DELETE | Method::POST)
+}
+
+/// Middleware rejecting clients over the limit.
+///
+/// Use with `axum::middleware::from_fn_with_state(limit, rate_limit::middleware)`.
+pub async fn middleware(
+    State(limit): State<RateLimit>,
+    ClientIp(ip): ClientIp,
+    request: Request,
+    next: Next,
+) -> Response {
+    if !is_limited(request.method(), request.uri().path()) {
+        return next.run(request).await;
+    }
+
+    let client = limit.client(&request, &ip.to_string());
+
+    match limit.acquire(&client) {
+        Ok(()) => next.run(request).await,
+        Err(retry_after) => {
+            #[cfg(feature = "tracing")]
+            tracing::debug!(client, "Rate limit exceeded");
+
+            (
+                [(
+                    header::RETRY_AFTER,
+                    retry_after.as_secs_f64().ceil().max(1.0).to_string(),
+                )],
+                Error::TooManyRequests("rate limit exceeded".into()),
+            )
+                .into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refills_over_time() {
+        let limit = RateLimit::new(2, 1.0);
+        let start = Instant::now();
+
+        assert!(limit.acquire_at("a", start).is_ok());
+        assert!(limit.acquire_at("a", start).is_ok());
+
+        let retry_after = limit.acquire_at("a", start).unwrap_err();
+        assert_eq!(retry_after, Duration::from_secs(1));
+
+        // Other clients have their own bucket
+        assert!(limit.acquire_at("b", start).is_ok());
+
+        assert!(
+            limit
+                .acquire_at("a", start + Duration::from_millis(1500))
+                .is_ok()
+        );
+        assert!(
+            limit
+                .acquire_at("a", start + Duration::from_millis(1500))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn limits_writes_and_commands() {
+        assert!(is_limited(&Method::PUT, "/.fs/a.md"));
+        assert!(is_limited(&Method::DELETE, "/.fs/a.md"));
+        assert!(!is_limited(&Method::GET, "/.fs/a.md"));
+        assert!(is_limited(&Method::POST, "/.shell"));
+        assert!(is_limited(&Method::GET, "/.proxy/example.com"));
+        assert!(!is_limited(&Method::GET, "/.config"));
+    }
+}
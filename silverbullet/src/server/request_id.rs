@@ -0,0 +1,104 @@
+//! Request IDs for correlating logs with client reports
+//!
+//! Enable with [`Builder::request_id`](crate::server::Builder::request_id). Each request
+//! gets an ID, taken from its `x-request-id` header when set by a reverse proxy and
+//! generated otherwise, which is returned in the response's `x-request-id` header. With
+//! the `tracing` feature requests run in a `request` span carrying the ID, so errors
+//! logged by [`Error`](crate::server::Error) include it.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use axum::{extract::Request, middleware::Next, response::Response};
+use http::{HeaderName, HeaderValue};
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[cfg(target_arch = "wasm32")]
+use web_time::{SystemTime, UNIX_EPOCH};
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Longest incoming request ID that is reused
+const MAX_LEN: usize = 128;
+
+/// ID of the current request, added to the request extensions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(pub String);
+
+/// Middleware assigning a request ID.
+///
+/// Use with `axum::middleware::from_fn(request_id::middleware)`.
+pub async fn middleware(mut request: Request, next: Next) -> Response {
+    let id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|id| is_valid(id))
+        .map_or_else(generate, str::to_string);
+
+    request.extensions_mut().insert(RequestId(id.clone()));
+
+    #[cfg(feature = "tracing")]
+    let mut response = {
+        use tracing::Instrument as _;
+
+        let span = tracing::info_span!(
+            "request",
+            request_id = %id,
+            method = %request.method(),
+            path = %request.uri().path(),
+        );
+
+        next.run(request).instrument(span).await
+    };
+
+    #[cfg(not(feature = "tracing"))]
+    let mut response = next.run(request).await;
+
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+
+    response
+}
+
+fn is_valid(id: &str) -> bool {
+    !id.is_empty() && id.len() <= MAX_LEN && id.bytes().all(|b| b.is_ascii_graphic())
+}
+
+/// Generate an ID from the current time and a per-process counter.
+fn generate() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_nanos() as u64);
+
+    let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+
+    format!("{nanos:016x}{:08x}", count as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generates_unique_ids() {
+        let a = generate();
+        let b = generate();
+
+        assert_ne!(a, b);
+        assert_eq!(a.len(), 24);
+        assert!(is_valid(&a));
+    }
+
+    #[test]
+    fn validates_incoming_ids() {
+        assert!(is_valid("req-123"));
+        assert!(!is_valid(""));
+        assert!(!is_valid("has space"));
+        assert!(!is_valid(&"a".repeat(MAX_LEN + 1)));
+    }
+}
@@ -1,50 +1,86 @@
+#[cfg(feature = "client-assets")]
+pub mod assets;
+#[cfg(feature = "backup")]
+pub mod backup;
+#[cfg(all(feature = "ssr", feature = "client-assets"))]
+pub mod boot;
+#[cfg(feature = "bundle")]
+pub mod bundle;
+pub mod calendar;
+pub mod capture;
 pub mod fs;
+pub mod ingest;
 pub mod log;
+pub mod plugs;
 pub mod proxy;
+#[cfg(all(feature = "ssr", feature = "signed-urls"))]
+pub mod share;
 pub mod shell;
+#[cfg(feature = "stats")]
+pub mod stats;
+#[cfg(feature = "git-sync")]
+pub mod sync;
+pub mod template;
+#[cfg(feature = "opendal")]
+pub mod upload;
 
-use axum::{extract::State, response::IntoResponse};
+use axum::{Extension, extract::State, response::IntoResponse};
+use http::{HeaderMap, Uri};
 
 use crate::client;
 
+/// Serve the client config, adjusted to the authenticated user.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    get,
+    path = "/.config",
+    tag = "client",
+    responses((status = 200, description = "Client config", body = client::Config)),
+))]
 #[cfg_attr(feature = "debug", axum::debug_handler)]
-pub async fn config(State(config): State<client::Config>) -> impl IntoResponse {
+pub async fn config(
+    State(config): State<client::Config>,
+    user: Option<Extension<client::User>>,
+) -> impl IntoResponse {
+    let config = config.for_user(user.as_ref().map(|Extension(user)| user));
+
     ([("Cache-Control", "no-cache")], axum::Json(config))
 }
 
+#[cfg_attr(feature = "openapi", utoipa::path(
+    get,
+    path = "/.client/manifest.json",
+    tag = "client",
+    responses((status = 200, description = "PWA manifest", body = client::Manifest)),
+))]
 #[cfg_attr(feature = "debug", axum::debug_handler)]
-pub async fn client_manifest() -> impl IntoResponse {
-    let host_prefix_url: Option<String> = None;
-
-    let client_manifest = client::Manifest {
-        short_name: "space name".to_string(),
-        name: "space name".to_string(),
-        icons: vec![client::ManifestIcon {
-            src: host_prefix_url
-                .clone()
-                .map_or("/.client/logo-dock.png".to_string(), |u| {
-                    format!("{}/.client/logo-dock.png", u)
-                }),
-            sizes: "512x512".to_string(),
-            type_: "image/png".to_string(),
-        }],
-        capture_links: "new-client".to_string(),
-        start_url: host_prefix_url
-            .clone()
-            .map_or("/#boot".to_string(), |u| format!("{}/#boot", u)),
-        display: "standalone".to_string(),
-        display_override: vec!["window-controls-overlay".to_string()],
-        scope: host_prefix_url
-            .clone()
-            .map_or("/".to_string(), |u| format!("{}/", u)),
-        theme_color: "#e1e1e1".to_string(),
-        description: "description".to_string(),
-    };
-
-    axum::Json(client_manifest)
+pub async fn client_manifest(State(config): State<client::ManifestConfig>) -> impl IntoResponse {
+    axum::Json(config.manifest())
 }
 
+#[cfg_attr(feature = "openapi", utoipa::path(
+    get,
+    path = "/.ping",
+    tag = "client",
+    responses((status = 200, description = "Server is up", body = String, example = "OK")),
+))]
 #[cfg_attr(feature = "debug", axum::debug_handler)]
 pub async fn ping() -> impl IntoResponse {
     ([("Cache-Control", "no-cache"), ("X-Space-Path", "")], "OK")
 }
+
+/// Whether a request sends `token`, as the `header` or the `token` query parameter.
+pub(crate) fn sends_token(headers: &HeaderMap, uri: &Uri, header: &str, token: &str) -> bool {
+    let sent = headers
+        .get(header)
+        .and_then(|value| value.to_str().ok())
+        .or_else(|| {
+            uri.query()
+                .unwrap_or_default()
+                .split('&')
+                .find_map(|pair| pair.strip_prefix("token="))
+        });
+
+    sent.is_some_and(|sent| {
+        crate::server::auth::constant_time_eq(sent.as_bytes(), token.as_bytes())
+    })
+}
@@ -0,0 +1,115 @@
+//! Web client served from the binary
+//!
+//! With the `client-assets` feature the client bundle in `silverbullet/client` is embedded
+//! at build time and mounted by [`Builder::build`](crate::server::Builder::build) as the
+//! router fallback. Fill it with the pinned build from `dagger call client export --path
+//! silverbullet/client`; without it the binary builds, but serves no client.
+//!
+//! Files are served by their path in the bundle, e.g. `/.client/client.js`. Other paths
+//! not starting with `.` are page names and get `index.html`, which boots the client.
+
+use std::borrow::Cow;
+
+use axum::{
+    body::Body,
+    response::{IntoResponse, Response},
+};
+use http::{HeaderValue, Method, Uri, header};
+use rust_embed::Embed;
+
+use crate::server::error::Error;
+
+/// Pinned build of the SilverBullet web client
+#[derive(Embed)]
+#[folder = "$CARGO_MANIFEST_DIR/client"]
+#[allow_missing = true]
+pub struct Assets;
+
+const INDEX: &str = "index.html";
+
+/// Serve a file of the bundle, or `index.html` for page paths.
+pub async fn asset<E>(method: Method, uri: Uri) -> Result<Response, Error>
+where
+    E: Embed,
+{
+    if method != Method::GET && method != Method::HEAD {
+        return Err(Error::not_found(format!("no route for {method} {uri}")));
+    }
+
+    let path = uri.path().trim_start_matches('/');
+
+    let file = match E::get(path) {
+        Some(file) if !path.is_empty() => file,
+        _ if path.starts_with('.') => {
+            return Err(Error::not_found(format!("no client asset at /{path}")));
+        }
+        _ => E::get(INDEX).ok_or_else(|| Error::not_found("the web client is not bundled"))?,
+    };
+
+    let content_type = HeaderValue::from_str(file.metadata.mimetype())?;
+
+    // The bundle changes with the binary, so revalidate instead of caching stale clients
+    let cache_control = HeaderValue::from_static("no-cache");
+
+    let body = match method {
+        Method::HEAD => Body::empty(),
+        _ => match file.data {
+            Cow::Borrowed(slice) => Body::from(slice),
+            Cow::Owned(vec) => Body::from(vec),
+        },
+    };
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type),
+            (header::CACHE_CONTROL, cache_control),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::BodyExt;
+
+    #[derive(Embed)]
+    #[folder = "src/server/routes/testdata/assets"]
+    struct TestAssets;
+
+    async fn get(path: &str) -> Result<(String, String), Error> {
+        let response = asset::<TestAssets>(Method::GET, Uri::try_from(path).unwrap()).await?;
+
+        let content_type = response.headers()[header::CONTENT_TYPE]
+            .to_str()
+            .unwrap()
+            .to_string();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+
+        Ok((content_type, String::from_utf8(body.to_vec()).unwrap()))
+    }
+
+    #[tokio::test]
+    async fn serves_assets_and_index_for_pages() {
+        let (content_type, body) = get("/.client/client.js").await.unwrap();
+        assert_eq!(content_type, "text/javascript");
+        assert!(body.contains("boot"));
+
+        for page in ["/", "/index", "/Journal/2026-01-01"] {
+            let (content_type, body) = get(page).await.unwrap();
+            assert_eq!(content_type, "text/html");
+            assert!(body.contains("<html"), "{page}");
+        }
+
+        assert!(matches!(
+            get("/.client/missing.js").await,
+            Err(Error::NotFound(_))
+        ));
+
+        let response = asset::<TestAssets>(Method::PUT, Uri::from_static("/index"))
+            .await
+            .unwrap_err();
+        assert!(matches!(response, Error::NotFound(_)));
+    }
+}
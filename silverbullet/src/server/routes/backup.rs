@@ -0,0 +1,31 @@
+use axum::{Extension, Json, extract::State};
+
+use crate::backup::{Backup, Snapshot};
+use crate::client;
+use crate::server::error::Error;
+
+/// Take a snapshot now, see [`Builder::backup`](crate::server::Builder::backup).
+#[cfg_attr(feature = "openapi", utoipa::path(
+    post,
+    path = "/.backup/trigger",
+    tag = "backup",
+    responses(
+        (status = 200, description = "Snapshot taken", body = Snapshot),
+        (status = 403, description = "Read-only user"),
+    ),
+))]
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(name = "backup_trigger", skip_all)
+)]
+#[cfg_attr(feature = "cloudflare", worker::send)]
+pub async fn trigger(
+    State(backup): State<Backup>,
+    user: Option<Extension<client::User>>,
+) -> Result<Json<Snapshot>, Error> {
+    if user.is_some_and(|Extension(user)| user.read_only) {
+        return Err(Error::forbidden("read-only users can't trigger backups"));
+    }
+
+    Ok(Json(backup.snapshot().await?))
+}
@@ -0,0 +1,103 @@
+//! Boot page at `/`, the client shell with its startup data inlined
+//!
+//! Enable with [`Builder::boot`](crate::server::Builder::boot). The `index.html` of the
+//! embedded client is served with the `/.config` JSON and the index page rendered into it
+//! by the `boot.html` template (see [`ssr`](crate::ssr)), saving the client those round
+//! trips on cold starts. Without a bundled client the index page is rendered on its own by
+//! the `page.html` template, with the pages it includes transcluded.
+
+use std::sync::Arc;
+
+use axum::{
+    Extension,
+    body::Body,
+    extract::State,
+    response::{Html, IntoResponse, Response},
+};
+use futures::TryStreamExt;
+use http::header;
+
+use super::assets::Assets;
+use super::fs::Filesystem;
+use crate::client;
+use crate::fs::ReadOnlyFilesystem;
+use crate::server::error::Error;
+use crate::ssr::{Boot, Page, Renderer, View, transclusion};
+
+/// Renderer used for the boot page
+pub type BootRenderer = Arc<dyn Renderer>;
+
+#[cfg_attr(feature = "cloudflare", worker::send)]
+pub async fn boot<F>(
+    Filesystem(fs): Filesystem<F>,
+    State(config): State<client::Config>,
+    Extension(renderer): Extension<BootRenderer>,
+    user: Option<Extension<client::User>>,
+) -> Result<Response, Error>
+where
+    F: ReadOnlyFilesystem,
+{
+    let config = config.for_user(user.as_ref().map(|Extension(user)| user));
+
+    let content = read(&fs, &format!("{}.md", config.index_page)).await?;
+    let page = content.as_deref().map(|content| Page {
+        name: &config.index_page,
+        content,
+    });
+
+    let Some(shell) = <Assets as rust_embed::Embed>::get("index.html") else {
+        let content = match page {
+            Some(page) => Some(transclusion::transclude(&fs, page.name, page.content).await?),
+            None => None,
+        };
+        let body = View {
+            config: &config,
+            page: content.as_deref().map(|content| Page {
+                name: &config.index_page,
+                content,
+            }),
+        }
+        .render_stream(renderer.as_ref())
+        .await?;
+
+        return Ok((
+            [
+                (header::CONTENT_TYPE, "text/html; charset=utf-8"),
+                (header::CACHE_CONTROL, "no-cache"),
+            ],
+            Body::from_stream(body),
+        )
+            .into_response());
+    };
+
+    let html = Boot {
+        config: &config,
+        index_page: page,
+    }
+    .render(renderer.as_ref(), &String::from_utf8_lossy(&shell.data))
+    .await?;
+
+    Ok(([(header::CACHE_CONTROL, "no-cache")], Html(html)).into_response())
+}
+
+/// Text of a page, `None` if it doesn't exist or isn't UTF-8.
+async fn read<F>(fs: &F, path: &str) -> Result<Option<String>, Error>
+where
+    F: ReadOnlyFilesystem,
+{
+    let stream = match fs.get(path).await.map_err(Error::from) {
+        Ok((stream, _)) => stream,
+        Err(Error::NotFound(_)) => return Ok(None),
+        Err(err) => return Err(err),
+    };
+
+    let bytes = stream
+        .try_fold(Vec::new(), |mut acc, chunk| async move {
+            acc.extend_from_slice(&chunk);
+            Ok(acc)
+        })
+        .await
+        .map_err(Error::internal)?;
+
+    Ok(String::from_utf8(bytes).ok())
+}
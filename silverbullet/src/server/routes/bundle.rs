@@ -0,0 +1,255 @@
+//! Downloads of a page and the pages it links, at `GET /.bundle/{page}`
+//!
+//! Enable with [`Builder::bundles`](crate::server::Builder::bundles). The page is
+//! [collected](crate::bundle::Bundle::collect) with the pages up to `?depth=` links away,
+//! 1 by default and [`Bundles::max_depth`] at most, and downloaded as a zip archive, or
+//! with `?format=html` as a single HTML document.
+
+use std::sync::Arc;
+
+use axum::{
+    Extension, Router,
+    extract::{FromRef, Path, State},
+    response::{IntoResponse, Response},
+    routing,
+};
+use http::{Uri, header};
+
+use super::fs::{Filesystem, Provider};
+use crate::bundle::{self, Bundle};
+use crate::client;
+use crate::fs::ReadOnlyFilesystem;
+use crate::server::error::Error;
+use crate::ssr::Renderer;
+
+/// Settings of the bundle downloads
+#[derive(Clone)]
+pub struct Bundles {
+    renderer: Arc<dyn Renderer>,
+    max_depth: usize,
+}
+
+impl Bundles {
+    /// Downloads rendering their HTML documents with `renderer`.
+    pub fn new(renderer: impl Renderer + 'static) -> Self {
+        Self {
+            renderer: Arc::new(renderer),
+            max_depth: 3,
+        }
+    }
+
+    /// Links followed at most, whatever the request asks for (3 by default).
+    #[must_use]
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = depth;
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Zip,
+    Html,
+}
+
+pub(crate) fn router<S>(bundles: Bundles) -> Router<S>
+where
+    S: Provider + Clone + Send + Sync + 'static,
+    client::Config: FromRef<S>,
+{
+    Router::new()
+        .route("/{*page}", routing::get(download))
+        .layer(Extension(Arc::new(bundles)))
+}
+
+/// Download a page and the pages it links.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    get,
+    path = "/.bundle/{page}",
+    tag = "bundles",
+    params(
+        ("page" = String, Path, description = "Name of the page, e.g. `Projects/Launch`"),
+        ("depth" = Option<usize>, Query, description = "Links followed from the page, 1 by default"),
+        ("format" = Option<String>, Query, description = "`zip`, the default, or `html`"),
+    ),
+    responses(
+        (status = 200, description = "Zip archive of the pages and their files", content_type = "application/zip"),
+        (status = 200, description = "HTML document of the pages, with `?format=html`", content_type = "text/html"),
+        (status = 400, description = "Invalid depth or format"),
+        (status = 404, description = "No such page"),
+    ),
+))]
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(name = "bundle_download", skip_all)
+)]
+#[cfg_attr(feature = "cloudflare", worker::send)]
+pub async fn download<F>(
+    Extension(bundles): Extension<Arc<Bundles>>,
+    State(config): State<client::Config>,
+    user: Option<Extension<client::User>>,
+    Filesystem(fs): Filesystem<F>,
+    Path(page): Path<String>,
+    uri: Uri,
+) -> Result<Response, Error>
+where
+    F: ReadOnlyFilesystem,
+{
+    let (depth, format) = query(uri.query().unwrap_or_default())?;
+    let page = page.strip_suffix(".md").unwrap_or(&page);
+
+    let bundle = Bundle::collect(&fs, page, depth.min(bundles.max_depth))
+        .await
+        .map_err(error)?;
+
+    #[cfg(feature = "tracing")]
+    tracing::info!(
+        page,
+        pages = bundle.pages.len(),
+        files = bundle.files.len(),
+        "Bundled"
+    );
+
+    let name = filename(page);
+    let (body, content_type, extension) = match format {
+        Format::Zip => (
+            bundle.zip(&fs).await.map_err(error)?,
+            "application/zip",
+            "zip",
+        ),
+        Format::Html => {
+            let config = config.for_user(user.as_ref().map(|Extension(user)| user));
+            let html = bundle
+                .html(&fs, bundles.renderer.as_ref(), &config)
+                .await
+                .map_err(error)?;
+
+            (html.into_bytes(), "text/html; charset=utf-8", "html")
+        }
+    };
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{name}.{extension}\""),
+            ),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+/// `depth` and `format` of a query.
+fn query(query: &str) -> Result<(usize, Format), Error> {
+    let mut depth = 1;
+    let mut format = Format::Zip;
+
+    for pair in query.split('&') {
+        match pair.split_once('=') {
+            Some(("depth", value)) => {
+                depth = value
+                    .parse()
+                    .map_err(|_| Error::BadRequest(format!("Invalid depth {value:?}").into()))?;
+            }
+            Some(("format", "zip")) => format = Format::Zip,
+            Some(("format", "html")) => format = Format::Html,
+            Some(("format", value)) => {
+                return Err(Error::BadRequest(
+                    format!("Unsupported format {value:?}, expected zip or html").into(),
+                ));
+            }
+            _ => {}
+        }
+    }
+
+    Ok((depth, format))
+}
+
+fn error(err: bundle::Error) -> Error {
+    match err {
+        bundle::Error::NotFound(_) => Error::not_found(err),
+        bundle::Error::Fs(err) => err.into(),
+        bundle::Error::Zip(_) | bundle::Error::Render(_) => Error::internal(err),
+    }
+}
+
+/// Name of the download, the last part of the page name in safe characters.
+fn filename(page: &str) -> String {
+    page.rsplit('/')
+        .next()
+        .unwrap_or(page)
+        .chars()
+        .map(
+            |c| match c.is_ascii_alphanumeric() || matches!(c, ' ' | '-' | '_' | '.') {
+                true => c,
+                false => '_',
+            },
+        )
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::testing::MemoryFs;
+    use crate::server::Builder;
+    use crate::server::test::TestServer;
+    use crate::ssr::Jinja;
+    use http::StatusCode;
+
+    fn server() -> TestServer {
+        let space = MemoryFs::new()
+            .with_file("Projects/Launch.md", b"# Launch\n[[Plan]] ![[chart.png]]")
+            .with_file("Plan.md", b"[[Budget]]")
+            .with_file("Budget.md", b"# Budget")
+            .with_file("chart.png", b"png");
+
+        TestServer::build(
+            Builder::new().bundles(Bundles::new(Jinja::new()).max_depth(1)),
+            client::Config::default(),
+            space,
+        )
+    }
+
+    #[tokio::test]
+    async fn downloads_bundles() {
+        let server = server();
+
+        let response = server.get("/.bundle/Projects/Launch?depth=5").await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.header("Content-Type"), Some("application/zip"));
+        assert_eq!(
+            response.header("Content-Disposition"),
+            Some("attachment; filename=\"Launch.zip\"")
+        );
+
+        // Cut at the max depth
+        let zip = zip::ZipArchive::new(std::io::Cursor::new(response.body.to_vec())).unwrap();
+        let mut names: Vec<_> = zip.file_names().collect();
+        names.sort_unstable();
+        assert_eq!(names, ["Plan.md", "Projects/Launch.md", "chart.png"]);
+
+        let response = server.get("/.bundle/Projects/Launch.md?format=html").await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert!(response.text().contains(r#"<article id="page-plan">"#));
+        assert_eq!(
+            response.header("Content-Disposition"),
+            Some("attachment; filename=\"Launch.html\"")
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_requests() {
+        let server = server();
+
+        for (uri, status) in [
+            ("/.bundle/Missing", StatusCode::NOT_FOUND),
+            ("/.bundle/Plan?format=pdf", StatusCode::BAD_REQUEST),
+            ("/.bundle/Plan?depth=many", StatusCode::BAD_REQUEST),
+        ] {
+            assert_eq!(server.get(uri).await.status, status, "{uri}");
+        }
+    }
+}
@@ -0,0 +1,466 @@
+//! iCalendar feed of the dates of the space, at `GET /.calendar.ics`
+//!
+//! Enable with [`Builder::calendar`](crate::server::Builder::calendar), then subscribe
+//! to the URL in a calendar app. Events are all-day, for:
+//!
+//! - open tasks with a due date, as `- [ ] File taxes 📅 2024-06-01`,
+//!   `[due: 2024-06-01]` or `[deadline: "2024-06-01"]`
+//! - pages with one of the [`Calendar::keys`] in their frontmatter, `date`, `due` and
+//!   `deadline` by default, e.g. `date: 2024-06-01`
+//!
+//! Pages are read on the first request and again only when they change. The feed is
+//! behind the server auth: apps that can't send credentials can subscribe to a signed
+//! URL of it.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use axum::{
+    Extension, Router,
+    response::{IntoResponse, Response},
+    routing,
+};
+use futures::TryStreamExt as _;
+use http::{HeaderMap, header};
+
+use super::fs::{Filesystem, Provider};
+use crate::fs::{self, FileMeta, ReadOnlyFilesystem, time};
+use crate::server::error::Error;
+
+/// Calendar of the space
+#[derive(Debug)]
+pub struct Calendar {
+    name: String,
+    keys: Vec<String>,
+    /// Events of the pages, by name, with the version they were read at
+    cache: Mutex<HashMap<String, (String, Vec<Event>)>>,
+}
+
+impl Default for Calendar {
+    fn default() -> Self {
+        Self {
+            name: "SilverBullet".to_string(),
+            keys: ["date", "due", "deadline"].map(str::to_string).to_vec(),
+            cache: Mutex::default(),
+        }
+    }
+}
+
+impl Clone for Calendar {
+    fn clone(&self) -> Self {
+        Self {
+            name: self.name.clone(),
+            keys: self.keys.clone(),
+            cache: Mutex::default(),
+        }
+    }
+}
+
+impl Calendar {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Name of the calendar in apps, `SilverBullet` by default.
+    #[must_use]
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    /// Frontmatter keys of the dates of pages.
+    #[must_use]
+    pub fn keys<I>(mut self, keys: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.keys = keys.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Events of a page, read from the space unless cached for its version.
+    async fn events<F>(&self, fs: &F, meta: &FileMeta) -> Result<Vec<Event>, Error>
+    where
+        F: ReadOnlyFilesystem,
+    {
+        let version = format!(
+            "{}-{}-{}",
+            meta.last_modified,
+            meta.size,
+            meta.etag.as_deref().unwrap_or_default()
+        );
+
+        if let Some((cached, events)) = self.cache.lock().unwrap().get(&meta.name)
+            && *cached == version
+        {
+            return Ok(events.clone());
+        }
+
+        let (stream, _) = fs.get(&meta.name).await?;
+        let bytes = stream
+            .try_fold(Vec::new(), |mut acc, chunk| async move {
+                acc.extend_from_slice(&chunk);
+                Ok(acc)
+            })
+            .await
+            .map_err(fs::Error::from)?;
+
+        let page = meta.name.trim_end_matches(".md");
+        let events = parse(page, &String::from_utf8_lossy(&bytes), &self.keys);
+
+        self.cache
+            .lock()
+            .unwrap()
+            .insert(meta.name.clone(), (version, events.clone()));
+
+        Ok(events)
+    }
+}
+
+/// All-day event of a page
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Event {
+    page: String,
+    summary: String,
+    /// Days since the epoch
+    day: i64,
+}
+
+/// Events of the tasks and frontmatter of a page.
+fn parse(page: &str, text: &str, keys: &[String]) -> Vec<Event> {
+    let mut events = Vec::new();
+    let mut lines = text.lines();
+
+    if text.starts_with("---\n") || text.starts_with("---\r\n") {
+        lines.next();
+
+        for line in lines.by_ref() {
+            if line.trim_end() == "---" {
+                break;
+            }
+
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            if line.starts_with(char::is_whitespace) || !keys.iter().any(|k| k == key.trim()) {
+                continue;
+            }
+
+            if let Some(day) = date(value.trim().trim_matches(['"', '\''])) {
+                events.push(Event {
+                    page: page.to_string(),
+                    summary: page.rsplit('/').next().unwrap_or(page).to_string(),
+                    day,
+                });
+            }
+        }
+    }
+
+    for line in lines {
+        let Some(task) = line
+            .trim_start()
+            .get(2..)
+            .and_then(|rest| rest.strip_prefix("[ ] "))
+        else {
+            continue;
+        };
+        if !line.trim_start().starts_with(['-', '*', '+']) {
+            continue;
+        }
+
+        if let Some((summary, day)) = due(task) {
+            events.push(Event {
+                page: page.to_string(),
+                summary,
+                day,
+            });
+        }
+    }
+
+    events
+}
+
+/// Text and due date of a task, `None` without one.
+fn due(task: &str) -> Option<(String, i64)> {
+    if let Some(at) = task.find('📅') {
+        let rest = task[at + '📅'.len_utf8()..].trim_start();
+        let day = date(rest.get(..10)?)?;
+        let summary = format!("{}{}", &task[..at], &rest[10..]);
+
+        return Some((clean(&summary), day));
+    }
+
+    for attribute in ["[due:", "[deadline:"] {
+        let Some(start) = task.find(attribute) else {
+            continue;
+        };
+        let end = start + task[start..].find(']')?;
+        let value = task[start + attribute.len()..end]
+            .trim()
+            .trim_matches(['"', '\'']);
+        let day = date(value.get(..10).unwrap_or(value))?;
+        let summary = format!("{}{}", &task[..start], &task[end + 1..]);
+
+        return Some((clean(&summary), day));
+    }
+
+    None
+}
+
+fn clean(summary: &str) -> String {
+    summary.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Days since the epoch of a `YYYY-MM-DD` date, or of the date of a date and time.
+fn date(value: &str) -> Option<i64> {
+    time::parse_date(value.get(..10)?)
+}
+
+pub(crate) fn router<S>(calendar: Calendar) -> Router<S>
+where
+    S: Provider + Clone + Send + Sync + 'static,
+{
+    Router::new()
+        .route("/.calendar.ics", routing::get(feed))
+        .layer(Extension(Arc::new(calendar)))
+}
+
+/// Serve the calendar of the space.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    get,
+    path = "/.calendar.ics",
+    tag = "calendar",
+    responses((status = 200, description = "Calendar of the due tasks and dated pages", content_type = "text/calendar")),
+))]
+#[cfg_attr(feature = "cloudflare", worker::send)]
+pub async fn feed<F>(
+    Extension(calendar): Extension<Arc<Calendar>>,
+    Filesystem(fs): Filesystem<F>,
+    headers: HeaderMap,
+) -> Result<Response, Error>
+where
+    F: ReadOnlyFilesystem,
+{
+    let pages: Vec<FileMeta> = fs
+        .list()
+        .await?
+        .into_iter()
+        .filter(|meta| meta.name.ends_with(".md"))
+        .collect();
+
+    let mut events = Vec::new();
+    for meta in &pages {
+        events.extend(calendar.events(&fs, meta).await?);
+    }
+
+    // Forget deleted pages
+    calendar
+        .cache
+        .lock()
+        .unwrap()
+        .retain(|name, _| pages.iter().any(|meta| meta.name == *name));
+
+    events.sort_by(|a, b| (a.day, &a.page).cmp(&(b.day, &b.page)));
+
+    let origin = origin(&headers);
+    let body = ics(&calendar.name, &events, origin.as_deref(), time::now());
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/calendar; charset=utf-8"),
+            (header::CACHE_CONTROL, "no-cache"),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+/// Origin of the request, for the URLs of the pages.
+fn origin(headers: &HeaderMap) -> Option<String> {
+    let host = headers.get(header::HOST)?.to_str().ok()?;
+    let scheme = headers
+        .get("x-forwarded-proto")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("http");
+
+    Some(format!("{scheme}://{host}"))
+}
+
+fn ics(name: &str, events: &[Event], origin: Option<&str>, now: u64) -> String {
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//silverbullet-rs//calendar//EN".to_string(),
+        "CALSCALE:GREGORIAN".to_string(),
+        format!("X-WR-CALNAME:{}", escape(name)),
+    ];
+
+    let stamp = {
+        let secs = time::as_secs(now) as i64;
+        let (year, month, day) = time::civil_from_days(secs.div_euclid(86400));
+        let secs = secs.rem_euclid(86400);
+        format!(
+            "{year:04}{month:02}{day:02}T{:02}{:02}{:02}Z",
+            secs / 3600,
+            secs / 60 % 60,
+            secs % 60
+        )
+    };
+
+    for event in events {
+        lines.push("BEGIN:VEVENT".to_string());
+        lines.push(format!("UID:{:016x}@silverbullet", uid(event)));
+        lines.push(format!("DTSTAMP:{stamp}"));
+        lines.push(format!("DTSTART;VALUE=DATE:{}", ics_date(event.day)));
+        lines.push(format!("DTEND;VALUE=DATE:{}", ics_date(event.day + 1)));
+        lines.push(format!("SUMMARY:{}", escape(&event.summary)));
+        lines.push(format!("DESCRIPTION:{}", escape(&event.page)));
+        if let Some(origin) = origin {
+            lines.push(format!("URL:{origin}/{}", event.page.replace(' ', "%20")));
+        }
+        lines.push("END:VEVENT".to_string());
+    }
+
+    lines.push("END:VCALENDAR".to_string());
+
+    lines.iter().map(|line| fold(line) + "\r\n").collect()
+}
+
+fn ics_date(day: i64) -> String {
+    let (year, month, day) = time::civil_from_days(day);
+    format!("{year:04}{month:02}{day:02}")
+}
+
+/// Stable id of an event, so apps update rather than duplicate it.
+fn uid(event: &Event) -> u64 {
+    // FNV-1a, as the std hashers may change between releases
+    format!("{}\n{}\n{}", event.page, event.summary, event.day)
+        .bytes()
+        .fold(0xcbf29ce484222325, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+        })
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// Line folded at 75 bytes, without splitting characters.
+fn fold(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len());
+    let mut width = 0;
+
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            width = 1;
+        }
+        folded.push(c);
+        width += c.len_utf8();
+    }
+
+    folded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client;
+    use crate::fs::testing::MemoryFs;
+    use crate::server::Builder;
+    use crate::server::test::TestServer;
+    use http::StatusCode;
+
+    fn keys() -> Vec<String> {
+        Calendar::new().keys
+    }
+
+    #[test]
+    fn finds_events() {
+        let text = "---\ndate: \"2024-06-01\"\n  due: 2024-01-01\ntags: trip\n---\n# Trip\n\
+            - [ ] Book  hotel 📅 2024-05-20 #travel\n\
+            - [x] Pack 📅 2024-05-21\n\
+            * [ ] Renew passport [due: \"2024-05-01\"]\n\
+            - [ ] Someday [due: soon]\n\
+            [ ] Not a task 📅 2024-05-22\n";
+
+        let day = |year, month, day| time::days_from_civil(year, month, day);
+        let event = |summary: &str, day| Event {
+            page: "Travel/Trip".to_string(),
+            summary: summary.to_string(),
+            day,
+        };
+
+        assert_eq!(
+            parse("Travel/Trip", text, &keys()),
+            [
+                event("Trip", day(2024, 6, 1)),
+                event("Book hotel #travel", day(2024, 5, 20)),
+                event("Renew passport", day(2024, 5, 1)),
+            ]
+        );
+
+        assert_eq!(date("2024-02-30"), None);
+        assert_eq!(date("2024-06-01T10:00"), Some(day(2024, 6, 1)));
+    }
+
+    #[test]
+    fn writes_ics() {
+        let events = [Event {
+            page: "Trip".to_string(),
+            summary: "Pay; book, pack".to_string(),
+            day: time::days_from_civil(2024, 6, 1),
+        }];
+        let now = time::from_secs(time::days_from_civil(2024, 5, 1) as u64 * 86400 + 3661);
+
+        let ics = ics("Notes", &events, Some("https://notes.example.com"), now);
+        assert!(ics.starts_with("BEGIN:VCALENDAR\r\nVERSION:2.0\r\n"));
+        assert!(ics.contains("\r\nDTSTAMP:20240501T010101Z\r\n"));
+        assert!(ics.contains("\r\nDTSTART;VALUE=DATE:20240601\r\nDTEND;VALUE=DATE:20240602\r\n"));
+        assert!(ics.contains("\r\nSUMMARY:Pay\\; book\\, pack\r\n"));
+        assert!(ics.contains("\r\nURL:https://notes.example.com/Trip\r\n"));
+        assert!(ics.ends_with("END:VEVENT\r\nEND:VCALENDAR\r\n"));
+
+        let folded = fold(&format!("SUMMARY:{}", "é".repeat(50)));
+        assert!(folded.split("\r\n").all(|line| line.len() <= 75));
+        assert_eq!(
+            folded.replace("\r\n ", ""),
+            format!("SUMMARY:{}", "é".repeat(50))
+        );
+    }
+
+    #[tokio::test]
+    async fn serves_the_feed() {
+        let fs = MemoryFs::new()
+            .with_file("Trip.md", b"- [ ] Book hotel \xF0\x9F\x93\x85 2024-05-20\n")
+            .with_file("Plain.md", b"Nothing")
+            .with_file("notes.txt", b"- [ ] Ignored [due: 2024-05-20]");
+        let server = TestServer::build(
+            Builder::new().calendar(Calendar::new().name("Notes")),
+            client::Config::default(),
+            fs,
+        );
+
+        let response = server.get("/.calendar.ics").await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(
+            response.header("Content-Type"),
+            Some("text/calendar; charset=utf-8")
+        );
+        assert_eq!(response.text().matches("BEGIN:VEVENT").count(), 1);
+        assert!(response.text().contains("SUMMARY:Book hotel\r\n"));
+        assert!(response.text().contains("X-WR-CALNAME:Notes\r\n"));
+
+        // Changed pages are read again
+        server
+            .put("/.fs/Trip.md", "- [ ] Book flight [due: 2024-05-21]\n")
+            .await;
+        let response = server.get("/.calendar.ics").await;
+        assert!(response.text().contains("SUMMARY:Book flight\r\n"));
+        assert!(!response.text().contains("hotel"));
+    }
+}
@@ -0,0 +1,446 @@
+//! Quick capture of snippets into a page, at `POST /.capture`
+//!
+//! Enable with [`Builder::capture`](crate::server::Builder::capture). Each snippet is
+//! appended to the [`Capture::page`] as a list item with its time, e.g.
+//! `- 14:05 Call the plumber`, and pages that don't exist yet are created from the
+//! [`Capture::template`], so the page can be the daily note, e.g. `Journal/{date}`.
+//!
+//! The body is JSON or a form, as share sheets and bookmarklets send them, with the
+//! `text`, `title` and `url` of the snippet, or plain text. Requests must send the
+//! token as `X-Capture-Token` or `?token=`, besides the credentials of the server auth,
+//! and are denied to read-only users.
+//!
+//! A bookmarklet posting the current page, with the token in the query so the request
+//! needs no CORS preflight:
+//!
+//! ```text
+//! javascript:fetch('https://notes.example.com/.capture?token=TOKEN',{method:'POST',
+//! body:new URLSearchParams({title:document.title,url:location.href})})
+//! ```
+
+use std::sync::Arc;
+
+use axum::{
+    Extension, Json, Router,
+    response::{IntoResponse, Response},
+    routing,
+};
+use bytes::Bytes;
+use futures::{TryStreamExt as _, stream};
+use http::{HeaderMap, StatusCode, Uri, header};
+use serde::{Deserialize, Serialize};
+
+use super::fs::{Filesystem, Provider};
+use crate::client;
+use crate::fs::{self, IncomingFileMeta, ReadWriteFilesystem, StreamExt, time};
+use crate::server::error::Error;
+
+/// Page snippets are captured into
+#[derive(Debug, Clone)]
+pub struct Capture {
+    token: String,
+    page: String,
+    template: Option<String>,
+    utc_offset: i64,
+}
+
+impl Capture {
+    /// Capture into `Inbox`, for requests sending `token`.
+    pub fn new(token: impl Into<String>) -> Self {
+        Self {
+            token: token.into(),
+            page: "Inbox".to_string(),
+            template: None,
+            utc_offset: 0,
+        }
+    }
+
+    /// Page of the snippets, with `{date}` as the day of the capture, e.g.
+    /// `Journal/{date}` for `Journal/2026-10-14`.
+    #[must_use]
+    pub fn page(mut self, page: impl Into<String>) -> Self {
+        self.page = page.into();
+        self
+    }
+
+    /// Page whose content new pages start with, `{date}` and `{time}` being those of
+    /// the first capture (none by default).
+    #[must_use]
+    pub fn template(mut self, page: impl Into<String>) -> Self {
+        self.template = Some(page.into());
+        self
+    }
+
+    /// Minutes from UTC of the dates and times of captures (UTC by default).
+    #[must_use]
+    pub fn utc_offset(mut self, minutes: i64) -> Self {
+        self.utc_offset = minutes;
+        self
+    }
+
+    /// Date and time of a capture at `millis`, in the offset of the captures.
+    fn now(&self, millis: u64) -> (String, String) {
+        let minutes = millis as i64 / 60_000 + self.utc_offset;
+        let (year, month, day) = time::civil_from_days(minutes.div_euclid(1440));
+        let minute = minutes.rem_euclid(1440);
+
+        (
+            format!("{year:04}-{month:02}-{day:02}"),
+            format!("{:02}:{:02}", minute / 60, minute % 60),
+        )
+    }
+}
+
+/// Snippet to capture
+#[derive(Debug, Clone, Default, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Snippet {
+    pub text: Option<String>,
+    /// Title of the `url`, its text by default
+    pub title: Option<String>,
+    pub url: Option<String>,
+}
+
+impl Snippet {
+    fn from_form(body: &[u8]) -> Self {
+        let mut snippet = Self::default();
+
+        for (key, value) in form_urlencoded::parse(body) {
+            let field = match key.as_ref() {
+                "text" => &mut snippet.text,
+                "title" => &mut snippet.title,
+                "url" => &mut snippet.url,
+                _ => continue,
+            };
+            *field = Some(value.into_owned()).filter(|value| !value.trim().is_empty());
+        }
+
+        snippet
+    }
+
+    /// List item of the snippet, its other lines indented to stay in it.
+    fn entry(&self, time: &str) -> Option<String> {
+        let text = non_empty(&self.text);
+        let link = non_empty(&self.url).map(|url| match non_empty(&self.title) {
+            Some(title) => format!("[{}]({url})", title.replace(['[', ']'], "")),
+            None => url.to_string(),
+        });
+
+        let content = match (text, link) {
+            (Some(text), Some(link)) => format!("{text} {link}"),
+            (Some(text), None) => text.to_string(),
+            (None, Some(link)) => link,
+            (None, None) => return None,
+        };
+
+        Some(format!("- {time} {}", content.replace('\n', "\n  ")))
+    }
+}
+
+fn non_empty(value: &Option<String>) -> Option<&str> {
+    value
+        .as_deref()
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+}
+
+/// Page a snippet went to
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Captured {
+    /// Name of the page, without `.md`
+    pub page: String,
+    /// Whether the page is new, rather than appended to
+    pub created: bool,
+}
+
+pub(crate) fn router<S>(capture: Capture) -> Router<S>
+where
+    S: Provider + Clone + Send + Sync + 'static,
+{
+    Router::new()
+        .route("/", routing::post(append))
+        .layer(Extension(Arc::new(capture)))
+}
+
+/// Append a snippet to the capture page.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    post,
+    path = "/.capture",
+    tag = "capture",
+    request_body(content(
+        (Snippet = "application/json"),
+        (Snippet = "application/x-www-form-urlencoded"),
+        (String = "text/plain"),
+    )),
+    params(("token" = Option<String>, Query, description = "Token of the capture, unless sent as `X-Capture-Token`")),
+    responses(
+        (status = 201, description = "Page created", body = Captured),
+        (status = 200, description = "Snippet appended to the page", body = Captured),
+        (status = 400, description = "Empty snippet, or invalid body"),
+        (status = 401, description = "Missing or wrong token"),
+        (status = 403, description = "Read-only user"),
+    ),
+))]
+#[cfg_attr(feature = "tracing", tracing::instrument(name = "capture", skip_all))]
+#[cfg_attr(feature = "cloudflare", worker::send)]
+pub async fn append<F>(
+    Extension(capture): Extension<Arc<Capture>>,
+    user: Option<Extension<client::User>>,
+    Filesystem(fs): Filesystem<F>,
+    uri: Uri,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Response, Error>
+where
+    F: ReadWriteFilesystem,
+{
+    if !super::sends_token(&headers, &uri, "x-capture-token", &capture.token) {
+        return Err(Error::Unauthorized("Missing or wrong capture token".into()));
+    }
+
+    if user.is_some_and(|Extension(user)| user.read_only) {
+        return Err(Error::forbidden("read-only users can't capture"));
+    }
+
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("text/plain");
+    let snippet = match content_type.split(';').next().unwrap_or_default().trim() {
+        "application/json" => serde_json::from_slice::<Snippet>(&body)
+            .map_err(|err| Error::BadRequest(format!("Invalid snippet: {err}").into()))?,
+        "application/x-www-form-urlencoded" => Snippet::from_form(&body),
+        _ => Snippet {
+            text: Some(String::from_utf8_lossy(&body).into_owned()),
+            ..Default::default()
+        },
+    };
+
+    let (date, time) = capture.now(time::now());
+    let entry = snippet
+        .entry(&time)
+        .ok_or_else(|| Error::BadRequest("Nothing to capture, send a text or a url".into()))?;
+
+    let page = capture.page.replace("{date}", &date);
+    let path = format!("{page}.md");
+
+    let (created, content) = match read(&fs, &path).await {
+        Ok(existing) => (false, format!("{}\n{entry}\n", existing.trim_end())),
+        Err(fs::Error::NotFound(_)) => {
+            let template = match &capture.template {
+                Some(template) => template_of(&fs, template, &date, &time).await?,
+                None => String::new(),
+            };
+
+            match template.trim_end() {
+                "" => (true, format!("{entry}\n")),
+                template => (true, format!("{template}\n\n{entry}\n")),
+            }
+        }
+        Err(err) => return Err(err.into()),
+    };
+
+    let meta = IncomingFileMeta {
+        content_type: Some("text/markdown".to_string()),
+        size: Some(content.len() as u64),
+        ..Default::default()
+    };
+    let data = stream::once(async move { Ok(Bytes::from(content)) }).into_boxed();
+    fs.put(&path, data, meta).await?;
+
+    #[cfg(feature = "tracing")]
+    tracing::info!(page, created, "Snippet captured");
+
+    let status = match created {
+        true => StatusCode::CREATED,
+        false => StatusCode::OK,
+    };
+
+    Ok((status, Json(Captured { page, created })).into_response())
+}
+
+/// Content of the template page, empty with a warning when it doesn't exist so the
+/// snippet isn't lost.
+async fn template_of<F>(fs: &F, template: &str, date: &str, time: &str) -> Result<String, Error>
+where
+    F: ReadWriteFilesystem,
+{
+    let path = match template.ends_with(".md") {
+        true => template.to_string(),
+        false => format!("{template}.md"),
+    };
+
+    match read(fs, &path).await {
+        Ok(content) => Ok(content.replace("{date}", date).replace("{time}", time)),
+        Err(fs::Error::NotFound(_)) => {
+            #[cfg(feature = "tracing")]
+            tracing::warn!(template, "Capture template not found");
+
+            Ok(String::new())
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+async fn read<F>(fs: &F, path: &str) -> fs::Result<String>
+where
+    F: ReadWriteFilesystem,
+{
+    let (stream, _) = fs.get(path).await?;
+
+    let bytes = stream
+        .try_fold(Vec::new(), |mut acc, chunk| async move {
+            acc.extend_from_slice(&chunk);
+            Ok(acc)
+        })
+        .await?;
+
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::testing::MemoryFs;
+    use crate::server::Builder;
+    use crate::server::test::TestServer;
+    use axum::body::Body;
+
+    fn server(capture: Capture) -> TestServer {
+        let fs = MemoryFs::new()
+            .with_file("Inbox.md", b"# Inbox\n\n- 09:00 Earlier\n\n")
+            .with_file("Templates/Daily.md", b"# {date}\n");
+
+        TestServer::build(
+            Builder::new().capture(capture),
+            client::Config::default(),
+            fs,
+        )
+    }
+
+    fn post(content_type: &str, body: impl Into<Body>) -> http::Request<Body> {
+        http::Request::post("/.capture")
+            .header(header::CONTENT_TYPE, content_type)
+            .header("x-capture-token", "s3cret")
+            .body(body.into())
+            .unwrap()
+    }
+
+    #[test]
+    fn formats_snippets() {
+        let snippet =
+            Snippet::from_form(b"title=Rust+%5Bbook%5D&url=https%3A%2F%2Fexample.com&x=1");
+        assert_eq!(
+            snippet.entry("14:05").as_deref(),
+            Some("- 14:05 [Rust book](https://example.com)")
+        );
+
+        let snippet = Snippet {
+            text: Some(" Two\nlines ".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            snippet.entry("14:05").as_deref(),
+            Some("- 14:05 Two\n  lines")
+        );
+
+        assert_eq!(Snippet::from_form(b"text=+&url=").entry("14:05"), None);
+    }
+
+    #[test]
+    fn dates_captures() {
+        // 2026-10-14T23:30:00Z
+        let millis = (time::days_from_civil(2026, 10, 14) as u64 * 1440 + 23 * 60 + 30) * 60_000;
+
+        let capture = Capture::new("");
+        assert_eq!(
+            capture.now(millis),
+            ("2026-10-14".to_string(), "23:30".to_string())
+        );
+
+        let capture = capture.utc_offset(120);
+        assert_eq!(
+            capture.now(millis),
+            ("2026-10-15".to_string(), "01:30".to_string())
+        );
+
+        let capture = capture.utc_offset(-24 * 60);
+        assert_eq!(
+            capture.now(millis),
+            ("2026-10-13".to_string(), "23:30".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn appends_snippets() {
+        let server = server(Capture::new("s3cret"));
+
+        let response = server.request(post("text/plain", "Call the plumber")).await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(
+            response.json::<Captured>(),
+            Captured {
+                page: "Inbox".to_string(),
+                created: false,
+            }
+        );
+
+        let page = server.get("/.fs/Inbox.md").await.text().to_string();
+        assert!(page.starts_with("# Inbox\n\n- 09:00 Earlier\n- "), "{page}");
+        assert!(page.ends_with(" Call the plumber\n"), "{page}");
+    }
+
+    #[tokio::test]
+    async fn creates_pages_from_templates() {
+        let server = server(
+            Capture::new("s3cret")
+                .page("Journal/{date}")
+                .template("Templates/Daily"),
+        );
+
+        let json = serde_json::json!({ "url": "https://example.com" });
+        let response = server
+            .request(post("application/json", json.to_string()))
+            .await;
+        assert_eq!(response.status, StatusCode::CREATED);
+
+        let captured: Captured = response.json();
+        let date = captured.page.strip_prefix("Journal/").unwrap();
+        let page = server
+            .get(&format!("/.fs/{}.md", captured.page))
+            .await
+            .text()
+            .to_string();
+        assert!(page.starts_with(&format!("# {date}\n\n- ")), "{page}");
+        assert!(page.ends_with(" https://example.com\n"), "{page}");
+    }
+
+    #[tokio::test]
+    async fn checks_requests() {
+        let server = server(Capture::new("s3cret"));
+
+        let request = http::Request::post("/.capture")
+            .body(Body::from("Hi"))
+            .unwrap();
+        assert_eq!(
+            server.request(request).await.status,
+            StatusCode::UNAUTHORIZED
+        );
+
+        let request = http::Request::post("/.capture?token=s3cret")
+            .body(Body::from("Hi"))
+            .unwrap();
+        assert_eq!(server.request(request).await.status, StatusCode::OK);
+
+        let response = server.request(post("text/plain", "  ")).await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+
+        let mut request = post("text/plain", "Hi");
+        request.extensions_mut().insert(client::User {
+            name: "guest".to_string(),
+            read_only: true,
+        });
+        assert_eq!(server.request(request).await.status, StatusCode::FORBIDDEN);
+    }
+}
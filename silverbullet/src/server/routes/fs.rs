@@ -5,12 +5,14 @@ use axum::{
     response::{AppendHeaders, IntoResponse, Response},
     routing,
 };
-use futures::TryStreamExt;
+use bytes::Bytes;
+use futures::{StreamExt as _, TryStreamExt, future, stream};
 use http::request::Parts;
 use http::{HeaderMap, StatusCode};
 
 use crate::fs::{
-    self, FileMeta, IncomingFileMeta, ReadOnlyFilesystem, ReadWriteFilesystem, Stream, StreamExt,
+    FileMeta, FileStream, IncomingFileMeta, ReadOnlyFilesystem, ReadWriteFilesystem, Stream,
+    StreamExt,
 };
 use crate::server::error::Error;
 
@@ -35,6 +37,69 @@ where
     }
 }
 
+/// Longest file name, in bytes
+const MAX_PATH_LEN: usize = 4096;
+
+/// Longest file or folder name within a path, in bytes
+const MAX_SEGMENT_LEN: usize = 255;
+
+/// File name of a request, validated before reaching the filesystem
+///
+/// Names are passed as is, so files stored under decomposed names stay reachable: the
+/// [`normalized`](crate::fs::normalized) layer of the space folds them. Rejects with 400 names that are empty, absolute, contain `.` or `..` segments, NUL
+/// bytes or empty segments, or are longer than the limits of common filesystems.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilePath(pub String);
+
+impl<S> FromRequestParts<S> for FilePath
+where
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Path(path) = Path::<String>::from_request_parts(parts, state)
+            .await
+            .map_err(IntoResponse::into_response)?;
+
+        validate(&path).map_err(IntoResponse::into_response)
+    }
+}
+
+fn validate(path: &str) -> Result<FilePath, Error> {
+    let invalid =
+        |reason: &str| Error::BadRequest(format!("Invalid file name {path:?}: {reason}").into());
+
+    if path.is_empty() {
+        return Err(invalid("empty"));
+    }
+
+    if path.contains('\0') {
+        return Err(invalid("contains a NUL byte"));
+    }
+
+    let drive = path.as_bytes().get(1) == Some(&b':') && path.as_bytes()[0].is_ascii_alphabetic();
+    if path.starts_with(['/', '\\']) || drive {
+        return Err(invalid("absolute path"));
+    }
+
+    if path.len() > MAX_PATH_LEN {
+        return Err(invalid("too long"));
+    }
+
+    // Backslashes separate folders on Windows
+    for segment in path.split(['/', '\\']) {
+        match segment {
+            "" => return Err(invalid("empty folder name")),
+            "." | ".." => return Err(invalid("relative segment")),
+            _ if segment.len() > MAX_SEGMENT_LEN => return Err(invalid("name too long")),
+            _ => {}
+        }
+    }
+
+    Ok(FilePath(path.to_string()))
+}
+
 pub fn router<S>() -> Router<S>
 where
     S: Provider + Clone + Send + Sync + 'static,
@@ -45,26 +110,202 @@ where
     )
 }
 
+/// Entries written to the body of a listing at once, of the files listed so far
+const LIST_BATCH: usize = 256;
+
+/// Media type of [`Listing::Packed`]
+pub const PACKED_LISTING: &str = "application/vnd.silverbullet.packed+json";
+
+/// Columns of the rows of [`Listing::Packed`]
+const PACKED_COLUMNS: &[u8] =
+    br#"[["name","created","perm","contentType","lastModified","size","etag","version"]"#;
+
+/// Representation of a listing, negotiated with `Accept`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Listing {
+    /// JSON array of the file objects, `application/json`, what the client expects
+    Json,
+    /// One file object per line, `application/x-ndjson`
+    Ndjson,
+    /// JSON array of the column names followed by an array of values per file,
+    /// [`PACKED_LISTING`], without repeating the keys of every file
+    Packed,
+}
+
+impl Listing {
+    /// Format the `Accept` header prefers, [`Listing::Json`] unless it prefers another.
+    pub fn negotiate(headers: &HeaderMap) -> Self {
+        let mut best = (Listing::Json, 0.0);
+
+        let accept = headers
+            .get_all(http::header::ACCEPT)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','));
+
+        for range in accept {
+            let mut params = range.split(';').map(str::trim);
+            let listing = match params.next().unwrap_or_default() {
+                "application/json" | "application/*" | "*/*" => Listing::Json,
+                "application/x-ndjson" => Listing::Ndjson,
+                PACKED_LISTING => Listing::Packed,
+                _ => continue,
+            };
+            let quality = params
+                .find_map(|param| param.strip_prefix("q="))
+                .map_or(1.0, |q| q.parse().unwrap_or(0.0));
+
+            if quality > best.1 {
+                best = (listing, quality);
+            }
+        }
+
+        best.0
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Listing::Json => "application/json",
+            Listing::Ndjson => "application/x-ndjson",
+            Listing::Packed => PACKED_LISTING,
+        }
+    }
+
+    fn open(self) -> &'static [u8] {
+        match self {
+            Listing::Json => b"[",
+            Listing::Ndjson => b"",
+            Listing::Packed => PACKED_COLUMNS,
+        }
+    }
+
+    fn close(self) -> &'static [u8] {
+        match self {
+            Listing::Json | Listing::Packed => b"]",
+            Listing::Ndjson => b"",
+        }
+    }
+
+    fn write(self, chunk: &mut Vec<u8>, first: bool, file: &FileMeta) -> serde_json::Result<()> {
+        match self {
+            Listing::Json => {
+                if !first {
+                    chunk.push(b',');
+                }
+
+                serde_json::to_writer(chunk, file)
+            }
+            Listing::Ndjson => {
+                serde_json::to_writer(&mut *chunk, file)?;
+                chunk.push(b'\n');
+
+                Ok(())
+            }
+            Listing::Packed => {
+                chunk.push(b',');
+
+                let row = (
+                    &file.name,
+                    file.created,
+                    &file.perm,
+                    &file.content_type,
+                    file.last_modified,
+                    file.size,
+                    &file.etag,
+                    &file.version,
+                );
+                serde_json::to_writer(chunk, &row)
+            }
+        }
+    }
+
+    /// Body of a listing of the files, in batches of the entries ready.
+    fn body(self, files: FileStream) -> Stream {
+        let open = stream::once(future::ready(Ok(Bytes::from_static(self.open()))));
+        let close = stream::once(future::ready(Ok(Bytes::from_static(self.close()))));
+
+        let entries = files
+            .ready_chunks(LIST_BATCH)
+            .enumerate()
+            .map(move |(batch, files)| {
+                let mut chunk = Vec::new();
+
+                for (index, file) in files.into_iter().enumerate() {
+                    let file = file.map_err(std::io::Error::other)?;
+                    self.write(&mut chunk, batch == 0 && index == 0, &file)?;
+                }
+
+                Ok(Bytes::from(chunk))
+            });
+
+        open.chain(entries).chain(close).into_boxed()
+    }
+}
+
+/// List all files of the space.
+///
+/// The files are streamed as the backend lists them, see
+/// [`ReadOnlyFilesystem::list_stream`], so large spaces start arriving before the listing
+/// is done. A backend failing after the first files aborts the body, which the client
+/// can't parse then. Clients asking for it with `Accept` get a more compact [`Listing`].
+#[cfg_attr(feature = "openapi", utoipa::path(
+    get,
+    path = "/.fs",
+    tag = "fs",
+    params(
+        ("Accept" = Option<String>, Header, description = "`application/x-ndjson` for a file per line, `application/vnd.silverbullet.packed+json` for rows of values"),
+    ),
+    responses((status = 200, description = "Files of the space", content(
+        ([FileMeta] = "application/json"),
+        (FileMeta = "application/x-ndjson"),
+        (Vec<serde_json::Value> = "application/vnd.silverbullet.packed+json"),
+    ))),
+))]
 #[cfg_attr(feature = "cloudflare", worker::send)]
-pub async fn list<F>(Filesystem(fs): Filesystem<F>) -> Result<impl IntoResponse, fs::Error>
+pub async fn list<F>(
+    Filesystem(fs): Filesystem<F>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, Error>
 where
     F: ReadOnlyFilesystem,
 {
-    let files = fs.list().await.map(|mut files| {
-        files.sort_by(|a, b| a.name.cmp(&b.name));
+    let listing = Listing::negotiate(&headers);
+    let files = fs.list_stream().await?;
 
-        files
-    })?;
-
-    Ok(Json(files))
+    Ok((
+        [
+            (http::header::CONTENT_TYPE, listing.content_type()),
+            (http::header::VARY, "Accept"),
+        ],
+        Body::from_stream(listing.body(files)),
+    ))
 }
 
+/// Read a file, or only its metadata with `X-Get-Meta`.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    get,
+    path = "/.fs/{path}",
+    tag = "fs",
+    params(
+        ("path" = String, Path, description = "File name, e.g. `notes/page.md`"),
+        ("X-Get-Meta" = Option<String>, Header, description = "Only return the metadata headers"),
+    ),
+    responses(
+        (status = 200, description = "File content", content_type = "application/octet-stream", headers(
+            ("X-Content-Length" = u64, description = "Size in bytes"),
+            ("X-Created" = u64, description = "Creation time in milliseconds"),
+            ("X-Last-Modified" = u64, description = "Modification time in milliseconds"),
+            ("X-Permission" = String, description = "`rw` or `ro`"),
+        )),
+        (status = 404, description = "No such file"),
+    ),
+))]
 #[cfg_attr(feature = "cloudflare", worker::send)]
 pub async fn get<F>(
     Filesystem(fs): Filesystem<F>,
-    Path(path): Path<String>,
+    FilePath(path): FilePath,
     headers: HeaderMap,
-) -> Result<impl IntoResponse, Response>
+) -> Result<impl IntoResponse, Error>
 where
     F: ReadOnlyFilesystem,
 {
@@ -82,16 +323,34 @@ where
         body = Body::from_stream(stream);
     }
 
-    Ok((HeaderMap::try_from(meta).map_err(Error::from)?, body))
+    Ok((HeaderMap::try_from(meta)?, body))
 }
 
+/// Write a file with the request body.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    put,
+    path = "/.fs/{path}",
+    tag = "fs",
+    params(
+        ("path" = String, Path, description = "File name, e.g. `notes/page.md`"),
+        ("X-Created" = Option<u64>, Header, description = "Creation time in milliseconds"),
+        ("X-Last-Modified" = Option<u64>, Header, description = "Modification time in milliseconds, the time of the write by default"),
+        ("If-Match" = Option<String>, Header, description = "Only write over this etag, `*` for any file"),
+        ("If-None-Match" = Option<String>, Header, description = "Only write a new file with `*`"),
+    ),
+    request_body(content = Vec<u8>, content_type = "application/octet-stream"),
+    responses(
+        (status = 200, description = "Metadata of the written file", body = FileMeta),
+        (status = 412, description = "The file doesn't match `If-Match` or `If-None-Match`"),
+    ),
+))]
 #[cfg_attr(feature = "cloudflare", worker::send)]
 pub async fn put<F>(
     Filesystem(fs): Filesystem<F>,
-    Path(path): Path<String>,
+    FilePath(path): FilePath,
     incoming_meta: IncomingFileMeta,
     body: Body,
-) -> Result<impl IntoResponse, Response>
+) -> Result<impl IntoResponse, Error>
 where
     F: ReadWriteFilesystem,
 {
@@ -102,18 +361,34 @@ where
 
     let meta = fs.put(&path, stream, incoming_meta).await?;
 
+    // The body is the JSON meta, not the file
+    let mut headers = HeaderMap::try_from(meta.clone())?;
+    headers.remove(http::header::CONTENT_LENGTH);
+    headers.remove(http::header::CONTENT_TYPE);
+
     Ok((
-        HeaderMap::try_from(meta.clone()).map_err(Error::from)?,
+        headers,
         AppendHeaders([("Cache-Control", "no-cache")]),
         Json(meta),
     ))
 }
 
+/// Delete a file.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    delete,
+    path = "/.fs/{path}",
+    tag = "fs",
+    params(("path" = String, Path, description = "File name, e.g. `notes/page.md`")),
+    responses(
+        (status = 200, description = "Deleted", body = String, example = "OK"),
+        (status = 404, description = "No such file"),
+    ),
+))]
 #[cfg_attr(feature = "cloudflare", worker::send)]
 pub async fn delete<F>(
     Filesystem(fs): Filesystem<F>,
-    Path(path): Path<String>,
-) -> Result<impl IntoResponse, fs::Error>
+    FilePath(path): FilePath,
+) -> Result<impl IntoResponse, Error>
 where
     F: ReadWriteFilesystem,
 {
@@ -127,3 +402,239 @@ where
 pub async fn options() -> impl IntoResponse {
     ([("Allow", "GET, PUT, DELETE, OPTIONS")], StatusCode::OK)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::testing::{MemoryFs, read_stream};
+
+    fn file(name: String) -> FileMeta {
+        FileMeta {
+            name,
+            created: 0,
+            perm: "rw".to_string(),
+            content_type: "text/markdown".to_string(),
+            last_modified: 0,
+            size: 0,
+            etag: None,
+            version: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn streams_listing_as_json_array() {
+        let empty = Listing::Json.body(stream::empty().into_file_stream());
+        assert_eq!(read_stream(empty).await, b"[]");
+
+        let fs = MemoryFs::new()
+            .with_file("b.md", b"")
+            .with_file("a.md", b"");
+        let body = read_stream(Listing::Json.body(fs.list_stream().await.unwrap())).await;
+        let files: Vec<FileMeta> = serde_json::from_slice(&body).unwrap();
+        assert_eq!(files[0].name, "a.md");
+        assert_eq!(files[1].name, "b.md");
+
+        // Across batches
+        let many = (0..LIST_BATCH + 3).map(|i| Ok(file(format!("{i}.md"))));
+        let body = read_stream(Listing::Json.body(stream::iter(many).into_file_stream())).await;
+        let files: Vec<FileMeta> = serde_json::from_slice(&body).unwrap();
+        assert_eq!(files.len(), LIST_BATCH + 3);
+    }
+
+    #[tokio::test]
+    async fn streams_compact_listings() {
+        let files = || stream::iter([Ok(file("a.md".to_string())), Ok(file("b.md".to_string()))]);
+
+        let body = read_stream(Listing::Ndjson.body(files().into_file_stream())).await;
+        let lines: Vec<FileMeta> = body
+            .split(|&byte| byte == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| serde_json::from_slice(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1].name, "b.md");
+
+        let body = read_stream(Listing::Packed.body(files().into_file_stream())).await;
+        assert_eq!(
+            String::from_utf8(body).unwrap(),
+            r#"[["name","created","perm","contentType","lastModified","size","etag","version"],["a.md",0,"rw","text/markdown",0,0,null,null],["b.md",0,"rw","text/markdown",0,0,null,null]]"#
+        );
+
+        let empty = Listing::Packed.body(stream::empty().into_file_stream());
+        let rows: Vec<serde_json::Value> =
+            serde_json::from_slice(&read_stream(empty).await).unwrap();
+        assert_eq!(rows.len(), 1);
+    }
+
+    #[test]
+    fn negotiates_listing() {
+        let accept = |value: &'static str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(http::header::ACCEPT, value.parse().unwrap());
+
+            Listing::negotiate(&headers)
+        };
+
+        assert_eq!(Listing::negotiate(&HeaderMap::new()), Listing::Json);
+        assert_eq!(accept("*/*"), Listing::Json);
+        assert_eq!(accept("application/x-ndjson"), Listing::Ndjson);
+        assert_eq!(
+            accept("application/json;q=0.5, application/vnd.silverbullet.packed+json"),
+            Listing::Packed
+        );
+        assert_eq!(
+            accept("application/json, application/x-ndjson"),
+            Listing::Json
+        );
+        assert_eq!(accept("application/x-ndjson;q=0, text/html"), Listing::Json);
+    }
+
+    #[derive(Clone)]
+    struct Space(std::sync::Arc<MemoryFs>);
+
+    impl Provider for Space {
+        type Output = std::sync::Arc<MemoryFs>;
+
+        fn provide(&self, _parts: &mut Parts) -> Result<Self::Output, Error> {
+            Ok(self.0.clone())
+        }
+    }
+
+    async fn call(
+        router: &mut Router,
+        method: http::Method,
+        uri: &str,
+        body: &'static str,
+    ) -> (StatusCode, HeaderMap, Bytes) {
+        use tower_service::Service as _;
+
+        let request = http::Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(Body::from(body))
+            .unwrap();
+        let response = router.call(request).await.unwrap();
+        let (parts, body) = response.into_parts();
+
+        (
+            parts.status,
+            parts.headers,
+            axum::body::to_bytes(body, usize::MAX).await.unwrap(),
+        )
+    }
+
+    #[tokio::test]
+    async fn round_trips_files() {
+        use http::Method;
+
+        let mut router = router().with_state(Space(std::sync::Arc::new(MemoryFs::new())));
+
+        let (status, _, body) = call(&mut router, Method::GET, "/", "").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "[]");
+
+        let (status, headers, body) = call(&mut router, Method::PUT, "/notes/a.md", "hello").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(headers.get("X-Content-Length").unwrap(), "5");
+        let meta: FileMeta = serde_json::from_slice(&body).unwrap();
+        assert_eq!((meta.name.as_str(), meta.size), ("notes/a.md", 5));
+
+        let (_, _, body) = call(&mut router, Method::GET, "/", "").await;
+        let files: Vec<FileMeta> = serde_json::from_slice(&body).unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].name, "notes/a.md");
+
+        let (status, headers, body) = call(&mut router, Method::GET, "/notes/a.md", "").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(headers.get("X-Permission").unwrap(), "rw");
+        assert_eq!(body, "hello");
+
+        let (status, _, body) = call(&mut router, Method::DELETE, "/notes/a.md", "").await;
+        assert_eq!((status, body), (StatusCode::OK, Bytes::from("OK")));
+
+        let (status, _, _) = call(&mut router, Method::GET, "/notes/a.md", "").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let (_, _, body) = call(&mut router, Method::GET, "/", "").await;
+        assert_eq!(body, "[]");
+    }
+
+    #[tokio::test]
+    async fn aborts_listing_on_failure() {
+        let files = stream::iter([
+            Ok(file("a.md".to_string())),
+            Err(crate::fs::Error::Transient("throttled".into())),
+        ]);
+
+        let body: std::result::Result<Vec<Bytes>, _> = Listing::Json
+            .body(files.into_file_stream())
+            .try_collect()
+            .await;
+        assert!(body.is_err());
+    }
+
+    #[test]
+    fn accepts_file_names() {
+        for path in [
+            "index.md",
+            "Daily notes/2024-01-01.md",
+            ".hidden/a..b.md",
+            "C/d:e.md",
+        ] {
+            assert_eq!(validate(path).unwrap().0, path);
+        }
+        assert_eq!(validate("Cafe\u{301}.md").unwrap().0, "Cafe\u{301}.md");
+    }
+
+    #[tokio::test]
+    async fn serves_decomposed_names() {
+        use crate::client;
+        use crate::server::Builder;
+        use crate::server::test::TestServer;
+
+        let server = TestServer::build(
+            Builder::new(),
+            client::Config::default(),
+            MemoryFs::new().with_file("Cafe\u{301}.md", b"menu"),
+        );
+
+        let response = server.get("/.fs/Cafe%CC%81.md").await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.text(), "menu");
+
+        assert_eq!(
+            server.delete("/.fs/Cafe%CC%81.md").await.status,
+            StatusCode::OK
+        );
+        assert_eq!(
+            server.get("/.fs/Cafe%CC%81.md").await.status,
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_file_names() {
+        let long_name = "a".repeat(256);
+        let long_path = "a/".repeat(2048) + "b";
+
+        for path in [
+            "",
+            "../secret",
+            "notes/../../etc/passwd",
+            "notes/./page.md",
+            "..\\secret",
+            "/etc/passwd",
+            "\\\\server\\share",
+            "C:\\Windows",
+            "c:/windows",
+            "page.md\0.png",
+            "notes//page.md",
+            "notes/",
+            &long_name,
+            &long_path,
+        ] {
+            let err = validate(path).unwrap_err();
+            assert!(matches!(err, Error::BadRequest(_)), "{path:?}");
+        }
+    }
+}
@@ -0,0 +1,428 @@
+//! Just enough of RFC 822 and MIME to turn a raw email into an [`Email`]
+//!
+//! Reads the headers, with RFC 2047 encoded words and RFC 2231 file names, multipart
+//! bodies, base64 and quoted-printable parts, and UTF-8 or Latin-1 text. Other charsets
+//! are read as UTF-8, lossily.
+
+use base64::Engine as _;
+use bytes::Bytes;
+
+use super::{Attachment, Email};
+
+/// Headers of a message or part, names in lowercase and values unfolded
+struct Headers(Vec<(String, String)>);
+
+impl Headers {
+    fn get(&self, name: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(header, _)| header == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Parse a raw email, never failing: anything unreadable is left out.
+pub(super) fn parse(raw: &[u8]) -> Email {
+    let (headers, body) = split(raw);
+
+    let mut email = Email {
+        from: headers.get("from").map(decode_words),
+        subject: headers.get("subject").map(decode_words),
+        date: headers.get("date").map(str::to_string),
+        ..Default::default()
+    };
+
+    let mut html = Vec::new();
+    walk(&headers, body, &mut email, &mut html);
+
+    if email.text.is_none() && !html.is_empty() {
+        email.html = Some(html.join("\n"));
+    }
+
+    email
+}
+
+/// Headers and body of a message or part.
+fn split(raw: &[u8]) -> (Headers, &[u8]) {
+    let (head, body) = match find(raw, b"\r\n\r\n") {
+        Some(at) => (&raw[..at], &raw[at + 4..]),
+        None => match find(raw, b"\n\n") {
+            Some(at) => (&raw[..at], &raw[at + 2..]),
+            None => (raw, &[][..]),
+        },
+    };
+
+    let mut headers: Vec<(String, String)> = Vec::new();
+
+    for line in String::from_utf8_lossy(head).lines() {
+        match line.starts_with([' ', '\t']) {
+            true => {
+                if let Some((_, value)) = headers.last_mut() {
+                    value.push(' ');
+                    value.push_str(line.trim());
+                }
+            }
+            false => {
+                if let Some((name, value)) = line.split_once(':') {
+                    headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+                }
+            }
+        }
+    }
+
+    (Headers(headers), body)
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Add the text and attachments of a part to the email, and its HTML to `html`.
+fn walk(headers: &Headers, body: &[u8], email: &mut Email, html: &mut Vec<String>) {
+    let content_type = headers.get("content-type").unwrap_or("text/plain");
+    let mime = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+
+    if mime.starts_with("multipart/") {
+        if let Some(boundary) = param(content_type, "boundary") {
+            for part in parts(body, &boundary) {
+                let (headers, body) = split(part);
+                walk(&headers, body, email, html);
+            }
+        }
+        return;
+    }
+
+    let disposition = headers.get("content-disposition").unwrap_or_default();
+    let filename = param(disposition, "filename").or_else(|| param(content_type, "name"));
+    let attached = disposition
+        .trim_start()
+        .to_ascii_lowercase()
+        .starts_with("attachment");
+    let content = decode_transfer(headers.get("content-transfer-encoding"), body);
+
+    match (mime.as_str(), filename) {
+        ("text/plain", None) if !attached => {
+            let text = decode_charset(param(content_type, "charset").as_deref(), &content);
+            match &mut email.text {
+                Some(existing) => {
+                    existing.push_str("\n\n");
+                    existing.push_str(&text);
+                }
+                None => email.text = Some(text),
+            }
+        }
+        ("text/html", None) if !attached => {
+            html.push(decode_charset(
+                param(content_type, "charset").as_deref(),
+                &content,
+            ));
+        }
+        (mime, filename) => {
+            let filename = filename.unwrap_or_else(|| match mime {
+                "message/rfc822" => "message.eml".to_string(),
+                _ => format!("attachment-{}", email.attachments.len() + 1),
+            });
+
+            email.attachments.push(Attachment {
+                filename,
+                content_type: Some(mime.to_string()),
+                content: Bytes::from(content),
+            });
+        }
+    }
+}
+
+/// Bodies of the parts of a multipart body, without the preamble and epilogue.
+fn parts<'a>(body: &'a [u8], boundary: &str) -> Vec<&'a [u8]> {
+    let delimiter = format!("--{boundary}");
+    let mut parts = Vec::new();
+    let mut start: Option<usize> = None;
+    let mut offset = 0;
+
+    for line in body.split_inclusive(|&byte| byte == b'\n') {
+        let trimmed = line.trim_ascii_end();
+
+        if let Some(rest) = trimmed.strip_prefix(delimiter.as_bytes()) {
+            if let Some(start) = start {
+                // The line break before the delimiter belongs to it
+                let part = &body[start..offset];
+                let part = part.strip_suffix(b"\n").unwrap_or(part);
+                parts.push(part.strip_suffix(b"\r").unwrap_or(part));
+            }
+
+            if rest == b"--" {
+                break;
+            }
+            start = Some(offset + line.len());
+        }
+
+        offset += line.len();
+    }
+
+    parts
+}
+
+/// Value of a parameter of a header, e.g. the `boundary` of a `Content-Type`.
+///
+/// Reads `name="value"`, `name=value` and the RFC 2231 `name*=utf-8''value`, and
+/// decodes encoded words, which some clients put in file names.
+fn param(header: &str, name: &str) -> Option<String> {
+    for param in header.split(';').skip(1) {
+        let Some((key, value)) = param.split_once('=') else {
+            continue;
+        };
+        let key = key.trim().to_ascii_lowercase();
+        let value = value.trim();
+
+        if key == format!("{name}*") {
+            let (charset, value) = match value.splitn(3, '\'').collect::<Vec<_>>()[..] {
+                [charset, _, value] => (Some(charset), value),
+                _ => (None, value),
+            };
+            return Some(decode_charset(charset, &percent_decode(value)));
+        }
+
+        if key == name {
+            let value = value
+                .strip_prefix('"')
+                .and_then(|value| value.strip_suffix('"'))
+                .unwrap_or(value);
+            return Some(decode_words(value));
+        }
+    }
+
+    None
+}
+
+fn percent_decode(value: &str) -> Vec<u8> {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match (bytes[i], bytes.get(i + 1..i + 3)) {
+            (b'%', Some(hex)) if hex.iter().all(u8::is_ascii_hexdigit) => {
+                decoded.push(hex_value(hex[0]) << 4 | hex_value(hex[1]));
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+
+    decoded
+}
+
+fn hex_value(digit: u8) -> u8 {
+    match digit {
+        b'0'..=b'9' => digit - b'0',
+        b'a'..=b'f' => digit - b'a' + 10,
+        _ => digit - b'A' + 10,
+    }
+}
+
+/// Body of a part, decoded from its `Content-Transfer-Encoding`.
+fn decode_transfer(encoding: Option<&str>, body: &[u8]) -> Vec<u8> {
+    match encoding.map(|encoding| encoding.trim().to_ascii_lowercase()) {
+        Some(encoding) if encoding == "base64" => {
+            let compact: Vec<u8> = body
+                .iter()
+                .copied()
+                .filter(|byte| !byte.is_ascii_whitespace())
+                .collect();
+            base64::engine::general_purpose::STANDARD
+                .decode(&compact)
+                .unwrap_or_default()
+        }
+        Some(encoding) if encoding == "quoted-printable" => quoted_printable(body, false),
+        _ => body.to_vec(),
+    }
+}
+
+/// Quoted-printable, with `_` as a space in encoded words.
+fn quoted_printable(encoded: &[u8], words: bool) -> Vec<u8> {
+    let mut decoded = Vec::with_capacity(encoded.len());
+    let mut i = 0;
+
+    while i < encoded.len() {
+        match encoded[i] {
+            b'=' => match encoded.get(i + 1..i + 3) {
+                Some(hex) if hex.iter().all(u8::is_ascii_hexdigit) => {
+                    decoded.push(hex_value(hex[0]) << 4 | hex_value(hex[1]));
+                    i += 3;
+                }
+                // Soft line break
+                Some([b'\r', b'\n']) => i += 3,
+                Some([b'\n', ..]) => i += 2,
+                _ => {
+                    decoded.push(b'=');
+                    i += 1;
+                }
+            },
+            b'_' if words => {
+                decoded.push(b' ');
+                i += 1;
+            }
+            byte => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+
+    decoded
+}
+
+fn decode_charset(charset: Option<&str>, bytes: &[u8]) -> String {
+    match charset.map(str::to_ascii_lowercase).as_deref() {
+        Some("iso-8859-1" | "latin1" | "windows-1252" | "cp1252") => {
+            bytes.iter().map(|&byte| char::from(byte)).collect()
+        }
+        _ => String::from_utf8_lossy(bytes).into_owned(),
+    }
+}
+
+/// Header value with its RFC 2047 encoded words decoded, e.g. `=?utf-8?q?Caf=C3=A9?=`.
+fn decode_words(value: &str) -> String {
+    let mut decoded = String::new();
+    let mut rest = value;
+    // Whitespace between two encoded words isn't part of the value
+    let mut after_word = false;
+
+    while let Some(start) = rest.find("=?") {
+        let word = rest[start + 2..].splitn(3, '?').collect::<Vec<_>>();
+        let [charset, encoding, tail] = word[..] else {
+            break;
+        };
+        let Some(end) = tail.find("?=") else {
+            break;
+        };
+
+        let bytes = match encoding.to_ascii_lowercase().as_str() {
+            "b" => base64::engine::general_purpose::STANDARD
+                .decode(&tail[..end])
+                .ok(),
+            "q" => Some(quoted_printable(&tail.as_bytes()[..end], true)),
+            _ => None,
+        };
+        let Some(bytes) = bytes else {
+            break;
+        };
+
+        let between = &rest[..start];
+        if !(after_word && between.trim().is_empty()) {
+            decoded.push_str(between);
+        }
+        decoded.push_str(&decode_charset(Some(charset), &bytes));
+
+        // `=?` + charset + `?` + encoding + `?` + text + `?=`
+        rest = &rest[start + 2 + charset.len() + 1 + encoding.len() + 1 + end + 2..];
+        after_word = true;
+    }
+
+    decoded.push_str(rest);
+    decoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_headers() {
+        assert_eq!(
+            decode_words("=?utf-8?q?Caf=C3=A9_au?= =?UTF-8?B?bGFpdA==?= maybe"),
+            "Café aulait maybe"
+        );
+        assert_eq!(decode_words("=?iso-8859-1?q?=E9t=E9?="), "été");
+        assert_eq!(decode_words("Plain =?bogus"), "Plain =?bogus");
+
+        assert_eq!(
+            param(r#"attachment; filename="a b.pdf""#, "filename").as_deref(),
+            Some("a b.pdf")
+        );
+        assert_eq!(
+            param("attachment; filename*=utf-8''%C3%A9t%C3%A9.txt", "filename").as_deref(),
+            Some("été.txt")
+        );
+        assert_eq!(
+            param("multipart/mixed; boundary=xyz", "boundary").as_deref(),
+            Some("xyz")
+        );
+    }
+
+    #[test]
+    fn parses_multipart_emails() {
+        let raw = concat!(
+            "From: Alice <alice@example.com>\r\n",
+            "Subject: =?utf-8?q?Caf=C3=A9?=\r\n",
+            " notes\r\n",
+            "Date: Tue, 13 Oct 2026 10:00:00 +0000\r\n",
+            "Content-Type: multipart/mixed; boundary=\"outer\"\r\n",
+            "\r\n",
+            "Preamble\r\n",
+            "--outer\r\n",
+            "Content-Type: multipart/alternative; boundary=inner\r\n",
+            "\r\n",
+            "--inner\r\n",
+            "Content-Type: text/plain; charset=utf-8\r\n",
+            "Content-Transfer-Encoding: quoted-printable\r\n",
+            "\r\n",
+            "Hello =E2=80=94 a long=\r\n",
+            " line\r\n",
+            "--inner\r\n",
+            "Content-Type: text/html\r\n",
+            "\r\n",
+            "<p>Hello</p>\r\n",
+            "--inner--\r\n",
+            "--outer\r\n",
+            "Content-Type: application/pdf; name=\"report.pdf\"\r\n",
+            "Content-Disposition: attachment\r\n",
+            "Content-Transfer-Encoding: base64\r\n",
+            "\r\n",
+            "JVBE\r\n",
+            "Rg==\r\n",
+            "--outer--\r\n",
+            "Epilogue\r\n",
+        );
+
+        let email = parse(raw.as_bytes());
+        assert_eq!(email.from.as_deref(), Some("Alice <alice@example.com>"));
+        assert_eq!(email.subject.as_deref(), Some("Café notes"));
+        assert_eq!(
+            email.date.as_deref(),
+            Some("Tue, 13 Oct 2026 10:00:00 +0000")
+        );
+        assert_eq!(email.text.as_deref(), Some("Hello — a long line"));
+        assert_eq!(email.html, None);
+
+        assert_eq!(email.attachments.len(), 1);
+        assert_eq!(email.attachments[0].filename, "report.pdf");
+        assert_eq!(
+            email.attachments[0].content_type.as_deref(),
+            Some("application/pdf")
+        );
+        assert_eq!(email.attachments[0].content, "%PDF");
+    }
+
+    #[test]
+    fn parses_simple_emails() {
+        let email = parse(b"Subject: Hi\nContent-Type: text/html\n\n<b>Hi</b> there\n");
+        assert_eq!(email.subject.as_deref(), Some("Hi"));
+        assert_eq!(email.text, None);
+        assert_eq!(email.html.as_deref(), Some("<b>Hi</b> there\n"));
+
+        let email = parse(b"Subject: Bare\n\nJust text");
+        assert_eq!(email.text.as_deref(), Some("Just text"));
+        assert!(email.attachments.is_empty());
+    }
+}
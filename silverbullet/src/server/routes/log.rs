@@ -1,6 +1,6 @@
 use axum::extract::{FromRef, State};
 use axum_client_ip::ClientIp;
-use http::StatusCode;
+use http::{HeaderMap, StatusCode, header};
 
 use crate::client::{self, LogEntry};
 
@@ -21,14 +21,35 @@ where
     }
 }
 
+#[cfg_attr(feature = "openapi", utoipa::path(
+    post,
+    path = "/.logs",
+    tag = "client",
+    request_body = [LogEntry],
+    responses((status = 200, description = "Entries logged")),
+))]
+#[cfg_attr(feature = "tracing", tracing::instrument(name = "client_logs", skip_all, fields(client_ip = %ip)))]
 pub async fn log<L>(
     State(Logger(logger)): State<Logger<L>>,
     ClientIp(ip): ClientIp,
-    axum::Json(entries): axum::Json<Vec<LogEntry>>,
+    headers: HeaderMap,
+    axum::Json(mut entries): axum::Json<Vec<LogEntry>>,
 ) -> StatusCode
 where
     L: client::Logger,
 {
+    let user_agent = headers
+        .get(header::USER_AGENT)
+        .and_then(|v| v.to_str().ok());
+
+    if let Some(user_agent) = user_agent {
+        for entry in &mut entries {
+            entry
+                .user_agent
+                .get_or_insert_with(|| user_agent.to_string());
+        }
+    }
+
     logger.log(ip.to_string(), entries);
 
     StatusCode::OK
@@ -0,0 +1,506 @@
+//! Plugs of the space, SilverBullet's plugins, under `/.plugs`
+//!
+//! Enable with [`Builder::plugs`](crate::server::Builder::plugs). Plugs are the
+//! `_plug/<name>.plug.js` bundles of the space, as the client keeps them.
+//!
+//! | Route | Action |
+//! |---|---|
+//! | `GET /.plugs` | List the installed plugs |
+//! | `GET /.plugs/{name}.plug.js` | Serve a bundle |
+//! | `POST /.plugs` | Install or update a plug from a URL |
+//!
+//! Bundles are served with their version as `ETag`, and cached for good when asked for
+//! with it, e.g. `/.plugs/git.plug.js?v=18f2a3c-4d2`, the `url` of the listing: an update
+//! changes the version, so the URL. Without it, caches revalidate every time.
+//!
+//! Installs only fetch URLs under one of the [`Plugs::source`]s, through the proxy and
+//! its policy, and are denied to read-only users.
+
+use std::sync::Arc;
+
+use axum::{
+    Extension, Json, Router,
+    extract::{Path, State},
+    response::{IntoResponse, Response},
+    routing,
+};
+use bytes::Bytes;
+use futures::stream;
+use http::{HeaderMap, Method, StatusCode, Uri, header};
+use serde::{Deserialize, Serialize};
+
+use super::fs::{Filesystem, Provider};
+use super::proxy::{Proxy, upstream_error};
+use crate::client;
+use crate::fs::{FileMeta, IncomingFileMeta, ReadOnlyFilesystem, ReadWriteFilesystem, StreamExt};
+use crate::proxy::{self, Client};
+use crate::server::error::Error;
+
+/// Folder of the plugs in the space
+pub const PREFIX: &str = "_plug/";
+
+/// Extension of a plug bundle
+pub const EXTENSION: &str = ".plug.js";
+
+/// `Cache-Control` of a bundle asked for with its version
+const IMMUTABLE: &str = "public, max-age=31536000, immutable";
+
+/// Where plugs may be installed from
+#[derive(Debug, Clone, Default)]
+pub struct Plugs {
+    sources: Vec<String>,
+}
+
+impl Plugs {
+    /// Plugs that can be listed and served, but not installed without a source.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allow installs from URLs starting with `prefix`, e.g.
+    /// `https://raw.githubusercontent.com/silverbulletmd/`. End it with a `/`, or
+    /// `https://example.com/plugs` also allows `https://example.com/plugs-unchecked/`.
+    #[must_use]
+    pub fn source(mut self, prefix: impl Into<String>) -> Self {
+        self.sources.push(prefix.into());
+        self
+    }
+
+    fn allows(&self, url: &str) -> bool {
+        self.sources.iter().any(|source| url.starts_with(source))
+    }
+}
+
+/// Installed plug
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct Plug {
+    /// Name, without `_plug/` nor `.plug.js`
+    pub name: String,
+    /// File of the bundle in the space
+    pub file: String,
+    /// URL of the bundle with its version, relative to `/.plugs/`
+    pub url: String,
+    /// Changes with every update
+    pub version: String,
+    pub size: u64,
+    /// Modification time in milliseconds
+    pub last_modified: u64,
+}
+
+impl From<&FileMeta> for Plug {
+    fn from(meta: &FileMeta) -> Self {
+        let name = meta.name[PREFIX.len()..meta.name.len() - EXTENSION.len()].to_string();
+        let version = version(meta);
+
+        Self {
+            url: format!("{name}{EXTENSION}?v={version}"),
+            name,
+            file: meta.name.clone(),
+            version,
+            size: meta.size,
+            last_modified: meta.last_modified,
+        }
+    }
+}
+
+/// Version of a bundle, its etag when the backend keeps one.
+fn version(meta: &FileMeta) -> String {
+    match &meta.etag {
+        Some(etag) => etag.trim_start_matches("W/").trim_matches('"').to_string(),
+        None => format!("{:x}-{:x}", meta.last_modified, meta.size),
+    }
+}
+
+/// Whether a file of the space is a plug bundle, not in a subfolder of `_plug/`.
+fn is_plug(name: &str) -> bool {
+    name.strip_prefix(PREFIX)
+        .and_then(|name| name.strip_suffix(EXTENSION))
+        .is_some_and(|name| valid_name(name) && !name.contains('/'))
+}
+
+/// Names are letters, digits, `-`, `_` and `.`, not starting with a `.`.
+fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+pub(crate) fn router<S>(plugs: Plugs) -> Router<S>
+where
+    S: Provider + super::proxy::Provider + Clone + Send + Sync + 'static,
+{
+    Router::new()
+        .route("/", routing::get(list).post(install))
+        .route("/{file}", routing::get(bundle))
+        .layer(Extension(Arc::new(plugs)))
+}
+
+/// List the installed plugs, by name.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    get,
+    path = "/.plugs",
+    tag = "plugs",
+    responses((status = 200, description = "Installed plugs", body = Vec<Plug>)),
+))]
+#[cfg_attr(feature = "cloudflare", worker::send)]
+pub async fn list<F>(Filesystem(fs): Filesystem<F>) -> Result<Json<Vec<Plug>>, Error>
+where
+    F: ReadOnlyFilesystem,
+{
+    let mut plugs: Vec<Plug> = fs
+        .list()
+        .await?
+        .iter()
+        .filter(|meta| is_plug(&meta.name))
+        .map(Plug::from)
+        .collect();
+    plugs.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(Json(plugs))
+}
+
+/// Serve a plug bundle.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    get,
+    path = "/.plugs/{file}",
+    tag = "plugs",
+    params(
+        ("file" = String, Path, description = "Bundle, e.g. `git.plug.js`"),
+        ("v" = Option<String>, Query, description = "Version of the bundle, cached for good when current"),
+    ),
+    responses(
+        (status = 200, description = "Bundle", content_type = "application/javascript"),
+        (status = 304, description = "The bundle still matches `If-None-Match`"),
+        (status = 404, description = "No such plug"),
+    ),
+))]
+#[cfg_attr(feature = "cloudflare", worker::send)]
+pub async fn bundle<F>(
+    Filesystem(fs): Filesystem<F>,
+    Path(file): Path<String>,
+    uri: Uri,
+    headers: HeaderMap,
+) -> Result<Response, Error>
+where
+    F: ReadOnlyFilesystem,
+{
+    let path = format!("{PREFIX}{file}");
+    if !is_plug(&path) {
+        return Err(Error::not_found(format!("no plug {file}")));
+    }
+
+    let meta = fs.meta(&path).await?;
+    let version = version(&meta);
+    let etag = format!("\"{version}\"");
+    let asked = uri
+        .query()
+        .unwrap_or_default()
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("v="));
+    let cache_control = match asked == Some(version.as_str()) {
+        true => IMMUTABLE,
+        false => "no-cache",
+    };
+    let cached = [
+        (header::ETAG, etag.clone()),
+        (header::CACHE_CONTROL, cache_control.to_string()),
+    ];
+
+    if headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| matches(value, &etag))
+    {
+        return Ok((StatusCode::NOT_MODIFIED, cached).into_response());
+    }
+
+    let (stream, _) = fs.get(&path).await?;
+
+    Ok((
+        cached,
+        [(
+            header::CONTENT_TYPE,
+            "application/javascript; charset=utf-8",
+        )],
+        axum::body::Body::from_stream(stream),
+    )
+        .into_response())
+}
+
+/// Whether an `If-None-Match` lists the etag, compared weakly.
+fn matches(if_none_match: &str, etag: &str) -> bool {
+    if_none_match
+        .split(',')
+        .map(|tag| tag.trim().trim_start_matches("W/"))
+        .any(|tag| tag == "*" || tag == etag)
+}
+
+/// Plug to install
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Install {
+    /// URL of the bundle, under one of the allowed sources
+    pub url: String,
+    /// Name of the plug, the file name of the URL without `.plug.js` by default
+    pub name: Option<String>,
+}
+
+/// Install a plug, or update it if already installed.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    post,
+    path = "/.plugs",
+    tag = "plugs",
+    request_body = Install,
+    responses(
+        (status = 200, description = "Plug installed", body = Plug),
+        (status = 400, description = "No valid name for the plug"),
+        (status = 403, description = "URL not under an allowed source, or denied by the proxy policy, or read-only user"),
+        (status = 502, description = "Bundle not fetched"),
+    ),
+))]
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(name = "plug_install", skip_all, fields(url = %install.url))
+)]
+#[cfg_attr(feature = "cloudflare", worker::send)]
+pub async fn install<F, C>(
+    Extension(plugs): Extension<Arc<Plugs>>,
+    user: Option<Extension<client::User>>,
+    Filesystem(fs): Filesystem<F>,
+    State(Proxy(proxy)): State<Proxy<C>>,
+    Json(install): Json<Install>,
+) -> Result<Json<Plug>, Error>
+where
+    F: ReadWriteFilesystem,
+    C: Client,
+{
+    if user.is_some_and(|Extension(user)| user.read_only) {
+        return Err(Error::forbidden("read-only users can't install plugs"));
+    }
+
+    if !plugs.allows(&install.url) {
+        return Err(Error::forbidden(format!(
+            "{} is not under an allowed plug source",
+            install.url
+        )));
+    }
+
+    let name = match install.name {
+        Some(name) => name,
+        None => default_name(&install.url)
+            .ok_or_else(|| Error::BadRequest(format!("No plug name in {}", install.url).into()))?,
+    };
+    if !valid_name(&name) {
+        return Err(Error::BadRequest(
+            format!("Invalid plug name {name:?}").into(),
+        ));
+    }
+
+    let bundle = fetch(&proxy, &install.url).await?;
+
+    let meta = IncomingFileMeta {
+        content_type: Some("application/javascript".to_string()),
+        size: Some(bundle.len() as u64),
+        ..Default::default()
+    };
+    let data = stream::once(async move { Ok(bundle) }).into_boxed();
+    let meta = fs
+        .put(&format!("{PREFIX}{name}{EXTENSION}"), data, meta)
+        .await?;
+
+    #[cfg(feature = "tracing")]
+    tracing::info!(name, size = meta.size, "Plug installed");
+
+    Ok(Json(Plug::from(&meta)))
+}
+
+/// File name of a bundle URL without `.plug.js`, e.g. `git` for `…/git.plug.js?raw`.
+fn default_name(url: &str) -> Option<String> {
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    let file = path.rsplit('/').next()?;
+
+    file.strip_suffix(EXTENSION).map(str::to_string)
+}
+
+/// Fetch a bundle through the proxy, as the client would with `/.proxy/<url>`.
+async fn fetch<C>(proxy: &proxy::Proxy<C>, url: &str) -> Result<Bytes, Error>
+where
+    C: Client,
+{
+    let request = http::Request::builder()
+        .method(Method::GET)
+        .uri(format!("/.proxy/{url}"))
+        .body(Bytes::new())
+        .map_err(|err| Error::BadRequest(err.into()))?;
+    let host = proxy::upstream_host(request.uri());
+
+    let response = proxy
+        .proxy(request)
+        .await
+        .map_err(|err| upstream_error(err, host))?;
+
+    // The proxy answers 200, with the status of the upstream in a header
+    let status = response
+        .headers()
+        .get("x-proxy-status-code")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("200");
+    if status != "200" {
+        return Err(Error::Upstream(format!("{url} answered {status}").into()));
+    }
+
+    Ok(response.into_body())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::testing::MemoryFs;
+    use crate::server::Builder;
+    use crate::server::test::TestServer;
+    use axum::body::Body;
+
+    fn server() -> TestServer {
+        let fs = MemoryFs::new()
+            .with_file("_plug/git.plug.js", b"export default {}")
+            .with_file("_plug/old/nested.plug.js", b"")
+            .with_file("_plug/notes.md", b"");
+
+        TestServer::build(
+            Builder::new().plugs(Plugs::new().source("https://plugs.example.com/")),
+            client::Config::default(),
+            fs,
+        )
+    }
+
+    fn post(install: serde_json::Value) -> http::Request<Body> {
+        http::Request::post("/.plugs")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(install.to_string()))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn lists_plugs() {
+        let server = server();
+
+        let plugs: Vec<Plug> = server.get("/.plugs").await.json();
+        assert_eq!(plugs.len(), 1);
+        assert_eq!(plugs[0].name, "git");
+        assert_eq!(plugs[0].file, "_plug/git.plug.js");
+        assert_eq!(plugs[0].url, format!("git.plug.js?v={}", plugs[0].version));
+    }
+
+    #[tokio::test]
+    async fn caches_bundles() {
+        let server = server();
+        let plug = server.get("/.plugs").await.json::<Vec<Plug>>().remove(0);
+
+        let response = server.get("/.plugs/git.plug.js").await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.text(), "export default {}");
+        assert_eq!(
+            response.header("Content-Type"),
+            Some("application/javascript; charset=utf-8")
+        );
+        assert_eq!(response.header("Cache-Control"), Some("no-cache"));
+        let etag = response.header("ETag").unwrap().to_string();
+        assert_eq!(etag, format!("\"{}\"", plug.version));
+
+        let response = server.get(&format!("/.plugs/{}", plug.url)).await;
+        assert_eq!(response.header("Cache-Control"), Some(IMMUTABLE));
+
+        // An outdated version is only revalidated
+        let response = server.get("/.plugs/git.plug.js?v=0-0").await;
+        assert_eq!(response.header("Cache-Control"), Some("no-cache"));
+
+        let request = http::Request::get("/.plugs/git.plug.js")
+            .header(header::IF_NONE_MATCH, format!("W/{etag}"))
+            .body(Body::empty())
+            .unwrap();
+        let response = server.request(request).await;
+        assert_eq!(response.status, StatusCode::NOT_MODIFIED);
+        assert!(response.body.is_empty());
+
+        for missing in ["nested.plug.js", "notes.md", "missing.plug.js"] {
+            let response = server.get(&format!("/.plugs/{missing}")).await;
+            assert_eq!(response.status, StatusCode::NOT_FOUND, "{missing}");
+        }
+    }
+
+    #[tokio::test]
+    async fn checks_installs() {
+        let server = server();
+
+        for (install, status) in [
+            (
+                serde_json::json!({ "url": "https://evil.example.com/git.plug.js" }),
+                StatusCode::FORBIDDEN,
+            ),
+            (
+                serde_json::json!({ "url": "https://plugs.example.com/git.js" }),
+                StatusCode::BAD_REQUEST,
+            ),
+            (
+                serde_json::json!({ "url": "https://plugs.example.com/git.plug.js", "name": "../git" }),
+                StatusCode::BAD_REQUEST,
+            ),
+            // Installs go through the proxy, unavailable here
+            (
+                serde_json::json!({ "url": "https://plugs.example.com/git.plug.js" }),
+                StatusCode::NOT_IMPLEMENTED,
+            ),
+        ] {
+            let response = server.request(post(install.clone())).await;
+            assert_eq!(response.status, status, "{install}");
+        }
+
+        let mut request =
+            post(serde_json::json!({ "url": "https://plugs.example.com/git.plug.js" }));
+        request.extensions_mut().insert(client::User {
+            name: "guest".to_string(),
+            read_only: true,
+        });
+        assert_eq!(server.request(request).await.status, StatusCode::FORBIDDEN);
+    }
+
+    /// Upstream answering every request with `status` and `body`
+    struct Canned(StatusCode, &'static str);
+
+    #[async_trait::async_trait]
+    impl Client for Canned {
+        async fn send(
+            &self,
+            request: http::Request<Bytes>,
+        ) -> proxy::Result<http::Response<Bytes>> {
+            assert_eq!(
+                request.uri().to_string(),
+                "https://plugs.example.com/v2/git.plug.js?raw=1"
+            );
+
+            Ok(http::Response::builder()
+                .status(self.0)
+                .body(Bytes::from_static(self.1.as_bytes()))?)
+        }
+    }
+
+    #[tokio::test]
+    async fn fetches_through_the_proxy() {
+        let url = "https://plugs.example.com/v2/git.plug.js?raw=1";
+        assert_eq!(default_name(url).as_deref(), Some("git"));
+
+        let proxy = proxy::Proxy::new(Canned(StatusCode::OK, "export {}"));
+        assert_eq!(fetch(&proxy, url).await.unwrap(), "export {}");
+
+        let proxy = proxy::Proxy::new(Canned(StatusCode::NOT_FOUND, ""));
+        assert!(matches!(fetch(&proxy, url).await, Err(Error::Upstream(_))));
+
+        let policy = proxy::Policy::new().allow_host("other.example.com");
+        let proxy = proxy::Proxy::new(Canned(StatusCode::OK, "")).policy(policy);
+        assert!(matches!(fetch(&proxy, url).await, Err(Error::Forbidden(_))));
+    }
+}
@@ -1,15 +1,28 @@
 use axum::{
-    extract::{FromRef, Request, State},
+    extract::{FromRef, FromRequestParts as _, Request, State},
     response::{IntoResponse, Response},
 };
+use axum_client_ip::ClientIp;
+use http::{Method, StatusCode, header};
 use http_body_util::BodyExt;
 
 use crate::proxy::{self, Client};
+use crate::server::error::{Error, Upstream};
+
+#[cfg(all(not(target_arch = "wasm32"), feature = "websocket"))]
+mod websocket;
 
 pub trait Provider {
     type Output: Client + Send + Sync;
 
     fn provide(&self) -> Self::Output;
+
+    /// Build the proxy used for a request.
+    ///
+    /// Override to configure the proxy, eg. with a [`proxy::Policy`].
+    fn proxy(&self) -> proxy::Proxy<Self::Output> {
+        proxy::Proxy::new(self.provide())
+    }
 }
 
 pub struct Proxy<C>(pub proxy::Proxy<C>);
@@ -19,38 +32,69 @@ where
     S: Provider + Send + Sync,
 {
     fn from_ref(state: &S) -> Self {
-        Proxy(proxy::Proxy::new(state.provide()))
+        Proxy(state.proxy())
     }
 }
 
+#[cfg_attr(feature = "openapi", utoipa::path(
+    get,
+    path = "/.proxy/{url}",
+    tag = "proxy",
+    params(("url" = String, Path, description = "Upstream URL without its scheme, e.g. `example.com/feed.xml`")),
+    responses(
+        (status = 200, description = "Upstream response, for the methods allowed by the policy, GET, POST, PUT, DELETE and HEAD by default"),
+        (status = 204, description = "OPTIONS, answered without forwarding with the methods allowed"),
+        (status = 403, description = "Upstream or method denied by the proxy policy"),
+        (status = 502, description = "Upstream unreachable"),
+    ),
+))]
 #[cfg_attr(feature = "cloudflare", worker::send)]
 pub async fn proxy<C>(
     State(Proxy(proxy)): State<Proxy<C>>,
     request: Request,
-) -> Result<impl IntoResponse, Response>
+) -> Result<Response, Error>
 where
     C: Client,
 {
+    // Preflights never reach the upstream, nor OPTIONS unless the policy forwards it
+    if request.method() == Method::OPTIONS
+        && (request
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_REQUEST_METHOD)
+            || proxy
+                .allowed_methods()
+                .is_some_and(|methods| !methods.contains(&Method::OPTIONS)))
+    {
+        return Ok(options(proxy.allowed_methods()));
+    }
+
+    #[cfg(all(not(target_arch = "wasm32"), feature = "websocket"))]
+    if proxy::websocket::is_upgrade(request.headers()) {
+        return websocket::upgrade(proxy, request).await;
+    }
+
+    let host = proxy::upstream_host(request.uri());
+
     // Collect body to Bytes
-    let (parts, body) = request.into_parts();
+    let (mut parts, body) = request.into_parts();
+
+    // For X-Forwarded-For, with the ClientIpSource of the server
+    if let Ok(ClientIp(ip)) = ClientIp::from_request_parts(&mut parts, &()).await {
+        parts.extensions.insert(proxy::ClientAddr(ip));
+    }
+
     let body_bytes = body
         .collect()
         .await
-        .map_err(|_| http::StatusCode::BAD_REQUEST.into_response())?
+        .map_err(|err| Error::BadRequest(err.into()))?
         .to_bytes();
     let request_with_bytes = http::Request::from_parts(parts, body_bytes);
 
     // Send through proxy
-    let response = proxy.proxy(request_with_bytes).await.map_err(|e| {
-        #[cfg(feature = "tracing")]
-        tracing::error!("Proxy request failed: {}", e);
-
-        // Check if it's a NotSupported error
-        match e {
-            proxy::Error::NotSupported(_) => http::StatusCode::NOT_IMPLEMENTED.into_response(),
-            _ => http::StatusCode::BAD_GATEWAY.into_response(),
-        }
-    })?;
+    let response = proxy
+        .proxy(request_with_bytes)
+        .await
+        .map_err(|err| upstream_error(err, host))?;
 
     // Convert Response<Bytes> to Response<Body> for axum
     let (parts, body_bytes) = response.into_parts();
@@ -59,3 +103,109 @@ where
         axum::body::Body::from(body_bytes),
     ))
 }
+
+/// Methods listed for a policy forwarding every method
+const ANY_METHOD: [Method; 7] = [
+    Method::GET,
+    Method::HEAD,
+    Method::POST,
+    Method::PUT,
+    Method::PATCH,
+    Method::DELETE,
+    Method::TRACE,
+];
+
+/// Answer to OPTIONS, with the methods forwarded.
+///
+/// The CORS middleware adds the origin headers for allowed origins, and answers their
+/// preflights before they get here.
+fn options(methods: Option<&[Method]>) -> Response {
+    let mut allow: Vec<&str> = methods
+        .unwrap_or(&ANY_METHOD)
+        .iter()
+        .map(Method::as_str)
+        .collect();
+
+    if !allow.contains(&"OPTIONS") {
+        allow.push("OPTIONS");
+    }
+
+    let allow = allow.join(", ");
+
+    (
+        StatusCode::NO_CONTENT,
+        [
+            (header::ALLOW, allow.clone()),
+            (header::ACCESS_CONTROL_ALLOW_METHODS, allow),
+        ],
+    )
+        .into_response()
+}
+
+/// Error response of a failed proxy request, with its [`Upstream`] when it failed.
+///
+/// Only the message is kept, proxy errors aren't `Send` on wasm.
+pub(super) fn upstream_error(e: proxy::Error, host: Option<String>) -> Error {
+    #[cfg(feature = "tracing")]
+    tracing::error!(host = host.as_deref(), category = ?e.category(), "Proxy request failed: {}", e);
+
+    let message: Box<dyn std::error::Error + Send + Sync> = match (host, e.category()) {
+        (Some(host), Some(category)) => Box::new(Upstream {
+            host,
+            category,
+            message: e.to_string(),
+        }),
+        _ => e.to_string().into(),
+    };
+
+    match e {
+        proxy::Error::InvalidUrl(_) => Error::BadRequest(message),
+        proxy::Error::NotSupported(_) => Error::NotImplemented(message),
+        proxy::Error::Forbidden(_) => Error::Forbidden(message),
+        proxy::Error::Timeout(_) => Error::UpstreamTimeout(message),
+        proxy::Error::CircuitOpen(_) => Error::Unavailable(message),
+        _ => Error::Upstream(message),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::test::TestServer;
+    use axum::body::Body;
+
+    #[tokio::test]
+    async fn answers_options() {
+        let server = TestServer::new();
+
+        let response = server
+            .send(Method::OPTIONS, "/.proxy/example.com/api", Body::empty())
+            .await;
+        assert_eq!(response.status, StatusCode::NO_CONTENT);
+        assert_eq!(
+            response.header("Allow"),
+            Some("GET, POST, PUT, DELETE, HEAD, OPTIONS")
+        );
+
+        let preflight = http::Request::options("/.proxy/example.com/api")
+            .header("Access-Control-Request-Method", "PUT")
+            .body(Body::empty())
+            .unwrap();
+        let response = server.request(preflight).await;
+        assert_eq!(response.status, StatusCode::NO_CONTENT);
+        assert_eq!(
+            response.header("Access-Control-Allow-Methods"),
+            Some("GET, POST, PUT, DELETE, HEAD, OPTIONS")
+        );
+    }
+
+    #[tokio::test]
+    async fn denies_other_methods() {
+        let response = TestServer::new()
+            .send(Method::TRACE, "/.proxy/example.com/api", Body::empty())
+            .await;
+
+        assert_eq!(response.status, StatusCode::FORBIDDEN);
+        assert!(response.text().contains("method TRACE is not allowed"));
+    }
+}
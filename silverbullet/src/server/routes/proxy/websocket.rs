@@ -0,0 +1,184 @@
+use axum::extract::ws::{self, WebSocket, WebSocketUpgrade};
+use axum::extract::{FromRequestParts, Request};
+use axum::response::Response;
+use futures::{SinkExt, StreamExt, future};
+use tokio_tungstenite::tungstenite;
+
+use super::upstream_error;
+use crate::proxy::{self, websocket::Upstream};
+use crate::server::error::Error;
+
+/// Connect to the upstream and tunnel messages between it and the client.
+pub(super) async fn upgrade<C>(
+    proxy: proxy::Proxy<C>,
+    request: Request,
+) -> Result<Response, Error> {
+    let host = proxy::upstream_host(request.uri());
+    let (mut parts, _) = request.into_parts();
+
+    let upgrade = WebSocketUpgrade::from_request_parts(&mut parts, &())
+        .await
+        .map_err(|err| Error::BadRequest(err.into()))?;
+
+    let (upstream, protocol) = proxy
+        .connect_websocket(&parts)
+        .await
+        .map_err(|err| upstream_error(err, host))?;
+
+    let upgrade = match protocol.as_ref().and_then(|p| p.to_str().ok()) {
+        Some(protocol) => upgrade.protocols([protocol.to_string()]),
+        None => upgrade,
+    };
+
+    Ok(upgrade.on_upgrade(move |socket| tunnel(socket, upstream)))
+}
+
+async fn tunnel(client: WebSocket, upstream: Upstream) {
+    let (mut client_tx, mut client_rx) = client.split();
+    let (mut upstream_tx, mut upstream_rx) = upstream.split();
+
+    let to_upstream = async {
+        while let Some(Ok(message)) = client_rx.next().await {
+            if upstream_tx.send(to_upstream(message)).await.is_err() {
+                break;
+            }
+        }
+
+        let _ = upstream_tx.close().await;
+    };
+
+    let to_client = async {
+        while let Some(Ok(message)) = upstream_rx.next().await {
+            let Some(message) = to_client(message) else {
+                continue;
+            };
+
+            if client_tx.send(message).await.is_err() {
+                break;
+            }
+        }
+
+        let _ = client_tx.close().await;
+    };
+
+    // Once either side is done the other one is dropped, closing its connection
+    future::select(Box::pin(to_upstream), Box::pin(to_client)).await;
+}
+
+fn to_upstream(message: ws::Message) -> tungstenite::Message {
+    match message {
+        ws::Message::Text(text) => tungstenite::Message::text(text.as_str()),
+        ws::Message::Binary(data) => tungstenite::Message::Binary(data),
+        ws::Message::Ping(data) => tungstenite::Message::Ping(data),
+        ws::Message::Pong(data) => tungstenite::Message::Pong(data),
+        ws::Message::Close(frame) => {
+            tungstenite::Message::Close(frame.map(|frame| tungstenite::protocol::CloseFrame {
+                code: frame.code.into(),
+                reason: frame.reason.as_str().into(),
+            }))
+        }
+    }
+}
+
+fn to_client(message: tungstenite::Message) -> Option<ws::Message> {
+    Some(match message {
+        tungstenite::Message::Text(text) => ws::Message::text(text.as_str()),
+        tungstenite::Message::Binary(data) => ws::Message::Binary(data),
+        tungstenite::Message::Ping(data) => ws::Message::Ping(data),
+        tungstenite::Message::Pong(data) => ws::Message::Pong(data),
+        tungstenite::Message::Close(frame) => {
+            ws::Message::Close(frame.map(|frame| ws::CloseFrame {
+                code: frame.code.into(),
+                reason: frame.reason.as_str().into(),
+            }))
+        }
+        tungstenite::Message::Frame(_) => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{Router, routing};
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::proxy::{NoProxy, Policy};
+    use crate::server::routes::proxy::Provider;
+
+    #[derive(Clone)]
+    struct State(Policy);
+
+    impl Provider for State {
+        type Output = NoProxy;
+
+        fn provide(&self) -> Self::Output {
+            NoProxy
+        }
+
+        fn proxy(&self) -> proxy::Proxy<Self::Output> {
+            proxy::Proxy::new(NoProxy).policy(self.0.clone())
+        }
+    }
+
+    async fn echo_server() -> std::net::SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(socket).await.unwrap();
+
+            while let Some(Ok(message)) = ws.next().await {
+                if message.is_text() && ws.send(message).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        addr
+    }
+
+    async fn proxy_server(policy: Policy) -> std::net::SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let app = Router::new()
+            .route("/.proxy/{*url}", routing::any(super::super::proxy))
+            .with_state(State(policy));
+
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        addr
+    }
+
+    #[tokio::test]
+    async fn tunnels_messages() {
+        let upstream = echo_server().await;
+        let proxy = proxy_server(Policy::default()).await;
+
+        let (mut ws, _) =
+            tokio_tungstenite::connect_async(format!("ws://{proxy}/.proxy/{upstream}/socket"))
+                .await
+                .unwrap();
+
+        ws.send(tungstenite::Message::text("hello")).await.unwrap();
+
+        let reply = ws.next().await.unwrap().unwrap();
+        assert_eq!(reply.into_text().unwrap().as_str(), "hello");
+    }
+
+    #[tokio::test]
+    async fn rejects_denied_targets() {
+        let upstream = echo_server().await;
+        let proxy = proxy_server(Policy::new().allow_host("example.com")).await;
+
+        let result =
+            tokio_tungstenite::connect_async(format!("ws://{proxy}/.proxy/{upstream}/socket"))
+                .await;
+
+        match result {
+            Err(tungstenite::Error::Http(response)) => assert_eq!(response.status(), 403),
+            other => panic!("expected HTTP error, got {other:?}"),
+        }
+    }
+}
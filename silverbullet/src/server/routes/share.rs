@@ -0,0 +1,832 @@
+//! Public read-only links to pages and folders, at `/share/{token}`
+//!
+//! Enable with [`Builder::shares`](crate::server::Builder::shares), which also needs
+//! [`Builder::signed_urls`](crate::server::Builder::signed_urls). Users with write access
+//! mint a share of a page, e.g. `Projects/Launch`, or of a folder, e.g. `Projects/`, and
+//! anyone with its link reads it without credentials, until it expires or is revoked.
+//!
+//! | Route | Action |
+//! |---|---|
+//! | `GET /.shares` | List the shares |
+//! | `POST /.shares` | Share a page or folder, with a [`NewShare`] |
+//! | `DELETE /.shares/{token}` | Revoke a share |
+//! | `GET /share/{token}` | The shared page, or the pages of the shared folder |
+//! | `GET /share/{token}/{page}` | A page of the shared folder |
+//!
+//! Shared pages are rendered by the `share.html` template (see [`ssr`](crate::ssr)), on
+//! their own: embedded pages aren't included, so nothing outside the share shows. The
+//! files they embed, e.g. `![diagram](diagram.png)`, are linked with signed URLs, valid
+//! for [`Shares::attachment_ttl`].
+//!
+//! Shares are kept in a `.silverbullet-shares.json` file of their store, so revoking one
+//! takes effect on the next request.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::{
+    Extension, Json, Router,
+    body::Body,
+    extract::{FromRef, Path, Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    routing,
+};
+use bytes::Bytes;
+use futures::{TryStreamExt as _, lock::Mutex, stream};
+use http::{StatusCode, header};
+use serde::{Deserialize, Serialize};
+
+use super::fs::{Filesystem, Provider};
+use crate::client;
+use crate::fs::{self, IncomingFileMeta, ReadOnlyFilesystem, ReadWriteFilesystem, StreamExt, time};
+use crate::percent;
+use crate::server::error::Error;
+use crate::server::signed::Signer;
+use crate::ssr::{Attachment, Page, Renderer, Shared};
+
+/// File of the shares in their store
+const FILE: &str = ".silverbullet-shares.json";
+
+const IMAGES: [&str; 7] = ["png", "jpg", "jpeg", "gif", "webp", "svg", "avif"];
+
+/// Shares of a space, kept in a store
+#[derive(Clone)]
+pub struct Shares {
+    store: Arc<dyn ReadWriteFilesystem>,
+    renderer: Arc<dyn Renderer>,
+    attachment_ttl: Duration,
+    /// Held while the file is rewritten, so concurrent changes aren't lost
+    lock: Arc<Mutex<()>>,
+}
+
+/// Page or folder readable by the holders of its token
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct Share {
+    pub token: String,
+    /// Name of the page, or of the folder ending with `/`
+    pub path: String,
+    /// Time the share was made, in milliseconds since the epoch
+    pub created: u64,
+    /// Time the share expires, in milliseconds since the epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires: Option<u64>,
+}
+
+impl Share {
+    fn folder(&self) -> Option<&str> {
+        self.path.strip_suffix('/')
+    }
+
+    /// Whether the share shows `page`.
+    fn shows(&self, page: &str) -> bool {
+        match self.folder() {
+            Some(folder) => page.strip_prefix(folder).is_some_and(|rest| {
+                rest.strip_prefix('/')
+                    .is_some_and(|rest| !rest.is_empty() && valid(rest))
+            }),
+            None => page == self.path,
+        }
+    }
+}
+
+/// Share to make
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct NewShare {
+    /// Name of the page, e.g. `Projects/Launch`, or of the folder ending with `/`, e.g.
+    /// `Projects/`
+    pub path: String,
+    /// Seconds until the share expires, never by default
+    #[serde(default)]
+    pub ttl: Option<u64>,
+}
+
+/// Share with its public URL
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Link {
+    #[serde(flatten)]
+    pub share: Share,
+    /// URL of the share, relative to the server
+    pub url: String,
+}
+
+impl Shares {
+    /// Shares kept in `store`, rendered with `renderer`.
+    pub fn new(store: Arc<dyn ReadWriteFilesystem>, renderer: impl Renderer + 'static) -> Self {
+        Self {
+            store,
+            renderer: Arc::new(renderer),
+            attachment_ttl: Duration::from_secs(60 * 60),
+            lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    /// How long the URLs of the files of a shared page are valid (an hour by default).
+    #[must_use]
+    pub fn attachment_ttl(mut self, ttl: Duration) -> Self {
+        self.attachment_ttl = ttl;
+        self
+    }
+
+    pub async fn list(&self) -> fs::Result<Vec<Share>> {
+        let (stream, _) = match self.store.get(FILE).await {
+            Ok(file) => file,
+            Err(fs::Error::NotFound(_)) => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+
+        let bytes = stream
+            .try_fold(Vec::new(), |mut acc, chunk| async move {
+                acc.extend_from_slice(&chunk);
+                Ok(acc)
+            })
+            .await?;
+
+        serde_json::from_slice(&bytes).map_err(|err| fs::Error::Other(err.into()))
+    }
+
+    /// Share of `token`, unless it expired at `now`.
+    pub async fn find(&self, token: &str, now: u64) -> fs::Result<Option<Share>> {
+        Ok(self.list().await?.into_iter().find(|share| {
+            crate::server::auth::constant_time_eq(share.token.as_bytes(), token.as_bytes())
+                && share.expires.is_none_or(|expires| expires > now)
+        }))
+    }
+
+    /// Share `path` as the new share, with a token from `signer`.
+    pub async fn add(
+        &self,
+        path: &str,
+        ttl: Option<Duration>,
+        signer: &Signer,
+    ) -> fs::Result<Share> {
+        let _guard = self.lock.lock().await;
+        let mut shares = self.list().await?;
+
+        let now = time::now();
+        let share = Share {
+            token: signer.token(path),
+            path: path.to_string(),
+            created: now,
+            expires: ttl.map(|ttl| now + ttl.as_millis() as u64),
+        };
+
+        // Expired shares are dropped on the next change
+        shares.retain(|share| share.expires.is_none_or(|expires| expires > now));
+        shares.push(share.clone());
+        self.write(&shares).await?;
+
+        Ok(share)
+    }
+
+    /// Revoke the share of `token`, `false` if there's none.
+    pub async fn revoke(&self, token: &str) -> fs::Result<bool> {
+        let _guard = self.lock.lock().await;
+        let mut shares = self.list().await?;
+
+        let count = shares.len();
+        shares.retain(|share| share.token != token);
+        if shares.len() == count {
+            return Ok(false);
+        }
+
+        self.write(&shares).await?;
+
+        Ok(true)
+    }
+
+    async fn write(&self, shares: &[Share]) -> fs::Result<()> {
+        let json = serde_json::to_vec_pretty(shares).map_err(|err| fs::Error::Other(err.into()))?;
+        let meta = IncomingFileMeta {
+            content_type: Some("application/json".to_string()),
+            size: Some(json.len() as u64),
+            ..Default::default()
+        };
+        let data = stream::once(async move { Ok(Bytes::from(json)) }).into_boxed();
+
+        self.store.put(FILE, data, meta).await?;
+
+        Ok(())
+    }
+}
+
+/// Server settings used by the routes
+#[derive(Clone)]
+pub struct Context {
+    signer: Signer,
+    base_path: Arc<str>,
+}
+
+/// Routes minting and revoking shares, under `/.shares`.
+pub(crate) fn api<S>(shares: Shares, signer: Signer, base_path: &str) -> Router<S>
+where
+    S: Provider + Clone + Send + Sync + 'static,
+{
+    Router::new()
+        .route("/", routing::get(list).post(add))
+        .route("/{token}", routing::delete(revoke))
+        .route_layer(axum::middleware::from_fn(require_writer))
+        .layer(Extension(Arc::new(shares)))
+        .layer(Extension(Context {
+            signer,
+            base_path: base_path.into(),
+        }))
+}
+
+/// Public routes of the shares, under `/share`.
+pub(crate) fn public<S>(shares: Shares, signer: Signer, base_path: &str) -> Router<S>
+where
+    S: Provider + Clone + Send + Sync + 'static,
+    client::Config: FromRef<S>,
+{
+    Router::new()
+        .route("/{token}", routing::get(root))
+        .route("/{token}/{*page}", routing::get(page))
+        .layer(Extension(Arc::new(shares)))
+        .layer(Extension(Context {
+            signer,
+            base_path: base_path.into(),
+        }))
+}
+
+/// Reject read-only users.
+async fn require_writer(
+    user: Option<Extension<client::User>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    match user {
+        Some(Extension(user)) if user.read_only => {
+            Error::forbidden("read-only users can't share pages").into_response()
+        }
+        _ => next.run(request).await,
+    }
+}
+
+#[cfg_attr(feature = "openapi", utoipa::path(
+    get,
+    path = "/.shares",
+    tag = "shares",
+    responses((status = 200, description = "Shares of the space", body = [Link])),
+))]
+#[cfg_attr(feature = "cloudflare", worker::send)]
+pub async fn list(
+    Extension(shares): Extension<Arc<Shares>>,
+    Extension(context): Extension<Context>,
+) -> Result<Json<Vec<Link>>, Error> {
+    let links = shares
+        .list()
+        .await?
+        .into_iter()
+        .map(|share| link(share, &context.base_path))
+        .collect();
+
+    Ok(Json(links))
+}
+
+#[cfg_attr(feature = "openapi", utoipa::path(
+    post,
+    path = "/.shares",
+    tag = "shares",
+    request_body = NewShare,
+    responses(
+        (status = 201, description = "Share made", body = Link),
+        (status = 400, description = "Invalid page or folder name"),
+        (status = 404, description = "No such page or folder"),
+    ),
+))]
+#[cfg_attr(feature = "tracing", tracing::instrument(name = "share_add", skip_all))]
+#[cfg_attr(feature = "cloudflare", worker::send)]
+pub async fn add<F>(
+    Extension(shares): Extension<Arc<Shares>>,
+    Extension(context): Extension<Context>,
+    Filesystem(fs): Filesystem<F>,
+    Json(new): Json<NewShare>,
+) -> Result<Response, Error>
+where
+    F: ReadOnlyFilesystem,
+{
+    let path = new.path.trim_start_matches('/');
+    let name = path.trim_end_matches('/');
+    if name.is_empty() || !valid(name) {
+        return Err(Error::BadRequest(
+            format!("Invalid page or folder name {:?}", new.path).into(),
+        ));
+    }
+
+    let path = match path.ends_with('/') {
+        true => {
+            let prefix = format!("{name}/");
+            if !fs
+                .list()
+                .await?
+                .iter()
+                .any(|meta| meta.name.starts_with(&prefix))
+            {
+                return Err(Error::not_found(format!("no folder named {name}")));
+            }
+            prefix
+        }
+        false => {
+            fs.meta(&format!("{name}.md")).await?;
+            name.to_string()
+        }
+    };
+
+    let share = shares
+        .add(&path, new.ttl.map(Duration::from_secs), &context.signer)
+        .await?;
+
+    #[cfg(feature = "tracing")]
+    tracing::info!(path = share.path, "Shared");
+
+    Ok((StatusCode::CREATED, Json(link(share, &context.base_path))).into_response())
+}
+
+#[cfg_attr(feature = "openapi", utoipa::path(
+    delete,
+    path = "/.shares/{token}",
+    tag = "shares",
+    params(("token" = String, Path, description = "Token of the share")),
+    responses(
+        (status = 204, description = "Share revoked"),
+        (status = 404, description = "No such share"),
+    ),
+))]
+#[cfg_attr(feature = "cloudflare", worker::send)]
+pub async fn revoke(
+    Extension(shares): Extension<Arc<Shares>>,
+    Path(token): Path<String>,
+) -> Result<StatusCode, Error> {
+    match shares.revoke(&token).await? {
+        true => Ok(StatusCode::NO_CONTENT),
+        false => Err(Error::not_found("no such share")),
+    }
+}
+
+/// Open a share.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    get,
+    path = "/share/{token}",
+    tag = "shares",
+    params(("token" = String, Path, description = "Token of the share")),
+    responses(
+        (status = 200, description = "The shared page, or the pages of the shared folder", content_type = "text/html"),
+        (status = 404, description = "No such share, or expired"),
+    ),
+))]
+#[cfg_attr(feature = "cloudflare", worker::send)]
+pub async fn root<F>(
+    Extension(shares): Extension<Arc<Shares>>,
+    Extension(context): Extension<Context>,
+    State(config): State<client::Config>,
+    Filesystem(fs): Filesystem<F>,
+    Path(token): Path<String>,
+) -> Result<Response, Error>
+where
+    F: ReadOnlyFilesystem,
+{
+    let share = find(&shares, &token).await?;
+    let page = share.folder().map_or(share.path.as_str(), |folder| folder);
+
+    open(&shares, &context, config, &fs, &share, page).await
+}
+
+/// Open a page of a shared folder.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    get,
+    path = "/share/{token}/{page}",
+    tag = "shares",
+    params(
+        ("token" = String, Path, description = "Token of the share"),
+        ("page" = String, Path, description = "Name of the page, e.g. `Projects/Launch`"),
+    ),
+    responses(
+        (status = 200, description = "The page", content_type = "text/html"),
+        (status = 404, description = "No such share, expired, or not showing the page"),
+    ),
+))]
+#[cfg_attr(feature = "cloudflare", worker::send)]
+pub async fn page<F>(
+    Extension(shares): Extension<Arc<Shares>>,
+    Extension(context): Extension<Context>,
+    State(config): State<client::Config>,
+    Filesystem(fs): Filesystem<F>,
+    Path((token, page)): Path<(String, String)>,
+) -> Result<Response, Error>
+where
+    F: ReadOnlyFilesystem,
+{
+    let share = find(&shares, &token).await?;
+
+    open(&shares, &context, config, &fs, &share, &page).await
+}
+
+async fn find(shares: &Shares, token: &str) -> Result<Share, Error> {
+    shares
+        .find(token, time::now())
+        .await?
+        .ok_or_else(|| Error::not_found("no such share"))
+}
+
+/// Render `page` of a share, the pages of the folder for the folder itself.
+async fn open<F>(
+    shares: &Shares,
+    context: &Context,
+    config: client::Config,
+    fs: &F,
+    share: &Share,
+    page: &str,
+) -> Result<Response, Error>
+where
+    F: ReadOnlyFilesystem,
+{
+    let root = share.folder().unwrap_or(&share.path);
+    let url_prefix = format!("{}/share/{}", context.base_path, share.token);
+    // The nav leads to the root of the share
+    let config = client::Config {
+        index_page: root.to_string(),
+        ..config.for_user(None)
+    };
+
+    if share.folder() == Some(page) {
+        let pages: Vec<String> = fs
+            .list()
+            .await?
+            .into_iter()
+            .filter_map(|meta| meta.name.strip_suffix(".md").map(str::to_string))
+            .filter(|page| share.shows(page))
+            .collect();
+
+        let view = Shared {
+            config: &config,
+            url_prefix: &url_prefix,
+            page: None,
+            folder: root,
+            pages: &pages,
+            attachments: &[],
+        };
+        return render(shares, view).await;
+    }
+
+    if !share.shows(page) {
+        return Err(Error::not_found("the share doesn't show this page"));
+    }
+
+    let (stream, _) = fs.get(&format!("{page}.md")).await?;
+    let bytes = stream
+        .try_fold(Vec::new(), |mut acc, chunk| async move {
+            acc.extend_from_slice(&chunk);
+            Ok(acc)
+        })
+        .await
+        .map_err(Error::internal)?;
+
+    let (content, attachments) = attachments(page, &String::from_utf8_lossy(&bytes), |path| {
+        format!(
+            "{}{}",
+            context.base_path,
+            context.signer.url(path, shares.attachment_ttl)
+        )
+    });
+
+    let view = Shared {
+        config: &config,
+        url_prefix: &url_prefix,
+        page: Some(Page {
+            name: page,
+            content: &content,
+        }),
+        folder: share.folder().unwrap_or_default(),
+        pages: &[],
+        attachments: &attachments,
+    };
+
+    render(shares, view).await
+}
+
+async fn render(shares: &Shares, view: Shared<'_>) -> Result<Response, Error> {
+    let body = view.render_stream(shares.renderer.as_ref()).await?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/html; charset=utf-8"),
+            // Revoked shares shouldn't linger in caches
+            (header::CACHE_CONTROL, "no-store"),
+            (header::HeaderName::from_static("x-robots-tag"), "noindex"),
+        ],
+        Body::from_stream(body),
+    )
+        .into_response())
+}
+
+fn link(share: Share, base_path: &str) -> Link {
+    Link {
+        url: format!("{base_path}/share/{}", share.token),
+        share,
+    }
+}
+
+/// Checks a page or folder name stays in the space.
+fn valid(name: &str) -> bool {
+    !name.chars().any(char::is_control)
+        && name
+            .split('/')
+            .all(|segment| !segment.trim().is_empty() && !segment.starts_with('.'))
+}
+
+/// Content of `page` with the files it embeds linked with `sign`, and those files.
+///
+/// Markdown images link files relative to the folder of the page, or to the space with a
+/// leading `/`, e.g. `![](/diagram.png)`, wiki links to the space, e.g. `![[diagram.png]]`.
+/// Embedded pages, URLs and paths leaving the space are left alone.
+fn attachments(
+    page: &str,
+    content: &str,
+    sign: impl Fn(&str) -> String,
+) -> (String, Vec<Attachment>) {
+    let folder = page.rsplit_once('/').map(|(folder, _)| folder);
+    let mut found: Vec<Attachment> = Vec::new();
+    let mut out = String::with_capacity(content.len());
+    let mut rest = content;
+
+    while let Some(at) = rest.find("![") {
+        out.push_str(&rest[..at]);
+        rest = &rest[at..];
+
+        let embed = match rest.strip_prefix("![[") {
+            Some(inner) => inner.find("]]").map(|end| {
+                let target = inner[..end].split('|').next().unwrap_or_default();
+                (
+                    3,
+                    3 + target.len(),
+                    target.trim().to_string(),
+                    percent::encode(target.trim()),
+                )
+            }),
+            None => markdown_image(rest).map(|(start, end)| {
+                let target = rest[start..end].trim_matches(['<', '>']);
+                let path = match (target.strip_prefix('/'), folder) {
+                    (Some(path), _) => path.to_string(),
+                    (None, Some(folder)) => format!("{}/{target}", percent::encode(folder)),
+                    (None, None) => target.to_string(),
+                };
+                (start, end, target.to_string(), path)
+            }),
+        };
+
+        match embed {
+            Some((start, end, name, path)) if is_file(&path) => {
+                let url = sign(&path);
+                out.push_str(&rest[..start]);
+                out.push_str(&url);
+                rest = &rest[end..];
+
+                if !found.iter().any(|attachment| attachment.url == url) {
+                    let extension = name.rsplit_once('.').map(|(_, extension)| extension);
+                    found.push(Attachment {
+                        image: extension.is_some_and(|extension| {
+                            IMAGES.contains(&extension.to_ascii_lowercase().as_str())
+                        }),
+                        name,
+                        url,
+                    });
+                }
+            }
+            _ => {
+                out.push_str("![");
+                rest = &rest[2..];
+            }
+        }
+    }
+    out.push_str(rest);
+
+    (out, found)
+}
+
+/// Span of the target of the `![alt](target "title")` at the start of `text`.
+fn markdown_image(text: &str) -> Option<(usize, usize)> {
+    let close = text.find("](")?;
+    if text[..close].contains('\n') {
+        return None;
+    }
+
+    let start = close + 2;
+    let len = text[start..].find([')', ' ', '\n'])?;
+    let end = start + len;
+
+    (text[end..].starts_with([')', ' ']) && len > 0).then_some((start, end))
+}
+
+/// Whether a link is a file of the space, rather than a page or a URL.
+fn is_file(path: &str) -> bool {
+    let name = path.rsplit('/').next().unwrap_or(path);
+
+    !path.contains(':')
+        && !path
+            .split('/')
+            .any(|segment| segment == ".." || segment == ".")
+        && name
+            .rsplit_once('.')
+            .is_some_and(|(stem, extension)| !stem.is_empty() && extension != "md")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::testing::MemoryFs;
+    use crate::server::Builder;
+    use crate::server::auth::Basic;
+    use crate::server::test::TestServer;
+    use crate::ssr::Jinja;
+    use http::Method;
+
+    const CREDENTIALS: &str = "Basic YWRtaW46czNjcmV0"; // admin:s3cret
+
+    fn server() -> TestServer {
+        let space = MemoryFs::new()
+            .with_file(
+                "Projects/Launch.md",
+                b"# Launch\n![diagram](diagram.png)\n![[Private]]",
+            )
+            .with_file("Projects/diagram.png", b"png")
+            .with_file("Projects/Plan.md", b"# Plan")
+            .with_file("Private.md", b"# Private");
+        let shares = Shares::new(Arc::new(MemoryFs::new()), Jinja::new());
+
+        TestServer::build(
+            Builder::new()
+                .auth(Basic::new("admin", "s3cret"))
+                .signed_urls(Signer::new("key"))
+                .shares(shares),
+            client::Config::default(),
+            space,
+        )
+    }
+
+    async fn call(
+        server: &TestServer,
+        method: Method,
+        uri: &str,
+        body: &str,
+    ) -> crate::server::test::TestResponse {
+        let request = http::Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::AUTHORIZATION, CREDENTIALS)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+
+        server.request(request).await
+    }
+
+    async fn share(server: &TestServer, path: &str) -> Link {
+        let response = call(
+            server,
+            Method::POST,
+            "/.shares",
+            &format!(r#"{{"path":"{path}"}}"#),
+        )
+        .await;
+        assert_eq!(response.status, StatusCode::CREATED, "{}", response.text());
+
+        response.json()
+    }
+
+    #[test]
+    fn links_attachments() {
+        let (content, attachments) = attachments(
+            "Projects/Launch",
+            "![a](diagram.png) ![b](/logo.svg \"Logo\") ![[Files/Report 1.pdf|report]]\n\
+             ![[Other page]] ![c](https://example.com/x.png) ![d](../secret.png) ![e](diagram.png)",
+            |path| format!("<{path}>"),
+        );
+
+        assert_eq!(
+            content,
+            "![a](<Projects/diagram.png>) ![b](<logo.svg> \"Logo\") \
+             ![[<Files/Report%201.pdf>|report]]\n![[Other page]] \
+             ![c](https://example.com/x.png) ![d](../secret.png) ![e](<Projects/diagram.png>)"
+        );
+        assert_eq!(
+            attachments
+                .iter()
+                .map(|attachment| (attachment.name.as_str(), attachment.image))
+                .collect::<Vec<_>>(),
+            [
+                ("diagram.png", true),
+                ("/logo.svg", true),
+                ("Files/Report 1.pdf", false)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn shares_pages() {
+        let server = server();
+        let link = share(&server, "Projects/Launch").await;
+        assert_eq!(link.url, format!("/share/{}", link.share.token));
+        assert_eq!(link.share.path, "Projects/Launch");
+
+        // Without credentials
+        let response = server.get(&link.url).await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.header("Cache-Control"), Some("no-store"));
+        let html = response.text();
+        assert!(html.contains("<h1>Projects&#x2f;Launch</h1>"));
+        assert!(!html.contains("# Private"));
+
+        // The embedded file is readable through its signed URL only
+        let start = html.find("<img src=\"").unwrap() + 10;
+        let url = html[start..]
+            .split('"')
+            .next()
+            .unwrap()
+            .replace("&#x2f;", "/")
+            .replace("&amp;", "&");
+        assert!(url.starts_with("/.fs/Projects/diagram.png?exp="));
+        assert_eq!(server.get(&url).await.text(), "png");
+        assert_eq!(
+            server.get("/.fs/Projects/diagram.png").await.status,
+            StatusCode::UNAUTHORIZED
+        );
+
+        // Only the shared page
+        let response = server.get(&format!("{}/Private", link.url)).await;
+        assert_eq!(response.status, StatusCode::NOT_FOUND);
+
+        let response = call(&server, Method::GET, "/.shares", "").await;
+        assert_eq!(response.json::<Vec<Link>>(), std::slice::from_ref(&link));
+
+        let uri = format!("/.shares/{}", link.share.token);
+        assert_eq!(
+            call(&server, Method::DELETE, &uri, "").await.status,
+            StatusCode::NO_CONTENT
+        );
+        assert_eq!(
+            call(&server, Method::DELETE, &uri, "").await.status,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(server.get(&link.url).await.status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn shares_folders() {
+        let server = server();
+        let link = share(&server, "Projects/").await;
+
+        let response = server.get(&link.url).await;
+        assert_eq!(response.status, StatusCode::OK);
+        let html = response.text();
+        let prefix = format!("/share/{}", link.share.token);
+        assert!(html.contains(&format!(
+            r#"<a href="{prefix}/Projects/Plan">Projects&#x2f;Plan</a>"#
+        )));
+        assert!(html.contains(&format!(r#"<a href="{prefix}/Projects/Launch">"#)));
+        assert!(!html.contains("Private"));
+
+        assert_eq!(
+            server.get(&format!("{prefix}/Projects/Plan")).await.status,
+            StatusCode::OK
+        );
+        assert_eq!(
+            server.get(&format!("{prefix}/Projects")).await.status,
+            StatusCode::OK
+        );
+        for page in ["Private", "Projects/../Private", "Projects/.hidden"] {
+            let response = server.get(&format!("{prefix}/{page}")).await;
+            assert_eq!(response.status, StatusCode::NOT_FOUND, "{page}");
+        }
+        assert_eq!(
+            server.get("/share/guessed").await.status,
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[tokio::test]
+    async fn checks_new_shares() {
+        let server = server();
+
+        for (path, status) in [
+            ("Missing", StatusCode::NOT_FOUND),
+            ("Missing/", StatusCode::NOT_FOUND),
+            ("../Private", StatusCode::BAD_REQUEST),
+            ("", StatusCode::BAD_REQUEST),
+        ] {
+            let body = format!(r#"{{"path":"{path}"}}"#);
+            let response = call(&server, Method::POST, "/.shares", &body).await;
+            assert_eq!(response.status, status, "{path}");
+        }
+
+        // Minting needs credentials
+        let response = server
+            .send(Method::POST, "/.shares", r#"{"path":"Private"}"#)
+            .await;
+        assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    }
+}
@@ -1,38 +1,94 @@
 use axum::{
     Json,
-    extract::{FromRef, State},
-    response::IntoResponse,
+    extract::FromRequestParts,
+    response::{
+        IntoResponse, Response as HttpResponse,
+        sse::{Event, Sse},
+    },
 };
-use http::StatusCode;
+use futures::{Stream, StreamExt};
+use http::request::Parts;
 
+use crate::server::error::Error;
 use crate::shell::{self, Request, Response};
 
+/// Provides the shell used for a request.
+///
+/// Implementations can inspect the request (eg. the authenticated user) to scope the shell
+/// or return [`Error::forbidden`] to deny shell access altogether.
 pub trait Provider {
     type Output: shell::Shell + Send + Sync;
 
-    fn provide(&self) -> Self::Output;
+    fn provide(&self, parts: &mut Parts) -> Result<Self::Output, Error>;
 }
 
 pub struct Shell<S>(pub S);
 
-impl<S> FromRef<S> for Shell<S::Output>
+impl<S> FromRequestParts<S> for Shell<S::Output>
 where
     S: Provider + Send + Sync,
 {
-    fn from_ref(state: &S) -> Self {
-        Shell(state.provide())
+    type Rejection = HttpResponse;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Shell(
+            state.provide(parts).map_err(|err| err.into_response())?,
+        ))
     }
 }
 
+#[cfg_attr(feature = "openapi", utoipa::path(
+    post,
+    path = "/.shell",
+    tag = "shell",
+    request_body = Request,
+    responses(
+        (status = 200, description = "Command output", body = Response),
+        (status = 403, description = "Shell access denied"),
+    ),
+))]
+#[cfg_attr(feature = "cloudflare", worker::send)]
 pub async fn shell<S>(
-    State(Shell(shell)): State<Shell<S>>,
+    Shell(shell): Shell<S>,
     Json(request): Json<Request>,
-) -> Result<Json<Response>, impl IntoResponse>
+) -> Result<Json<Response>, Error>
 where
     S: shell::Shell,
 {
-    shell
-        .exec(request)
-        .map(Json::from)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())
+    Ok(Json(shell.exec(request).await?))
+}
+
+/// Run a command and stream its output as server-sent events.
+///
+/// Each event carries a JSON encoded [`shell::Event`] and is named after its type
+/// (`stdout`, `stderr` or `exit`). The exit event is always the last one.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    post,
+    path = "/.shell/stream",
+    tag = "shell",
+    request_body = Request,
+    responses(
+        (status = 200, description = "Server-sent events, each with a JSON encoded event", content_type = "text/event-stream", body = shell::Event),
+        (status = 403, description = "Shell access denied"),
+    ),
+))]
+#[cfg_attr(feature = "cloudflare", worker::send)]
+pub async fn stream<S>(
+    Shell(shell): Shell<S>,
+    Json(request): Json<Request>,
+) -> Result<Sse<impl Stream<Item = Result<Event, axum::Error>>>, Error>
+where
+    S: shell::Shell,
+{
+    let events = shell.exec_stream(request).await?;
+
+    Ok(Sse::new(events.map(|event| {
+        let name = match event {
+            shell::Event::Stdout(_) => "stdout",
+            shell::Event::Stderr(_) => "stderr",
+            shell::Event::Exit(_) => "exit",
+        };
+
+        Event::default().event(name).json_data(event)
+    })))
 }
@@ -0,0 +1,79 @@
+use axum::{Json, extract::State};
+use http::Uri;
+
+use crate::server::error::Error;
+use crate::stats::{Day, Stats};
+
+/// Daily totals of the space, see [`Builder::stats`](crate::server::Builder::stats).
+#[cfg_attr(feature = "openapi", utoipa::path(
+    get,
+    path = "/.stats",
+    tag = "stats",
+    params(("days" = Option<usize>, Query, description = "Only the last days, today included")),
+    responses(
+        (status = 200, description = "Totals of the recorded days, oldest first, and of today", body = [Day]),
+        (status = 400, description = "Invalid number of days"),
+    ),
+))]
+#[cfg_attr(feature = "tracing", tracing::instrument(name = "stats", skip_all))]
+#[cfg_attr(feature = "cloudflare", worker::send)]
+pub async fn history(State(stats): State<Stats>, uri: Uri) -> Result<Json<Vec<Day>>, Error> {
+    let mut days = stats.history().await?;
+
+    for pair in uri.query().unwrap_or_default().split('&') {
+        if let Some(("days", value)) = pair.split_once('=') {
+            let last: usize = value
+                .parse()
+                .map_err(|_| Error::BadRequest(format!("invalid days: {value}").into()))?;
+            days.drain(..days.len().saturating_sub(last));
+        }
+    }
+
+    Ok(Json(days))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::client;
+    use crate::fs::testing::MemoryFs;
+    use crate::server::Builder;
+    use crate::server::test::TestServer;
+    use http::StatusCode;
+
+    #[tokio::test]
+    async fn serves_history() {
+        let space = Arc::new(
+            MemoryFs::new()
+                .with_file(
+                    ".stats.json",
+                    br#"[{"date":"2026-01-01","pages":1,"attachments":0,"bytes":4,"writes":2}]"#,
+                )
+                .with_file("index.md", b"# Home")
+                .with_file("photo.png", b"png"),
+        );
+        let server = TestServer::build(
+            Builder::new().stats(Stats::new(space)),
+            client::Config::default(),
+            MemoryFs::new(),
+        );
+
+        let response = server.get("/.stats").await;
+        assert_eq!(response.status, StatusCode::OK);
+        let days: Vec<serde_json::Value> = response.json();
+        assert_eq!(days.len(), 2);
+        assert_eq!(days[0]["date"], "2026-01-01");
+        assert_eq!(days[1]["pages"], 1);
+        assert_eq!(days[1]["attachments"], 1);
+        assert_eq!(days[1]["bytes"], 9);
+
+        let days: Vec<serde_json::Value> = server.get("/.stats?days=1").await.json();
+        assert_eq!(days.len(), 1);
+        assert_eq!(days[0]["bytes"], 9);
+
+        let response = server.get("/.stats?days=all").await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,35 @@
+use axum::{Extension, Json, extract::State};
+
+use crate::client;
+use crate::server::error::Error;
+use crate::sync::git::{self, GitSync, Report};
+
+/// Sync the space with its git remote now, see
+/// [`Builder::git_sync`](crate::server::Builder::git_sync).
+#[cfg_attr(feature = "openapi", utoipa::path(
+    post,
+    path = "/.sync/git",
+    tag = "sync",
+    responses(
+        (status = 200, description = "Space synced", body = Report),
+        (status = 403, description = "Read-only user"),
+        (status = 409, description = "Conflicting changes left unmerged"),
+    ),
+))]
+#[cfg_attr(feature = "tracing", tracing::instrument(name = "git_sync", skip_all))]
+#[cfg_attr(feature = "cloudflare", worker::send)]
+pub async fn trigger(
+    State(sync): State<GitSync>,
+    user: Option<Extension<client::User>>,
+) -> Result<Json<Report>, Error> {
+    if user.is_some_and(|Extension(user)| user.read_only) {
+        return Err(Error::forbidden("read-only users can't sync the space"));
+    }
+
+    match sync.sync().await {
+        Ok(report) => Ok(Json(report)),
+        Err(git::Error::Fs(err)) => Err(err.into()),
+        Err(err @ git::Error::Conflict(_)) => Err(Error::Conflict(err.into())),
+        Err(err) => Err(Error::internal(err)),
+    }
+}
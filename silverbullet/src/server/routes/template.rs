@@ -0,0 +1,205 @@
+//! Pages created from templates by opening them, under `/.template`
+//!
+//! Enable with [`Builder::templates`](crate::server::Builder::templates). Opening
+//! `/.template/daily` or `/.template/daily/2024-06-01` creates the page of the `daily`
+//! [`Template`](crate::templates::Template) unless it exists, and redirects to it, so a
+//! bookmark always opens the page of the day. Requests accepting `application/json`, e.g.
+//! of automations, get the [`Instance`] instead.
+//!
+//! Pages are written with the filesystem of the [`Templates`], so also for read-only
+//! users.
+
+use std::sync::Arc;
+
+use axum::{
+    Extension, Json, Router,
+    extract::Path,
+    response::{IntoResponse, Response},
+    routing,
+};
+use http::{HeaderMap, StatusCode, header};
+
+use crate::percent;
+use crate::server::error::Error;
+use crate::templates::{self, Instance, Templates};
+
+/// Base path of the pages the route redirects to
+#[derive(Clone)]
+pub struct BasePath(Arc<str>);
+
+pub(crate) fn router<S>(templates: Templates, base_path: &str) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    Router::new()
+        .route("/{name}", routing::get(today))
+        .route("/{name}/{*title}", routing::get(page))
+        .layer(Extension(Arc::new(templates)))
+        .layer(Extension(BasePath(base_path.into())))
+}
+
+/// Open the page of a template for today.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    get,
+    path = "/.template/{name}",
+    tag = "templates",
+    params(("name" = String, Path, description = "Name of the template, e.g. `daily`")),
+    responses(
+        (status = 303, description = "Redirect to the page"),
+        (status = 201, description = "Page created, when accepting JSON", body = Instance),
+        (status = 200, description = "Page already there, when accepting JSON", body = Instance),
+        (status = 404, description = "No such template"),
+    ),
+))]
+#[cfg_attr(feature = "cloudflare", worker::send)]
+pub async fn today(
+    Extension(templates): Extension<Arc<Templates>>,
+    Extension(BasePath(base_path)): Extension<BasePath>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, Error> {
+    let instance = templates.instantiate(&name, None).await.map_err(error)?;
+
+    Ok(respond(&instance, &base_path, &headers))
+}
+
+/// Open the page of a template for a title, e.g. a date.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    get,
+    path = "/.template/{name}/{title}",
+    tag = "templates",
+    params(
+        ("name" = String, Path, description = "Name of the template, e.g. `daily`"),
+        ("title" = String, Path, description = "Title of the page, e.g. `2024-06-01`"),
+    ),
+    responses(
+        (status = 303, description = "Redirect to the page"),
+        (status = 201, description = "Page created, when accepting JSON", body = Instance),
+        (status = 200, description = "Page already there, when accepting JSON", body = Instance),
+        (status = 400, description = "Invalid title"),
+        (status = 404, description = "No such template"),
+    ),
+))]
+#[cfg_attr(feature = "cloudflare", worker::send)]
+pub async fn page(
+    Extension(templates): Extension<Arc<Templates>>,
+    Extension(BasePath(base_path)): Extension<BasePath>,
+    Path((name, title)): Path<(String, String)>,
+    headers: HeaderMap,
+) -> Result<Response, Error> {
+    let instance = templates
+        .instantiate(&name, Some(&title))
+        .await
+        .map_err(error)?;
+
+    Ok(respond(&instance, &base_path, &headers))
+}
+
+fn error(err: templates::Error) -> Error {
+    match err {
+        templates::Error::Unknown(_) => Error::not_found(err),
+        templates::Error::InvalidTitle { .. } => Error::BadRequest(err.into()),
+        templates::Error::Fs(err) => err.into(),
+    }
+}
+
+fn respond(instance: &Instance, base_path: &str, headers: &HeaderMap) -> Response {
+    let json = headers
+        .get(header::ACCEPT)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|accept| accept.contains("application/json"));
+
+    if json {
+        let status = match instance.created {
+            true => StatusCode::CREATED,
+            false => StatusCode::OK,
+        };
+        return (status, Json(instance.clone())).into_response();
+    }
+
+    let location = format!("{base_path}/{}", percent::encode(&instance.page));
+    (StatusCode::SEE_OTHER, [(header::LOCATION, location)]).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client;
+    use crate::fs::ReadWriteFilesystem;
+    use crate::fs::testing::MemoryFs;
+    use crate::server::Builder;
+    use crate::server::test::TestServer;
+    use crate::templates::Template;
+    use axum::body::Body;
+
+    /// Server with templates writing to the returned space, rather than the one of the
+    /// server, as they would for read-only users
+    fn server() -> (TestServer, Arc<dyn ReadWriteFilesystem>) {
+        let fs: Arc<dyn ReadWriteFilesystem> =
+            Arc::new(MemoryFs::new().with_file("Templates/Daily.md", b"# {date}\n"));
+        let templates = Templates::new(fs.clone())
+            .template("daily", Template::new("Journal/{date}", "Templates/Daily"))
+            .template(
+                "meeting",
+                Template::new("Meetings/{title}", "Templates/Meeting"),
+            );
+
+        let server = TestServer::build(
+            Builder::new().templates(templates),
+            client::Config::default(),
+            MemoryFs::new(),
+        );
+
+        (server, fs)
+    }
+
+    #[tokio::test]
+    async fn redirects_to_pages() {
+        let (server, fs) = server();
+
+        let response = server.get("/.template/daily/2024-06-01").await;
+        assert_eq!(response.status, StatusCode::SEE_OTHER);
+        assert_eq!(response.header("Location"), Some("/Journal/2024-06-01"));
+        assert!(fs.meta("Journal/2024-06-01.md").await.is_ok());
+
+        let response = server.get("/.template/meeting/Weekly%20sync").await;
+        assert_eq!(response.header("Location"), Some("/Meetings/Weekly%20sync"));
+
+        let response = server.get("/.template/daily").await;
+        assert_eq!(response.status, StatusCode::SEE_OTHER);
+        assert!(
+            response
+                .header("Location")
+                .unwrap()
+                .starts_with("/Journal/20")
+        );
+    }
+
+    #[tokio::test]
+    async fn answers_automations() {
+        let (server, _) = server();
+        let request = |uri: &str| {
+            http::Request::get(uri)
+                .header(header::ACCEPT, "application/json")
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let response = server.request(request("/.template/daily/2024-06-01")).await;
+        assert_eq!(response.status, StatusCode::CREATED);
+        let response = server.request(request("/.template/daily/2024-06-01")).await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(
+            response.json::<Instance>(),
+            Instance {
+                page: "Journal/2024-06-01".to_string(),
+                created: false,
+            }
+        );
+
+        let response = server.request(request("/.template/weekly")).await;
+        assert_eq!(response.status, StatusCode::NOT_FOUND);
+        let response = server.request(request("/.template/meeting/.hidden")).await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    }
+}
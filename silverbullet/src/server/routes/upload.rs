@@ -0,0 +1,289 @@
+//! Uploads of large files straight to the storage, at `POST /.uploads/{path}`
+//!
+//! Enable with [`Builder::uploads`](crate::server::Builder::uploads). Instead of putting a
+//! file to `/.fs/{path}` through the server, clients post its [`NewUpload`] size and put
+//! it to the presigned URL of the [`Upload`], e.g. of an S3 or R2 bucket, before it
+//! expires. Files under [`Uploads::min_size`] are put to `/.fs` as usual.
+//!
+//! The storage must be the one behind the space, where the file shows once uploaded,
+//! with the times and content type the storage keeps. The name goes to the storage as
+//! is, so the hooks, case insensitivity and name normalization of the space don't apply.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::{Extension, Json, Router, routing};
+use http::header;
+use serde::{Deserialize, Serialize};
+
+use super::fs::FilePath;
+use crate::client;
+use crate::fs::perm::{Perm, PermissionResolver};
+use crate::fs::{opendal, time};
+use crate::server::error::Error;
+
+/// Settings of the direct uploads
+#[derive(Clone)]
+pub struct Uploads {
+    storage: Arc<opendal::Filesystem>,
+    min_size: u64,
+    ttl: Duration,
+    permissions: Option<Arc<dyn PermissionResolver>>,
+}
+
+impl Uploads {
+    /// Uploads presigned by `storage`, the storage of the space.
+    pub fn new(storage: opendal::Filesystem) -> Self {
+        Self {
+            storage: Arc::new(storage),
+            min_size: 64 * 1024 * 1024,
+            ttl: Duration::from_secs(60 * 60),
+            permissions: None,
+        }
+    }
+
+    /// Bytes from which files are uploaded straight to the storage (64 MiB by default).
+    #[must_use]
+    pub fn min_size(mut self, bytes: u64) -> Self {
+        self.min_size = bytes;
+        self
+    }
+
+    /// How long the presigned URLs are valid (an hour by default).
+    #[must_use]
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Deny uploads of the files `resolver` makes read-only, as the space does.
+    #[must_use]
+    pub fn permissions(mut self, resolver: impl PermissionResolver + 'static) -> Self {
+        self.permissions = Some(Arc::new(resolver));
+        self
+    }
+}
+
+/// File to upload
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct NewUpload {
+    /// Size of the file in bytes
+    pub size: u64,
+    /// Content type the file is stored with, sent as the `Content-Type` of the upload
+    #[serde(default)]
+    pub content_type: Option<String>,
+}
+
+/// Request uploading the file straight to the storage
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Upload {
+    /// Method of the request, e.g. `PUT`
+    pub method: String,
+    /// Presigned URL of the storage
+    pub url: String,
+    /// Headers to send with the file
+    pub headers: BTreeMap<String, String>,
+    /// Time the URL expires, in milliseconds since the epoch
+    pub expires: u64,
+}
+
+pub(crate) fn router<S>(uploads: Uploads) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    Router::new()
+        .route("/{*path}", routing::post(presign))
+        .layer(Extension(Arc::new(uploads)))
+}
+
+/// Presign the upload of a large file to the storage.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    post,
+    path = "/.uploads/{path}",
+    tag = "uploads",
+    params(("path" = String, Path, description = "File name, e.g. `Media/talk.mp4`")),
+    request_body = NewUpload,
+    responses(
+        (status = 200, description = "Request to upload the file with", body = Upload),
+        (status = 400, description = "Invalid name, or a file small enough for `/.fs`"),
+        (status = 403, description = "Read-only user or file"),
+        (status = 501, description = "The storage can't presign uploads"),
+    ),
+))]
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(name = "upload_presign", skip_all)
+)]
+#[cfg_attr(feature = "cloudflare", worker::send)]
+pub async fn presign(
+    Extension(uploads): Extension<Arc<Uploads>>,
+    user: Option<Extension<client::User>>,
+    FilePath(path): FilePath,
+    Json(upload): Json<NewUpload>,
+) -> Result<Json<Upload>, Error> {
+    if user.is_some_and(|Extension(user)| user.read_only) {
+        return Err(Error::forbidden("read-only users can't upload files"));
+    }
+
+    if let Some(permissions) = &uploads.permissions
+        && permissions.resolve(&path, Perm::ReadWrite) == Perm::Read
+    {
+        return Err(Error::forbidden(format!("{path} is read-only")));
+    }
+
+    if upload.size < uploads.min_size {
+        return Err(Error::BadRequest(
+            format!(
+                "Files under {} bytes are put to /.fs/{path}",
+                uploads.min_size
+            )
+            .into(),
+        ));
+    }
+
+    let expires = time::now() + uploads.ttl.as_millis() as u64;
+    let request = uploads
+        .storage
+        .presign_put(&path, uploads.ttl)
+        .await?
+        .ok_or_else(|| Error::NotImplemented("The storage can't presign uploads".into()))?;
+
+    let mut headers: BTreeMap<_, _> = request
+        .header()
+        .iter()
+        .filter(|(name, _)| *name != header::HOST)
+        .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+        .collect();
+
+    if let Some(content_type) = upload.content_type {
+        headers.insert(header::CONTENT_TYPE.to_string(), content_type);
+    }
+
+    #[cfg(feature = "tracing")]
+    tracing::info!(path, size = upload.size, "Presigned upload");
+
+    Ok(Json(Upload {
+        method: request.method().to_string(),
+        url: request.uri().to_string(),
+        headers,
+        expires,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::perm::Rules;
+    use crate::fs::testing::MemoryFs;
+    use crate::server::Builder;
+    use crate::server::auth::Basic;
+    use crate::server::test::{TestResponse, TestServer};
+    use ::opendal::Operator;
+    use ::opendal::services::{Memory, S3};
+    use axum::body::Body;
+    use http::StatusCode;
+
+    const ADMIN: &str = "Basic YWRtaW46czNjcmV0"; // admin:s3cret
+    const READER: &str = "Basic cmVhZGVyOnMzY3JldA=="; // reader:s3cret
+
+    fn s3() -> opendal::Filesystem {
+        let s3 = S3::default()
+            .bucket("space")
+            .region("us-east-1")
+            .endpoint("http://127.0.0.1:1")
+            .access_key_id("key")
+            .secret_access_key("secret")
+            .disable_config_load()
+            .disable_ec2_metadata();
+
+        opendal::Filesystem::new(Operator::new(s3).unwrap().finish())
+    }
+
+    fn server(uploads: Uploads, auth: Basic) -> TestServer {
+        TestServer::build(
+            Builder::new().auth(auth).uploads(uploads),
+            client::Config::default(),
+            MemoryFs::new(),
+        )
+    }
+
+    async fn post(server: &TestServer, credentials: &str, uri: &str, body: &str) -> TestResponse {
+        let request = http::Request::builder()
+            .method(http::Method::POST)
+            .uri(uri)
+            .header(header::AUTHORIZATION, credentials)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+
+        server.request(request).await
+    }
+
+    #[tokio::test]
+    async fn presigns_large_uploads() {
+        let uploads = Uploads::new(s3())
+            .min_size(1000)
+            .ttl(Duration::from_secs(60));
+        let server = server(uploads, Basic::new("admin", "s3cret"));
+
+        let response = post(
+            &server,
+            ADMIN,
+            "/.uploads/Media/talk.mp4",
+            r#"{"size":5000,"contentType":"video/mp4"}"#,
+        )
+        .await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+
+        let upload: Upload = response.json();
+        assert_eq!(upload.method, "PUT");
+        assert!(
+            upload
+                .url
+                .starts_with("http://127.0.0.1:1/space/Media/talk.mp4?")
+        );
+        assert!(upload.url.contains("X-Amz-Expires=60"));
+        assert_eq!(upload.headers["content-type"], "video/mp4");
+        assert!(upload.expires > time::now());
+
+        // Small files go through the server
+        let response = post(&server, ADMIN, "/.uploads/Media/clip.mp4", r#"{"size":10}"#).await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn rejects_uploads() {
+        let uploads = Uploads::new(s3())
+            .min_size(0)
+            .permissions(Rules::new().read_only("Library/**"));
+        let server = server(uploads, Basic::new("admin", "s3cret"));
+
+        for (uri, status) in [
+            ("/.uploads/Library/big.zip", StatusCode::FORBIDDEN),
+            ("/.uploads/../big.zip", StatusCode::BAD_REQUEST),
+        ] {
+            let response = post(&server, ADMIN, uri, r#"{"size":1}"#).await;
+            assert_eq!(response.status, status, "{uri}");
+        }
+
+        let server = self::server(
+            Uploads::new(s3()).min_size(0),
+            Basic::new("reader", "s3cret").read_only(true),
+        );
+        let response = post(&server, READER, "/.uploads/big.zip", r#"{"size":1}"#).await;
+        assert_eq!(response.status, StatusCode::FORBIDDEN);
+
+        // Nothing to presign with
+        let memory = opendal::Filesystem::new(Operator::new(Memory::default()).unwrap().finish());
+        let server = self::server(
+            Uploads::new(memory).min_size(0),
+            Basic::new("admin", "s3cret"),
+        );
+        let response = post(&server, ADMIN, "/.uploads/big.zip", r#"{"size":1}"#).await;
+        assert_eq!(response.status, StatusCode::NOT_IMPLEMENTED);
+    }
+}
@@ -0,0 +1,202 @@
+//! Security headers for the pages served to browsers
+//!
+//! Enable with [`Builder::security_headers`](crate::server::Builder::security_headers).
+//! Every response gets `X-Content-Type-Options: nosniff`, HTML responses, the client shell
+//! and the boot page, also get a `Content-Security-Policy` and a `Referrer-Policy`.
+//! Headers set by a handler are kept.
+//!
+//! The default policy fits the SilverBullet client: plugs run from `blob:` workers and
+//! space scripts are evaluated, so scripts may be inline and use `eval`; images and media
+//! load from anywhere, while objects, base URIs, forms and framing are limited to the
+//! server itself.
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use http::{HeaderMap, HeaderValue, header};
+
+/// Policy the client works with, without `frame-ancestors`
+pub const DEFAULT_CSP: &str = "default-src 'self'; \
+    script-src 'self' 'unsafe-inline' 'unsafe-eval' blob:; \
+    style-src 'self' 'unsafe-inline'; \
+    img-src * data: blob:; \
+    media-src * data: blob:; \
+    font-src 'self' data:; \
+    connect-src 'self'; \
+    worker-src 'self' blob:; \
+    frame-src *; \
+    object-src 'none'; \
+    base-uri 'self'; \
+    form-action 'self'";
+
+/// Security header settings
+#[derive(Debug, Clone)]
+pub struct SecurityHeaders {
+    content_security_policy: Option<String>,
+    frame_ancestors: Vec<String>,
+    referrer_policy: Option<String>,
+}
+
+impl Default for SecurityHeaders {
+    fn default() -> Self {
+        Self {
+            content_security_policy: Some(DEFAULT_CSP.to_string()),
+            frame_ancestors: vec!["'self'".to_string()],
+            referrer_policy: Some("same-origin".to_string()),
+        }
+    }
+}
+
+impl SecurityHeaders {
+    /// The default policy, see the [module docs](self).
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replace the `Content-Security-Policy`, `None` leaves it out.
+    #[must_use]
+    pub fn content_security_policy(mut self, policy: Option<String>) -> Self {
+        self.content_security_policy = policy;
+        self
+    }
+
+    /// Sources allowed to frame the pages (`'self'` by default), empty for none.
+    #[must_use]
+    pub fn frame_ancestors(mut self, sources: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.frame_ancestors = sources.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Replace the `Referrer-Policy` (`same-origin` by default), `None` leaves it out.
+    #[must_use]
+    pub fn referrer_policy(mut self, policy: Option<String>) -> Self {
+        self.referrer_policy = policy;
+        self
+    }
+
+    /// Full `Content-Security-Policy`, with the `frame-ancestors` directive.
+    fn policy(&self) -> Option<String> {
+        let ancestors = match self.frame_ancestors.as_slice() {
+            [] => "frame-ancestors 'none'".to_string(),
+            sources => format!("frame-ancestors {}", sources.join(" ")),
+        };
+
+        match self.content_security_policy.as_deref().map(str::trim) {
+            Some("") | None => None,
+            Some(policy) => Some(format!("{}; {ancestors}", policy.trim_end_matches(';'))),
+        }
+    }
+
+    fn apply(&self, headers: &mut HeaderMap) {
+        headers
+            .entry(header::X_CONTENT_TYPE_OPTIONS)
+            .or_insert(HeaderValue::from_static("nosniff"));
+
+        let html = headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.starts_with("text/html"));
+
+        if !html {
+            return;
+        }
+
+        if let Some(policy) = self.policy().and_then(|p| HeaderValue::try_from(p).ok()) {
+            headers
+                .entry(header::CONTENT_SECURITY_POLICY)
+                .or_insert(policy);
+        }
+
+        if let Some(policy) = self
+            .referrer_policy
+            .as_deref()
+            .and_then(|p| HeaderValue::try_from(p).ok())
+        {
+            headers.entry(header::REFERRER_POLICY).or_insert(policy);
+        }
+    }
+}
+
+/// Middleware adding the security headers to responses.
+///
+/// Use with `axum::middleware::from_fn_with_state(Arc::new(headers), security::middleware)`.
+pub async fn middleware(
+    State(headers): State<Arc<SecurityHeaders>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let mut response = next.run(request).await;
+
+    headers.apply(response.headers_mut());
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(content_type: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CONTENT_TYPE, content_type.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn secures_html_responses() {
+        let mut html = headers("text/html; charset=utf-8");
+        SecurityHeaders::new().apply(&mut html);
+
+        assert_eq!(html[header::X_CONTENT_TYPE_OPTIONS], "nosniff");
+        assert_eq!(html[header::REFERRER_POLICY], "same-origin");
+
+        let policy = html[header::CONTENT_SECURITY_POLICY].to_str().unwrap();
+        assert!(policy.starts_with("default-src 'self'; "));
+        assert!(policy.contains("worker-src 'self' blob:"));
+        assert!(policy.ends_with("; frame-ancestors 'self'"));
+
+        let mut json = headers("application/json");
+        SecurityHeaders::new().apply(&mut json);
+
+        assert_eq!(json[header::X_CONTENT_TYPE_OPTIONS], "nosniff");
+        assert!(!json.contains_key(header::CONTENT_SECURITY_POLICY));
+        assert!(!json.contains_key(header::REFERRER_POLICY));
+    }
+
+    #[test]
+    fn applies_settings() {
+        let security = SecurityHeaders::new()
+            .content_security_policy(Some("default-src 'none';".to_string()))
+            .frame_ancestors(Vec::<String>::new())
+            .referrer_policy(None);
+
+        let mut html = headers("text/html");
+        security.apply(&mut html);
+
+        assert_eq!(
+            html[header::CONTENT_SECURITY_POLICY],
+            "default-src 'none'; frame-ancestors 'none'"
+        );
+        assert!(!html.contains_key(header::REFERRER_POLICY));
+
+        // Handlers can set their own
+        let mut html = headers("text/html");
+        html.insert(header::CONTENT_SECURITY_POLICY, "sandbox".parse().unwrap());
+        SecurityHeaders::new()
+            .frame_ancestors(["https://wiki.example.com"])
+            .apply(&mut html);
+
+        assert_eq!(html[header::CONTENT_SECURITY_POLICY], "sandbox");
+
+        let mut html = headers("text/html");
+        SecurityHeaders::new()
+            .content_security_policy(None)
+            .apply(&mut html);
+
+        assert!(!html.contains_key(header::CONTENT_SECURITY_POLICY));
+    }
+}
@@ -0,0 +1,201 @@
+//! Time-limited signed URLs for files
+//!
+//! Enable with [`Builder::signed_urls`](crate::server::Builder::signed_urls). A URL made
+//! by [`Signer::url`], `/.fs/{path}?exp=…&sig=…`, reads the file without credentials
+//! until it expires, so pages rendered for the public can embed images of a protected
+//! space. The signature is an HMAC-SHA256 of the path and expiry time, and is only valid
+//! for `GET` and `HEAD` requests of that path. Requests with an invalid or expired
+//! signature are rejected before authentication.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use base64::Engine as _;
+use hmac::{Hmac, Mac};
+use http::Method;
+use sha2::Sha256;
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[cfg(target_arch = "wasm32")]
+use web_time::{SystemTime, UNIX_EPOCH};
+
+use crate::server::error::Error;
+
+const PREFIX: &str = "/.fs/";
+
+/// Marks requests let through by a valid signature, checked by the auth middleware
+#[derive(Debug, Clone, Copy)]
+pub struct Signed;
+
+/// Signs and verifies file URLs with a secret key
+#[derive(Clone)]
+pub struct Signer {
+    key: Arc<[u8]>,
+}
+
+impl Signer {
+    pub fn new(key: impl AsRef<[u8]>) -> Self {
+        Self {
+            key: key.as_ref().into(),
+        }
+    }
+
+    /// URL of a file valid for `ttl`, relative to the base path of the server.
+    ///
+    /// `path` is the file name as it appears in URLs, percent-encoded if needed.
+    pub fn url(&self, path: &str, ttl: Duration) -> String {
+        let path = path.trim_start_matches('/');
+        let expires = now() + ttl.as_secs();
+
+        format!(
+            "{PREFIX}{path}?exp={expires}&sig={}",
+            self.signature(path, expires)
+        )
+    }
+
+    /// Unguessable token, e.g. of a share: a signature of `context`, the time and a
+    /// per-process counter, so only the holder of the key could make it.
+    #[cfg(feature = "ssr")]
+    pub(crate) fn token(&self, context: &str) -> String {
+        use std::sync::atomic::{AtomicU64, Ordering};
+
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_nanos());
+        let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.key).expect("HMAC takes any key");
+        mac.update(format!("token\n{context}\n{nanos}\n{count}").as_bytes());
+
+        // 192 bits, 32 characters
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(&mac.finalize().into_bytes()[..24])
+    }
+
+    fn mac(&self, path: &str, expires: u64) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.key).expect("HMAC takes any key");
+        mac.update(format!("{path}\n{expires}").as_bytes());
+        mac
+    }
+
+    fn signature(&self, path: &str, expires: u64) -> String {
+        let signature = self.mac(path, expires).finalize().into_bytes();
+
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(signature)
+    }
+
+    fn verify(&self, path: &str, expires: u64, signature: &str, now: u64) -> bool {
+        let Ok(signature) = base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(signature)
+        else {
+            return false;
+        };
+
+        expires >= now && self.mac(path, expires).verify_slice(&signature).is_ok()
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+/// `exp` and `sig` of a query, `None` when it isn't signed.
+fn signature(query: &str) -> Option<(Option<u64>, &str)> {
+    let mut expires = None;
+    let mut signature = None;
+
+    for pair in query.split('&') {
+        match pair.split_once('=') {
+            Some(("exp", value)) => expires = value.parse().ok(),
+            Some(("sig", value)) => signature = Some(value),
+            _ => {}
+        }
+    }
+
+    signature.map(|signature| (expires, signature))
+}
+
+/// Middleware letting requests with a valid signature skip authentication.
+///
+/// Use with `axum::middleware::from_fn_with_state(signer, signed::middleware)`.
+pub async fn middleware(
+    State(signer): State<Signer>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let Some((expires, sig)) = request.uri().query().and_then(signature) else {
+        return next.run(request).await;
+    };
+
+    let valid = matches!(*request.method(), Method::GET | Method::HEAD)
+        && request
+            .uri()
+            .path()
+            .strip_prefix(PREFIX)
+            .zip(expires)
+            .is_some_and(|(path, expires)| signer.verify(path, expires, sig, now()));
+
+    if !valid {
+        return Error::Forbidden("invalid or expired signature".into()).into_response();
+    }
+
+    request.extensions_mut().insert(Signed);
+
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parts(url: &str) -> (&str, u64, &str) {
+        let (path, query) = url.split_once('?').unwrap();
+        let (expires, sig) = signature(query).unwrap();
+
+        (path.strip_prefix(PREFIX).unwrap(), expires.unwrap(), sig)
+    }
+
+    #[test]
+    fn verifies_signed_urls() {
+        let signer = Signer::new("secret");
+        let url = signer.url("/photos/cat%20pic.png", Duration::from_secs(60));
+
+        assert!(url.starts_with("/.fs/photos/cat%20pic.png?exp="));
+
+        let (path, expires, sig) = parts(&url);
+        assert!(signer.verify(path, expires, sig, now()));
+
+        // Expired, other path, other key
+        assert!(!signer.verify(path, expires, sig, expires + 1));
+        assert!(!signer.verify("photos/dog.png", expires, sig, now()));
+        assert!(!signer.verify(path, expires + 60, sig, now()));
+        assert!(!Signer::new("other").verify(path, expires, sig, now()));
+        assert!(!signer.verify(path, expires, "not base64!", now()));
+    }
+
+    #[cfg(feature = "ssr")]
+    #[test]
+    fn makes_unique_tokens() {
+        let signer = Signer::new("secret");
+        let token = signer.token("Projects");
+
+        assert_eq!(token.len(), 32);
+        assert_ne!(token, signer.token("Projects"));
+    }
+
+    #[test]
+    fn parses_signed_queries() {
+        assert!(signature("download=1").is_none());
+        assert_eq!(signature("sig=abc&exp=10"), Some((Some(10), "abc")));
+        assert_eq!(signature("sig=abc&exp=soon"), Some((None, "abc")));
+    }
+}
@@ -0,0 +1,332 @@
+//! Test harness for the server router
+//!
+//! [`TestServer`] serves the router of a space in memory, without a socket: requests are
+//! handed to the router with `tower::ServiceExt::oneshot` and the responses read whole
+//! into a [`TestResponse`]. [`client_api`] checks the parts of the API the SilverBullet
+//! client relies on, so the authors of a backend can check their filesystem behind the
+//! router. Enabled with the `test-util` feature.
+//!
+//! ```ignore
+//! #[tokio::test]
+//! async fn serves_the_client() {
+//!     let server = TestServer::with_fs(MyFilesystem::new());
+//!
+//!     silverbullet::server::test::client_api(&server).await;
+//! }
+//! ```
+
+use std::sync::Arc;
+
+use axum::Router;
+use axum::body::Body;
+use axum::extract::FromRef;
+use bytes::Bytes;
+use http::request::Parts;
+use http::{HeaderMap, Method, Request, StatusCode};
+use serde::de::DeserializeOwned;
+use tower::ServiceExt as _;
+
+use crate::fs::testing::MemoryFs;
+use crate::fs::{FileMeta, ReadWriteFilesystem};
+use crate::server::{Builder, Error, routes};
+use crate::{client, proxy, shell};
+
+/// State of the router of a [`TestServer`], without a shell, proxy or log sink
+#[derive(Clone, FromRef)]
+struct State {
+    config: client::Config,
+    manifest: client::ManifestConfig,
+    fs: Arc<dyn ReadWriteFilesystem>,
+}
+
+impl routes::fs::Provider for State {
+    type Output = Arc<dyn ReadWriteFilesystem>;
+
+    fn provide(&self, _parts: &mut Parts) -> Result<Self::Output, Error> {
+        Ok(self.fs.clone())
+    }
+}
+
+impl routes::shell::Provider for State {
+    type Output = shell::NoShell;
+
+    fn provide(&self, _parts: &mut Parts) -> Result<Self::Output, Error> {
+        Ok(shell::NoShell {})
+    }
+}
+
+impl routes::proxy::Provider for State {
+    type Output = proxy::NoProxy;
+
+    fn provide(&self) -> Self::Output {
+        proxy::NoProxy
+    }
+}
+
+impl routes::log::Provider for State {
+    type Output = client::DiscardLogger;
+
+    fn provide(&self) -> Self::Output {
+        client::DiscardLogger
+    }
+}
+
+/// Router of a space, called in memory
+pub struct TestServer {
+    router: Router,
+    fs: Arc<dyn ReadWriteFilesystem>,
+}
+
+impl Default for TestServer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TestServer {
+    /// Default routes of an empty [`MemoryFs`] space.
+    pub fn new() -> Self {
+        Self::with_fs(MemoryFs::new())
+    }
+
+    /// Default routes of a space.
+    pub fn with_fs(fs: impl ReadWriteFilesystem + 'static) -> Self {
+        Self::build(Builder::new(), client::Config::default(), fs)
+    }
+
+    /// Routes of a configured builder, e.g. with auth, with the client config of a space.
+    pub fn build(
+        builder: Builder,
+        config: client::Config,
+        fs: impl ReadWriteFilesystem + 'static,
+    ) -> Self {
+        let fs: Arc<dyn ReadWriteFilesystem> = Arc::new(fs);
+        let router = builder.build().with_state(State {
+            config,
+            manifest: client::ManifestConfig::default(),
+            fs: fs.clone(),
+        });
+
+        Self { router, fs }
+    }
+
+    /// Space behind the router, e.g. to set up files or check writes.
+    pub fn fs(&self) -> &Arc<dyn ReadWriteFilesystem> {
+        &self.fs
+    }
+
+    pub async fn request(&self, request: Request<Body>) -> TestResponse {
+        let response = match self.router.clone().oneshot(request).await {
+            Ok(response) => response,
+            Err(infallible) => match infallible {},
+        };
+        let (parts, body) = response.into_parts();
+        let body = axum::body::to_bytes(body, usize::MAX)
+            .await
+            .expect("response body should be readable");
+
+        TestResponse {
+            status: parts.status,
+            headers: parts.headers,
+            body,
+        }
+    }
+
+    /// Send a request without headers.
+    pub async fn send(&self, method: Method, uri: &str, body: impl Into<Body>) -> TestResponse {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(body.into())
+            .expect("request should be valid");
+
+        self.request(request).await
+    }
+
+    pub async fn get(&self, uri: &str) -> TestResponse {
+        self.send(Method::GET, uri, Body::empty()).await
+    }
+
+    /// Read only the metadata of a file, as the client does with `X-Get-Meta`.
+    pub async fn get_meta(&self, uri: &str) -> TestResponse {
+        let request = Request::get(uri)
+            .header("X-Get-Meta", "true")
+            .body(Body::empty())
+            .expect("request should be valid");
+
+        self.request(request).await
+    }
+
+    pub async fn put(&self, uri: &str, body: impl Into<Body>) -> TestResponse {
+        self.send(Method::PUT, uri, body).await
+    }
+
+    pub async fn delete(&self, uri: &str) -> TestResponse {
+        self.send(Method::DELETE, uri, Body::empty()).await
+    }
+}
+
+/// Response of a [`TestServer`], with the whole body
+#[derive(Debug, Clone)]
+pub struct TestResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+}
+
+impl TestResponse {
+    /// Value of a header, `None` when missing or not text.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).and_then(|value| value.to_str().ok())
+    }
+
+    /// Body as text, panicking if it isn't UTF-8.
+    pub fn text(&self) -> &str {
+        std::str::from_utf8(&self.body).expect("response body should be UTF-8")
+    }
+
+    /// Body as JSON, panicking if it doesn't parse.
+    pub fn json<T: DeserializeOwned>(&self) -> T {
+        serde_json::from_slice(&self.body).expect("response body should be JSON")
+    }
+
+    /// Value of a header holding a number, panicking if missing or not one.
+    fn number(&self, name: &str) -> u64 {
+        self.header(name)
+            .and_then(|value| value.parse().ok())
+            .unwrap_or_else(|| panic!("{name} should be a number, got {:?}", self.header(name)))
+    }
+}
+
+/// Check the API the SilverBullet client relies on, panicking at the first difference.
+///
+/// Writes and deletes `test-util/Client API.md`, which must not exist, and leaves the
+/// other files of the space alone.
+pub async fn client_api(server: &TestServer) {
+    const FILE: &str = "/.fs/test-util/Client%20API.md";
+    const NAME: &str = "test-util/Client API.md";
+
+    let ping = server.get("/.ping").await;
+    assert_eq!(ping.status, StatusCode::OK, "GET /.ping");
+    assert_eq!(ping.text(), "OK", "GET /.ping");
+
+    let missing = server.get(FILE).await;
+    assert_eq!(
+        missing.status,
+        StatusCode::NOT_FOUND,
+        "GET of a missing file"
+    );
+
+    let put = server.put(FILE, "# Hello").await;
+    assert_eq!(put.status, StatusCode::OK, "PUT");
+    assert_eq!(put.header("Cache-Control"), Some("no-cache"), "PUT");
+    let meta: FileMeta = put.json();
+    assert_eq!((meta.name.as_str(), meta.size), (NAME, 7), "PUT body");
+    assert_eq!(put.number("X-Last-Modified"), meta.last_modified, "PUT");
+
+    let get = server.get(FILE).await;
+    assert_eq!(get.status, StatusCode::OK, "GET");
+    assert_eq!(get.body, "# Hello", "GET body");
+    assert_eq!(get.number("Content-Length"), 7, "GET");
+    assert_eq!(get.number("X-Content-Length"), 7, "GET");
+    get.number("X-Created");
+    get.number("X-Last-Modified");
+    assert!(
+        matches!(get.header("X-Permission"), Some("rw" | "ro")),
+        "X-Permission should be rw or ro, got {:?}",
+        get.header("X-Permission")
+    );
+
+    let head = server.get_meta(FILE).await;
+    assert_eq!(head.status, StatusCode::OK, "GET with X-Get-Meta");
+    assert!(
+        head.body.is_empty(),
+        "GET with X-Get-Meta should have no body"
+    );
+    assert_eq!(head.number("X-Content-Length"), 7, "GET with X-Get-Meta");
+    assert_eq!(
+        head.header("X-Last-Modified"),
+        get.header("X-Last-Modified"),
+        "GET with X-Get-Meta"
+    );
+
+    let list = server.get("/.fs").await;
+    assert_eq!(list.status, StatusCode::OK, "GET /.fs");
+    let files: Vec<FileMeta> = list.json();
+    let listed = files.iter().find(|file| file.name == NAME);
+    assert_eq!(listed.map(|file| file.size), Some(7), "GET /.fs");
+
+    let invalid = server.get("/.fs/test-util//Client%20API.md").await;
+    assert_eq!(
+        invalid.status,
+        StatusCode::BAD_REQUEST,
+        "GET of an invalid name"
+    );
+
+    let options = server.send(Method::OPTIONS, FILE, Body::empty()).await;
+    assert_eq!(
+        options.header("Allow"),
+        Some("GET, PUT, DELETE, OPTIONS"),
+        "OPTIONS"
+    );
+
+    let delete = server.delete(FILE).await;
+    assert_eq!(delete.status, StatusCode::OK, "DELETE");
+    assert_eq!(delete.text(), "OK", "DELETE body");
+
+    let deleted = server.get(FILE).await;
+    assert_eq!(
+        deleted.status,
+        StatusCode::NOT_FOUND,
+        "GET of a deleted file"
+    );
+
+    let files: Vec<FileMeta> = server.get("/.fs").await.json();
+    assert!(
+        files.iter().all(|file| file.name != NAME),
+        "GET /.fs should not list a deleted file"
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn memory_space_meets_client_api() {
+        client_api(&TestServer::new()).await;
+    }
+
+    #[cfg(feature = "opendal")]
+    #[tokio::test]
+    async fn opendal_space_meets_client_api() {
+        // On disk, the memory service has no file times to compare
+        let root =
+            std::env::temp_dir().join(format!("silverbullet-client-api-{}", std::process::id()));
+        let operator = ::opendal::Operator::new(
+            ::opendal::services::Fs::default().root(root.to_str().unwrap()),
+        )
+        .unwrap()
+        .finish();
+
+        client_api(&TestServer::with_fs(crate::fs::opendal::Filesystem::new(
+            operator,
+        )))
+        .await;
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn serves_the_client_config() {
+        let config = client::Config {
+            read_only: true,
+            ..Default::default()
+        };
+        let server = TestServer::build(Builder::new(), config, MemoryFs::new());
+
+        let response = server.get("/.config").await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.json::<serde_json::Value>()["readOnly"], true);
+    }
+}
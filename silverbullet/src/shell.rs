@@ -1,15 +1,55 @@
+use async_trait::async_trait;
+use futures::StreamExt as _;
+use futures::stream::{self, BoxStream};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
+pub mod allowlist;
+pub mod audit;
+pub mod builtin;
+
+#[cfg(all(not(target_arch = "wasm32"), feature = "process"))]
+pub mod process;
+
+#[cfg(all(not(target_arch = "wasm32"), feature = "process"))]
+pub mod sandbox;
+
 #[derive(Error, Debug)]
-#[error("Failed to run command")]
-pub struct Error {}
+pub enum Error {
+    #[error("Failed to run command: {0}")]
+    Io(#[from] std::io::Error),
 
-pub trait Shell {
-    fn exec(&self, request: Request) -> Result<Response, Error>;
+    #[error(transparent)]
+    Other(#[from] Box<dyn std::error::Error + Send + Sync>),
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+/// Stream of events produced by a running command
+pub type EventStream = BoxStream<'static, Event>;
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+pub trait Shell: Send + Sync {
+    async fn exec(&self, request: Request) -> Result<Response, Error>;
+
+    /// Run a command and emit its output as it is produced.
+    ///
+    /// The default implementation buffers the whole output using [`Shell::exec`]
+    /// and replays it as a single stdout and stderr chunk followed by the exit code.
+    async fn exec_stream(&self, request: Request) -> Result<EventStream, Error> {
+        let response = self.exec(request).await?;
+
+        let events = [
+            (!response.stdout.is_empty()).then_some(Event::Stdout(response.stdout)),
+            (!response.stderr.is_empty()).then_some(Event::Stderr(response.stderr)),
+            Some(Event::Exit(response.code)),
+        ];
+
+        Ok(stream::iter(events.into_iter().flatten()).boxed())
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct Request {
     pub cmd: String,
@@ -18,6 +58,7 @@ pub struct Request {
 }
 
 #[derive(Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct Response {
     pub code: u16,
@@ -25,11 +66,23 @@ pub struct Response {
     pub stderr: String,
 }
 
+/// A single event emitted by a streaming command execution
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(tag = "type", content = "data", rename_all = "camelCase")]
+pub enum Event {
+    Stdout(String),
+    Stderr(String),
+    Exit(u16),
+}
+
 #[derive(Debug, Default)]
 pub struct NoShell {}
 
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
 impl Shell for NoShell {
-    fn exec(&self, _request: Request) -> Result<Response, Error> {
+    async fn exec(&self, _request: Request) -> Result<Response, Error> {
         Ok(Response {
             code: 1,
             stdout: "".to_string(),
@@ -37,3 +90,40 @@ impl Shell for NoShell {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(cmd: &str) -> Request {
+        Request {
+            cmd: cmd.to_string(),
+            args: vec![],
+            stdin: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn default_exec_stream_replays_response() {
+        let events: Vec<_> = NoShell::default()
+            .exec_stream(request("ls"))
+            .await
+            .unwrap()
+            .collect()
+            .await;
+
+        assert_eq!(
+            events,
+            vec![Event::Stderr("Not supported".to_string()), Event::Exit(1)]
+        );
+    }
+
+    #[test]
+    fn event_serialization() {
+        let json = serde_json::to_string(&Event::Stdout("hello\n".to_string())).unwrap();
+        assert_eq!(json, r#"{"type":"stdout","data":"hello\n"}"#);
+
+        let json = serde_json::to_string(&Event::Exit(0)).unwrap();
+        assert_eq!(json, r#"{"type":"exit","data":0}"#);
+    }
+}
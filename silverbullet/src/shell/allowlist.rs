@@ -0,0 +1,124 @@
+use std::collections::HashSet;
+
+use async_trait::async_trait;
+use futures::StreamExt as _;
+use futures::stream;
+
+use crate::shell::{self, Error, Event, EventStream, Request, Response};
+
+/// Exit code of commands rejected by an [`Allowlist`] (like a shell's "cannot execute")
+const NOT_ALLOWED: u16 = 126;
+
+/// Shell only running commands from a fixed set
+pub struct Allowlist<S> {
+    shell: S,
+    commands: Option<HashSet<String>>,
+}
+
+impl<S> Allowlist<S> {
+    pub fn new<I, T>(shell: S, commands: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        Self {
+            shell,
+            commands: Some(commands.into_iter().map(Into::into).collect()),
+        }
+    }
+
+    /// Allow every command, for setups where the allowlist is optional.
+    pub fn allow_all(shell: S) -> Self {
+        Self {
+            shell,
+            commands: None,
+        }
+    }
+
+    fn denied(&self, request: &Request) -> Option<Response> {
+        let commands = self.commands.as_ref()?;
+
+        (!commands.contains(&request.cmd)).then(|| Response {
+            code: NOT_ALLOWED,
+            stdout: String::new(),
+            stderr: format!("{}: command not allowed", request.cmd),
+        })
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl<S> shell::Shell for Allowlist<S>
+where
+    S: shell::Shell,
+{
+    async fn exec(&self, request: Request) -> Result<Response, Error> {
+        match self.denied(&request) {
+            Some(response) => Ok(response),
+            None => self.shell.exec(request).await,
+        }
+    }
+
+    async fn exec_stream(&self, request: Request) -> Result<EventStream, Error> {
+        match self.denied(&request) {
+            Some(response) => {
+                Ok(
+                    stream::iter([Event::Stderr(response.stderr), Event::Exit(response.code)])
+                        .boxed(),
+                )
+            }
+            None => self.shell.exec_stream(request).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shell::{NoShell, Shell as _};
+
+    fn request(cmd: &str) -> Request {
+        Request {
+            cmd: cmd.to_string(),
+            args: vec![],
+            stdin: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn runs_allowed_commands_only() {
+        let shell = Allowlist::new(NoShell::default(), ["git"]);
+
+        let response = shell.exec(request("git")).await.unwrap();
+        assert_eq!(response.stderr, "Not supported");
+
+        let response = shell.exec(request("rm")).await.unwrap();
+        assert_eq!(response.code, 126);
+        assert_eq!(response.stderr, "rm: command not allowed");
+
+        let shell = Allowlist::allow_all(NoShell::default());
+
+        let response = shell.exec(request("rm")).await.unwrap();
+        assert_eq!(response.stderr, "Not supported");
+    }
+
+    #[tokio::test]
+    async fn streams_rejection() {
+        let shell = Allowlist::new(NoShell::default(), Vec::<String>::new());
+
+        let events: Vec<_> = shell
+            .exec_stream(request("ls"))
+            .await
+            .unwrap()
+            .collect()
+            .await;
+
+        assert_eq!(
+            events,
+            vec![
+                Event::Stderr("ls: command not allowed".to_string()),
+                Event::Exit(126)
+            ]
+        );
+    }
+}
@@ -0,0 +1,256 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures::StreamExt as _;
+
+use crate::shell::{self, Error, Event, EventStream, Request, Response};
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+
+#[cfg(target_arch = "wasm32")]
+use web_time::Instant;
+
+/// Number of output bytes kept per stream in audit entries by default
+const DEFAULT_MAX_OUTPUT: usize = 1024;
+
+/// Record of a single shell invocation
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub cmd: String,
+    pub args: Vec<String>,
+    pub requester: Option<String>,
+    /// Exit code, or `None` if the command could not be run
+    pub code: Option<u16>,
+    pub duration: Duration,
+    pub stdout: String,
+    pub stderr: String,
+    pub error: Option<String>,
+}
+
+/// Destination of shell audit entries
+pub trait Sink: Send + Sync {
+    fn record(&self, entry: Entry);
+}
+
+pub struct DiscardSink;
+
+impl Sink for DiscardSink {
+    fn record(&self, _entry: Entry) {}
+}
+
+#[cfg(feature = "tracing")]
+#[derive(Default)]
+pub struct TracingSink;
+
+#[cfg(feature = "tracing")]
+impl Sink for TracingSink {
+    fn record(&self, entry: Entry) {
+        tracing::info!(
+            target: "shell::audit",
+            cmd = entry.cmd,
+            args = ?entry.args,
+            requester = entry.requester,
+            code = entry.code,
+            duration_ms = entry.duration.as_millis() as u64,
+            stdout = entry.stdout,
+            stderr = entry.stderr,
+            error = entry.error,
+            "Shell command executed",
+        );
+    }
+}
+
+/// Shell recording every invocation of the wrapped shell to a [`Sink`]
+pub struct Audited<S, K> {
+    shell: S,
+    sink: Arc<K>,
+    requester: Option<String>,
+    max_output: usize,
+}
+
+impl<S, K> Audited<S, K> {
+    pub fn new(shell: S, sink: K) -> Self {
+        Self::with_sink(shell, Arc::new(sink))
+    }
+
+    /// Create an audited shell sharing a sink with others.
+    pub fn with_sink(shell: S, sink: Arc<K>) -> Self {
+        Self {
+            shell,
+            sink,
+            requester: None,
+            max_output: DEFAULT_MAX_OUTPUT,
+        }
+    }
+
+    /// Identify who runs the commands (eg. user name or client IP).
+    #[must_use]
+    pub fn requester(mut self, requester: impl Into<String>) -> Self {
+        self.requester = Some(requester.into());
+        self
+    }
+
+    /// Maximum number of bytes of stdout and stderr kept in each entry.
+    #[must_use]
+    pub fn max_output(mut self, bytes: usize) -> Self {
+        self.max_output = bytes;
+        self
+    }
+
+    fn entry(&self, request: &Request, started: Instant) -> Entry {
+        Entry {
+            cmd: request.cmd.clone(),
+            args: request.args.clone(),
+            requester: self.requester.clone(),
+            code: None,
+            duration: started.elapsed(),
+            stdout: String::new(),
+            stderr: String::new(),
+            error: None,
+        }
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl<S, K> shell::Shell for Audited<S, K>
+where
+    S: shell::Shell,
+    K: Sink + 'static,
+{
+    async fn exec(&self, request: Request) -> Result<Response, Error> {
+        let started = Instant::now();
+        let result = self.shell.exec(request.clone()).await;
+
+        let mut entry = self.entry(&request, started);
+
+        match &result {
+            Ok(response) => {
+                entry.code = Some(response.code);
+                entry.stdout = truncate(&response.stdout, self.max_output);
+                entry.stderr = truncate(&response.stderr, self.max_output);
+            }
+            Err(err) => entry.error = Some(err.to_string()),
+        }
+
+        self.sink.record(entry);
+
+        result
+    }
+
+    async fn exec_stream(&self, request: Request) -> Result<EventStream, Error> {
+        let started = Instant::now();
+
+        let events = match self.shell.exec_stream(request.clone()).await {
+            Ok(events) => events,
+            Err(err) => {
+                let mut entry = self.entry(&request, started);
+                entry.error = Some(err.to_string());
+                self.sink.record(entry);
+
+                return Err(err);
+            }
+        };
+
+        let mut entry = self.entry(&request, started);
+        let sink = self.sink.clone();
+        let max_output = self.max_output;
+
+        Ok(events
+            .inspect(move |event| match event {
+                Event::Stdout(chunk) => append(&mut entry.stdout, chunk, max_output),
+                Event::Stderr(chunk) => append(&mut entry.stderr, chunk, max_output),
+                Event::Exit(code) => {
+                    entry.code = Some(*code);
+                    entry.duration = started.elapsed();
+                    sink.record(entry.clone());
+                }
+            })
+            .boxed())
+    }
+}
+
+fn truncate(output: &str, max: usize) -> String {
+    let mut truncated = String::new();
+    append(&mut truncated, output, max);
+    truncated
+}
+
+/// Append as much of `chunk` as fits in `max` bytes without splitting a character.
+fn append(output: &mut String, chunk: &str, max: usize) {
+    let available = max.saturating_sub(output.len());
+    let mut end = available.min(chunk.len());
+
+    while !chunk.is_char_boundary(end) {
+        end -= 1;
+    }
+
+    output.push_str(&chunk[..end]);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::shell::{NoShell, Shell as _};
+
+    #[derive(Default)]
+    struct MemorySink(Mutex<Vec<Entry>>);
+
+    impl Sink for MemorySink {
+        fn record(&self, entry: Entry) {
+            self.0.lock().unwrap().push(entry);
+        }
+    }
+
+    fn request() -> Request {
+        Request {
+            cmd: "ls".to_string(),
+            args: vec!["-l".to_string()],
+            stdin: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn exec_records_entry() {
+        let sink = Arc::new(MemorySink::default());
+        let shell = Audited::with_sink(NoShell::default(), sink.clone())
+            .requester("alice")
+            .max_output(3);
+
+        shell.exec(request()).await.unwrap();
+
+        let entries = sink.0.lock().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].cmd, "ls");
+        assert_eq!(entries[0].args, vec!["-l"]);
+        assert_eq!(entries[0].requester.as_deref(), Some("alice"));
+        assert_eq!(entries[0].code, Some(1));
+        assert_eq!(entries[0].stderr, "Not");
+    }
+
+    #[tokio::test]
+    async fn exec_stream_records_entry_on_exit() {
+        let sink = Arc::new(MemorySink::default());
+        let shell = Audited::with_sink(NoShell::default(), sink.clone());
+
+        let events: Vec<_> = shell.exec_stream(request()).await.unwrap().collect().await;
+
+        assert_eq!(events.last(), Some(&Event::Exit(1)));
+
+        let entries = sink.0.lock().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].code, Some(1));
+        assert_eq!(entries[0].stderr, "Not supported");
+    }
+
+    #[test]
+    fn truncate_respects_char_boundaries() {
+        assert_eq!(truncate("héllo", 2), "h");
+        assert_eq!(truncate("héllo", 3), "hé");
+        assert_eq!(truncate("hi", 10), "hi");
+    }
+}
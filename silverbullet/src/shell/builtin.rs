@@ -0,0 +1,742 @@
+use std::collections::BTreeSet;
+
+use async_trait::async_trait;
+use futures::TryStreamExt;
+
+use crate::fs::{self, FileMeta, ReadOnlyFilesystem};
+use crate::glob;
+use crate::shell::{self, Error, Request, Response};
+
+/// Shell implementing a small set of read-only commands against a filesystem.
+///
+/// Commands never touch the host system, which makes this shell usable on platforms
+/// without processes (eg. Cloudflare Workers):
+///
+/// - `ls [DIR]`: list the entries of a folder
+/// - `find [DIR] [-name PATTERN]`: list files recursively, optionally filtered by a name glob
+/// - `grep [-i] [-n] [-l] [-c] PATTERN [PATH...]`: search for a fixed string in files or stdin
+/// - `wc [-l] [-w] [-c] [PATH...]`: count lines, words and bytes in files or stdin
+/// - `cat PATH...`: print files
+/// - `git status`: with the `gix` feature, the files changed since the index of the `.git`
+///   folder of the filesystem, in the short format. Staged changes and `.gitignore` are
+///   not considered
+pub struct Shell<F> {
+    fs: F,
+}
+
+impl<F> Shell<F> {
+    pub fn new(fs: F) -> Self {
+        Self { fs }
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl<F> shell::Shell for Shell<F>
+where
+    F: ReadOnlyFilesystem,
+{
+    async fn exec(&self, request: Request) -> Result<Response, Error> {
+        let args: Vec<&str> = request.args.iter().map(String::as_str).collect();
+
+        match request.cmd.as_str() {
+            "ls" => self.ls(&args).await,
+            "find" => self.find(&args).await,
+            "grep" => self.grep(&args, request.stdin).await,
+            "wc" => self.wc(&args, request.stdin).await,
+            "cat" => self.cat(&args).await,
+            #[cfg(feature = "gix")]
+            "git" => self.git(&args).await,
+            cmd => Ok(Output::default()
+                .err(format!("{cmd}: command not found"))
+                .code(127)
+                .into()),
+        }
+    }
+}
+
+impl<F> Shell<F>
+where
+    F: ReadOnlyFilesystem,
+{
+    async fn ls(&self, args: &[&str]) -> Result<Response, Error> {
+        let dir = args.first().copied().unwrap_or("");
+
+        let entries: BTreeSet<String> = self
+            .files_under(dir)
+            .await?
+            .iter()
+            .map(|file| {
+                let relative = relative_to(dir, &file.name);
+
+                match relative.split_once('/') {
+                    Some((folder, _)) => format!("{folder}/"),
+                    None => relative.to_string(),
+                }
+            })
+            .collect();
+
+        if entries.is_empty() && !dir.is_empty() && self.fs.meta(dir).await.is_err() {
+            return Ok(Output::default()
+                .err(format!("ls: {dir}: No such file or directory"))
+                .code(1)
+                .into());
+        }
+
+        let mut output = Output::default();
+        entries.into_iter().for_each(|entry| output.line(entry));
+
+        Ok(output.into())
+    }
+
+    async fn find(&self, args: &[&str]) -> Result<Response, Error> {
+        let mut dir = "";
+        let mut name = None;
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match *arg {
+                "-name" => name = args.next().copied(),
+                "." => {}
+                path => dir = path,
+            }
+        }
+
+        let mut output = Output::default();
+
+        for file in self.files_under(dir).await? {
+            let basename = file.name.rsplit('/').next().unwrap_or(&file.name);
+
+            if name.is_none_or(|pattern| glob::matches(pattern, basename)) {
+                output.line(file.name);
+            }
+        }
+
+        Ok(output.into())
+    }
+
+    async fn grep(&self, args: &[&str], stdin: Option<String>) -> Result<Response, Error> {
+        let (flags, operands) = split_flags(args);
+        let ignore_case = flags.contains(&'i');
+        let line_numbers = flags.contains(&'n');
+        let files_only = flags.contains(&'l');
+        let count_only = flags.contains(&'c');
+
+        let Some((pattern, paths)) = operands.split_first() else {
+            return Ok(Output::default()
+                .err("usage: grep [-i] [-n] [-l] [-c] PATTERN [PATH...]")
+                .code(2)
+                .into());
+        };
+
+        let needle = if ignore_case {
+            pattern.to_lowercase()
+        } else {
+            pattern.to_string()
+        };
+
+        let (inputs, show_names) = match (paths.is_empty(), stdin) {
+            (true, Some(stdin)) => (vec![("(standard input)".to_string(), stdin)], false),
+            (recursive, _) => {
+                let inputs = self.read_all(paths).await?;
+                let show_names = recursive || inputs.len() > 1;
+
+                (inputs, show_names)
+            }
+        };
+
+        let mut output = Output::default();
+        let mut matched = false;
+
+        for (name, content) in inputs {
+            let matches: Vec<_> = content
+                .lines()
+                .enumerate()
+                .filter(|(_, line)| {
+                    if ignore_case {
+                        line.to_lowercase().contains(&needle)
+                    } else {
+                        line.contains(&needle)
+                    }
+                })
+                .collect();
+
+            matched |= !matches.is_empty();
+
+            if files_only {
+                if !matches.is_empty() {
+                    output.line(name);
+                }
+            } else if count_only {
+                match show_names {
+                    true => output.line(format!("{name}:{}", matches.len())),
+                    false => output.line(matches.len().to_string()),
+                }
+            } else {
+                for (number, line) in matches {
+                    let prefix = match (show_names, line_numbers) {
+                        (true, true) => format!("{name}:{}:", number + 1),
+                        (true, false) => format!("{name}:"),
+                        (false, true) => format!("{}:", number + 1),
+                        (false, false) => String::new(),
+                    };
+
+                    output.line(format!("{prefix}{line}"));
+                }
+            }
+        }
+
+        Ok(output.code(if matched { 0 } else { 1 }).into())
+    }
+
+    async fn wc(&self, args: &[&str], stdin: Option<String>) -> Result<Response, Error> {
+        let (flags, paths) = split_flags(args);
+        let all = flags.is_empty();
+
+        let inputs = match (paths.is_empty(), stdin) {
+            (true, Some(stdin)) => vec![(String::new(), Ok(stdin))],
+            _ => self.read_each(&paths).await?,
+        };
+
+        let mut output = Output::default();
+        let mut totals = [0usize; 3];
+
+        let format = |counts: [usize; 3], name: &str| {
+            let columns: Vec<String> = ['l', 'w', 'c']
+                .iter()
+                .zip(counts)
+                .filter(|(flag, _)| all || flags.contains(flag))
+                .map(|(_, count)| count.to_string())
+                .chain((!name.is_empty()).then(|| name.to_string()))
+                .collect();
+
+            columns.join(" ")
+        };
+
+        let multiple = inputs.len() > 1;
+
+        for (name, content) in inputs {
+            match content {
+                Ok(content) => {
+                    let counts = [
+                        content.lines().count(),
+                        content.split_whitespace().count(),
+                        content.len(),
+                    ];
+
+                    totals.iter_mut().zip(counts).for_each(|(t, c)| *t += c);
+                    output.line(format(counts, &name));
+                }
+                Err(message) => output.error(format!("wc: {message}")),
+            }
+        }
+
+        if multiple {
+            output.line(format(totals, "total"));
+        }
+
+        Ok(output.into())
+    }
+
+    async fn cat(&self, args: &[&str]) -> Result<Response, Error> {
+        let mut output = Output::default();
+
+        for (_, content) in self.read_each(args).await? {
+            match content {
+                Ok(content) => output.stdout.push_str(&content),
+                Err(message) => output.error(format!("cat: {message}")),
+            }
+        }
+
+        Ok(output.into())
+    }
+
+    #[cfg(feature = "gix")]
+    async fn git(&self, args: &[&str]) -> Result<Response, Error> {
+        use std::collections::BTreeMap;
+
+        use gix_hash::Kind;
+
+        if !matches!(
+            args,
+            ["status"] | ["status", "-s" | "--short" | "--porcelain"]
+        ) {
+            return Ok(Output::default()
+                .err("usage: git status [--short]")
+                .code(129)
+                .into());
+        }
+
+        let index = match self.fs.get(".git/index").await {
+            Ok((stream, _)) => read_bytes(stream).await?,
+            Err(fs::Error::NotFound(_)) => {
+                return Ok(Output::default()
+                    .err("fatal: not a git repository")
+                    .code(128)
+                    .into());
+            }
+            Err(err) => return Err(fs_error(err)),
+        };
+
+        let options = gix_index::decode::Options {
+            thread_limit: Some(1),
+            ..Default::default()
+        };
+        let (state, _) =
+            gix_index::State::from_bytes(&index, filetime::FileTime::zero(), Kind::Sha1, options)
+                .map_err(|err| Error::Other(format!("invalid git index: {err}").into()))?;
+
+        let files: BTreeMap<String, FileMeta> = self
+            .fs
+            .list()
+            .await
+            .map_err(fs_error)?
+            .into_iter()
+            .filter(|file| !is_under(".git", &file.name))
+            .map(|file| (file.name.clone(), file))
+            .collect();
+
+        let mut changes = BTreeMap::new();
+
+        for entry in state.entries() {
+            let path = entry.path(&state).to_string();
+
+            if entry.stage_raw() != 0 {
+                changes.insert(path, "UU");
+                continue;
+            }
+
+            if entry.mode.is_submodule() {
+                changes.insert(path, "");
+                continue;
+            }
+
+            let changed = match files.get(&path) {
+                None => Some(" D"),
+                Some(file) if file.size != u64::from(entry.stat.size) => Some(" M"),
+                Some(_) => {
+                    let (stream, _) = self.fs.get(&path).await.map_err(fs_error)?;
+                    let content = read_bytes(stream).await?;
+                    let id = gix_object::compute_hash(Kind::Sha1, gix_object::Kind::Blob, &content)
+                        .map_err(|err| Error::Other(err.to_string().into()))?;
+
+                    (id != entry.id).then_some(" M")
+                }
+            };
+
+            changes.insert(path, changed.unwrap_or_default());
+        }
+
+        for name in files.into_keys() {
+            changes.entry(name).or_insert("??");
+        }
+
+        let mut output = Output::default();
+        for (path, status) in changes {
+            if !status.is_empty() {
+                output.line(format!("{status} {path}"));
+            }
+        }
+
+        Ok(output.into())
+    }
+
+    async fn files_under(&self, dir: &str) -> Result<Vec<FileMeta>, Error> {
+        let mut files = self.fs.list().await.map_err(fs_error)?;
+
+        files.retain(|file| is_under(dir, &file.name));
+        files.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(files)
+    }
+
+    /// Read the text contents of the given files, expanding folders recursively.
+    ///
+    /// Without paths every file is read. Files that are not valid UTF-8 are skipped.
+    async fn read_all(&self, paths: &[&str]) -> Result<Vec<(String, String)>, Error> {
+        let files = self.fs.list().await.map_err(fs_error)?;
+        let mut names: Vec<_> = files
+            .into_iter()
+            .map(|file| file.name)
+            .filter(|name| paths.is_empty() || paths.iter().any(|path| is_under(path, name)))
+            .collect();
+        names.sort();
+
+        let mut contents = Vec::new();
+
+        for name in names {
+            if let Some(content) = self.read(&name).await? {
+                contents.push((name, content));
+            }
+        }
+
+        Ok(contents)
+    }
+
+    /// Read the text contents of each given file, reporting missing files per path.
+    async fn read_each(
+        &self,
+        paths: &[&str],
+    ) -> Result<Vec<(String, std::result::Result<String, String>)>, Error> {
+        let mut contents = Vec::new();
+
+        for path in paths {
+            let content = match self.read(path).await {
+                Ok(Some(content)) => Ok(content),
+                Ok(None) => Err(format!("{path}: Binary file")),
+                Err(Error::Other(err)) if is_not_found(err.as_ref()) => {
+                    Err(format!("{path}: No such file or directory"))
+                }
+                Err(err) => return Err(err),
+            };
+
+            contents.push((path.to_string(), content));
+        }
+
+        Ok(contents)
+    }
+
+    async fn read(&self, path: &str) -> Result<Option<String>, Error> {
+        let (stream, _) = self.fs.get(path).await.map_err(fs_error)?;
+
+        Ok(String::from_utf8(read_bytes(stream).await?).ok())
+    }
+}
+
+async fn read_bytes(stream: fs::Stream) -> Result<Vec<u8>, Error> {
+    let bytes = stream
+        .try_fold(Vec::new(), |mut acc, chunk| async move {
+            acc.extend_from_slice(&chunk);
+            Ok(acc)
+        })
+        .await?;
+
+    Ok(bytes)
+}
+
+#[derive(Default)]
+struct Output {
+    code: u16,
+    stdout: String,
+    stderr: String,
+}
+
+impl Output {
+    fn line(&mut self, line: impl AsRef<str>) {
+        self.stdout.push_str(line.as_ref());
+        self.stdout.push('\n');
+    }
+
+    /// Record an error message and mark the command as failed.
+    fn error(&mut self, line: impl AsRef<str>) {
+        self.stderr.push_str(line.as_ref());
+        self.stderr.push('\n');
+        self.code = 1;
+    }
+
+    fn err(mut self, line: impl AsRef<str>) -> Self {
+        self.error(line);
+        self
+    }
+
+    fn code(mut self, code: u16) -> Self {
+        self.code = code;
+        self
+    }
+}
+
+impl From<Output> for Response {
+    fn from(output: Output) -> Self {
+        Response {
+            code: output.code,
+            stdout: output.stdout,
+            stderr: output.stderr,
+        }
+    }
+}
+
+fn fs_error(err: fs::Error) -> Error {
+    Error::Other(Box::new(err))
+}
+
+fn is_not_found(err: &(dyn std::error::Error + Send + Sync + 'static)) -> bool {
+    matches!(
+        err.downcast_ref::<fs::Error>(),
+        Some(fs::Error::NotFound(_))
+    )
+}
+
+/// Separate single-letter flags (`-in` or `-i -n`) from operands.
+fn split_flags<'a>(args: &[&'a str]) -> (Vec<char>, Vec<&'a str>) {
+    let (flags, operands): (Vec<&str>, Vec<&str>) = args
+        .iter()
+        .partition(|arg| arg.len() > 1 && arg.starts_with('-'));
+
+    (
+        flags.iter().flat_map(|flag| flag[1..].chars()).collect(),
+        operands,
+    )
+}
+
+fn is_under(dir: &str, name: &str) -> bool {
+    let dir = dir.trim_matches('/');
+
+    dir.is_empty()
+        || dir == "."
+        || name == dir
+        || name
+            .strip_prefix(dir)
+            .is_some_and(|rest| rest.starts_with('/'))
+}
+
+fn relative_to<'a>(dir: &str, name: &'a str) -> &'a str {
+    let dir = dir.trim_matches('/');
+
+    match dir {
+        "" | "." => name,
+        dir => name
+            .strip_prefix(dir)
+            .map(|rest| rest.trim_start_matches('/'))
+            .unwrap_or(name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use bytes::Bytes;
+    use futures::{StreamExt, stream};
+
+    use super::*;
+    use crate::fs::{Result, Stream};
+    use crate::shell::Shell as _;
+
+    struct StaticFs(HashMap<&'static str, &'static str>);
+
+    #[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+    #[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+    impl ReadOnlyFilesystem for StaticFs {
+        async fn list(&self) -> Result<Vec<FileMeta>> {
+            Ok(self.0.keys().map(|name| meta(name)).collect())
+        }
+
+        async fn get(&self, path: &str) -> Result<(Stream, FileMeta)> {
+            let content = self
+                .0
+                .get(path)
+                .ok_or_else(|| fs::Error::NotFound(path.into()))?;
+            let bytes = Bytes::from_static(content.as_bytes());
+
+            Ok((stream::once(async move { Ok(bytes) }).boxed(), meta(path)))
+        }
+
+        async fn meta(&self, path: &str) -> Result<FileMeta> {
+            self.0
+                .get(path)
+                .map(|_| meta(path))
+                .ok_or_else(|| fs::Error::NotFound(path.into()))
+        }
+    }
+
+    fn meta(name: &str) -> FileMeta {
+        FileMeta {
+            name: name.to_string(),
+            created: 0,
+            perm: "ro".to_string(),
+            content_type: "text/markdown".to_string(),
+            last_modified: 0,
+            size: 0,
+            etag: None,
+            version: None,
+        }
+    }
+
+    fn shell() -> Shell<StaticFs> {
+        Shell::new(StaticFs(HashMap::from([
+            ("index.md", "# Welcome\nSee [[Projects/Alpha]]\n"),
+            ("Projects/Alpha.md", "alpha notes\nTODO: ship it\n"),
+            ("Projects/Beta.md", "beta notes\n"),
+            ("Journal/2024-01-01.md", "todo: nothing\n"),
+        ])))
+    }
+
+    async fn run(cmd: &str, args: &[&str]) -> Response {
+        shell()
+            .exec(Request {
+                cmd: cmd.to_string(),
+                args: args.iter().map(|a| a.to_string()).collect(),
+                stdin: None,
+            })
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn ls_lists_top_level_entries() {
+        let response = run("ls", &[]).await;
+
+        assert_eq!(response.stdout, "Journal/\nProjects/\nindex.md\n");
+    }
+
+    #[tokio::test]
+    async fn ls_lists_folder() {
+        let response = run("ls", &["Projects"]).await;
+
+        assert_eq!(response.stdout, "Alpha.md\nBeta.md\n");
+    }
+
+    #[tokio::test]
+    async fn ls_missing_folder_fails() {
+        let response = run("ls", &["Missing"]).await;
+
+        assert_eq!(response.code, 1);
+    }
+
+    #[tokio::test]
+    async fn find_filters_by_name() {
+        let response = run("find", &["Projects", "-name", "A*"]).await;
+
+        assert_eq!(response.stdout, "Projects/Alpha.md\n");
+    }
+
+    #[tokio::test]
+    async fn grep_searches_all_files() {
+        let response = run("grep", &["-i", "todo"]).await;
+
+        assert_eq!(
+            response.stdout,
+            "Journal/2024-01-01.md:todo: nothing\nProjects/Alpha.md:TODO: ship it\n"
+        );
+        assert_eq!(response.code, 0);
+    }
+
+    #[tokio::test]
+    async fn grep_single_file_with_line_numbers() {
+        let response = run("grep", &["-n", "ship", "Projects/Alpha.md"]).await;
+
+        assert_eq!(response.stdout, "2:TODO: ship it\n");
+    }
+
+    #[tokio::test]
+    async fn grep_without_match_exits_one() {
+        let response = run("grep", &["-l", "missing"]).await;
+
+        assert_eq!(response.stdout, "");
+        assert_eq!(response.code, 1);
+    }
+
+    #[tokio::test]
+    async fn grep_reads_stdin() {
+        let response = shell()
+            .exec(Request {
+                cmd: "grep".to_string(),
+                args: vec!["b".to_string()],
+                stdin: Some("a\nb\nc\n".to_string()),
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(response.stdout, "b\n");
+    }
+
+    #[tokio::test]
+    async fn wc_counts_with_total() {
+        let response = run("wc", &["-l", "Projects/Alpha.md", "Projects/Beta.md"]).await;
+
+        assert_eq!(
+            response.stdout,
+            "2 Projects/Alpha.md\n1 Projects/Beta.md\n3 total\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn cat_reports_missing_files() {
+        let response = run("cat", &["Projects/Beta.md", "missing.md"]).await;
+
+        assert_eq!(response.stdout, "beta notes\n");
+        assert_eq!(
+            response.stderr,
+            "cat: missing.md: No such file or directory\n"
+        );
+        assert_eq!(response.code, 1);
+    }
+
+    #[tokio::test]
+    async fn unknown_command() {
+        let response = run("vim", &["index.md"]).await;
+
+        assert_eq!(response.code, 127);
+        assert_eq!(response.stderr, "vim: command not found\n");
+    }
+
+    #[cfg(feature = "gix")]
+    fn index(files: &[(&str, &[u8])]) -> Vec<u8> {
+        use gix_hash::Kind;
+        use gix_index::entry::{Flags, Mode, Stat};
+
+        let mut state = gix_index::State::new(Kind::Sha1);
+        for (path, content) in files {
+            let id = gix_object::compute_hash(Kind::Sha1, gix_object::Kind::Blob, content).unwrap();
+            let stat = Stat {
+                size: content.len() as u32,
+                ..Default::default()
+            };
+            state.dangerously_push_entry(stat, id, Flags::empty(), Mode::FILE, (*path).into());
+        }
+        state.sort_entries();
+
+        let mut index = Vec::new();
+        state.write_to(&mut index, Default::default()).unwrap();
+
+        // Git ends the index with the hash of its content
+        let mut hasher = gix_hash::hasher(Kind::Sha1);
+        hasher.update(&index);
+        index.extend_from_slice(hasher.try_finalize().unwrap().as_bytes());
+        index
+    }
+
+    #[cfg(feature = "gix")]
+    #[tokio::test]
+    async fn git_status_compares_with_the_index() {
+        use crate::fs::testing::MemoryFs;
+
+        let index = index(&[
+            ("index.md", b"home"),
+            ("notes.md", b"todo"),
+            ("longer.md", b"short"),
+            ("gone.md", b"gone"),
+        ]);
+        let fs = MemoryFs::new()
+            .with_file(".git/index", &index)
+            .with_file(".git/HEAD", b"ref: refs/heads/main\n")
+            .with_file("index.md", b"home")
+            .with_file("notes.md", b"done")
+            .with_file("longer.md", b"longer")
+            .with_file("new.md", b"new");
+
+        let response = Shell::new(fs)
+            .exec(Request {
+                cmd: "git".to_string(),
+                args: vec!["status".to_string(), "-s".to_string()],
+                stdin: None,
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(response.code, 0);
+        assert_eq!(
+            response.stdout,
+            " D gone.md\n M longer.md\n?? new.md\n M notes.md\n"
+        );
+    }
+
+    #[cfg(feature = "gix")]
+    #[tokio::test]
+    async fn git_status_outside_a_repository() {
+        let response = run("git", &["status"]).await;
+
+        assert_eq!(response.code, 128);
+        assert_eq!(response.stderr, "fatal: not a git repository\n");
+    }
+}
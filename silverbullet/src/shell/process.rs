@@ -0,0 +1,365 @@
+use std::process::Stdio;
+
+use async_trait::async_trait;
+use futures::StreamExt as _;
+use futures::stream;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::process::Command;
+
+use crate::shell::{self, Error, Event, EventStream, Request, Response, sandbox::Sandbox};
+
+/// Shell running commands as local processes
+#[derive(Debug, Default)]
+pub struct Shell {
+    sandbox: Option<Sandbox>,
+}
+
+impl Shell {
+    pub fn new() -> Self {
+        Self { sandbox: None }
+    }
+
+    /// Run every command with the restrictions of the given sandbox.
+    #[must_use]
+    pub fn sandbox(mut self, sandbox: Sandbox) -> Self {
+        self.sandbox = Some(sandbox);
+        self
+    }
+
+    fn output_limit(&self) -> usize {
+        self.sandbox
+            .as_ref()
+            .and_then(Sandbox::output_limit)
+            .unwrap_or(usize::MAX)
+    }
+
+    fn command(&self, request: &Request) -> std::io::Result<Command> {
+        if let Some(sandbox) = &self.sandbox {
+            return sandbox.command(request);
+        }
+
+        let mut command = Command::new(&request.cmd);
+
+        command
+            .args(&request.args)
+            .stdin(if request.stdin.is_some() {
+                Stdio::piped()
+            } else {
+                Stdio::null()
+            })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+
+        Ok(command)
+    }
+}
+
+#[async_trait]
+impl shell::Shell for Shell {
+    async fn exec(&self, request: Request) -> Result<Response, Error> {
+        let limit = self.output_limit();
+        let mut child = self.command(&request)?.spawn()?;
+
+        let stdin = feed_stdin(&mut child, request.stdin);
+        let stdout = read_limited(child.stdout.take(), limit);
+        let stderr = read_limited(child.stderr.take(), limit);
+
+        let (written, stdout, stderr, status) = futures::join!(stdin, stdout, stderr, child.wait());
+
+        written?;
+
+        Ok(Response {
+            code: exit_code(status?),
+            stdout: String::from_utf8_lossy(&stdout?).into_owned(),
+            stderr: String::from_utf8_lossy(&stderr?).into_owned(),
+        })
+    }
+
+    async fn exec_stream(&self, request: Request) -> Result<EventStream, Error> {
+        let limit = self.output_limit();
+        let mut child = self.command(&request)?.spawn()?;
+
+        // Feed stdin while output is being consumed; it never yields an event itself
+        let stdin = stream::once(feed_stdin(&mut child, request.stdin))
+            .filter_map(|_| std::future::ready(None));
+
+        let stdout = child
+            .stdout
+            .take()
+            .map(|out| chunks(out, limit, Event::Stdout));
+        let stderr = child
+            .stderr
+            .take()
+            .map(|err| chunks(err, limit, Event::Stderr));
+
+        let output = stream::select(
+            stdin,
+            stream::select(
+                stream::iter(stdout).flatten(),
+                stream::iter(stderr).flatten(),
+            ),
+        );
+
+        let exit = stream::once(async move {
+            // A failed wait is reported the same way as a process killed by a signal
+            Event::Exit(child.wait().await.map(exit_code).unwrap_or(1))
+        });
+
+        Ok(output.chain(exit).boxed())
+    }
+}
+
+fn feed_stdin(
+    child: &mut tokio::process::Child,
+    stdin: Option<String>,
+) -> impl Future<Output = std::io::Result<()>> + Send + 'static {
+    let pipe = child.stdin.take();
+
+    async move {
+        if let (Some(mut pipe), Some(input)) = (pipe, stdin) {
+            pipe.write_all(input.as_bytes()).await?;
+            // Dropping the pipe closes it so the process sees EOF
+        }
+
+        Ok(())
+    }
+}
+
+/// Read the whole pipe, keeping at most `limit` bytes.
+async fn read_limited<R>(reader: Option<R>, limit: usize) -> std::io::Result<Vec<u8>>
+where
+    R: AsyncRead + Unpin,
+{
+    let Some(mut reader) = reader else {
+        return Ok(Vec::new());
+    };
+
+    let mut output = Vec::new();
+    (&mut reader)
+        .take(limit as u64)
+        .read_to_end(&mut output)
+        .await?;
+
+    // Drain the rest so the process does not block on a full pipe
+    tokio::io::copy(&mut reader, &mut tokio::io::sink()).await?;
+
+    Ok(output)
+}
+
+/// Size of the reads of a pipe
+const CHUNK: usize = 8 * 1024;
+
+/// Pipe of a command being read, see [`chunks`]
+struct Output<R> {
+    reader: R,
+    buf: bytes::BytesMut,
+    /// Start of a character split between reads
+    pending: Vec<u8>,
+    left: usize,
+    done: bool,
+}
+
+impl<R> Output<R> {
+    /// Decode the bytes read, keeping a character cut at their end for the next read.
+    fn decode(&mut self, bytes: &[u8]) -> String {
+        self.pending.extend_from_slice(bytes);
+        let complete = self.pending.len() - incomplete(&self.pending);
+        let text = String::from_utf8_lossy(&self.pending[..complete]).into_owned();
+        self.pending.drain(..complete);
+
+        text
+    }
+}
+
+/// Length of the UTF-8 character cut at the end of `bytes`, 0 when it is complete.
+fn incomplete(bytes: &[u8]) -> usize {
+    for back in 1..=bytes.len().min(3) {
+        let byte = bytes[bytes.len() - back];
+
+        // Continuation bytes are 0b10xxxxxx
+        if byte & 0xc0 != 0x80 {
+            let len = match byte {
+                0xc0..=0xdf => 2,
+                0xe0..=0xef => 3,
+                0xf0..=0xf7 => 4,
+                _ => 1,
+            };
+
+            return if len > back { back } else { 0 };
+        }
+    }
+
+    0
+}
+
+/// Emit the output of the pipe as it is read, invalid UTF-8 replaced, up to `limit` bytes.
+///
+/// The rest is read and dropped, so the process does not block on a full pipe. A failed
+/// read ends the stream with a [`Event::Stderr`] reporting it.
+fn chunks<R>(reader: R, limit: usize, event: fn(String) -> Event) -> EventStream
+where
+    R: AsyncRead + Send + Unpin + 'static,
+{
+    let output = Output {
+        reader,
+        buf: bytes::BytesMut::with_capacity(CHUNK),
+        pending: Vec::new(),
+        left: limit,
+        done: false,
+    };
+
+    stream::unfold(output, move |mut output| async move {
+        if output.done {
+            return None;
+        }
+
+        output.buf.clear();
+        let text = match output.reader.read_buf(&mut output.buf).await {
+            Ok(0) => {
+                output.done = true;
+                String::from_utf8_lossy(&std::mem::take(&mut output.pending)).into_owned()
+            }
+            Ok(read) => {
+                let kept = read.min(output.left);
+                output.left -= kept;
+                let bytes = output.buf.split_to(kept);
+                output.decode(&bytes)
+            }
+            Err(err) => {
+                output.done = true;
+                let message = format!("Failed to read the output: {err}\n");
+                return Some((Some(Event::Stderr(message)), output));
+            }
+        };
+
+        Some(((!text.is_empty()).then(|| event(text)), output))
+    })
+    .filter_map(std::future::ready)
+    .boxed()
+}
+
+fn exit_code(status: std::process::ExitStatus) -> u16 {
+    status.code().map(|c| c as u16).unwrap_or(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shell::Shell as _;
+
+    fn request(cmd: &str, args: &[&str]) -> Request {
+        Request {
+            cmd: cmd.to_string(),
+            args: args.iter().map(|a| a.to_string()).collect(),
+            stdin: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn exec_captures_output_and_code() {
+        let response = Shell::new()
+            .exec(request("sh", &["-c", "echo out; echo err >&2; exit 3"]))
+            .await
+            .unwrap();
+
+        assert_eq!(response.code, 3);
+        assert_eq!(response.stdout, "out\n");
+        assert_eq!(response.stderr, "err\n");
+    }
+
+    #[tokio::test]
+    async fn exec_passes_stdin() {
+        let mut req = request("cat", &[]);
+        req.stdin = Some("from stdin".to_string());
+
+        let response = Shell::new().exec(req).await.unwrap();
+
+        assert_eq!(response.stdout, "from stdin");
+    }
+
+    #[tokio::test]
+    async fn exec_missing_command_fails() {
+        let result = Shell::new().exec(request("does-not-exist-xyz", &[])).await;
+
+        assert!(matches!(result, Err(Error::Io(_))));
+    }
+
+    /// Output of a stream, concatenated, and its exit code
+    async fn output(events: EventStream) -> (String, String, Option<u16>) {
+        let mut output = (String::new(), String::new(), None);
+
+        for event in events.collect::<Vec<_>>().await {
+            match event {
+                Event::Stdout(chunk) => output.0.push_str(&chunk),
+                Event::Stderr(chunk) => output.1.push_str(&chunk),
+                Event::Exit(code) => output.2 = Some(code),
+            }
+        }
+
+        output
+    }
+
+    #[tokio::test]
+    async fn exec_stream_emits_output_then_exit() {
+        let events = Shell::new()
+            .exec_stream(request("sh", &["-c", "echo one; echo two >&2; exit 2"]))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            output(events).await,
+            ("one\n".to_string(), "two\n".to_string(), Some(2))
+        );
+    }
+
+    #[tokio::test]
+    async fn exec_stream_replaces_invalid_utf8() {
+        let events = Shell::new()
+            .exec_stream(request("sh", &["-c", r"printf 'a\377b\n'; echo after"]))
+            .await
+            .unwrap();
+
+        let (stdout, _, code) = output(events).await;
+        assert_eq!(stdout, "a\u{fffd}b\nafter\n");
+        assert_eq!(code, Some(0));
+    }
+
+    #[tokio::test]
+    async fn exec_stream_emits_unfinished_lines() {
+        let mut events = Shell::new()
+            .exec_stream(request("sh", &["-c", r"printf '50%%\r'; sleep 5"]))
+            .await
+            .unwrap();
+
+        let first = tokio::time::timeout(std::time::Duration::from_secs(2), events.next())
+            .await
+            .unwrap();
+        assert_eq!(first, Some(Event::Stdout("50%\r".to_string())));
+    }
+
+    #[tokio::test]
+    async fn exec_stream_drains_past_the_limit() {
+        let shell = Shell::new().sandbox(crate::shell::sandbox::Sandbox::new().max_output(4));
+        let script = "head -c 1000000 /dev/zero | tr '\\0' a; echo done >&2";
+        let events = shell
+            .exec_stream(request("sh", &["-c", script]))
+            .await
+            .unwrap();
+
+        // Both pipes are cut
+        assert_eq!(
+            output(events).await,
+            ("aaaa".to_string(), "done".to_string(), Some(0))
+        );
+    }
+
+    #[test]
+    fn keeps_cut_characters() {
+        assert_eq!(incomplete(b"abc"), 0);
+        assert_eq!(incomplete("é".as_bytes()), 0);
+        assert_eq!(incomplete(&"é".as_bytes()[..1]), 1);
+        assert_eq!(incomplete(&"€".as_bytes()[..2]), 2);
+        assert_eq!(incomplete(b"\xff"), 0);
+    }
+}
@@ -0,0 +1,330 @@
+use std::ffi::OsStr;
+use std::path::PathBuf;
+use std::process::Stdio;
+
+use tokio::process::Command;
+
+use crate::shell::Request;
+
+/// Environment variables passed through to sandboxed commands by default
+const DEFAULT_INHERITED_ENV: &[&str] = &["PATH", "HOME", "LANG"];
+
+/// Restrictions applied to commands run by [`crate::shell::process::Shell`]
+///
+/// The environment is cleared by default; only the variables listed with
+/// [`Sandbox::inherit_env`] (`PATH`, `HOME` and `LANG` unless overridden) and
+/// explicitly set ones are passed to the command.
+#[derive(Debug, Clone)]
+pub struct Sandbox {
+    inherit_env: Vec<String>,
+    env: Vec<(String, String)>,
+    working_dir: Option<PathBuf>,
+    #[cfg(unix)]
+    uid: Option<u32>,
+    #[cfg(unix)]
+    gid: Option<u32>,
+    #[cfg(unix)]
+    cpu_seconds: Option<u64>,
+    #[cfg(unix)]
+    memory_bytes: Option<u64>,
+    max_output: Option<usize>,
+    no_network: bool,
+}
+
+impl Default for Sandbox {
+    fn default() -> Self {
+        Self {
+            inherit_env: DEFAULT_INHERITED_ENV
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+            env: Vec::new(),
+            working_dir: None,
+            #[cfg(unix)]
+            uid: None,
+            #[cfg(unix)]
+            gid: None,
+            #[cfg(unix)]
+            cpu_seconds: None,
+            #[cfg(unix)]
+            memory_bytes: None,
+            max_output: None,
+            no_network: false,
+        }
+    }
+}
+
+impl Sandbox {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replace the list of environment variables inherited from the server process.
+    #[must_use]
+    pub fn inherit_env<I, K>(mut self, names: I) -> Self
+    where
+        I: IntoIterator<Item = K>,
+        K: Into<String>,
+    {
+        self.inherit_env = names.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Set an environment variable for every command.
+    #[must_use]
+    pub fn env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.env.push((key.into(), value.into()));
+        self
+    }
+
+    #[must_use]
+    pub fn working_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.working_dir = Some(dir.into());
+        self
+    }
+
+    /// Run commands as the given user. Requires the server to have the privilege to switch users.
+    #[cfg(unix)]
+    #[must_use]
+    pub fn uid(mut self, uid: u32) -> Self {
+        self.uid = Some(uid);
+        self
+    }
+
+    /// Run commands with the given group. Requires the server to have the privilege to switch groups.
+    #[cfg(unix)]
+    #[must_use]
+    pub fn gid(mut self, gid: u32) -> Self {
+        self.gid = Some(gid);
+        self
+    }
+
+    /// Limit the CPU time of a command (`RLIMIT_CPU`).
+    #[cfg(unix)]
+    #[must_use]
+    pub fn cpu_seconds(mut self, seconds: u64) -> Self {
+        self.cpu_seconds = Some(seconds);
+        self
+    }
+
+    /// Limit the address space of a command (`RLIMIT_AS`).
+    #[cfg(unix)]
+    #[must_use]
+    pub fn memory_bytes(mut self, bytes: u64) -> Self {
+        self.memory_bytes = Some(bytes);
+        self
+    }
+
+    /// Limit the number of bytes captured from each of stdout and stderr.
+    ///
+    /// Output past the limit is read and discarded so the command is not blocked on a full pipe.
+    #[must_use]
+    pub fn max_output(mut self, bytes: usize) -> Self {
+        self.max_output = Some(bytes);
+        self
+    }
+
+    /// Run commands in a new network namespace using `unshare`, failing them when it
+    /// isn't on the `PATH` rather than running them with network access.
+    #[must_use]
+    pub fn no_network(mut self, no_network: bool) -> Self {
+        self.no_network = no_network;
+        self
+    }
+
+    pub(crate) fn output_limit(&self) -> Option<usize> {
+        self.max_output
+    }
+
+    pub(crate) fn command(&self, request: &Request) -> std::io::Result<Command> {
+        let mut command = self.program(request, std::env::var_os("PATH").as_deref())?;
+
+        command.args(&request.args).env_clear();
+
+        for name in &self.inherit_env {
+            if let Some(value) = std::env::var_os(name) {
+                command.env(name, value);
+            }
+        }
+
+        command.envs(self.env.iter().map(|(k, v)| (k, v)));
+
+        if let Some(dir) = &self.working_dir {
+            command.current_dir(dir);
+        }
+
+        #[cfg(unix)]
+        self.apply_unix(&mut command);
+
+        command
+            .stdin(if request.stdin.is_some() {
+                Stdio::piped()
+            } else {
+                Stdio::null()
+            })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+
+        Ok(command)
+    }
+
+    /// The command itself, or `unshare` running it without network.
+    fn program(&self, request: &Request, path: Option<&OsStr>) -> std::io::Result<Command> {
+        if !self.no_network {
+            return Ok(Command::new(&request.cmd));
+        }
+
+        let Some(unshare) = unshare(path) else {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "unshare is needed to run commands without network",
+            ));
+        };
+
+        let mut command = Command::new(unshare);
+        command
+            .args(["--net", "--map-root-user", "--"])
+            .arg(&request.cmd);
+
+        Ok(command)
+    }
+
+    #[cfg(unix)]
+    fn apply_unix(&self, command: &mut Command) {
+        if let Some(gid) = self.gid {
+            command.gid(gid);
+        }
+
+        if let Some(uid) = self.uid {
+            command.uid(uid);
+        }
+
+        let cpu_seconds = self.cpu_seconds;
+        let memory_bytes = self.memory_bytes;
+
+        if cpu_seconds.is_none() && memory_bytes.is_none() {
+            return;
+        }
+
+        macro_rules! set_rlimit {
+            ($resource:expr, $value:expr) => {{
+                let limit = libc::rlimit {
+                    rlim_cur: $value as libc::rlim_t,
+                    rlim_max: $value as libc::rlim_t,
+                };
+
+                if libc::setrlimit($resource, &limit) != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }};
+        }
+
+        // SAFETY: the closure runs in the forked child before exec and only calls
+        // setrlimit, which is async-signal-safe.
+        unsafe {
+            command.pre_exec(move || {
+                if let Some(seconds) = cpu_seconds {
+                    set_rlimit!(libc::RLIMIT_CPU, seconds);
+                }
+
+                if let Some(bytes) = memory_bytes {
+                    set_rlimit!(libc::RLIMIT_AS, bytes);
+                }
+
+                Ok(())
+            });
+        }
+    }
+}
+
+fn unshare(path: Option<&OsStr>) -> Option<PathBuf> {
+    std::env::split_paths(path?)
+        .map(|dir| dir.join("unshare"))
+        .find(|candidate| candidate.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shell::{Shell as _, process::Shell};
+
+    fn request(cmd: &str, args: &[&str]) -> Request {
+        Request {
+            cmd: cmd.to_string(),
+            args: args.iter().map(|a| a.to_string()).collect(),
+            stdin: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn clears_environment() {
+        let shell =
+            Shell::new().sandbox(Sandbox::new().inherit_env(["PATH"]).env("VISIBLE", "yes"));
+
+        let response = shell.exec(request("env", &[])).await.unwrap();
+        let names: Vec<_> = response
+            .stdout
+            .lines()
+            .filter_map(|line| line.split_once('=').map(|(name, _)| name))
+            .collect();
+
+        assert_eq!(names.len(), 2);
+        assert!(names.contains(&"PATH"));
+        assert!(names.contains(&"VISIBLE"));
+    }
+
+    #[tokio::test]
+    async fn truncates_output() {
+        let shell = Shell::new().sandbox(Sandbox::new().max_output(4));
+
+        let response = shell
+            .exec(request("sh", &["-c", "echo 0123456789; echo abcdefgh >&2"]))
+            .await
+            .unwrap();
+
+        assert_eq!(response.stdout, "0123");
+        assert_eq!(response.stderr, "abcd");
+        assert_eq!(response.code, 0);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn applies_cpu_limit() {
+        let shell = Shell::new().sandbox(Sandbox::new().cpu_seconds(7));
+
+        let response = shell
+            .exec(request("sh", &["-c", "ulimit -t"]))
+            .await
+            .unwrap();
+
+        assert_eq!(response.stdout.trim(), "7");
+    }
+
+    #[test]
+    fn needs_unshare_without_network() {
+        let sandbox = Sandbox::new().no_network(true);
+        let empty = std::env::temp_dir().join("silverbullet-no-unshare");
+
+        let err = sandbox
+            .program(&request("true", &[]), Some(empty.as_os_str()))
+            .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+
+        assert!(
+            Sandbox::new()
+                .program(&request("true", &[]), Some(empty.as_os_str()))
+                .is_ok()
+        );
+    }
+
+    #[tokio::test]
+    async fn sets_working_dir() {
+        let dir = std::env::temp_dir().canonicalize().unwrap();
+        let shell = Shell::new().sandbox(Sandbox::new().working_dir(&dir));
+
+        let response = shell.exec(request("pwd", &[])).await.unwrap();
+
+        assert_eq!(response.stdout.trim(), dir.to_str().unwrap());
+    }
+}
@@ -0,0 +1,564 @@
+//! Server-side rendering of HTML templates
+//!
+//! A [`Renderer`] turns a named template and a JSON context into HTML, whole or as a
+//! stream of chunks for the body of a response. Rendering is async, so renderers can read
+//! what their templates include, e.g. other pages of the space. [`Jinja`] renders
+//! minijinja templates and comes with the built-in ones below, which can be replaced with
+//! [`Jinja::template`], and with the filters and functions of [`helpers`]. More are added
+//! with [`Jinja::filter`], [`Jinja::function`] and [`Jinja::global`] before the renderer
+//! is handed to the server, e.g. [`Builder::boot`](crate::server::Builder::boot).
+//!
+//! | Template | Context |
+//! |---|---|
+//! | `boot.html` | [`Boot`]: the client config and the index page, inlined into the client shell |
+//! | `page.html` | [`View`], with the `sections` and `toc` of the page (see [`toc`]): a page as a standalone document, extending `layout.html` |
+//! | `share.html` | [`Shared`]: a shared page, extending `layout.html` like `page.html`, or the pages of a shared folder |
+//! | `bundle.html` | [`Document`]: pages one after the other, with the `sections` of each, and the files they link |
+//! | `layout.html` | The document of `page.html`, with a nav, a footer and blocks `title`, `head` and `content` |
+//! | `style.css` | Styles of `layout.html`, following the light or dark scheme of the browser |
+//!
+//! The built-in templates need no setup, so a server shows its pages without templates
+//! of its own, and replacing `layout.html` or `style.css` restyles every page.
+//!
+//! The pages of a [`View`] are best [transcluded](transclusion) first, so their `![[page]]`
+//! show the pages they include.
+
+use std::borrow::Cow;
+use std::io;
+
+use async_trait::async_trait;
+use bytes::{Bytes, BytesMut};
+use futures::stream;
+use minijinja::Environment;
+use minijinja::functions::Function;
+use minijinja::value::{FunctionArgs, FunctionResult};
+use serde::Serialize;
+
+use crate::client;
+use crate::fs::{Stream, StreamExt as _};
+
+pub mod helpers;
+pub mod toc;
+pub mod transclusion;
+
+/// The minijinja of [`Jinja`], for the `Value` and `State` of custom filters and functions
+pub use minijinja;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Template not found: {0}")]
+    NotFound(String),
+
+    #[error("Failed to render template: {0}")]
+    Render(#[source] Box<dyn std::error::Error + Send + Sync>),
+}
+
+/// Size of the chunks of [`Jinja::render_stream`]
+const CHUNK: usize = 16 * 1024;
+
+/// Renders named templates to HTML
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+pub trait Renderer: Send + Sync {
+    async fn render(&self, template: &str, context: &serde_json::Value) -> Result<String, Error>;
+
+    /// Render as a body, the whole of [`Renderer::render`] in one chunk by default.
+    ///
+    /// Renderers of large outputs override it to hand out chunks as they're rendered.
+    async fn render_stream(
+        &self,
+        template: &str,
+        context: &serde_json::Value,
+    ) -> Result<Stream, Error> {
+        let html = Bytes::from(self.render(template, context).await?);
+
+        Ok(stream::once(async move { Ok(html) }).into_boxed())
+    }
+}
+
+/// Renderer for minijinja templates
+///
+/// Templates ending in `.html` escape their values, use `|tojson` to embed data in scripts.
+pub struct Jinja {
+    env: Environment<'static>,
+}
+
+impl Default for Jinja {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Jinja {
+    /// Renderer with the built-in templates and [`helpers`].
+    pub fn new() -> Self {
+        let mut env = Environment::new();
+        helpers::register(&mut env);
+
+        for (name, source) in [
+            ("boot.html", include_str!("ssr/templates/boot.html")),
+            ("bundle.html", include_str!("ssr/templates/bundle.html")),
+            ("layout.html", include_str!("ssr/templates/layout.html")),
+            ("page.html", include_str!("ssr/templates/page.html")),
+            ("share.html", include_str!("ssr/templates/share.html")),
+            ("style.css", include_str!("ssr/templates/style.css")),
+        ] {
+            env.add_template(name, source)
+                .expect("built-in templates should parse");
+        }
+
+        Self { env }
+    }
+
+    /// Add a template, or replace a built-in one.
+    pub fn template(
+        mut self,
+        name: impl Into<String>,
+        source: impl Into<String>,
+    ) -> Result<Self, Error> {
+        self.env
+            .add_template_owned(name.into(), source.into())
+            .map_err(|err| Error::Render(err.into()))?;
+
+        Ok(self)
+    }
+
+    /// Add a filter, e.g. `{{ name|shout }}`, or replace a helper.
+    #[must_use]
+    pub fn filter<N, F, Rv, Args>(mut self, name: N, filter: F) -> Self
+    where
+        N: Into<Cow<'static, str>>,
+        F: Function<Rv, Args>,
+        Rv: FunctionResult,
+        Args: for<'a> FunctionArgs<'a>,
+    {
+        self.env.add_filter(name, filter);
+        self
+    }
+
+    /// Add a function, e.g. `{{ shout(name) }}`, or replace a helper.
+    #[must_use]
+    pub fn function<N, F, Rv, Args>(mut self, name: N, function: F) -> Self
+    where
+        N: Into<Cow<'static, str>>,
+        F: Function<Rv, Args>,
+        Rv: FunctionResult,
+        Args: for<'a> FunctionArgs<'a>,
+    {
+        self.env.add_function(name, function);
+        self
+    }
+
+    /// Add a variable of every template, e.g. the `url_prefix` of `url_for`.
+    #[must_use]
+    pub fn global(mut self, name: impl Into<Cow<'static, str>>, value: impl Serialize) -> Self {
+        self.env
+            .add_global(name, minijinja::Value::from_serialize(value));
+        self
+    }
+
+    fn get(&self, name: &str) -> Result<minijinja::Template<'_, '_>, Error> {
+        self.env.get_template(name).map_err(|err| match err.kind() {
+            minijinja::ErrorKind::TemplateNotFound => Error::NotFound(name.to_string()),
+            _ => Error::Render(err.into()),
+        })
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl Renderer for Jinja {
+    async fn render(&self, name: &str, context: &serde_json::Value) -> Result<String, Error> {
+        self.get(name)?
+            .render(context)
+            .map_err(|err| Error::Render(err.into()))
+    }
+
+    /// Render in chunks of 16 KiB, so a large page is never one buffer grown to fit it.
+    async fn render_stream(
+        &self,
+        name: &str,
+        context: &serde_json::Value,
+    ) -> Result<Stream, Error> {
+        let mut chunks = Chunks::default();
+
+        self.get(name)?
+            .render_captured_to(context, &mut chunks)
+            .map_err(|err| Error::Render(err.into()))?;
+        chunks.flush_chunk();
+
+        Ok(stream::iter(chunks.done.into_iter().map(Ok)).into_boxed())
+    }
+}
+
+/// Output of a render, cut into chunks of [`CHUNK`] bytes
+#[derive(Default)]
+struct Chunks {
+    current: BytesMut,
+    done: Vec<Bytes>,
+}
+
+impl Chunks {
+    fn flush_chunk(&mut self) {
+        if !self.current.is_empty() {
+            self.done.push(self.current.split().freeze());
+        }
+    }
+}
+
+impl io::Write for Chunks {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = buf.len().min(CHUNK - self.current.len());
+
+        if self.current.capacity() == 0 {
+            self.current.reserve(CHUNK);
+        }
+        self.current.extend_from_slice(&buf[..len]);
+
+        if self.current.len() == CHUNK {
+            self.flush_chunk();
+        }
+
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Context of the `boot.html` template
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Boot<'a> {
+    /// Config the client would fetch from `/.config`
+    pub config: &'a client::Config,
+    /// Index page, if it exists
+    pub index_page: Option<Page<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Page<'a> {
+    pub name: &'a str,
+    pub content: &'a str,
+}
+
+impl Boot<'_> {
+    /// Render `boot.html` into the `<head>` of the client shell.
+    pub async fn render(&self, renderer: &dyn Renderer, shell: &str) -> Result<String, Error> {
+        let context = json(self)?;
+        let fragment = renderer.render("boot.html", &context).await?;
+
+        // Before the client scripts in the head run, or first thing without a head
+        let at = shell.find("</head>").unwrap_or(0);
+
+        Ok([&shell[..at], &fragment, &shell[at..]].concat())
+    }
+}
+
+/// Context of the `page.html` template
+#[derive(Debug, Serialize)]
+pub struct View<'a> {
+    pub config: &'a client::Config,
+    /// Page to show, if it exists
+    pub page: Option<Page<'a>>,
+}
+
+impl View<'_> {
+    /// Render `page.html`, a whole HTML document.
+    pub async fn render(&self, renderer: &dyn Renderer) -> Result<String, Error> {
+        renderer.render("page.html", &self.context()?).await
+    }
+
+    /// Render `page.html` as the body of a response.
+    pub async fn render_stream(&self, renderer: &dyn Renderer) -> Result<Stream, Error> {
+        renderer.render_stream("page.html", &self.context()?).await
+    }
+
+    fn context(&self) -> Result<serde_json::Value, Error> {
+        with_sections(json(self)?, self.page.as_ref())
+    }
+}
+
+/// Context of the `share.html` template
+#[derive(Debug, Serialize)]
+pub struct Shared<'a> {
+    pub config: &'a client::Config,
+    /// URL of the share, the prefix of `url_for` in the template
+    pub url_prefix: &'a str,
+    /// Shared page, `None` for the index of a shared folder
+    pub page: Option<Page<'a>>,
+    /// Name of the shared folder
+    pub folder: &'a str,
+    /// Pages of the shared folder
+    pub pages: &'a [String],
+    /// Files the page embeds
+    pub attachments: &'a [Attachment],
+}
+
+/// Context of the `bundle.html` template
+#[derive(Debug, Serialize)]
+pub struct Document<'a> {
+    pub config: &'a client::Config,
+    /// Title of the document, e.g. the page it starts from
+    pub title: &'a str,
+    pub pages: &'a [Page<'a>],
+    /// Files the pages link, e.g. embedded as `data:` URLs
+    pub attachments: &'a [Attachment],
+}
+
+impl Document<'_> {
+    /// Render `bundle.html`, a whole HTML document.
+    pub async fn render(&self, renderer: &dyn Renderer) -> Result<String, Error> {
+        let mut context = json(self)?;
+        let pages = self
+            .pages
+            .iter()
+            .map(|page| {
+                serde_json::json!({
+                    "name": page.name,
+                    "id": format!("page-{}", toc::slug(page.name)),
+                    "sections": toc::sections(page.content),
+                })
+            })
+            .collect();
+        context["pages"] = serde_json::Value::Array(pages);
+
+        renderer.render("bundle.html", &context).await
+    }
+}
+
+/// File embedded in a shared page, or linked from a [`Document`]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Attachment {
+    /// Name of the file as the page links it
+    pub name: String,
+    /// URL reading the file without credentials
+    pub url: String,
+    pub image: bool,
+}
+
+impl Shared<'_> {
+    /// Render `share.html` as the body of a response.
+    pub async fn render_stream(&self, renderer: &dyn Renderer) -> Result<Stream, Error> {
+        let context = with_sections(json(self)?, self.page.as_ref())?;
+
+        renderer.render_stream("share.html", &context).await
+    }
+}
+
+/// Add the `sections` and `toc` of the page to a context.
+fn with_sections(
+    mut context: serde_json::Value,
+    page: Option<&Page<'_>>,
+) -> Result<serde_json::Value, Error> {
+    if let Some(page) = page {
+        let sections = toc::sections(page.content);
+
+        context["toc"] = json(toc::toc(&sections))?;
+        context["sections"] = json(sections)?;
+    }
+
+    Ok(context)
+}
+
+fn json(value: impl Serialize) -> Result<serde_json::Value, Error> {
+    serde_json::to_value(value).map_err(|err| Error::Render(err.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHELL: &str = "<html><head><title>SB</title></head><body></body></html>";
+
+    #[tokio::test]
+    async fn inlines_boot_data_into_shell() {
+        let config = client::Config {
+            index_page: "index".to_string(),
+            ..Default::default()
+        };
+
+        let html = Boot {
+            config: &config,
+            index_page: Some(Page {
+                name: "index",
+                content: "# Hello </script><script>alert(1)",
+            }),
+        }
+        .render(&Jinja::new(), SHELL)
+        .await
+        .unwrap();
+
+        assert!(html.starts_with("<html><head><title>SB</title><script"));
+        assert!(html.ends_with("</script></head><body></body></html>"));
+        assert!(html.contains(r#"id="sb-config">{"#));
+        assert!(html.contains(r#""indexPage":"index""#));
+        assert!(html.contains(r#""name":"index""#));
+
+        // Page content can't close the script element
+        assert_eq!(html.matches("</script>").count(), 2);
+
+        let html = Boot {
+            config: &config,
+            index_page: None,
+        }
+        .render(&Jinja::new(), "<p>no head</p>")
+        .await
+        .unwrap();
+
+        assert!(html.starts_with("<script"));
+        assert!(!html.contains("sb-index-page"));
+    }
+
+    #[tokio::test]
+    async fn replaces_templates() {
+        let renderer = Jinja::new()
+            .template(
+                "boot.html",
+                "<meta name=\"index\" content=\"{{ config.indexPage }}\">",
+            )
+            .unwrap();
+
+        let config = client::Config {
+            index_page: "<home>".to_string(),
+            ..Default::default()
+        };
+        let boot = Boot {
+            config: &config,
+            index_page: None,
+        };
+
+        assert_eq!(
+            boot.render(&renderer, "").await.unwrap(),
+            "<meta name=\"index\" content=\"&lt;home&gt;\">"
+        );
+
+        assert!(matches!(
+            renderer
+                .render("missing.html", &serde_json::Value::Null)
+                .await,
+            Err(Error::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn renders_pages_without_templates() {
+        let config = client::Config {
+            index_page: "index".to_string(),
+            space_name: Some("Notes".to_string()),
+            ..Default::default()
+        };
+        let renderer = Jinja::new().global("url_prefix", "/notes");
+
+        let html = View {
+            config: &config,
+            page: Some(Page {
+                name: "Journal/Today",
+                content: "# Today\n<script>alert(1)</script>",
+            }),
+        }
+        .render(&renderer)
+        .await
+        .unwrap();
+
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<title>Journal&#x2f;Today - Notes</title>"));
+        assert!(html.contains("prefers-color-scheme: dark"));
+        assert!(html.contains(r#"<a class="space" href="/notes/index">Notes</a>"#));
+        assert!(html.contains("&lt;script&gt;alert(1)&lt;&#x2f;script&gt;"));
+        assert!(html.contains("<footer>"));
+
+        let html = View {
+            config: &config,
+            page: None,
+        }
+        .render(&renderer)
+        .await
+        .unwrap();
+
+        assert!(html.contains("<code>index.md</code>"));
+    }
+
+    #[tokio::test]
+    async fn links_headings() {
+        let config = client::Config::default();
+        let html = View {
+            config: &config,
+            page: Some(Page {
+                name: "Guide",
+                content: "# Setup\nsteps\n## Setup\nmore",
+            }),
+        }
+        .render(&Jinja::new())
+        .await
+        .unwrap();
+
+        assert!(html.contains(r##"<li class="toc-1"><a href="#setup">Setup</a></li>"##));
+        assert!(html.contains(r##"<li class="toc-2"><a href="#setup-1">Setup</a></li>"##));
+        assert!(html.contains(r#"<h2 id="setup-1">Setup</h2>"#));
+        assert!(html.contains(r#"<div class="content">more</div>"#));
+
+        // No table of contents for a single heading
+        let html = View {
+            config: &config,
+            page: Some(Page {
+                name: "Guide",
+                content: "# Setup\nsteps",
+            }),
+        }
+        .render(&Jinja::new())
+        .await
+        .unwrap();
+
+        assert!(!html.contains(r#"class="toc""#));
+    }
+
+    #[tokio::test]
+    async fn adds_filters_functions_and_globals() {
+        let renderer = Jinja::new()
+            .filter("shout", |value: &str| value.to_uppercase())
+            .function("greet", |name: &str| format!("Hello {name}"))
+            .global("url_prefix", "/notes")
+            .template(
+                "page.html",
+                "{{ greet(name|shout) }} <a href=\"{{ url_for(name) }}\">",
+            )
+            .unwrap();
+
+        let context = serde_json::json!({ "name": "index" });
+        assert_eq!(
+            renderer.render("page.html", &context).await.unwrap(),
+            "Hello INDEX <a href=\"/notes/index\">"
+        );
+    }
+
+    #[tokio::test]
+    async fn streams_in_chunks() {
+        use futures::TryStreamExt as _;
+
+        let renderer = Jinja::new()
+            .template("big.html", "{% for _ in range(n) %}0123456789{% endfor %}")
+            .unwrap();
+        let context = serde_json::json!({ "n": 5000 });
+
+        let chunks: Vec<Bytes> = renderer
+            .render_stream("big.html", &context)
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+
+        assert_eq!(chunks.len(), 4);
+        assert!(chunks[..3].iter().all(|chunk| chunk.len() == CHUNK));
+        assert_eq!(
+            chunks.concat(),
+            renderer
+                .render("big.html", &context)
+                .await
+                .unwrap()
+                .as_bytes()
+        );
+    }
+}
@@ -0,0 +1,182 @@
+//! Filters and functions of every [`Jinja`](super::Jinja) renderer
+//!
+//! | Name | Kind | Result |
+//! |---|---|---|
+//! | `url_for(name)` | function | URL of a page or file, percent-encoded, under the `url_prefix` global or variable |
+//! | `asset_hash(path)` | function | Short hash of a bundled client asset, to bust caches, with `client-assets` |
+//! | `date(format="%Y-%m-%d")` | filter | Time in milliseconds, e.g. `lastModified`, in UTC |
+//! | `excerpt(length=200)` | filter | Text on a single line, cut at a word with an ellipsis |
+//! | `slug` | filter | Anchor of a heading, as the ids of [`toc`](super::toc) |
+//!
+//! `date` formats with `%Y`, `%m`, `%d`, `%H`, `%M`, `%S`, `%b` (`Oct`) and `%%`.
+
+use minijinja::{Environment, Error, ErrorKind, State, Value};
+
+use crate::fs::time;
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+pub(super) fn register(env: &mut Environment<'static>) {
+    env.add_function("url_for", url_for);
+    #[cfg(feature = "client-assets")]
+    env.add_function("asset_hash", asset_hash);
+    env.add_filter("date", date);
+    env.add_filter("excerpt", excerpt);
+    env.add_filter("slug", |title: &str| super::toc::slug(title));
+}
+
+/// Safe to embed, every segment being percent-encoded, the prefix too.
+fn url_for(state: &State, name: &str) -> Value {
+    let prefix = state.lookup("url_prefix");
+    let prefix = prefix
+        .as_ref()
+        .and_then(|prefix| prefix.as_str())
+        .unwrap_or("");
+    let mut url = String::new();
+
+    for segment in prefix.split('/').filter(|segment| !segment.is_empty()) {
+        url.push('/');
+        url.push_str(&crate::percent::encode(segment));
+    }
+
+    for segment in name.trim_start_matches('/').split('/') {
+        url.push('/');
+        url.push_str(&crate::percent::encode(segment));
+    }
+
+    Value::from_safe_string(url)
+}
+
+/// First 8 hex digits of the SHA-256 of an asset, `None` (undefined) if not bundled.
+#[cfg(feature = "client-assets")]
+fn asset_hash(path: &str) -> Option<String> {
+    use crate::server::routes::assets::Assets;
+
+    let file = <Assets as rust_embed::Embed>::get(path.trim_start_matches('/'))?;
+
+    Some(
+        file.metadata.sha256_hash()[..4]
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect(),
+    )
+}
+
+fn date(millis: u64, format: Option<&str>) -> Result<String, Error> {
+    let secs = time::as_secs(millis);
+    let (year, month, day) = time::civil_from_days((secs / 86_400) as i64);
+    let of_day = secs % 86_400;
+
+    let mut out = String::new();
+    let mut chars = format.unwrap_or("%Y-%m-%d").chars();
+
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('Y') => out.push_str(&format!("{year:04}")),
+            Some('m') => out.push_str(&format!("{month:02}")),
+            Some('d') => out.push_str(&format!("{day:02}")),
+            Some('H') => out.push_str(&format!("{:02}", of_day / 3600)),
+            Some('M') => out.push_str(&format!("{:02}", of_day % 3600 / 60)),
+            Some('S') => out.push_str(&format!("{:02}", of_day % 60)),
+            Some('b') => out.push_str(MONTHS[usize::from(month - 1)]),
+            Some('%') => out.push('%'),
+            other => {
+                return Err(Error::new(
+                    ErrorKind::InvalidOperation,
+                    format!("unsupported date directive %{}", other.unwrap_or(' ')),
+                ));
+            }
+        }
+    }
+
+    Ok(out)
+}
+
+fn excerpt(text: &str, length: Option<usize>) -> String {
+    let length = length.unwrap_or(200);
+    let mut out = String::new();
+    let mut chars = 0;
+
+    for word in text.split_whitespace() {
+        let separator = usize::from(!out.is_empty());
+        let len = word.chars().count();
+
+        if chars + separator + len > length {
+            // A first word longer than the excerpt is cut within it
+            if out.is_empty() {
+                out.extend(word.chars().take(length));
+            }
+            out.push('…');
+            return out;
+        }
+
+        if separator == 1 {
+            out.push(' ');
+        }
+        out.push_str(word);
+        chars += separator + len;
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(source: &str, context: serde_json::Value) -> String {
+        let mut env = Environment::new();
+        register(&mut env);
+
+        env.render_str(source, context).unwrap()
+    }
+
+    #[test]
+    fn builds_urls() {
+        let context = serde_json::json!({});
+        assert_eq!(
+            render("{{ url_for('Journal/Café ☕') }}", context),
+            "/Journal/Caf%C3%A9%20%E2%98%95"
+        );
+
+        let context = serde_json::json!({ "url_prefix": "/notes/" });
+        assert_eq!(render("{{ url_for('/index') }}", context), "/notes/index");
+    }
+
+    #[test]
+    fn formats_dates() {
+        let context = serde_json::json!({ "at": 1_791_979_200_123u64 });
+
+        assert_eq!(render("{{ at|date }}", context.clone()), "2026-10-14");
+        assert_eq!(
+            render("{{ at|date('%d %b %Y, %H:%M:%S %%') }}", context.clone()),
+            "14 Oct 2026, 12:00:00 %"
+        );
+
+        let mut env = Environment::new();
+        register(&mut env);
+        assert!(env.render_str("{{ at|date('%A') }}", context).is_err());
+    }
+
+    #[test]
+    fn cuts_excerpts() {
+        let context = serde_json::json!({ "text": "# Hello\n\nthere  world", "long": "abcdef" });
+
+        assert_eq!(
+            render("{{ text|excerpt }}", context.clone()),
+            "# Hello there world"
+        );
+        assert_eq!(
+            render("{{ text|excerpt(14) }}", context.clone()),
+            "# Hello there…"
+        );
+        assert_eq!(render("{{ long|excerpt(3) }}", context), "abc…");
+    }
+}
@@ -0,0 +1,154 @@
+//! Headings of a page, with their anchors
+//!
+//! [`sections`] cuts a page at its headings, outside fenced code, and gives every heading
+//! an id from [`slug`], unique within the page: the second `## Notes` is `notes-1`. The
+//! `page.html` template renders the sections with those ids and links them from a table
+//! of contents, so `Page#notes` deep links into a published page.
+//!
+//! Slugs are the lowercased title, its letters and digits kept, its spaces and hyphens
+//! turned into a single hyphen and the rest dropped: `Q&A: Setup 2` is `qa-setup-2`.
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Heading {
+    pub level: u8,
+    pub title: String,
+    /// Anchor of the heading, without the `#`
+    pub id: String,
+}
+
+/// Heading and the text under it, up to the next heading
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Section {
+    /// `None` for the text before the first heading
+    pub heading: Option<Heading>,
+    /// Text without the blank lines around it
+    pub text: String,
+}
+
+/// Anchor of a heading titled `title`.
+pub fn slug(title: &str) -> String {
+    let mut slug = String::with_capacity(title.len());
+
+    for c in title.chars() {
+        if c.is_alphanumeric() {
+            slug.extend(c.to_lowercase());
+        } else if (c.is_whitespace() || c == '-') && !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+
+    slug.trim_end_matches('-').to_string()
+}
+
+/// Level and title of a heading line.
+pub(crate) fn heading(line: &str) -> Option<(usize, &str)> {
+    let level = line.bytes().take_while(|&byte| byte == b'#').count();
+
+    match (1..=6).contains(&level) {
+        true => line[level..]
+            .strip_prefix(' ')
+            .map(|title| (level, title.trim())),
+        false => None,
+    }
+}
+
+/// Sections of a page, the text before its first heading being the first if not blank.
+pub fn sections(content: &str) -> Vec<Section> {
+    let mut sections = Vec::new();
+    let mut seen = HashMap::new();
+    let mut current = Section {
+        heading: None,
+        text: String::new(),
+    };
+    let mut fenced = false;
+
+    for line in content.split_inclusive('\n') {
+        if line.trim_start().starts_with("```") {
+            fenced = !fenced;
+        }
+
+        let found = match fenced {
+            true => None,
+            false => heading(line.trim_end()),
+        };
+
+        let Some((level, title)) = found else {
+            current.text.push_str(line);
+            continue;
+        };
+
+        let base = match slug(title) {
+            slug if slug.is_empty() => "section".to_string(),
+            slug => slug,
+        };
+        let count = seen.entry(base.clone()).or_insert(0);
+        let id = match *count {
+            0 => base,
+            n => format!("{base}-{n}"),
+        };
+        *count += 1;
+
+        let next = Section {
+            heading: Some(Heading {
+                level: level as u8,
+                title: title.to_string(),
+                id,
+            }),
+            text: String::new(),
+        };
+        sections.push(std::mem::replace(&mut current, next));
+    }
+    sections.push(current);
+
+    for section in &mut sections {
+        section.text = section.text.trim_matches('\n').to_string();
+    }
+    sections.retain(|section| section.heading.is_some() || !section.text.trim().is_empty());
+
+    sections
+}
+
+/// Headings of the sections, for a table of contents.
+pub fn toc(sections: &[Section]) -> Vec<&Heading> {
+    sections
+        .iter()
+        .filter_map(|section| section.heading.as_ref())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slugifies_titles() {
+        assert_eq!(slug("Q&A: Setup 2"), "qa-setup-2");
+        assert_eq!(slug("  Café -- Crème  "), "café-crème");
+        assert_eq!(slug("!!!"), "");
+    }
+
+    #[test]
+    fn cuts_pages_at_headings() {
+        let content = "intro\n\n# Notes\none\n```\n# not a heading\n```\n## Notes\n\ntwo\n# !!\n";
+        let sections = sections(content);
+
+        let ids: Vec<_> = toc(&sections)
+            .iter()
+            .map(|heading| (heading.level, heading.id.as_str()))
+            .collect();
+        assert_eq!(ids, [(1, "notes"), (2, "notes-1"), (1, "section")]);
+
+        assert_eq!(sections[0].heading, None);
+        assert_eq!(sections[0].text, "intro");
+        assert_eq!(sections[1].text, "one\n```\n# not a heading\n```");
+        assert_eq!(sections[2].text, "two");
+        assert_eq!(sections[3].text, "");
+
+        // Without text before the first heading, the first section is a heading
+        assert!(super::sections("# Title\n")[0].heading.is_some());
+    }
+}
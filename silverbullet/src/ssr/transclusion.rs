@@ -0,0 +1,229 @@
+//! Transclusion of pages, SilverBullet's `![[page]]`
+//!
+//! [`transclude`] replaces every `![[page]]` of a page with the content of `page.md`, and
+//! `![[page#Heading]]` with the section under that heading, so a page rendered on the
+//! server shows what the editor shows. Included pages are transcluded in turn, up to
+//! [`MAX_DEPTH`] pages deep, and a page including itself, directly or not, is left as
+//! written at the repeat. So are pages that don't exist, and embeds in fenced code.
+//!
+//! `![[page|alias]]` includes `page`, the alias being only shown by the editor.
+
+use futures::TryStreamExt as _;
+
+use super::toc::heading;
+use crate::fs::{Error, ReadOnlyFilesystem, Result};
+
+/// Pages included within each other at most
+pub const MAX_DEPTH: usize = 8;
+
+/// Page being expanded
+struct Frame {
+    name: String,
+    text: String,
+    at: usize,
+    fenced: bool,
+}
+
+impl Frame {
+    fn new(name: String, text: String) -> Self {
+        Self {
+            name,
+            text,
+            at: 0,
+            fenced: false,
+        }
+    }
+
+    /// Range of the next embed outside fenced code, from the current position.
+    fn next_embed(&mut self) -> Option<(usize, usize)> {
+        let text = self.text.as_str();
+
+        while self.at < text.len() {
+            let end = text[self.at..]
+                .find('\n')
+                .map_or(text.len(), |i| self.at + i + 1);
+            let line = &text[self.at..end];
+            let line_start = self.at == 0 || text.as_bytes()[self.at - 1] == b'\n';
+
+            if line_start && line.trim_start().starts_with("```") {
+                self.fenced = !self.fenced;
+            } else if !self.fenced
+                && let Some(start) = line.find("![[")
+                && let Some(len) = line[start + 3..].find("]]")
+            {
+                let start = self.at + start;
+                return Some((start, start + 3 + len + 2));
+            }
+
+            self.at = end;
+        }
+
+        None
+    }
+}
+
+/// Content of `page` with its transclusions expanded, `content` being its text.
+pub async fn transclude<F>(fs: &F, page: &str, content: &str) -> Result<String>
+where
+    F: ReadOnlyFilesystem + ?Sized,
+{
+    let mut out = String::with_capacity(content.len());
+    let mut stack = vec![Frame::new(page.to_string(), content.to_string())];
+
+    while let Some(frame) = stack.last_mut() {
+        let from = frame.at;
+
+        let Some((start, end)) = frame.next_embed() else {
+            out.push_str(&frame.text[from..]);
+            stack.pop();
+            continue;
+        };
+
+        out.push_str(&frame.text[from..start]);
+        frame.at = end;
+
+        let embed = frame.text[start..end].to_string();
+        let target = &embed[3..embed.len() - 2];
+        let target = target.split_once('|').map_or(target, |(target, _)| target);
+        let (name, heading) = match target.split_once('#') {
+            Some((name, heading)) => (name.trim(), Some(heading.trim())),
+            None => (target.trim(), None),
+        };
+
+        // A repeat is a cycle, unlike twice the same page side by side
+        let repeat = stack.iter().any(|frame| frame.name == name);
+        let included = match name.is_empty() || repeat || stack.len() > MAX_DEPTH {
+            true => None,
+            false => read(fs, name).await?,
+        };
+        let included = included.and_then(|text| match heading {
+            Some(heading) => section(&text, heading).map(str::to_string),
+            None => Some(strip_frontmatter(&text).to_string()),
+        });
+
+        match included {
+            Some(text) => stack.push(Frame::new(name.to_string(), text)),
+            None => out.push_str(&embed),
+        }
+    }
+
+    Ok(out)
+}
+
+/// Text of a page, `None` if it doesn't exist or isn't UTF-8.
+async fn read<F>(fs: &F, name: &str) -> Result<Option<String>>
+where
+    F: ReadOnlyFilesystem + ?Sized,
+{
+    let stream = match fs.get(&format!("{name}.md")).await {
+        Ok((stream, _)) => stream,
+        Err(Error::NotFound(_)) => return Ok(None),
+        Err(err) => return Err(err),
+    };
+
+    let bytes = stream
+        .try_fold(Vec::new(), |mut acc, chunk| async move {
+            acc.extend_from_slice(&chunk);
+            Ok(acc)
+        })
+        .await?;
+
+    Ok(String::from_utf8(bytes).ok())
+}
+
+fn strip_frontmatter(text: &str) -> &str {
+    let Some(rest) = text.strip_prefix("---\n") else {
+        return text;
+    };
+
+    match rest.find("\n---\n") {
+        Some(end) => &rest[end + 5..],
+        None => text,
+    }
+}
+
+/// The heading line titled `title` and the lines under it, up to the next heading at its
+/// level or above.
+fn section<'a>(text: &'a str, title: &str) -> Option<&'a str> {
+    let mut start = None;
+    let mut at = 0;
+
+    for line in text.split_inclusive('\n') {
+        match (start, heading(line.trim_end())) {
+            (None, Some((level, found))) if found == title => start = Some((at, level)),
+            (Some((from, level)), Some((next, _))) if next <= level => {
+                return Some(&text[from..at]);
+            }
+            _ => {}
+        }
+
+        at += line.len();
+    }
+
+    start.map(|(from, _)| &text[from..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::testing::MemoryFs;
+
+    #[tokio::test]
+    async fn includes_pages() {
+        let fs = MemoryFs::new()
+            .with_file("Header.md", b"---\ntags: nav\n---\n# Header ![[Links]]")
+            .with_file("Links/Index.md", b"[[home]]")
+            .with_file("Links.md", b"![[Links/Index]]")
+            .with_file(
+                "Doc.md",
+                b"intro\n## Usage\nrun it\n### Flags\n-v\n## Misc\nmore",
+            );
+
+        let page = "![[Header]]\n![[Doc#Usage]] and ![[Missing|alias]] ![[Doc#None]]";
+        assert_eq!(
+            transclude(&fs, "index", page).await.unwrap(),
+            "# Header [[home]]\n## Usage\nrun it\n### Flags\n-v\n and ![[Missing|alias]] ![[Doc#None]]"
+        );
+
+        // Twice the same page isn't a cycle
+        assert_eq!(
+            transclude(&fs, "index", "![[Links]] ![[Links]]")
+                .await
+                .unwrap(),
+            "[[home]] [[home]]"
+        );
+    }
+
+    #[tokio::test]
+    async fn stops_at_cycles_and_depth() {
+        let fs = MemoryFs::new()
+            .with_file("A.md", b"a ![[B]]")
+            .with_file("B.md", b"b ![[A]] ![[index]]");
+
+        assert_eq!(
+            transclude(&fs, "index", "![[A]]").await.unwrap(),
+            "a b ![[A]] ![[index]]"
+        );
+
+        let mut fs = MemoryFs::new();
+        for depth in 0..20 {
+            let page = format!("Level {depth}.md");
+            let text = format!("{depth} ![[Level {}]]", depth + 1);
+            fs = fs.with_file(&page, text.as_bytes());
+        }
+
+        let out = transclude(&fs, "index", "![[Level 0]]").await.unwrap();
+        assert!(out.ends_with(&format!("{} ![[Level {}]]", MAX_DEPTH - 1, MAX_DEPTH)));
+    }
+
+    #[tokio::test]
+    async fn leaves_code_alone() {
+        let fs = MemoryFs::new().with_file("A.md", b"a");
+
+        let page = "```md\n![[A]]\n```\n![[A]]";
+        assert_eq!(
+            transclude(&fs, "index", page).await.unwrap(),
+            "```md\n![[A]]\n```\na"
+        );
+    }
+}
@@ -0,0 +1,352 @@
+//! Daily totals of a space, to see how it grows
+//!
+//! [`Stats::attach`] wraps the space in a [`Counted`] filesystem counting its writes, and
+//! [`Stats::record`] keeps the totals of the day, in UTC, in a hidden file of the space,
+//! `.stats.json` by default: its pages, attachments and bytes, and the writes since the
+//! day started. Recording again the same day updates its totals, so record periodically
+//! with [`Stats::run`] or a `stats` job. The writes not recorded yet are lost on restart.
+//!
+//! The server serves the history, with the current totals of today, at `GET /.stats`.
+//! Hidden files, like the history itself, are left out of the totals and the writes.
+//!
+//! ```ignore
+//! let stats = Stats::new(space.clone());
+//! let space = Arc::new(stats.attach(space));
+//! tokio::spawn(stats.clone().run(Duration::from_secs(60 * 60)));
+//! ```
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::TryStreamExt as _;
+use serde::{Deserialize, Serialize};
+
+use crate::fs::{
+    self, FileMeta, FileStream, IncomingFileMeta, ReadOnlyFilesystem, ReadWriteFilesystem,
+    WritableFilesystem, time,
+};
+
+/// Totals of a space on a day
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Day {
+    /// Date in UTC, e.g. `2026-10-14`
+    pub date: String,
+    /// Markdown files, as of the last record of the day
+    pub pages: usize,
+    /// Other files, as of the last record of the day
+    pub attachments: usize,
+    /// Total size in bytes, as of the last record of the day
+    pub bytes: u64,
+    /// Files written or deleted during the day
+    pub writes: u64,
+}
+
+/// Recorder of the daily totals of a space
+#[derive(Clone)]
+pub struct Stats {
+    fs: Arc<dyn ReadWriteFilesystem>,
+    file: String,
+    keep: usize,
+    writes: Arc<AtomicU64>,
+}
+
+impl Stats {
+    /// Totals of `fs`, recorded in it.
+    pub fn new(fs: Arc<dyn ReadWriteFilesystem>) -> Self {
+        Self {
+            fs,
+            file: ".stats.json".to_string(),
+            keep: 365,
+            writes: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Hidden file of the space the history is kept in (`.stats.json` by default).
+    #[must_use]
+    pub fn file(mut self, name: impl Into<String>) -> Self {
+        self.file = name.into();
+        self
+    }
+
+    /// Days of history kept, the older ones being dropped (365 by default).
+    #[must_use]
+    pub fn keep(mut self, days: usize) -> Self {
+        self.keep = days.max(1);
+        self
+    }
+
+    /// Wrap the space, its writes being counted in the totals of the day.
+    pub fn attach<F>(&self, fs: F) -> Counted<F> {
+        Counted {
+            inner: fs,
+            writes: self.writes.clone(),
+        }
+    }
+
+    /// Record the totals of today, returned.
+    pub async fn record(&self) -> fs::Result<Day> {
+        self.record_on(today()).await
+    }
+
+    async fn record_on(&self, date: String) -> fs::Result<Day> {
+        let mut days = self.recorded().await?;
+        let writes = self.writes.swap(0, Ordering::Relaxed);
+
+        let result = async {
+            let day = self.totals(date, writes).await?;
+            merge(&mut days, day.clone(), self.keep);
+
+            let json = serde_json::to_vec(&days).map_err(|err| fs::Error::Other(err.into()))?;
+            let data = futures::stream::once(async { Ok(Bytes::from(json)) });
+            let meta = IncomingFileMeta {
+                content_type: Some("application/json".to_string()),
+                ..Default::default()
+            };
+            self.fs.put(&self.file, Box::pin(data), meta).await?;
+
+            Ok(days.pop().unwrap_or(day))
+        }
+        .await;
+
+        // Counted in the next record instead
+        if result.is_err() {
+            self.writes.fetch_add(writes, Ordering::Relaxed);
+        }
+
+        #[cfg(feature = "tracing")]
+        if let Ok(day) = &result {
+            tracing::info!(
+                pages = day.pages,
+                attachments = day.attachments,
+                bytes = day.bytes,
+                writes = day.writes,
+                "Recorded space stats"
+            );
+        }
+
+        result
+    }
+
+    /// The recorded days, oldest first, followed by the current totals of today.
+    pub async fn history(&self) -> fs::Result<Vec<Day>> {
+        let mut days = self.recorded().await?;
+        let day = self
+            .totals(today(), self.writes.load(Ordering::Relaxed))
+            .await?;
+        merge(&mut days, day, self.keep);
+
+        Ok(days)
+    }
+
+    /// Record the totals forever, every `interval`.
+    ///
+    /// Failures are logged with the `tracing` feature and retried at the next interval.
+    pub async fn run(self, interval: Duration) {
+        loop {
+            futures_timer::Delay::new(interval).await;
+
+            #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+            if let Err(err) = self.record().await {
+                #[cfg(feature = "tracing")]
+                tracing::error!(error = %err, "Recording space stats failed");
+            }
+        }
+    }
+
+    async fn recorded(&self) -> fs::Result<Vec<Day>> {
+        let stream = match self.fs.get(&self.file).await {
+            Ok((stream, _)) => stream,
+            Err(fs::Error::NotFound(_)) => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+        let bytes = stream
+            .try_fold(Vec::new(), |mut bytes, chunk| async move {
+                bytes.extend_from_slice(&chunk);
+                Ok(bytes)
+            })
+            .await
+            .map_err(fs::Error::from)?;
+
+        serde_json::from_slice(&bytes).map_err(|err| fs::Error::Other(err.into()))
+    }
+
+    async fn totals(&self, date: String, writes: u64) -> fs::Result<Day> {
+        let files = self.fs.list().await?;
+        let files: Vec<&FileMeta> = files.iter().filter(|meta| !hidden(&meta.name)).collect();
+        let pages = files
+            .iter()
+            .filter(|meta| meta.name.ends_with(".md"))
+            .count();
+
+        Ok(Day {
+            date,
+            pages,
+            attachments: files.len() - pages,
+            bytes: files.iter().map(|meta| meta.size).sum(),
+            writes,
+        })
+    }
+}
+
+/// Add `day` to the history, or update it when already there, keeping the last `keep`.
+fn merge(days: &mut Vec<Day>, mut day: Day, keep: usize) {
+    match days.last_mut() {
+        Some(last) if last.date == day.date => {
+            day.writes += last.writes;
+            *last = day;
+        }
+        _ => days.push(day),
+    }
+
+    let excess = days.len().saturating_sub(keep);
+    days.drain(..excess);
+}
+
+fn hidden(name: &str) -> bool {
+    name.split('/').any(|segment| segment.starts_with('.'))
+}
+
+/// Today in UTC, e.g. `2026-10-14`.
+fn today() -> String {
+    let (year, month, day) = time::civil_from_days((time::now() / 86_400_000) as i64);
+
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+/// Space counting its writes for the [`Stats`]
+pub struct Counted<F> {
+    inner: F,
+    writes: Arc<AtomicU64>,
+}
+
+impl<F> Counted<F> {
+    fn count(&self, path: &str) {
+        if !hidden(path) {
+            self.writes.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl<F> ReadOnlyFilesystem for Counted<F>
+where
+    F: ReadOnlyFilesystem,
+{
+    async fn list(&self) -> fs::Result<Vec<FileMeta>> {
+        self.inner.list().await
+    }
+
+    async fn get(&self, path: &str) -> fs::Result<(fs::Stream, FileMeta)> {
+        self.inner.get(path).await
+    }
+
+    async fn meta(&self, path: &str) -> fs::Result<FileMeta> {
+        self.inner.meta(path).await
+    }
+
+    async fn list_stream(&self) -> fs::Result<FileStream> {
+        self.inner.list_stream().await
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl<F> WritableFilesystem for Counted<F>
+where
+    F: WritableFilesystem,
+{
+    async fn put(
+        &self,
+        path: &str,
+        data: fs::Stream,
+        meta: IncomingFileMeta,
+    ) -> fs::Result<FileMeta> {
+        let meta = self.inner.put(path, data, meta).await?;
+        self.count(path);
+
+        Ok(meta)
+    }
+
+    async fn delete(&self, path: &str) -> fs::Result<()> {
+        self.inner.delete(path).await?;
+        self.count(path);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::testing::MemoryFs;
+
+    async fn write(fs: &dyn ReadWriteFilesystem, name: &str, content: &'static [u8]) {
+        let data = futures::stream::once(async move { Ok(Bytes::from_static(content)) });
+        fs.put(name, Box::pin(data), IncomingFileMeta::default())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn records_daily_totals() {
+        let storage: Arc<dyn ReadWriteFilesystem> = Arc::new(
+            MemoryFs::new()
+                .with_file("index.md", b"# Home")
+                .with_file("photo.png", b"png"),
+        );
+        let stats = Stats::new(storage.clone()).keep(2);
+        let space = stats.attach(storage);
+
+        write(&space, "Journal/today.md", b"today").await;
+        write(&space, ".hidden/state.json", b"{}").await;
+        let day = stats.record_on("2026-10-12".to_string()).await.unwrap();
+        assert_eq!(
+            day,
+            Day {
+                date: "2026-10-12".to_string(),
+                pages: 2,
+                attachments: 1,
+                bytes: 14,
+                writes: 1,
+            }
+        );
+
+        // Later the same day
+        space.delete("photo.png").await.unwrap();
+        let day = stats.record_on("2026-10-12".to_string()).await.unwrap();
+        assert_eq!((day.attachments, day.bytes, day.writes), (0, 11, 2));
+
+        stats.record_on("2026-10-13".to_string()).await.unwrap();
+        write(&space, "index.md", b"# Home!").await;
+
+        let days = stats.history().await.unwrap();
+        let dates: Vec<_> = days.iter().map(|day| day.date.as_str()).collect();
+        assert_eq!(dates, ["2026-10-13", today().as_str()]);
+        assert_eq!(days[0].writes, 0);
+        assert_eq!(days[1].writes, 1);
+        assert_eq!(days[1].bytes, 12);
+    }
+
+    #[test]
+    fn merges_days() {
+        let day = |date: &str, writes| Day {
+            date: date.to_string(),
+            pages: 0,
+            attachments: 0,
+            bytes: 0,
+            writes,
+        };
+
+        let mut days = vec![day("2026-10-12", 3)];
+        merge(&mut days, day("2026-10-12", 2), 10);
+        assert_eq!(days, [day("2026-10-12", 5)]);
+
+        merge(&mut days, day("2026-10-13", 1), 1);
+        assert_eq!(days, [day("2026-10-13", 1)]);
+    }
+}
@@ -0,0 +1,6 @@
+//! Mirrors of a space kept in sync with other places
+//!
+//! [`git::GitSync`] mirrors any space to a git remote, e.g. a space stored in S3 to a
+//! GitHub repository, pulling and pushing on a schedule or on demand.
+
+pub mod git;
@@ -0,0 +1,665 @@
+//! Mirror of a space to a git remote, through a local clone
+//!
+//! A sync copies the files of the space changed since the previous sync into the clone
+//! and commits them, pulls the remote, copies what the pull changed back into the space,
+//! then pushes. Both sides may change between syncs: git merges their changes, keeping
+//! those of the [`Strategy`] where they overlap, or stopping the sync.
+//!
+//! ```ignore
+//! let sync = GitSync::new(space, checkout, shell, "/var/lib/silverbullet/git")
+//!     .remote("git@github.com:me/notes.git")
+//!     .strategy(Strategy::Remote);
+//!
+//! tokio::spawn(sync.clone().run(Duration::from_secs(15 * 60)));
+//! ```
+//!
+//! `git` runs with the [`Shell`], e.g. the process shell, in the clone at its path, and
+//! the files of the clone are read and written with a filesystem of the same folder, so
+//! the clone is on a disk of the server while the space may be stored anywhere, e.g. in
+//! S3. What was synced last is kept in the space as `.silverbullet-git.json`. Files named
+//! `.silverbullet-*`, like it, aren't synced, and neither are files in a `.git` folder,
+//! which would let writers of the space configure the commands git runs.
+
+use std::collections::BTreeSet;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use bytes::Bytes;
+use futures::{TryStreamExt as _, stream};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::fs::{self, FileMeta, IncomingFileMeta, ReadWriteFilesystem, StreamExt as _};
+use crate::shell::{self, Shell};
+
+const STATE: &str = ".silverbullet-git.json";
+
+/// Tree without any file, what the first commit of a clone is diffed against
+const EMPTY_TREE: &str = "4b825dc642cb6eb9a060e54bf8d69288fbee4904";
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("git {command} exited with {code}: {output}")]
+    Git {
+        command: String,
+        code: u16,
+        output: String,
+    },
+
+    /// The pull had conflicts with [`Strategy::Stop`], or ones git can't resolve, e.g. a
+    /// file changed on one side and deleted on the other
+    #[error("Conflicting changes, resolve them in the clone: {0}")]
+    Conflict(String),
+
+    #[error(transparent)]
+    Shell(#[from] shell::Error),
+
+    #[error(transparent)]
+    Fs(#[from] fs::Error),
+}
+
+/// Changes kept where the space and the remote changed the same lines
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Strategy {
+    /// Those of the space
+    #[default]
+    Space,
+    /// Those of the remote
+    Remote,
+    /// None, the sync stops until the conflict is resolved in the clone
+    Stop,
+}
+
+impl FromStr for Strategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "space" => Ok(Strategy::Space),
+            "remote" => Ok(Strategy::Remote),
+            "stop" => Ok(Strategy::Stop),
+            other => Err(format!(
+                "unsupported conflict strategy: {other}, expected space, remote or stop"
+            )),
+        }
+    }
+}
+
+/// Outcome of a sync
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct Report {
+    /// Files of the space committed to the clone
+    pub committed: usize,
+    /// Files of the space written or deleted with the changes of the remote
+    pub pulled: usize,
+    /// Whether commits were pushed to the remote
+    pub pushed: bool,
+    /// Commit of the clone after the sync
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub head: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct State {
+    /// Time the space was listed at by the previous sync, in milliseconds since the epoch
+    synced: u64,
+    /// Files of the space after the previous sync
+    files: BTreeSet<String>,
+    /// Commit of the clone after the previous sync, none before the first one
+    commit: Option<String>,
+}
+
+/// Syncs a space with a git remote
+///
+/// Clones share a lock, so syncs never run concurrently.
+#[derive(Clone)]
+pub struct GitSync {
+    space: Arc<dyn ReadWriteFilesystem>,
+    checkout: Arc<dyn ReadWriteFilesystem>,
+    shell: Arc<dyn Shell>,
+    path: String,
+    remote: Option<String>,
+    branch: Option<String>,
+    strategy: Strategy,
+    message: String,
+    author: (String, String),
+    lock: Arc<futures::lock::Mutex<()>>,
+}
+
+impl GitSync {
+    /// Sync of `space` through the clone at `path`, whose files `checkout` reads and writes.
+    pub fn new(
+        space: Arc<dyn ReadWriteFilesystem>,
+        checkout: Arc<dyn ReadWriteFilesystem>,
+        shell: Arc<dyn Shell>,
+        path: impl Into<String>,
+    ) -> Self {
+        Self {
+            space,
+            checkout,
+            shell,
+            path: path.into(),
+            remote: None,
+            branch: None,
+            strategy: Strategy::default(),
+            message: "Update space".to_string(),
+            author: (
+                "SilverBullet".to_string(),
+                "silverbullet@localhost".to_string(),
+            ),
+            lock: Arc::default(),
+        }
+    }
+
+    /// Clone `url` to the path of the clone unless there's one already.
+    #[must_use]
+    pub fn remote(mut self, url: impl Into<String>) -> Self {
+        self.remote = Some(url.into());
+        self
+    }
+
+    /// Pull and push `branch` of `origin`, rather than the branch the clone tracks.
+    #[must_use]
+    pub fn branch(mut self, branch: impl Into<String>) -> Self {
+        self.branch = Some(branch.into());
+        self
+    }
+
+    /// Changes kept on conflicts ([`Strategy::Space`] by default).
+    #[must_use]
+    pub fn strategy(mut self, strategy: Strategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Message of the commits of the changes of the space (`Update space` by default).
+    #[must_use]
+    pub fn message(mut self, message: impl Into<String>) -> Self {
+        self.message = message.into();
+        self
+    }
+
+    /// Author of the commits (`SilverBullet <silverbullet@localhost>` by default).
+    #[must_use]
+    pub fn author(mut self, name: impl Into<String>, email: impl Into<String>) -> Self {
+        self.author = (name.into(), email.into());
+        self
+    }
+
+    /// Commit the changes of the space, pull the remote into the space and push.
+    pub async fn sync(&self) -> Result<Report, Error> {
+        let _guard = self.lock.lock().await;
+
+        self.clone_remote().await?;
+        let state = self.state().await?;
+
+        // Taken before listing, so files changed while listing are in the next sync
+        let started = fs::time::now();
+        let files: Vec<FileMeta> = self
+            .space
+            .list()
+            .await?
+            .into_iter()
+            .filter(|meta| synced(&meta.name))
+            .collect();
+        let mut names: BTreeSet<String> = files.iter().map(|meta| meta.name.clone()).collect();
+
+        for meta in &files {
+            if meta.last_modified >= state.synced || !state.files.contains(&meta.name) {
+                let (data, _) = self.space.get(&meta.name).await?;
+                self.checkout.put(&meta.name, data, incoming(meta)).await?;
+            }
+        }
+        for name in state.files.difference(&names).filter(|name| synced(name)) {
+            match self.checkout.delete(name).await {
+                Ok(()) | Err(fs::Error::NotFound(_)) => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        self.git(&["add", "--all"]).await?;
+        let committed = self.git(&["status", "--porcelain"]).await?.lines().count();
+        if committed > 0 {
+            self.git(&["commit", "--quiet", "-m", &self.message])
+                .await?;
+        }
+
+        let before = self.head().await?;
+        self.pull().await?;
+        let head = self.head().await?;
+
+        // All the files of the clone are new to the space at first
+        let base = match (&state.commit, &before) {
+            (None, _) | (Some(_), None) => head.as_ref().map(|_| EMPTY_TREE),
+            (Some(_), Some(before)) => (head.as_ref() != Some(before)).then_some(before.as_str()),
+        };
+
+        let mut pulled = 0;
+        if let Some(base) = base {
+            let diff = self
+                .git(&["diff", "--name-status", "--no-renames", "-z", base, "HEAD"])
+                .await?;
+
+            for (status, name) in changes(&diff).filter(|(_, name)| synced(name)) {
+                match status {
+                    "D" => {
+                        match self.space.delete(name).await {
+                            Ok(()) | Err(fs::Error::NotFound(_)) => {}
+                            Err(err) => return Err(err.into()),
+                        }
+                        names.remove(name);
+                    }
+                    _ => {
+                        let (data, meta) = self.checkout.get(name).await?;
+                        self.space.put(name, data, incoming(&meta)).await?;
+                        names.insert(name.to_string());
+                    }
+                }
+                pulled += 1;
+            }
+        }
+
+        let pushed = self.push(head.is_some()).await?;
+
+        self.save(&State {
+            synced: started,
+            files: names,
+            commit: head.clone(),
+        })
+        .await?;
+
+        Ok(Report {
+            committed,
+            pulled,
+            pushed,
+            head,
+        })
+    }
+
+    /// Sync forever, every `interval`.
+    ///
+    /// Failures are logged with the `tracing` feature and retried at the next interval.
+    pub async fn run(self, interval: Duration) {
+        loop {
+            futures_timer::Delay::new(interval).await;
+
+            #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+            match self.sync().await {
+                Ok(report) => {
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!(
+                        committed = report.committed,
+                        pulled = report.pulled,
+                        pushed = report.pushed,
+                        "Git sync"
+                    );
+                }
+                Err(err) => {
+                    #[cfg(feature = "tracing")]
+                    tracing::error!(error = %err, "Git sync failed");
+                }
+            }
+        }
+    }
+
+    async fn clone_remote(&self) -> Result<(), Error> {
+        let response = self.exec(&["rev-parse", "--git-dir"]).await?;
+        let Some(remote) = self.remote.as_deref().filter(|_| response.code != 0) else {
+            return check("rev-parse", response).map(drop);
+        };
+
+        let request = shell::Request {
+            cmd: "git".to_string(),
+            args: ["clone", "--quiet", remote, &self.path]
+                .map(str::to_string)
+                .to_vec(),
+            stdin: None,
+        };
+        check("clone", self.shell.exec(request).await?).map(drop)
+    }
+
+    async fn pull(&self) -> Result<(), Error> {
+        let branch = self.branch.as_deref().unwrap_or("HEAD");
+
+        // Nothing to pull from an empty remote
+        let response = self
+            .exec(&["ls-remote", "--exit-code", "origin", branch])
+            .await?;
+        if response.code == 2 {
+            return Ok(());
+        }
+        check("ls-remote", response)?;
+
+        let mut args = vec!["pull", "--quiet", "--no-rebase", "--no-edit"];
+        match self.strategy {
+            Strategy::Space => args.extend(["-X", "ours"]),
+            Strategy::Remote => args.extend(["-X", "theirs"]),
+            Strategy::Stop => {}
+        }
+        if let Some(branch) = &self.branch {
+            args.extend(["origin", branch]);
+        }
+
+        let response = self.exec(&args).await?;
+        if response.code == 0 {
+            return Ok(());
+        }
+
+        let merging = self
+            .exec(&["rev-parse", "-q", "--verify", "MERGE_HEAD"])
+            .await?;
+        if merging.code != 0 {
+            return check("pull", response).map(drop);
+        }
+
+        self.git(&["merge", "--abort"]).await?;
+        Err(Error::Conflict(output(&response)))
+    }
+
+    /// Push the commits the remote doesn't have, whether there were any.
+    async fn push(&self, commits: bool) -> Result<bool, Error> {
+        let upstream = match &self.branch {
+            Some(branch) => format!("origin/{branch}"),
+            None => "@{upstream}".to_string(),
+        };
+        let ahead = self
+            .exec(&["rev-list", "--count", &format!("{upstream}..HEAD")])
+            .await?;
+
+        // Without the branch on the remote, the clone's commits are all new to it
+        let ahead = match ahead.code {
+            0 => ahead.stdout.trim() != "0",
+            _ => commits,
+        };
+        if !ahead {
+            return Ok(false);
+        }
+
+        let refspec = match &self.branch {
+            Some(branch) => format!("HEAD:{branch}"),
+            None => "HEAD".to_string(),
+        };
+        self.git(&["push", "--quiet", "--set-upstream", "origin", &refspec])
+            .await?;
+
+        Ok(true)
+    }
+
+    async fn head(&self) -> Result<Option<String>, Error> {
+        let response = self.exec(&["rev-parse", "-q", "--verify", "HEAD"]).await?;
+
+        Ok((response.code == 0).then(|| response.stdout.trim().to_string()))
+    }
+
+    async fn state(&self) -> Result<State, Error> {
+        let (stream, _) = match self.space.get(STATE).await {
+            Ok(file) => file,
+            Err(fs::Error::NotFound(_)) => return Ok(State::default()),
+            Err(err) => return Err(err.into()),
+        };
+
+        let bytes = stream
+            .try_fold(Vec::new(), |mut acc, chunk| async move {
+                acc.extend_from_slice(&chunk);
+                Ok(acc)
+            })
+            .await
+            .map_err(fs::Error::from)?;
+
+        serde_json::from_slice(&bytes).map_err(|err| fs::Error::Other(err.into()).into())
+    }
+
+    async fn save(&self, state: &State) -> Result<(), Error> {
+        let json = serde_json::to_vec_pretty(state).map_err(|err| fs::Error::Other(err.into()))?;
+        let meta = IncomingFileMeta {
+            content_type: Some("application/json".to_string()),
+            size: Some(json.len() as u64),
+            ..Default::default()
+        };
+        let data = stream::once(async move { Ok(Bytes::from(json)) }).into_boxed();
+
+        self.space.put(STATE, data, meta).await?;
+
+        Ok(())
+    }
+
+    /// Output of git in the clone, committing as the author.
+    async fn exec(&self, args: &[&str]) -> Result<shell::Response, Error> {
+        let mut full = vec![
+            "-C".to_string(),
+            self.path.clone(),
+            "-c".to_string(),
+            format!("user.name={}", self.author.0),
+            "-c".to_string(),
+            format!("user.email={}", self.author.1),
+        ];
+        full.extend(args.iter().map(|arg| arg.to_string()));
+
+        let request = shell::Request {
+            cmd: "git".to_string(),
+            args: full,
+            stdin: None,
+        };
+
+        Ok(self.shell.exec(request).await?)
+    }
+
+    /// Standard output of git in the clone, failing unless it exits with 0.
+    async fn git(&self, args: &[&str]) -> Result<String, Error> {
+        check(args[0], self.exec(args).await?)
+    }
+}
+
+fn check(command: &str, response: shell::Response) -> Result<String, Error> {
+    match response.code {
+        0 => Ok(response.stdout),
+        code => Err(Error::Git {
+            command: command.to_string(),
+            code,
+            output: output(&response),
+        }),
+    }
+}
+
+/// What git said, on either output.
+fn output(response: &shell::Response) -> String {
+    format!("{}\n{}", response.stdout.trim(), response.stderr.trim())
+        .trim()
+        .to_string()
+}
+
+fn synced(name: &str) -> bool {
+    !name.starts_with(".silverbullet-")
+        && !name
+            .split('/')
+            .any(|segment| segment == ".." || segment.eq_ignore_ascii_case(".git"))
+}
+
+fn incoming(meta: &FileMeta) -> IncomingFileMeta {
+    IncomingFileMeta {
+        created: Some(meta.created).filter(|created| *created > 0),
+        content_type: Some(meta.content_type.clone())
+            .filter(|content_type| !content_type.is_empty()),
+        size: Some(meta.size),
+        ..Default::default()
+    }
+}
+
+/// Status letters and names of the files of `git diff --name-status -z`.
+fn changes(diff: &str) -> impl Iterator<Item = (&str, &str)> {
+    let mut parts = diff.split('\0').filter(|part| !part.is_empty());
+
+    std::iter::from_fn(move || Some((parts.next()?, parts.next()?)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::testing::MemoryFs;
+    use crate::fs::{ReadOnlyFilesystem as _, WritableFilesystem as _};
+    use async_trait::async_trait;
+    use std::sync::Mutex;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// git answering with canned outputs, recording the commands it ran
+    struct Git {
+        commands: Mutex<Vec<String>>,
+        heads: AtomicUsize,
+        conflict: bool,
+    }
+
+    impl Git {
+        fn new(conflict: bool) -> Arc<Self> {
+            Arc::new(Self {
+                commands: Mutex::default(),
+                heads: AtomicUsize::new(0),
+                conflict,
+            })
+        }
+
+        fn ran(&self, command: &str) -> bool {
+            let commands = self.commands.lock().unwrap();
+            commands.iter().any(|ran| ran.starts_with(command))
+        }
+    }
+
+    #[async_trait]
+    impl Shell for Git {
+        async fn exec(&self, request: shell::Request) -> Result<shell::Response, shell::Error> {
+            // Past -C and the author
+            let command = request.args[6..].join(" ");
+            self.commands.lock().unwrap().push(command.clone());
+
+            let (code, stdout) = match command.as_str() {
+                "status --porcelain" => (0, "A  a.md\n".to_string()),
+                "rev-parse -q --verify HEAD" => {
+                    let head = self.heads.fetch_add(1, Ordering::SeqCst);
+                    (0, format!("{head}{head}{head}\n"))
+                }
+                "rev-parse -q --verify MERGE_HEAD" => (u16::from(!self.conflict), String::new()),
+                command if command.starts_with("pull") && self.conflict => {
+                    (1, "CONFLICT (content): Merge conflict in a.md".to_string())
+                }
+                command if command.starts_with("diff") => (0, "A\0b.md\0D\0c.md\0".to_string()),
+                command if command.starts_with("rev-list") => (0, "1\n".to_string()),
+                _ => (0, String::new()),
+            };
+
+            Ok(shell::Response {
+                code,
+                stdout,
+                stderr: String::new(),
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn never_writes_the_git_folder() {
+        let space = Arc::new(
+            MemoryFs::new()
+                .with_file("a.md", b"# Local")
+                .with_file(".git/config", b"[core]\n\tfsmonitor = touch /tmp/pwned")
+                .with_file(".GIT/hooks/post-commit", b"#!/bin/sh")
+                .with_file("Notes/.git/config", b"[core]"),
+        );
+        let checkout = Arc::new(
+            MemoryFs::new()
+                .with_file(".git/HEAD", b"ref: refs/heads/main")
+                .with_file("b.md", b"# Remote"),
+        );
+        let sync = GitSync::new(space.clone(), checkout.clone(), Git::new(false), "/clone");
+
+        sync.sync().await.unwrap();
+        assert!(checkout.meta("a.md").await.is_ok());
+        assert!(checkout.meta(".git/config").await.is_err());
+        assert!(checkout.meta(".GIT/hooks/post-commit").await.is_err());
+        assert!(checkout.meta("Notes/.git/config").await.is_err());
+
+        // Nor deletes it for a state listing it
+        let state = br#"{"commit":"111","synced":0,"files":[".git/HEAD"]}"#;
+        let data = futures::stream::once(async { Ok(bytes::Bytes::from_static(state)) });
+        space
+            .put(STATE, Box::pin(data), IncomingFileMeta::default())
+            .await
+            .unwrap();
+        sync.sync().await.unwrap();
+        assert!(checkout.meta(".git/HEAD").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn syncs_both_ways() {
+        let space = Arc::new(
+            MemoryFs::new()
+                .with_file("a.md", b"# Local")
+                .with_file("c.md", b"# Deleted remotely")
+                .with_file(".silverbullet-shares.json", b"[]"),
+        );
+        // The pull brought b.md to the clone
+        let checkout = Arc::new(MemoryFs::new().with_file("b.md", b"# Remote"));
+        let git = Git::new(false);
+
+        let sync = GitSync::new(space.clone(), checkout.clone(), git.clone(), "/clone")
+            .branch("main")
+            .strategy(Strategy::Remote);
+
+        let report = sync.sync().await.unwrap();
+        assert_eq!(
+            report,
+            Report {
+                committed: 1,
+                pulled: 2,
+                pushed: true,
+                head: Some("111".to_string()),
+            }
+        );
+
+        assert!(checkout.meta("a.md").await.is_ok());
+        assert!(checkout.meta(".silverbullet-shares.json").await.is_err());
+        assert!(space.meta("b.md").await.is_ok());
+        assert!(space.meta("c.md").await.is_err());
+
+        assert!(git.ran("commit --quiet -m Update space"));
+        assert!(git.ran("pull --quiet --no-rebase --no-edit -X theirs origin main"));
+        assert!(git.ran(&format!(
+            "diff --name-status --no-renames -z {EMPTY_TREE} HEAD"
+        )));
+        assert!(git.ran("push --quiet --set-upstream origin HEAD:main"));
+
+        // Files deleted from the space since are deleted from the clone
+        space.delete("a.md").await.unwrap();
+        sync.sync().await.unwrap();
+        assert!(checkout.meta("a.md").await.is_err());
+        assert!(git.ran("diff --name-status --no-renames -z 222 HEAD"));
+    }
+
+    #[tokio::test]
+    async fn stops_on_conflicts() {
+        let space = Arc::new(MemoryFs::new().with_file("a.md", b"# Local"));
+        let git = Git::new(true);
+
+        let sync = GitSync::new(space, Arc::new(MemoryFs::new()), git.clone(), "/clone")
+            .strategy(Strategy::Stop);
+
+        let err = sync.sync().await.unwrap_err();
+        assert!(
+            matches!(err, Error::Conflict(ref output) if output.contains("a.md")),
+            "{err}"
+        );
+        assert!(git.ran("pull --quiet --no-rebase --no-edit"));
+        assert!(git.ran("merge --abort"));
+        assert!(!git.ran("push"));
+    }
+
+    #[test]
+    fn parses_strategies() {
+        assert_eq!("Remote".parse(), Ok(Strategy::Remote));
+        assert!("theirs".parse::<Strategy>().is_err());
+        assert_eq!(
+            changes("M\0a b.md\0D\0c.md\0").collect::<Vec<_>>(),
+            [("M", "a b.md"), ("D", "c.md")]
+        );
+    }
+}
@@ -0,0 +1,362 @@
+//! Pages created from templates on first use, e.g. the journal page of a day
+//!
+//! A [`Template`] names its pages with variables, e.g. `Journal/{date}`, and starts them
+//! with the content of a template page, with the same variables substituted:
+//!
+//! ```ignore
+//! let templates = Templates::new(space)
+//!     .template("daily", Template::new("Journal/{date}", "Library/Templates/Daily"));
+//!
+//! // Journal/2024-06-01, created unless it exists
+//! let instance = templates.instantiate("daily", Some("2024-06-01")).await?;
+//! ```
+//!
+//! | Variable | Value |
+//! |---|---|
+//! | `{title}` | The title asked for, today's date by default |
+//! | `{date}` | The title when it's a `YYYY-MM-DD` date, today otherwise |
+//! | `{year}`, `{month}`, `{day}` | Parts of `{date}`, e.g. `2024`, `06` and `01` |
+//! | `{weekday}` | Day of the week of `{date}`, e.g. `Saturday` |
+//! | `{time}` | Time of the creation, e.g. `14:05` |
+//!
+//! Pages are written to the filesystem the templates were created with, so they can be
+//! materialized for users who can't write, e.g. read-only visitors of a daily page.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use futures::{TryStreamExt as _, stream};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::fs::{self, IncomingFileMeta, ReadWriteFilesystem, StreamExt, time};
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("No template {0}")]
+    Unknown(String),
+
+    #[error("Invalid page title {title:?}: {reason}")]
+    InvalidTitle { title: String, reason: &'static str },
+
+    #[error(transparent)]
+    Fs(#[from] fs::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+const WEEKDAYS: [&str; 7] = [
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+    "Sunday",
+];
+
+/// Pages of a template
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    page: String,
+    source: String,
+}
+
+impl Template {
+    /// Pages named `page`, e.g. `Journal/{date}`, starting with the content of the
+    /// `source` page, e.g. `Library/Templates/Daily`.
+    pub fn new(page: impl Into<String>, source: impl Into<String>) -> Self {
+        Self {
+            page: page.into(),
+            source: source.into(),
+        }
+    }
+}
+
+/// Page of a template
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Instance {
+    /// Name of the page, without `.md`
+    pub page: String,
+    /// Whether the page was created, rather than already there
+    pub created: bool,
+}
+
+/// Templates of a space, by name
+#[derive(Clone)]
+pub struct Templates {
+    fs: Arc<dyn ReadWriteFilesystem>,
+    templates: BTreeMap<String, Template>,
+    utc_offset: i64,
+}
+
+impl Templates {
+    pub fn new(fs: Arc<dyn ReadWriteFilesystem>) -> Self {
+        Self {
+            fs,
+            templates: BTreeMap::new(),
+            utc_offset: 0,
+        }
+    }
+
+    #[must_use]
+    pub fn template(mut self, name: impl Into<String>, template: Template) -> Self {
+        self.templates.insert(name.into(), template);
+        self
+    }
+
+    /// Minutes from UTC of today's date and the time (UTC by default).
+    #[must_use]
+    pub fn utc_offset(mut self, minutes: i64) -> Self {
+        self.utc_offset = minutes;
+        self
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.templates.keys().map(String::as_str)
+    }
+
+    /// Create the page of `title` from a template, unless it exists.
+    ///
+    /// A missing source page creates an empty page.
+    pub async fn instantiate(&self, name: &str, title: Option<&str>) -> Result<Instance> {
+        let template = self
+            .templates
+            .get(name)
+            .ok_or_else(|| Error::Unknown(name.to_string()))?;
+
+        let variables = self.variables(title, time::now())?;
+        let page = substitute(&template.page, &variables);
+        validate(&page)?;
+        let path = format!("{page}.md");
+
+        match self.fs.meta(&path).await {
+            Ok(_) => {
+                return Ok(Instance {
+                    page,
+                    created: false,
+                });
+            }
+            Err(fs::Error::NotFound(_)) => {}
+            Err(err) => return Err(err.into()),
+        }
+
+        let source = match template.source.ends_with(".md") {
+            true => template.source.clone(),
+            false => format!("{}.md", template.source),
+        };
+        let content = match read(&*self.fs, &source).await {
+            Ok(content) => substitute(&content, &variables),
+            Err(fs::Error::NotFound(_)) => {
+                #[cfg(feature = "tracing")]
+                tracing::warn!(template = name, source, "Template source not found");
+
+                String::new()
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        let meta = IncomingFileMeta {
+            content_type: Some("text/markdown".to_string()),
+            size: Some(content.len() as u64),
+            ..Default::default()
+        };
+        let data = stream::once(async move { Ok(Bytes::from(content)) }).into_boxed();
+        self.fs.put(&path, data, meta).await?;
+
+        #[cfg(feature = "tracing")]
+        tracing::info!(template = name, page, "Page created from template");
+
+        Ok(Instance {
+            page,
+            created: true,
+        })
+    }
+
+    /// Variables of a page of `title` created at `millis`.
+    fn variables(&self, title: Option<&str>, millis: u64) -> Result<Vec<(&'static str, String)>> {
+        let minutes = millis as i64 / 60_000 + self.utc_offset;
+        let today = minutes.div_euclid(1440);
+        let minute = minutes.rem_euclid(1440);
+
+        let title = title.map(|title| title.trim_matches('/'));
+        let days = title.and_then(time::parse_date).unwrap_or(today);
+        let (year, month, day) = time::civil_from_days(days);
+        let date = format!("{year:04}-{month:02}-{day:02}");
+
+        let title = match title {
+            Some(title) => {
+                validate(title)?;
+                title.to_string()
+            }
+            None => date.clone(),
+        };
+
+        // The epoch was a Thursday
+        let weekday = WEEKDAYS[(days + 3).rem_euclid(7) as usize];
+
+        Ok(vec![
+            ("title", title),
+            ("date", date),
+            ("year", format!("{year:04}")),
+            ("month", format!("{month:02}")),
+            ("day", format!("{day:02}")),
+            ("weekday", weekday.to_string()),
+            ("time", format!("{:02}:{:02}", minute / 60, minute % 60)),
+        ])
+    }
+}
+
+fn substitute(text: &str, variables: &[(&str, String)]) -> String {
+    variables
+        .iter()
+        .fold(text.to_string(), |text, (name, value)| {
+            text.replace(&format!("{{{name}}}"), value)
+        })
+}
+
+/// Checks a page name, which comes from the request, stays a page of the space.
+fn validate(page: &str) -> Result<()> {
+    let invalid = |reason| {
+        Err(Error::InvalidTitle {
+            title: page.to_string(),
+            reason,
+        })
+    };
+
+    if page.is_empty() {
+        return invalid("empty");
+    }
+    if page
+        .chars()
+        .any(|c| c.is_control() || matches!(c, '[' | ']' | '|' | '\\'))
+    {
+        return invalid("contains a control character, a bracket, a pipe or a backslash");
+    }
+    if page
+        .split('/')
+        .any(|segment| segment.trim().is_empty() || segment.starts_with('.'))
+    {
+        return invalid("empty or hidden folder name");
+    }
+
+    Ok(())
+}
+
+async fn read(fs: &dyn ReadWriteFilesystem, path: &str) -> fs::Result<String> {
+    let (stream, _) = fs.get(path).await?;
+
+    let bytes = stream
+        .try_fold(Vec::new(), |mut acc, chunk| async move {
+            acc.extend_from_slice(&chunk);
+            Ok(acc)
+        })
+        .await?;
+
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::testing::MemoryFs;
+
+    fn templates() -> Templates {
+        let fs = MemoryFs::new()
+            .with_file(
+                "Library/Templates/Daily.md",
+                b"# {weekday} {day}/{month}/{year}\n",
+            )
+            .with_file("Journal/2024-06-02.md", b"Written");
+
+        Templates::new(Arc::new(fs))
+            .template(
+                "daily",
+                Template::new("Journal/{date}", "Library/Templates/Daily"),
+            )
+            .template("project", Template::new("Projects/{title}", "Missing"))
+    }
+
+    #[test]
+    fn substitutes_variables() {
+        // 2024-06-01T23:30:00Z
+        let millis = (time::days_from_civil(2024, 6, 1) as u64 * 1440 + 23 * 60 + 30) * 60_000;
+        let variables = templates().variables(Some("2024-06-01"), millis).unwrap();
+        assert_eq!(
+            substitute("{title} {date} {weekday} {time} {other}", &variables),
+            "2024-06-01 2024-06-01 Saturday 23:30 {other}"
+        );
+
+        // Today, in the offset
+        let variables = templates().utc_offset(60).variables(None, millis).unwrap();
+        assert_eq!(
+            substitute("{title} {weekday} {time}", &variables),
+            "2024-06-02 Sunday 00:30"
+        );
+
+        let variables = templates().variables(Some("Launch"), millis).unwrap();
+        assert_eq!(
+            substitute("{title} {date}", &variables),
+            "Launch 2024-06-01"
+        );
+
+        for title in ["../secret", "a//b", ".hidden", "a|b"] {
+            assert!(
+                matches!(
+                    templates().variables(Some(title), millis),
+                    Err(Error::InvalidTitle { .. })
+                ),
+                "{title}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn instantiates_pages() {
+        let templates = templates();
+
+        let instance = templates
+            .instantiate("daily", Some("2024-06-01"))
+            .await
+            .unwrap();
+        assert_eq!(
+            instance,
+            Instance {
+                page: "Journal/2024-06-01".to_string(),
+                created: true,
+            }
+        );
+        assert_eq!(
+            read(&*templates.fs, "Journal/2024-06-01.md").await.unwrap(),
+            "# Saturday 01/06/2024\n"
+        );
+
+        // Existing pages are left alone
+        let instance = templates
+            .instantiate("daily", Some("2024-06-02"))
+            .await
+            .unwrap();
+        assert!(!instance.created);
+        assert_eq!(
+            read(&*templates.fs, "Journal/2024-06-02.md").await.unwrap(),
+            "Written"
+        );
+
+        let instance = templates
+            .instantiate("project", Some("Launch"))
+            .await
+            .unwrap();
+        assert_eq!(instance.page, "Projects/Launch");
+        assert_eq!(
+            read(&*templates.fs, "Projects/Launch.md").await.unwrap(),
+            ""
+        );
+
+        assert!(matches!(
+            templates.instantiate("weekly", None).await,
+            Err(Error::Unknown(_))
+        ));
+    }
+}